@@ -1,5 +1,7 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use intan_importer::deinterleave::{deinterleave, deinterleave_scalar};
 use intan_importer::load;
+use ndarray::Array2;
 use std::path::Path;
 
 pub fn bench_load_header(c: &mut Criterion) {
@@ -36,5 +38,28 @@ pub fn bench_file_processing(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, bench_load_header, bench_file_processing);
+pub fn bench_deinterleave(c: &mut Criterion) {
+    // Transpose synthetic data blocks of 128 samples, as read from an RHS file
+    let num_samples = 128;
+    let mut group = c.benchmark_group("deinterleave");
+
+    for num_channels in [16, 64, 256] {
+        let interleaved: Vec<u16> = (0..num_channels * num_samples)
+            .map(|i| (i * 7919) as u16)
+            .collect();
+        let mut out = Array2::<u16>::zeros((num_channels, num_samples));
+        group.throughput(Throughput::Bytes((interleaved.len() * 2) as u64));
+
+        group.bench_with_input(BenchmarkId::new("scalar", num_channels), &interleaved, |b, words| {
+            b.iter(|| deinterleave_scalar(black_box(words), num_channels, None, out.view_mut()));
+        });
+        group.bench_with_input(BenchmarkId::new("simd", num_channels), &interleaved, |b, words| {
+            b.iter(|| deinterleave(black_box(words), num_channels, None, out.view_mut()));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_load_header, bench_file_processing, bench_deinterleave);
 criterion_main!(benches);
\ No newline at end of file
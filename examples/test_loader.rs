@@ -22,7 +22,7 @@ fn main() {
             if let Some(sources) = &file.source_files {
                 println!("  Source files: {}", sources.len());
                 for (i, source) in sources.iter().enumerate() {
-                    println!("    {}: {}", i + 1, source);
+                    println!("    {}: {} ({} samples)", i + 1, source.path, source.num_samples);
                 }
             }
             
@@ -0,0 +1,933 @@
+//! Per-channel signal analysis: noise, clipping, power spectra, and the similarity
+//! between channels.
+//!
+//! # Noise
+//!
+//! [`channel_noise`] measures the noise of every amplifier channel in μV: the RMS
+//! about the channel's mean, the median absolute deviation (MAD) from its median,
+//! and the noise floor estimated from the MAD, `MAD / 0.6745`, which is the
+//! standard deviation of Gaussian noise and is barely raised by spikes. The RMS,
+//! in contrast, grows with every spike and artifact, so the two together tell
+//! background noise from activity.
+//!
+//! Stimulation artifacts dominate both measures on stimulated channels.
+//! [`channel_noise_with`] leaves out the samples where a channel was stimulating,
+//! recovering charge, or settling, using the stimulation data and the charge
+//! recovery and amplifier settle flags of the recording, optionally followed by a
+//! number of samples of padding for the artifact to decay.
+//!
+//! The data can be loaded in any form: `f64`, `f32`, raw codes, or spilled to disk.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::analysis::{channel_noise, channel_noise_with, NoiseOptions};
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! let data = rhs_file.data.as_ref().unwrap();
+//! let channels = &rhs_file.header.amplifier_channels;
+//! for (channel, noise) in channels.iter().zip(channel_noise(data).unwrap()) {
+//!     println!("{}: {:.1} μV RMS, noise floor {:.1} μV",
+//!              channel.native_channel_name, noise.rms, noise.noise_floor);
+//! }
+//!
+//! // Leave out stimulation, charge recovery, and settle periods, plus 1 ms after them
+//! let options = NoiseOptions { exclude_stim: true, exclude_settle: true, padding: 30 };
+//! let noise = channel_noise_with(data, &options).unwrap();
+//! ```
+//!
+//! # Clipping
+//!
+//! A signal larger than the amplifier's input range saturates the ADC, which then
+//! reads 0 or 65535, and the saturated samples carry no information about the
+//! signal. [`clipping`] finds the samples of every amplifier channel at these
+//! rails and reports the fraction and the intervals of clipped samples, so they
+//! can be left out of further analysis. Raw codes are compared with the rails
+//! exactly, scaled values to within half a step; the notch filter moves the
+//! values of saturated samples off the rails, so clipping is best measured on data
+//! loaded without it.
+//!
+//! ```no_run
+//! use intan_importer::analysis::clipping;
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! for channel in clipping(rhs_file.data.as_ref().unwrap()).unwrap() {
+//!     if channel.clipped_samples > 0 {
+//!         println!("channel {}: {:.2}% clipped in {} intervals",
+//!                  channel.channel, channel.clipped_fraction * 100.0, channel.intervals.len());
+//!     }
+//! }
+//! ```
+//!
+//! # Power spectra
+//!
+//! [`psd`] estimates the power spectral density of every channel of an analog
+//! signal with Welch's method: the channel is cut into overlapping segments, each
+//! segment has its mean removed and is multiplied by a window, and the
+//! periodograms of the segments are averaged. The result is one-sided, in the
+//! squared unit of the signal per Hz (μV²/Hz for amplifier data), so that summing
+//! it over all frequencies gives the variance of the channel. [`band_power`]
+//! integrates the spectrum over a frequency band, such as the standard bands of
+//! [`FrequencyBand`].
+//!
+//! ```no_run
+//! use intan_importer::analysis::{band_power, FrequencyBand, PsdOptions, Window};
+//! use intan_importer::load;
+//! use intan_importer::signal::SignalType;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! let options = PsdOptions { segment_length: 8192, overlap: 0.5, window: Window::Hann };
+//! let psd = rhs_file.psd(SignalType::Amplifier, &options).unwrap();
+//! println!("{} Hz resolution over {} segments", psd.resolution(), psd.num_segments);
+//!
+//! for band in FrequencyBand::ALL {
+//!     let power = band_power(&psd, band.range());
+//!     println!("{}: {:.1} μV² on channel 0", band.name(), power[0]);
+//! }
+//! ```
+//!
+//! # Similarity between channels
+//!
+//! Electrode contacts shorted or bridged together record the same signal.
+//! [`correlation_matrix`] gives the Pearson correlation of every pair of amplifier
+//! channels and [`coherence_matrix`] their magnitude-squared coherence, averaged
+//! over a frequency band; both are close to 1 for bridged contacts. Coherence is
+//! estimated over the segments of Welch's method, so it needs many segments: a
+//! single segment gives a coherence of 1 for every pair. Both can be restricted to
+//! a range of samples.
+//!
+//! ```no_run
+//! use intan_importer::analysis::{coherence_matrix, correlation_matrix, CoherenceOptions};
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! let data = rhs_file.data.as_ref().unwrap();
+//!
+//! // The first minute, in the spike band where separate contacts are independent
+//! let minute = Some(0..rhs_file.header.sample_rate as usize * 60);
+//! let correlation = correlation_matrix(data, minute.clone()).unwrap();
+//! let options = CoherenceOptions { band: 300.0..5000.0, ..Default::default() };
+//! let sample_rate = rhs_file.header.sample_rate as f64;
+//! let coherence = coherence_matrix(data, sample_rate, minute, &options).unwrap();
+//!
+//! for ((i, j), &value) in coherence.indexed_iter() {
+//!     if i < j && value > 0.9 {
+//!         println!("channels {i} and {j} may be bridged (r = {:.3})", correlation[[i, j]]);
+//!     }
+//! }
+//! ```
+
+use std::error::Error;
+use std::f64::consts::PI;
+use std::ops::Range;
+
+use ndarray::parallel::prelude::*;
+use ndarray::{s, Array1, Array2};
+
+use crate::dsp::{fft, Complex};
+use crate::flags::StimFlags;
+use crate::qc::{is_clipped, median, MAD_TO_SIGMA};
+use crate::signal::SignalType;
+use crate::spill::SpilledSignal;
+use crate::types::{IntanError, RhsData, RhsFile};
+use crate::units::Unit;
+
+/// Samples left out of [`channel_noise_with`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NoiseOptions {
+    /// Whether to leave out the samples where the channel's stimulation current is
+    /// not zero or its charge recovery switch is on
+    pub exclude_stim: bool,
+    /// Whether to leave out the samples where the channel's amplifier settle is on
+    pub exclude_settle: bool,
+    /// Number of samples also left out after each excluded period
+    pub padding: usize,
+}
+
+/// Noise measures of one amplifier channel, in μV.
+///
+/// The measures are NaN when every sample of the channel is excluded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelNoise {
+    /// Index of the channel in the amplifier data
+    pub channel: usize,
+    /// Root-mean-square amplitude about the channel's mean (μV)
+    pub rms: f64,
+    /// Median absolute deviation from the channel's median (μV)
+    pub mad: f64,
+    /// Noise standard deviation estimated from the MAD (μV)
+    pub noise_floor: f64,
+    /// Number of samples measured
+    pub num_samples: usize,
+    /// Number of samples excluded
+    pub excluded_samples: usize,
+}
+
+/// Measures the noise of every amplifier channel over all its samples.
+///
+/// See the [module documentation](crate::analysis).
+///
+/// # Errors
+///
+/// Returns an error if a spilled channel cannot be read, or if the amplifier data
+/// is in a unit that cannot be converted to μV.
+pub fn channel_noise(data: &RhsData) -> Result<Vec<ChannelNoise>, Box<dyn Error>> {
+    channel_noise_with(data, &NoiseOptions::default())
+}
+
+/// Measures the noise of every amplifier channel, leaving out the samples chosen
+/// by `options`.
+///
+/// Channels are measured in parallel. Channels without stimulation data or flags
+/// have no samples left out.
+///
+/// # Errors
+///
+/// See [`channel_noise`].
+pub fn channel_noise_with(
+    data: &RhsData,
+    options: &NoiseOptions,
+) -> Result<Vec<ChannelNoise>, Box<dyn Error>> {
+    let to_uv = amplifier_to_uv(data)?;
+
+    // Errors are carried as strings across the worker threads
+    (0..num_channels(data, SpilledSignal::Amplifier))
+        .into_par_iter()
+        .map(|channel| {
+            let values = data.amplifier_channel(channel).map_err(|e| e.to_string())?;
+            let excluded = excluded_samples(data, channel, values.len(), options);
+            let values: Vec<f64> = values
+                .iter()
+                .zip(&excluded)
+                .filter(|(_, &excluded)| !excluded)
+                .map(|(value, _)| value * to_uv)
+                .collect();
+            Ok(measure(channel, values, excluded.len()))
+        })
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(|message| IntanError::Other(message).into())
+}
+
+/// Returns which samples of `channel` to leave out
+fn excluded_samples(
+    data: &RhsData,
+    channel: usize,
+    num_samples: usize,
+    options: &NoiseOptions,
+) -> Vec<bool> {
+    let mut excluded = vec![false; num_samples];
+    if options.exclude_stim {
+        if let Some(stim) = &data.stim_data {
+            if channel < stim.nrows() {
+                for (excluded, &current) in excluded.iter_mut().zip(stim.row(channel)) {
+                    *excluded |= current != 0.0;
+                }
+            }
+        }
+        exclude_flagged(&mut excluded, data.charge_recovery_data.as_ref(), channel);
+    }
+    if options.exclude_settle {
+        exclude_flagged(&mut excluded, data.amp_settle_data.as_ref(), channel);
+    }
+
+    // Extend every excluded period by the padding
+    let mut remaining = 0;
+    for excluded in excluded.iter_mut() {
+        if *excluded {
+            remaining = options.padding;
+        } else if remaining > 0 {
+            *excluded = true;
+            remaining -= 1;
+        }
+    }
+    excluded
+}
+
+/// Marks the samples where `flags` are set on `channel`
+fn exclude_flagged(excluded: &mut [bool], flags: Option<&StimFlags>, channel: usize) {
+    let Some(flags) = flags.filter(|flags| channel < flags.num_channels()) else {
+        return;
+    };
+    for run in flags.runs(channel) {
+        let end = run.end.min(excluded.len());
+        excluded[run.start.min(end)..end].fill(true);
+    }
+}
+
+/// Computes the noise measures of the samples kept
+fn measure(channel: usize, mut values: Vec<f64>, total_samples: usize) -> ChannelNoise {
+    let num_samples = values.len();
+    let mean = values.iter().sum::<f64>() / num_samples as f64;
+    let rms = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / num_samples as f64).sqrt();
+    let mad = median(&mut values).map_or(f64::NAN, |center| {
+        for value in values.iter_mut() {
+            *value = (*value - center).abs();
+        }
+        median(&mut values).unwrap_or(f64::NAN)
+    });
+
+    ChannelNoise {
+        channel,
+        rms,
+        mad,
+        noise_floor: mad * MAD_TO_SIGMA,
+        num_samples,
+        excluded_samples: total_samples - num_samples,
+    }
+}
+
+/// Clipping of one amplifier channel at the ADC rails, found by [`clipping`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelClipping {
+    /// Index of the channel in the amplifier data
+    pub channel: usize,
+    /// Number of samples at the rails
+    pub clipped_samples: usize,
+    /// Fraction of the channel's samples at the rails
+    pub clipped_fraction: f64,
+    /// Ranges of consecutive samples at the rails, in order
+    pub intervals: Vec<Range<usize>>,
+}
+
+/// Finds the samples of every amplifier channel at the ADC rails.
+///
+/// Channels are examined in parallel, in whichever form the data was loaded. See
+/// the [module documentation](crate::analysis).
+///
+/// # Errors
+///
+/// Returns an error if a spilled channel cannot be read, or if the amplifier data
+/// is in a unit that cannot be converted to μV.
+pub fn clipping(data: &RhsData) -> Result<Vec<ChannelClipping>, Box<dyn Error>> {
+    if let Some(raw) = &data.amplifier_data_raw {
+        return Ok(raw
+            .outer_iter()
+            .into_par_iter()
+            .enumerate()
+            .map(|(channel, codes)| {
+                let clipped = codes.iter().map(|&code| code == 0 || code == u16::MAX);
+                clipped_intervals(channel, clipped)
+            })
+            .collect());
+    }
+
+    let to_uv = amplifier_to_uv(data)?;
+    // Errors are carried as strings across the worker threads
+    (0..num_channels(data, SpilledSignal::Amplifier))
+        .into_par_iter()
+        .map(|channel| {
+            let values = data.amplifier_channel(channel).map_err(|e| e.to_string())?;
+            let clipped = values.iter().map(|value| is_clipped(value * to_uv));
+            Ok(clipped_intervals(channel, clipped))
+        })
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(|message| IntanError::Other(message).into())
+}
+
+/// Collects the runs of clipped samples of one channel
+fn clipped_intervals(channel: usize, clipped: impl Iterator<Item = bool>) -> ChannelClipping {
+    let mut intervals: Vec<Range<usize>> = Vec::new();
+    let mut num_samples = 0;
+    for (sample, clipped) in clipped.enumerate() {
+        num_samples += 1;
+        if !clipped {
+            continue;
+        }
+        match intervals.last_mut() {
+            Some(interval) if interval.end == sample => interval.end += 1,
+            _ => intervals.push(sample..sample + 1),
+        }
+    }
+
+    let clipped_samples = intervals.iter().map(Range::len).sum();
+    ChannelClipping {
+        channel,
+        clipped_samples,
+        clipped_fraction: if num_samples == 0 {
+            0.0
+        } else {
+            clipped_samples as f64 / num_samples as f64
+        },
+        intervals,
+    }
+}
+
+/// Window applied to each segment of a [`psd`] estimate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Window {
+    /// Hann (raised cosine) window
+    #[default]
+    Hann,
+    /// Hamming window, with lower nearest side lobes than Hann but slower decay
+    Hamming,
+    /// No window, for the finest frequency resolution at the cost of leakage
+    Rectangular,
+}
+
+impl Window {
+    /// Returns the periodic window of `length` samples
+    fn coefficients(self, length: usize) -> Vec<f64> {
+        let cosine = |n: usize| (2.0 * PI * n as f64 / length as f64).cos();
+        (0..length)
+            .map(|n| match self {
+                Window::Hann => 0.5 - 0.5 * cosine(n),
+                Window::Hamming => 0.54 - 0.46 * cosine(n),
+                Window::Rectangular => 1.0,
+            })
+            .collect()
+    }
+}
+
+/// Settings of a Welch [`psd`] estimate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PsdOptions {
+    /// Number of samples in each segment (default: 4096). Each segment is
+    /// zero-padded to the next power of two, which sets the frequency resolution
+    /// to the sample rate divided by that length. A channel shorter than a
+    /// segment is analyzed as a single segment.
+    pub segment_length: usize,
+    /// Fraction of each segment shared with the next, from 0 up to but not
+    /// including 1 (default: 0.5)
+    pub overlap: f64,
+    /// Window applied to each segment (default: Hann)
+    pub window: Window,
+}
+
+impl Default for PsdOptions {
+    fn default() -> Self {
+        PsdOptions {
+            segment_length: 4096,
+            overlap: 0.5,
+            window: Window::Hann,
+        }
+    }
+}
+
+/// Power spectral density of every channel of a signal, estimated by [`psd`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Psd {
+    /// Frequency of each bin in Hz, from 0 to the Nyquist frequency
+    pub frequencies: Array1<f64>,
+    /// One-sided power spectral density, channels × frequencies, in the squared
+    /// unit of the signal per Hz
+    pub power: Array2<f64>,
+    /// Unit of the signal; the density is in this unit squared per Hz
+    pub unit: Unit,
+    /// Number of segments averaged for every channel
+    pub num_segments: usize,
+}
+
+impl Psd {
+    /// Spacing of the frequency bins in Hz.
+    pub fn resolution(&self) -> f64 {
+        self.frequencies.get(1).copied().unwrap_or(f64::NAN)
+    }
+}
+
+/// Standard frequency bands of neural recordings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrequencyBand {
+    /// 1–4 Hz
+    Delta,
+    /// 4–8 Hz
+    Theta,
+    /// 8–13 Hz
+    Alpha,
+    /// 13–30 Hz
+    Beta,
+    /// 30–80 Hz
+    Gamma,
+    /// 80–200 Hz
+    HighGamma,
+    /// 300–5000 Hz, where spikes carry their power
+    Spike,
+}
+
+impl FrequencyBand {
+    /// All bands, from the lowest to the highest.
+    pub const ALL: [FrequencyBand; 7] = [
+        FrequencyBand::Delta,
+        FrequencyBand::Theta,
+        FrequencyBand::Alpha,
+        FrequencyBand::Beta,
+        FrequencyBand::Gamma,
+        FrequencyBand::HighGamma,
+        FrequencyBand::Spike,
+    ];
+
+    /// The band's frequencies in Hz, including the lower edge and excluding the
+    /// upper one.
+    pub fn range(&self) -> Range<f64> {
+        match self {
+            FrequencyBand::Delta => 1.0..4.0,
+            FrequencyBand::Theta => 4.0..8.0,
+            FrequencyBand::Alpha => 8.0..13.0,
+            FrequencyBand::Beta => 13.0..30.0,
+            FrequencyBand::Gamma => 30.0..80.0,
+            FrequencyBand::HighGamma => 80.0..200.0,
+            FrequencyBand::Spike => 300.0..5000.0,
+        }
+    }
+
+    /// Lowercase name of the band.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrequencyBand::Delta => "delta",
+            FrequencyBand::Theta => "theta",
+            FrequencyBand::Alpha => "alpha",
+            FrequencyBand::Beta => "beta",
+            FrequencyBand::Gamma => "gamma",
+            FrequencyBand::HighGamma => "high_gamma",
+            FrequencyBand::Spike => "spike",
+        }
+    }
+}
+
+/// Estimates the power spectral density of every channel of an analog signal
+/// with Welch's method.
+///
+/// `signal` is [`SignalType::Amplifier`], [`SignalType::DcAmplifier`],
+/// [`SignalType::BoardAdc`], or [`SignalType::BoardDac`], loaded in any form, and
+/// `sample_rate` is its rate in Hz. Channels are analyzed in parallel. See the
+/// [module documentation](crate::analysis).
+///
+/// # Errors
+///
+/// Returns an error if `signal` is not one of the above, the options or sample
+/// rate are invalid, the signal has no samples, or a spilled channel cannot be
+/// read.
+pub fn psd(
+    data: &RhsData,
+    signal: SignalType,
+    sample_rate: f64,
+    options: &PsdOptions,
+) -> Result<Psd, Box<dyn Error>> {
+    let (spilled_signal, unit) = match signal {
+        SignalType::Amplifier => (SpilledSignal::Amplifier, data.units.amplifier),
+        SignalType::DcAmplifier => (SpilledSignal::DcAmplifier, data.units.dc_amplifier),
+        SignalType::BoardAdc => (SpilledSignal::BoardAdc, data.units.board_adc),
+        SignalType::BoardDac => (SpilledSignal::BoardDac, data.units.board_dac),
+        _ => {
+            return Err(Box::new(IntanError::Other(format!(
+                "The power spectrum of {} data cannot be estimated",
+                signal.name()
+            ))))
+        }
+    };
+    if !(sample_rate > 0.0 && sample_rate.is_finite()) {
+        return Err(Box::new(IntanError::Other(format!(
+            "Invalid sample rate {sample_rate} Hz"
+        ))));
+    }
+    let welch = Welch::new(options, data.timestamps.len())?;
+    let Welch {
+        fft_length,
+        num_segments,
+        ..
+    } = welch;
+
+    // Scaling to a density whose one-sided sum over the bins is the variance
+    let window_power = welch.window.iter().map(|w| w * w).sum::<f64>();
+    let scale = 1.0 / (sample_rate * window_power * num_segments as f64);
+
+    let rows = (0..num_channels(data, spilled_signal))
+        .into_par_iter()
+        .map(|channel| {
+            let values = read_channel(data, spilled_signal, channel).map_err(|e| e.to_string())?;
+            let mut power = vec![0.0; fft_length / 2 + 1];
+            let mut spectrum = Vec::new();
+            for segment in 0..num_segments {
+                welch.spectrum(&values, segment, &mut spectrum);
+                for (power, bin) in power.iter_mut().zip(&spectrum) {
+                    *power += bin.norm_sqr();
+                }
+            }
+
+            // Fold the negative frequencies onto the positive ones; the DC and
+            // Nyquist bins have no counterpart
+            let last = power.len() - 1;
+            for (bin, power) in power.iter_mut().enumerate() {
+                let one_sided = if bin == 0 || (bin == last && fft_length > 1) {
+                    1.0
+                } else {
+                    2.0
+                };
+                *power *= one_sided * scale;
+            }
+            Ok(power)
+        })
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(IntanError::Other)?;
+
+    let num_bins = fft_length / 2 + 1;
+    let mut power = Array2::zeros((rows.len(), num_bins));
+    for (mut row, values) in power.rows_mut().into_iter().zip(rows) {
+        row.assign(&Array1::from(values));
+    }
+    Ok(Psd {
+        frequencies: Array1::from_shape_fn(num_bins, |bin| {
+            bin as f64 * sample_rate / fft_length as f64
+        }),
+        power,
+        unit,
+        num_segments,
+    })
+}
+
+/// Integrates the power spectral density of every channel over `band`, in Hz.
+///
+/// Sums the bins whose frequency lies in the band, times the bin spacing, giving
+/// the power in the squared unit of the signal (μV² for amplifier data). Bands
+/// narrower than the [resolution](Psd::resolution) may contain no bin and give 0.
+pub fn band_power(psd: &Psd, band: Range<f64>) -> Array1<f64> {
+    let resolution = psd.resolution();
+    psd.power
+        .rows()
+        .into_iter()
+        .map(|row| {
+            row.iter()
+                .zip(&psd.frequencies)
+                .filter(|(_, frequency)| band.contains(frequency))
+                .fold(0.0, |sum, (power, _)| sum + power * resolution)
+        })
+        .collect()
+}
+
+/// Settings of a [`coherence_matrix`] estimate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoherenceOptions {
+    /// Segment length, overlap, and window of the Welch estimate of the spectra
+    pub psd: PsdOptions,
+    /// Frequencies in Hz over which the coherence is averaged (default: all)
+    pub band: Range<f64>,
+}
+
+impl Default for CoherenceOptions {
+    fn default() -> Self {
+        CoherenceOptions {
+            psd: PsdOptions::default(),
+            band: 0.0..f64::INFINITY,
+        }
+    }
+}
+
+/// Computes the Pearson correlation of every pair of amplifier channels.
+///
+/// Returns a symmetric channels × channels matrix with ones on the diagonal.
+/// Correlations with a constant channel are NaN. `samples` restricts the
+/// computation to a range of samples; `None` uses all of them. The data can be
+/// loaded in any form. See the [module documentation](crate::analysis).
+///
+/// # Errors
+///
+/// Returns an error if `samples` is out of bounds or empty, or if a spilled
+/// channel cannot be read.
+pub fn correlation_matrix(
+    data: &RhsData,
+    samples: Option<Range<usize>>,
+) -> Result<Array2<f64>, Box<dyn Error>> {
+    let channels = amplifier_window(data, samples)?;
+
+    // Centered and scaled to unit norm, so that correlations are dot products
+    let normalized: Vec<Option<Array1<f64>>> = channels
+        .into_par_iter()
+        .map(|mut values| {
+            let mean = values.mean().unwrap_or(0.0);
+            values -= mean;
+            let norm = values.dot(&values).sqrt();
+            (norm > 0.0).then(|| values / norm)
+        })
+        .collect();
+
+    let num_channels = normalized.len();
+    let rows: Vec<Vec<f64>> = (0..num_channels)
+        .into_par_iter()
+        .map(|i| {
+            (i..num_channels)
+                .map(|j| match (&normalized[i], &normalized[j]) {
+                    (Some(a), Some(b)) => a.dot(b).clamp(-1.0, 1.0),
+                    _ => f64::NAN,
+                })
+                .collect()
+        })
+        .collect();
+    Ok(symmetric(rows))
+}
+
+/// Computes the magnitude-squared coherence of every pair of amplifier channels,
+/// averaged over a frequency band.
+///
+/// The coherence at each frequency is `|Pxy|² / (Pxx Pyy)`, from cross and auto
+/// spectra averaged over the segments of Welch's method, and lies between 0 and 1.
+/// Returns a symmetric channels × channels matrix with ones on the diagonal;
+/// pairs with a channel without power in the band are NaN. `samples` restricts
+/// the computation to a range of samples; `None` uses all of them.
+///
+/// The cross spectra of all pairs are kept over the bins of the band, so memory
+/// grows with the square of the number of channels times the number of bins.
+/// See the [module documentation](crate::analysis).
+///
+/// # Errors
+///
+/// Returns an error if the sample rate or options are invalid, `samples` is out
+/// of bounds or empty, no frequency bin lies in the band, or a spilled channel
+/// cannot be read.
+pub fn coherence_matrix(
+    data: &RhsData,
+    sample_rate: f64,
+    samples: Option<Range<usize>>,
+    options: &CoherenceOptions,
+) -> Result<Array2<f64>, Box<dyn Error>> {
+    if !(sample_rate > 0.0 && sample_rate.is_finite()) {
+        return Err(Box::new(IntanError::Other(format!(
+            "Invalid sample rate {sample_rate} Hz"
+        ))));
+    }
+    let channels = amplifier_window(data, samples)?;
+    let num_samples = channels.first().map_or(0, |values| values.len());
+    let welch = Welch::new(&options.psd, num_samples)?;
+    let bins: Vec<usize> = (0..=welch.fft_length / 2)
+        .filter(|&bin| {
+            options
+                .band
+                .contains(&(bin as f64 * sample_rate / welch.fft_length as f64))
+        })
+        .collect();
+    if bins.is_empty() {
+        return Err(Box::new(IntanError::Other(format!(
+            "No frequency bin lies between {} and {} Hz",
+            options.band.start, options.band.end
+        ))));
+    }
+
+    // Cross spectra of channel i with channels i.. over the band's bins, summed
+    // over the segments
+    let num_channels = channels.len();
+    let mut cross: Vec<Vec<Complex>> = (0..num_channels)
+        .map(|i| vec![Complex::real(0.0); (num_channels - i) * bins.len()])
+        .collect();
+    for segment in 0..welch.num_segments {
+        let spectra: Vec<Vec<Complex>> = channels
+            .par_iter()
+            .map_init(Vec::new, |spectrum, values| {
+                welch.spectrum(values, segment, spectrum);
+                bins.iter().map(|&bin| spectrum[bin]).collect()
+            })
+            .collect();
+        cross.par_iter_mut().enumerate().for_each(|(i, row)| {
+            let pairs = row.chunks_exact_mut(bins.len()).zip(&spectra[i..]);
+            for (sums, other) in pairs {
+                for ((sum, x), y) in sums.iter_mut().zip(&spectra[i]).zip(other) {
+                    *sum = *sum + *x * y.conj();
+                }
+            }
+        });
+    }
+
+    let power = |i: usize| &cross[i][..bins.len()];
+    let rows: Vec<Vec<f64>> = (0..num_channels)
+        .into_par_iter()
+        .map(|i| {
+            cross[i]
+                .chunks_exact(bins.len())
+                .enumerate()
+                .map(|(offset, sums)| {
+                    let (mut total, mut count) = (0.0, 0);
+                    for ((sum, x), y) in sums.iter().zip(power(i)).zip(power(i + offset)) {
+                        if x.re > 0.0 && y.re > 0.0 {
+                            total += sum.norm_sqr() / (x.re * y.re);
+                            count += 1;
+                        }
+                    }
+                    if count == 0 {
+                        f64::NAN
+                    } else {
+                        (total / count as f64).min(1.0)
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    Ok(symmetric(rows))
+}
+
+impl RhsFile {
+    /// Estimates the power spectral density of every channel of an analog signal
+    /// at the recording's sample rate.
+    ///
+    /// See [`psd`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recording has no data, or for the reasons given by
+    /// [`psd`].
+    pub fn psd(&self, signal: SignalType, options: &PsdOptions) -> Result<Psd, Box<dyn Error>> {
+        let data = self.data.as_ref().ok_or_else(|| {
+            IntanError::Other(
+                "The recording has no data to estimate the power spectrum from".into(),
+            )
+        })?;
+        psd(data, signal, f64::from(self.header.sample_rate), options)
+    }
+}
+
+/// Reads every amplifier channel over `samples`
+fn amplifier_window(
+    data: &RhsData,
+    samples: Option<Range<usize>>,
+) -> Result<Vec<Array1<f64>>, Box<dyn Error>> {
+    let num_samples = data.timestamps.len();
+    let samples = samples.unwrap_or(0..num_samples);
+    if samples.is_empty() || samples.end > num_samples {
+        return Err(Box::new(IntanError::Other(format!(
+            "Invalid sample range {}..{} for a recording of {num_samples} samples",
+            samples.start, samples.end
+        ))));
+    }
+
+    // Errors are carried as strings across the worker threads
+    (0..num_channels(data, SpilledSignal::Amplifier))
+        .into_par_iter()
+        .map(|channel| {
+            let values = data.amplifier_channel(channel).map_err(|e| e.to_string())?;
+            Ok(values.slice_move(s![samples.clone()]))
+        })
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(|message| IntanError::Other(message).into())
+}
+
+/// Builds a symmetric matrix from the rows of its upper triangle, each starting
+/// at the diagonal
+fn symmetric(rows: Vec<Vec<f64>>) -> Array2<f64> {
+    let size = rows.len();
+    let mut matrix = Array2::zeros((size, size));
+    for (i, row) in rows.into_iter().enumerate() {
+        for (offset, value) in row.into_iter().enumerate() {
+            matrix[[i, i + offset]] = value;
+            matrix[[i + offset, i]] = value;
+        }
+    }
+    matrix
+}
+
+/// Segmentation of a channel for Welch's method
+struct Welch {
+    segment_length: usize,
+    step: usize,
+    num_segments: usize,
+    fft_length: usize,
+    window: Vec<f64>,
+}
+
+impl Welch {
+    /// Checks `options` and segments `num_samples` samples
+    fn new(options: &PsdOptions, num_samples: usize) -> Result<Welch, Box<dyn Error>> {
+        if options.segment_length == 0 {
+            return Err(Box::new(IntanError::Other(
+                "The PSD segment length must be at least 1 sample".to_string(),
+            )));
+        }
+        if !(0.0..1.0).contains(&options.overlap) {
+            return Err(Box::new(IntanError::Other(format!(
+                "The PSD overlap must be from 0 up to 1, not {}",
+                options.overlap
+            ))));
+        }
+        if num_samples == 0 {
+            return Err(Box::new(IntanError::Other(
+                "There are no samples to estimate the spectrum from".to_string(),
+            )));
+        }
+
+        let segment_length = options.segment_length.min(num_samples);
+        let overlap =
+            ((options.overlap * segment_length as f64).round() as usize).min(segment_length - 1);
+        let step = segment_length - overlap;
+        Ok(Welch {
+            segment_length,
+            step,
+            num_segments: (num_samples - segment_length) / step + 1,
+            fft_length: segment_length.next_power_of_two(),
+            window: options.window.coefficients(segment_length),
+        })
+    }
+
+    /// Fills `spectrum` with the Fourier transform of one segment of `values`,
+    /// with its mean removed, windowed, and zero-padded
+    fn spectrum(&self, values: &Array1<f64>, segment: usize, spectrum: &mut Vec<Complex>) {
+        let start = segment * self.step;
+        let samples = values.slice(s![start..start + self.segment_length]);
+        let mean = samples.sum() / self.segment_length as f64;
+        spectrum.clear();
+        spectrum.extend(
+            samples
+                .iter()
+                .zip(&self.window)
+                .map(|(sample, w)| Complex::real((sample - mean) * w)),
+        );
+        spectrum.resize(self.fft_length, Complex::real(0.0));
+        fft(spectrum);
+    }
+}
+
+/// Returns the factor that converts the amplifier data into μV
+fn amplifier_to_uv(data: &RhsData) -> Result<f64, Box<dyn Error>> {
+    data.units
+        .amplifier
+        .factor_to(Unit::Microvolts)
+        .ok_or_else(|| {
+            IntanError::Other(format!(
+                "Amplifier data in {} cannot be converted to μV",
+                data.units.amplifier
+            ))
+            .into()
+        })
+}
+
+/// Returns the number of channels of `signal`, whichever form it was loaded in
+fn num_channels(data: &RhsData, signal: SpilledSignal) -> usize {
+    let loaded = match signal {
+        SpilledSignal::Amplifier => [
+            data.amplifier_data.as_ref().map(|a| a.nrows()),
+            data.amplifier_data_f32.as_ref().map(|a| a.nrows()),
+            data.amplifier_data_raw.as_ref().map(|a| a.nrows()),
+        ],
+        SpilledSignal::DcAmplifier => [
+            data.dc_amplifier_data.as_ref().map(|a| a.nrows()),
+            data.dc_amplifier_data_f32.as_ref().map(|a| a.nrows()),
+            data.dc_amplifier_data_raw.as_ref().map(|a| a.nrows()),
+        ],
+        SpilledSignal::BoardAdc => [
+            data.board_adc_data.as_ref().map(|a| a.nrows()),
+            data.board_adc_data_f32.as_ref().map(|a| a.nrows()),
+            data.board_adc_data_raw.as_ref().map(|a| a.nrows()),
+        ],
+        SpilledSignal::BoardDac => [
+            data.board_dac_data.as_ref().map(|a| a.nrows()),
+            data.board_dac_data_f32.as_ref().map(|a| a.nrows()),
+            data.board_dac_data_raw.as_ref().map(|a| a.nrows()),
+        ],
+    };
+    loaded.into_iter().flatten().next().unwrap_or_else(|| {
+        data.spilled
+            .as_ref()
+            .map_or(0, |spilled| spilled.num_channels(signal))
+    })
+}
+
+/// Reads one channel of `signal` in the unit of the data
+fn read_channel(
+    data: &RhsData,
+    signal: SpilledSignal,
+    channel: usize,
+) -> Result<Array1<f64>, Box<dyn Error>> {
+    let values = match signal {
+        SpilledSignal::Amplifier => data.amplifier_channel(channel),
+        SpilledSignal::DcAmplifier => data.dc_amplifier_channel(channel),
+        SpilledSignal::BoardAdc => data.board_adc_channel(channel),
+        SpilledSignal::BoardDac => data.board_dac_channel(channel),
+    };
+    Ok(values?)
+}
@@ -0,0 +1,194 @@
+//! Anonymization of identifying metadata.
+//!
+//! Recordings often carry subject IDs or experimenter names in their notes, custom
+//! channel names, and file names. [`RhsFile::anonymize`] strips or pseudonymizes
+//! these fields in place so the recording can be shared publicly. The RHX settings
+//! sidecar is removed whenever notes or channel names are anonymized. Because exporters
+//! read from the `RhsFile`, anything exported after anonymization carries the same
+//! redactions.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::anonymize::{AnonymizeMode, AnonymizeOptions};
+//! use intan_importer::load;
+//!
+//! let mut rhs_file = load("subject_042_session.rhs").unwrap();
+//! rhs_file.anonymize(&AnonymizeOptions {
+//!     mode: AnonymizeMode::Hash { salt: "lab-secret".to_string() },
+//!     ..AnonymizeOptions::default()
+//! });
+//! println!("Note 1 is now: {}", rhs_file.header.notes.note1);
+//! ```
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::types::{LoadWarning, RhsFile};
+
+/// FNV-1a 64-bit offset basis
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+/// FNV-1a 64-bit prime
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// How identifying fields are replaced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnonymizeMode {
+    /// Remove the content entirely (notes become empty, channel names revert to
+    /// their native names, file names become `recording_NNN.rhs`)
+    Strip,
+    /// Replace the content with a salted hash, so identical values remain
+    /// identifiable across files without revealing the original text.
+    ///
+    /// The hash is a fast non-cryptographic hash; keep the salt secret and prefer
+    /// [`AnonymizeMode::Strip`] when values could be guessed by brute force.
+    Hash {
+        /// Secret salt mixed into every hash
+        salt: String,
+    },
+}
+
+/// Selects which fields are anonymized and how.
+#[derive(Debug, Clone)]
+pub struct AnonymizeOptions {
+    /// Replacement mode
+    pub mode: AnonymizeMode,
+    /// Anonymize the three header notes
+    pub notes: bool,
+    /// Anonymize custom channel names of all channel types
+    pub channel_names: bool,
+    /// Anonymize the source and skipped file paths of combined recordings and the
+    /// paths in the load warnings and file fingerprints, and remove the recording
+    /// start time taken from the file names
+    pub source_paths: bool,
+}
+
+impl Default for AnonymizeOptions {
+    fn default() -> Self {
+        AnonymizeOptions {
+            mode: AnonymizeMode::Strip,
+            notes: true,
+            channel_names: true,
+            source_paths: true,
+        }
+    }
+}
+
+impl RhsFile {
+    /// Strips or hashes identifying metadata in place.
+    ///
+    /// See the [`anonymize`](crate::anonymize) module for details.
+    pub fn anonymize(&mut self, options: &AnonymizeOptions) {
+        if options.notes {
+            let notes = &mut self.header.notes;
+            for note in [&mut notes.note1, &mut notes.note2, &mut notes.note3] {
+                if !note.is_empty() {
+                    *note = match &options.mode {
+                        AnonymizeMode::Strip => String::new(),
+                        AnonymizeMode::Hash { salt } => hash_value(salt, note),
+                    };
+                }
+            }
+        }
+
+        if options.channel_names {
+            let header = &mut self.header;
+            let channel_lists = [
+                &mut header.amplifier_channels,
+                &mut header.aux_input_channels,
+                &mut header.supply_voltage_channels,
+                &mut header.board_adc_channels,
+                &mut header.board_dac_channels,
+                &mut header.board_dig_in_channels,
+                &mut header.board_dig_out_channels,
+            ];
+            for channels in channel_lists {
+                for channel in channels.iter_mut() {
+                    // Names that were never customized carry no identifying information
+                    if channel.custom_channel_name == channel.native_channel_name {
+                        continue;
+                    }
+                    channel.custom_channel_name = match &options.mode {
+                        AnonymizeMode::Strip => channel.native_channel_name.clone(),
+                        AnonymizeMode::Hash { salt } => {
+                            hash_value(salt, &channel.custom_channel_name)
+                        }
+                    };
+                }
+            }
+        }
+
+        // The settings sidecar can repeat notes and custom channel names in its
+        // free-form sections, so it is dropped rather than partially redacted
+        if options.notes || options.channel_names {
+            self.settings = None;
+        }
+
+        if options.source_paths {
+            // The start time comes from the file names
+            self.recording_start = None;
+            let mut names = HashMap::new();
+            let sources = self
+                .source_files
+                .iter_mut()
+                .flatten()
+                .map(|source| &mut source.path);
+            let skipped = self
+                .skipped_files
+                .iter_mut()
+                .map(|skipped| &mut skipped.path);
+            for (i, path) in sources.chain(skipped).enumerate() {
+                let name = anonymize_path(&options.mode, i, path);
+                names
+                    .entry(std::mem::take(path))
+                    .or_insert_with(|| name.clone());
+                *path = name;
+            }
+
+            // Warnings about and fingerprints of those files get the same names, and
+            // other files new ones
+            let mut index =
+                self.source_files.as_ref().map_or(0, Vec::len) + self.skipped_files.len();
+            let fingerprints = self
+                .fingerprints
+                .iter_mut()
+                .map(|fingerprint| &mut fingerprint.path);
+            let warnings = self.warnings.iter_mut().filter_map(LoadWarning::path_mut);
+            for path in warnings.chain(fingerprints) {
+                if path.is_empty() {
+                    continue;
+                }
+                *path = names
+                    .entry(std::mem::take(path))
+                    .or_insert_with_key(|path| {
+                        index += 1;
+                        anonymize_path(&options.mode, index - 1, path)
+                    })
+                    .clone();
+            }
+        }
+    }
+}
+
+/// Replaces a file path with an anonymous file name, keeping the extension.
+fn anonymize_path(mode: &AnonymizeMode, index: usize, path: &str) -> String {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("rhs");
+
+    match mode {
+        AnonymizeMode::Strip => format!("recording_{:03}.{}", index, extension),
+        AnonymizeMode::Hash { salt } => format!("{}.{}", hash_value(salt, path), extension),
+    }
+}
+
+/// Returns a salted FNV-1a hash of `value` as a 16-digit hex string.
+fn hash_value(salt: &str, value: &str) -> String {
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in salt.bytes().chain([0u8]).chain(value.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    format!("{:016x}", hash)
+}
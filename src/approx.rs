@@ -0,0 +1,275 @@
+//! Approximate comparison of recorded data.
+//!
+//! Exporters, writers, and processing stages often reproduce data only up to
+//! quantization or floating-point rounding. [`RhsData::approx_eq`] compares two
+//! datasets with a separate absolute tolerance for each analog signal type, while
+//! timestamps, digital lines, stimulation flags, and raw codes must match exactly.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::approx::Tolerances;
+//! use intan_importer::load;
+//!
+//! let original = load("original.rhs").unwrap();
+//! let resaved = load("resaved.rhs").unwrap();
+//!
+//! let (a, b) = (original.data.unwrap(), resaved.data.unwrap());
+//! let mismatches = a.approx_diff(&b, &Tolerances::default());
+//! assert!(mismatches.is_empty(), "data differs: {:?}", mismatches);
+//! ```
+
+use ndarray::{Array2, Zip};
+use std::fmt;
+
+use crate::reader::{ADC_DAC_SCALE_FACTOR, AMPLIFIER_SCALE_FACTOR, DC_AMPLIFIER_SCALE_FACTOR};
+use crate::flags::StimFlags;
+use crate::types::RhsData;
+
+/// Absolute tolerances for each analog signal type, in the units the loader produces.
+///
+/// The default allows half of one ADC step for every voltage signal, which is the
+/// error introduced by re-quantizing scaled data. The stimulation step size
+/// depends on the recording, so stimulation current must match exactly unless
+/// `stim` is set (e.g. to half of the header's `stim_step_size`).
+#[derive(Debug, Clone)]
+pub struct Tolerances {
+    /// Tolerance for amplifier data (μV)
+    pub amplifier: f64,
+    /// Tolerance for DC amplifier data (V)
+    pub dc_amplifier: f64,
+    /// Tolerance for stimulation current data (μA)
+    pub stim: f64,
+    /// Tolerance for board ADC data (V)
+    pub board_adc: f64,
+    /// Tolerance for board DAC data (V)
+    pub board_dac: f64,
+}
+
+impl Default for Tolerances {
+    fn default() -> Self {
+        Tolerances {
+            amplifier: AMPLIFIER_SCALE_FACTOR / 2.0,
+            dc_amplifier: DC_AMPLIFIER_SCALE_FACTOR / 1000.0 / 2.0,
+            stim: 0.0,
+            board_adc: ADC_DAC_SCALE_FACTOR / 2.0,
+            board_dac: ADC_DAC_SCALE_FACTOR / 2.0,
+        }
+    }
+}
+
+impl Tolerances {
+    /// Tolerances requiring exact equality of every signal.
+    pub fn exact() -> Self {
+        Tolerances {
+            amplifier: 0.0,
+            dc_amplifier: 0.0,
+            stim: 0.0,
+            board_adc: 0.0,
+            board_dac: 0.0,
+        }
+    }
+}
+
+/// A signal that differs between two datasets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataMismatch {
+    /// Name of the differing field, e.g. `amplifier_data`
+    pub signal: &'static str,
+    /// Human-readable description of the first difference found
+    pub description: String,
+}
+
+impl fmt::Display for DataMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.signal, self.description)
+    }
+}
+
+impl RhsData {
+    /// Returns true if both datasets match within the given tolerances.
+    pub fn approx_eq(&self, other: &RhsData, tolerances: &Tolerances) -> bool {
+        self.approx_diff(other, tolerances).is_empty()
+    }
+
+    /// Returns one entry for every signal that differs beyond its tolerance.
+    ///
+    /// Each entry describes the first differing sample and how many samples differ.
+    pub fn approx_diff(&self, other: &RhsData, tolerances: &Tolerances) -> Vec<DataMismatch> {
+        let mut mismatches = Vec::new();
+
+        if self.units != other.units {
+            mismatches.push(DataMismatch {
+                signal: "units",
+                description: format!("{:?} vs {:?}", self.units, other.units),
+            });
+        }
+
+        if self.timestamps != other.timestamps {
+            let description = if self.timestamps.len() != other.timestamps.len() {
+                format!("length {} vs {}", self.timestamps.len(), other.timestamps.len())
+            } else {
+                let (i, (a, b)) = self
+                    .timestamps
+                    .iter()
+                    .zip(other.timestamps.iter())
+                    .enumerate()
+                    .find(|(_, (a, b))| a != b)
+                    .unwrap_or((0, (&0, &0)));
+                format!("first difference at sample {}: {} vs {}", i, a, b)
+            };
+            mismatches.push(DataMismatch {
+                signal: "timestamps",
+                description,
+            });
+        }
+
+        let float_signals = [
+            ("amplifier_data", &self.amplifier_data, &other.amplifier_data, tolerances.amplifier),
+            (
+                "dc_amplifier_data",
+                &self.dc_amplifier_data,
+                &other.dc_amplifier_data,
+                tolerances.dc_amplifier,
+            ),
+            ("stim_data", &self.stim_data, &other.stim_data, tolerances.stim),
+            ("board_adc_data", &self.board_adc_data, &other.board_adc_data, tolerances.board_adc),
+            ("board_dac_data", &self.board_dac_data, &other.board_dac_data, tolerances.board_dac),
+        ];
+        for (signal, a, b, tolerance) in float_signals {
+            compare_signal(&mut mismatches, signal, a, b, |x: &f64, y: &f64| {
+                (x - y).abs() <= tolerance || (x.is_nan() && y.is_nan())
+            });
+        }
+
+        let single_signals = [
+            (
+                "amplifier_data_f32",
+                &self.amplifier_data_f32,
+                &other.amplifier_data_f32,
+                tolerances.amplifier,
+            ),
+            (
+                "dc_amplifier_data_f32",
+                &self.dc_amplifier_data_f32,
+                &other.dc_amplifier_data_f32,
+                tolerances.dc_amplifier,
+            ),
+            (
+                "board_adc_data_f32",
+                &self.board_adc_data_f32,
+                &other.board_adc_data_f32,
+                tolerances.board_adc,
+            ),
+            (
+                "board_dac_data_f32",
+                &self.board_dac_data_f32,
+                &other.board_dac_data_f32,
+                tolerances.board_dac,
+            ),
+        ];
+        for (signal, a, b, tolerance) in single_signals {
+            compare_signal(&mut mismatches, signal, a, b, |x: &f32, y: &f32| {
+                (x - y).abs() as f64 <= tolerance || (x.is_nan() && y.is_nan())
+            });
+        }
+
+        let flag_signals = [
+            (
+                "compliance_limit_data",
+                &self.compliance_limit_data,
+                &other.compliance_limit_data,
+            ),
+            (
+                "charge_recovery_data",
+                &self.charge_recovery_data,
+                &other.charge_recovery_data,
+            ),
+            ("amp_settle_data", &self.amp_settle_data, &other.amp_settle_data),
+        ];
+        for (signal, a, b) in flag_signals {
+            // Flags are only expanded to arrays to locate a difference
+            if a != b {
+                let a = a.as_ref().map(StimFlags::to_array);
+                let b = b.as_ref().map(StimFlags::to_array);
+                compare_signal(&mut mismatches, signal, &a, &b, |x, y| x == y);
+            }
+        }
+
+        let digital_signals = [
+            ("board_dig_in_data", &self.board_dig_in_data, &other.board_dig_in_data),
+            ("board_dig_out_data", &self.board_dig_out_data, &other.board_dig_out_data),
+        ];
+        for (signal, a, b) in digital_signals {
+            compare_signal(&mut mismatches, signal, a, b, |x, y| x == y);
+        }
+
+        let raw_signals = [
+            ("amplifier_data_raw", &self.amplifier_data_raw, &other.amplifier_data_raw),
+            ("dc_amplifier_data_raw", &self.dc_amplifier_data_raw, &other.dc_amplifier_data_raw),
+            ("board_adc_data_raw", &self.board_adc_data_raw, &other.board_adc_data_raw),
+            ("board_dac_data_raw", &self.board_dac_data_raw, &other.board_dac_data_raw),
+        ];
+        for (signal, a, b) in raw_signals {
+            compare_signal(&mut mismatches, signal, a, b, |x, y| x == y);
+        }
+
+        mismatches
+    }
+}
+
+/// Compares one optional signal, recording a mismatch for presence, shape, or value differences.
+fn compare_signal<T, F>(
+    mismatches: &mut Vec<DataMismatch>,
+    signal: &'static str,
+    a: &Option<Array2<T>>,
+    b: &Option<Array2<T>>,
+    close: F,
+) where
+    T: fmt::Debug,
+    F: Fn(&T, &T) -> bool,
+{
+    let (a, b) = match (a, b) {
+        (None, None) => return,
+        (Some(a), Some(b)) => (a, b),
+        (a, _) => {
+            mismatches.push(DataMismatch {
+                signal,
+                description: format!(
+                    "present in {} dataset only",
+                    if a.is_some() { "first" } else { "second" }
+                ),
+            });
+            return;
+        }
+    };
+
+    if a.shape() != b.shape() {
+        mismatches.push(DataMismatch {
+            signal,
+            description: format!("shape {:?} vs {:?}", a.shape(), b.shape()),
+        });
+        return;
+    }
+
+    let mut first = None;
+    let mut count = 0usize;
+    Zip::indexed(a).and(b).for_each(|(ch, s), x, y| {
+        if !close(x, y) {
+            if first.is_none() {
+                first = Some(format!(
+                    "channel {}, sample {}: {:?} vs {:?}",
+                    ch, s, x, y
+                ));
+            }
+            count += 1;
+        }
+    });
+
+    if let Some(first) = first {
+        mismatches.push(DataMismatch {
+            signal,
+            description: format!("{} samples differ; first at {}", count, first),
+        });
+    }
+}
@@ -0,0 +1,559 @@
+//! Synthetic recordings for tests and examples.
+//!
+//! [`RhsFileBuilder`] creates a complete, valid [`RhsFile`] without a recording on
+//! disk: a header for the requested channel counts and sample rate, and data with
+//! sine waves on amplifier channels, biphasic stimulation pulses, and digital
+//! input pulses added on request. Tests of code built on this crate can run on
+//! such fixtures instead of proprietary data files.
+//!
+//! The data is generated as the 16-bit codes an RHS file stores and converted the
+//! same way the loader converts them, so a built recording is identical to the one
+//! loaded back from its [`to_bytes`](RhsFileBuilder::to_bytes) or
+//! [`save`](RhsFileBuilder::save) output. Values are therefore rounded to whole
+//! ADC steps (0.195 μV for amplifier data) and stimulation currents to whole
+//! multiples of the stimulation step size.
+//!
+//! # Examples
+//!
+//! ```
+//! use intan_importer::RhsFileBuilder;
+//!
+//! let rhs_file = RhsFileBuilder::new()
+//!     .sample_rate(20000.0)
+//!     .num_samples(128 * 50)
+//!     .amplifier_channels(8)
+//!     .digital_inputs(2)
+//!     .sine(0, 10.0, 100.0)
+//!     .stim_pulse(1, 1000, 4, 20.0)
+//!     .digital_pulse(0, 1000..1200)
+//!     .build()
+//!     .unwrap();
+//!
+//! let data = rhs_file.data.as_ref().unwrap();
+//! assert_eq!(data.amplifier_data.as_ref().unwrap().dim(), (8, 6400));
+//! assert_eq!(data.stim_data.as_ref().unwrap()[[1, 1000]], -20.0);
+//! assert_eq!(data.board_dig_in_data.as_ref().unwrap()[[0, 1100]], 1);
+//!
+//! // The same recording as the contents of an .rhs file
+//! let bytes = RhsFileBuilder::new().amplifier_channels(2).to_bytes().unwrap();
+//! ```
+
+use std::error::Error;
+use std::f64::consts::PI;
+use std::ops::Range;
+use std::path::Path;
+
+use ndarray::{Array1, Array2};
+
+use crate::loader::LoadOptions;
+use crate::reader::{
+    self, RawData, ADC_DAC_OFFSET, AMPLIFIER_SCALE_FACTOR, DC_AMPLIFIER_OFFSET,
+    SAMPLES_PER_DATA_BLOCK,
+};
+use crate::types::{
+    ChannelInfo, FrequencyParameters, IntanError, Notes, RhsFile, RhsHeader, SpikeTrigger,
+    StimParameters, Version,
+};
+
+/// Amplifier channels per port, and the ports they are assigned to in turn
+const CHANNELS_PER_PORT: usize = 32;
+const PORTS: [&str; 4] = ["A", "B", "C", "D"];
+
+/// Builder of synthetic RHS recordings.
+///
+/// By default, a recording has 4 amplifier channels and no other channels, and
+/// 12,800 samples (100 data blocks) at 30 kHz with timestamps starting at 0. All
+/// signals are zero except for the sine waves and pulses that are added.
+#[derive(Debug, Clone)]
+pub struct RhsFileBuilder {
+    sample_rate: f32,
+    num_samples: usize,
+    first_timestamp: i32,
+    num_amplifier_channels: usize,
+    dc_amplifier: bool,
+    num_board_adc_channels: usize,
+    num_board_dac_channels: usize,
+    num_digital_inputs: usize,
+    num_digital_outputs: usize,
+    stim_step_size: f32,
+    notes: [String; 3],
+    sines: Vec<Sine>,
+    stim_pulses: Vec<StimPulse>,
+    digital_pulses: Vec<(usize, Range<usize>)>,
+}
+
+/// A sine wave added to an amplifier channel
+#[derive(Debug, Clone)]
+struct Sine {
+    channel: usize,
+    frequency: f64,
+    amplitude: f64,
+}
+
+/// A biphasic, cathodic-first stimulation pulse
+#[derive(Debug, Clone)]
+struct StimPulse {
+    channel: usize,
+    start: usize,
+    phase_samples: usize,
+    amplitude: f64,
+}
+
+impl Default for RhsFileBuilder {
+    fn default() -> Self {
+        RhsFileBuilder {
+            sample_rate: 30000.0,
+            num_samples: 100 * SAMPLES_PER_DATA_BLOCK,
+            first_timestamp: 0,
+            num_amplifier_channels: 4,
+            dc_amplifier: false,
+            num_board_adc_channels: 0,
+            num_board_dac_channels: 0,
+            num_digital_inputs: 0,
+            num_digital_outputs: 0,
+            stim_step_size: 1.0,
+            notes: Default::default(),
+            sines: Vec::new(),
+            stim_pulses: Vec::new(),
+            digital_pulses: Vec::new(),
+        }
+    }
+}
+
+impl RhsFileBuilder {
+    /// Creates a builder with the default settings.
+    pub fn new() -> Self {
+        RhsFileBuilder::default()
+    }
+
+    /// Sets the sample rate (Hz).
+    pub fn sample_rate(mut self, sample_rate: f32) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Sets the number of samples per channel.
+    ///
+    /// Only recordings of whole data blocks, a multiple of 128 samples, can be
+    /// serialized.
+    pub fn num_samples(mut self, num_samples: usize) -> Self {
+        self.num_samples = num_samples;
+        self
+    }
+
+    /// Sets the timestamp of the first sample; later samples count up by one.
+    pub fn first_timestamp(mut self, first_timestamp: i32) -> Self {
+        self.first_timestamp = first_timestamp;
+        self
+    }
+
+    /// Sets the number of amplifier channels, at most 128.
+    ///
+    /// Channels are assigned to ports A to D, 32 per port, and named as RHX names
+    /// them (e.g. "A-000").
+    pub fn amplifier_channels(mut self, count: usize) -> Self {
+        self.num_amplifier_channels = count;
+        self
+    }
+
+    /// Sets whether DC amplifier data is saved, as zeros.
+    pub fn dc_amplifier(mut self, saved: bool) -> Self {
+        self.dc_amplifier = saved;
+        self
+    }
+
+    /// Sets the number of board ADC channels, at most 8.
+    pub fn board_adc_channels(mut self, count: usize) -> Self {
+        self.num_board_adc_channels = count;
+        self
+    }
+
+    /// Sets the number of board DAC channels, at most 8.
+    pub fn board_dac_channels(mut self, count: usize) -> Self {
+        self.num_board_dac_channels = count;
+        self
+    }
+
+    /// Sets the number of board digital inputs, at most 16.
+    pub fn digital_inputs(mut self, count: usize) -> Self {
+        self.num_digital_inputs = count;
+        self
+    }
+
+    /// Sets the number of board digital outputs, at most 16.
+    pub fn digital_outputs(mut self, count: usize) -> Self {
+        self.num_digital_outputs = count;
+        self
+    }
+
+    /// Sets the stimulation current step size (μA) stored in the header.
+    pub fn stim_step_size(mut self, stim_step_size: f32) -> Self {
+        self.stim_step_size = stim_step_size;
+        self
+    }
+
+    /// Sets the three notes stored in the header.
+    pub fn notes(mut self, note1: &str, note2: &str, note3: &str) -> Self {
+        self.notes = [note1.to_string(), note2.to_string(), note3.to_string()];
+        self
+    }
+
+    /// Adds a sine wave of `frequency` (Hz) and `amplitude` (μV) to an amplifier
+    /// channel, with phase zero at the first sample.
+    ///
+    /// Sine waves added to the same channel are summed.
+    pub fn sine(mut self, channel: usize, frequency: f64, amplitude: f64) -> Self {
+        self.sines.push(Sine {
+            channel,
+            frequency,
+            amplitude,
+        });
+        self
+    }
+
+    /// Adds a biphasic stimulation pulse to an amplifier channel.
+    ///
+    /// The pulse starts at sample `start` with a cathodic phase of `-amplitude`
+    /// (μA), followed by an anodic phase of `amplitude`, each `phase_samples` long.
+    /// Samples past the end of the recording are left out.
+    pub fn stim_pulse(
+        mut self,
+        channel: usize,
+        start: usize,
+        phase_samples: usize,
+        amplitude: f64,
+    ) -> Self {
+        self.stim_pulses.push(StimPulse {
+            channel,
+            start,
+            phase_samples,
+            amplitude,
+        });
+        self
+    }
+
+    /// Sets a digital input high over a range of samples.
+    ///
+    /// Samples past the end of the recording are left out.
+    pub fn digital_pulse(mut self, input: usize, samples: Range<usize>) -> Self {
+        self.digital_pulses.push((input, samples));
+        self
+    }
+
+    /// Builds the recording.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a channel count exceeds its limit, the sample rate is
+    /// not positive, a sine wave or pulse refers to a channel that does not exist,
+    /// or a stimulation amplitude exceeds the 255 steps a stimulation word holds.
+    pub fn build(&self) -> Result<RhsFile, Box<dyn Error>> {
+        self.check()?;
+        let header = self.header();
+        let data_present = self.num_samples > 0;
+        let data = if data_present {
+            let options = LoadOptions {
+                verbose: false,
+                ..LoadOptions::default()
+            };
+            Some(reader::process_data(
+                &header,
+                self.raw_data(&header),
+                &options,
+            )?)
+        } else {
+            None
+        };
+
+        Ok(RhsFile {
+            header,
+            data,
+            data_present,
+            source_files: None,
+            skipped_files: Vec::new(),
+            fingerprints: Vec::new(),
+            warnings: Vec::new(),
+            settings: None,
+            recording_start: None,
+        })
+    }
+
+    /// Builds the recording and serializes it as the contents of an .rhs file.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`build`](RhsFileBuilder::build), and an error if the
+    /// number of samples is not a multiple of 128.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut bytes = Vec::new();
+        self.build()?.write_to(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Builds the recording and saves it as an .rhs file.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`to_bytes`](RhsFileBuilder::to_bytes), and an error
+    /// if the file cannot be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        self.build()?.save(path)
+    }
+
+    /// Checks the settings against the limits of the RHS format
+    fn check(&self) -> Result<(), Box<dyn Error>> {
+        let error = |message: String| Err(IntanError::Other(message).into());
+        if !(self.sample_rate > 0.0 && self.sample_rate.is_finite()) {
+            return error(format!("Invalid sample rate {} Hz", self.sample_rate));
+        }
+        for (count, limit, what) in [
+            (
+                self.num_amplifier_channels,
+                PORTS.len() * CHANNELS_PER_PORT,
+                "amplifier channels",
+            ),
+            (self.num_board_adc_channels, 8, "board ADC channels"),
+            (self.num_board_dac_channels, 8, "board DAC channels"),
+            (self.num_digital_inputs, 16, "digital inputs"),
+            (self.num_digital_outputs, 16, "digital outputs"),
+        ] {
+            if count > limit {
+                return error(format!("An RHS recording has at most {} {}", limit, what));
+            }
+        }
+
+        let channels = self.sines.iter().map(|sine| sine.channel);
+        let channels = channels.chain(self.stim_pulses.iter().map(|pulse| pulse.channel));
+        if let Some(channel) = channels.clone().find(|&c| c >= self.num_amplifier_channels) {
+            return error(format!("There is no amplifier channel {}", channel));
+        }
+        if let Some((input, _)) = self
+            .digital_pulses
+            .iter()
+            .find(|(input, _)| *input >= self.num_digital_inputs)
+        {
+            return error(format!("There is no digital input {}", input));
+        }
+        let max_amplitude = 255.0 * self.stim_step_size as f64;
+        if let Some(pulse) = self
+            .stim_pulses
+            .iter()
+            .find(|p| p.amplitude.abs() > max_amplitude)
+        {
+            return error(format!(
+                "Stimulation amplitude {} μA exceeds 255 steps of {} μA",
+                pulse.amplitude, self.stim_step_size
+            ));
+        }
+        Ok(())
+    }
+
+    /// Creates the header for the requested channels
+    fn header(&self) -> RhsHeader {
+        let channel =
+            |port_name: &str, port_prefix: &str, name: String, order: usize| ChannelInfo {
+                port_name: port_name.to_string(),
+                port_prefix: port_prefix.to_string(),
+                port_number: 0,
+                native_channel_name: name.clone(),
+                custom_channel_name: name,
+                native_order: order as i32,
+                custom_order: order as i32,
+                chip_channel: order as i32,
+                command_stream: 0,
+                board_stream: 0,
+                electrode_impedance_magnitude: 0.0,
+                electrode_impedance_phase: 0.0,
+            };
+
+        let amplifier_channels = (0..self.num_amplifier_channels)
+            .map(|i| {
+                let (port, order) = (PORTS[i / CHANNELS_PER_PORT], i % CHANNELS_PER_PORT);
+                ChannelInfo {
+                    chip_channel: (order % 16) as i32,
+                    board_stream: (i / 16) as i32,
+                    ..channel(
+                        &format!("Port {}", port),
+                        port,
+                        format!("{}-{:03}", port, order),
+                        order,
+                    )
+                }
+            })
+            .collect();
+        let board_channels = |count: usize, port_name: &str, prefix: &str, width: usize| {
+            (0..count)
+                .map(|i| {
+                    channel(
+                        port_name,
+                        prefix,
+                        format!("{}-{:0width$}", prefix, i + 1),
+                        i,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let frequency_parameters = FrequencyParameters {
+            amplifier_sample_rate: self.sample_rate,
+            board_adc_sample_rate: self.sample_rate,
+            board_dig_in_sample_rate: self.sample_rate,
+            desired_dsp_cutoff_frequency: 1.0,
+            actual_dsp_cutoff_frequency: 1.0,
+            dsp_enabled: 1,
+            desired_lower_bandwidth: 0.1,
+            desired_lower_settle_bandwidth: 1000.0,
+            actual_lower_bandwidth: 0.1,
+            actual_lower_settle_bandwidth: 1000.0,
+            desired_upper_bandwidth: 7500.0,
+            actual_upper_bandwidth: 7500.0,
+            notch_filter_frequency: None,
+            desired_impedance_test_frequency: 1000.0,
+            actual_impedance_test_frequency: 1000.0,
+        };
+        let stim_parameters = StimParameters {
+            stim_step_size: self.stim_step_size,
+            charge_recovery_current_limit: 1.0,
+            charge_recovery_target_voltage: 0.0,
+            amp_settle_mode: 0,
+            charge_recovery_mode: 0,
+        };
+
+        RhsHeader {
+            version: Version { major: 3, minor: 0 },
+            sample_rate: self.sample_rate,
+            num_samples_per_data_block: SAMPLES_PER_DATA_BLOCK as i32,
+            dsp_enabled: frequency_parameters.dsp_enabled,
+            actual_dsp_cutoff_frequency: frequency_parameters.actual_dsp_cutoff_frequency,
+            actual_lower_bandwidth: frequency_parameters.actual_lower_bandwidth,
+            actual_lower_settle_bandwidth: frequency_parameters.actual_lower_settle_bandwidth,
+            actual_upper_bandwidth: frequency_parameters.actual_upper_bandwidth,
+            desired_dsp_cutoff_frequency: frequency_parameters.desired_dsp_cutoff_frequency,
+            desired_lower_bandwidth: frequency_parameters.desired_lower_bandwidth,
+            desired_lower_settle_bandwidth: frequency_parameters.desired_lower_settle_bandwidth,
+            desired_upper_bandwidth: frequency_parameters.desired_upper_bandwidth,
+            notch_filter_frequency: None,
+            desired_impedance_test_frequency: frequency_parameters.desired_impedance_test_frequency,
+            actual_impedance_test_frequency: frequency_parameters.actual_impedance_test_frequency,
+            amp_settle_mode: stim_parameters.amp_settle_mode,
+            charge_recovery_mode: stim_parameters.charge_recovery_mode,
+            stim_step_size: self.stim_step_size,
+            recovery_current_limit: stim_parameters.charge_recovery_current_limit,
+            recovery_target_voltage: stim_parameters.charge_recovery_target_voltage,
+            notes: Notes {
+                note1: self.notes[0].clone(),
+                note2: self.notes[1].clone(),
+                note3: self.notes[2].clone(),
+            },
+            dc_amplifier_data_saved: self.dc_amplifier,
+            eval_board_mode: 0,
+            reference_channel: String::new(),
+            amplifier_channels,
+            spike_triggers: (0..self.num_amplifier_channels)
+                .map(|_| SpikeTrigger {
+                    voltage_trigger_mode: 0,
+                    voltage_threshold: 0,
+                    digital_trigger_channel: 0,
+                    digital_edge_polarity: 0,
+                })
+                .collect(),
+            aux_input_channels: Vec::new(),
+            supply_voltage_channels: Vec::new(),
+            board_adc_channels: board_channels(
+                self.num_board_adc_channels,
+                "Analog In",
+                "ANALOG-IN",
+                1,
+            ),
+            board_dac_channels: board_channels(
+                self.num_board_dac_channels,
+                "Analog Out",
+                "ANALOG-OUT",
+                1,
+            ),
+            board_dig_in_channels: board_channels(
+                self.num_digital_inputs,
+                "Digital In",
+                "DIGITAL-IN",
+                2,
+            ),
+            board_dig_out_channels: board_channels(
+                self.num_digital_outputs,
+                "Digital Out",
+                "DIGITAL-OUT",
+                2,
+            ),
+            frequency_parameters,
+            stim_parameters,
+        }
+    }
+
+    /// Generates the 16-bit codes an RHS file would store for the data
+    fn raw_data(&self, header: &RhsHeader) -> RawData {
+        let n = self.num_samples;
+        // Counting on in 64 bits matches the unwrapped timestamps of a loaded file
+        let first_timestamp = self.first_timestamp as i64;
+        let timestamps = Array1::from_iter(first_timestamp..first_timestamp + n as i64);
+        let codes =
+            |count: usize, value: u16| (count > 0).then(|| Array2::from_elem((count, n), value));
+
+        let num_amplifier_channels = header.amplifier_channels.len();
+        let mut amplifier = Array2::<f64>::zeros((num_amplifier_channels, n));
+        for sine in &self.sines {
+            let step = 2.0 * PI * sine.frequency / self.sample_rate as f64;
+            for (j, value) in amplifier.row_mut(sine.channel).iter_mut().enumerate() {
+                *value += sine.amplitude * (step * j as f64).sin();
+            }
+        }
+        let amplifier = (num_amplifier_channels > 0).then(|| {
+            amplifier.mapv(|uv| {
+                (uv / AMPLIFIER_SCALE_FACTOR + ADC_DAC_OFFSET)
+                    .round()
+                    .clamp(0.0, 65535.0) as u16
+            })
+        });
+
+        let mut stim = codes(num_amplifier_channels, 0);
+        if let Some(stim) = &mut stim {
+            for pulse in &self.stim_pulses {
+                let steps = (pulse.amplitude.abs() / self.stim_step_size as f64).round() as u16;
+                let (cathodic, anodic) = if pulse.amplitude < 0.0 {
+                    (steps, steps | 256)
+                } else {
+                    (steps | 256, steps)
+                };
+                let phase = pulse.start..pulse.start + pulse.phase_samples;
+                let next_phase = phase.end..phase.end + pulse.phase_samples;
+                for (samples, word) in [(phase, cathodic), (next_phase, anodic)] {
+                    for j in samples.take_while(|&j| j < n) {
+                        stim[[pulse.channel, j]] = word;
+                    }
+                }
+            }
+        }
+
+        let digital_words = |count: usize, pulses: &[(usize, Range<usize>)]| {
+            (count > 0).then(|| {
+                let mut words = Array2::<u16>::zeros((1, n));
+                for (input, samples) in pulses {
+                    for j in samples.clone().take_while(|&j| j < n) {
+                        words[[0, j]] |= 1 << input;
+                    }
+                }
+                words
+            })
+        };
+
+        RawData {
+            timestamps,
+            amplifier_data_raw: amplifier,
+            dc_amplifier_data_raw: codes(num_amplifier_channels, DC_AMPLIFIER_OFFSET as u16)
+                .filter(|_| self.dc_amplifier),
+            stim_data_raw: stim,
+            board_adc_data_raw: codes(self.num_board_adc_channels, ADC_DAC_OFFSET as u16),
+            board_dac_data_raw: codes(self.num_board_dac_channels, ADC_DAC_OFFSET as u16),
+            board_dig_in_raw: digital_words(self.num_digital_inputs, &self.digital_pulses),
+            board_dig_out_raw: digital_words(self.num_digital_outputs, &[]),
+        }
+    }
+}
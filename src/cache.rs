@@ -0,0 +1,142 @@
+//! Header caching through JSON sidecar files.
+//!
+//! Cataloging a directory of recordings only needs their headers, but each header
+//! read still opens and parses a binary file. [`read_header_cached`] keeps a copy
+//! of every header in a `<name>.intan-header.json` sidecar next to the recording.
+//! The sidecar records the recording's size and modification time, and is used
+//! only while both still match, so edited or replaced recordings are re-read.
+//!
+//! Sidecars are plain JSON, holding the header in the layout used by the
+//! exporters' metadata files.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::cache::read_header_cached;
+//!
+//! // The first call parses the file and writes recording.intan-header.json;
+//! // later calls read the sidecar while the recording is unchanged.
+//! let header = read_header_cached("recording.rhs").unwrap();
+//! println!("{} amplifier channels", header.amplifier_channels.len());
+//! ```
+
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::export::{header_from_json, header_json};
+use crate::json::Json;
+use crate::reader;
+use crate::types::{IntanError, RhsHeader};
+
+/// Extension of header sidecar files, replacing the recording's extension
+pub const SIDECAR_EXTENSION: &str = "intan-header.json";
+
+/// Version of the sidecar layout; sidecars with another version are ignored
+const SIDECAR_VERSION: i32 = 1;
+
+/// Returns the path of the header sidecar for a recording.
+pub fn sidecar_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    path.as_ref().with_extension(SIDECAR_EXTENSION)
+}
+
+/// Reads the header of an RHS file, using its sidecar when it is up to date.
+///
+/// On a cache miss the header is parsed from the file and a new sidecar is
+/// written. Failing to write the sidecar (for example in a read-only directory)
+/// is not an error; the header is still returned.
+///
+/// # Errors
+///
+/// Returns an error if the recording cannot be read or has an invalid header.
+pub fn read_header_cached<P: AsRef<Path>>(path: P) -> Result<RhsHeader, IntanError> {
+    let path = path.as_ref();
+    if let Some(header) = read_header_sidecar(path)? {
+        return Ok(header);
+    }
+
+    let header = reader::read_header_file(path)?;
+    let _ = write_header_sidecar(path, &header);
+    Ok(header)
+}
+
+/// Returns the header stored in a recording's sidecar, if the sidecar is up to date.
+///
+/// Returns `None` if there is no sidecar, it cannot be parsed, or it was written
+/// for a different size or modification time of the recording.
+///
+/// # Errors
+///
+/// Returns an error if the recording itself cannot be accessed.
+pub fn read_header_sidecar<P: AsRef<Path>>(path: P) -> Result<Option<RhsHeader>, IntanError> {
+    let path = path.as_ref();
+    let Some(stamp) =
+        file_stamp(&fs::metadata(path).map_err(|e| IntanError::from(e).in_file(path, None))?)
+    else {
+        return Ok(None);
+    };
+    let Ok(text) = fs::read_to_string(sidecar_path(path)) else {
+        return Ok(None);
+    };
+    let Ok(json) = Json::parse(&text) else {
+        return Ok(None);
+    };
+
+    let number = |key: &str| json.get(key).and_then(Json::as_f64);
+    let fresh = number("sidecar_version") == Some(SIDECAR_VERSION as f64)
+        && number("file_size") == Some(stamp.size as f64)
+        && number("modified_seconds") == Some(stamp.seconds as f64)
+        && number("modified_nanos") == Some(stamp.nanos as f64);
+    if !fresh {
+        return Ok(None);
+    }
+    Ok(json
+        .get("header")
+        .and_then(|header| header_from_json(header).ok()))
+}
+
+/// Writes the sidecar for a recording, replacing any existing one.
+///
+/// # Errors
+///
+/// Returns an error if the recording's size or modification time cannot be read,
+/// or the sidecar cannot be written.
+pub fn write_header_sidecar<P: AsRef<Path>>(path: P, header: &RhsHeader) -> Result<(), IntanError> {
+    let path = path.as_ref();
+    let stamp =
+        file_stamp(&fs::metadata(path).map_err(|e| IntanError::from(e).in_file(path, None))?)
+            .ok_or_else(|| {
+                IntanError::Other(format!(
+                    "Cannot cache the header of '{}': its modification time is unavailable",
+                    path.display()
+                ))
+            })?;
+
+    let sidecar = Json::object(vec![
+        ("sidecar_version", SIDECAR_VERSION.into()),
+        ("file_size", stamp.size.into()),
+        ("modified_seconds", stamp.seconds.into()),
+        ("modified_nanos", (stamp.nanos as usize).into()),
+        ("header", header_json(header)),
+    ]);
+    let sidecar_path = sidecar_path(path);
+    fs::write(&sidecar_path, sidecar.to_string_pretty())
+        .map_err(|e| IntanError::from(e).in_file(&sidecar_path, None))?;
+    Ok(())
+}
+
+/// Size and modification time identifying one version of a file
+struct FileStamp {
+    size: u64,
+    seconds: u64,
+    nanos: u32,
+}
+
+fn file_stamp(metadata: &Metadata) -> Option<FileStamp> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(FileStamp {
+        size: metadata.len(),
+        seconds: modified.as_secs(),
+        nanos: modified.subsec_nanos(),
+    })
+}
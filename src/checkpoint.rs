@@ -0,0 +1,257 @@
+//! Checkpointing for long directory jobs.
+//!
+//! Combining or converting a multi-hour session can take long enough that an
+//! interruption (a crash, a full disk, a closed laptop) is likely. A [`Checkpoint`]
+//! records which source files have been fully processed, together with the
+//! position in the job's output that was reached after each one, and persists this
+//! after every file. When the job is restarted with the same checkpoint, finished
+//! files are skipped and the output can be truncated to the last recorded position
+//! before continuing.
+//!
+//! [`process_directory_resumable`] drives this loop for a directory of RHS files.
+//! Files are the unit of work, so at most one file is redone after an interruption.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::checkpoint::{process_directory_resumable, Checkpoint};
+//! use std::fs::OpenOptions;
+//! use std::io::{Seek, SeekFrom, Write};
+//!
+//! let mut checkpoint = Checkpoint::open("session.checkpoint").unwrap();
+//! let mut output = OpenOptions::new()
+//!     .create(true)
+//!     .write(true)
+//!     .truncate(false)
+//!     .open("session_amplifier.bin")
+//!     .unwrap();
+//!
+//! // Discard anything written after the last checkpoint
+//! output.set_len(checkpoint.output_position()).unwrap();
+//! output.seek(SeekFrom::End(0)).unwrap();
+//!
+//! process_directory_resumable("session/", &mut checkpoint, |_path, rhs_file| {
+//!     if let Some(amp) = rhs_file.data.as_ref().and_then(|d| d.amplifier_data.as_ref()) {
+//!         for sample in amp.t().iter() {
+//!             output.write_all(&(*sample as f32).to_le_bytes())?;
+//!         }
+//!     }
+//!     output.sync_data()?;
+//!     Ok(output.stream_position()?)
+//! }).unwrap();
+//!
+//! checkpoint.finish().unwrap();
+//! ```
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::loader::{HeaderCompatibility, LoadOptions};
+use crate::reader;
+use crate::types::{IntanError, RhsFile};
+
+/// First line of every checkpoint file
+const CHECKPOINT_HEADER: &str = "intan_importer checkpoint v1";
+
+/// A source file that was fully processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckpointEntry {
+    /// Path of the source file
+    pub path: PathBuf,
+    /// Size of the source file in bytes when it was processed
+    pub size: u64,
+    /// Modification time of the source file (seconds since the Unix epoch)
+    pub modified: u64,
+    /// Output position recorded after the file was processed
+    pub output_position: u64,
+}
+
+/// Persistent record of the files a job has completed.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    path: PathBuf,
+    entries: Vec<CheckpointEntry>,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint at `path`, or starts an empty one if the file does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Checkpoint, IntanError> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            return Ok(Checkpoint {
+                path,
+                entries: Vec::new(),
+            });
+        }
+
+        let mut lines = BufReader::new(File::open(&path)?).lines();
+        match lines.next() {
+            Some(Ok(line)) if line == CHECKPOINT_HEADER => {}
+            _ => return Err(IntanError::UnrecognizedFileFormat.in_file(&path, None)),
+        }
+
+        let mut entries = Vec::new();
+        for line in lines {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.splitn(4, '\t');
+            let mut number = || -> Result<u64, IntanError> {
+                let field = fields.next().ok_or_else(|| malformed(&line))?;
+                field.parse().map_err(|_| malformed(&line))
+            };
+            let size = number()?;
+            let modified = number()?;
+            let output_position = number()?;
+            let file = fields.next().ok_or_else(|| malformed(&line))?;
+            entries.push(CheckpointEntry {
+                path: PathBuf::from(file),
+                size,
+                modified,
+                output_position,
+            });
+        }
+
+        Ok(Checkpoint { path, entries })
+    }
+
+    /// Files completed so far, in the order they were completed.
+    pub fn entries(&self) -> &[CheckpointEntry] {
+        &self.entries
+    }
+
+    /// Output position after the last completed file, or 0 if nothing is done yet.
+    pub fn output_position(&self) -> u64 {
+        self.entries.last().map_or(0, |entry| entry.output_position)
+    }
+
+    /// Returns true if `file` was completed and has not changed since.
+    pub fn is_done<P: AsRef<Path>>(&self, file: P) -> bool {
+        let file = file.as_ref();
+        let identity = match file_identity(file) {
+            Ok(identity) => identity,
+            Err(_) => return false,
+        };
+        self.entries
+            .iter()
+            .any(|entry| entry.path == file && (entry.size, entry.modified) == identity)
+    }
+
+    /// Records `file` as completed and writes the checkpoint to disk.
+    ///
+    /// The checkpoint is written to a temporary file and renamed into place, so an
+    /// interruption never leaves a partially written checkpoint behind.
+    pub fn mark_done<P: AsRef<Path>>(
+        &mut self,
+        file: P,
+        output_position: u64,
+    ) -> Result<(), IntanError> {
+        let file = file.as_ref();
+        let (size, modified) = file_identity(file)?;
+        self.entries.retain(|entry| entry.path != file);
+        self.entries.push(CheckpointEntry {
+            path: file.to_path_buf(),
+            size,
+            modified,
+            output_position,
+        });
+        self.save()
+    }
+
+    /// Removes the checkpoint file once the job has finished.
+    pub fn finish(self) -> Result<(), IntanError> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), IntanError> {
+        let mut temp_name = self.path.as_os_str().to_owned();
+        temp_name.push(".tmp");
+        let temp_path = PathBuf::from(temp_name);
+
+        let mut file = File::create(&temp_path)?;
+        writeln!(file, "{}", CHECKPOINT_HEADER)?;
+        for entry in &self.entries {
+            writeln!(
+                file,
+                "{}\t{}\t{}\t{}",
+                entry.size,
+                entry.modified,
+                entry.output_position,
+                entry.path.display()
+            )?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Processes every RHS file in a directory that `checkpoint` has not recorded as done.
+///
+/// Files are visited in the same order [`load`](crate::load) combines them. For
+/// each one, `process` receives the loaded file and returns the output position
+/// reached afterwards, which is then recorded in the checkpoint. Headers are
+/// checked for compatibility with the first file, as when combining.
+///
+/// Before calling this, discard any output written after
+/// [`Checkpoint::output_position`]; after it succeeds, call [`Checkpoint::finish`].
+///
+/// # Errors
+///
+/// Returns the errors of loading the files and of updating the checkpoint. An
+/// error returned by `process` is converted to an [`IntanError`]: an
+/// `IntanError` or I/O error is kept as it is, and any other error as
+/// [`IntanError::Other`] with its message.
+pub fn process_directory_resumable<P, F>(
+    dir_path: P,
+    checkpoint: &mut Checkpoint,
+    mut process: F,
+) -> Result<(), IntanError>
+where
+    P: AsRef<Path>,
+    F: FnMut(&Path, RhsFile) -> Result<u64, Box<dyn Error>>,
+{
+    let rhs_files = crate::find_rhs_files(dir_path.as_ref())?;
+    let first_header = reader::read_header_file(&rhs_files[0])?;
+
+    for file in &rhs_files {
+        if checkpoint.is_done(file) {
+            continue;
+        }
+
+        let rhs_file = reader::load_file(file, &LoadOptions::default(), &mut [])?;
+        reader::verify_header_compatibility(
+            &first_header,
+            &rhs_file.header,
+            file,
+            HeaderCompatibility::Standard,
+        )
+        .map_err(|e| e.in_file(file, None))?;
+
+        let output_position = process(file, rhs_file)?;
+        checkpoint.mark_done(file, output_position)?;
+    }
+
+    Ok(())
+}
+
+/// Size and modification time used to detect files changed after they were processed.
+fn file_identity(file: &Path) -> Result<(u64, u64), IntanError> {
+    let metadata = fs::metadata(file)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    Ok((metadata.len(), modified))
+}
+
+fn malformed(line: &str) -> IntanError {
+    IntanError::Other(format!("Malformed checkpoint entry: {}", line))
+}
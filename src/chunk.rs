@@ -0,0 +1,371 @@
+//! Re-chunking of recorded data into caller-specified block sizes.
+//!
+//! Intan files store data in fixed 128-sample blocks, but downstream writers often
+//! want chunks of their own size, such as one second of data aligned to whole
+//! seconds of recording time. This module provides:
+//!
+//! - [`RhsData::chunks`] for iterating over loaded data as zero-copy views
+//! - [`Rechunker`], a [`BlockProcessor`] that regroups blocks into owned chunks
+//!   while a file is being loaded
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::chunk::ChunkAlignment;
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! let one_second = rhs_file.header.sample_rate as usize;
+//!
+//! if let Some(data) = &rhs_file.data {
+//!     for chunk in data.chunks(one_second, ChunkAlignment::Timestamp) {
+//!         println!("chunk {} starts at sample {} ({} samples)",
+//!                  chunk.index, chunk.sample_offset, chunk.data.num_samples());
+//!     }
+//! }
+//! ```
+
+use ndarray::{concatenate, Array2, ArrayView1, ArrayView2, Axis};
+use std::error::Error;
+
+use crate::flags::{StimFlags, StimFlagsView};
+use crate::processor::{BlockProcessor, DataBlockMut};
+use crate::types::{IntanError, RhsData, RhsDataView};
+
+/// How chunk boundaries are placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkAlignment {
+    /// Chunks start at the first sample and every `chunk_samples` samples after it
+    Start,
+    /// Chunks start at timestamps that are whole multiples of `chunk_samples`.
+    ///
+    /// The first and last chunks may be shorter. Chunks are also split where the
+    /// timestamps jump across a multiple, so gaps never shift the alignment.
+    Timestamp,
+}
+
+/// A chunk of data borrowed from a loaded recording.
+#[derive(Debug, Clone)]
+pub struct Chunk<'a> {
+    /// Index of this chunk (0-based)
+    pub index: usize,
+    /// Index of the first sample of this chunk within the source data
+    pub sample_offset: usize,
+    /// View of the chunk's data
+    pub data: RhsDataView<'a>,
+}
+
+/// A chunk of data owned by the caller, produced by [`Rechunker`].
+#[derive(Debug, Clone)]
+pub struct OwnedChunk {
+    /// Index of this chunk (0-based)
+    pub index: usize,
+    /// Index of the first sample of this chunk within the loaded stream
+    pub sample_offset: usize,
+    /// The chunk's data
+    pub data: RhsData,
+}
+
+/// Iterator over chunks of loaded data, created by [`RhsData::chunks`].
+pub struct Chunks<'a> {
+    view: RhsDataView<'a>,
+    chunk_samples: usize,
+    alignment: ChunkAlignment,
+    position: usize,
+    index: usize,
+}
+
+impl RhsData {
+    /// Iterates over the data in chunks of at most `chunk_samples` samples.
+    ///
+    /// Chunks are zero-copy views into this data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_samples` is zero.
+    pub fn chunks(&self, chunk_samples: usize, alignment: ChunkAlignment) -> Chunks<'_> {
+        assert!(chunk_samples > 0, "chunk size must be at least one sample");
+        Chunks {
+            view: self.view(),
+            chunk_samples,
+            alignment,
+            position: 0,
+            index: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Chunk<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let num_samples = self.view.num_samples();
+        if self.position >= num_samples {
+            return None;
+        }
+
+        let start = self.position;
+        let end = chunk_end(
+            &self.view.timestamps,
+            start,
+            self.chunk_samples,
+            self.alignment,
+        );
+
+        let chunk = Chunk {
+            index: self.index,
+            sample_offset: start,
+            data: self.view.slice_samples(start, end),
+        };
+
+        self.position = end;
+        self.index += 1;
+        Some(chunk)
+    }
+}
+
+/// Finds the exclusive end of the chunk starting at `start`.
+fn chunk_end(
+    timestamps: &ArrayView1<'_, i64>,
+    start: usize,
+    chunk_samples: usize,
+    alignment: ChunkAlignment,
+) -> usize {
+    let limit = (start + chunk_samples).min(timestamps.len());
+
+    match alignment {
+        ChunkAlignment::Start => limit,
+        ChunkAlignment::Timestamp => (start + 1..limit)
+            .find(|&i| crosses_boundary(timestamps[i - 1], timestamps[i], chunk_samples))
+            .unwrap_or(limit),
+    }
+}
+
+/// Returns true if a chunk boundary lies in `(previous, current]`.
+fn crosses_boundary(previous: i64, current: i64, chunk_samples: usize) -> bool {
+    let n = chunk_samples as i64;
+    current.div_euclid(n) != previous.div_euclid(n)
+}
+
+/// Block processor that regroups data into fixed-size chunks during loading.
+///
+/// Each completed chunk is passed to the callback as an [`OwnedChunk`]. Chunks
+/// continue across file boundaries when loading a directory. Because the final
+/// chunk is usually partial, call [`Rechunker::flush`] after loading to emit it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use intan_importer::chunk::{ChunkAlignment, OwnedChunk, Rechunker};
+/// use intan_importer::load_with_processors;
+///
+/// let mut rechunker = Rechunker::new(30_000, ChunkAlignment::Timestamp, |chunk: OwnedChunk| {
+///     println!("chunk {}: {} samples", chunk.index, chunk.data.num_samples());
+///     Ok(())
+/// });
+/// load_with_processors("recording.rhs", &mut [&mut rechunker]).unwrap();
+/// rechunker.flush().unwrap();
+/// ```
+pub struct Rechunker<F>
+where
+    F: FnMut(OwnedChunk) -> Result<(), Box<dyn Error>>,
+{
+    chunk_samples: usize,
+    alignment: ChunkAlignment,
+    callback: F,
+    pending: Vec<RhsData>,
+    pending_samples: usize,
+    last_timestamp: Option<i64>,
+    sample_offset: usize,
+    index: usize,
+}
+
+impl<F> Rechunker<F>
+where
+    F: FnMut(OwnedChunk) -> Result<(), Box<dyn Error>>,
+{
+    /// Creates a rechunker emitting chunks of at most `chunk_samples` samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_samples` is zero.
+    pub fn new(chunk_samples: usize, alignment: ChunkAlignment, callback: F) -> Self {
+        assert!(chunk_samples > 0, "chunk size must be at least one sample");
+        Rechunker {
+            chunk_samples,
+            alignment,
+            callback,
+            pending: Vec::new(),
+            pending_samples: 0,
+            last_timestamp: None,
+            sample_offset: 0,
+            index: 0,
+        }
+    }
+
+    /// Emits any buffered samples as a final, possibly partial chunk.
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.pending_samples == 0 {
+            return Ok(());
+        }
+
+        let views: Vec<RhsDataView<'_>> = self.pending.iter().map(|d| d.view()).collect();
+        let data = concat_views(&views)?;
+        let chunk = OwnedChunk {
+            index: self.index,
+            sample_offset: self.sample_offset,
+            data,
+        };
+
+        self.sample_offset += self.pending_samples;
+        self.index += 1;
+        self.pending.clear();
+        self.pending_samples = 0;
+
+        (self.callback)(chunk)
+    }
+
+    /// Adds the samples of `view` after those already buffered, emitting every chunk
+    /// they complete.
+    pub(crate) fn push(&mut self, view: RhsDataView<'_>) -> Result<(), Box<dyn Error>> {
+        let num_samples = view.num_samples();
+        let mut start = 0;
+
+        while start < num_samples {
+            // Close the pending chunk if the first sample starts a new aligned chunk
+            if self.alignment == ChunkAlignment::Timestamp && self.pending_samples > 0 {
+                if let Some(previous) = self.last_timestamp {
+                    if crosses_boundary(previous, view.timestamps[start], self.chunk_samples) {
+                        self.flush()?;
+                    }
+                }
+            }
+
+            let remaining = self.chunk_samples - self.pending_samples;
+            let limit = (start + remaining).min(num_samples);
+            let end = match self.alignment {
+                ChunkAlignment::Start => limit,
+                ChunkAlignment::Timestamp => (start + 1..limit)
+                    .find(|&i| {
+                        crosses_boundary(
+                            view.timestamps[i - 1],
+                            view.timestamps[i],
+                            self.chunk_samples,
+                        )
+                    })
+                    .unwrap_or(limit),
+            };
+
+            self.pending.push(view.slice_samples(start, end).to_owned());
+            self.pending_samples += end - start;
+            self.last_timestamp = Some(view.timestamps[end - 1]);
+
+            if self.pending_samples == self.chunk_samples || end < limit {
+                self.flush()?;
+            }
+
+            start = end;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F> BlockProcessor for Rechunker<F>
+where
+    F: FnMut(OwnedChunk) -> Result<(), Box<dyn Error>>,
+{
+    fn process_block(&mut self, block: &mut DataBlockMut<'_>) -> Result<(), Box<dyn Error>> {
+        self.push(block.view())
+    }
+}
+
+/// Concatenates views along the sample axis into a new `RhsData`.
+///
+/// All views must contain the same set of signals with matching channel counts.
+pub(crate) fn concat_views(views: &[RhsDataView<'_>]) -> Result<RhsData, Box<dyn Error>> {
+    if views.is_empty() {
+        return Err(Box::new(IntanError::Other(
+            "Cannot concatenate an empty list of data".to_string(),
+        )));
+    }
+
+    let units = views[0].units;
+    if views.iter().any(|v| v.units != units) {
+        return Err(Box::new(IntanError::Other(
+            "Cannot concatenate data recorded in differing units".to_string(),
+        )));
+    }
+
+    let timestamps: Vec<ArrayView1<'_, i64>> = views.iter().map(|v| v.timestamps).collect();
+
+    Ok(RhsData {
+        timestamps: concatenate(Axis(0), &timestamps)?,
+        amplifier_data: concat_field(views, |v| v.amplifier_data)?,
+        dc_amplifier_data: concat_field(views, |v| v.dc_amplifier_data)?,
+        stim_data: concat_field(views, |v| v.stim_data)?,
+        compliance_limit_data: concat_flags(views, |v| v.compliance_limit_data)?,
+        charge_recovery_data: concat_flags(views, |v| v.charge_recovery_data)?,
+        amp_settle_data: concat_flags(views, |v| v.amp_settle_data)?,
+        board_adc_data: concat_field(views, |v| v.board_adc_data)?,
+        board_dac_data: concat_field(views, |v| v.board_dac_data)?,
+        board_dig_in_data: concat_field(views, |v| v.board_dig_in_data)?,
+        board_dig_out_data: concat_field(views, |v| v.board_dig_out_data)?,
+        amplifier_data_raw: concat_field(views, |v| v.amplifier_data_raw)?,
+        dc_amplifier_data_raw: concat_field(views, |v| v.dc_amplifier_data_raw)?,
+        board_adc_data_raw: concat_field(views, |v| v.board_adc_data_raw)?,
+        board_dac_data_raw: concat_field(views, |v| v.board_dac_data_raw)?,
+        amplifier_data_f32: concat_field(views, |v| v.amplifier_data_f32)?,
+        dc_amplifier_data_f32: concat_field(views, |v| v.dc_amplifier_data_f32)?,
+        board_adc_data_f32: concat_field(views, |v| v.board_adc_data_f32)?,
+        board_dac_data_f32: concat_field(views, |v| v.board_dac_data_f32)?,
+        spilled: None,
+        units,
+    })
+}
+
+/// Concatenates one optional set of stimulation flags across views, requiring it to
+/// be present in all or none.
+fn concat_flags<'a, G>(
+    views: &[RhsDataView<'a>],
+    get: G,
+) -> Result<Option<StimFlags>, Box<dyn Error>>
+where
+    G: Fn(&RhsDataView<'a>) -> Option<StimFlagsView<'a>>,
+{
+    let parts: Vec<StimFlagsView<'a>> = views.iter().filter_map(&get).collect();
+
+    if parts.is_empty() {
+        return Ok(None);
+    }
+    if parts.len() != views.len() {
+        return Err(Box::new(IntanError::Other(
+            "Cannot concatenate data with differing signal types".to_string(),
+        )));
+    }
+
+    Ok(Some(StimFlags::concat(&parts)?))
+}
+
+/// Concatenates one optional signal across views, requiring it to be present in all or none.
+fn concat_field<'a, T, G>(
+    views: &[RhsDataView<'a>],
+    get: G,
+) -> Result<Option<Array2<T>>, Box<dyn Error>>
+where
+    T: Clone + 'a,
+    G: Fn(&RhsDataView<'a>) -> Option<ArrayView2<'a, T>>,
+{
+    let parts: Vec<ArrayView2<'a, T>> = views.iter().filter_map(&get).collect();
+
+    if parts.is_empty() {
+        return Ok(None);
+    }
+    if parts.len() != views.len() {
+        return Err(Box::new(IntanError::Other(
+            "Cannot concatenate data with differing signal types".to_string(),
+        )));
+    }
+
+    Ok(Some(concatenate(Axis(1), &parts)?))
+}
@@ -0,0 +1,312 @@
+//! Wall-clock dates and times of recordings.
+//!
+//! RHX names every file it saves after the local date and time at which it
+//! started the file, as `name_YYMMDD_HHMMSS.rhs`, and names the directories of
+//! "one file per signal type" and "one file per channel" recordings the same way.
+//! The loader parses this time into [`RhsFile::recording_start`], the wall-clock
+//! time of the first loaded sample, from which
+//! [`RhsFile::timestamp_to_wallclock`] and [`RhsFile::sample_to_wallclock`] place
+//! any sample on the wall clock, so recordings can be aligned with behavioral or
+//! video systems.
+//!
+//! The names carry no time zone, so a [`DateTime`] is a plain local date and time,
+//! and they resolve whole seconds only: wall-clock times are as accurate as the
+//! recording computer's clock, to within a second.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("mouse12_230105_101500.rhs").unwrap();
+//! if let Some(start) = rhs_file.recording_start {
+//!     println!("Recording started at {start}");
+//!     // Wall-clock time of the sample 10 s into the recording
+//!     let index = (10.0 * rhs_file.header.sample_rate) as usize;
+//!     let time = rhs_file.sample_to_wallclock(index).unwrap();
+//!     println!("Sample {index} was recorded at {time}");
+//! }
+//! ```
+
+use std::fmt;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::types::RhsFile;
+
+const NANOSECONDS_PER_SECOND: i128 = 1_000_000_000;
+const SECONDS_PER_DAY: i128 = 86_400;
+
+/// A calendar date and time of day, without a time zone.
+///
+/// Dates are in the proleptic Gregorian calendar. Comparing two values compares
+/// them in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DateTime {
+    /// Year
+    pub year: i32,
+    /// Month, from 1 to 12
+    pub month: u8,
+    /// Day of the month, from 1
+    pub day: u8,
+    /// Hour, from 0 to 23
+    pub hour: u8,
+    /// Minute, from 0 to 59
+    pub minute: u8,
+    /// Second, from 0 to 59
+    pub second: u8,
+    /// Fraction of the second in nanoseconds, below 1 000 000 000
+    pub nanosecond: u32,
+}
+
+impl DateTime {
+    /// Creates a date and time at a whole second, or returns `None` if any field is
+    /// out of range.
+    pub fn new(
+        year: i32,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Option<DateTime> {
+        let valid = (1..=12).contains(&month)
+            && day >= 1
+            && day <= days_in_month(year, month)
+            && hour < 24
+            && minute < 60
+            && second < 60;
+        valid.then_some(DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            nanosecond: 0,
+        })
+    }
+
+    /// Parses the start time RHX appends to file and directory names,
+    /// `_YYMMDD_HHMMSS`, as in `mouse12_230105_101500.rhs`.
+    ///
+    /// Two-digit years are taken to be in the 2000s. Returns `None` if the name
+    /// does not end with a valid date and time.
+    pub fn from_rhx_filename<P: AsRef<Path>>(path: P) -> Option<DateTime> {
+        let stem = path.as_ref().file_stem()?.to_str()?;
+        let mut parts = stem.rsplit('_');
+        let time = parts.next()?;
+        let date = parts.next()?;
+        // The base name before the date may be empty
+        parts.next()?;
+
+        let digits = |text: &str| -> Option<[u8; 3]> {
+            if text.len() != 6 || !text.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let pair = |i: usize| text[i..i + 2].parse::<u8>().ok();
+            Some([pair(0)?, pair(2)?, pair(4)?])
+        };
+        let [year, month, day] = digits(date)?;
+        let [hour, minute, second] = digits(time)?;
+        DateTime::new(2000 + i32::from(year), month, day, hour, minute, second)
+    }
+
+    /// Returns the date and time `seconds` later, or earlier for a negative value,
+    /// rounded to the nanosecond.
+    pub fn add_seconds(&self, seconds: f64) -> DateTime {
+        let offset = (seconds * NANOSECONDS_PER_SECOND as f64).round() as i128;
+        DateTime::from_nanoseconds(self.nanoseconds() + offset)
+    }
+
+    /// Returns the number of seconds from `earlier` to this time, negative if
+    /// `earlier` is in fact later.
+    pub fn seconds_since(&self, earlier: &DateTime) -> f64 {
+        (self.nanoseconds() - earlier.nanoseconds()) as f64 / NANOSECONDS_PER_SECOND as f64
+    }
+
+    /// Nanoseconds since 1970-01-01 00:00:00 on the same clock
+    fn nanoseconds(&self) -> i128 {
+        let days = days_from_civil(self.year, self.month, self.day) as i128;
+        let seconds = days * SECONDS_PER_DAY
+            + i128::from(self.hour) * 3600
+            + i128::from(self.minute) * 60
+            + i128::from(self.second);
+        seconds * NANOSECONDS_PER_SECOND + i128::from(self.nanosecond)
+    }
+
+    fn from_nanoseconds(nanoseconds: i128) -> DateTime {
+        let seconds = nanoseconds.div_euclid(NANOSECONDS_PER_SECOND);
+        let days = seconds.div_euclid(SECONDS_PER_DAY);
+        let second_of_day = seconds.rem_euclid(SECONDS_PER_DAY) as u32;
+        let (year, month, day) = civil_from_days(days as i64);
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (second_of_day / 3600) as u8,
+            minute: (second_of_day / 60 % 60) as u8,
+            second: (second_of_day % 60) as u8,
+            nanosecond: nanoseconds.rem_euclid(NANOSECONDS_PER_SECOND) as u32,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    /// Formats as ISO 8601, `2023-01-05T10:15:00`, with the fraction of the second
+    /// only when it is not zero.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )?;
+        if self.nanosecond != 0 {
+            let fraction = format!("{:09}", self.nanosecond);
+            write!(f, ".{}", fraction.trim_end_matches('0'))?;
+        }
+        Ok(())
+    }
+}
+
+impl RhsFile {
+    /// Returns the wall-clock time at which the sample with `timestamp` was
+    /// recorded, or `None` if the start of the recording is unknown or no data
+    /// is present.
+    ///
+    /// The time is counted from [`recording_start`](RhsFile::recording_start)
+    /// using the difference between `timestamp` and the first loaded timestamp, so
+    /// it also holds for timestamps outside the loaded data.
+    pub fn timestamp_to_wallclock(&self, timestamp: i64) -> Option<DateTime> {
+        let start = self.recording_start?;
+        let first = *self.data.as_ref()?.timestamps.first()?;
+        let seconds = (timestamp - first) as f64 / self.header.sample_rate as f64;
+        Some(start.add_seconds(seconds))
+    }
+
+    /// Returns the wall-clock time at which the sample at `index` was recorded, or
+    /// `None` if the start of the recording is unknown or there is no such sample.
+    ///
+    /// See [`timestamp_to_wallclock`](RhsFile::timestamp_to_wallclock).
+    pub fn sample_to_wallclock(&self, index: usize) -> Option<DateTime> {
+        let timestamp = *self.data.as_ref()?.timestamps.get(index)?;
+        self.timestamp_to_wallclock(timestamp)
+    }
+}
+
+/// A calendar date and time in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct UtcDateTime {
+    pub(crate) year: i64,
+    pub(crate) month: u32,
+    pub(crate) day: u32,
+    pub(crate) hour: u32,
+    pub(crate) minute: u32,
+    pub(crate) second: u32,
+}
+
+impl UtcDateTime {
+    /// Converts seconds since the Unix epoch to a calendar date (proleptic Gregorian)
+    pub(crate) fn from_unix_seconds(seconds: i64) -> UtcDateTime {
+        let (days, time_of_day) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+        let (year, month, day) = civil_from_days(days);
+
+        UtcDateTime {
+            year: i64::from(year),
+            month: u32::from(month),
+            day: u32::from(day),
+            hour: (time_of_day / 3600) as u32,
+            minute: (time_of_day % 3600 / 60) as u32,
+            second: (time_of_day % 60) as u32,
+        }
+    }
+
+    /// Converts a system time, truncated to whole seconds
+    pub(crate) fn from_system_time(time: SystemTime) -> UtcDateTime {
+        let seconds = match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => after.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs_f64().ceil() as i64),
+        };
+        UtcDateTime::from_unix_seconds(seconds)
+    }
+
+    /// Takes the fields of a date and time without a time zone as they are,
+    /// dropping the fraction of the second
+    pub(crate) fn from_local(time: DateTime) -> UtcDateTime {
+        UtcDateTime {
+            year: i64::from(time.year),
+            month: u32::from(time.month),
+            day: u32::from(time.day),
+            hour: u32::from(time.hour),
+            minute: u32::from(time.minute),
+            second: u32::from(time.second),
+        }
+    }
+
+    /// The current time
+    pub(crate) fn now() -> UtcDateTime {
+        UtcDateTime::from_system_time(SystemTime::now())
+    }
+
+    /// Extended ISO 8601 format with an explicit UTC offset, e.g. `2024-03-15T14:30:00+00:00`
+    #[cfg(any(feature = "nwb", feature = "mat"))]
+    pub(crate) fn to_iso8601(self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}+00:00",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+
+    /// Basic ISO 8601 format, e.g. `20240315T143000Z`
+    #[cfg(feature = "remote")]
+    pub(crate) fn to_iso8601_basic(self) -> String {
+        format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+fn is_leap_year(year: i32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from 1970-01-01 to the given date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    let year = i64::from(year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The date `days` after 1970-01-01, as year, month, and day
+fn civil_from_days(days: i64) -> (i32, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u8;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u8;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year as i32, month, day)
+}
@@ -0,0 +1,333 @@
+//! Deinterleaving of sample words into per-channel rows.
+//!
+//! RHS data blocks, and the `.dat` files of the "one file per signal type" format,
+//! store the samples of a signal type frame by frame: the words of every channel
+//! for the first sample, then for the second sample, and so on. The loaded arrays
+//! hold one row per channel instead, so every word is moved to a transposed
+//! position while reading, which makes this transpose the hot path of loading.
+//!
+//! [`deinterleave`] transposes whole tiles of 8 channels by 8 samples with SSE2
+//! instructions on x86-64, and falls back to [`deinterleave_scalar`] for the
+//! remaining words, for channel selections, and on other architectures.
+//!
+//! # Examples
+//!
+//! ```
+//! use intan_importer::deinterleave::deinterleave;
+//! use ndarray::Array2;
+//!
+//! // Two frames of three channels
+//! let words = [10, 20, 30, 11, 21, 31];
+//! let mut rows = Array2::zeros((3, 2));
+//! deinterleave(&words, 3, None, rows.view_mut());
+//! assert_eq!(rows, ndarray::arr2(&[[10, 11], [20, 21], [30, 31]]));
+//! ```
+
+use ndarray::ArrayViewMut2;
+
+/// Copies interleaved words into one row per channel.
+///
+/// `interleaved` holds frames of `num_channels` words. Row `i` of `out` receives
+/// the words of channel `rows[i]`, or of channel `i` if `rows` is `None`, and
+/// `out` must have one column per frame.
+///
+/// # Panics
+///
+/// Panics if the length of `interleaved` is not a multiple of `num_channels`, if
+/// the shape of `out` does not match, or if a selected channel does not exist.
+pub fn deinterleave(
+    interleaved: &[u16],
+    num_channels: usize,
+    rows: Option<&[usize]>,
+    mut out: ArrayViewMut2<'_, u16>,
+) {
+    check_shapes(interleaved, num_channels, rows, &out);
+
+    #[cfg(target_arch = "x86_64")]
+    if rows.is_none() && out.ncols() > 1 && out.strides()[1] == 1 {
+        let num_frames = out.ncols();
+        let (frames, channels) = sse2::deinterleave_tiles(interleaved, num_channels, &mut out);
+        copy_region(
+            interleaved,
+            num_channels,
+            None,
+            &mut out,
+            channels.end..num_channels,
+            0..num_frames,
+        );
+        copy_region(
+            interleaved,
+            num_channels,
+            None,
+            &mut out,
+            channels,
+            frames.end..num_frames,
+        );
+        return;
+    }
+
+    let (num_rows, num_frames) = out.dim();
+    copy_region(
+        interleaved,
+        num_channels,
+        rows,
+        &mut out,
+        0..num_rows,
+        0..num_frames,
+    );
+}
+
+/// Copies interleaved words into one row per channel, one word at a time.
+///
+/// Produces the same result as [`deinterleave`] on every architecture.
+///
+/// # Panics
+///
+/// Panics under the same conditions as [`deinterleave`].
+pub fn deinterleave_scalar(
+    interleaved: &[u16],
+    num_channels: usize,
+    rows: Option<&[usize]>,
+    mut out: ArrayViewMut2<'_, u16>,
+) {
+    check_shapes(interleaved, num_channels, rows, &out);
+
+    let (num_rows, num_frames) = out.dim();
+    copy_region(
+        interleaved,
+        num_channels,
+        rows,
+        &mut out,
+        0..num_rows,
+        0..num_frames,
+    );
+}
+
+fn check_shapes(
+    interleaved: &[u16],
+    num_channels: usize,
+    rows: Option<&[usize]>,
+    out: &ArrayViewMut2<'_, u16>,
+) {
+    let num_frames = if num_channels == 0 {
+        0
+    } else {
+        assert!(
+            interleaved.len().is_multiple_of(num_channels),
+            "{} words do not form whole frames of {} channels",
+            interleaved.len(),
+            num_channels
+        );
+        interleaved.len() / num_channels
+    };
+    let num_rows = rows.map_or(num_channels, <[usize]>::len);
+    assert_eq!(
+        out.dim(),
+        (num_rows, num_frames),
+        "output shape does not match the input"
+    );
+    if let Some(rows) = rows {
+        assert!(
+            rows.iter().all(|&ch| ch < num_channels),
+            "selected channel does not exist"
+        );
+    }
+}
+
+/// Copies the given output rows and frames word by word
+fn copy_region(
+    interleaved: &[u16],
+    num_channels: usize,
+    rows: Option<&[usize]>,
+    out: &mut ArrayViewMut2<'_, u16>,
+    out_rows: std::ops::Range<usize>,
+    frames: std::ops::Range<usize>,
+) {
+    for row in out_rows {
+        let ch = rows.map_or(row, |rows| rows[row]);
+        let mut out_row = out.row_mut(row);
+        for frame in frames.clone() {
+            out_row[frame] = interleaved[frame * num_channels + ch];
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use std::arch::x86_64::*;
+    use std::ops::Range;
+
+    use ndarray::ArrayViewMut2;
+
+    /// Transposes every whole tile of 8 channels by 8 frames into `out`, whose rows
+    /// must be contiguous
+    ///
+    /// Returns the frames and the channels covered by tiles; the words outside
+    /// them are left for the caller.
+    pub(super) fn deinterleave_tiles(
+        interleaved: &[u16],
+        num_channels: usize,
+        out: &mut ArrayViewMut2<'_, u16>,
+    ) -> (Range<usize>, Range<usize>) {
+        let (_, num_frames) = out.dim();
+        let tiled_channels = num_channels / 8 * 8;
+        let tiled_frames = num_frames / 8 * 8;
+        assert!(interleaved.len() >= num_frames * num_channels);
+        assert_eq!(out.strides()[1], 1);
+        assert_eq!(out.nrows(), num_channels);
+
+        let row_stride = out.strides()[0];
+        let out_ptr = out.as_mut_ptr();
+        for frame in (0..tiled_frames).step_by(8) {
+            for ch in (0..tiled_channels).step_by(8) {
+                // SAFETY: SSE2 is part of the x86-64 baseline. The 8 frames of 8
+                // words read start at frame * num_channels + ch with ch + 8 <=
+                // num_channels and frame + 8 <= num_frames, so they lie within
+                // `interleaved`. Row ch + i of `out` holds num_frames contiguous
+                // words, of which frame..frame + 8 are written.
+                unsafe {
+                    let src = interleaved.as_ptr().add(frame * num_channels + ch);
+                    let load = |i: usize| _mm_loadu_si128(src.add(i * num_channels).cast());
+                    let columns = transpose([
+                        load(0),
+                        load(1),
+                        load(2),
+                        load(3),
+                        load(4),
+                        load(5),
+                        load(6),
+                        load(7),
+                    ]);
+                    for (i, column) in columns.iter().enumerate() {
+                        let dst = out_ptr.offset((ch + i) as isize * row_stride).add(frame);
+                        _mm_storeu_si128(dst.cast(), *column);
+                    }
+                }
+            }
+        }
+
+        (0..tiled_frames, 0..tiled_channels)
+    }
+
+    /// Transposes an 8 by 8 tile of 16-bit words
+    #[inline(always)]
+    unsafe fn transpose(r: [__m128i; 8]) -> [__m128i; 8] {
+        let a0 = _mm_unpacklo_epi16(r[0], r[1]);
+        let a1 = _mm_unpackhi_epi16(r[0], r[1]);
+        let a2 = _mm_unpacklo_epi16(r[2], r[3]);
+        let a3 = _mm_unpackhi_epi16(r[2], r[3]);
+        let a4 = _mm_unpacklo_epi16(r[4], r[5]);
+        let a5 = _mm_unpackhi_epi16(r[4], r[5]);
+        let a6 = _mm_unpacklo_epi16(r[6], r[7]);
+        let a7 = _mm_unpackhi_epi16(r[6], r[7]);
+
+        let b0 = _mm_unpacklo_epi32(a0, a2);
+        let b1 = _mm_unpackhi_epi32(a0, a2);
+        let b2 = _mm_unpacklo_epi32(a1, a3);
+        let b3 = _mm_unpackhi_epi32(a1, a3);
+        let b4 = _mm_unpacklo_epi32(a4, a6);
+        let b5 = _mm_unpackhi_epi32(a4, a6);
+        let b6 = _mm_unpacklo_epi32(a5, a7);
+        let b7 = _mm_unpackhi_epi32(a5, a7);
+
+        [
+            _mm_unpacklo_epi64(b0, b4),
+            _mm_unpackhi_epi64(b0, b4),
+            _mm_unpacklo_epi64(b1, b5),
+            _mm_unpackhi_epi64(b1, b5),
+            _mm_unpacklo_epi64(b2, b6),
+            _mm_unpackhi_epi64(b2, b6),
+            _mm_unpacklo_epi64(b3, b7),
+            _mm_unpackhi_epi64(b3, b7),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{s, Array2};
+
+    /// Words that identify their position, so that misplaced words show up
+    fn words(len: usize) -> Vec<u16> {
+        (0..len).map(|i| (i * 7 + 3) as u16).collect()
+    }
+
+    /// Asserts that `deinterleave` and `deinterleave_scalar` agree on `interleaved`
+    fn assert_matches_scalar(interleaved: &[u16], num_channels: usize, rows: Option<&[usize]>) {
+        let num_frames = interleaved.len() / num_channels;
+        let num_rows = rows.map_or(num_channels, <[usize]>::len);
+        let mut expected = Array2::zeros((num_rows, num_frames));
+        deinterleave_scalar(interleaved, num_channels, rows, expected.view_mut());
+        let mut actual = Array2::from_elem((num_rows, num_frames), u16::MAX);
+        deinterleave(interleaved, num_channels, rows, actual.view_mut());
+        assert_eq!(
+            actual, expected,
+            "{} channels, {} frames",
+            num_channels, num_frames
+        );
+    }
+
+    #[test]
+    fn scalar_transposes_frames_into_rows() {
+        let interleaved = words(3 * 5);
+        let mut out = Array2::zeros((3, 5));
+        deinterleave_scalar(&interleaved, 3, None, out.view_mut());
+        for ((ch, frame), &word) in out.indexed_iter() {
+            assert_eq!(word, interleaved[frame * 3 + ch]);
+        }
+    }
+
+    #[test]
+    fn matches_scalar_for_all_tile_remainders() {
+        for num_channels in [1, 7, 8, 9, 16, 17] {
+            for num_frames in [0, 1, 7, 8, 9, 15, 16, 21, 128, 131] {
+                assert_matches_scalar(&words(num_channels * num_frames), num_channels, None);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_scalar_at_unaligned_input_offsets() {
+        let buffer = words(17 * 40 + 8);
+        for offset in 1..8 {
+            for num_channels in [8, 9, 16, 17] {
+                let interleaved = &buffer[offset..offset + num_channels * 37];
+                assert_matches_scalar(interleaved, num_channels, None);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_scalar_for_channel_selections() {
+        let interleaved = words(17 * 19);
+        assert_matches_scalar(&interleaved, 17, Some(&[16, 0, 8, 8, 3]));
+        assert_matches_scalar(&interleaved, 17, Some(&[]));
+    }
+
+    #[test]
+    fn writes_into_views_of_larger_arrays() {
+        let interleaved = words(16 * 24);
+        let mut expected = Array2::zeros((16, 24));
+        deinterleave_scalar(&interleaved, 16, None, expected.view_mut());
+
+        // Rows of the view are longer than the frames, and start past column 0
+        let mut larger = Array2::from_elem((16, 40), u16::MAX);
+        deinterleave(&interleaved, 16, None, larger.slice_mut(s![.., 5..29]));
+        assert_eq!(larger.slice(s![.., 5..29]), expected);
+        assert!(larger.slice(s![.., ..5]).iter().all(|&w| w == u16::MAX));
+        assert!(larger.slice(s![.., 29..]).iter().all(|&w| w == u16::MAX));
+
+        // Columns that are not contiguous take the scalar path
+        let mut strided = Array2::zeros((16, 48));
+        deinterleave(&interleaved, 16, None, strided.slice_mut(s![.., ..;2]));
+        assert_eq!(strided.slice(s![.., ..;2]), expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "whole frames")]
+    fn rejects_partial_frames() {
+        let mut out = Array2::zeros((8, 2));
+        deinterleave(&words(17), 8, None, out.view_mut());
+    }
+}
@@ -0,0 +1,305 @@
+//! Field-by-field comparison of RHS headers.
+//!
+//! [`RhsHeader::diff`] lists every header field that differs between two
+//! recordings, which helps explain why files could not be combined and confirms
+//! that acquisition settings stayed constant across sessions.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let monday = load("monday.rhs").unwrap();
+//! let tuesday = load("tuesday.rhs").unwrap();
+//!
+//! for difference in monday.header.diff(&tuesday.header) {
+//!     println!("{}", difference);
+//! }
+//! ```
+
+use std::fmt;
+
+use crate::types::{ChannelInfo, RhsHeader, SpikeTrigger};
+
+/// A single header field that differs between two headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderDifference {
+    /// Path of the differing field, e.g. `sample_rate` or `amplifier_channels[3].custom_channel_name`
+    pub field: String,
+    /// Value in the header `diff` was called on
+    pub left: String,
+    /// Value in the header passed to `diff`
+    pub right: String,
+}
+
+impl fmt::Display for HeaderDifference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} vs {}", self.field, self.left, self.right)
+    }
+}
+
+/// Records a difference if the two values are not equal.
+fn compare<T: PartialEq + fmt::Debug>(
+    differences: &mut Vec<HeaderDifference>,
+    field: &str,
+    left: &T,
+    right: &T,
+) {
+    if left != right {
+        differences.push(HeaderDifference {
+            field: field.to_string(),
+            left: format!("{:?}", left),
+            right: format!("{:?}", right),
+        });
+    }
+}
+
+impl RhsHeader {
+    /// Returns every field that differs between this header and `other`.
+    ///
+    /// Channel lists are compared element by element; if their lengths differ,
+    /// the length difference is reported along with differences in the common
+    /// prefix. The consolidated `frequency_parameters` and `stim_parameters` are
+    /// not compared separately because they mirror top-level fields.
+    pub fn diff(&self, other: &RhsHeader) -> Vec<HeaderDifference> {
+        let mut d = Vec::new();
+
+        compare(&mut d, "version.major", &self.version.major, &other.version.major);
+        compare(&mut d, "version.minor", &self.version.minor, &other.version.minor);
+        compare(&mut d, "sample_rate", &self.sample_rate, &other.sample_rate);
+        compare(
+            &mut d,
+            "num_samples_per_data_block",
+            &self.num_samples_per_data_block,
+            &other.num_samples_per_data_block,
+        );
+        compare(&mut d, "dsp_enabled", &self.dsp_enabled, &other.dsp_enabled);
+        compare(
+            &mut d,
+            "actual_dsp_cutoff_frequency",
+            &self.actual_dsp_cutoff_frequency,
+            &other.actual_dsp_cutoff_frequency,
+        );
+        compare(
+            &mut d,
+            "actual_lower_bandwidth",
+            &self.actual_lower_bandwidth,
+            &other.actual_lower_bandwidth,
+        );
+        compare(
+            &mut d,
+            "actual_lower_settle_bandwidth",
+            &self.actual_lower_settle_bandwidth,
+            &other.actual_lower_settle_bandwidth,
+        );
+        compare(
+            &mut d,
+            "actual_upper_bandwidth",
+            &self.actual_upper_bandwidth,
+            &other.actual_upper_bandwidth,
+        );
+        compare(
+            &mut d,
+            "desired_dsp_cutoff_frequency",
+            &self.desired_dsp_cutoff_frequency,
+            &other.desired_dsp_cutoff_frequency,
+        );
+        compare(
+            &mut d,
+            "desired_lower_bandwidth",
+            &self.desired_lower_bandwidth,
+            &other.desired_lower_bandwidth,
+        );
+        compare(
+            &mut d,
+            "desired_lower_settle_bandwidth",
+            &self.desired_lower_settle_bandwidth,
+            &other.desired_lower_settle_bandwidth,
+        );
+        compare(
+            &mut d,
+            "desired_upper_bandwidth",
+            &self.desired_upper_bandwidth,
+            &other.desired_upper_bandwidth,
+        );
+        compare(
+            &mut d,
+            "notch_filter_frequency",
+            &self.notch_filter_frequency,
+            &other.notch_filter_frequency,
+        );
+        compare(
+            &mut d,
+            "desired_impedance_test_frequency",
+            &self.desired_impedance_test_frequency,
+            &other.desired_impedance_test_frequency,
+        );
+        compare(
+            &mut d,
+            "actual_impedance_test_frequency",
+            &self.actual_impedance_test_frequency,
+            &other.actual_impedance_test_frequency,
+        );
+        compare(&mut d, "amp_settle_mode", &self.amp_settle_mode, &other.amp_settle_mode);
+        compare(
+            &mut d,
+            "charge_recovery_mode",
+            &self.charge_recovery_mode,
+            &other.charge_recovery_mode,
+        );
+        compare(&mut d, "stim_step_size", &self.stim_step_size, &other.stim_step_size);
+        compare(
+            &mut d,
+            "recovery_current_limit",
+            &self.recovery_current_limit,
+            &other.recovery_current_limit,
+        );
+        compare(
+            &mut d,
+            "recovery_target_voltage",
+            &self.recovery_target_voltage,
+            &other.recovery_target_voltage,
+        );
+        compare(&mut d, "notes.note1", &self.notes.note1, &other.notes.note1);
+        compare(&mut d, "notes.note2", &self.notes.note2, &other.notes.note2);
+        compare(&mut d, "notes.note3", &self.notes.note3, &other.notes.note3);
+        compare(
+            &mut d,
+            "dc_amplifier_data_saved",
+            &self.dc_amplifier_data_saved,
+            &other.dc_amplifier_data_saved,
+        );
+        compare(&mut d, "eval_board_mode", &self.eval_board_mode, &other.eval_board_mode);
+        compare(
+            &mut d,
+            "reference_channel",
+            &self.reference_channel,
+            &other.reference_channel,
+        );
+
+        diff_channels(
+            &mut d,
+            "amplifier_channels",
+            &self.amplifier_channels,
+            &other.amplifier_channels,
+        );
+        diff_spike_triggers(&mut d, &self.spike_triggers, &other.spike_triggers);
+        diff_channels(
+            &mut d,
+            "aux_input_channels",
+            &self.aux_input_channels,
+            &other.aux_input_channels,
+        );
+        diff_channels(
+            &mut d,
+            "supply_voltage_channels",
+            &self.supply_voltage_channels,
+            &other.supply_voltage_channels,
+        );
+        diff_channels(
+            &mut d,
+            "board_adc_channels",
+            &self.board_adc_channels,
+            &other.board_adc_channels,
+        );
+        diff_channels(
+            &mut d,
+            "board_dac_channels",
+            &self.board_dac_channels,
+            &other.board_dac_channels,
+        );
+        diff_channels(
+            &mut d,
+            "board_dig_in_channels",
+            &self.board_dig_in_channels,
+            &other.board_dig_in_channels,
+        );
+        diff_channels(
+            &mut d,
+            "board_dig_out_channels",
+            &self.board_dig_out_channels,
+            &other.board_dig_out_channels,
+        );
+
+        d
+    }
+}
+
+/// Compares two channel lists element by element.
+fn diff_channels(
+    d: &mut Vec<HeaderDifference>,
+    name: &str,
+    left: &[ChannelInfo],
+    right: &[ChannelInfo],
+) {
+    compare(d, &format!("{}.len", name), &left.len(), &right.len());
+
+    for (i, (a, b)) in left.iter().zip(right).enumerate() {
+        let field = |f: &str| format!("{}[{}].{}", name, i, f);
+        compare(d, &field("port_name"), &a.port_name, &b.port_name);
+        compare(d, &field("port_prefix"), &a.port_prefix, &b.port_prefix);
+        compare(d, &field("port_number"), &a.port_number, &b.port_number);
+        compare(
+            d,
+            &field("native_channel_name"),
+            &a.native_channel_name,
+            &b.native_channel_name,
+        );
+        compare(
+            d,
+            &field("custom_channel_name"),
+            &a.custom_channel_name,
+            &b.custom_channel_name,
+        );
+        compare(d, &field("native_order"), &a.native_order, &b.native_order);
+        compare(d, &field("custom_order"), &a.custom_order, &b.custom_order);
+        compare(d, &field("chip_channel"), &a.chip_channel, &b.chip_channel);
+        compare(
+            d,
+            &field("command_stream"),
+            &a.command_stream,
+            &b.command_stream,
+        );
+        compare(d, &field("board_stream"), &a.board_stream, &b.board_stream);
+        compare(
+            d,
+            &field("electrode_impedance_magnitude"),
+            &a.electrode_impedance_magnitude,
+            &b.electrode_impedance_magnitude,
+        );
+        compare(
+            d,
+            &field("electrode_impedance_phase"),
+            &a.electrode_impedance_phase,
+            &b.electrode_impedance_phase,
+        );
+    }
+}
+
+/// Compares two spike trigger lists element by element.
+fn diff_spike_triggers(d: &mut Vec<HeaderDifference>, left: &[SpikeTrigger], right: &[SpikeTrigger]) {
+    compare(d, "spike_triggers.len", &left.len(), &right.len());
+
+    for (i, (a, b)) in left.iter().zip(right).enumerate() {
+        let field = |f: &str| format!("spike_triggers[{}].{}", i, f);
+        compare(
+            d,
+            &field("voltage_trigger_mode"),
+            &a.voltage_trigger_mode,
+            &b.voltage_trigger_mode,
+        );
+        compare(d, &field("voltage_threshold"), &a.voltage_threshold, &b.voltage_threshold);
+        compare(
+            d,
+            &field("digital_trigger_channel"),
+            &a.digital_trigger_channel,
+            &b.digital_trigger_channel,
+        );
+        compare(
+            d,
+            &field("digital_edge_polarity"),
+            &a.digital_edge_polarity,
+            &b.digital_edge_polarity,
+        );
+    }
+}
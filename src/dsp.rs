@@ -0,0 +1,1390 @@
+//! Butterworth and notch filtering of amplifier and ADC data, and re-referencing
+//! of amplifier data.
+//!
+//! A [`ButterworthFilter`] describes a low-pass, high-pass, or band-pass filter by
+//! its order and cutoff frequencies. It is designed for a sample rate as a cascade
+//! of second-order sections, through the bilinear transform with the cutoffs
+//! prewarped, so the response is down 3 dB at exactly the requested frequencies. A
+//! band-pass filter of order `n` has `n` poles for each edge of the band.
+//!
+//! Filters can be applied in three ways:
+//!
+//! - [`RhsFile::apply_filter`] filters a loaded signal in place, in parallel
+//!   across channels.
+//! - [`RhsBlockReader::with_filter`](crate::stream::RhsBlockReader::with_filter)
+//!   filters each block read by the streaming reader, carrying the filter state
+//!   across blocks so the result matches filtering the whole signal.
+//! - A [`SignalFilter`] is a [`BlockProcessor`] that filters each block while a
+//!   recording is loaded with [`load_with_processors`](crate::load_with_processors),
+//!   across the files of a directory.
+//!
+//! The filters are causal, so their output is delayed by the group delay of the
+//! filter. Each channel's filter starts in the steady state of its first sample, so
+//! a DC offset does not cause a start-up transient.
+//!
+//! For offline analysis, [`ButterworthFilter::zero_phase`] makes
+//! [`RhsFile::apply_filter`] filter each channel forward and then backward in time,
+//! like `filtfilt` in SciPy or MATLAB. The phase shifts of the two passes cancel,
+//! and the attenuation of every frequency is squared, so the response is down 6 dB
+//! at the cutoffs. Zero-phase filtering needs the whole signal, so it cannot be
+//! applied block by block.
+//!
+//! A [`NotchFilter`] is the line-noise filter that loading applies when the header
+//! asks for it, with a configurable bandwidth and number of sections. By default it
+//! matches Intan's filter bit for bit. [`NotchFilter::apply`] filters any signal,
+//! and [`LoadOptions`](crate::loader::LoadOptions) sets the shape of the filter
+//! applied while loading.
+//!
+//! [`rereference`] subtracts a common average or median reference from the
+//! amplifier channels of a loaded recording, over all channels, per port, or per
+//! group of a [`ChannelMap`]. [`rereference_to_channels`] references every
+//! channel to a chosen channel, or to the mean of a set of channels, instead.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::dsp::ButterworthFilter;
+//! use intan_importer::stream::RhsBlockReader;
+//! use intan_importer::{load, SignalType};
+//!
+//! // Spike band of the amplifier data, 4th order
+//! let mut rhs_file = load("recording.rhs").unwrap();
+//! let spike_band = ButterworthFilter::bandpass(4, 300.0, 6000.0);
+//! rhs_file.apply_filter(SignalType::Amplifier, &spike_band).unwrap();
+//!
+//! // Low-pass filtered ADC data, block by block
+//! let reader = RhsBlockReader::open("recording.rhs")
+//!     .unwrap()
+//!     .with_filter(SignalType::BoardAdc, ButterworthFilter::lowpass(2, 100.0))
+//!     .unwrap();
+//! for block in reader {
+//!     let block = block.unwrap();
+//!     println!("{:?}", block.data.board_adc_data.map(|adc| adc.dim()));
+//! }
+//! ```
+
+use std::error::Error;
+use std::f64::consts::PI;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use ndarray::parallel::prelude::*;
+use ndarray::{ArrayViewMut1, ArrayViewMut2, Axis};
+
+use crate::groups::ChannelMap;
+use crate::processor::{BlockProcessor, DataBlockMut};
+use crate::qc::median;
+use crate::reader::Sample;
+use crate::signal::SignalType;
+use crate::types::{IntanError, RhsData, RhsFile, RhsHeader};
+
+/// Highest supported filter order.
+pub const MAX_ORDER: usize = 16;
+
+/// Frequencies passed by a filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterBand {
+    /// Frequencies below the cutoff (Hz)
+    Lowpass(f64),
+    /// Frequencies above the cutoff (Hz)
+    Highpass(f64),
+    /// Frequencies between the low and high cutoffs (Hz)
+    Bandpass(f64, f64),
+}
+
+/// A Butterworth filter, described by its band and order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ButterworthFilter {
+    /// Frequencies the filter passes
+    pub band: FilterBand,
+    /// Order of the filter, from 1 to [`MAX_ORDER`]; band-pass filters have this
+    /// order at each edge of the band
+    pub order: usize,
+    /// Whether the filter runs forward and then backward in time, so it shifts no
+    /// phase
+    pub zero_phase: bool,
+}
+
+impl ButterworthFilter {
+    /// Creates a low-pass filter.
+    pub fn lowpass(order: usize, cutoff: f64) -> Self {
+        ButterworthFilter {
+            band: FilterBand::Lowpass(cutoff),
+            order,
+            zero_phase: false,
+        }
+    }
+
+    /// Creates a high-pass filter.
+    pub fn highpass(order: usize, cutoff: f64) -> Self {
+        ButterworthFilter {
+            band: FilterBand::Highpass(cutoff),
+            order,
+            zero_phase: false,
+        }
+    }
+
+    /// Creates a band-pass filter.
+    pub fn bandpass(order: usize, low: f64, high: f64) -> Self {
+        ButterworthFilter {
+            band: FilterBand::Bandpass(low, high),
+            order,
+            zero_phase: false,
+        }
+    }
+
+    /// Makes the filter run forward and then backward in time.
+    ///
+    /// Only [`RhsFile::apply_filter`] can apply a zero-phase filter, which squares
+    /// the filter's attenuation at every frequency.
+    pub fn zero_phase(mut self) -> Self {
+        self.zero_phase = true;
+        self
+    }
+
+    /// Designs the second-order sections of the filter for `sample_rate`.
+    pub(crate) fn design(&self, sample_rate: f64) -> Result<Vec<Biquad>, Box<dyn Error>> {
+        if self.order == 0 || self.order > MAX_ORDER {
+            return Err(Box::new(IntanError::Other(format!(
+                "Filter order must be between 1 and {}, not {}",
+                MAX_ORDER, self.order
+            ))));
+        }
+        let nyquist = sample_rate / 2.0;
+        let valid = |frequency: f64| frequency > 0.0 && frequency < nyquist;
+        let cutoffs_valid = match self.band {
+            FilterBand::Lowpass(cutoff) | FilterBand::Highpass(cutoff) => valid(cutoff),
+            FilterBand::Bandpass(low, high) => valid(low) && valid(high) && low < high,
+        };
+        if !cutoffs_valid {
+            return Err(Box::new(IntanError::Other(format!(
+                "Invalid cutoffs {:?} for a sample rate of {} Hz: they must lie between 0 \
+                 and {} Hz, in increasing order",
+                self.band, sample_rate, nyquist
+            ))));
+        }
+
+        // Analog frequency of a digital one, prewarped for the bilinear transform
+        let prewarp = |frequency: f64| 2.0 * sample_rate * (PI * frequency / sample_rate).tan();
+        let bilinear = |s: Complex| {
+            (Complex::real(2.0 * sample_rate) + s) / (Complex::real(2.0 * sample_rate) - s)
+        };
+
+        // Poles of the analog prototype in the upper half plane, or on the real axis
+        let prototype = (0..self.order.div_ceil(2)).map(|k| {
+            let angle = PI * (2 * k + self.order + 1) as f64 / (2 * self.order) as f64;
+            Complex::from_angle(angle)
+        });
+
+        let mut sections = Vec::new();
+        match self.band {
+            FilterBand::Lowpass(cutoff) | FilterBand::Highpass(cutoff) => {
+                let lowpass = matches!(self.band, FilterBand::Lowpass(_));
+                let w = prewarp(cutoff);
+                for p in prototype {
+                    let s = if lowpass {
+                        p * Complex::real(w)
+                    } else {
+                        Complex::real(w) / p
+                    };
+                    let z = bilinear(s);
+                    let (numerator, denominator) = if p.im.abs() < 1e-12 {
+                        let zero = if lowpass { 1.0 } else { -1.0 };
+                        ([1.0, zero, 0.0], [1.0, -z.re, 0.0])
+                    } else {
+                        let zero = if lowpass { 2.0 } else { -2.0 };
+                        ([1.0, zero, 1.0], conjugate_pair(z))
+                    };
+                    let reference = if lowpass { 0.0 } else { PI };
+                    sections.push(Biquad::normalized(numerator, denominator, reference));
+                }
+            }
+            FilterBand::Bandpass(low, high) => {
+                let (w1, w2) = (prewarp(low), prewarp(high));
+                let (bandwidth, center) = (w2 - w1, (w1 * w2).sqrt());
+                let reference = 2.0 * (center / (2.0 * sample_rate)).atan();
+                for p in prototype {
+                    // Each prototype pole p becomes the roots of s² - pBs + W0² = 0
+                    let pb = p * Complex::real(bandwidth);
+                    let root = (pb * pb - Complex::real(4.0 * center * center)).sqrt();
+                    let s1 = (pb + root) / Complex::real(2.0);
+                    let s2 = (pb - root) / Complex::real(2.0);
+                    let (z1, z2) = (bilinear(s1), bilinear(s2));
+                    let denominators = if p.im.abs() < 1e-12 {
+                        // Two real poles, or a conjugate pair, of one section
+                        vec![[1.0, -(z1 + z2).re, (z1 * z2).re]]
+                    } else {
+                        vec![conjugate_pair(z1), conjugate_pair(z2)]
+                    };
+                    for denominator in denominators {
+                        sections.push(Biquad::normalized([1.0, 0.0, -1.0], denominator, reference));
+                    }
+                }
+            }
+        }
+        Ok(sections)
+    }
+}
+
+/// Denominator `[1, a1, a2]` of a section with the poles `z` and its conjugate
+fn conjugate_pair(z: Complex) -> [f64; 3] {
+    [1.0, -2.0 * z.re, z.re * z.re + z.im * z.im]
+}
+
+/// Coefficients of a second-order section, with `a0 = 1`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+}
+
+impl Biquad {
+    /// Creates a section from its numerator and denominator, scaled to a gain of 1
+    /// at the digital frequency `reference` (radians per sample)
+    fn normalized(b: [f64; 3], a: [f64; 3], reference: f64) -> Biquad {
+        let z = Complex::from_angle(-reference);
+        let evaluate = |c: [f64; 3]| {
+            Complex::real(c[0]) + Complex::real(c[1]) * z + Complex::real(c[2]) * z * z
+        };
+        let gain = evaluate(b).abs() / evaluate(a).abs();
+        Biquad {
+            b: b.map(|coefficient| coefficient / gain),
+            a,
+        }
+    }
+
+    /// State `[z1, z2]` of the section after a constant input `x` for all time
+    fn steady_state(&self, x: f64) -> [f64; 2] {
+        let [b0, b1, b2] = self.b;
+        let [_, a1, a2] = self.a;
+        let y = x * (b0 + b1 + b2) / (1.0 + a1 + a2);
+        let z2 = b2 * x - a2 * y;
+        [b1 * x - a1 * y + z2, z2]
+    }
+
+    /// Filters one sample in direct form II transposed
+    fn process(&self, x: f64, state: &mut [f64; 2]) -> f64 {
+        let [b0, b1, b2] = self.b;
+        let [_, a1, a2] = self.a;
+        let y = b0 * x + state[0];
+        state[0] = b1 * x - a1 * y + state[1];
+        state[1] = b2 * x - a2 * y;
+        y
+    }
+}
+
+/// Filters the next samples of one channel in place
+///
+/// `states` holds the state of each section, and is set to the steady state of the
+/// first sample if `first` is true.
+pub(crate) fn filter_channel<T: Sample>(
+    sections: &[Biquad],
+    mut channel: ArrayViewMut1<'_, T>,
+    states: &mut [[f64; 2]],
+    first: bool,
+) {
+    if first {
+        if let Some(x) = channel.first() {
+            let mut x = x.to_f64();
+            for (section, state) in sections.iter().zip(states.iter_mut()) {
+                *state = section.steady_state(x);
+                x *= (section.b.iter().sum::<f64>()) / section.a.iter().sum::<f64>();
+            }
+        }
+    }
+    for value in channel.iter_mut() {
+        let mut x = value.to_f64();
+        for (section, state) in sections.iter().zip(states.iter_mut()) {
+            x = section.process(x, state);
+        }
+        *value = T::from_f64(x);
+    }
+}
+
+/// Filters a whole channel forward and then backward in time, in place
+///
+/// The channel is extended at both ends by its odd reflection about the end
+/// samples, so the start-up transients of the two passes fall mostly outside it.
+fn filter_channel_zero_phase<T: Sample>(sections: &[Biquad], mut channel: ArrayViewMut1<'_, T>) {
+    let n = channel.len();
+    if n == 0 {
+        return;
+    }
+    let padding = (3 * (2 * sections.len() + 1)).min(n - 1);
+    let (first, last) = (channel[0].to_f64(), channel[n - 1].to_f64());
+    let mut extended = Vec::with_capacity(n + 2 * padding);
+    extended.extend(
+        (1..=padding)
+            .rev()
+            .map(|i| 2.0 * first - channel[i].to_f64()),
+    );
+    extended.extend(channel.iter().map(|value| value.to_f64()));
+    extended.extend((1..=padding).map(|i| 2.0 * last - channel[n - 1 - i].to_f64()));
+
+    let mut states = vec![[0.0; 2]; sections.len()];
+    filter_channel(sections, (&mut extended[..]).into(), &mut states, true);
+    extended.reverse();
+    filter_channel(sections, (&mut extended[..]).into(), &mut states, true);
+    extended.reverse();
+
+    for (value, &x) in channel.iter_mut().zip(&extended[padding..]) {
+        *value = T::from_f64(x);
+    }
+}
+
+/// Bandwidth of the notch filter of Intan's software and reference loaders (Hz).
+pub const INTAN_NOTCH_BANDWIDTH: f64 = 10.0;
+
+/// A notch filter removing one frequency, such as 50 or 60 Hz line noise.
+///
+/// Each section is the second-order notch of Intan's software, with zeros on the
+/// unit circle at the notch frequency and poles just inside them. The bandwidth,
+/// roughly the width of the notch between its -3 dB points, sets how far inside.
+/// [`NotchFilter::new`] gives Intan's filter, one section 10 Hz wide, which filters
+/// a signal bit for bit as Intan's reference loaders do. Cascading sections widens
+/// and deepens the notch.
+///
+/// As in Intan's implementation, each section passes the first two samples of a
+/// signal through unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use intan_importer::dsp::NotchFilter;
+/// use ndarray::Array1;
+/// use std::f64::consts::PI;
+///
+/// // One second of a 60 Hz sine, sampled at 3 kHz
+/// let mut signal = Array1::from_shape_fn(3000, |i| (i as f64 * 0.04 * PI).sin());
+///
+/// let filter = NotchFilter::new(60.0).quality_factor(10.0).sections(2);
+/// filter.apply(signal.view_mut(), 3000.0).unwrap();
+/// assert!(signal.iter().skip(2000).all(|value| value.abs() < 0.01));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NotchFilter {
+    /// Frequency removed (Hz)
+    pub frequency: f64,
+    /// Width of the notch (Hz)
+    pub bandwidth: f64,
+    /// Number of identical sections in cascade, from 1 to [`MAX_ORDER`]
+    pub sections: usize,
+    /// Whether the filter runs forward and then backward in time, so it shifts no
+    /// phase
+    pub zero_phase: bool,
+}
+
+impl NotchFilter {
+    /// Creates Intan's notch filter at `frequency`: one section, 10 Hz wide.
+    pub fn new(frequency: f64) -> Self {
+        NotchFilter {
+            frequency,
+            bandwidth: INTAN_NOTCH_BANDWIDTH,
+            sections: 1,
+            zero_phase: false,
+        }
+    }
+
+    /// Sets the width of the notch (Hz).
+    pub fn bandwidth(mut self, bandwidth: f64) -> Self {
+        self.bandwidth = bandwidth;
+        self
+    }
+
+    /// Sets the width of the notch from its quality factor, the notch frequency
+    /// divided by the bandwidth.
+    pub fn quality_factor(mut self, q: f64) -> Self {
+        self.bandwidth = self.frequency / q;
+        self
+    }
+
+    /// Sets the number of sections in cascade.
+    pub fn sections(mut self, sections: usize) -> Self {
+        self.sections = sections;
+        self
+    }
+
+    /// Makes the filter run forward and then backward in time.
+    pub fn zero_phase(mut self) -> Self {
+        self.zero_phase = true;
+        self
+    }
+
+    /// Filters `signal`, sampled at `sample_rate` Hz, in place.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notch frequency does not lie between 0 and half the
+    /// sample rate, the bandwidth is not positive, or the number of sections is
+    /// not between 1 and [`MAX_ORDER`].
+    pub fn apply(
+        &self,
+        signal: ArrayViewMut1<'_, f64>,
+        sample_rate: f64,
+    ) -> Result<(), Box<dyn Error>> {
+        let design = self.design(sample_rate)?;
+        if self.zero_phase {
+            design.filter_zero_phase(signal);
+        } else {
+            design.filter(signal, &mut design.history(), 0);
+        }
+        Ok(())
+    }
+
+    /// Computes the coefficients of the sections for `sample_rate`
+    pub(crate) fn design(&self, sample_rate: f64) -> Result<NotchDesign, Box<dyn Error>> {
+        check_notch_shape(self.bandwidth, self.sections)?;
+        if !(self.frequency > 0.0 && self.frequency < sample_rate / 2.0) {
+            return Err(Box::new(IntanError::Other(format!(
+                "Invalid notch frequency {} Hz for a sample rate of {} Hz",
+                self.frequency, sample_rate
+            ))));
+        }
+
+        // Intan's design, term for term, so the default filter matches bit for bit
+        let t_step = 1.0 / sample_rate;
+        let f_c = self.frequency * t_step;
+        let d = (-2.0 * PI * (self.bandwidth / 2.0) * t_step).exp();
+        let b = (1.0 + d * d) * (2.0 * PI * f_c).cos();
+        let a = (1.0 + d * d) / 2.0;
+        let b1 = -2.0 * (2.0 * PI * f_c).cos();
+        Ok(NotchDesign {
+            coefficients: [a, -b, d * d, b1],
+            sections: self.sections,
+        })
+    }
+}
+
+/// Checks the bandwidth and number of sections of a notch filter
+pub(crate) fn check_notch_shape(bandwidth: f64, sections: usize) -> Result<(), Box<dyn Error>> {
+    if !(bandwidth > 0.0 && bandwidth.is_finite()) {
+        return Err(Box::new(IntanError::Other(format!(
+            "The notch bandwidth must be positive, not {} Hz",
+            bandwidth
+        ))));
+    }
+    if sections == 0 || sections > MAX_ORDER {
+        return Err(Box::new(IntanError::Other(format!(
+            "A notch filter must have between 1 and {} sections, not {}",
+            MAX_ORDER, sections
+        ))));
+    }
+    Ok(())
+}
+
+/// A notch filter designed for one sample rate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct NotchDesign {
+    /// `[a, a1, a2, b1]` of every section, with `a0 = b0 = b2 = 1`
+    coefficients: [f64; 4],
+    sections: usize,
+}
+
+impl NotchDesign {
+    /// Returns the history of a channel with no samples filtered yet
+    pub(crate) fn history(&self) -> Vec<[f64; 4]> {
+        vec![[0.0; 4]; self.sections]
+    }
+
+    /// Filters the next samples of one channel in place
+    ///
+    /// `history` holds the previous `[input, input before, output, output before]`
+    /// of each section, and `samples_filtered` the number of samples filtered before.
+    pub(crate) fn filter<T: Sample>(
+        &self,
+        mut channel: ArrayViewMut1<'_, T>,
+        history: &mut [[f64; 4]],
+        samples_filtered: usize,
+    ) {
+        let [a, a1, a2, b1] = self.coefficients;
+        for (j, value) in channel.iter_mut().enumerate() {
+            let mut x = value.to_f64();
+            for section in history.iter_mut() {
+                let [x1, x2, y1, y2] = *section;
+                // The first two samples of the signal pass through unchanged
+                let y = if samples_filtered + j < 2 {
+                    x
+                } else {
+                    a * x + a * b1 * x1 + a * x2 - a2 * y2 - a1 * y1
+                };
+                *section = [x, x1, y, y1];
+                x = y;
+            }
+            *value = T::from_f64(x);
+        }
+    }
+
+    /// Filters a whole channel forward and then backward in time, in place
+    ///
+    /// The phase shifts of the two passes cancel, so features of the signal keep
+    /// their timing, and the attenuation of each frequency is squared.
+    pub(crate) fn filter_zero_phase<T: Sample>(&self, mut channel: ArrayViewMut1<'_, T>) {
+        self.filter(channel.view_mut(), &mut self.history(), 0);
+        channel.invert_axis(Axis(0));
+        self.filter(channel, &mut self.history(), 0);
+    }
+}
+
+/// A Butterworth filter applied to one signal type, with the state of every
+/// channel carried from block to block.
+///
+/// As a [`BlockProcessor`], the filter is designed for the sample rate and channel
+/// count of the first file it sees and continues through the following files of a
+/// directory. Amplifier and board ADC data can be filtered, in `f64` or `f32`.
+#[derive(Debug, Clone)]
+pub struct SignalFilter {
+    signal: SignalType,
+    filter: ButterworthFilter,
+    sample_rate: f64,
+    sections: Vec<Biquad>,
+    /// State of every section of every channel
+    states: Vec<Vec<[f64; 2]>>,
+    samples_filtered: usize,
+}
+
+impl SignalFilter {
+    /// Creates a filter of `signal`, designed when the header is known.
+    pub fn new(signal: SignalType, filter: ButterworthFilter) -> Self {
+        SignalFilter {
+            signal,
+            filter,
+            sample_rate: 0.0,
+            sections: Vec::new(),
+            states: Vec::new(),
+            samples_filtered: 0,
+        }
+    }
+
+    /// Returns the signal type the filter applies to.
+    pub fn signal(&self) -> SignalType {
+        self.signal
+    }
+
+    /// Returns the filter's description.
+    pub fn filter(&self) -> &ButterworthFilter {
+        &self.filter
+    }
+
+    /// Designs the filter for the recording described by `header`, or checks that
+    /// the header matches the recording filtered so far.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the filter is zero-phase, the signal type cannot be
+    /// filtered, the filter is not valid at the header's sample rate, or the sample
+    /// rate or channel count differ from those of a recording filtered before.
+    pub fn prepare(&mut self, header: &RhsHeader) -> Result<(), Box<dyn Error>> {
+        if self.filter.zero_phase {
+            return Err(Box::new(IntanError::Other(
+                "A zero-phase filter needs the whole signal and cannot be applied block by \
+                 block; use RhsFile::apply_filter instead"
+                    .to_string(),
+            )));
+        }
+        self.design(header)
+    }
+
+    /// Designs the filter for the header, or checks it against the header of the
+    /// recording filtered so far
+    fn design(&mut self, header: &RhsHeader) -> Result<(), Box<dyn Error>> {
+        if !matches!(self.signal, SignalType::Amplifier | SignalType::BoardAdc) {
+            return Err(Box::new(IntanError::Other(format!(
+                "Only amplifier and board ADC data can be filtered, not {}",
+                self.signal
+            ))));
+        }
+        let sample_rate = header.sample_rate as f64;
+        let num_channels = self.signal.channels(header).len();
+
+        if !self.sections.is_empty() {
+            if (sample_rate - self.sample_rate).abs() > 0.01 || num_channels != self.states.len() {
+                return Err(Box::new(IntanError::Other(
+                    "Filtering requires identical sample rates and channel counts across files"
+                        .to_string(),
+                )));
+            }
+            return Ok(());
+        }
+
+        self.sections = self.filter.design(sample_rate)?;
+        self.sample_rate = sample_rate;
+        self.states = vec![vec![[0.0; 2]; self.sections.len()]; num_channels];
+        Ok(())
+    }
+
+    /// Filters the next samples of the signal in `data` in place.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the filter has not been prepared, the signal is loaded
+    /// as raw codes, or its channel count differs from the header's.
+    pub fn process_data(&mut self, data: &mut RhsData) -> Result<(), Box<dyn Error>> {
+        let (values, values_f32, raw) = signal_views(self.signal, data);
+        self.filter_views(values, values_f32, raw, false)
+    }
+
+    /// Filters the signal in whichever form it is present
+    fn filter_views(
+        &mut self,
+        values: Option<ArrayViewMut2<'_, f64>>,
+        values_f32: Option<ArrayViewMut2<'_, f32>>,
+        raw: bool,
+        parallel: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.sections.is_empty() {
+            return Err(Box::new(IntanError::Other(
+                "The filter must be prepared with a header before filtering".to_string(),
+            )));
+        }
+        if raw {
+            return Err(Box::new(IntanError::Other(format!(
+                "Raw {} codes cannot be filtered; load the data scaled instead",
+                self.signal
+            ))));
+        }
+        let num_samples = match (values, values_f32) {
+            (Some(values), _) => self.filter_rows(values, parallel)?,
+            (None, Some(values)) => self.filter_rows(values, parallel)?,
+            (None, None) => 0,
+        };
+        self.samples_filtered += num_samples;
+        Ok(())
+    }
+
+    /// Filters every row of `data`, one per channel, and returns the number of
+    /// samples filtered
+    fn filter_rows<T: Sample>(
+        &mut self,
+        mut data: ArrayViewMut2<'_, T>,
+        parallel: bool,
+    ) -> Result<usize, Box<dyn Error>> {
+        if data.nrows() != self.states.len() {
+            return Err(Box::new(IntanError::Other(format!(
+                "The filter was prepared for {} channels, but the data has {}",
+                self.states.len(),
+                data.nrows()
+            ))));
+        }
+        let (sections, first) = (&self.sections, self.samples_filtered == 0);
+        if self.filter.zero_phase {
+            data.axis_iter_mut(Axis(0))
+                .into_par_iter()
+                .for_each(|channel| filter_channel_zero_phase(sections, channel));
+        } else if parallel {
+            data.axis_iter_mut(Axis(0))
+                .into_par_iter()
+                .zip(self.states.par_iter_mut())
+                .for_each(|(channel, states)| filter_channel(sections, channel, states, first));
+        } else {
+            for (channel, states) in data.rows_mut().into_iter().zip(&mut self.states) {
+                filter_channel(sections, channel, states, first);
+            }
+        }
+        Ok(data.ncols())
+    }
+}
+
+impl BlockProcessor for SignalFilter {
+    fn begin(&mut self, header: &RhsHeader) -> Result<(), Box<dyn Error>> {
+        self.prepare(header)
+    }
+
+    fn process_block(&mut self, block: &mut DataBlockMut<'_>) -> Result<(), Box<dyn Error>> {
+        let (values, values_f32, raw) = match self.signal {
+            SignalType::Amplifier => (
+                block.amplifier_data.as_mut().map(|a| a.view_mut()),
+                block.amplifier_data_f32.as_mut().map(|a| a.view_mut()),
+                block.amplifier_data_raw.is_some(),
+            ),
+            _ => (
+                block.board_adc_data.as_mut().map(|a| a.view_mut()),
+                block.board_adc_data_f32.as_mut().map(|a| a.view_mut()),
+                block.board_adc_data_raw.is_some(),
+            ),
+        };
+        self.filter_views(values, values_f32, raw, false)
+    }
+}
+
+impl RhsFile {
+    /// Filters the amplifier or board ADC data in place, in parallel across
+    /// channels.
+    ///
+    /// The filter is designed for the header's sample rate. Both `f64` and `f32`
+    /// data can be filtered. A [zero-phase](ButterworthFilter::zero_phase) filter
+    /// runs over each whole channel forward and then backward.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `signal` is not [`SignalType::Amplifier`] or
+    /// [`SignalType::BoardAdc`], the filter is not valid at the sample rate, or
+    /// the signal was loaded as raw codes or spilled to disk.
+    pub fn apply_filter(
+        &mut self,
+        signal: SignalType,
+        filter: &ButterworthFilter,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut signal_filter = SignalFilter::new(signal, filter.clone());
+        signal_filter.design(&self.header)?;
+        let Some(data) = self.data.as_mut() else {
+            return Ok(());
+        };
+        if data.spilled.is_some() {
+            return Err(Box::new(IntanError::Other(
+                "Signals spilled to disk cannot be filtered in place".to_string(),
+            )));
+        }
+        let (values, values_f32, raw) = signal_views(signal, data);
+        signal_filter.filter_views(values, values_f32, raw, true)
+    }
+}
+
+/// `f64` and `f32` views of a signal that can be filtered, and whether it is
+/// loaded as raw codes
+type SignalViews<'a> = (
+    Option<ArrayViewMut2<'a, f64>>,
+    Option<ArrayViewMut2<'a, f32>>,
+    bool,
+);
+
+/// Returns the views of the amplifier data, or of the board ADC data for any other
+/// signal type
+fn signal_views(signal: SignalType, data: &mut RhsData) -> SignalViews<'_> {
+    match signal {
+        SignalType::Amplifier => (
+            data.amplifier_data.as_mut().map(|a| a.view_mut()),
+            data.amplifier_data_f32.as_mut().map(|a| a.view_mut()),
+            data.amplifier_data_raw.is_some(),
+        ),
+        _ => (
+            data.board_adc_data.as_mut().map(|a| a.view_mut()),
+            data.board_adc_data_f32.as_mut().map(|a| a.view_mut()),
+            data.board_adc_data_raw.is_some(),
+        ),
+    }
+}
+
+/// Statistic of a group's channels subtracted from each of them by
+/// [`rereference`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReferenceMethod {
+    /// Common average reference (CAR): the mean of the channels at each sample
+    #[default]
+    Average,
+    /// Common median reference (CMR): the median of the channels at each sample,
+    /// which a single noisy or saturated channel barely moves
+    Median,
+}
+
+/// Groups of amplifier channels that share a reference in [`rereference`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ReferenceGroups {
+    /// All amplifier channels form one group
+    #[default]
+    All,
+    /// The channels of each port form a group, see [`RhsFile::groups`]
+    ByPort,
+    /// The groups of a channel map, such as the shanks of a probe, see
+    /// [`RhsFile::groups_from_map`]; channels in no group are left unchanged
+    Map(ChannelMap),
+}
+
+/// Settings of [`rereference`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RereferenceOptions {
+    /// Statistic used as the reference, the average by default
+    pub method: ReferenceMethod,
+    /// Channels sharing each reference, all channels by default
+    pub groups: ReferenceGroups,
+    /// Native names of channels left out of the reference, such as bad channels.
+    ///
+    /// These channels are still referenced: the reference of their group is
+    /// subtracted from them as from the others.
+    pub exclude: Vec<String>,
+}
+
+/// Re-references the amplifier data of a recording in place.
+///
+/// For each group of channels, the average or median of the group's channels is
+/// computed at every sample and subtracted from each of them, removing noise
+/// common to the group. Both `f64` and `f32` data can be re-referenced. The
+/// header's [`reference_channel`](RhsHeader::reference_channel) is then set to
+/// describe the new reference: `CAR` or `CMR` for one group of all channels,
+/// followed by `per port` or `per group` for the other groupings.
+///
+/// # Errors
+///
+/// Returns an error if the amplifier data was loaded as raw codes or spilled to
+/// disk, an excluded channel is not an amplifier channel, a channel belongs to
+/// more than one group of a channel map, or every channel of a group is
+/// excluded.
+///
+/// # Examples
+///
+/// ```no_run
+/// use intan_importer::dsp::{rereference, ReferenceGroups, ReferenceMethod, RereferenceOptions};
+/// use intan_importer::load;
+///
+/// let mut rhs_file = load("recording.rhs").unwrap();
+/// let options = RereferenceOptions {
+///     method: ReferenceMethod::Median,
+///     groups: ReferenceGroups::ByPort,
+///     exclude: vec!["A-013".to_string()],
+/// };
+/// rereference(&mut rhs_file, &options).unwrap();
+/// assert_eq!(rhs_file.header.reference_channel, "CMR per port");
+/// ```
+pub fn rereference(
+    rhs_file: &mut RhsFile,
+    options: &RereferenceOptions,
+) -> Result<(), Box<dyn Error>> {
+    let channels = &rhs_file.header.amplifier_channels;
+    let excluded = amplifier_rows(rhs_file, &options.exclude, "Excluded channel")?;
+
+    // Rows of each group, and the name of the grouping for the header
+    let (groups, grouping): (Vec<(String, Vec<usize>)>, &str) = match &options.groups {
+        ReferenceGroups::All => (vec![(String::new(), (0..channels.len()).collect())], ""),
+        ReferenceGroups::ByPort => (
+            rhs_file
+                .groups()
+                .into_iter()
+                .map(|g| (g.name, g.rows))
+                .collect(),
+            " per port",
+        ),
+        ReferenceGroups::Map(map) => {
+            let groups: Vec<(String, Vec<usize>)> = rhs_file
+                .groups_from_map(map)?
+                .into_iter()
+                .map(|g| (g.name, g.rows))
+                .collect();
+            let mut seen = vec![false; channels.len()];
+            for &row in groups.iter().flat_map(|(_, rows)| rows) {
+                if std::mem::replace(&mut seen[row], true) {
+                    return Err(Box::new(IntanError::Other(format!(
+                        "Channel '{}' belongs to more than one group",
+                        channels[row].native_channel_name
+                    ))));
+                }
+            }
+            (groups, " per group")
+        }
+    };
+
+    // Each group keeps the rows it is referenced to
+    let mut references = Vec::with_capacity(groups.len());
+    for (name, rows) in groups {
+        if rows.is_empty() {
+            continue;
+        }
+        let reference_rows: Vec<usize> = rows
+            .iter()
+            .copied()
+            .filter(|row| !excluded.contains(row))
+            .collect();
+        if reference_rows.is_empty() {
+            return Err(Box::new(IntanError::Other(format!(
+                "Every channel of group '{}' is excluded from the reference",
+                name
+            ))));
+        }
+        references.push((rows, reference_rows));
+    }
+
+    apply_references(rhs_file, &references, options.method)?;
+
+    let method = match options.method {
+        ReferenceMethod::Average => "CAR",
+        ReferenceMethod::Median => "CMR",
+    };
+    rhs_file.header.reference_channel = format!("{}{}", method, grouping);
+    Ok(())
+}
+
+/// Re-references every amplifier channel to a chosen channel, or to the mean of a
+/// set of channels, in place.
+///
+/// The reference is subtracted from every amplifier channel, including those it is
+/// computed from, as the hardware reference would have been: a single reference
+/// channel becomes zero. This replaces a hardware reference that turned out to be
+/// noisy with a quieter electrode. The header's
+/// [`reference_channel`](RhsHeader::reference_channel) is set to the native name of
+/// the reference channel, or to `mean of` followed by the names of the channels.
+///
+/// # Errors
+///
+/// Returns an error if `channels` is empty or names a channel that is not an
+/// amplifier channel, or if the amplifier data was loaded as raw codes or spilled
+/// to disk.
+///
+/// # Examples
+///
+/// ```no_run
+/// use intan_importer::dsp::rereference_to_channels;
+/// use intan_importer::load;
+///
+/// let mut rhs_file = load("recording.rhs").unwrap();
+/// rereference_to_channels(&mut rhs_file, ["B-031"]).unwrap();
+/// assert_eq!(rhs_file.header.reference_channel, "B-031");
+/// ```
+pub fn rereference_to_channels<I, S>(
+    rhs_file: &mut RhsFile,
+    channels: I,
+) -> Result<(), Box<dyn Error>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let names: Vec<String> = channels
+        .into_iter()
+        .map(|name| name.as_ref().to_string())
+        .collect();
+    if names.is_empty() {
+        return Err(Box::new(IntanError::Other(
+            "At least one reference channel is required".to_string(),
+        )));
+    }
+    let reference_rows = amplifier_rows(rhs_file, &names, "Reference channel")?;
+    let rows = (0..rhs_file.header.amplifier_channels.len()).collect();
+    apply_references(
+        rhs_file,
+        &[(rows, reference_rows)],
+        ReferenceMethod::Average,
+    )?;
+
+    rhs_file.header.reference_channel = match names.as_slice() {
+        [name] => name.clone(),
+        _ => format!("mean of {}", names.join(", ")),
+    };
+    Ok(())
+}
+
+/// Returns the amplifier rows of the channels with the native names `names`,
+/// described as `what` in the error for a name that is not found
+fn amplifier_rows(
+    rhs_file: &RhsFile,
+    names: &[String],
+    what: &str,
+) -> Result<Vec<usize>, Box<dyn Error>> {
+    let channels = &rhs_file.header.amplifier_channels;
+    names
+        .iter()
+        .map(|name| {
+            channels
+                .iter()
+                .position(|channel| &channel.native_channel_name == name)
+                .ok_or_else(|| {
+                    IntanError::Other(format!("{} '{}' is not an amplifier channel", what, name))
+                        .into()
+                })
+        })
+        .collect()
+}
+
+/// Subtracts the reference of each group from the loaded amplifier data, in
+/// whichever form it is present
+fn apply_references(
+    rhs_file: &mut RhsFile,
+    references: &[(Vec<usize>, Vec<usize>)],
+    method: ReferenceMethod,
+) -> Result<(), Box<dyn Error>> {
+    let Some(data) = rhs_file.data.as_mut() else {
+        return Ok(());
+    };
+    if data.spilled.is_some() {
+        return Err(Box::new(IntanError::Other(
+            "Signals spilled to disk cannot be re-referenced in place".to_string(),
+        )));
+    }
+    match signal_views(SignalType::Amplifier, data) {
+        (_, _, true) => {
+            return Err(Box::new(IntanError::Other(
+                "Raw amplifier codes cannot be re-referenced; load the data scaled instead"
+                    .to_string(),
+            )));
+        }
+        (Some(values), _, _) => rereference_rows(values, references, method),
+        (None, Some(values), _) => rereference_rows(values, references, method),
+        (None, None, _) => {}
+    }
+    Ok(())
+}
+
+/// Subtracts the reference of each group from its rows, given as the rows of the
+/// group and the rows the reference is computed from
+fn rereference_rows<T: Sample>(
+    mut data: ArrayViewMut2<'_, T>,
+    groups: &[(Vec<usize>, Vec<usize>)],
+    method: ReferenceMethod,
+) {
+    let num_samples = data.ncols();
+    for (rows, reference_rows) in groups {
+        let view = data.view();
+        let reference: Vec<f64> = match method {
+            ReferenceMethod::Average => {
+                let mut sum = vec![0.0; num_samples];
+                for &row in reference_rows {
+                    for (total, value) in sum.iter_mut().zip(view.row(row)) {
+                        *total += value.to_f64();
+                    }
+                }
+                let count = reference_rows.len() as f64;
+                sum.into_iter().map(|total| total / count).collect()
+            }
+            ReferenceMethod::Median => (0..num_samples)
+                .into_par_iter()
+                .map_init(Vec::new, |values, sample| {
+                    values.clear();
+                    values.extend(
+                        reference_rows
+                            .iter()
+                            .map(|&row| view[[row, sample]].to_f64()),
+                    );
+                    // An empty reference leaves the rows as they are
+                    median(values).unwrap_or(0.0)
+                })
+                .collect(),
+        };
+        for &row in rows {
+            for (value, offset) in data.row_mut(row).iter_mut().zip(&reference) {
+                *value = T::from_f64(value.to_f64() - offset);
+            }
+        }
+    }
+}
+
+/// Minimal complex arithmetic for the filter design and the spectral analysis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Complex {
+    pub(crate) re: f64,
+    pub(crate) im: f64,
+}
+
+impl Complex {
+    pub(crate) fn real(re: f64) -> Complex {
+        Complex { re, im: 0.0 }
+    }
+
+    /// The point on the unit circle at `angle` radians
+    fn from_angle(angle: f64) -> Complex {
+        Complex {
+            re: angle.cos(),
+            im: angle.sin(),
+        }
+    }
+
+    fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    /// Squared magnitude
+    pub(crate) fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    pub(crate) fn conj(self) -> Complex {
+        Complex {
+            re: self.re,
+            im: -self.im,
+        }
+    }
+
+    /// Principal square root
+    fn sqrt(self) -> Complex {
+        let magnitude = self.abs().sqrt();
+        let angle = self.im.atan2(self.re) / 2.0;
+        Complex {
+            re: magnitude * angle.cos(),
+            im: magnitude * angle.sin(),
+        }
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re + other.re,
+            im: self.im + other.im,
+        }
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        self + -other
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Complex {
+        Complex {
+            re: -self.re,
+            im: -self.im,
+        }
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, other: Complex) -> Complex {
+        let denominator = other.re * other.re + other.im * other.im;
+        Complex {
+            re: (self.re * other.re + self.im * other.im) / denominator,
+            im: (self.im * other.re - self.re * other.im) / denominator,
+        }
+    }
+}
+
+/// Transforms `values` in place with the radix-2 fast Fourier transform.
+///
+/// The length must be a power of two.
+pub(crate) fn fft(values: &mut [Complex]) {
+    let n = values.len();
+    debug_assert!(n.is_power_of_two());
+
+    // Bit-reversed reordering
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+
+    // Twiddle factors computed once rather than by repeated multiplication, which
+    // would accumulate rounding error over long transforms
+    let twiddles: Vec<Complex> = (0..n / 2)
+        .map(|k| Complex::from_angle(-2.0 * PI * k as f64 / n as f64))
+        .collect();
+    let mut length = 2;
+    while length <= n {
+        let half = length / 2;
+        let stride = n / length;
+        for start in (0..n).step_by(length) {
+            for k in 0..half {
+                let even = values[start + k];
+                let odd = values[start + k + half] * twiddles[k * stride];
+                values[start + k] = even + odd;
+                values[start + k + half] = even - odd;
+            }
+        }
+        length *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::RhsFileBuilder;
+    use ndarray::{s, Array1};
+
+    const SAMPLE_RATE: f64 = 30000.0;
+
+    /// Gain of the cascaded sections at `frequency` (Hz)
+    fn gain(sections: &[Biquad], frequency: f64) -> f64 {
+        let z = Complex::from_angle(-2.0 * PI * frequency / SAMPLE_RATE);
+        let evaluate = |c: [f64; 3]| {
+            Complex::real(c[0]) + Complex::real(c[1]) * z + Complex::real(c[2]) * z * z
+        };
+        sections
+            .iter()
+            .map(|section| evaluate(section.b).abs() / evaluate(section.a).abs())
+            .product()
+    }
+
+    fn decibels(gain: f64) -> f64 {
+        20.0 * gain.log10()
+    }
+
+    #[test]
+    fn gain_at_the_cutoffs_is_3_db_down() {
+        for order in 1..=MAX_ORDER {
+            for filter in [
+                ButterworthFilter::lowpass(order, 300.0),
+                ButterworthFilter::highpass(order, 300.0),
+                ButterworthFilter::bandpass(order, 300.0, 6000.0),
+            ] {
+                let sections = filter.design(SAMPLE_RATE).unwrap();
+                let cutoffs = match filter.band {
+                    FilterBand::Lowpass(cutoff) | FilterBand::Highpass(cutoff) => vec![cutoff],
+                    FilterBand::Bandpass(low, high) => vec![low, high],
+                };
+                for cutoff in cutoffs {
+                    let gain = gain(&sections, cutoff);
+                    assert!(
+                        (gain - 0.5f64.sqrt()).abs() < 1e-9,
+                        "{:?}: {} dB at {} Hz",
+                        filter,
+                        decibels(gain),
+                        cutoff
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn passband_gain_is_one_and_stopband_falls_with_the_order() {
+        let mut previous = [1.0; 3];
+        // Geometric center of the band before the bilinear transform
+        let prewarped = |frequency: f64| (PI * frequency / SAMPLE_RATE).tan();
+        let center = (prewarped(300.0) * prewarped(6000.0)).sqrt().atan() * SAMPLE_RATE / PI;
+        for order in 1..=MAX_ORDER {
+            let lowpass = ButterworthFilter::lowpass(order, 300.0)
+                .design(SAMPLE_RATE)
+                .unwrap();
+            let highpass = ButterworthFilter::highpass(order, 300.0)
+                .design(SAMPLE_RATE)
+                .unwrap();
+            let bandpass = ButterworthFilter::bandpass(order, 300.0, 6000.0)
+                .design(SAMPLE_RATE)
+                .unwrap();
+            assert!((gain(&lowpass, 0.0) - 1.0).abs() < 1e-9);
+            assert!((gain(&lowpass, 30.0) - 1.0).abs() < 0.01);
+            assert!((gain(&highpass, SAMPLE_RATE / 2.0) - 1.0).abs() < 1e-9);
+            assert!((gain(&highpass, 3000.0) - 1.0).abs() < 0.01);
+            assert!((gain(&bandpass, center) - 1.0).abs() < 1e-9);
+
+            // An octave into the stopband, each order attenuates by several dB more
+            let stopband = [
+                gain(&lowpass, 600.0),
+                gain(&highpass, 150.0),
+                gain(&bandpass, 150.0),
+            ];
+            for (gain, previous) in stopband.iter().zip(&previous) {
+                assert!(
+                    *gain < previous / 1.5,
+                    "order {}: {} dB",
+                    order,
+                    decibels(*gain)
+                );
+            }
+            previous = stopband;
+        }
+    }
+
+    #[test]
+    fn sines_are_passed_and_attenuated() {
+        let sections = ButterworthFilter::lowpass(4, 1000.0)
+            .design(SAMPLE_RATE)
+            .unwrap();
+        // Amplitude of a unit sine after the filter has settled, from its RMS over
+        // whole periods
+        let amplitude = |frequency: f64| {
+            let mut signal = Array1::from_shape_fn(30000, |i| {
+                (2.0 * PI * frequency * i as f64 / SAMPLE_RATE).sin()
+            });
+            let mut states = vec![[0.0; 2]; sections.len()];
+            filter_channel(&sections, signal.view_mut(), &mut states, true);
+            let settled = signal.slice(s![15000..]);
+            (2.0 * settled.mapv(|value| value * value).mean().unwrap()).sqrt()
+        };
+        assert!((amplitude(100.0) - 1.0).abs() < 1e-3);
+        assert!((amplitude(1000.0) - 0.5f64.sqrt()).abs() < 1e-3);
+        assert!(amplitude(4000.0) < 0.01);
+    }
+
+    #[test]
+    fn filtering_block_by_block_matches_the_whole_signal() {
+        let recording = RhsFileBuilder::new()
+            .num_samples(128 * 20)
+            .amplifier_channels(3)
+            .sine(0, 200.0, 100.0)
+            .sine(1, 3000.0, 50.0)
+            .sine(2, 7000.0, 80.0)
+            .build()
+            .unwrap();
+        let data = recording.data.as_ref().unwrap();
+        let n = data.num_samples();
+
+        for filter in [
+            ButterworthFilter::highpass(2, 300.0),
+            ButterworthFilter::bandpass(4, 300.0, 6000.0),
+        ] {
+            let mut whole = recording.clone();
+            whole.apply_filter(SignalType::Amplifier, &filter).unwrap();
+            let expected = whole.data.unwrap().amplifier_data.unwrap();
+
+            for block_size in [1, 7, 128, 1000, n] {
+                let mut signal_filter = SignalFilter::new(SignalType::Amplifier, filter.clone());
+                signal_filter.prepare(&recording.header).unwrap();
+                for start in (0..n).step_by(block_size) {
+                    let end = (start + block_size).min(n);
+                    let amplifier = data.amplifier_data.as_ref().unwrap();
+                    let mut block = data.clone();
+                    block.amplifier_data = Some(amplifier.slice(s![.., start..end]).to_owned());
+                    signal_filter.process_data(&mut block).unwrap();
+                    let filtered = block.amplifier_data.unwrap();
+                    assert_eq!(
+                        filtered,
+                        expected.slice(s![.., start..end]),
+                        "{:?} in blocks of {}",
+                        filter,
+                        block_size
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        for order in [0, MAX_ORDER + 1] {
+            assert!(ButterworthFilter::lowpass(order, 300.0)
+                .design(SAMPLE_RATE)
+                .is_err());
+            assert!(ButterworthFilter::bandpass(order, 300.0, 6000.0)
+                .design(SAMPLE_RATE)
+                .is_err());
+        }
+        for filter in [
+            ButterworthFilter::lowpass(2, 0.0),
+            ButterworthFilter::highpass(2, SAMPLE_RATE / 2.0),
+            ButterworthFilter::lowpass(2, f64::NAN),
+            ButterworthFilter::bandpass(2, 6000.0, 300.0),
+            ButterworthFilter::bandpass(2, 300.0, 20000.0),
+        ] {
+            assert!(filter.design(SAMPLE_RATE).is_err(), "{:?}", filter);
+        }
+
+        let recording = RhsFileBuilder::new().num_samples(128).build().unwrap();
+        let zero_phase = ButterworthFilter::lowpass(2, 300.0).zero_phase();
+        assert!(SignalFilter::new(SignalType::Amplifier, zero_phase)
+            .prepare(&recording.header)
+            .is_err());
+        let lowpass = ButterworthFilter::lowpass(2, 300.0);
+        assert!(SignalFilter::new(SignalType::Stim, lowpass.clone())
+            .prepare(&recording.header)
+            .is_err());
+
+        // A filter used before being prepared, or on raw codes
+        let mut data = recording.data.unwrap();
+        let mut signal_filter = SignalFilter::new(SignalType::Amplifier, lowpass);
+        assert!(signal_filter.process_data(&mut data).is_err());
+        signal_filter.prepare(&recording.header).unwrap();
+        data.amplifier_data_raw = data.amplifier_data.as_ref().map(|a| a.mapv(|_| 0));
+        assert!(signal_filter.process_data(&mut data).is_err());
+    }
+}
@@ -0,0 +1,506 @@
+//! Edge and pulse detection on digital input channels.
+//!
+//! Digital inputs carry TTL signals such as trial markers, camera frames, or
+//! stimulus onsets, stored as one 0 or 1 per sample. [`extract_digital_events`]
+//! finds the rising and falling edges of one input, with their sample indices and
+//! times, and pairs them into pulses with their durations.
+//!
+//! A [`DigitalLineMap`] names the inputs after what they signal, so that
+//! [`RhsFile::named_events`] returns one list of [`NamedEvent`]s across all
+//! mapped inputs. Inputs that together carry a binary event code, such as 8 lines
+//! set by a behavior controller, are decoded with [`decode_digital_codes`].
+//!
+//! Times are computed from the timestamps, as by
+//! [`RhsFile::time_seconds`], so they stay correct across timestamp gaps and
+//! start at the first timestamp of the recording.
+//!
+//! A line map file is either JSON, mapping native or custom channel names to event
+//! names:
+//!
+//! ```text
+//! {
+//!     "DIGITAL-IN-01": "camera_trigger",
+//!     "DIGITAL-IN-02": "reward"
+//! }
+//! ```
+//!
+//! or text with one line per input holding the channel name and the event name,
+//! separated by commas, tabs, or spaces. Blank lines, lines starting with `#`, and
+//! a header line starting with `channel` are ignored. Files ending in `.json` are
+//! read as JSON, and all others as text.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::events::{extract_digital_events, DigitalLineMap};
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! let data = rhs_file.data.as_ref().unwrap();
+//! let events = extract_digital_events(data, 0, rhs_file.header.sample_rate).unwrap();
+//! println!("{} pulses", events.num_pulses());
+//! for pulse in &events.pulses {
+//!     println!("pulse at {:.4} s lasting {:.1} ms", pulse.start_time, pulse.duration * 1e3);
+//! }
+//!
+//! // The same, with the sample rate taken from the header
+//! let events = rhs_file.digital_events(0).unwrap();
+//!
+//! // Named events of several inputs, in time order
+//! let map = DigitalLineMap::from_file("digital_lines.csv").unwrap();
+//! for event in rhs_file.named_events(&map).unwrap() {
+//!     println!("{:.4} s: {} {}", event.time, event.name, event.edge);
+//! }
+//!
+//! // Trial codes on inputs 1 to 8, least significant bit first
+//! let lines: Vec<usize> = (0..8).collect();
+//! for code in rhs_file.decode_digital_codes(&lines, 3).unwrap() {
+//!     println!("{:.4} s: code {}", code.start_time, code.code);
+//! }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::json::Json;
+use crate::types::{IntanError, RhsData, RhsFile};
+
+/// Edges and pulses of one digital input channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigitalEvents {
+    /// Row of the channel in the digital input data
+    pub channel: usize,
+    /// Indices of the samples at which the input went from 0 to 1
+    pub rising_samples: Vec<usize>,
+    /// Indices of the samples at which the input went from 1 to 0
+    pub falling_samples: Vec<usize>,
+    /// Times of the rising edges (s), from their timestamps
+    pub rising_times: Vec<f64>,
+    /// Times of the falling edges (s), from their timestamps
+    pub falling_times: Vec<f64>,
+    /// Complete pulses, each from a rising edge to the next falling edge
+    ///
+    /// An input that is already high at the first sample or still high at the
+    /// last has edges without a matching pulse.
+    pub pulses: Vec<DigitalPulse>,
+}
+
+/// One pulse of a digital input, from a rising edge to the next falling edge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigitalPulse {
+    /// Indices of the samples during which the input was high
+    pub samples: Range<usize>,
+    /// Time of the rising edge (s)
+    pub start_time: f64,
+    /// Time from the rising to the falling edge (s)
+    pub duration: f64,
+}
+
+impl DigitalEvents {
+    /// Returns the number of rising edges.
+    pub fn num_rising(&self) -> usize {
+        self.rising_samples.len()
+    }
+
+    /// Returns the number of falling edges.
+    pub fn num_falling(&self) -> usize {
+        self.falling_samples.len()
+    }
+
+    /// Returns the number of complete pulses.
+    pub fn num_pulses(&self) -> usize {
+        self.pulses.len()
+    }
+
+    /// Returns the duration of each complete pulse (s).
+    pub fn pulse_durations(&self) -> Vec<f64> {
+        self.pulses.iter().map(|pulse| pulse.duration).collect()
+    }
+}
+
+/// Direction of a digital edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Edge {
+    /// From 0 to 1
+    Rising,
+    /// From 1 to 0
+    Falling,
+}
+
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Edge::Rising => write!(f, "rising"),
+            Edge::Falling => write!(f, "falling"),
+        }
+    }
+}
+
+/// An edge of a digital input named by a [`DigitalLineMap`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedEvent {
+    /// Event name of the input, from the line map
+    pub name: String,
+    /// Row of the input in the digital input data
+    pub channel: usize,
+    /// Direction of the edge
+    pub edge: Edge,
+    /// Index of the sample at which the input changed
+    pub sample: usize,
+    /// Time of the edge (s), from its timestamp
+    pub time: f64,
+}
+
+/// A value of a binary code carried by several digital inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeEvent {
+    /// Value of the code, with the first line as the least significant bit
+    pub code: u32,
+    /// Indices of the samples during which the code was held
+    pub samples: Range<usize>,
+    /// Time of the first sample of the code (s), from its timestamp
+    pub start_time: f64,
+}
+
+/// Event names of digital inputs, such as "camera_trigger" for DIGITAL-IN-01.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DigitalLineMap {
+    /// One entry per named input
+    pub lines: Vec<DigitalLineName>,
+}
+
+/// One entry of a [`DigitalLineMap`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigitalLineName {
+    /// Native or custom name of the digital input channel (e.g. "DIGITAL-IN-01")
+    pub channel: String,
+    /// Name of the events on the input
+    pub name: String,
+}
+
+impl DigitalLineMap {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        DigitalLineMap::default()
+    }
+
+    /// Names the events of a digital input channel, given by its native or custom
+    /// name.
+    pub fn line(mut self, channel: &str, name: &str) -> Self {
+        self.lines.push(DigitalLineName {
+            channel: channel.to_string(),
+            name: name.to_string(),
+        });
+        self
+    }
+
+    /// Reads a line map file, as JSON if its name ends in `.json` and as text
+    /// otherwise.
+    ///
+    /// See the [module documentation](crate::events) for the file formats.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a valid line map.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<DigitalLineMap, Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let is_json = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        if is_json {
+            DigitalLineMap::parse_json(&text)
+        } else {
+            DigitalLineMap::parse_text(&text)
+        }
+    }
+
+    /// Parses a line map in the JSON format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the text is not a JSON object of event names.
+    pub fn parse_json(text: &str) -> Result<DigitalLineMap, Box<dyn Error>> {
+        let invalid = || {
+            IntanError::Other("A JSON line map must map channel names to event names".to_string())
+        };
+        let json = Json::parse(text)
+            .map_err(|e| IntanError::Other(format!("Invalid JSON line map: {}", e)))?;
+        let Json::Object(entries) = json else {
+            return Err(Box::new(invalid()));
+        };
+
+        let mut map = DigitalLineMap::new();
+        for (channel, name) in entries {
+            let name = name.as_str().ok_or_else(invalid)?;
+            map = map.line(&channel, name);
+        }
+        Ok(map)
+    }
+
+    /// Parses a line map in the text format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line does not have a channel name and an event name.
+    pub fn parse_text(text: &str) -> Result<DigitalLineMap, Box<dyn Error>> {
+        let mut map = DigitalLineMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|field| !field.is_empty())
+                .collect();
+            match fields.as_slice() {
+                [channel, ..] if channel.eq_ignore_ascii_case("channel") => {}
+                [channel, name, ..] => map = map.line(channel, name),
+                _ => {
+                    return Err(Box::new(IntanError::Other(format!(
+                        "Line map line {} needs a channel name and an event name",
+                        number + 1
+                    ))))
+                }
+            }
+        }
+        Ok(map)
+    }
+}
+
+/// Finds the edges and pulses of digital input `channel`.
+///
+/// An edge is a sample whose value differs from that of the previous sample, so
+/// the first sample is never an edge. `sample_rate` converts timestamps to
+/// seconds, normally the [`sample_rate`](crate::RhsHeader::sample_rate) of the
+/// header.
+///
+/// # Errors
+///
+/// Returns an error if no digital input data is loaded or there is no such
+/// channel.
+pub fn extract_digital_events(
+    data: &RhsData,
+    channel: usize,
+    sample_rate: f32,
+) -> Result<DigitalEvents, Box<dyn Error>> {
+    let digital = data
+        .board_dig_in_data
+        .as_ref()
+        .ok_or_else(|| IntanError::Other("No digital input data is loaded".to_string()))?;
+    if channel >= digital.nrows() {
+        return Err(Box::new(IntanError::Other(format!(
+            "There is no digital input {}: the recording has {}",
+            channel,
+            digital.nrows()
+        ))));
+    }
+
+    let time = |sample: usize| data.timestamps[sample] as f64 / sample_rate as f64;
+    let mut events = DigitalEvents {
+        channel,
+        rising_samples: Vec::new(),
+        falling_samples: Vec::new(),
+        rising_times: Vec::new(),
+        falling_times: Vec::new(),
+        pulses: Vec::new(),
+    };
+    let mut rising = None;
+    let row = digital.row(channel);
+    for sample in 1..row.len() {
+        match (row[sample - 1] != 0, row[sample] != 0) {
+            (false, true) => {
+                events.rising_samples.push(sample);
+                events.rising_times.push(time(sample));
+                rising = Some(sample);
+            }
+            (true, false) => {
+                events.falling_samples.push(sample);
+                events.falling_times.push(time(sample));
+                if let Some(start) = rising.take() {
+                    events.pulses.push(DigitalPulse {
+                        samples: start..sample,
+                        start_time: time(start),
+                        duration: time(sample) - time(start),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(events)
+}
+
+/// Decodes the binary code carried by the digital inputs `lines`, the first of
+/// which is the least significant bit.
+///
+/// The lines of a code rarely switch on exactly the same sample, so a code is only
+/// reported once it has been held for `settle_samples` samples, and the samples
+/// in which the lines were still changing count towards it. A glitch shorter than
+/// `settle_samples` does not interrupt the code it occurs in. A code of 0 means
+/// that no event is signalled and is not reported.
+///
+/// # Errors
+///
+/// Returns an error if no digital input data is loaded, a line does not exist, or
+/// more than 32 lines are given.
+pub fn decode_digital_codes(
+    data: &RhsData,
+    lines: &[usize],
+    settle_samples: usize,
+    sample_rate: f32,
+) -> Result<Vec<CodeEvent>, Box<dyn Error>> {
+    let digital = data
+        .board_dig_in_data
+        .as_ref()
+        .ok_or_else(|| IntanError::Other("No digital input data is loaded".to_string()))?;
+    if lines.len() > 32 {
+        return Err(Box::new(IntanError::Other(format!(
+            "A code of {} lines does not fit in 32 bits",
+            lines.len()
+        ))));
+    }
+    if let Some(&line) = lines.iter().find(|&&line| line >= digital.nrows()) {
+        return Err(Box::new(IntanError::Other(format!(
+            "There is no digital input {}: the recording has {}",
+            line,
+            digital.nrows()
+        ))));
+    }
+
+    let code_at = |sample: usize| {
+        lines.iter().enumerate().fold(0u32, |code, (bit, &line)| {
+            code | (u32::from(digital[[line, sample]] != 0) << bit)
+        })
+    };
+    let time = |sample: usize| data.timestamps[sample] as f64 / sample_rate as f64;
+    let num_samples = digital.ncols();
+    let mut codes: Vec<CodeEvent> = Vec::new();
+    // Code of the last run held for long enough, and the end of that run
+    let mut settled = None;
+    let mut settled_end = 0;
+    let mut run_start = 0;
+    let mut run_code = 0;
+    for sample in 0..=num_samples {
+        let code = (sample < num_samples).then(|| code_at(sample));
+        if sample == 0 || code == Some(run_code) {
+            run_code = code.unwrap_or_default();
+            continue;
+        }
+
+        if sample - run_start >= settle_samples.max(1) {
+            if settled == Some(run_code) {
+                // Back to the same code after a glitch shorter than the settle time
+                if let Some(last) = codes.last_mut().filter(|_| run_code != 0) {
+                    last.samples.end = sample;
+                }
+            } else if run_code != 0 {
+                codes.push(CodeEvent {
+                    code: run_code,
+                    samples: settled_end..sample,
+                    start_time: time(settled_end),
+                });
+            }
+            settled = Some(run_code);
+            settled_end = sample;
+        }
+        run_start = sample;
+        run_code = code.unwrap_or_default();
+    }
+    Ok(codes)
+}
+
+impl RhsFile {
+    /// Finds the edges and pulses of digital input `channel`, with times from the
+    /// header's sample rate.
+    ///
+    /// See [`extract_digital_events`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no digital input data is loaded or there is no such
+    /// channel.
+    pub fn digital_events(&self, channel: usize) -> Result<DigitalEvents, Box<dyn Error>> {
+        let data = self
+            .data
+            .as_ref()
+            .ok_or_else(|| IntanError::Other("The recording has no data".to_string()))?;
+        extract_digital_events(data, channel, self.header.sample_rate)
+    }
+
+    /// Returns the edges of the digital inputs named by `map`, labelled with their
+    /// event names and sorted by sample.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the map names a channel that is not a digital input of
+    /// the recording, or no digital input data is loaded.
+    pub fn named_events(&self, map: &DigitalLineMap) -> Result<Vec<NamedEvent>, Box<dyn Error>> {
+        let mut named = Vec::new();
+        for line in &map.lines {
+            let channel = self
+                .header
+                .board_dig_in_channels
+                .iter()
+                .position(|c| {
+                    c.native_channel_name == line.channel || c.custom_channel_name == line.channel
+                })
+                .ok_or_else(|| {
+                    IntanError::Other(format!(
+                        "Channel '{}' of event '{}' is not a digital input",
+                        line.channel, line.name
+                    ))
+                })?;
+            let events = self.digital_events(channel)?;
+            let edges = |edge, samples: Vec<usize>, times: Vec<f64>| {
+                samples
+                    .into_iter()
+                    .zip(times)
+                    .map(move |(sample, time)| NamedEvent {
+                        name: line.name.clone(),
+                        channel,
+                        edge,
+                        sample,
+                        time,
+                    })
+            };
+            named.extend(edges(
+                Edge::Rising,
+                events.rising_samples,
+                events.rising_times,
+            ));
+            named.extend(edges(
+                Edge::Falling,
+                events.falling_samples,
+                events.falling_times,
+            ));
+        }
+        named.sort_by_key(|event| event.sample);
+        Ok(named)
+    }
+
+    /// Decodes the binary code carried by the digital inputs `lines`, with times
+    /// from the header's sample rate.
+    ///
+    /// See [`decode_digital_codes`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no digital input data is loaded, a line does not exist, or
+    /// more than 32 lines are given.
+    pub fn decode_digital_codes(
+        &self,
+        lines: &[usize],
+        settle_samples: usize,
+    ) -> Result<Vec<CodeEvent>, Box<dyn Error>> {
+        let data = self
+            .data
+            .as_ref()
+            .ok_or_else(|| IntanError::Other("The recording has no data".to_string()))?;
+        decode_digital_codes(data, lines, settle_samples, self.header.sample_rate)
+    }
+}
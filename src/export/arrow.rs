@@ -0,0 +1,383 @@
+//! Conversion to Apache Arrow record batches.
+//!
+//! [`RhsFile::to_arrow`] turns a recording into Arrow [`RecordBatch`]es, so the
+//! data can be handed to DataFusion, Arrow Flight, Polars, or a Parquet writer.
+//! Two layouts are available:
+//!
+//! - [`ArrowLayout::Wide`]: a `timestamp` column plus one column per channel, named
+//!   after the native channel name (`A-000`, `ANALOG-IN-1`, ...). DC amplifier,
+//!   stimulation, and stimulation status columns of a channel get a suffix
+//!   (`A-000_DC`, `A-000_STIM`, `A-000_COMPLIANCE`, `A-000_CHARGE_RECOVERY`,
+//!   `A-000_AMP_SETTLE`). Analog channels are `Float64`, all others `Boolean`.
+//! - [`ArrowLayout::Long`]: one row per channel and sample, with `timestamp`,
+//!   `channel` (dictionary encoded), and `value` columns. Only analog channels are
+//!   included, ordered channel by channel.
+//!
+//! Each field of the wide layout carries `signal_type`, `custom_channel_name`, and
+//! (for analog channels) `unit` metadata. The schema metadata holds the
+//! `sample_rate` and the full header as JSON under `header`; the long layout also
+//! lists its channels as JSON under `channels`.
+//!
+//! [`RhsFile::into_arrow`] builds the wide layout without copying the analog data
+//! or timestamps: each column points into the buffer of the original array.
+//!
+//! This module requires the `arrow` feature. The batches use the types of the
+//! `arrow-array` crate, which the `arrow` crate re-exports.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::export::arrow::{ArrowLayout, ArrowOptions};
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! let batches = rhs_file.to_arrow(&ArrowOptions::default()).unwrap();
+//! println!("{} batches, schema: {}", batches.len(), batches[0].schema());
+//!
+//! let options = ArrowOptions {
+//!     layout: ArrowLayout::Long,
+//!     ..ArrowOptions::default()
+//! };
+//! let long = rhs_file.to_arrow(&options).unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+
+use arrow_array::types::UInt32Type;
+use arrow_array::{
+    ArrayRef, BooleanArray, DictionaryArray, Float64Array, Int64Array, RecordBatch, StringArray,
+    UInt32Array,
+};
+use arrow_buffer::{ArrowNativeType, BooleanBuffer, BooleanBufferBuilder, Buffer, ScalarBuffer};
+use arrow_schema::{DataType, Field, Schema};
+use ndarray::{Array2, Axis};
+
+use super::header_json;
+use crate::json::Json;
+use crate::types::{ChannelInfo, IntanError, RhsData, RhsFile, RhsHeader};
+
+/// Table layout of the record batches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrowLayout {
+    /// One row per sample, one column per channel
+    Wide,
+    /// One row per channel and sample, analog channels only
+    Long,
+}
+
+/// Settings for Arrow conversion.
+#[derive(Debug, Clone)]
+pub struct ArrowOptions {
+    /// Table layout
+    pub layout: ArrowLayout,
+    /// Maximum number of rows per record batch
+    pub batch_size: usize,
+}
+
+impl Default for ArrowOptions {
+    fn default() -> Self {
+        ArrowOptions {
+            layout: ArrowLayout::Wide,
+            batch_size: 65536,
+        }
+    }
+}
+
+impl RhsFile {
+    /// Converts the recording to Arrow record batches.
+    ///
+    /// The data is copied once into Arrow buffers. Batches are zero-copy slices of
+    /// a single table, so all of them share the same schema.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recording has no data, its signals are spilled to
+    /// disk, or `batch_size` is zero.
+    pub fn to_arrow(&self, options: &ArrowOptions) -> Result<Vec<RecordBatch>, Box<dyn Error>> {
+        let data = self.data.as_ref().ok_or_else(|| {
+            IntanError::Other("Cannot convert to Arrow: the recording has no data".to_string())
+        })?;
+        record_batches(&self.header, data.clone(), options)
+    }
+
+    /// Converts the recording to Arrow record batches, reusing its buffers.
+    ///
+    /// In the wide layout the timestamp and analog columns point into the arrays of
+    /// the recording, so no sample is copied unless an array is not in standard
+    /// (row-major) layout. Boolean columns are always bit-packed into new buffers,
+    /// and raw codes and single-precision data are converted to new `f64` arrays.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recording has no data, its signals are spilled to
+    /// disk, or `batch_size` is zero.
+    pub fn into_arrow(self, options: &ArrowOptions) -> Result<Vec<RecordBatch>, Box<dyn Error>> {
+        let data = self.data.ok_or_else(|| {
+            IntanError::Other("Cannot convert to Arrow: the recording has no data".to_string())
+        })?;
+        record_batches(&self.header, data, options)
+    }
+}
+
+/// A channel column, before it is turned into a field
+struct Column {
+    name: String,
+    signal_type: &'static str,
+    custom_channel_name: String,
+    unit: Option<&'static str>,
+    values: ArrayRef,
+}
+
+impl Column {
+    fn field(&self) -> Field {
+        let mut metadata = HashMap::from([
+            ("signal_type".to_string(), self.signal_type.to_string()),
+            (
+                "custom_channel_name".to_string(),
+                self.custom_channel_name.clone(),
+            ),
+        ]);
+        if let Some(unit) = self.unit {
+            metadata.insert("unit".to_string(), unit.to_string());
+        }
+        Field::new(&self.name, self.values.data_type().clone(), false).with_metadata(metadata)
+    }
+
+    fn json(&self) -> Json {
+        Json::object(vec![
+            ("name", self.name.as_str().into()),
+            ("signal_type", self.signal_type.into()),
+            (
+                "custom_channel_name",
+                self.custom_channel_name.as_str().into(),
+            ),
+            ("unit", self.unit.into()),
+        ])
+    }
+}
+
+/// Builds the table in the requested layout and slices it into batches
+pub(crate) fn record_batches(
+    header: &RhsHeader,
+    data: RhsData,
+    options: &ArrowOptions,
+) -> Result<Vec<RecordBatch>, Box<dyn Error>> {
+    if options.batch_size == 0 {
+        return Err(Box::new(IntanError::Other(
+            "Arrow batch size must be greater than zero".to_string(),
+        )));
+    }
+
+    let mut data = data.into_double_precision()?;
+    let num_samples = data.num_samples();
+    let timestamps =
+        primitive_rows(std::mem::take(&mut data.timestamps).insert_axis(Axis(0))).remove(0);
+    let (analog, digital) = columns(header, data);
+
+    let mut metadata = HashMap::from([
+        ("sample_rate".to_string(), header.sample_rate.to_string()),
+        ("header".to_string(), header_json(header).to_string_pretty()),
+    ]);
+    let mut fields = vec![Field::new("timestamp", DataType::Int64, false)];
+    let mut arrays: Vec<ArrayRef> = Vec::new();
+
+    match options.layout {
+        ArrowLayout::Wide => {
+            arrays.push(Arc::new(Int64Array::new(timestamps, None)));
+            for column in analog.into_iter().chain(digital) {
+                fields.push(column.field());
+                arrays.push(column.values);
+            }
+        }
+        ArrowLayout::Long => {
+            let num_rows = analog.len() * num_samples;
+            let mut timestamp = Vec::with_capacity(num_rows);
+            let mut channel = Vec::with_capacity(num_rows);
+            let mut value = Vec::with_capacity(num_rows);
+            for (index, column) in analog.iter().enumerate() {
+                timestamp.extend_from_slice(&timestamps);
+                channel.extend(std::iter::repeat_n(index as u32, num_samples));
+                if let Some(values) = column.values.as_any().downcast_ref::<Float64Array>() {
+                    value.extend_from_slice(values.values());
+                }
+            }
+
+            let names = StringArray::from_iter_values(analog.iter().map(|c| c.name.as_str()));
+            let channel = DictionaryArray::<UInt32Type>::try_new(
+                UInt32Array::from(channel),
+                Arc::new(names),
+            )?;
+            fields.push(Field::new(
+                "channel",
+                DataType::Dictionary(Box::new(DataType::UInt32), Box::new(DataType::Utf8)),
+                false,
+            ));
+            fields.push(Field::new("value", DataType::Float64, false));
+            arrays.push(Arc::new(Int64Array::from(timestamp)));
+            arrays.push(Arc::new(channel));
+            arrays.push(Arc::new(Float64Array::from(value)));
+
+            let channels = Json::Array(analog.iter().map(Column::json).collect());
+            metadata.insert("channels".to_string(), channels.to_string_pretty());
+        }
+    }
+
+    let schema = Arc::new(Schema::new_with_metadata(fields, metadata));
+    let table = RecordBatch::try_new(schema, arrays)?;
+
+    let num_rows = table.num_rows();
+    if num_rows == 0 {
+        return Ok(vec![table]);
+    }
+    Ok((0..num_rows)
+        .step_by(options.batch_size)
+        .map(|offset| table.slice(offset, options.batch_size.min(num_rows - offset)))
+        .collect())
+}
+
+/// Splits the data into analog (`Float64`) and digital (`Boolean`) channel columns
+fn columns(header: &RhsHeader, data: RhsData) -> (Vec<Column>, Vec<Column>) {
+    let units = data.units;
+    let mut analog = Vec::new();
+    for (signal_type, suffix, channels, values, unit) in [
+        (
+            "amplifier",
+            "",
+            &header.amplifier_channels,
+            data.amplifier_data,
+            units.amplifier,
+        ),
+        (
+            "dc_amplifier",
+            "_DC",
+            &header.amplifier_channels,
+            data.dc_amplifier_data,
+            units.dc_amplifier,
+        ),
+        (
+            "stim",
+            "_STIM",
+            &header.amplifier_channels,
+            data.stim_data,
+            units.stim,
+        ),
+        (
+            "board_adc",
+            "",
+            &header.board_adc_channels,
+            data.board_adc_data,
+            units.board_adc,
+        ),
+        (
+            "board_dac",
+            "",
+            &header.board_dac_channels,
+            data.board_dac_data,
+            units.board_dac,
+        ),
+    ] {
+        let Some(values) = values else { continue };
+        for (channel, values) in channels.iter().zip(primitive_rows(values)) {
+            analog.push(column(
+                channel,
+                signal_type,
+                suffix,
+                Some(unit.symbol()),
+                Arc::new(Float64Array::new(values, None)),
+            ));
+        }
+    }
+
+    let mut digital = Vec::new();
+    for (signal_type, suffix, values) in [
+        (
+            "compliance_limit",
+            "_COMPLIANCE",
+            data.compliance_limit_data,
+        ),
+        (
+            "charge_recovery",
+            "_CHARGE_RECOVERY",
+            data.charge_recovery_data,
+        ),
+        ("amp_settle", "_AMP_SETTLE", data.amp_settle_data),
+    ] {
+        let Some(values) = values else { continue };
+        for (index, channel) in header.amplifier_channels.iter().enumerate() {
+            let mut bits = BooleanBufferBuilder::new(values.num_samples());
+            for run in values.runs(index) {
+                bits.append_n(run.start - bits.len(), false);
+                bits.append_n(run.len(), true);
+            }
+            bits.append_n(values.num_samples() - bits.len(), false);
+            let bits = bits.finish();
+            digital.push(column(
+                channel,
+                signal_type,
+                suffix,
+                None,
+                Arc::new(BooleanArray::new(bits, None)),
+            ));
+        }
+    }
+    for (signal_type, channels, values) in [
+        (
+            "board_dig_in",
+            &header.board_dig_in_channels,
+            data.board_dig_in_data,
+        ),
+        (
+            "board_dig_out",
+            &header.board_dig_out_channels,
+            data.board_dig_out_data,
+        ),
+    ] {
+        let Some(values) = values else { continue };
+        for (channel, row) in channels.iter().zip(values.rows()) {
+            let bits = BooleanBuffer::from_iter(row.iter().map(|&value| value != 0));
+            digital.push(column(
+                channel,
+                signal_type,
+                "",
+                None,
+                Arc::new(BooleanArray::new(bits, None)),
+            ));
+        }
+    }
+
+    (analog, digital)
+}
+
+fn column(
+    channel: &ChannelInfo,
+    signal_type: &'static str,
+    suffix: &str,
+    unit: Option<&'static str>,
+    values: ArrayRef,
+) -> Column {
+    Column {
+        name: format!("{}{}", channel.native_channel_name, suffix),
+        signal_type,
+        custom_channel_name: channel.custom_channel_name.clone(),
+        unit,
+        values,
+    }
+}
+
+/// Moves a 2-D array into one Arrow buffer and returns a slice of it for each row
+fn primitive_rows<T: ArrowNativeType>(values: Array2<T>) -> Vec<ScalarBuffer<T>> {
+    let (num_rows, row_length) = values.dim();
+    let values = if values.is_standard_layout() {
+        values
+    } else {
+        values.as_standard_layout().into_owned()
+    };
+    let (raw, offset) = values.into_raw_vec_and_offset();
+    let offset = offset.unwrap_or(0);
+    let buffer = Buffer::from_vec(raw);
+    (0..num_rows)
+        .map(|row| ScalarBuffer::new(buffer.clone(), offset + row * row_length, row_length))
+        .collect()
+}
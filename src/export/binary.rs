@@ -0,0 +1,164 @@
+//! Export to flat int16 binary for Kilosort and SpikeInterface.
+//!
+//! [`RhsFile::to_int16_binary`] writes the amplifier data as interleaved
+//! little-endian `int16` samples (sample 0 of every channel, then sample 1, ...),
+//! the raw format expected by Kilosort and SpikeInterface's `read_binary`. Each
+//! value is the amplifier ADC step count, so multiplying by 0.195 gives μV.
+//!
+//! Two metadata files are written next to the binary:
+//!
+//! - `<name>.json`: sample rate, channel count, dtype, gain and offset to μV, and
+//!   the channel names, i.e. everything `spikeinterface.read_binary` needs.
+//! - `<name>.probe.json`: a Kilosort probe (`chanMap`, `xc`, `yc`, `kcoords`,
+//!   `n_chan`). Intan files do not record electrode positions, so channels are
+//!   placed on a vertical line 20 μm apart, one column (and `kcoords` group) per
+//!   port. Replace it with the real probe geometry for spike sorting, for example
+//!   with [`RhsFile::to_probeinterface`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! rhs_file.to_int16_binary("recording.bin").unwrap();
+//! // Writes recording.bin, recording.json, and recording.probe.json
+//! ```
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use ndarray::ArrayView2;
+
+use crate::json::Json;
+use crate::reader::AMPLIFIER_SCALE_FACTOR;
+use crate::types::{IntanError, RhsFile, RhsHeader};
+use crate::units::Unit;
+
+/// Spacing of the placeholder electrode positions (μm)
+const PLACEHOLDER_PITCH: f64 = 20.0;
+/// Horizontal distance between the columns of different ports (μm)
+const PLACEHOLDER_PORT_SPACING: f64 = 200.0;
+
+impl RhsFile {
+    /// Writes the amplifier data as interleaved int16 samples with JSON metadata.
+    ///
+    /// See the [module documentation](crate::export::binary) for the files written.
+    /// Values outside the int16 range (possible after processing) are clamped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recording has no amplifier data, its signals are
+    /// spilled to disk, or a file cannot be written.
+    pub fn to_int16_binary<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let data = self.data.as_ref().ok_or_else(|| {
+            IntanError::Other("Cannot export to binary: the recording has no data".to_string())
+        })?;
+        let data = data.to_double_precision()?;
+        let amplifier_data = data.amplifier_data.as_ref().ok_or_else(|| {
+            IntanError::Other(
+                "Cannot export to binary: the recording has no amplifier data".to_string(),
+            )
+        })?;
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_int16_samples(&mut writer, amplifier_data.view(), data.units.amplifier)?;
+        writer.flush()?;
+        write_binary_metadata(path, &self.header, data.num_samples())?;
+
+        Ok(())
+    }
+}
+
+/// Writes amplifier data in `unit` as interleaved int16 ADC steps, clamping
+/// values outside the int16 range
+pub(crate) fn write_int16_samples<W: Write>(
+    writer: &mut W,
+    amplifier_data: ArrayView2<'_, f64>,
+    unit: Unit,
+) -> Result<(), Box<dyn Error>> {
+    let to_steps = unit.factor_to(Unit::Microvolts).unwrap_or(1.0) / AMPLIFIER_SCALE_FACTOR;
+    for value in amplifier_data.t().iter() {
+        let step = (value * to_steps)
+            .round()
+            .clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16;
+        writer.write_all(&step.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Writes the JSON metadata and placeholder probe next to a binary file at `path`
+/// holding `num_samples` samples of the amplifier channels of `header`
+pub(crate) fn write_binary_metadata(
+    path: &Path,
+    header: &RhsHeader,
+    num_samples: usize,
+) -> Result<(), Box<dyn Error>> {
+    let channels = &header.amplifier_channels;
+    let metadata = Json::object(vec![
+        (
+            "file_paths",
+            vec![path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()]
+            .into(),
+        ),
+        ("sampling_frequency", header.sample_rate.into()),
+        ("num_channels", channels.len().into()),
+        ("num_samples", num_samples.into()),
+        ("dtype", "int16".into()),
+        ("time_axis", 0i32.into()),
+        ("gain_to_uV", AMPLIFIER_SCALE_FACTOR.into()),
+        ("offset_to_uV", 0.0.into()),
+        (
+            "channel_ids",
+            channels
+                .iter()
+                .map(|channel| channel.native_channel_name.as_str())
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+        (
+            "channel_names",
+            channels
+                .iter()
+                .map(|channel| channel.custom_channel_name.as_str())
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+    ]);
+    fs::write(path.with_extension("json"), metadata.to_string_pretty())?;
+
+    // Placeholder geometry: one vertical column per port
+    let mut ports: Vec<&str> = Vec::new();
+    let mut xc = Vec::with_capacity(channels.len());
+    let mut yc = Vec::with_capacity(channels.len());
+    let mut kcoords = Vec::with_capacity(channels.len());
+    for channel in channels {
+        let port = match ports.iter().position(|&p| p == channel.port_prefix) {
+            Some(port) => port,
+            None => {
+                ports.push(&channel.port_prefix);
+                ports.len() - 1
+            }
+        };
+        let row = kcoords.iter().filter(|&&k| k == port + 1).count();
+        xc.push(port as f64 * PLACEHOLDER_PORT_SPACING);
+        yc.push(row as f64 * PLACEHOLDER_PITCH);
+        kcoords.push(port + 1);
+    }
+    let probe = Json::object(vec![
+        ("chanMap", (0..channels.len()).collect::<Vec<_>>().into()),
+        ("xc", xc.into()),
+        ("yc", yc.into()),
+        ("kcoords", kcoords.into()),
+        ("n_chan", channels.len().into()),
+    ]);
+    fs::write(path.with_extension("probe.json"), probe.to_string_pretty())?;
+
+    Ok(())
+}
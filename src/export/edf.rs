@@ -0,0 +1,487 @@
+//! Export to EDF+ and BDF+.
+//!
+//! [`RhsFile::to_edf`] writes amplifier and board ADC channels as signals of an
+//! EDF+ (16-bit) or BDF+ (24-bit) file, the formats read by most clinical EEG
+//! review software. Amplifier channels are written in `uV` and ADC channels in
+//! `V`, with physical ranges matching the Intan ADC, so EDF's 16 bits hold
+//! amplifier data without loss. Digital inputs become annotations: every period
+//! during which a digital input is high is written as one annotation with the
+//! channel name as its text and the length of the period as its duration.
+//!
+//! EDF files consist of fixed-length data records. If the recording does not fill
+//! the last record, the remaining samples are written as zeros. Gaps in the
+//! timestamps are not represented; samples are written back to back.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::export::edf::{EdfFormat, EdfOptions};
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! rhs_file.to_edf("recording.edf", &EdfOptions::default()).unwrap();
+//!
+//! // 24-bit BDF+ for signals that need more resolution than the Intan ADC range
+//! let options = EdfOptions {
+//!     format: EdfFormat::Bdf,
+//!     ..EdfOptions::default()
+//! };
+//! rhs_file.to_edf("recording.bdf", &options).unwrap();
+//! ```
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use ndarray::ArrayView2;
+
+use crate::datetime::UtcDateTime;
+use crate::reader::{ADC_DAC_OFFSET, ADC_DAC_SCALE_FACTOR, AMPLIFIER_SCALE_FACTOR};
+use crate::types::{ChannelInfo, IntanError, RhsFile};
+use crate::units::Unit;
+
+/// Month abbreviations used in the EDF+ recording field
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+/// Sample format of the exported file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdfFormat {
+    /// EDF+ with 16-bit samples
+    Edf,
+    /// BDF+ with 24-bit samples
+    Bdf,
+}
+
+impl EdfFormat {
+    fn bytes_per_sample(self) -> usize {
+        match self {
+            EdfFormat::Edf => 2,
+            EdfFormat::Bdf => 3,
+        }
+    }
+
+    fn digital_range(self) -> (i32, i32) {
+        match self {
+            EdfFormat::Edf => (-32768, 32767),
+            EdfFormat::Bdf => (-8388608, 8388607),
+        }
+    }
+}
+
+/// Options for EDF+/BDF+ export.
+#[derive(Debug, Clone)]
+pub struct EdfOptions {
+    /// 16-bit EDF+ or 24-bit BDF+
+    pub format: EdfFormat,
+    /// Length of each data record (seconds); the sample rate times this duration
+    /// must be a whole number of samples
+    pub record_duration: f64,
+    /// EDF+ patient identification field ("code sex birthdate name", `X` for unknown)
+    pub patient_id: String,
+    /// Start of the recording; if `None`, the recording's
+    /// [`recording_start`](RhsFile::recording_start) is used when known, and the
+    /// time of export otherwise
+    pub start_time: Option<SystemTime>,
+    /// Whether to include board ADC channels as signals
+    pub include_adc: bool,
+    /// Whether to write digital input high periods as annotations
+    pub digital_annotations: bool,
+}
+
+impl Default for EdfOptions {
+    fn default() -> Self {
+        EdfOptions {
+            format: EdfFormat::Edf,
+            record_duration: 1.0,
+            patient_id: "X X X X".to_string(),
+            start_time: None,
+            include_adc: true,
+            digital_annotations: true,
+        }
+    }
+}
+
+/// One ordinary (non-annotation) signal of the file
+struct Signal<'a> {
+    label: &'a str,
+    transducer: &'static str,
+    dimension: &'static str,
+    prefiltering: String,
+    physical_min: f64,
+    physical_max: f64,
+    /// Data of all signals of this type, the signal's row is `row`
+    values: ArrayView2<'a, f64>,
+    row: usize,
+    /// Factor converting `values` to the physical dimension
+    factor: f64,
+}
+
+/// A digital input high period
+struct Annotation<'a> {
+    onset: usize,
+    duration: usize,
+    text: &'a str,
+}
+
+impl RhsFile {
+    /// Exports amplifier and ADC channels to an EDF+ or BDF+ file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recording has no data, its signals are spilled to
+    /// disk, the record duration does not hold a whole number of samples, or the
+    /// file cannot be written.
+    pub fn to_edf<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &EdfOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let data = self
+            .data
+            .as_ref()
+            .filter(|data| !data.timestamps.is_empty())
+            .ok_or_else(|| {
+                IntanError::Other("Cannot export to EDF: the recording has no data".to_string())
+            })?
+            .to_double_precision()?;
+
+        let sample_rate = f64::from(self.header.sample_rate);
+        let samples_per_record = (sample_rate * options.record_duration).round() as usize;
+        if samples_per_record == 0
+            || (samples_per_record as f64 - sample_rate * options.record_duration).abs() > 1e-6
+        {
+            return Err(Box::new(IntanError::Other(format!(
+                "Record duration of {} s does not hold a whole number of samples at {} Hz",
+                options.record_duration, sample_rate
+            ))));
+        }
+
+        let num_samples = data.timestamps.len();
+        let num_records = num_samples.div_ceil(samples_per_record);
+        let format = options.format;
+        let (digital_min, digital_max) = format.digital_range();
+
+        // Ordinary signals
+        let mut signals = Vec::new();
+        if let Some(amplifier_data) = &data.amplifier_data {
+            let factor = data
+                .units
+                .amplifier
+                .factor_to(Unit::Microvolts)
+                .unwrap_or(1.0);
+            let prefiltering = self.prefiltering();
+            let (physical_min, physical_max) = physical_range(AMPLIFIER_SCALE_FACTOR);
+            for (row, channel) in self.header.amplifier_channels.iter().enumerate() {
+                signals.push(Signal {
+                    label: &channel.custom_channel_name,
+                    transducer: "Intan amplifier",
+                    dimension: "uV",
+                    prefiltering: prefiltering.clone(),
+                    physical_min,
+                    physical_max,
+                    values: amplifier_data.view(),
+                    row,
+                    factor,
+                });
+            }
+        }
+        if let Some(adc_data) = data.board_adc_data.as_ref().filter(|_| options.include_adc) {
+            let factor = data.units.board_adc.factor_to(Unit::Volts).unwrap_or(1.0);
+            let (physical_min, physical_max) = physical_range(ADC_DAC_SCALE_FACTOR);
+            for (row, channel) in self.header.board_adc_channels.iter().enumerate() {
+                signals.push(Signal {
+                    label: &channel.custom_channel_name,
+                    transducer: "Intan board ADC",
+                    dimension: "V",
+                    prefiltering: String::new(),
+                    physical_min,
+                    physical_max,
+                    values: adc_data.view(),
+                    row,
+                    factor,
+                });
+            }
+        }
+
+        // Annotations, grouped by the record they start in
+        let mut record_annotations: Vec<Vec<Annotation>> =
+            (0..num_records).map(|_| Vec::new()).collect();
+        if let Some(dig_in_data) = data
+            .board_dig_in_data
+            .as_ref()
+            .filter(|_| options.digital_annotations)
+        {
+            for (row, channel) in self.header.board_dig_in_channels.iter().enumerate() {
+                for annotation in high_periods(dig_in_data.row(row).iter().copied(), channel) {
+                    record_annotations[annotation.onset / samples_per_record].push(annotation);
+                }
+            }
+        }
+        let annotation_records: Vec<Vec<u8>> = record_annotations
+            .iter()
+            .enumerate()
+            .map(|(record, annotations)| {
+                annotation_record(
+                    record as f64 * options.record_duration,
+                    annotations,
+                    sample_rate,
+                )
+            })
+            .collect();
+        let bytes_per_sample = format.bytes_per_sample();
+        let annotation_samples = annotation_records
+            .iter()
+            .map(|record| record.len().div_ceil(bytes_per_sample))
+            .max()
+            .unwrap_or(1);
+
+        // Header
+        let start = match (options.start_time, self.recording_start) {
+            (Some(time), _) => UtcDateTime::from_system_time(time),
+            (None, Some(start)) => UtcDateTime::from_local(start),
+            (None, None) => UtcDateTime::now(),
+        };
+        let num_signals = signals.len() + 1;
+        let mut header = String::with_capacity(256 * (num_signals + 1));
+        let (version, reserved, annotation_label) = match format {
+            EdfFormat::Edf => ("0       ", "EDF+C", "EDF Annotations"),
+            // Preceded by a 0xFF byte, written below
+            EdfFormat::Bdf => ("BIOSEMI", "BDF+C", "BDF Annotations"),
+        };
+        header.push_str(version);
+        push_field(&mut header, &options.patient_id, 80);
+        push_field(
+            &mut header,
+            &format!(
+                "Startdate {:02}-{}-{:04} X X Intan_RHS",
+                start.day,
+                MONTHS[start.month as usize - 1],
+                start.year
+            ),
+            80,
+        );
+        push_field(
+            &mut header,
+            &format!(
+                "{:02}.{:02}.{:02}",
+                start.day,
+                start.month,
+                start.year.rem_euclid(100)
+            ),
+            8,
+        );
+        push_field(
+            &mut header,
+            &format!("{:02}.{:02}.{:02}", start.hour, start.minute, start.second),
+            8,
+        );
+        push_field(&mut header, &(256 * (num_signals + 1)).to_string(), 8);
+        push_field(&mut header, reserved, 44);
+        push_field(&mut header, &num_records.to_string(), 8);
+        push_field(&mut header, &format_number(options.record_duration, 8), 8);
+        push_field(&mut header, &num_signals.to_string(), 4);
+
+        for signal in &signals {
+            push_field(&mut header, signal.label, 16);
+        }
+        push_field(&mut header, annotation_label, 16);
+        for signal in &signals {
+            push_field(&mut header, signal.transducer, 80);
+        }
+        push_field(&mut header, "", 80);
+        for signal in &signals {
+            push_field(&mut header, signal.dimension, 8);
+        }
+        push_field(&mut header, "", 8);
+        for signal in &signals {
+            push_field(&mut header, &format_number(signal.physical_min, 8), 8);
+        }
+        push_field(&mut header, "-1", 8);
+        for signal in &signals {
+            push_field(&mut header, &format_number(signal.physical_max, 8), 8);
+        }
+        push_field(&mut header, "1", 8);
+        for _ in 0..num_signals {
+            push_field(&mut header, &digital_min.to_string(), 8);
+        }
+        for _ in 0..num_signals {
+            push_field(&mut header, &digital_max.to_string(), 8);
+        }
+        for signal in &signals {
+            push_field(&mut header, &signal.prefiltering, 80);
+        }
+        push_field(&mut header, "", 80);
+        for _ in &signals {
+            push_field(&mut header, &samples_per_record.to_string(), 8);
+        }
+        push_field(&mut header, &annotation_samples.to_string(), 8);
+        for _ in 0..num_signals {
+            push_field(&mut header, "", 32);
+        }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        if format == EdfFormat::Bdf {
+            writer.write_all(&[0xFF])?;
+        }
+        writer.write_all(header.as_bytes())?;
+
+        // Data records
+        let mut record = Vec::new();
+        for (index, annotations) in annotation_records.iter().enumerate() {
+            record.clear();
+            let first = index * samples_per_record;
+            for signal in &signals {
+                let gain = (digital_max - digital_min) as f64
+                    / (signal.physical_max - signal.physical_min);
+                let row = signal.values.row(signal.row);
+                for sample in first..first + samples_per_record {
+                    let digital = match row.get(sample) {
+                        Some(&value) => {
+                            let physical = value * signal.factor;
+                            ((physical - signal.physical_min) * gain + digital_min as f64)
+                                .round()
+                                .clamp(digital_min as f64, digital_max as f64)
+                                as i32
+                        }
+                        None => 0,
+                    };
+                    record.extend_from_slice(&digital.to_le_bytes()[..bytes_per_sample]);
+                }
+            }
+            record.extend_from_slice(annotations);
+            record.resize(
+                record.len() + annotation_samples * bytes_per_sample - annotations.len(),
+                0,
+            );
+            writer.write_all(&record)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Describes the hardware filters of the amplifier channels
+    fn prefiltering(&self) -> String {
+        let mut prefiltering = format!(
+            "HP:{}Hz LP:{}Hz",
+            format_number(f64::from(self.header.actual_lower_bandwidth), 8),
+            format_number(f64::from(self.header.actual_upper_bandwidth), 8)
+        );
+        if let Some(notch) = self.header.notch_filter_frequency {
+            prefiltering.push_str(&format!(" N:{}Hz", notch));
+        }
+        prefiltering
+    }
+}
+
+/// Physical range of a signal sampled by the 16-bit Intan ADC with the given step size
+///
+/// The limits are rounded to what fits in an 8-character header field, so the
+/// scaling used for the samples is exactly the one readers compute from the header.
+fn physical_range(step: f64) -> (f64, f64) {
+    let limit = |code: f64| {
+        format_number(code * step, 8)
+            .parse::<f64>()
+            .unwrap_or(code * step)
+    };
+    (limit(-ADC_DAC_OFFSET), limit(ADC_DAC_OFFSET - 1.0))
+}
+
+/// Finds the periods during which a digital input is high
+fn high_periods<'a>(
+    values: impl Iterator<Item = i32>,
+    channel: &'a ChannelInfo,
+) -> Vec<Annotation<'a>> {
+    let mut annotations = Vec::new();
+    let mut rise = None;
+    let mut length = 0;
+    for (sample, value) in values.enumerate() {
+        length = sample + 1;
+        match (value != 0, rise) {
+            (true, None) => rise = Some(sample),
+            (false, Some(onset)) => {
+                annotations.push(Annotation {
+                    onset,
+                    duration: sample - onset,
+                    text: &channel.custom_channel_name,
+                });
+                rise = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(onset) = rise {
+        annotations.push(Annotation {
+            onset,
+            duration: length - onset,
+            text: &channel.custom_channel_name,
+        });
+    }
+    annotations
+}
+
+/// Builds the annotation signal bytes of one data record
+///
+/// Every record starts with a time-keeping annotation giving its onset, followed
+/// by one time-stamped annotation list (TAL) per annotation.
+fn annotation_record(record_onset: f64, annotations: &[Annotation], sample_rate: f64) -> Vec<u8> {
+    let mut bytes = format!("+{}\x14\x14\x00", format_seconds(record_onset)).into_bytes();
+    for annotation in annotations {
+        bytes.extend_from_slice(
+            format!(
+                "+{}\x15{}\x14{}\x14\x00",
+                format_seconds(annotation.onset as f64 / sample_rate),
+                format_seconds(annotation.duration as f64 / sample_rate),
+                ascii(annotation.text)
+            )
+            .as_bytes(),
+        );
+    }
+    bytes
+}
+
+/// Appends a left-aligned, space-padded ASCII header field
+fn push_field(header: &mut String, value: &str, width: usize) {
+    let value: String = ascii(value).chars().take(width).collect();
+    header.push_str(&value);
+    header.extend(std::iter::repeat_n(' ', width - value.len()));
+}
+
+/// Replaces characters EDF does not allow (anything but printable ASCII)
+fn ascii(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_ascii_graphic() || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Formats a number with as many decimals as fit in `width` characters
+fn format_number(value: f64, width: usize) -> String {
+    (0..=width)
+        .rev()
+        .map(|decimals| trim_decimals(format!("{:.*}", decimals, value)))
+        .find(|text| text.len() <= width)
+        .unwrap_or_else(|| format!("{:.0}", value))
+}
+
+/// Formats a time in seconds for an annotation, with up to 7 decimals
+fn format_seconds(seconds: f64) -> String {
+    trim_decimals(format!("{:.7}", seconds))
+}
+
+fn trim_decimals(text: String) -> String {
+    if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        text
+    }
+}
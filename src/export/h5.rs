@@ -0,0 +1,58 @@
+//! Small helpers shared by the HDF5-based exporters.
+
+use std::error::Error;
+
+use hdf5::types::VarLenUnicode;
+use hdf5::{H5Type, Location};
+use ndarray::{arr0, Array1};
+
+use crate::types::IntanError;
+
+/// Converts a string to an HDF5 variable-length UTF-8 string
+pub(super) fn h5_string(value: &str) -> Result<VarLenUnicode, Box<dyn Error>> {
+    value.parse::<VarLenUnicode>().map_err(|e| {
+        Box::new(IntanError::Other(format!(
+            "Cannot store {:?} as an HDF5 string: {}",
+            value, e
+        ))) as Box<dyn Error>
+    })
+}
+
+/// Writes a scalar attribute
+pub(super) fn write_attr<T: H5Type>(
+    location: &Location,
+    name: &str,
+    value: T,
+) -> Result<(), Box<dyn Error>> {
+    location
+        .new_attr_builder()
+        .with_data(&arr0(value))
+        .create(name)?;
+    Ok(())
+}
+
+/// Writes a string attribute
+pub(super) fn write_str_attr(
+    location: &Location,
+    name: &str,
+    value: &str,
+) -> Result<(), Box<dyn Error>> {
+    write_attr(location, name, h5_string(value)?)
+}
+
+/// Writes a one-dimensional string attribute
+pub(super) fn write_str_list_attr(
+    location: &Location,
+    name: &str,
+    values: &[&str],
+) -> Result<(), Box<dyn Error>> {
+    let values = values
+        .iter()
+        .map(|value| h5_string(value))
+        .collect::<Result<Array1<_>, _>>()?;
+    location
+        .new_attr_builder()
+        .with_data(&values)
+        .create(name)?;
+    Ok(())
+}
@@ -0,0 +1,316 @@
+//! Export to a plain HDF5 file.
+//!
+//! [`RhsFile::to_hdf5`] writes every data array of a recording as its own
+//! dataset, so it can be read with h5py, MATLAB's `h5read`, or any other HDF5
+//! tool without knowing anything about Intan files. The layout is:
+//!
+//! ```text
+//! /                       header fields as attributes (sample_rate, notes, ...)
+//! /data/timestamps        [num_samples]
+//! /data/amplifier_data    [num_channels, num_samples], `unit` attribute
+//! /data/...               one dataset per signal present in the recording
+//! /channels/amplifier     channel metadata, one dataset per ChannelInfo field
+//! /channels/...           one group per channel type
+//! ```
+//!
+//! Analog datasets carry a `unit` attribute with the unit they are stored in.
+//! Data arrays are chunked along time, one channel per chunk, so reading a
+//! single channel or a short time range only touches the chunks it needs.
+//!
+//! This module requires the `hdf5` feature, which links against the HDF5 library.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::export::hdf5::Hdf5Options;
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! rhs_file.to_hdf5("recording.h5").unwrap();
+//!
+//! // Uncompressed, for the fastest possible reads
+//! let options = Hdf5Options {
+//!     compression_level: None,
+//!     ..Hdf5Options::default()
+//! };
+//! rhs_file.to_hdf5_with_options("recording_raw.h5", &options).unwrap();
+//! ```
+
+use std::error::Error;
+use std::path::Path;
+
+use hdf5::{Dataset, Group, H5Type};
+use ndarray::{Array1, ArrayView, Dimension};
+
+use super::h5::{h5_string, write_attr, write_str_attr};
+use crate::types::{ChannelInfo, RhsFile};
+
+/// Storage settings for HDF5 export.
+#[derive(Debug, Clone)]
+pub struct Hdf5Options {
+    /// Gzip compression level (0-9), or `None` to store the data uncompressed
+    pub compression_level: Option<u8>,
+    /// Number of samples per chunk along the time axis
+    pub chunk_samples: usize,
+}
+
+impl Default for Hdf5Options {
+    fn default() -> Self {
+        Hdf5Options {
+            compression_level: Some(4),
+            chunk_samples: 65536,
+        }
+    }
+}
+
+impl RhsFile {
+    /// Exports the recording to an HDF5 file with default options.
+    ///
+    /// See the [module documentation](crate::export::hdf5) for the layout of the file.
+    /// An existing file at `path` is overwritten.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use intan_importer::load;
+    ///
+    /// let rhs_file = load("recording.rhs").unwrap();
+    /// rhs_file.to_hdf5("recording.h5").unwrap();
+    /// ```
+    pub fn to_hdf5<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        self.to_hdf5_with_options(path, &Hdf5Options::default())
+    }
+
+    /// Exports the recording to an HDF5 file with the given chunking and compression.
+    ///
+    /// Analog signals are written in double precision, with raw codes scaled.
+    /// Signals spilled to disk return an error.
+    pub fn to_hdf5_with_options<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &Hdf5Options,
+    ) -> Result<(), Box<dyn Error>> {
+        let data = self
+            .data
+            .as_ref()
+            .map(|data| data.to_double_precision())
+            .transpose()?;
+        let file = hdf5::File::create(path.as_ref())?;
+        let header = &self.header;
+
+        // Header fields
+        let integers = [
+            ("version_major", header.version.major),
+            ("version_minor", header.version.minor),
+            (
+                "num_samples_per_data_block",
+                header.num_samples_per_data_block,
+            ),
+            ("dsp_enabled", header.dsp_enabled),
+            // 0 means the notch filter was disabled, as in the RHS header itself
+            (
+                "notch_filter_frequency",
+                header.notch_filter_frequency.unwrap_or(0),
+            ),
+            ("amp_settle_mode", header.amp_settle_mode),
+            ("charge_recovery_mode", header.charge_recovery_mode),
+            ("eval_board_mode", header.eval_board_mode),
+        ];
+        let floats = [
+            ("sample_rate", header.sample_rate),
+            (
+                "actual_dsp_cutoff_frequency",
+                header.actual_dsp_cutoff_frequency,
+            ),
+            ("actual_lower_bandwidth", header.actual_lower_bandwidth),
+            (
+                "actual_lower_settle_bandwidth",
+                header.actual_lower_settle_bandwidth,
+            ),
+            ("actual_upper_bandwidth", header.actual_upper_bandwidth),
+            (
+                "desired_dsp_cutoff_frequency",
+                header.desired_dsp_cutoff_frequency,
+            ),
+            ("desired_lower_bandwidth", header.desired_lower_bandwidth),
+            (
+                "desired_lower_settle_bandwidth",
+                header.desired_lower_settle_bandwidth,
+            ),
+            ("desired_upper_bandwidth", header.desired_upper_bandwidth),
+            (
+                "desired_impedance_test_frequency",
+                header.desired_impedance_test_frequency,
+            ),
+            (
+                "actual_impedance_test_frequency",
+                header.actual_impedance_test_frequency,
+            ),
+            ("stim_step_size", header.stim_step_size),
+            ("recovery_current_limit", header.recovery_current_limit),
+            ("recovery_target_voltage", header.recovery_target_voltage),
+        ];
+        let strings = [
+            ("source_format", "Intan RHS"),
+            ("note1", header.notes.note1.as_str()),
+            ("note2", header.notes.note2.as_str()),
+            ("note3", header.notes.note3.as_str()),
+            ("reference_channel", header.reference_channel.as_str()),
+        ];
+        for (name, value) in integers {
+            write_attr(&file, name, value)?;
+        }
+        for (name, value) in floats {
+            write_attr(&file, name, value)?;
+        }
+        for (name, value) in strings {
+            write_str_attr(&file, name, value)?;
+        }
+        write_attr(
+            &file,
+            "dc_amplifier_data_saved",
+            header.dc_amplifier_data_saved,
+        )?;
+
+        // Channel metadata
+        let channels = file.create_group("channels")?;
+        for (name, list) in [
+            ("amplifier", &header.amplifier_channels),
+            ("aux_input", &header.aux_input_channels),
+            ("supply_voltage", &header.supply_voltage_channels),
+            ("board_adc", &header.board_adc_channels),
+            ("board_dac", &header.board_dac_channels),
+            ("board_dig_in", &header.board_dig_in_channels),
+            ("board_dig_out", &header.board_dig_out_channels),
+        ] {
+            if !list.is_empty() {
+                write_channels(&channels.create_group(name)?, list)?;
+            }
+        }
+
+        // Data arrays
+        let Some(data) = &data else {
+            return Ok(());
+        };
+        let group = file.create_group("data")?;
+        write_array(&group, "timestamps", data.timestamps.view(), options)?;
+
+        let analog = [
+            ("amplifier_data", &data.amplifier_data, data.units.amplifier),
+            (
+                "dc_amplifier_data",
+                &data.dc_amplifier_data,
+                data.units.dc_amplifier,
+            ),
+            ("stim_data", &data.stim_data, data.units.stim),
+            ("board_adc_data", &data.board_adc_data, data.units.board_adc),
+            ("board_dac_data", &data.board_dac_data, data.units.board_dac),
+        ];
+        for (name, values, unit) in analog {
+            if let Some(values) = values {
+                let dataset = write_array(&group, name, values.view(), options)?;
+                write_str_attr(&dataset, "unit", unit.symbol())?;
+            }
+        }
+
+        for (name, values) in [
+            ("compliance_limit_data", &data.compliance_limit_data),
+            ("charge_recovery_data", &data.charge_recovery_data),
+            ("amp_settle_data", &data.amp_settle_data),
+        ] {
+            if let Some(values) = values {
+                write_array(&group, name, values.to_array().view(), options)?;
+            }
+        }
+
+        for (name, values) in [
+            ("board_dig_in_data", &data.board_dig_in_data),
+            ("board_dig_out_data", &data.board_dig_out_data),
+        ] {
+            if let Some(values) = values {
+                write_array(&group, name, values.view(), options)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes a data array, chunked along the last (time) axis with one row per chunk
+fn write_array<T: H5Type + Clone, D: Dimension>(
+    group: &Group,
+    name: &str,
+    values: ArrayView<T, D>,
+    options: &Hdf5Options,
+) -> Result<Dataset, Box<dyn Error>> {
+    let values = values.as_standard_layout();
+    let shape = values.shape().to_vec();
+
+    let builder = group.new_dataset_builder().with_data(values.view());
+    if shape.contains(&0) {
+        return Ok(builder.create(name)?);
+    }
+
+    let mut chunk = vec![1; shape.len()];
+    if let (Some(chunk_time), Some(&num_samples)) = (chunk.last_mut(), shape.last()) {
+        *chunk_time = options.chunk_samples.clamp(1, num_samples);
+    }
+    let builder = builder.chunk(chunk);
+    let dataset = match options.compression_level {
+        Some(level) => builder.deflate(level).create(name)?,
+        None => builder.create(name)?,
+    };
+    Ok(dataset)
+}
+
+/// Writes one dataset per `ChannelInfo` field
+fn write_channels(group: &Group, channels: &[ChannelInfo]) -> Result<(), Box<dyn Error>> {
+    let strings: [(&str, fn(&ChannelInfo) -> &str); 4] = [
+        ("port_name", |c| &c.port_name),
+        ("port_prefix", |c| &c.port_prefix),
+        ("native_channel_name", |c| &c.native_channel_name),
+        ("custom_channel_name", |c| &c.custom_channel_name),
+    ];
+    for (name, field) in strings {
+        let values = channels
+            .iter()
+            .map(|channel| h5_string(field(channel)))
+            .collect::<Result<Array1<_>, _>>()?;
+        group
+            .new_dataset_builder()
+            .with_data(&values)
+            .create(name)?;
+    }
+
+    let integers: [(&str, fn(&ChannelInfo) -> i32); 6] = [
+        ("port_number", |c| c.port_number),
+        ("native_order", |c| c.native_order),
+        ("custom_order", |c| c.custom_order),
+        ("chip_channel", |c| c.chip_channel),
+        ("command_stream", |c| c.command_stream),
+        ("board_stream", |c| c.board_stream),
+    ];
+    for (name, field) in integers {
+        let values: Array1<i32> = channels.iter().map(field).collect();
+        group
+            .new_dataset_builder()
+            .with_data(&values)
+            .create(name)?;
+    }
+
+    let floats: [(&str, fn(&ChannelInfo) -> f32); 2] = [
+        ("electrode_impedance_magnitude", |c| {
+            c.electrode_impedance_magnitude
+        }),
+        ("electrode_impedance_phase", |c| c.electrode_impedance_phase),
+    ];
+    for (name, field) in floats {
+        let values: Array1<f32> = channels.iter().map(field).collect();
+        group
+            .new_dataset_builder()
+            .with_data(&values)
+            .create(name)?;
+    }
+
+    Ok(())
+}
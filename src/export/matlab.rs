@@ -0,0 +1,622 @@
+//! Export to MATLAB `.mat` files (version 7.3).
+//!
+//! [`RhsFile::to_mat`] writes the variables produced by Intan's official MATLAB
+//! reader (`read_Intan_RHS2000_file.m`), so existing analysis scripts can switch
+//! to exported files without changes:
+//!
+//! - `t`: sample times in seconds (1 x num_samples)
+//! - `amplifier_data` (μV), `dc_amplifier_data` (V), `stim_data` (μA),
+//!   `board_adc_data` (V), `board_dac_data` (V): num_channels x num_samples
+//! - `compliance_limit_data`, `charge_recovery_data`, `amp_settle_data` (logical),
+//!   `board_dig_in_data`, `board_dig_out_data`
+//! - `header`: a struct with the fields of [`RhsHeader`](crate::RhsHeader); channel
+//!   lists are struct arrays with the fields of [`ChannelInfo`]
+//!
+//! Version 7.3 files are HDF5 files, so this module requires the `mat` feature,
+//! which links against the HDF5 library. Variables are loaded with `load` as usual.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! rhs_file.to_mat("recording.mat").unwrap();
+//! // In MATLAB: load('recording.mat'); plot(t, amplifier_data(1, :))
+//! ```
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use hdf5::types::{FixedAscii, VarLenArray};
+use hdf5::{Dataset, Group, H5Type, Location, ObjectReference1};
+use ndarray::{arr1, Array1, Array2, ArrayView2, Axis};
+
+use super::h5::write_attr;
+use crate::datetime::UtcDateTime;
+use crate::types::{ChannelInfo, RhsData, RhsFile, RhsHeader, SpikeTrigger};
+use crate::units::Unit;
+
+/// Size of the user block that holds the MAT-file header
+const USER_BLOCK_SIZE: u64 = 512;
+/// Group MATLAB uses for the contents of struct array fields
+const REFS_GROUP: &str = "#refs#";
+
+impl RhsFile {
+    /// Exports the recording to a MATLAB v7.3 `.mat` file.
+    ///
+    /// Signals are written in the units of Intan's MATLAB reader, regardless of the
+    /// units they are held in, with raw codes scaled. Signals spilled to disk
+    /// return an error. An existing file at `path` is overwritten.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use intan_importer::load;
+    ///
+    /// let rhs_file = load("recording.rhs").unwrap();
+    /// rhs_file.to_mat("recording.mat").unwrap();
+    /// ```
+    pub fn to_mat<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let data = self
+            .data
+            .as_ref()
+            .map(|data| data.to_double_precision())
+            .transpose()?;
+        {
+            let file = hdf5::File::with_options()
+                .with_fcpl(|fcpl| fcpl.userblock(USER_BLOCK_SIZE))
+                .create(path)?;
+            let mut writer = MatWriter {
+                refs: file.create_group(REFS_GROUP)?,
+                file,
+                next_ref: 0,
+            };
+            writer.write_recording(&self.header, data.as_deref())?;
+        }
+
+        // The MAT-file header goes in the user block, which HDF5 leaves untouched
+        let mut text = format!(
+            "MATLAB 7.3 MAT-file, Platform: GLNXA64, Created on: {} HDF5 schema 1.00 .",
+            UtcDateTime::now().to_iso8601()
+        )
+        .into_bytes();
+        text.resize(116, b' ');
+        text.extend_from_slice(&[0; 8]); // no subsystem data
+        text.extend_from_slice(&0x0200u16.to_le_bytes());
+        text.extend_from_slice(b"IM");
+
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.write_all(&text)?;
+        Ok(())
+    }
+}
+
+/// A value stored in a field of a struct array
+enum Value<'a> {
+    Number(f64),
+    Text(&'a str),
+}
+
+/// Writes MATLAB variables into an open HDF5 file.
+///
+/// MATLAB stores arrays column-major, so a MATLAB matrix of size rows x columns is
+/// an HDF5 dataset of shape [columns, rows]. Every object carries a `MATLAB_class`
+/// attribute naming its MATLAB type.
+struct MatWriter {
+    file: hdf5::File,
+    refs: Group,
+    next_ref: usize,
+}
+
+impl MatWriter {
+    fn write_recording(
+        &mut self,
+        header: &RhsHeader,
+        data: Option<&RhsData>,
+    ) -> Result<(), Box<dyn Error>> {
+        let root: Group = (*self.file).clone();
+        self.write_header(&root, header)?;
+
+        let Some(data) = data else {
+            return Ok(());
+        };
+        let sample_rate = f64::from(header.sample_rate);
+        let t = data.timestamps.mapv(|t| t as f64 / sample_rate);
+        self.double(&root, "t", t.view().insert_axis(Axis(0)))?;
+
+        let analog = [
+            (
+                "amplifier_data",
+                &data.amplifier_data,
+                data.units.amplifier,
+                Unit::Microvolts,
+            ),
+            (
+                "dc_amplifier_data",
+                &data.dc_amplifier_data,
+                data.units.dc_amplifier,
+                Unit::Volts,
+            ),
+            (
+                "stim_data",
+                &data.stim_data,
+                data.units.stim,
+                Unit::Microamps,
+            ),
+            (
+                "board_adc_data",
+                &data.board_adc_data,
+                data.units.board_adc,
+                Unit::Volts,
+            ),
+            (
+                "board_dac_data",
+                &data.board_dac_data,
+                data.units.board_dac,
+                Unit::Volts,
+            ),
+        ];
+        for (name, values, unit, target) in analog {
+            if let Some(values) = values {
+                let factor = unit.factor_to(target).unwrap_or(1.0);
+                let values = if factor == 1.0 {
+                    Cow::Borrowed(values)
+                } else {
+                    Cow::Owned(values * factor)
+                };
+                self.double(&root, name, values.view())?;
+            }
+        }
+
+        for (name, values) in [
+            ("compliance_limit_data", &data.compliance_limit_data),
+            ("charge_recovery_data", &data.charge_recovery_data),
+            ("amp_settle_data", &data.amp_settle_data),
+        ] {
+            if let Some(values) = values {
+                self.logical(&root, name, values.to_array().view())?;
+            }
+        }
+
+        for (name, values) in [
+            ("board_dig_in_data", &data.board_dig_in_data),
+            ("board_dig_out_data", &data.board_dig_out_data),
+        ] {
+            if let Some(values) = values {
+                self.double(&root, name, values.mapv(f64::from).view())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_header(&mut self, parent: &Group, header: &RhsHeader) -> Result<(), Box<dyn Error>> {
+        let group = self.structure(parent, "header")?;
+        let mut fields = Vec::new();
+
+        let version = self.structure(&group, "version")?;
+        self.scalar(&version, "major", f64::from(header.version.major))?;
+        self.scalar(&version, "minor", f64::from(header.version.minor))?;
+        write_fields(&version, &["major", "minor"])?;
+        fields.push("version");
+
+        let numbers = [
+            ("sample_rate", f64::from(header.sample_rate)),
+            (
+                "num_samples_per_data_block",
+                f64::from(header.num_samples_per_data_block),
+            ),
+            ("dsp_enabled", f64::from(header.dsp_enabled)),
+            (
+                "actual_dsp_cutoff_frequency",
+                f64::from(header.actual_dsp_cutoff_frequency),
+            ),
+            (
+                "actual_lower_bandwidth",
+                f64::from(header.actual_lower_bandwidth),
+            ),
+            (
+                "actual_lower_settle_bandwidth",
+                f64::from(header.actual_lower_settle_bandwidth),
+            ),
+            (
+                "actual_upper_bandwidth",
+                f64::from(header.actual_upper_bandwidth),
+            ),
+            (
+                "desired_dsp_cutoff_frequency",
+                f64::from(header.desired_dsp_cutoff_frequency),
+            ),
+            (
+                "desired_lower_bandwidth",
+                f64::from(header.desired_lower_bandwidth),
+            ),
+            (
+                "desired_lower_settle_bandwidth",
+                f64::from(header.desired_lower_settle_bandwidth),
+            ),
+            (
+                "desired_upper_bandwidth",
+                f64::from(header.desired_upper_bandwidth),
+            ),
+            // 0 means the notch filter was disabled, as in Intan's reader
+            (
+                "notch_filter_frequency",
+                f64::from(header.notch_filter_frequency.unwrap_or(0)),
+            ),
+            (
+                "desired_impedance_test_frequency",
+                f64::from(header.desired_impedance_test_frequency),
+            ),
+            (
+                "actual_impedance_test_frequency",
+                f64::from(header.actual_impedance_test_frequency),
+            ),
+            ("amp_settle_mode", f64::from(header.amp_settle_mode)),
+            (
+                "charge_recovery_mode",
+                f64::from(header.charge_recovery_mode),
+            ),
+            ("stim_step_size", f64::from(header.stim_step_size)),
+            (
+                "recovery_current_limit",
+                f64::from(header.recovery_current_limit),
+            ),
+            (
+                "recovery_target_voltage",
+                f64::from(header.recovery_target_voltage),
+            ),
+            (
+                "dc_amplifier_data_saved",
+                f64::from(u8::from(header.dc_amplifier_data_saved)),
+            ),
+            ("eval_board_mode", f64::from(header.eval_board_mode)),
+        ];
+        for (name, value) in numbers {
+            self.scalar(&group, name, value)?;
+            fields.push(name);
+        }
+
+        let notes = self.structure(&group, "notes")?;
+        self.char(&notes, "note1", &header.notes.note1)?;
+        self.char(&notes, "note2", &header.notes.note2)?;
+        self.char(&notes, "note3", &header.notes.note3)?;
+        write_fields(&notes, &["note1", "note2", "note3"])?;
+        fields.push("notes");
+
+        self.char(&group, "reference_channel", &header.reference_channel)?;
+        fields.push("reference_channel");
+
+        for (name, channels) in [
+            ("amplifier_channels", &header.amplifier_channels),
+            ("aux_input_channels", &header.aux_input_channels),
+            ("supply_voltage_channels", &header.supply_voltage_channels),
+            ("board_adc_channels", &header.board_adc_channels),
+            ("board_dac_channels", &header.board_dac_channels),
+            ("board_dig_in_channels", &header.board_dig_in_channels),
+            ("board_dig_out_channels", &header.board_dig_out_channels),
+        ] {
+            self.channels(&group, name, channels)?;
+            fields.push(name);
+        }
+        self.spike_triggers(&group, &header.spike_triggers)?;
+        fields.push("spike_triggers");
+
+        let frequency = self.structure(&group, "frequency_parameters")?;
+        let parameters = &header.frequency_parameters;
+        let frequency_fields = [
+            (
+                "amplifier_sample_rate",
+                f64::from(parameters.amplifier_sample_rate),
+            ),
+            (
+                "board_adc_sample_rate",
+                f64::from(parameters.board_adc_sample_rate),
+            ),
+            (
+                "board_dig_in_sample_rate",
+                f64::from(parameters.board_dig_in_sample_rate),
+            ),
+            (
+                "desired_dsp_cutoff_frequency",
+                f64::from(parameters.desired_dsp_cutoff_frequency),
+            ),
+            (
+                "actual_dsp_cutoff_frequency",
+                f64::from(parameters.actual_dsp_cutoff_frequency),
+            ),
+            ("dsp_enabled", f64::from(parameters.dsp_enabled)),
+            (
+                "desired_lower_bandwidth",
+                f64::from(parameters.desired_lower_bandwidth),
+            ),
+            (
+                "desired_lower_settle_bandwidth",
+                f64::from(parameters.desired_lower_settle_bandwidth),
+            ),
+            (
+                "actual_lower_bandwidth",
+                f64::from(parameters.actual_lower_bandwidth),
+            ),
+            (
+                "actual_lower_settle_bandwidth",
+                f64::from(parameters.actual_lower_settle_bandwidth),
+            ),
+            (
+                "desired_upper_bandwidth",
+                f64::from(parameters.desired_upper_bandwidth),
+            ),
+            (
+                "actual_upper_bandwidth",
+                f64::from(parameters.actual_upper_bandwidth),
+            ),
+            (
+                "notch_filter_frequency",
+                f64::from(parameters.notch_filter_frequency.unwrap_or(0)),
+            ),
+            (
+                "desired_impedance_test_frequency",
+                f64::from(parameters.desired_impedance_test_frequency),
+            ),
+            (
+                "actual_impedance_test_frequency",
+                f64::from(parameters.actual_impedance_test_frequency),
+            ),
+        ];
+        for (name, value) in frequency_fields {
+            self.scalar(&frequency, name, value)?;
+        }
+        write_fields(&frequency, &frequency_fields.map(|(name, _)| name))?;
+        fields.push("frequency_parameters");
+
+        let stim = self.structure(&group, "stim_parameters")?;
+        let parameters = &header.stim_parameters;
+        let stim_fields = [
+            ("stim_step_size", f64::from(parameters.stim_step_size)),
+            (
+                "charge_recovery_current_limit",
+                f64::from(parameters.charge_recovery_current_limit),
+            ),
+            (
+                "charge_recovery_target_voltage",
+                f64::from(parameters.charge_recovery_target_voltage),
+            ),
+            ("amp_settle_mode", f64::from(parameters.amp_settle_mode)),
+            (
+                "charge_recovery_mode",
+                f64::from(parameters.charge_recovery_mode),
+            ),
+        ];
+        for (name, value) in stim_fields {
+            self.scalar(&stim, name, value)?;
+        }
+        write_fields(&stim, &stim_fields.map(|(name, _)| name))?;
+        fields.push("stim_parameters");
+
+        write_fields(&group, &fields)
+    }
+
+    /// Writes a channel list as a 1 x n struct array
+    fn channels(
+        &mut self,
+        parent: &Group,
+        name: &str,
+        channels: &[ChannelInfo],
+    ) -> Result<(), Box<dyn Error>> {
+        let text = |field: fn(&ChannelInfo) -> &str| -> Vec<Value> {
+            channels.iter().map(|c| Value::Text(field(c))).collect()
+        };
+        let number = |field: fn(&ChannelInfo) -> f64| -> Vec<Value> {
+            channels.iter().map(|c| Value::Number(field(c))).collect()
+        };
+        let fields = vec![
+            ("port_name", text(|c| &c.port_name)),
+            ("port_prefix", text(|c| &c.port_prefix)),
+            ("port_number", number(|c| f64::from(c.port_number))),
+            ("native_channel_name", text(|c| &c.native_channel_name)),
+            ("custom_channel_name", text(|c| &c.custom_channel_name)),
+            ("native_order", number(|c| f64::from(c.native_order))),
+            ("custom_order", number(|c| f64::from(c.custom_order))),
+            ("chip_channel", number(|c| f64::from(c.chip_channel))),
+            ("command_stream", number(|c| f64::from(c.command_stream))),
+            ("board_stream", number(|c| f64::from(c.board_stream))),
+            (
+                "electrode_impedance_magnitude",
+                number(|c| f64::from(c.electrode_impedance_magnitude)),
+            ),
+            (
+                "electrode_impedance_phase",
+                number(|c| f64::from(c.electrode_impedance_phase)),
+            ),
+        ];
+        self.struct_array(parent, name, channels.len(), fields)
+    }
+
+    /// Writes the spike trigger settings as a 1 x n struct array
+    fn spike_triggers(
+        &mut self,
+        parent: &Group,
+        triggers: &[SpikeTrigger],
+    ) -> Result<(), Box<dyn Error>> {
+        let number = |field: fn(&SpikeTrigger) -> i32| -> Vec<Value> {
+            triggers
+                .iter()
+                .map(|t| Value::Number(f64::from(field(t))))
+                .collect()
+        };
+        let fields = vec![
+            ("voltage_trigger_mode", number(|t| t.voltage_trigger_mode)),
+            ("voltage_threshold", number(|t| t.voltage_threshold)),
+            (
+                "digital_trigger_channel",
+                number(|t| t.digital_trigger_channel),
+            ),
+            ("digital_edge_polarity", number(|t| t.digital_edge_polarity)),
+        ];
+        self.struct_array(parent, "spike_triggers", triggers.len(), fields)
+    }
+
+    /// Writes a 1 x `len` struct array
+    ///
+    /// Each field is a dataset of references to the field's values, which are
+    /// stored in the `#refs#` group.
+    fn struct_array(
+        &mut self,
+        parent: &Group,
+        name: &str,
+        len: usize,
+        fields: Vec<(&str, Vec<Value>)>,
+    ) -> Result<(), Box<dyn Error>> {
+        if len == 0 {
+            return self.empty(parent, name, b"struct");
+        }
+
+        let group = self.structure(parent, name)?;
+        let mut names = Vec::with_capacity(fields.len());
+        for (field, values) in fields {
+            let mut references = Vec::with_capacity(len);
+            for value in values {
+                let ref_name = self.next_ref_name();
+                match value {
+                    Value::Number(number) => self.scalar(&self.refs, &ref_name, number)?,
+                    Value::Text(text) => self.char(&self.refs, &ref_name, text)?,
+                };
+                references.push(
+                    self.file
+                        .reference::<ObjectReference1>(&format!("/{}/{}", REFS_GROUP, ref_name))?,
+                );
+            }
+            let references = Array2::from_shape_vec((len, 1), references)?;
+            let dataset = group
+                .new_dataset_builder()
+                .with_data(&references)
+                .create(field)?;
+            write_class(&dataset, b"cell")?;
+            names.push(field);
+        }
+        write_fields(&group, &names)
+    }
+
+    /// Creates a struct variable
+    fn structure(&self, parent: &Group, name: &str) -> Result<Group, Box<dyn Error>> {
+        let group = parent.create_group(name)?;
+        write_class(&group, b"struct")?;
+        Ok(group)
+    }
+
+    /// Writes a double matrix
+    fn double(
+        &self,
+        parent: &Group,
+        name: &str,
+        values: ArrayView2<f64>,
+    ) -> Result<Dataset, Box<dyn Error>> {
+        let dataset = matrix(parent, name, values)?;
+        write_class(&dataset, b"double")?;
+        Ok(dataset)
+    }
+
+    /// Writes a 1 x 1 double
+    fn scalar(&self, parent: &Group, name: &str, value: f64) -> Result<Dataset, Box<dyn Error>> {
+        self.double(parent, name, Array2::from_elem((1, 1), value).view())
+    }
+
+    /// Writes a logical matrix
+    fn logical(
+        &self,
+        parent: &Group,
+        name: &str,
+        values: ArrayView2<bool>,
+    ) -> Result<Dataset, Box<dyn Error>> {
+        let dataset = matrix(parent, name, values.mapv(u8::from).view())?;
+        write_class(&dataset, b"logical")?;
+        write_attr(&dataset, "MATLAB_int_decode", 1i32)?;
+        Ok(dataset)
+    }
+
+    /// Writes a character row vector
+    fn char(&self, parent: &Group, name: &str, text: &str) -> Result<Dataset, Box<dyn Error>> {
+        if text.is_empty() {
+            self.empty(parent, name, b"char")?;
+            return Ok(parent.dataset(name)?);
+        }
+        let units: Vec<u16> = text.encode_utf16().collect();
+        let dataset = matrix(
+            parent,
+            name,
+            Array2::from_shape_vec((1, units.len()), units)?.view(),
+        )?;
+        write_class(&dataset, b"char")?;
+        write_attr(&dataset, "MATLAB_int_decode", 2i32)?;
+        Ok(dataset)
+    }
+
+    /// Writes an empty (0 x 0) variable of the given class
+    ///
+    /// MATLAB stores the dimensions of empty arrays as the dataset contents.
+    fn empty<const N: usize>(
+        &self,
+        parent: &Group,
+        name: &str,
+        class: &[u8; N],
+    ) -> Result<(), Box<dyn Error>> {
+        let dataset = parent
+            .new_dataset_builder()
+            .with_data(&arr1(&[0u64, 0]))
+            .create(name)?;
+        write_class(&dataset, class)?;
+        write_attr(&dataset, "MATLAB_empty", 1u8)?;
+        Ok(())
+    }
+
+    fn next_ref_name(&mut self) -> String {
+        self.next_ref += 1;
+        format!("r{}", self.next_ref)
+    }
+}
+
+/// Writes a matrix in MATLAB's column-major order
+fn matrix<T: H5Type + Clone>(
+    parent: &Group,
+    name: &str,
+    values: ArrayView2<T>,
+) -> Result<Dataset, Box<dyn Error>> {
+    let transposed = values.t().as_standard_layout();
+    Ok(parent
+        .new_dataset_builder()
+        .with_data(transposed.view())
+        .create(name)?)
+}
+
+/// Sets the `MATLAB_class` attribute, which MATLAB expects as a fixed-length string
+fn write_class<const N: usize>(location: &Location, class: &[u8; N]) -> Result<(), Box<dyn Error>> {
+    write_attr(
+        location,
+        "MATLAB_class",
+        FixedAscii::<N>::from_ascii(class)?,
+    )
+}
+
+/// Sets the `MATLAB_fields` attribute listing the fields of a struct in order
+fn write_fields(group: &Group, names: &[&str]) -> Result<(), Box<dyn Error>> {
+    let names = names
+        .iter()
+        .map(|name| {
+            let chars = name
+                .bytes()
+                .map(|byte| FixedAscii::<1>::from_ascii(&[byte]))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(VarLenArray::from_slice(&chars))
+        })
+        .collect::<Result<Array1<_>, Box<dyn Error>>>()?;
+    group
+        .new_attr_builder()
+        .with_data(&names)
+        .create("MATLAB_fields")?;
+    Ok(())
+}
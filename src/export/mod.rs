@@ -0,0 +1,398 @@
+//! Export recordings to file formats used by other analysis tools.
+//!
+//! Each exporter adds a method to [`RhsFile`](crate::RhsFile), except [`streaming`],
+//! which converts a directory of files without loading it. Exporters that depend on
+//! a native library or a large crate are behind a Cargo feature:
+//!
+//! | Module      | Feature   | Format |
+//! |-------------|-----------|--------|
+//! | `arrow`     | `arrow`   | Apache Arrow record batches (in memory) |
+//! | `binary`    | none      | Interleaved int16 for Kilosort and SpikeInterface |
+//! | `edf`       | none      | EDF+ and BDF+ |
+//! | `hdf5`      | `hdf5`    | Plain HDF5, one dataset per data array |
+//! | `matlab`    | `mat`     | MATLAB `.mat` version 7.3 (HDF5) |
+//! | `npy`       | none      | NumPy `.npz` archives with a JSON header sidecar |
+//! | `nwb`       | `nwb`     | Neurodata Without Borders 2.x (HDF5) |
+//! | `parquet`   | `parquet` | Apache Parquet, one column per channel |
+//! | `probe`     | none      | probeinterface JSON from user-supplied electrode positions |
+//! | `streaming` | none      | Binary or Parquet export of a directory without loading it |
+//! | `zarr`      | `zarr`    | Zarr version 3 directory stores |
+//!
+//! Analog signals are exported in double precision whichever form they were loaded
+//! in: raw codes are scaled and single-precision data is widened first. Signals
+//! spilled to disk are not in memory, so exporting them returns an error.
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod binary;
+pub mod edf;
+#[cfg(feature = "hdf5")]
+mod h5;
+#[cfg(feature = "hdf5")]
+pub mod hdf5;
+#[cfg(feature = "mat")]
+pub mod matlab;
+pub mod npy;
+#[cfg(feature = "nwb")]
+pub mod nwb;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod probe;
+pub mod streaming;
+#[cfg(feature = "zarr")]
+pub mod zarr;
+mod zip;
+
+use crate::json::Json;
+use crate::types::{
+    ChannelInfo, FrequencyParameters, IntanError, Notes, RhsHeader, SpikeTrigger, StimParameters,
+    Version,
+};
+
+/// Converts a header to JSON, for the metadata sidecars written next to exported data
+pub(crate) fn header_json(header: &RhsHeader) -> Json {
+    let channels = |channels: &[ChannelInfo]| {
+        Json::Array(
+            channels
+                .iter()
+                .map(|channel| {
+                    Json::object(vec![
+                        ("port_name", channel.port_name.as_str().into()),
+                        ("port_prefix", channel.port_prefix.as_str().into()),
+                        ("port_number", channel.port_number.into()),
+                        (
+                            "native_channel_name",
+                            channel.native_channel_name.as_str().into(),
+                        ),
+                        (
+                            "custom_channel_name",
+                            channel.custom_channel_name.as_str().into(),
+                        ),
+                        ("native_order", channel.native_order.into()),
+                        ("custom_order", channel.custom_order.into()),
+                        ("chip_channel", channel.chip_channel.into()),
+                        ("command_stream", channel.command_stream.into()),
+                        ("board_stream", channel.board_stream.into()),
+                        (
+                            "electrode_impedance_magnitude",
+                            channel.electrode_impedance_magnitude.into(),
+                        ),
+                        (
+                            "electrode_impedance_phase",
+                            channel.electrode_impedance_phase.into(),
+                        ),
+                    ])
+                })
+                .collect(),
+        )
+    };
+    let spike_triggers = header
+        .spike_triggers
+        .iter()
+        .map(|trigger| {
+            Json::object(vec![
+                ("voltage_trigger_mode", trigger.voltage_trigger_mode.into()),
+                ("voltage_threshold", trigger.voltage_threshold.into()),
+                (
+                    "digital_trigger_channel",
+                    trigger.digital_trigger_channel.into(),
+                ),
+                (
+                    "digital_edge_polarity",
+                    trigger.digital_edge_polarity.into(),
+                ),
+            ])
+        })
+        .collect();
+
+    Json::object(vec![
+        (
+            "version",
+            Json::object(vec![
+                ("major", header.version.major.into()),
+                ("minor", header.version.minor.into()),
+            ]),
+        ),
+        ("sample_rate", header.sample_rate.into()),
+        (
+            "num_samples_per_data_block",
+            header.num_samples_per_data_block.into(),
+        ),
+        ("dsp_enabled", header.dsp_enabled.into()),
+        (
+            "actual_dsp_cutoff_frequency",
+            header.actual_dsp_cutoff_frequency.into(),
+        ),
+        (
+            "actual_lower_bandwidth",
+            header.actual_lower_bandwidth.into(),
+        ),
+        (
+            "actual_lower_settle_bandwidth",
+            header.actual_lower_settle_bandwidth.into(),
+        ),
+        (
+            "actual_upper_bandwidth",
+            header.actual_upper_bandwidth.into(),
+        ),
+        (
+            "desired_dsp_cutoff_frequency",
+            header.desired_dsp_cutoff_frequency.into(),
+        ),
+        (
+            "desired_lower_bandwidth",
+            header.desired_lower_bandwidth.into(),
+        ),
+        (
+            "desired_lower_settle_bandwidth",
+            header.desired_lower_settle_bandwidth.into(),
+        ),
+        (
+            "desired_upper_bandwidth",
+            header.desired_upper_bandwidth.into(),
+        ),
+        (
+            "notch_filter_frequency",
+            header.notch_filter_frequency.into(),
+        ),
+        (
+            "desired_impedance_test_frequency",
+            header.desired_impedance_test_frequency.into(),
+        ),
+        (
+            "actual_impedance_test_frequency",
+            header.actual_impedance_test_frequency.into(),
+        ),
+        ("amp_settle_mode", header.amp_settle_mode.into()),
+        ("charge_recovery_mode", header.charge_recovery_mode.into()),
+        ("stim_step_size", header.stim_step_size.into()),
+        (
+            "recovery_current_limit",
+            header.recovery_current_limit.into(),
+        ),
+        (
+            "recovery_target_voltage",
+            header.recovery_target_voltage.into(),
+        ),
+        (
+            "notes",
+            Json::object(vec![
+                ("note1", header.notes.note1.as_str().into()),
+                ("note2", header.notes.note2.as_str().into()),
+                ("note3", header.notes.note3.as_str().into()),
+            ]),
+        ),
+        (
+            "dc_amplifier_data_saved",
+            header.dc_amplifier_data_saved.into(),
+        ),
+        ("eval_board_mode", header.eval_board_mode.into()),
+        (
+            "reference_channel",
+            header.reference_channel.as_str().into(),
+        ),
+        ("amplifier_channels", channels(&header.amplifier_channels)),
+        ("spike_triggers", Json::Array(spike_triggers)),
+        ("aux_input_channels", channels(&header.aux_input_channels)),
+        (
+            "supply_voltage_channels",
+            channels(&header.supply_voltage_channels),
+        ),
+        ("board_adc_channels", channels(&header.board_adc_channels)),
+        ("board_dac_channels", channels(&header.board_dac_channels)),
+        (
+            "board_dig_in_channels",
+            channels(&header.board_dig_in_channels),
+        ),
+        (
+            "board_dig_out_channels",
+            channels(&header.board_dig_out_channels),
+        ),
+    ])
+}
+
+/// Rebuilds a header from the JSON written by [`header_json`].
+///
+/// The derived `frequency_parameters` and `stim_parameters` are recomputed from
+/// the header fields, as the reader does.
+pub(crate) fn header_from_json(json: &Json) -> Result<RhsHeader, IntanError> {
+    let channels = |key: &str| -> Result<Vec<ChannelInfo>, IntanError> {
+        array_field(json, key)?
+            .iter()
+            .map(|channel| {
+                Ok(ChannelInfo {
+                    port_name: string_field(channel, "port_name")?,
+                    port_prefix: string_field(channel, "port_prefix")?,
+                    port_number: i32_field(channel, "port_number")?,
+                    native_channel_name: string_field(channel, "native_channel_name")?,
+                    custom_channel_name: string_field(channel, "custom_channel_name")?,
+                    native_order: i32_field(channel, "native_order")?,
+                    custom_order: i32_field(channel, "custom_order")?,
+                    chip_channel: i32_field(channel, "chip_channel")?,
+                    // Absent from headers exported before the field was kept
+                    command_stream: match channel.get("command_stream") {
+                        Some(_) => i32_field(channel, "command_stream")?,
+                        None => 0,
+                    },
+                    board_stream: i32_field(channel, "board_stream")?,
+                    electrode_impedance_magnitude: f32_field(
+                        channel,
+                        "electrode_impedance_magnitude",
+                    )?,
+                    electrode_impedance_phase: f32_field(channel, "electrode_impedance_phase")?,
+                })
+            })
+            .collect()
+    };
+    let spike_triggers = array_field(json, "spike_triggers")?
+        .iter()
+        .map(|trigger| {
+            Ok(SpikeTrigger {
+                voltage_trigger_mode: i32_field(trigger, "voltage_trigger_mode")?,
+                voltage_threshold: i32_field(trigger, "voltage_threshold")?,
+                digital_trigger_channel: i32_field(trigger, "digital_trigger_channel")?,
+                digital_edge_polarity: i32_field(trigger, "digital_edge_polarity")?,
+            })
+        })
+        .collect::<Result<Vec<_>, IntanError>>()?;
+    let version = field(json, "version")?;
+    let notes = field(json, "notes")?;
+    let notch_filter_frequency = match field(json, "notch_filter_frequency")? {
+        Json::Null => None,
+        _ => Some(i32_field(json, "notch_filter_frequency")?),
+    };
+
+    let sample_rate = f32_field(json, "sample_rate")?;
+    let header = RhsHeader {
+        version: Version {
+            major: i32_field(version, "major")?,
+            minor: i32_field(version, "minor")?,
+        },
+        sample_rate,
+        num_samples_per_data_block: i32_field(json, "num_samples_per_data_block")?,
+        dsp_enabled: i32_field(json, "dsp_enabled")?,
+        actual_dsp_cutoff_frequency: f32_field(json, "actual_dsp_cutoff_frequency")?,
+        actual_lower_bandwidth: f32_field(json, "actual_lower_bandwidth")?,
+        actual_lower_settle_bandwidth: f32_field(json, "actual_lower_settle_bandwidth")?,
+        actual_upper_bandwidth: f32_field(json, "actual_upper_bandwidth")?,
+        desired_dsp_cutoff_frequency: f32_field(json, "desired_dsp_cutoff_frequency")?,
+        desired_lower_bandwidth: f32_field(json, "desired_lower_bandwidth")?,
+        desired_lower_settle_bandwidth: f32_field(json, "desired_lower_settle_bandwidth")?,
+        desired_upper_bandwidth: f32_field(json, "desired_upper_bandwidth")?,
+        notch_filter_frequency,
+        desired_impedance_test_frequency: f32_field(json, "desired_impedance_test_frequency")?,
+        actual_impedance_test_frequency: f32_field(json, "actual_impedance_test_frequency")?,
+        amp_settle_mode: i32_field(json, "amp_settle_mode")?,
+        charge_recovery_mode: i32_field(json, "charge_recovery_mode")?,
+        stim_step_size: f32_field(json, "stim_step_size")?,
+        recovery_current_limit: f32_field(json, "recovery_current_limit")?,
+        recovery_target_voltage: f32_field(json, "recovery_target_voltage")?,
+        notes: Notes {
+            note1: string_field(notes, "note1")?,
+            note2: string_field(notes, "note2")?,
+            note3: string_field(notes, "note3")?,
+        },
+        dc_amplifier_data_saved: field(json, "dc_amplifier_data_saved")?
+            .as_bool()
+            .ok_or_else(|| invalid_field("dc_amplifier_data_saved"))?,
+        eval_board_mode: i32_field(json, "eval_board_mode")?,
+        reference_channel: string_field(json, "reference_channel")?,
+        amplifier_channels: channels("amplifier_channels")?,
+        spike_triggers,
+        aux_input_channels: channels("aux_input_channels")?,
+        supply_voltage_channels: channels("supply_voltage_channels")?,
+        board_adc_channels: channels("board_adc_channels")?,
+        board_dac_channels: channels("board_dac_channels")?,
+        board_dig_in_channels: channels("board_dig_in_channels")?,
+        board_dig_out_channels: channels("board_dig_out_channels")?,
+        frequency_parameters: FrequencyParameters {
+            amplifier_sample_rate: sample_rate,
+            board_adc_sample_rate: sample_rate,
+            board_dig_in_sample_rate: sample_rate,
+            desired_dsp_cutoff_frequency: 0.0,
+            actual_dsp_cutoff_frequency: 0.0,
+            dsp_enabled: 0,
+            desired_lower_bandwidth: 0.0,
+            desired_lower_settle_bandwidth: 0.0,
+            actual_lower_bandwidth: 0.0,
+            actual_lower_settle_bandwidth: 0.0,
+            desired_upper_bandwidth: 0.0,
+            actual_upper_bandwidth: 0.0,
+            notch_filter_frequency,
+            desired_impedance_test_frequency: 0.0,
+            actual_impedance_test_frequency: 0.0,
+        },
+        stim_parameters: StimParameters {
+            stim_step_size: 0.0,
+            charge_recovery_current_limit: 0.0,
+            charge_recovery_target_voltage: 0.0,
+            amp_settle_mode: 0,
+            charge_recovery_mode: 0,
+        },
+    };
+    Ok(with_derived_parameters(header))
+}
+
+/// Fills in the parameter structs that mirror individual header fields
+fn with_derived_parameters(mut header: RhsHeader) -> RhsHeader {
+    let frequency = &mut header.frequency_parameters;
+    frequency.desired_dsp_cutoff_frequency = header.desired_dsp_cutoff_frequency;
+    frequency.actual_dsp_cutoff_frequency = header.actual_dsp_cutoff_frequency;
+    frequency.dsp_enabled = header.dsp_enabled;
+    frequency.desired_lower_bandwidth = header.desired_lower_bandwidth;
+    frequency.desired_lower_settle_bandwidth = header.desired_lower_settle_bandwidth;
+    frequency.actual_lower_bandwidth = header.actual_lower_bandwidth;
+    frequency.actual_lower_settle_bandwidth = header.actual_lower_settle_bandwidth;
+    frequency.desired_upper_bandwidth = header.desired_upper_bandwidth;
+    frequency.actual_upper_bandwidth = header.actual_upper_bandwidth;
+    frequency.desired_impedance_test_frequency = header.desired_impedance_test_frequency;
+    frequency.actual_impedance_test_frequency = header.actual_impedance_test_frequency;
+
+    let stim = &mut header.stim_parameters;
+    stim.stim_step_size = header.stim_step_size;
+    stim.charge_recovery_current_limit = header.recovery_current_limit;
+    stim.charge_recovery_target_voltage = header.recovery_target_voltage;
+    stim.amp_settle_mode = header.amp_settle_mode;
+    stim.charge_recovery_mode = header.charge_recovery_mode;
+    header
+}
+
+fn invalid_field(key: &str) -> IntanError {
+    IntanError::Other(format!("Header JSON has a missing or invalid '{}'", key))
+}
+
+fn field<'a>(json: &'a Json, key: &str) -> Result<&'a Json, IntanError> {
+    json.get(key).ok_or_else(|| invalid_field(key))
+}
+
+fn array_field<'a>(json: &'a Json, key: &str) -> Result<&'a [Json], IntanError> {
+    field(json, key)?
+        .as_array()
+        .ok_or_else(|| invalid_field(key))
+}
+
+fn string_field(json: &Json, key: &str) -> Result<String, IntanError> {
+    field(json, key)?
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| invalid_field(key))
+}
+
+fn i32_field(json: &Json, key: &str) -> Result<i32, IntanError> {
+    field(json, key)?
+        .as_f64()
+        .filter(|n| n.fract() == 0.0 && *n >= i32::MIN as f64 && *n <= i32::MAX as f64)
+        .map(|n| n as i32)
+        .ok_or_else(|| invalid_field(key))
+}
+
+/// Reads an `f32`; `null` stands for a non-finite value, which JSON cannot hold
+fn f32_field(json: &Json, key: &str) -> Result<f32, IntanError> {
+    match field(json, key)? {
+        Json::Null => Ok(f32::NAN),
+        value => value
+            .as_f64()
+            .map(|n| n as f32)
+            .ok_or_else(|| invalid_field(key)),
+    }
+}
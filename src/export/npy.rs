@@ -0,0 +1,228 @@
+//! Export to NumPy `.npz` archives.
+//!
+//! [`RhsData::to_npz`] writes every data array into a single `.npz` file, one
+//! `.npy` entry per array, named after the `RhsData` field (`timestamps`,
+//! `amplifier_data`, `board_adc_data`, ...). Arrays keep their shape
+//! ([num_channels, num_samples]) and the units they are held in.
+//!
+//! [`RhsFile::to_npz`] additionally writes a JSON sidecar with the header and the
+//! unit of each array, so Python code needs nothing beyond NumPy and the standard
+//! library:
+//!
+//! ```python
+//! import json
+//! import numpy as np
+//!
+//! data = np.load("recording.npz")
+//! meta = json.load(open("recording.json"))
+//! print(meta["header"]["sample_rate"], data["amplifier_data"].shape)
+//! ```
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! rhs_file.to_npz("recording.npz").unwrap(); // also writes recording.json
+//! ```
+
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use ndarray::{ArrayView, Dimension};
+
+use super::header_json;
+use super::zip::ZipWriter;
+use crate::json::Json;
+use crate::types::{IntanError, RhsData, RhsFile};
+
+/// Size of the buffer used to convert array elements to bytes
+const WRITE_BUFFER_SIZE: usize = 1 << 16;
+
+impl RhsData {
+    /// Writes every data array into a `.npz` archive.
+    ///
+    /// Arrays that are `None` are left out of the archive. Analog signals are
+    /// written in double precision, with raw codes scaled to their units.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if signals are spilled to disk or the file cannot be
+    /// written.
+    pub fn to_npz<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let data = self.to_double_precision()?;
+        let mut zip = ZipWriter::create(path.as_ref())?;
+
+        add_array(&mut zip, "timestamps", data.timestamps.view())?;
+        for (name, values) in [
+            ("amplifier_data", &data.amplifier_data),
+            ("dc_amplifier_data", &data.dc_amplifier_data),
+            ("stim_data", &data.stim_data),
+            ("board_adc_data", &data.board_adc_data),
+            ("board_dac_data", &data.board_dac_data),
+        ] {
+            if let Some(values) = values {
+                add_array(&mut zip, name, values.view())?;
+            }
+        }
+        for (name, values) in [
+            ("compliance_limit_data", &data.compliance_limit_data),
+            ("charge_recovery_data", &data.charge_recovery_data),
+            ("amp_settle_data", &data.amp_settle_data),
+        ] {
+            if let Some(values) = values {
+                add_array(&mut zip, name, values.to_array().view())?;
+            }
+        }
+        for (name, values) in [
+            ("board_dig_in_data", &data.board_dig_in_data),
+            ("board_dig_out_data", &data.board_dig_out_data),
+        ] {
+            if let Some(values) = values {
+                add_array(&mut zip, name, values.view())?;
+            }
+        }
+
+        zip.finish()
+    }
+}
+
+impl RhsFile {
+    /// Writes the data arrays into a `.npz` archive and the header into a JSON sidecar.
+    ///
+    /// The sidecar has the same path as the archive with a `.json` extension. It
+    /// holds the header, the sample rate, and the unit of each analog array.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recording has no data, its signals are spilled to
+    /// disk, or a file cannot be written.
+    pub fn to_npz<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let data = self.data.as_ref().ok_or_else(|| {
+            IntanError::Other("Cannot export to NPZ: the recording has no data".to_string())
+        })?;
+        data.to_npz(path)?;
+
+        let units = &data.units;
+        let sidecar = Json::object(vec![
+            ("sample_rate", self.header.sample_rate.into()),
+            ("num_samples", data.num_samples().into()),
+            (
+                "units",
+                Json::object(vec![
+                    ("amplifier_data", units.amplifier.symbol().into()),
+                    ("dc_amplifier_data", units.dc_amplifier.symbol().into()),
+                    ("stim_data", units.stim.symbol().into()),
+                    ("board_adc_data", units.board_adc.symbol().into()),
+                    ("board_dac_data", units.board_dac.symbol().into()),
+                ]),
+            ),
+            ("header", header_json(&self.header)),
+        ]);
+        fs::write(path.with_extension("json"), sidecar.to_string_pretty())?;
+        Ok(())
+    }
+}
+
+/// An array element type with a NumPy equivalent
+trait NpyElement: Copy {
+    /// NumPy type descriptor
+    const DESCR: &'static str;
+    /// Size of one element in bytes
+    const SIZE: usize;
+    /// Appends the element's little-endian bytes
+    fn extend_le(self, out: &mut Vec<u8>);
+}
+
+impl NpyElement for f64 {
+    const DESCR: &'static str = "<f8";
+    const SIZE: usize = 8;
+    fn extend_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for i32 {
+    const DESCR: &'static str = "<i4";
+    const SIZE: usize = 4;
+    fn extend_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for i64 {
+    const DESCR: &'static str = "<i8";
+    const SIZE: usize = 8;
+    fn extend_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for bool {
+    const DESCR: &'static str = "|b1";
+    const SIZE: usize = 1;
+    fn extend_le(self, out: &mut Vec<u8>) {
+        out.push(u8::from(self));
+    }
+}
+
+/// Adds an array to the archive as `<name>.npy`
+fn add_array<T: NpyElement, D: Dimension>(
+    zip: &mut ZipWriter,
+    name: &str,
+    values: ArrayView<T, D>,
+) -> Result<(), Box<dyn Error>> {
+    let header = npy_header(T::DESCR, values.shape());
+    let size = header.len() as u64 + values.len() as u64 * T::SIZE as u64;
+
+    zip.add_entry(&format!("{}.npy", name), size, |out: &mut dyn Write| {
+        out.write_all(&header)?;
+        let mut buffer = Vec::with_capacity(WRITE_BUFFER_SIZE + T::SIZE);
+        // Logical iteration order is C order, whatever the memory layout
+        for &value in values.iter() {
+            value.extend_le(&mut buffer);
+            if buffer.len() >= WRITE_BUFFER_SIZE {
+                out.write_all(&buffer)?;
+                buffer.clear();
+            }
+        }
+        out.write_all(&buffer)?;
+        Ok(())
+    })
+}
+
+/// Builds a version 1.0 `.npy` header, padded so the data starts on a 64-byte boundary
+fn npy_header(descr: &str, shape: &[usize]) -> Vec<u8> {
+    let shape = match shape {
+        [length] => format!("({},)", length),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut dict = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, shape
+    );
+
+    // Magic (6 bytes), version (2), header length (2), then the dictionary and a newline
+    let unpadded = 10 + dict.len() + 1;
+    dict.extend(std::iter::repeat_n(
+        ' ',
+        unpadded.next_multiple_of(64) - unpadded,
+    ));
+    dict.push('\n');
+
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header
+}
@@ -0,0 +1,434 @@
+//! Export to Neurodata Without Borders (NWB) 2.x.
+//!
+//! [`RhsFile::to_nwb`] writes a recording as an NWB file that can be opened with
+//! PyNWB, MatNWB, or any other NWB reader:
+//!
+//! - Amplifier data becomes an `ElectricalSeries` in `/acquisition`.
+//! - Stimulation current becomes a `TimeSeries` in `/stimulus/presentation`.
+//! - Each amplifier channel becomes a row of the electrodes table, with one
+//!   `ElectrodeGroup` per port and the measured impedance in the `imp` column.
+//!
+//! Data is written in the units it is held in, with the NWB `conversion`
+//! attribute set so that readers recover volts and amperes.
+//!
+//! This module requires the `nwb` feature, which links against the HDF5 library.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::export::nwb::NwbOptions;
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! let options = NwbOptions {
+//!     session_description: "Acute stimulation, rat S1".to_string(),
+//!     electrode_location: "S1".to_string(),
+//!     ..NwbOptions::default()
+//! };
+//! rhs_file.to_nwb("recording.nwb", &options).unwrap();
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::time::SystemTime;
+
+use hdf5::{Group, Location, ObjectReference1};
+use ndarray::{arr0, arr1, Array1, ArrayView1, ArrayView2};
+
+use super::h5::{h5_string, write_attr, write_str_attr, write_str_list_attr};
+use crate::datetime::UtcDateTime;
+use crate::types::{IntanError, RhsFile};
+use crate::units::Unit;
+
+/// Version of the NWB schema the exported files follow
+const NWB_VERSION: &str = "2.7.0";
+/// Name of the device entry describing the acquisition system
+const DEVICE_NAME: &str = "Intan RHS";
+/// Path of the electrodes table
+const ELECTRODES_PATH: &str = "/general/extracellular_ephys/electrodes";
+/// Number of samples per HDF5 chunk in compressed time series
+const CHUNK_SAMPLES: usize = 16384;
+
+/// Session metadata and storage settings for NWB export.
+#[derive(Debug, Clone)]
+pub struct NwbOptions {
+    /// Description of the session
+    pub session_description: String,
+    /// Unique identifier of the session (a random UUID is generated if `None`)
+    pub identifier: Option<String>,
+    /// Start of the recording session (the time of export is used if `None`)
+    pub session_start_time: Option<SystemTime>,
+    /// Brain region or other location of the electrodes
+    pub electrode_location: String,
+    /// Description of the recording device
+    pub device_description: String,
+    /// Gzip compression level (0-9) for the time series, or `None` to store them uncompressed
+    pub compression_level: Option<u8>,
+}
+
+impl Default for NwbOptions {
+    fn default() -> Self {
+        NwbOptions {
+            session_description: "Intan RHS recording".to_string(),
+            identifier: None,
+            session_start_time: None,
+            electrode_location: "unknown".to_string(),
+            device_description: "Intan Stimulation/Recording Controller".to_string(),
+            compression_level: Some(4),
+        }
+    }
+}
+
+impl RhsFile {
+    /// Exports the recording to an NWB 2.x file.
+    ///
+    /// Returns an error if the recording has no data, its signals are spilled to
+    /// disk, or the file cannot be written.
+    /// An existing file at `path` is overwritten.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use intan_importer::export::nwb::NwbOptions;
+    /// use intan_importer::load;
+    ///
+    /// let rhs_file = load("recording.rhs").unwrap();
+    /// rhs_file.to_nwb("recording.nwb", &NwbOptions::default()).unwrap();
+    /// ```
+    pub fn to_nwb<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &NwbOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let data = self.data.as_ref().ok_or_else(|| {
+            IntanError::Other("Cannot export to NWB: the recording has no data".to_string())
+        })?;
+        let data = data.to_double_precision()?;
+
+        let file = hdf5::File::create(path.as_ref())?;
+
+        // File-level metadata
+        write_neurodata_attrs(&file, "core", "NWBFile")?;
+        write_str_attr(&file, "nwb_version", NWB_VERSION)?;
+
+        let start_time = options
+            .session_start_time
+            .map_or_else(UtcDateTime::now, UtcDateTime::from_system_time)
+            .to_iso8601();
+        let identifier = options.identifier.clone().unwrap_or_else(new_object_id);
+        file.new_dataset_builder()
+            .with_data(&arr1(&[h5_string(&UtcDateTime::now().to_iso8601())?]))
+            .create("file_create_date")?;
+        write_str_dataset(&file, "identifier", &identifier)?;
+        write_str_dataset(&file, "session_description", &options.session_description)?;
+        write_str_dataset(&file, "session_start_time", &start_time)?;
+        write_str_dataset(&file, "timestamps_reference_time", &start_time)?;
+
+        for name in [
+            "acquisition",
+            "analysis",
+            "processing",
+            "stimulus",
+            "stimulus/presentation",
+            "stimulus/templates",
+            "general",
+            "general/devices",
+            "general/extracellular_ephys",
+        ] {
+            file.create_group(name)?;
+        }
+
+        // Device and electrode groups, one group per port
+        let device = file.create_group(&format!("general/devices/{}", DEVICE_NAME))?;
+        write_neurodata_attrs(&device, "core", "Device")?;
+        write_str_attr(&device, "description", &options.device_description)?;
+        write_str_attr(&device, "manufacturer", "Intan Technologies")?;
+
+        let channels = &self.header.amplifier_channels;
+        let mut port_names: Vec<&str> = Vec::new();
+        for channel in channels {
+            if !port_names.contains(&channel.port_name.as_str()) {
+                port_names.push(&channel.port_name);
+            }
+        }
+        for port_name in &port_names {
+            let group = file.create_group(&format!("general/extracellular_ephys/{}", port_name))?;
+            write_neurodata_attrs(&group, "core", "ElectrodeGroup")?;
+            write_str_attr(
+                &group,
+                "description",
+                &format!("Amplifier channels on {}", port_name),
+            )?;
+            write_str_attr(&group, "location", &options.electrode_location)?;
+            group.link_soft(&format!("/general/devices/{}", DEVICE_NAME), "device")?;
+        }
+
+        // Electrodes table, one row per amplifier channel
+        let electrodes = file.create_group(ELECTRODES_PATH)?;
+        write_neurodata_attrs(&electrodes, "hdmf-common", "DynamicTable")?;
+        write_str_attr(
+            &electrodes,
+            "description",
+            "Metadata about the amplifier channels",
+        )?;
+        write_str_list_attr(
+            &electrodes,
+            "colnames",
+            &["location", "group", "group_name", "imp", "channel_name"],
+        )?;
+
+        let ids: Array1<i64> = (0..channels.len() as i64).collect();
+        let id = electrodes
+            .new_dataset_builder()
+            .with_data(&ids)
+            .create("id")?;
+        write_neurodata_attrs(&id, "hdmf-common", "ElementIdentifiers")?;
+
+        let locations = channels
+            .iter()
+            .map(|_| h5_string(&options.electrode_location))
+            .collect::<Result<Array1<_>, _>>()?;
+        write_column(
+            &electrodes,
+            "location",
+            "Location of the electrode",
+            locations.view(),
+        )?;
+
+        let group_refs = channels
+            .iter()
+            .map(|channel| {
+                file.reference::<ObjectReference1>(&format!(
+                    "/general/extracellular_ephys/{}",
+                    channel.port_name
+                ))
+            })
+            .collect::<Result<Array1<_>, _>>()?;
+        write_column(
+            &electrodes,
+            "group",
+            "Electrode group of the electrode",
+            group_refs.view(),
+        )?;
+
+        let group_names = channels
+            .iter()
+            .map(|channel| h5_string(&channel.port_name))
+            .collect::<Result<Array1<_>, _>>()?;
+        write_column(
+            &electrodes,
+            "group_name",
+            "Name of the electrode group",
+            group_names.view(),
+        )?;
+
+        let impedances: Array1<f64> = channels
+            .iter()
+            .map(|channel| f64::from(channel.electrode_impedance_magnitude))
+            .collect();
+        let impedance_description = format!(
+            "Impedance magnitude (ohms) measured at {} Hz",
+            self.header.actual_impedance_test_frequency
+        );
+        write_column(
+            &electrodes,
+            "imp",
+            &impedance_description,
+            impedances.view(),
+        )?;
+
+        let channel_names = channels
+            .iter()
+            .map(|channel| h5_string(&channel.custom_channel_name))
+            .collect::<Result<Array1<_>, _>>()?;
+        write_column(
+            &electrodes,
+            "channel_name",
+            "Name of the amplifier channel",
+            channel_names.view(),
+        )?;
+
+        // Time series
+        let timing = Timing::new(data.timestamps.view(), self.header.sample_rate);
+
+        if let Some(amplifier_data) = &data.amplifier_data {
+            let conversion = data.units.amplifier.factor_to(Unit::Volts).unwrap_or(1e-6);
+            let series = file.create_group("acquisition/ElectricalSeries")?;
+            write_neurodata_attrs(&series, "core", "ElectricalSeries")?;
+            write_str_attr(&series, "description", "Amplifier data")?;
+            write_str_attr(&series, "comments", "no comments")?;
+            write_series_data(&series, amplifier_data.view(), conversion, "volts", options)?;
+            timing.write(&series)?;
+
+            let region: Array1<i64> = (0..channels.len() as i64).collect();
+            let electrodes_region = series
+                .new_dataset_builder()
+                .with_data(&region)
+                .create("electrodes")?;
+            write_neurodata_attrs(&electrodes_region, "hdmf-common", "DynamicTableRegion")?;
+            write_str_attr(
+                &electrodes_region,
+                "description",
+                "Electrodes of the amplifier channels",
+            )?;
+            write_attr(
+                &electrodes_region,
+                "table",
+                file.reference::<ObjectReference1>(ELECTRODES_PATH)?,
+            )?;
+        }
+
+        if let Some(stim_data) = &data.stim_data {
+            let conversion = data.units.stim.factor_to(Unit::Microamps).unwrap_or(1.0) * 1e-6;
+            let series = file.create_group("stimulus/presentation/StimulationCurrent")?;
+            write_neurodata_attrs(&series, "core", "TimeSeries")?;
+            write_str_attr(
+                &series,
+                "description",
+                "Stimulation current on each amplifier channel",
+            )?;
+            write_str_attr(&series, "comments", "no comments")?;
+            write_series_data(&series, stim_data.view(), conversion, "amperes", options)?;
+            timing.write(&series)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Sample times of a time series, stored as a start time and rate when the
+/// timestamps are contiguous, or as explicit timestamps otherwise.
+enum Timing {
+    Regular { starting_time: f64, rate: f32 },
+    Irregular(Array1<f64>),
+}
+
+impl Timing {
+    fn new(timestamps: ArrayView1<i64>, sample_rate: f32) -> Timing {
+        let contiguous = timestamps
+            .iter()
+            .zip(timestamps.iter().skip(1))
+            .all(|(previous, next)| next - previous == 1);
+        let sample_rate = f64::from(sample_rate);
+
+        if contiguous {
+            Timing::Regular {
+                starting_time: timestamps
+                    .first()
+                    .map_or(0.0, |&t| t as f64 / sample_rate),
+                rate: sample_rate as f32,
+            }
+        } else {
+            Timing::Irregular(timestamps.mapv(|t| t as f64 / sample_rate))
+        }
+    }
+
+    fn write(&self, series: &Group) -> Result<(), Box<dyn Error>> {
+        match self {
+            Timing::Regular {
+                starting_time,
+                rate,
+            } => {
+                let dataset = series
+                    .new_dataset_builder()
+                    .with_data(&arr0(*starting_time))
+                    .create("starting_time")?;
+                write_attr(&dataset, "rate", *rate)?;
+                write_str_attr(&dataset, "unit", "seconds")?;
+            }
+            Timing::Irregular(timestamps) => {
+                let dataset = series
+                    .new_dataset_builder()
+                    .with_data(timestamps)
+                    .create("timestamps")?;
+                write_attr(&dataset, "interval", 1i32)?;
+                write_str_attr(&dataset, "unit", "seconds")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the `data` dataset of a time series as [time, channels]
+fn write_series_data(
+    series: &Group,
+    values: ArrayView2<f64>,
+    conversion: f64,
+    unit: &str,
+    options: &NwbOptions,
+) -> Result<(), Box<dyn Error>> {
+    // Channels are stored along the first axis here, NWB puts time first
+    let transposed = values.t().as_standard_layout();
+    let (num_samples, num_channels) = transposed.dim();
+
+    let builder = series.new_dataset_builder().with_data(transposed.view());
+    let dataset = match options.compression_level {
+        Some(level) if num_samples > 0 && num_channels > 0 => builder
+            .chunk((CHUNK_SAMPLES.min(num_samples), num_channels))
+            .deflate(level)
+            .create("data")?,
+        _ => builder.create("data")?,
+    };
+
+    write_attr(&dataset, "conversion", conversion as f32)?;
+    write_attr(&dataset, "offset", 0.0f32)?;
+    write_attr(&dataset, "resolution", -1.0f32)?;
+    write_str_attr(&dataset, "unit", unit)?;
+    Ok(())
+}
+
+/// Writes a `VectorData` column of a dynamic table
+fn write_column<T: hdf5::H5Type>(
+    table: &Group,
+    name: &str,
+    description: &str,
+    values: ArrayView1<T>,
+) -> Result<(), Box<dyn Error>> {
+    let column = table.new_dataset_builder().with_data(values).create(name)?;
+    write_neurodata_attrs(&column, "hdmf-common", "VectorData")?;
+    write_str_attr(&column, "description", description)?;
+    Ok(())
+}
+
+/// Writes a scalar string dataset
+fn write_str_dataset(group: &Group, name: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    group
+        .new_dataset_builder()
+        .with_data(&arr0(h5_string(value)?))
+        .create(name)?;
+    Ok(())
+}
+
+/// Writes the attributes that identify an NWB or HDMF object
+fn write_neurodata_attrs(
+    location: &Location,
+    namespace: &str,
+    neurodata_type: &str,
+) -> Result<(), Box<dyn Error>> {
+    write_str_attr(location, "namespace", namespace)?;
+    write_str_attr(location, "neurodata_type", neurodata_type)?;
+    write_str_attr(location, "object_id", &new_object_id())
+}
+
+/// Generates a random (version 4) UUID
+fn new_object_id() -> String {
+    let mut bytes = [0u8; 16];
+    for half in bytes.chunks_mut(8) {
+        let random = RandomState::new().build_hasher().finish();
+        half.copy_from_slice(&random.to_le_bytes());
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
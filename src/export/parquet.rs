@@ -0,0 +1,183 @@
+//! Export to Apache Parquet.
+//!
+//! [`RhsFile::to_parquet`] writes the [wide Arrow layout](crate::export::arrow) to a
+//! Parquet file: a `timestamp` column plus one column per channel, split into row
+//! groups of [`ParquetOptions::row_group_size`] samples. Query engines such as
+//! DataFusion, DuckDB, or Polars can then read a few channels or a time range of a
+//! long recording without scanning the whole file.
+//!
+//! The header is stored as key/value file metadata:
+//!
+//! | Key           | Value |
+//! |---------------|-------|
+//! | `sample_rate` | Sample rate in Hz |
+//! | `channels`    | JSON list of the written columns with their signal type and unit |
+//! | `header`      | The full header as JSON |
+//!
+//! The Arrow schema, including the per-column `unit` metadata, is embedded as well,
+//! so Arrow-based readers get it back unchanged.
+//!
+//! This module requires the `parquet` feature, which also enables `arrow`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::export::parquet::ParquetOptions;
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! rhs_file.to_parquet("recording.parquet", &ParquetOptions::default()).unwrap();
+//!
+//! // Two amplifier channels and a digital input, one row group per second
+//! let options = ParquetOptions {
+//!     channels: Some(vec!["A-000".into(), "A-001".into(), "DIGITAL-IN-01".into()]),
+//!     row_group_size: rhs_file.header.sample_rate as usize,
+//!     ..ParquetOptions::default()
+//! };
+//! rhs_file.to_parquet("subset.parquet", &options).unwrap();
+//! ```
+
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_schema::Schema;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+
+use super::arrow::{ArrowLayout, ArrowOptions};
+use super::header_json;
+use crate::json::Json;
+use crate::types::{IntanError, RhsFile, RhsHeader};
+
+/// Settings for Parquet export.
+#[derive(Debug, Clone)]
+pub struct ParquetOptions {
+    /// Column names to write (`A-000`, `A-000_DC`, `ANALOG-IN-1`, ...), or `None`
+    /// for every channel. The `timestamp` column is always written.
+    pub channels: Option<Vec<String>>,
+    /// Number of samples per row group
+    pub row_group_size: usize,
+    /// Whether to compress column chunks with Snappy
+    pub compress: bool,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        ParquetOptions {
+            channels: None,
+            row_group_size: 1 << 20,
+            compress: true,
+        }
+    }
+}
+
+impl RhsFile {
+    /// Exports the recording to a Parquet file.
+    ///
+    /// See the [module documentation](crate::export::parquet) for the layout of the
+    /// file. An existing file at `path` is overwritten.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recording has no data, `row_group_size` is zero, a
+    /// selected channel does not exist, or the file cannot be written.
+    pub fn to_parquet<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &ParquetOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        if options.row_group_size == 0 {
+            return Err(Box::new(IntanError::Other(
+                "Parquet row group size must be greater than zero".to_string(),
+            )));
+        }
+        let batches = self.to_arrow(&ArrowOptions {
+            layout: ArrowLayout::Wide,
+            batch_size: options.row_group_size,
+        })?;
+        let (mut writer, indices) =
+            create_writer(path.as_ref(), &self.header, &batches[0].schema(), options)?;
+        for batch in &batches {
+            writer.write(&batch.project(&indices)?)?;
+        }
+        writer.close()?;
+        Ok(())
+    }
+}
+
+/// Creates a writer for batches of the wide layout with `schema`, returning it with
+/// the indices of the columns to keep
+pub(crate) fn create_writer(
+    path: &Path,
+    header: &RhsHeader,
+    schema: &Schema,
+    options: &ParquetOptions,
+) -> Result<(ArrowWriter<File>, Vec<usize>), Box<dyn Error>> {
+    // Column indices to keep, with the timestamp first
+    let indices = match &options.channels {
+        None => (0..schema.fields().len()).collect::<Vec<_>>(),
+        Some(channels) => {
+            let mut indices = vec![0];
+            for channel in channels {
+                let index = schema.index_of(channel).map_err(|_| {
+                    IntanError::Other(format!(
+                        "Cannot export to Parquet: no channel named '{}'",
+                        channel
+                    ))
+                })?;
+                indices.push(index);
+            }
+            indices
+        }
+    };
+    let schema = schema.project(&indices)?;
+
+    let channels = Json::Array(
+        schema
+            .fields()
+            .iter()
+            .skip(1)
+            .map(|field| {
+                let metadata = field.metadata();
+                Json::object(vec![
+                    ("name", field.name().as_str().into()),
+                    (
+                        "signal_type",
+                        metadata.get("signal_type").map(String::as_str).into(),
+                    ),
+                    (
+                        "custom_channel_name",
+                        metadata
+                            .get("custom_channel_name")
+                            .map(String::as_str)
+                            .into(),
+                    ),
+                    ("unit", metadata.get("unit").map(String::as_str).into()),
+                ])
+            })
+            .collect(),
+    );
+    let properties = WriterProperties::builder()
+        .set_max_row_group_row_count(Some(options.row_group_size))
+        .set_compression(if options.compress {
+            Compression::SNAPPY
+        } else {
+            Compression::UNCOMPRESSED
+        })
+        .set_key_value_metadata(Some(vec![
+            KeyValue::new("sample_rate".to_string(), header.sample_rate.to_string()),
+            KeyValue::new("channels".to_string(), channels.to_string_pretty()),
+            KeyValue::new(
+                "header".to_string(),
+                header_json(header).to_string_pretty(),
+            ),
+        ]))
+        .build();
+
+    let writer = ArrowWriter::try_new(File::create(path)?, Arc::new(schema), Some(properties))?;
+    Ok((writer, indices))
+}
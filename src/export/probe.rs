@@ -0,0 +1,251 @@
+//! Export of electrode geometry as probeinterface JSON.
+//!
+//! Intan files record channel names but not where the electrodes are.
+//! [`RhsFile::to_probeinterface`] combines the amplifier channels of a recording
+//! with user-supplied positions and writes a [probeinterface] JSON file, the probe
+//! format read by SpikeInterface, so spike sorters see the real geometry.
+//!
+//! Each contact's `device_channel_indices` entry is the index of its channel among
+//! the amplifier channels, which is also its column in the
+//! [int16 binary](crate::export::binary) and the row of `amplifier_data`, so sorted
+//! units line up with the exported data.
+//!
+//! Positions come from a [`ProbeGeometry`], built in code or read from a channel
+//! map file with [`ProbeGeometry::from_channel_map`]. A channel map has one line
+//! per contact with the native channel name, x and y in μm, and an optional shank,
+//! separated by commas, tabs, or spaces. Blank lines, lines starting with `#`, and
+//! a header line are ignored:
+//!
+//! ```text
+//! channel,x,y,shank
+//! A-000,0,0,0
+//! A-001,0,25,0
+//! A-002,250,0,1
+//! ```
+//!
+//! [probeinterface]: https://probeinterface.readthedocs.io
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::export::probe::ProbeGeometry;
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! let geometry = ProbeGeometry::from_channel_map("channel_map.csv").unwrap();
+//! rhs_file.to_probeinterface("probe.json", &geometry).unwrap();
+//! ```
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::json::Json;
+use crate::types::{IntanError, RhsFile};
+
+/// probeinterface format version written to the `version` field
+const PROBEINTERFACE_VERSION: &str = "0.2.21";
+
+/// Position of one electrode contact.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contact {
+    /// Native name of the amplifier channel wired to the contact (e.g. "A-000")
+    pub channel: String,
+    /// Horizontal position (μm)
+    pub x: f64,
+    /// Vertical position (μm)
+    pub y: f64,
+    /// Shank the contact is on, if the probe has several
+    pub shank: Option<String>,
+}
+
+/// Electrode positions of a probe.
+#[derive(Debug, Clone)]
+pub struct ProbeGeometry {
+    /// One entry per contact
+    pub contacts: Vec<Contact>,
+    /// Radius of the circular contacts (μm)
+    pub contact_radius: f64,
+    /// Probe model, written to the probe's `model_name` annotation
+    pub model_name: Option<String>,
+}
+
+impl Default for ProbeGeometry {
+    fn default() -> Self {
+        ProbeGeometry {
+            contacts: Vec::new(),
+            contact_radius: 5.0,
+            model_name: None,
+        }
+    }
+}
+
+impl ProbeGeometry {
+    /// Reads contact positions from a channel map file.
+    ///
+    /// See the [module documentation](crate::export::probe) for the file format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or a line has fewer than three
+    /// columns or unparsable coordinates.
+    pub fn from_channel_map<P: AsRef<Path>>(path: P) -> Result<ProbeGeometry, Box<dyn Error>> {
+        let text = fs::read_to_string(path.as_ref())?;
+        let mut contacts = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|field| !field.is_empty())
+                .collect();
+            let coordinates = match fields.as_slice() {
+                [_, x, y, ..] => x.parse::<f64>().ok().zip(y.parse::<f64>().ok()),
+                _ => {
+                    return Err(Box::new(IntanError::Other(format!(
+                        "Channel map line {} needs a channel name, x, and y",
+                        number + 1
+                    ))))
+                }
+            };
+            let (x, y) = match coordinates {
+                Some(coordinates) => coordinates,
+                // A header line may precede the first contact
+                None if contacts.is_empty() => continue,
+                None => {
+                    return Err(Box::new(IntanError::Other(format!(
+                        "Channel map line {} has invalid coordinates: '{}'",
+                        number + 1,
+                        line
+                    ))))
+                }
+            };
+            contacts.push(Contact {
+                channel: fields[0].to_string(),
+                x,
+                y,
+                shank: fields.get(3).map(|shank| shank.to_string()),
+            });
+        }
+
+        Ok(ProbeGeometry {
+            contacts,
+            ..ProbeGeometry::default()
+        })
+    }
+}
+
+impl RhsFile {
+    /// Writes the probe geometry of the recording as a probeinterface JSON file.
+    ///
+    /// Amplifier channels without a contact in `geometry` are left off the probe.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `geometry` has no contacts, names a channel that is not
+    /// an amplifier channel of the recording, or lists a channel twice, or if the
+    /// file cannot be written.
+    pub fn to_probeinterface<P: AsRef<Path>>(
+        &self,
+        path: P,
+        geometry: &ProbeGeometry,
+    ) -> Result<(), Box<dyn Error>> {
+        if geometry.contacts.is_empty() {
+            return Err(Box::new(IntanError::Other(
+                "Probe geometry has no contacts".to_string(),
+            )));
+        }
+
+        let channels = &self.header.amplifier_channels;
+        let mut indices = Vec::with_capacity(geometry.contacts.len());
+        for contact in &geometry.contacts {
+            let index = channels
+                .iter()
+                .position(|channel| channel.native_channel_name == contact.channel)
+                .ok_or_else(|| {
+                    IntanError::Other(format!(
+                        "Probe contact '{}' is not an amplifier channel of the recording",
+                        contact.channel
+                    ))
+                })?;
+            if indices.contains(&index) {
+                return Err(Box::new(IntanError::Other(format!(
+                    "Channel '{}' appears more than once in the probe geometry",
+                    contact.channel
+                ))));
+            }
+            indices.push(index);
+        }
+
+        let contacts = &geometry.contacts;
+        let mut annotations = vec![("manufacturer", "".into())];
+        if let Some(model_name) = &geometry.model_name {
+            annotations.push(("model_name", model_name.as_str().into()));
+        }
+        let probe = Json::object(vec![
+            ("ndim", 2.into()),
+            ("si_units", "um".into()),
+            ("annotations", Json::object(annotations)),
+            ("contact_annotations", Json::Object(Vec::new())),
+            (
+                "contact_positions",
+                Json::Array(
+                    contacts
+                        .iter()
+                        .map(|contact| vec![contact.x, contact.y].into())
+                        .collect(),
+                ),
+            ),
+            (
+                "contact_plane_axes",
+                Json::Array(
+                    contacts
+                        .iter()
+                        .map(|_| vec![vec![1.0, 0.0], vec![0.0, 1.0]].into())
+                        .collect(),
+                ),
+            ),
+            (
+                "contact_shapes",
+                contacts.iter().map(|_| "circle").collect::<Vec<_>>().into(),
+            ),
+            (
+                "contact_shape_params",
+                Json::Array(
+                    contacts
+                        .iter()
+                        .map(|_| Json::object(vec![("radius", geometry.contact_radius.into())]))
+                        .collect(),
+                ),
+            ),
+            ("device_channel_indices", indices.into()),
+            (
+                "contact_ids",
+                contacts
+                    .iter()
+                    .map(|contact| contact.channel.as_str())
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+            (
+                "shank_ids",
+                contacts
+                    .iter()
+                    .map(|contact| contact.shank.as_deref().unwrap_or(""))
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+        ]);
+        let probe_group = Json::object(vec![
+            ("specification", "probeinterface".into()),
+            ("version", PROBEINTERFACE_VERSION.into()),
+            ("probes", Json::Array(vec![probe])),
+        ]);
+
+        fs::write(path.as_ref(), probe_group.to_string_pretty())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,380 @@
+//! Streaming export of directories of RHS files.
+//!
+//! [`export_directory`] converts all RHS files of a directory into one combined
+//! export without loading the recording into memory. It reads the files one after
+//! another, [block by block](crate::stream::RhsBlockReader), and writes the samples
+//! in chunks as they are read, so memory use does not grow with the length of the
+//! session and recordings far larger than RAM can be converted. The files written
+//! hold the same data and metadata as those of the exporter of the loaded
+//! recording:
+//!
+//! - [`StreamingFormat::Int16Binary`] writes the files of [`RhsFile::to_int16_binary`]
+//! - `StreamingFormat::Parquet` writes the file of `RhsFile::to_parquet`, with the
+//!   `parquet` feature
+//!
+//! Files are found, ordered, and combined as when [loading a
+//! directory](crate::Loader): the file pattern, header compatibility, gap handling,
+//! corrupt file, and duplicate file options apply, as do channel selection, the
+//! notch filter, and the data type. Every header is checked before anything is
+//! written. Options that need the whole recording are rejected: time ranges,
+//! wall-clock ranges, and bad channel masking.
+//!
+//! The HDF5-based formats are not streamed; load the recording to export it to
+//! HDF5, NWB, or MATLAB.
+//!
+//! [`RhsFile::to_int16_binary`]: crate::RhsFile::to_int16_binary
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::export::streaming::{export_directory, StreamingFormat};
+//! use intan_importer::LoadOptions;
+//!
+//! let export = export_directory(
+//!     "session_dir/",
+//!     "session.bin",
+//!     &StreamingFormat::Int16Binary,
+//!     &LoadOptions::default(),
+//! )
+//! .unwrap();
+//! println!(
+//!     "Wrote {} samples from {} files",
+//!     export.num_samples,
+//!     export.source_files.len()
+//! );
+//! ```
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+#[cfg(feature = "parquet")]
+use std::path::PathBuf;
+
+use log::info;
+
+use super::binary::{write_binary_metadata, write_int16_samples};
+#[cfg(feature = "parquet")]
+use super::parquet::ParquetOptions;
+use crate::chunk::{ChunkAlignment, OwnedChunk, Rechunker};
+use crate::fingerprint::{fingerprint_file, FileFingerprint};
+use crate::loader::{GapHandling, LoadOptions};
+use crate::reader;
+use crate::stream::RhsBlockReader;
+use crate::types::{IntanError, LoadWarning, RhsData, RhsHeader, SkippedFile, SourceFileSpan};
+
+/// Number of samples converted and written at a time
+const CHUNK_SAMPLES: usize = 1 << 16;
+
+/// Format of a streaming export.
+#[derive(Debug, Clone)]
+pub enum StreamingFormat {
+    /// Interleaved int16 amplifier data with JSON metadata, as written by
+    /// [`RhsFile::to_int16_binary`](crate::RhsFile::to_int16_binary)
+    Int16Binary,
+    /// Apache Parquet, as written by [`RhsFile::to_parquet`](crate::RhsFile::to_parquet)
+    #[cfg(feature = "parquet")]
+    Parquet(ParquetOptions),
+}
+
+/// Summary of a completed streaming export.
+#[derive(Debug, Clone)]
+pub struct StreamedExport {
+    /// Number of samples written, including any gap padding
+    pub num_samples: usize,
+    /// The exported files and the samples each contributed
+    pub source_files: Vec<SourceFileSpan>,
+    /// Files left out because they could not be read or duplicate other files
+    pub skipped_files: Vec<SkippedFile>,
+    /// Content hashes of the exported files, with
+    /// [`LoadOptions::fingerprint`](crate::LoadOptions::fingerprint)
+    pub fingerprints: Vec<FileFingerprint>,
+    /// Conditions found while exporting that did not stop it, as in
+    /// [`RhsFile::warnings`](crate::RhsFile::warnings)
+    pub warnings: Vec<LoadWarning>,
+}
+
+/// Exports all RHS files in a directory to one file at `output`, reading and
+/// writing them block by block.
+///
+/// See the [module documentation](crate::export::streaming) for the options that
+/// apply. An existing file at `output` is overwritten.
+///
+/// # Errors
+///
+/// Returns an error if the directory holds no RHS files, `options` are invalid or
+/// need the whole recording, the headers are incompatible, a gap is rejected by
+/// the gap handling, the recording has no data to export, or a file cannot be
+/// read or written. Files already written are left in place after an error.
+pub fn export_directory<P: AsRef<Path>, Q: AsRef<Path>>(
+    dir_path: P,
+    output: Q,
+    format: &StreamingFormat,
+    options: &LoadOptions,
+) -> Result<StreamedExport, Box<dyn Error>> {
+    let output = output.as_ref();
+    options.check()?;
+    check_streaming_options(options)?;
+
+    let file_paths =
+        crate::find_matching_rhs_files(dir_path.as_ref(), options.file_pattern.as_deref())?;
+    if file_paths.is_empty() {
+        return Err(Box::new(IntanError::NoFiles {
+            directory: Some(dir_path.as_ref().to_path_buf()),
+            pattern: options.file_pattern.clone(),
+        }));
+    }
+    let (file_paths, mut skipped_files) = if options.skip_corrupt_files {
+        reader::readable_files(&file_paths)?
+    } else {
+        (file_paths, Vec::new())
+    };
+    let (file_paths, duplicates) =
+        crate::manifest::remove_duplicate_files(&file_paths, options.duplicate_handling)?;
+    skipped_files.extend(duplicates);
+
+    // Check every header before writing anything
+    let mut warnings = Vec::new();
+    let mut headers = Vec::with_capacity(file_paths.len());
+    for path in &file_paths {
+        let (mut header, header_warnings) =
+            reader::read_header_file_with_mode(path, options.parse_mode)?;
+        reader::select_channels(&mut header, options)?;
+        // Logged by the block reader when the file is read
+        for mut warning in header_warnings {
+            if let Some(warning_path) = warning.path_mut() {
+                *warning_path = path.to_string_lossy().to_string();
+            }
+            warnings.push(warning);
+        }
+        if let Some(first) = headers.first() {
+            let header_warnings = reader::verify_header_compatibility(
+                first,
+                &header,
+                path,
+                options.header_compatibility,
+            )
+            .map_err(|e| e.in_file(path, None))?;
+            warnings.extend(header_warnings);
+        }
+        headers.push(header);
+    }
+    let header = &headers[0];
+
+    let mut sink = Sink::create(output, format)?;
+    let mut source_files = Vec::with_capacity(file_paths.len());
+    let mut fingerprints = Vec::new();
+    let mut num_samples = 0;
+    let mut previous_timestamp = None;
+    let mut rechunker =
+        Rechunker::new(CHUNK_SAMPLES, ChunkAlignment::Start, |chunk: OwnedChunk| {
+            sink.write(header, chunk.data)
+        });
+
+    for (i, path) in file_paths.iter().enumerate() {
+        if options.verbose {
+            info!(
+                "Exporting file {}/{}: {}",
+                i + 1,
+                file_paths.len(),
+                path.display()
+            );
+        }
+        let mut span = reader::source_file_span(path, None, num_samples);
+        let mut num_gaps = 0;
+        for block in RhsBlockReader::with_options(path, options)? {
+            let mut data = block?.data;
+            reader::unwrap_timestamps(data.timestamps.view_mut(), previous_timestamp);
+            if let (Some(last), Some(_)) = (previous_timestamp, span.first_timestamp) {
+                num_gaps += usize::from(data.timestamps.first() != Some(&(last + 1)));
+            }
+            num_gaps += data
+                .timestamps
+                .windows(2)
+                .into_iter()
+                .filter(|w| w[1] - w[0] != 1)
+                .count();
+            if let (Some(last), None) = (previous_timestamp, span.first_timestamp) {
+                let missing = data.timestamps[0] - last - 1;
+                if missing != 0 {
+                    let padding = reader::handle_gap(
+                        path,
+                        missing,
+                        header.sample_rate,
+                        options,
+                        &mut warnings,
+                    )?;
+                    let nan = options.gap_handling == GapHandling::PadNan;
+                    for start in (0..padding).step_by(CHUNK_SAMPLES) {
+                        let length = CHUNK_SAMPLES.min(padding - start);
+                        let first = last + 1 + start as i64;
+                        let piece = reader::padding_like(&data, first, length, nan);
+                        rechunker.push(piece.view())?;
+                    }
+                    num_samples += padding;
+                    span.sample_offset = num_samples;
+                }
+            }
+
+            span.first_timestamp = span.first_timestamp.or(data.timestamps.first().copied());
+            span.num_samples += data.num_samples();
+            num_samples += data.num_samples();
+            previous_timestamp = data.timestamps.last().copied().or(previous_timestamp);
+            rechunker.push(data.view())?;
+        }
+        if num_gaps > 0 {
+            warnings.push(LoadWarning::TimestampGaps {
+                path: path.to_string_lossy().to_string(),
+                num_gaps,
+            });
+        }
+        source_files.push(span);
+        if options.fingerprint {
+            fingerprints.push(fingerprint_file(path)?);
+        }
+    }
+    rechunker.flush()?;
+    drop(rechunker);
+
+    sink.finish(output, header, num_samples)?;
+    if options.verbose {
+        info!(
+            "Exported {} samples from {} files to {}",
+            num_samples,
+            file_paths.len(),
+            output.display()
+        );
+    }
+
+    Ok(StreamedExport {
+        num_samples,
+        source_files,
+        skipped_files,
+        fingerprints,
+        warnings,
+    })
+}
+
+/// Rejects the options that select or process the recording as a whole
+fn check_streaming_options(options: &LoadOptions) -> Result<(), Box<dyn Error>> {
+    let unsupported = if options.time_range.is_some() {
+        "a time range"
+    } else if options.wallclock_range.is_some() {
+        "a wall-clock range"
+    } else if options.mask_bad_channels.is_some() {
+        "bad channel masking"
+    } else {
+        return Ok(());
+    };
+    Err(Box::new(IntanError::Other(format!(
+        "Streaming export does not support {}; load the recording to use it",
+        unsupported
+    ))))
+}
+
+/// Destination of the exported samples
+enum Sink {
+    Int16Binary(BufWriter<File>),
+    #[cfg(feature = "parquet")]
+    Parquet {
+        path: PathBuf,
+        options: ParquetOptions,
+        /// Writer and the indices of the columns to keep, created with the first
+        /// chunk, whose schema it takes
+        writer: Option<Box<(parquet::arrow::ArrowWriter<File>, Vec<usize>)>>,
+    },
+}
+
+impl Sink {
+    fn create(output: &Path, format: &StreamingFormat) -> Result<Sink, Box<dyn Error>> {
+        Ok(match format {
+            StreamingFormat::Int16Binary => {
+                Sink::Int16Binary(BufWriter::new(File::create(output)?))
+            }
+            #[cfg(feature = "parquet")]
+            StreamingFormat::Parquet(options) => {
+                if options.row_group_size == 0 {
+                    return Err(Box::new(IntanError::Other(
+                        "Parquet row group size must be greater than zero".to_string(),
+                    )));
+                }
+                // Created now so that an unwritable path fails before reading
+                File::create(output)?;
+                Sink::Parquet {
+                    path: output.to_path_buf(),
+                    options: options.clone(),
+                    writer: None,
+                }
+            }
+        })
+    }
+
+    #[cfg_attr(not(feature = "parquet"), allow(unused_variables))]
+    fn write(&mut self, header: &RhsHeader, data: RhsData) -> Result<(), Box<dyn Error>> {
+        match self {
+            Sink::Int16Binary(writer) => {
+                let data = data.into_double_precision()?;
+                let amplifier_data = data.amplifier_data.as_ref().ok_or_else(|| {
+                    IntanError::Other(
+                        "Cannot export to binary: the recording has no amplifier data".to_string(),
+                    )
+                })?;
+                write_int16_samples(writer, amplifier_data.view(), data.units.amplifier)
+            }
+            #[cfg(feature = "parquet")]
+            Sink::Parquet {
+                path,
+                options,
+                writer,
+            } => {
+                use super::arrow::{record_batches, ArrowLayout, ArrowOptions};
+
+                let arrow_options = ArrowOptions {
+                    layout: ArrowLayout::Wide,
+                    batch_size: options.row_group_size,
+                };
+                let batches = record_batches(header, data, &arrow_options)?;
+                let writer = match writer {
+                    Some(writer) => writer,
+                    None => writer.insert(Box::new(super::parquet::create_writer(
+                        path,
+                        header,
+                        &batches[0].schema(),
+                        options,
+                    )?)),
+                };
+                let (writer, indices) = &mut **writer;
+                for batch in &batches {
+                    writer.write(&batch.project(indices)?)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(
+        self,
+        output: &Path,
+        header: &RhsHeader,
+        num_samples: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        if num_samples == 0 {
+            return Err(Box::new(IntanError::Other(
+                "Cannot export: the recording has no data".to_string(),
+            )));
+        }
+        match self {
+            Sink::Int16Binary(mut writer) => {
+                writer.flush()?;
+                write_binary_metadata(output, header, num_samples)
+            }
+            #[cfg(feature = "parquet")]
+            Sink::Parquet { writer, .. } => {
+                if let Some(writer) = writer {
+                    writer.0.close()?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
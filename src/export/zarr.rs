@@ -0,0 +1,411 @@
+//! Export to Zarr version 3 stores.
+//!
+//! [`RhsFile::to_zarr`] writes a recording as a Zarr v3 directory store, so it can
+//! be uploaded to object storage and read lazily, one chunk at a time, with
+//! zarr-python, TensorStore, or any other Zarr v3 reader. The layout is:
+//!
+//! ```text
+//! recording.zarr/zarr.json                 group, with sample_rate and header attributes
+//! recording.zarr/timestamps/               [num_samples], int64
+//! recording.zarr/amplifier_data/           [num_channels, num_samples], float64
+//! recording.zarr/...                       one array per signal present in the recording
+//! ```
+//!
+//! Analog arrays have `unit` and `channel_names` attributes. Chunks cover
+//! [`ZarrOptions::chunk_channels`] channels by [`ZarrOptions::chunk_samples`]
+//! samples and are gzip-compressed by default.
+//!
+//! This module requires the `zarr` feature.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::export::zarr::{ZarrCompression, ZarrOptions};
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! rhs_file.to_zarr("recording.zarr", &ZarrOptions::default()).unwrap();
+//!
+//! // Ten-second chunks of 16 channels, uncompressed
+//! let options = ZarrOptions {
+//!     chunk_channels: 16,
+//!     chunk_samples: 10 * rhs_file.header.sample_rate as usize,
+//!     compression: ZarrCompression::None,
+//! };
+//! rhs_file.to_zarr("recording_raw.zarr", &options).unwrap();
+//! ```
+
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use ndarray::{Array, ArrayView, Dimension, IxDyn, Slice};
+
+use super::header_json;
+use crate::json::Json;
+use crate::types::{ChannelInfo, IntanError, RhsFile};
+
+/// Compression applied to each chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZarrCompression {
+    /// Chunks are stored as raw little-endian bytes
+    None,
+    /// Zarr `gzip` codec with the given level (0-9)
+    Gzip(u32),
+}
+
+/// Chunking and compression settings for Zarr export.
+#[derive(Debug, Clone)]
+pub struct ZarrOptions {
+    /// Number of channels per chunk
+    pub chunk_channels: usize,
+    /// Number of samples per chunk
+    pub chunk_samples: usize,
+    /// Compression applied to each chunk
+    pub compression: ZarrCompression,
+}
+
+impl Default for ZarrOptions {
+    fn default() -> Self {
+        ZarrOptions {
+            chunk_channels: 1,
+            chunk_samples: 65536,
+            compression: ZarrCompression::Gzip(5),
+        }
+    }
+}
+
+impl RhsFile {
+    /// Exports the recording to a Zarr v3 directory store.
+    ///
+    /// See the [module documentation](crate::export::zarr) for the layout of the store.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recording has no data, a chunk dimension is zero,
+    /// `path` already exists, the signals are spilled to disk, or a file cannot be
+    /// written.
+    pub fn to_zarr<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &ZarrOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let data = self.data.as_ref().ok_or_else(|| {
+            IntanError::Other("Cannot export to Zarr: the recording has no data".to_string())
+        })?;
+        if options.chunk_channels == 0 || options.chunk_samples == 0 {
+            return Err(Box::new(IntanError::Other(
+                "Zarr chunk dimensions must be greater than zero".to_string(),
+            )));
+        }
+        // Never write into an existing store, whose stale chunks would be mixed with ours
+        if path.exists() {
+            return Err(Box::new(IntanError::Other(format!(
+                "Cannot export to Zarr: '{}' already exists",
+                path.display()
+            ))));
+        }
+        let data = data.to_double_precision()?;
+
+        fs::create_dir_all(path)?;
+        let group = Json::object(vec![
+            ("zarr_format", 3.into()),
+            ("node_type", "group".into()),
+            (
+                "attributes",
+                Json::object(vec![
+                    ("sample_rate", self.header.sample_rate.into()),
+                    ("header", header_json(&self.header)),
+                ]),
+            ),
+        ]);
+        fs::write(path.join("zarr.json"), group.to_string_pretty())?;
+
+        write_array(
+            path,
+            "timestamps",
+            data.timestamps.view(),
+            Json::Object(Vec::new()),
+            options,
+        )?;
+
+        let header = &self.header;
+        let units = &data.units;
+        for (name, channels, values, unit) in [
+            (
+                "amplifier_data",
+                &header.amplifier_channels,
+                &data.amplifier_data,
+                units.amplifier,
+            ),
+            (
+                "dc_amplifier_data",
+                &header.amplifier_channels,
+                &data.dc_amplifier_data,
+                units.dc_amplifier,
+            ),
+            (
+                "stim_data",
+                &header.amplifier_channels,
+                &data.stim_data,
+                units.stim,
+            ),
+            (
+                "board_adc_data",
+                &header.board_adc_channels,
+                &data.board_adc_data,
+                units.board_adc,
+            ),
+            (
+                "board_dac_data",
+                &header.board_dac_channels,
+                &data.board_dac_data,
+                units.board_dac,
+            ),
+        ] {
+            if let Some(values) = values {
+                let attributes = Json::object(vec![
+                    ("unit", unit.symbol().into()),
+                    ("channel_names", channel_names(channels)),
+                ]);
+                write_array(path, name, values.view(), attributes, options)?;
+            }
+        }
+        for (name, values) in [
+            ("compliance_limit_data", &data.compliance_limit_data),
+            ("charge_recovery_data", &data.charge_recovery_data),
+            ("amp_settle_data", &data.amp_settle_data),
+        ] {
+            if let Some(values) = values {
+                let attributes = Json::object(vec![(
+                    "channel_names",
+                    channel_names(&header.amplifier_channels),
+                )]);
+                write_array(path, name, values.to_array().view(), attributes, options)?;
+            }
+        }
+        for (name, channels, values) in [
+            (
+                "board_dig_in_data",
+                &header.board_dig_in_channels,
+                &data.board_dig_in_data,
+            ),
+            (
+                "board_dig_out_data",
+                &header.board_dig_out_channels,
+                &data.board_dig_out_data,
+            ),
+        ] {
+            if let Some(values) = values {
+                let attributes = Json::object(vec![("channel_names", channel_names(channels))]);
+                write_array(path, name, values.view(), attributes, options)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An array element type with a Zarr v3 data type
+trait ZarrElement: Copy + Default {
+    /// Zarr v3 data type name
+    const DATA_TYPE: &'static str;
+    /// Size of one element in bytes
+    const SIZE: usize;
+    /// Fill value for chunk padding, as JSON
+    fn fill_value() -> Json;
+    /// Appends the element's little-endian bytes
+    fn extend_le(self, out: &mut Vec<u8>);
+}
+
+impl ZarrElement for f64 {
+    const DATA_TYPE: &'static str = "float64";
+    const SIZE: usize = 8;
+    fn fill_value() -> Json {
+        0.0.into()
+    }
+    fn extend_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl ZarrElement for i32 {
+    const DATA_TYPE: &'static str = "int32";
+    const SIZE: usize = 4;
+    fn fill_value() -> Json {
+        0.into()
+    }
+    fn extend_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl ZarrElement for i64 {
+    const DATA_TYPE: &'static str = "int64";
+    const SIZE: usize = 8;
+    fn fill_value() -> Json {
+        0.into()
+    }
+    fn extend_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl ZarrElement for bool {
+    const DATA_TYPE: &'static str = "bool";
+    const SIZE: usize = 1;
+    fn fill_value() -> Json {
+        false.into()
+    }
+    fn extend_le(self, out: &mut Vec<u8>) {
+        out.push(u8::from(self));
+    }
+}
+
+/// Writes one array: its `zarr.json` and every chunk under `c/`
+fn write_array<T: ZarrElement, D: Dimension>(
+    store: &Path,
+    name: &str,
+    values: ArrayView<T, D>,
+    attributes: Json,
+    options: &ZarrOptions,
+) -> Result<(), Box<dyn Error>> {
+    let dir = store.join(name);
+    fs::create_dir_all(&dir)?;
+
+    let values = values.into_dyn();
+    let shape = values.shape().to_vec();
+    // Time is the last axis; a leading axis indexes channels
+    let chunk_shape: Vec<usize> = match shape.len() {
+        1 => vec![options.chunk_samples],
+        _ => vec![options.chunk_channels, options.chunk_samples],
+    };
+    let dimension_names: Vec<&str> = match shape.len() {
+        1 => vec!["time"],
+        _ => vec!["channel", "time"],
+    };
+
+    // Endianness is meaningless for single-byte types and must be left out
+    let mut codecs = vec![if T::SIZE == 1 {
+        Json::object(vec![("name", "bytes".into())])
+    } else {
+        Json::object(vec![
+            ("name", "bytes".into()),
+            (
+                "configuration",
+                Json::object(vec![("endian", "little".into())]),
+            ),
+        ])
+    }];
+    if let ZarrCompression::Gzip(level) = options.compression {
+        codecs.push(Json::object(vec![
+            ("name", "gzip".into()),
+            (
+                "configuration",
+                Json::object(vec![("level", (level.min(9) as usize).into())]),
+            ),
+        ]));
+    }
+
+    let metadata = Json::object(vec![
+        ("zarr_format", 3.into()),
+        ("node_type", "array".into()),
+        ("shape", shape.clone().into()),
+        ("data_type", T::DATA_TYPE.into()),
+        (
+            "chunk_grid",
+            Json::object(vec![
+                ("name", "regular".into()),
+                (
+                    "configuration",
+                    Json::object(vec![("chunk_shape", chunk_shape.clone().into())]),
+                ),
+            ]),
+        ),
+        (
+            "chunk_key_encoding",
+            Json::object(vec![
+                ("name", "default".into()),
+                (
+                    "configuration",
+                    Json::object(vec![("separator", "/".into())]),
+                ),
+            ]),
+        ),
+        ("fill_value", T::fill_value()),
+        ("codecs", Json::Array(codecs)),
+        ("attributes", attributes),
+        ("dimension_names", dimension_names.into()),
+    ]);
+    fs::write(dir.join("zarr.json"), metadata.to_string_pretty())?;
+
+    // Chunks on the edge of the grid are padded to the full chunk shape
+    let grid: Vec<usize> = shape
+        .iter()
+        .zip(&chunk_shape)
+        .map(|(&length, &chunk)| length.div_ceil(chunk))
+        .collect();
+    if grid.contains(&0) {
+        return Ok(());
+    }
+    let mut chunk = Array::from_elem(IxDyn(&chunk_shape), T::default());
+    let mut bytes = Vec::with_capacity(chunk.len() * T::SIZE);
+    let mut index = vec![0; grid.len()];
+    loop {
+        chunk.fill(T::default());
+        let source = values.slice_each_axis(|axis| {
+            let i = axis.axis.index();
+            let start = index[i] * chunk_shape[i];
+            Slice::from(start..(start + chunk_shape[i]).min(shape[i]))
+        });
+        chunk
+            .slice_each_axis_mut(|axis| Slice::from(0..source.shape()[axis.axis.index()]))
+            .assign(&source);
+
+        bytes.clear();
+        for &value in chunk.iter() {
+            value.extend_le(&mut bytes);
+        }
+        let mut key = dir.join("c");
+        for &i in &index[..index.len() - 1] {
+            key.push(i.to_string());
+        }
+        fs::create_dir_all(&key)?;
+        key.push(index[index.len() - 1].to_string());
+        match options.compression {
+            ZarrCompression::None => fs::write(&key, &bytes)?,
+            ZarrCompression::Gzip(level) => {
+                let mut encoder =
+                    GzEncoder::new(fs::File::create(&key)?, Compression::new(level.min(9)));
+                encoder.write_all(&bytes)?;
+                encoder.finish()?;
+            }
+        }
+
+        // Advance to the next chunk in C order
+        let mut axis = grid.len();
+        loop {
+            if axis == 0 {
+                return Ok(());
+            }
+            axis -= 1;
+            index[axis] += 1;
+            if index[axis] < grid[axis] {
+                break;
+            }
+            index[axis] = 0;
+        }
+    }
+}
+
+fn channel_names(channels: &[ChannelInfo]) -> Json {
+    channels
+        .iter()
+        .map(|channel| channel.native_channel_name.as_str())
+        .collect::<Vec<_>>()
+        .into()
+}
@@ -0,0 +1,284 @@
+//! Minimal writer for uncompressed ("stored") ZIP archives.
+//!
+//! Entries are streamed to disk and their CRC-32 is patched into the local header
+//! afterwards, so entries never have to be buffered in memory. ZIP64 records are
+//! added when an entry or the archive exceeds the 4 GiB limit of classic ZIP.
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::datetime::UtcDateTime;
+use crate::types::IntanError;
+
+/// Values at or above this limit need ZIP64 records
+const ZIP64_LIMIT: u64 = 0xFFFF_FFFF;
+/// Version needed to extract: 2.0 for classic entries, 4.5 for ZIP64
+const VERSION_CLASSIC: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+
+/// An entry that has been written, as needed for the central directory
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u64,
+    offset: u64,
+}
+
+/// Writes an uncompressed ZIP archive entry by entry.
+pub(super) struct ZipWriter {
+    file: BufWriter<File>,
+    entries: Vec<Entry>,
+    time: u16,
+    date: u16,
+}
+
+impl ZipWriter {
+    pub(super) fn create(path: &Path) -> Result<ZipWriter, Box<dyn Error>> {
+        let now = UtcDateTime::now();
+        Ok(ZipWriter {
+            file: BufWriter::new(File::create(path)?),
+            entries: Vec::new(),
+            time: dos_time(&now),
+            date: dos_date(&now),
+        })
+    }
+
+    /// Writes an entry of `size` bytes, produced by `write_contents`.
+    ///
+    /// `write_contents` must write exactly `size` bytes through the given writer.
+    pub(super) fn add_entry<F>(
+        &mut self,
+        name: &str,
+        size: u64,
+        write_contents: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<(), Box<dyn Error>>,
+    {
+        let offset = self.file.stream_position()?;
+        let zip64 = size >= ZIP64_LIMIT;
+
+        // Local file header, with the CRC filled in once the contents are written
+        self.file.write_u32::<LittleEndian>(0x04034b50)?;
+        self.file.write_u16::<LittleEndian>(if zip64 {
+            VERSION_ZIP64
+        } else {
+            VERSION_CLASSIC
+        })?;
+        self.file.write_u16::<LittleEndian>(0)?; // flags
+        self.file.write_u16::<LittleEndian>(0)?; // stored
+        self.file.write_u16::<LittleEndian>(self.time)?;
+        self.file.write_u16::<LittleEndian>(self.date)?;
+        self.file.write_u32::<LittleEndian>(0)?; // CRC-32
+        let size32 = field32(size);
+        self.file.write_u32::<LittleEndian>(size32)?; // compressed size
+        self.file.write_u32::<LittleEndian>(size32)?; // uncompressed size
+        self.file.write_u16::<LittleEndian>(name.len() as u16)?;
+        self.file
+            .write_u16::<LittleEndian>(if zip64 { 20 } else { 0 })?;
+        self.file.write_all(name.as_bytes())?;
+        if zip64 {
+            self.file.write_u16::<LittleEndian>(1)?;
+            self.file.write_u16::<LittleEndian>(16)?;
+            self.file.write_u64::<LittleEndian>(size)?;
+            self.file.write_u64::<LittleEndian>(size)?;
+        }
+
+        let mut contents = CrcWriter {
+            inner: &mut self.file,
+            crc: Crc32::new(),
+            written: 0,
+        };
+        write_contents(&mut contents)?;
+        let (crc, written) = (contents.crc.finish(), contents.written);
+        if written != size {
+            return Err(Box::new(IntanError::Other(format!(
+                "ZIP entry '{}' has {} bytes, expected {}",
+                name, written, size
+            ))));
+        }
+
+        let end = self.file.stream_position()?;
+        self.file.seek(SeekFrom::Start(offset + 14))?;
+        self.file.write_u32::<LittleEndian>(crc)?;
+        self.file.seek(SeekFrom::Start(end))?;
+
+        self.entries.push(Entry {
+            name: name.to_string(),
+            crc,
+            size,
+            offset,
+        });
+        Ok(())
+    }
+
+    /// Writes the central directory and closes the archive.
+    pub(super) fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        let directory_offset = self.file.stream_position()?;
+        for entry in &self.entries {
+            let mut extra = Vec::new();
+            if entry.size >= ZIP64_LIMIT {
+                extra.extend_from_slice(&entry.size.to_le_bytes());
+                extra.extend_from_slice(&entry.size.to_le_bytes());
+            }
+            if entry.offset >= ZIP64_LIMIT {
+                extra.extend_from_slice(&entry.offset.to_le_bytes());
+            }
+            let zip64 = !extra.is_empty();
+            let version = if zip64 {
+                VERSION_ZIP64
+            } else {
+                VERSION_CLASSIC
+            };
+
+            self.file.write_u32::<LittleEndian>(0x02014b50)?;
+            self.file.write_u16::<LittleEndian>(version)?; // made by
+            self.file.write_u16::<LittleEndian>(version)?; // needed to extract
+            self.file.write_u16::<LittleEndian>(0)?; // flags
+            self.file.write_u16::<LittleEndian>(0)?; // stored
+            self.file.write_u16::<LittleEndian>(self.time)?;
+            self.file.write_u16::<LittleEndian>(self.date)?;
+            self.file.write_u32::<LittleEndian>(entry.crc)?;
+            let size32 = field32(entry.size);
+            self.file.write_u32::<LittleEndian>(size32)?;
+            self.file.write_u32::<LittleEndian>(size32)?;
+            self.file
+                .write_u16::<LittleEndian>(entry.name.len() as u16)?;
+            let extra_len = if zip64 { extra.len() + 4 } else { 0 };
+            self.file.write_u16::<LittleEndian>(extra_len as u16)?;
+            self.file.write_u16::<LittleEndian>(0)?; // comment length
+            self.file.write_u16::<LittleEndian>(0)?; // disk number
+            self.file.write_u16::<LittleEndian>(0)?; // internal attributes
+            self.file.write_u32::<LittleEndian>(0)?; // external attributes
+            self.file.write_u32::<LittleEndian>(field32(entry.offset))?;
+            self.file.write_all(entry.name.as_bytes())?;
+            if zip64 {
+                self.file.write_u16::<LittleEndian>(1)?;
+                self.file.write_u16::<LittleEndian>(extra.len() as u16)?;
+                self.file.write_all(&extra)?;
+            }
+        }
+        let directory_end = self.file.stream_position()?;
+        let directory_size = directory_end - directory_offset;
+        let num_entries = self.entries.len() as u64;
+
+        let zip64 = num_entries >= 0xFFFF
+            || directory_offset >= ZIP64_LIMIT
+            || directory_size >= ZIP64_LIMIT;
+        if zip64 {
+            // ZIP64 end of central directory record and locator
+            self.file.write_u32::<LittleEndian>(0x06064b50)?;
+            self.file.write_u64::<LittleEndian>(44)?;
+            self.file.write_u16::<LittleEndian>(VERSION_ZIP64)?;
+            self.file.write_u16::<LittleEndian>(VERSION_ZIP64)?;
+            self.file.write_u32::<LittleEndian>(0)?;
+            self.file.write_u32::<LittleEndian>(0)?;
+            self.file.write_u64::<LittleEndian>(num_entries)?;
+            self.file.write_u64::<LittleEndian>(num_entries)?;
+            self.file.write_u64::<LittleEndian>(directory_size)?;
+            self.file.write_u64::<LittleEndian>(directory_offset)?;
+
+            self.file.write_u32::<LittleEndian>(0x07064b50)?;
+            self.file.write_u32::<LittleEndian>(0)?;
+            self.file.write_u64::<LittleEndian>(directory_end)?;
+            self.file.write_u32::<LittleEndian>(1)?;
+        }
+
+        self.file.write_u32::<LittleEndian>(0x06054b50)?;
+        self.file.write_u16::<LittleEndian>(0)?;
+        self.file.write_u16::<LittleEndian>(0)?;
+        let entries16 = num_entries.min(0xFFFF) as u16;
+        self.file.write_u16::<LittleEndian>(entries16)?;
+        self.file.write_u16::<LittleEndian>(entries16)?;
+        self.file
+            .write_u32::<LittleEndian>(field32(directory_size))?;
+        self.file
+            .write_u32::<LittleEndian>(field32(directory_offset))?;
+        self.file.write_u16::<LittleEndian>(0)?; // comment length
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Passes bytes through while computing their CRC-32
+struct CrcWriter<'a, W: Write> {
+    inner: &'a mut W,
+    crc: Crc32,
+    written: u64,
+}
+
+impl<W: Write> Write for CrcWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// CRC-32 (IEEE 802.3), as used by ZIP
+struct Crc32(u32);
+
+/// Lookup table for the reflected polynomial 0xEDB88320
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+impl Crc32 {
+    fn new() -> Crc32 {
+        Crc32(0xFFFF_FFFF)
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = CRC_TABLE[((self.0 ^ u32::from(byte)) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+/// Value of a 32-bit size or offset field, which ZIP64 archives set to all ones
+fn field32(value: u64) -> u32 {
+    if value >= ZIP64_LIMIT {
+        u32::MAX
+    } else {
+        value as u32
+    }
+}
+
+/// MS-DOS time of day, with two-second resolution
+fn dos_time(time: &UtcDateTime) -> u16 {
+    ((time.hour << 11) | (time.minute << 5) | (time.second / 2)) as u16
+}
+
+/// MS-DOS date, counting years from 1980
+fn dos_date(time: &UtcDateTime) -> u16 {
+    let year = (time.year - 1980).clamp(0, 127) as u32;
+    ((year << 9) | (time.month << 5) | time.day) as u16
+}
@@ -0,0 +1,278 @@
+//! Opening files for reading with the I/O tuning of [`IoOptions`].
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use log::debug;
+
+use crate::loader::{IoOptions, ReadAhead};
+
+/// A file opened for reading with the I/O tuning of
+/// [`LoadOptions::io`](crate::LoadOptions::io).
+///
+/// Reads are buffered, or go through an aligned buffer with `O_DIRECT` for
+/// [`IoOptions::unbuffered`].
+pub struct FileReader {
+    inner: Inner,
+}
+
+enum Inner {
+    Buffered(BufReader<AdvisedFile>),
+    #[cfg(target_os = "linux")]
+    Direct(direct::DirectReader),
+}
+
+/// Opens a file for reading with the given tuning, returning it with its size
+pub(crate) fn open(path: &Path, io_options: &IoOptions) -> io::Result<(FileReader, u64)> {
+    #[cfg(target_os = "linux")]
+    if io_options.unbuffered {
+        match direct::DirectReader::open(path, io_options.buffer_size) {
+            Ok(reader) => {
+                let file_size = reader.file_size();
+                let inner = Inner::Direct(reader);
+                return Ok((FileReader { inner }, file_size));
+            }
+            // EINVAL: the filesystem does not support O_DIRECT
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                debug!(
+                    "{} does not support O_DIRECT, reading it buffered",
+                    path.display()
+                );
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let file = AdvisedFile::new(file, io_options.read_ahead);
+    let inner = Inner::Buffered(BufReader::with_capacity(io_options.buffer_size, file));
+    Ok((FileReader { inner }, file_size))
+}
+
+impl Read for FileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            Inner::Buffered(reader) => reader.read(buf),
+            #[cfg(target_os = "linux")]
+            Inner::Direct(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl Seek for FileReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match &mut self.inner {
+            Inner::Buffered(reader) => reader.seek(pos),
+            #[cfg(target_os = "linux")]
+            Inner::Direct(reader) => reader.seek(pos),
+        }
+    }
+}
+
+/// A file that passes its read-ahead setting on to the operating system
+struct AdvisedFile {
+    file: File,
+    /// Bytes to keep prefetched past the position, for [`ReadAhead::Bytes`]
+    window: u64,
+    position: u64,
+    /// End of the range last requested for prefetching
+    advised_end: u64,
+}
+
+impl AdvisedFile {
+    fn new(file: File, read_ahead: ReadAhead) -> Self {
+        let window = match read_ahead {
+            ReadAhead::Auto => 0,
+            ReadAhead::Sequential => {
+                advise(&file, 0, 0, Advice::Sequential);
+                0
+            }
+            ReadAhead::Bytes(window) => window,
+            ReadAhead::Off => {
+                advise(&file, 0, 0, Advice::Random);
+                0
+            }
+        };
+        AdvisedFile {
+            file,
+            window,
+            position: 0,
+            advised_end: 0,
+        }
+    }
+}
+
+impl Read for AdvisedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Renew the request once half of the prefetched range has been read
+        if self.window > 0 && self.position + self.window / 2 >= self.advised_end {
+            advise(&self.file, self.position, self.window, Advice::WillNeed);
+            self.advised_end = self.position + self.window;
+        }
+        let n = self.file.read(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for AdvisedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.file.seek(pos)?;
+        if self.position < self.advised_end.saturating_sub(self.window) {
+            // Seeking backwards leaves the prefetched range behind
+            self.advised_end = 0;
+        }
+        Ok(self.position)
+    }
+}
+
+enum Advice {
+    Sequential,
+    Random,
+    WillNeed,
+}
+
+/// Passes an access pattern hint for `len` bytes from `offset` (0 for the rest of
+/// the file) to the operating system
+#[cfg(target_os = "linux")]
+fn advise(file: &File, offset: u64, len: u64, advice: Advice) {
+    use std::os::unix::io::AsRawFd;
+
+    let advice = match advice {
+        Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+        Advice::Random => libc::POSIX_FADV_RANDOM,
+        Advice::WillNeed => libc::POSIX_FADV_WILLNEED,
+    };
+    let len = len.min(i64::MAX as u64) as libc::off_t;
+    // SAFETY: posix_fadvise only reads its integer arguments; the descriptor is
+    // owned by `file` and stays open for the duration of the call
+    let result =
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), offset as libc::off_t, len, advice) };
+    if result != 0 {
+        debug!("posix_fadvise failed with error {}", result);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn advise(_file: &File, _offset: u64, _len: u64, _advice: Advice) {}
+
+#[cfg(target_os = "linux")]
+mod direct {
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Read, Seek, SeekFrom};
+    use std::os::unix::fs::{FileExt, OpenOptionsExt};
+    use std::path::Path;
+
+    /// Alignment of the offsets, lengths, and buffer addresses of `O_DIRECT` reads
+    const ALIGNMENT: usize = 4096;
+
+    /// Reader of a file opened with `O_DIRECT`
+    ///
+    /// Reads whole aligned pages into an aligned buffer and serves the bytes at the
+    /// current position from it, so callers can read and seek as usual.
+    pub(super) struct DirectReader {
+        file: File,
+        file_size: u64,
+        /// Allocation holding the buffer, with room to align its start
+        storage: Vec<u8>,
+        /// Offset of the aligned buffer within `storage`
+        start: usize,
+        capacity: usize,
+        /// File offset of the first buffered byte
+        buffer_offset: u64,
+        /// Number of valid bytes in the buffer
+        buffer_len: usize,
+        position: u64,
+    }
+
+    impl DirectReader {
+        pub(super) fn open(path: &Path, buffer_size: usize) -> io::Result<Self> {
+            let file = OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_DIRECT)
+                .open(path)?;
+            let file_size = file.metadata()?.len();
+
+            let capacity = buffer_size.max(1).div_ceil(ALIGNMENT) * ALIGNMENT;
+            let storage = vec![0u8; capacity + ALIGNMENT];
+            let start = storage.as_ptr().align_offset(ALIGNMENT);
+            let mut reader = DirectReader {
+                file,
+                file_size,
+                storage,
+                start,
+                capacity,
+                buffer_offset: 0,
+                buffer_len: 0,
+                position: 0,
+            };
+            // Some filesystems accept O_DIRECT when opening but reject the reads
+            reader.fill()?;
+            Ok(reader)
+        }
+
+        pub(super) fn file_size(&self) -> u64 {
+            self.file_size
+        }
+
+        /// Reads the aligned pages holding the current position into the buffer
+        fn fill(&mut self) -> io::Result<()> {
+            self.buffer_offset = self.position / ALIGNMENT as u64 * ALIGNMENT as u64;
+            self.buffer_len = 0;
+            let buffer = &mut self.storage[self.start..self.start + self.capacity];
+            while self.buffer_len < buffer.len() {
+                let offset = self.buffer_offset + self.buffer_len as u64;
+                match self.file.read_at(&mut buffer[self.buffer_len..], offset) {
+                    Ok(0) => break,
+                    Ok(n) => self.buffer_len += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+                // A short read that is not page-aligned means the end of the file
+                if !self.buffer_len.is_multiple_of(ALIGNMENT) {
+                    break;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Read for DirectReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let buffer_end = self.buffer_offset + self.buffer_len as u64;
+            if self.position < self.buffer_offset || self.position >= buffer_end {
+                if self.position >= self.file_size {
+                    return Ok(0);
+                }
+                self.fill()?;
+            }
+
+            let skip = (self.position - self.buffer_offset) as usize;
+            let available = self.buffer_len.saturating_sub(skip);
+            let n = available.min(buf.len());
+            let buffered = &self.storage[self.start + skip..self.start + skip + n];
+            buf[..n].copy_from_slice(buffered);
+            self.position += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl Seek for DirectReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            let position = match pos {
+                SeekFrom::Start(offset) => Some(offset),
+                SeekFrom::End(offset) => self.file_size.checked_add_signed(offset),
+                SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            };
+            self.position = position.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "invalid seek to a negative position",
+                )
+            })?;
+            Ok(self.position)
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Content hashes of loaded files, for provenance.
+//!
+//! With [`Loader::fingerprint`](crate::Loader::fingerprint), every file a load
+//! reads is hashed with BLAKE3 and listed in
+//! [`RhsFile::fingerprints`](crate::RhsFile::fingerprints), in the order the files
+//! were read: that of [`source_files`](crate::RhsFile::source_files) for a
+//! directory of RHS files, and name order for the `.dat` files of the other
+//! directory formats. Recording the fingerprints next to exported derivatives
+//! traces them back to the exact raw files they came from, and
+//! [`FileFingerprint::verify`] checks later that a file has not changed.
+//!
+//! Hashing needs the `fingerprint` feature. Each file is read once more from start
+//! to end, whatever channels and time range the load selects, so that the hash
+//! covers the file as it is stored.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::Loader;
+//!
+//! let recording = Loader::new("session_dir/").fingerprint(true).load().unwrap();
+//! for fingerprint in &recording.fingerprints {
+//!     println!("{}  {}", fingerprint.blake3, fingerprint.path);
+//! }
+//! ```
+
+use std::path::Path;
+
+use crate::types::IntanError;
+
+/// Content hash of a file, as listed in [`RhsFile::fingerprints`](crate::RhsFile::fingerprints).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileFingerprint {
+    /// Path of the file
+    pub path: String,
+    /// Size of the file in bytes
+    pub size: u64,
+    /// BLAKE3 hash of the file's contents, as 64 lowercase hex digits, as printed
+    /// by `b3sum`
+    pub blake3: String,
+}
+
+impl FileFingerprint {
+    /// Hashes the file at [`path`](FileFingerprint::path) again and returns
+    /// whether its contents are unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`fingerprint_file`].
+    pub fn verify(&self) -> Result<bool, IntanError> {
+        Ok(fingerprint_file(&self.path)? == *self)
+    }
+}
+
+/// Hashes the contents of the file at `path`.
+///
+/// # Errors
+///
+/// Returns an error naming the file if it cannot be read, and an
+/// [`IntanError::InvalidOptions`] without the `fingerprint` feature.
+pub fn fingerprint_file<P: AsRef<Path>>(path: P) -> Result<FileFingerprint, IntanError> {
+    let path = path.as_ref();
+    #[cfg(feature = "fingerprint")]
+    return hash_file(path).map_err(|e| IntanError::from(e).in_file(path, None));
+    #[cfg(not(feature = "fingerprint"))]
+    return Err(IntanError::InvalidOptions(format!(
+        "Fingerprinting {} requires the `fingerprint` feature",
+        path.display()
+    )));
+}
+
+/// Hashes the file at `path` with BLAKE3
+#[cfg(feature = "fingerprint")]
+fn hash_file(path: &Path) -> std::io::Result<FileFingerprint> {
+    let mut hasher = blake3::Hasher::new();
+    let size = hasher.update_reader(std::fs::File::open(path)?)?.count();
+    Ok(FileFingerprint {
+        path: path.to_string_lossy().to_string(),
+        size,
+        blake3: hasher.finalize().to_hex().to_string(),
+    })
+}
+
+/// Hashes the files of a recording saved as one file per signal type or per
+/// channel: its `info.rhs` and `.dat` files, in name order
+pub(crate) fn fingerprint_dat_directory(dir: &Path) -> Result<Vec<FileFingerprint>, IntanError> {
+    let mut paths = Vec::new();
+    for entry in dir
+        .read_dir()
+        .map_err(|e| IntanError::from(e).in_file(dir, None))?
+    {
+        let path = entry
+            .map_err(|e| IntanError::from(e).in_file(dir, None))?
+            .path();
+        let is_dat = path.extension().is_some_and(|ext| ext == "dat");
+        if path.is_file() && (is_dat || path.file_name().is_some_and(|name| name == "info.rhs")) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths.iter().map(fingerprint_file).collect()
+}
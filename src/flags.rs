@@ -0,0 +1,331 @@
+//! Compact storage for the stimulation status flags.
+//!
+//! Each stimulation sample carries three status bits: compliance limit, charge
+//! recovery, and amplifier settle. Stored as `Array2<bool>`, each flag would take
+//! one byte per channel and sample, although for most recordings it is set for a
+//! tiny fraction of the samples. [`StimFlags`] instead keeps, for every channel,
+//! the sorted list of sample ranges where the flag is set, so memory grows with
+//! the number of stimulation events rather than the length of the recording.
+//!
+//! Flags can be queried sample by sample, as ranges, or as counts, and expanded to
+//! a full `Array2<bool>` on demand with [`StimFlags::to_array`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! let data = rhs_file.data.as_ref().unwrap();
+//!
+//! if let Some(compliance) = &data.compliance_limit_data {
+//!     for channel in 0..compliance.num_channels() {
+//!         for run in compliance.runs(channel) {
+//!             println!("channel {}: compliance limit over samples {:?}", channel, run);
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::error::Error;
+use std::ops::Range;
+
+use ndarray::{s, Array1, Array2, ArrayView2};
+
+use crate::types::IntanError;
+
+/// A boolean flag for every channel and sample, stored as runs of set samples.
+///
+/// Mirrors an `Array2<bool>` of shape [num_channels, num_samples].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StimFlags {
+    num_samples: usize,
+    /// Sorted, disjoint, non-adjacent ranges of set samples for each channel
+    runs: Vec<Vec<Range<usize>>>,
+}
+
+impl StimFlags {
+    /// Creates flags that are clear for every channel and sample.
+    pub fn new(num_channels: usize, num_samples: usize) -> Self {
+        StimFlags {
+            num_samples,
+            runs: vec![Vec::new(); num_channels],
+        }
+    }
+
+    /// Creates flags from a [channels, samples] array.
+    pub fn from_array(values: ArrayView2<'_, bool>) -> Self {
+        StimFlags {
+            num_samples: values.ncols(),
+            runs: values
+                .rows()
+                .into_iter()
+                .map(|row| {
+                    let mut runs = RunBuilder::default();
+                    for (sample, &value) in row.iter().enumerate() {
+                        runs.push(sample, value);
+                    }
+                    runs.finish(row.len())
+                })
+                .collect(),
+        }
+    }
+
+    /// Creates flags from the runs of each channel, as collected by [`RunBuilder`].
+    pub(crate) fn from_runs(num_samples: usize, runs: Vec<Vec<Range<usize>>>) -> Self {
+        StimFlags { num_samples, runs }
+    }
+
+    /// Returns the number of channels.
+    pub fn num_channels(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Returns the number of samples.
+    pub fn num_samples(&self) -> usize {
+        self.num_samples
+    }
+
+    /// Returns the shape as (num_channels, num_samples).
+    pub fn dim(&self) -> (usize, usize) {
+        (self.num_channels(), self.num_samples)
+    }
+
+    /// Returns the flag of one sample, or `None` if the index is out of bounds.
+    pub fn get(&self, channel: usize, sample: usize) -> Option<bool> {
+        self.view().get(channel, sample)
+    }
+
+    /// Returns the ranges of samples where the flag is set on `channel`, in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of bounds.
+    pub fn runs(&self, channel: usize) -> &[Range<usize>] {
+        &self.runs[channel]
+    }
+
+    /// Returns the number of samples where the flag is set on `channel`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of bounds.
+    pub fn count(&self, channel: usize) -> usize {
+        self.runs[channel].iter().map(Range::len).sum()
+    }
+
+    /// Returns true if the flag is set anywhere.
+    pub fn any(&self) -> bool {
+        self.runs.iter().any(|runs| !runs.is_empty())
+    }
+
+    /// Expands one channel into a boolean array.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of bounds.
+    pub fn channel(&self, channel: usize) -> Array1<bool> {
+        let mut values = Array1::from_elem(self.num_samples, false);
+        for run in &self.runs[channel] {
+            values.slice_mut(s![run.clone()]).fill(true);
+        }
+        values
+    }
+
+    /// Expands the flags into a [channels, samples] boolean array.
+    pub fn to_array(&self) -> Array2<bool> {
+        self.view().to_array()
+    }
+
+    /// Returns the flags of the given channels, in the given order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a channel is out of bounds.
+    pub fn select(&self, channels: &[usize]) -> StimFlags {
+        StimFlags {
+            num_samples: self.num_samples,
+            runs: channels.iter().map(|&channel| self.runs[channel].clone()).collect(),
+        }
+    }
+
+    /// Returns a borrowed view of all samples.
+    pub fn view(&self) -> StimFlagsView<'_> {
+        StimFlagsView {
+            flags: self,
+            samples: (0, self.num_samples),
+        }
+    }
+
+    /// Appends the samples of `other` after the samples of `self`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the channel counts differ.
+    pub fn append(&mut self, other: StimFlagsView<'_>) -> Result<(), Box<dyn Error>> {
+        if other.num_channels() != self.num_channels() {
+            return Err(Box::new(IntanError::Other(format!(
+                "Cannot append stimulation flags of {} channels to flags of {} channels",
+                other.num_channels(),
+                self.num_channels()
+            ))));
+        }
+
+        let offset = self.num_samples;
+        for (channel, runs) in self.runs.iter_mut().enumerate() {
+            for run in other.runs(channel) {
+                let run = run.start + offset..run.end + offset;
+                match runs.last_mut() {
+                    Some(last) if last.end == run.start => last.end = run.end,
+                    _ => runs.push(run),
+                }
+            }
+        }
+        self.num_samples += other.num_samples();
+        Ok(())
+    }
+
+    /// Concatenates views along the sample axis.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the channel counts differ.
+    pub fn concat(views: &[StimFlagsView<'_>]) -> Result<StimFlags, Box<dyn Error>> {
+        let mut flags = StimFlags::new(views.first().map_or(0, StimFlagsView::num_channels), 0);
+        for view in views {
+            flags.append(*view)?;
+        }
+        Ok(flags)
+    }
+}
+
+/// Borrowed view of a range of samples of [`StimFlags`].
+#[derive(Debug, Clone, Copy)]
+pub struct StimFlagsView<'a> {
+    flags: &'a StimFlags,
+    /// Viewed samples of `flags`
+    samples: (usize, usize),
+}
+
+impl<'a> StimFlagsView<'a> {
+    /// Returns the number of channels.
+    pub fn num_channels(&self) -> usize {
+        self.flags.num_channels()
+    }
+
+    /// Returns the number of samples in the view.
+    pub fn num_samples(&self) -> usize {
+        self.samples.1 - self.samples.0
+    }
+
+    /// Returns the shape as (num_channels, num_samples).
+    pub fn dim(&self) -> (usize, usize) {
+        (self.num_channels(), self.num_samples())
+    }
+
+    /// Restricts the view to the sample range `start..end` (relative to this view).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn slice(&self, start: usize, end: usize) -> StimFlagsView<'a> {
+        assert!(
+            start <= end && end <= self.num_samples(),
+            "sample range {}..{} out of bounds for {} samples",
+            start,
+            end,
+            self.num_samples()
+        );
+        StimFlagsView {
+            flags: self.flags,
+            samples: (self.samples.0 + start, self.samples.0 + end),
+        }
+    }
+
+    /// Returns the flag of one sample, or `None` if the index is out of bounds.
+    pub fn get(&self, channel: usize, sample: usize) -> Option<bool> {
+        if sample >= self.num_samples() {
+            return None;
+        }
+        let runs = self.flags.runs.get(channel)?;
+        let sample = self.samples.0 + sample;
+        let index = runs.partition_point(|run| run.end <= sample);
+        Some(runs.get(index).is_some_and(|run| run.start <= sample))
+    }
+
+    /// Returns the ranges of samples where the flag is set on `channel`, relative
+    /// to the view and clipped to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of bounds.
+    pub fn runs(&self, channel: usize) -> impl Iterator<Item = Range<usize>> + 'a {
+        let (start, end) = self.samples;
+        let runs = &self.flags.runs[channel];
+        let first = runs.partition_point(|run| run.end <= start);
+        runs[first..]
+            .iter()
+            .take_while(move |run| run.start < end)
+            .map(move |run| run.start.max(start) - start..run.end.min(end) - start)
+    }
+
+    /// Returns the number of samples in the view where the flag is set on `channel`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of bounds.
+    pub fn count(&self, channel: usize) -> usize {
+        self.runs(channel).map(|run| run.len()).sum()
+    }
+
+    /// Expands the view into a [channels, samples] boolean array.
+    pub fn to_array(&self) -> Array2<bool> {
+        let mut values = Array2::from_elem(self.dim(), false);
+        for (channel, mut row) in values.rows_mut().into_iter().enumerate() {
+            for run in self.runs(channel) {
+                row.slice_mut(s![run]).fill(true);
+            }
+        }
+        values
+    }
+
+    /// Copies the viewed samples into new, owned flags.
+    pub fn to_owned(&self) -> StimFlags {
+        StimFlags {
+            num_samples: self.num_samples(),
+            runs: (0..self.num_channels())
+                .map(|channel| self.runs(channel).collect())
+                .collect(),
+        }
+    }
+}
+
+/// Collects the runs of set samples of one channel, one sample at a time.
+#[derive(Debug, Default)]
+pub(crate) struct RunBuilder {
+    runs: Vec<Range<usize>>,
+    start: Option<usize>,
+}
+
+impl RunBuilder {
+    /// Records the flag of `sample`; samples must be pushed in increasing order
+    /// without gaps.
+    pub(crate) fn push(&mut self, sample: usize, value: bool) {
+        match (value, self.start) {
+            (true, None) => self.start = Some(sample),
+            (false, Some(start)) => {
+                self.runs.push(start..sample);
+                self.start = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Closes the last run at `num_samples` and returns the runs.
+    pub(crate) fn finish(mut self, num_samples: usize) -> Vec<Range<usize>> {
+        if let Some(start) = self.start {
+            self.runs.push(start..num_samples);
+        }
+        self.runs
+    }
+}
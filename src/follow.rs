@@ -0,0 +1,409 @@
+//! Following RHS files while they are being recorded.
+//!
+//! During acquisition, RHX appends a data block to the active .rhs file every 128
+//! samples, and when it is set to start a new file every few minutes, it closes the
+//! file and continues the session in a new one in the same directory.
+//! [`RhsFollower`] watches the active file, yields each data block once it has been
+//! written in full, and moves on to the next file of the session when RHX rolls
+//! over. Blocks are processed as by [`RhsBlockReader`](crate::stream::RhsBlockReader),
+//! and the notch filter and the block and sample counts carry on across files.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use intan_importer::follow::{FollowOptions, RhsFollower};
+//! use intan_importer::LoadOptions;
+//!
+//! // Stop once no data has arrived for 10 seconds, after the recording has ended
+//! let follow_options = FollowOptions {
+//!     idle_timeout: Some(Duration::from_secs(10)),
+//!     ..FollowOptions::default()
+//! };
+//! let follower =
+//!     RhsFollower::with_options("session/", &LoadOptions::default(), &follow_options).unwrap();
+//! for block in follower {
+//!     let block = block.unwrap();
+//!     println!("{} new samples", block.data.num_samples());
+//! }
+//! ```
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Seek};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+
+use crate::loader::{LoadOptions, NotchMode};
+use crate::reader::{self, BlockNotchFilter, ChannelRows};
+use crate::stream::DataBlock;
+use crate::types::{IntanError, RhsHeader};
+
+/// How an [`RhsFollower`] waits for new data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FollowOptions {
+    /// Time to wait between checks for new data blocks.
+    ///
+    /// Defaults to 100 ms, a little over 3 data blocks at 30 kHz.
+    pub poll_interval: Duration,
+    /// Time without a new data block after which iteration ends, or `None` to
+    /// wait for new data indefinitely.
+    pub idle_timeout: Option<Duration>,
+    /// Whether to start at the end of the file, yielding only blocks written after
+    /// the follower was opened, instead of at its first data block.
+    pub skip_existing: bool,
+}
+
+impl Default for FollowOptions {
+    fn default() -> Self {
+        FollowOptions {
+            poll_interval: Duration::from_millis(100),
+            idle_timeout: None,
+            skip_existing: false,
+        }
+    }
+}
+
+/// Reader that follows an RHS file as it is recorded, continuing into the next
+/// file of the session when RHX starts one.
+///
+/// Iterating waits for each new data block, sleeping between checks, until the
+/// [idle timeout](FollowOptions::idle_timeout) passes. [`RhsFollower::poll`]
+/// checks for a block without waiting.
+///
+/// See the [module documentation](crate::follow) for an overview.
+pub struct RhsFollower {
+    file: FollowedFile,
+    /// Header listing only the selected channels
+    header: RhsHeader,
+    rows: Option<ChannelRows>,
+    /// Options used to process each block, with printing, progress reports, and
+    /// the whole-signal notch filter turned off
+    block_options: LoadOptions,
+    buffer_size: usize,
+    verbose: bool,
+    follow_options: FollowOptions,
+    notch: Option<BlockNotchFilter>,
+    index: usize,
+    sample_offset: usize,
+    /// Last timestamp yielded, from which the next block's timestamps are unwrapped
+    last_timestamp: Option<i64>,
+    failed: bool,
+}
+
+/// An RHS file of the session, opened at its first data block
+struct FollowedFile {
+    path: PathBuf,
+    reader: BufReader<File>,
+    /// Header as stored in the file, describing the layout of the data blocks
+    header: RhsHeader,
+    /// Offset of the first data block
+    data_start: u64,
+    bytes_per_block: u64,
+    /// Data blocks of this file read so far
+    blocks_read: u64,
+}
+
+impl FollowedFile {
+    fn open(path: PathBuf, buffer_size: usize) -> Result<FollowedFile, IntanError> {
+        let file = File::open(&path).map_err(|e| IntanError::from(e).in_file(&path, None))?;
+        let mut reader = BufReader::with_capacity(buffer_size, file);
+        let (header, data_start, bytes_per_block) = FollowedFile::read_start(&mut reader)
+            .map_err(|e| reader::file_error(&path, &mut reader, e))?;
+        Ok(FollowedFile {
+            path,
+            reader,
+            header,
+            data_start,
+            bytes_per_block,
+            blocks_read: 0,
+        })
+    }
+
+    /// Reads the header, returning it with the offset of the first data block and
+    /// the size of the blocks
+    fn read_start(reader: &mut BufReader<File>) -> Result<(RhsHeader, u64, u64), Box<dyn Error>> {
+        let header = reader::read_header(reader)?;
+        let data_start = reader.stream_position()?;
+        let bytes_per_block = reader::get_bytes_per_data_block(&header)? as u64;
+        Ok((header, data_start, bytes_per_block))
+    }
+
+    /// Returns the number of data blocks written in full so far, and the bytes
+    /// written of the block after them
+    fn written_blocks(&self) -> Result<(u64, u64), Box<dyn Error>> {
+        let data_size = self
+            .reader
+            .get_ref()
+            .metadata()?
+            .len()
+            .saturating_sub(self.data_start);
+        Ok((
+            data_size / self.bytes_per_block,
+            data_size % self.bytes_per_block,
+        ))
+    }
+
+    fn has_new_block(&self) -> Result<bool, Box<dyn Error>> {
+        Ok(self.written_blocks()?.0 > self.blocks_read)
+    }
+}
+
+impl RhsFollower {
+    /// Follows an RHS file with the default options.
+    ///
+    /// `path` is either the file being recorded or the session directory, in which
+    /// case the last .rhs file in recording order is followed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened or its header has not been
+    /// written yet or is invalid.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, IntanError> {
+        RhsFollower::with_options(path, &LoadOptions::default(), &FollowOptions::default())
+    }
+
+    /// Follows an RHS file with the given options.
+    ///
+    /// The channel selection, notch mode, data type, and buffer size of `options`
+    /// apply as for [`RhsBlockReader`](crate::stream::RhsBlockReader). Progress is
+    /// not reported, since the length of the recording is not known.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`RhsFollower::open`], and an error if a selected
+    /// channel does not exist or if `options` sets a time range or wall-clock
+    /// range, which cannot be applied to a recording still in progress.
+    pub fn with_options<P: AsRef<Path>>(
+        path: P,
+        options: &LoadOptions,
+        follow_options: &FollowOptions,
+    ) -> Result<Self, IntanError> {
+        options.check()?;
+        if options.time_range.is_some() || options.wallclock_range.is_some() {
+            return Err(IntanError::InvalidOptions(
+                "A time range cannot be applied when following a recording".to_string(),
+            ));
+        }
+
+        let path = path.as_ref();
+        let path = if path.is_dir() {
+            // The session's active file is its last, and find_matching_rhs_files
+            // never returns an empty list
+            let mut rhs_files =
+                crate::find_matching_rhs_files(path, options.file_pattern.as_deref())?;
+            rhs_files.swap_remove(rhs_files.len() - 1)
+        } else {
+            path.to_path_buf()
+        };
+        let mut file = FollowedFile::open(path, options.io.buffer_size)?;
+        if options.verbose {
+            info!("Following {}", file.path.display());
+            reader::log_header_summary(&file.header);
+        }
+        let mut last_timestamp = None;
+        if follow_options.skip_existing {
+            file.blocks_read = file.written_blocks()?.0;
+            last_timestamp =
+                reader::skip_to_block(&mut file.reader, &file.header, file.blocks_read)?;
+        }
+
+        let mut header = file.header.clone();
+        let rows = reader::select_channels(&mut header, options)?;
+        let notch = BlockNotchFilter::new(&header, options)?;
+
+        Ok(RhsFollower {
+            file,
+            header,
+            rows,
+            block_options: LoadOptions {
+                verbose: false,
+                notch: NotchMode::Skip,
+                progress: None,
+                ..options.clone()
+            },
+            buffer_size: options.io.buffer_size,
+            verbose: options.verbose,
+            follow_options: follow_options.clone(),
+            notch,
+            index: 0,
+            sample_offset: 0,
+            last_timestamp,
+            failed: false,
+        })
+    }
+
+    /// Returns the header of the file being followed, listing only the selected
+    /// channels.
+    pub fn header(&self) -> &RhsHeader {
+        &self.header
+    }
+
+    /// Returns the path of the file being followed.
+    pub fn current_path(&self) -> &Path {
+        &self.file.path
+    }
+
+    /// Returns the next data block if it has been written in full, without
+    /// waiting.
+    ///
+    /// When the file being followed has no new block and a later .rhs file with a
+    /// complete header has appeared in its directory, the follower switches to
+    /// that file. Once an error has been returned, no further blocks are read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a data block cannot be read, or if the next file of the
+    /// session has a different sample rate or channel count.
+    pub fn poll(&mut self) -> Result<Option<DataBlock>, IntanError> {
+        if self.failed {
+            return Ok(None);
+        }
+        let result = self.poll_file();
+        // The position within the file is unknown after an error
+        self.failed = result.is_err();
+        result
+    }
+
+    fn poll_file(&mut self) -> Result<Option<DataBlock>, IntanError> {
+        if self.file.has_new_block()? {
+            return self.read_block().map(Some);
+        }
+
+        let Some(next_file) = self.next_file()? else {
+            return Ok(None);
+        };
+        // RHX may have finished the last blocks of the file since the check above
+        if self.file.has_new_block()? {
+            return self.read_block().map(Some);
+        }
+        self.switch_to(next_file)?;
+
+        if self.file.has_new_block()? {
+            return self.read_block().map(Some);
+        }
+        Ok(None)
+    }
+
+    /// Opens the .rhs file that follows the current one in recording order in its
+    /// directory, among those matching the file pattern, if one exists and its
+    /// header has been written in full
+    fn next_file(&self) -> Result<Option<FollowedFile>, Box<dyn Error>> {
+        let dir_path = match self.file.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let file_name = self.file.path.file_name();
+        let pattern = self.block_options.file_pattern.as_deref();
+        let rhs_files = crate::find_matching_rhs_files(dir_path, pattern)?;
+        let Some(path) = rhs_files
+            .iter()
+            .position(|path| path.file_name() == file_name)
+            .and_then(|current| rhs_files.get(current + 1))
+            .cloned()
+        else {
+            return Ok(None);
+        };
+
+        match FollowedFile::open(path, self.buffer_size) {
+            Ok(file) => Ok(Some(file)),
+            Err(e) => {
+                // RHX may still be writing the header
+                debug!("Header of the next file is not readable yet: {}", e);
+                Ok(None)
+            }
+        }
+    }
+
+    fn switch_to(&mut self, next_file: FollowedFile) -> Result<(), Box<dyn Error>> {
+        let (_, partial_bytes) = self.file.written_blocks()?;
+        if partial_bytes > 0 {
+            warn!(
+                "{} ends with {} bytes of an incomplete data block, which are skipped",
+                self.file.path.display(),
+                partial_bytes
+            );
+        }
+        reader::verify_header_compatibility(
+            &self.file.header,
+            &next_file.header,
+            &next_file.path,
+            self.block_options.header_compatibility,
+        )
+        .map_err(|e| e.in_file(&next_file.path, None))?;
+
+        let mut header = next_file.header.clone();
+        self.rows = reader::select_channels(&mut header, &self.block_options)?;
+        self.header = header;
+        self.file = next_file;
+        if self.verbose {
+            info!("Following {}", self.file.path.display());
+        }
+        Ok(())
+    }
+
+    /// Reads the next data block, naming the file and the position reached in
+    /// errors
+    fn read_block(&mut self) -> Result<DataBlock, IntanError> {
+        self.read_next_block()
+            .map_err(|e| reader::file_error(&self.file.path, &mut self.file.reader, e))
+    }
+
+    fn read_next_block(&mut self) -> Result<DataBlock, Box<dyn Error>> {
+        let raw_data = reader::read_data_blocks(
+            &self.file.header,
+            self.rows.as_ref(),
+            1,
+            &self.block_options,
+            &mut self.file.reader,
+        )?;
+        self.file.blocks_read += 1;
+
+        let mut data = reader::process_data(&self.header, raw_data, &self.block_options)?;
+        reader::unwrap_timestamps(data.timestamps.view_mut(), self.last_timestamp);
+        self.last_timestamp = data.timestamps.last().copied();
+        if let Some(notch) = &mut self.notch {
+            if let Some(amp_data) = &mut data.amplifier_data {
+                notch.filter(amp_data);
+            }
+            if let Some(amp_data) = &mut data.amplifier_data_f32 {
+                notch.filter(amp_data);
+            }
+        }
+
+        let data_block = DataBlock {
+            index: self.index,
+            sample_offset: self.sample_offset,
+            data,
+        };
+        self.index += 1;
+        self.sample_offset += data_block.data.num_samples();
+        Ok(data_block)
+    }
+}
+
+impl Iterator for RhsFollower {
+    type Item = Result<DataBlock, IntanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let started = Instant::now();
+        while !self.failed {
+            match self.poll() {
+                Ok(Some(block)) => return Some(Ok(block)),
+                Ok(None) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            if let Some(idle_timeout) = self.follow_options.idle_timeout {
+                if started.elapsed() >= idle_timeout {
+                    return None;
+                }
+            }
+            thread::sleep(self.follow_options.poll_interval);
+        }
+        None
+    }
+}
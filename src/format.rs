@@ -0,0 +1,180 @@
+//! Detection of Intan recording formats.
+//!
+//! Intan software saves recordings in several layouts: traditional `.rhs`/`.rhd`
+//! files (optionally split into several files in one directory), and directories
+//! in the "one file per signal type" and "one file per channel" formats, which hold
+//! an `info.rhs`/`info.rhd` header next to raw `.dat` files. [`detect_format`]
+//! identifies which one a path contains by sniffing magic numbers and directory
+//! contents, and [`load`](crate::load) uses it to pick the right reader.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::format::{detect_format, RecordingFormat};
+//!
+//! match detect_format("session/").unwrap() {
+//!     RecordingFormat::RhsChannelDirectory => println!("one file per channel"),
+//!     format => println!("{}", format),
+//! }
+//! ```
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::path::Path;
+
+use crate::reader::RHS_MAGIC_NUMBER;
+use crate::rhd::RHD_MAGIC_NUMBER;
+use crate::types::IntanError;
+
+/// The layout of an Intan recording on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordingFormat {
+    /// A traditional RHS file
+    RhsFile,
+    /// A traditional RHD file
+    RhdFile,
+    /// A directory of traditional RHS files from one session
+    RhsFileDirectory,
+    /// A directory of traditional RHD files from one session
+    RhdFileDirectory,
+    /// An RHS recording saved as one file per signal type (`info.rhs`, `amplifier.dat`, ...)
+    RhsSignalTypeDirectory,
+    /// An RHD recording saved as one file per signal type (`info.rhd`, `amplifier.dat`, ...)
+    RhdSignalTypeDirectory,
+    /// An RHS recording saved as one file per channel (`info.rhs`, `amp-A-000.dat`, ...)
+    RhsChannelDirectory,
+    /// An RHD recording saved as one file per channel (`info.rhd`, `amp-A-000.dat`, ...)
+    RhdChannelDirectory,
+}
+
+impl RecordingFormat {
+    /// Returns true for recordings from an RHD2000 system.
+    pub fn is_rhd(&self) -> bool {
+        matches!(
+            self,
+            RecordingFormat::RhdFile
+                | RecordingFormat::RhdFileDirectory
+                | RecordingFormat::RhdSignalTypeDirectory
+                | RecordingFormat::RhdChannelDirectory
+        )
+    }
+}
+
+impl fmt::Display for RecordingFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            RecordingFormat::RhsFile => "traditional RHS file",
+            RecordingFormat::RhdFile => "traditional RHD file",
+            RecordingFormat::RhsFileDirectory => "directory of RHS files",
+            RecordingFormat::RhdFileDirectory => "directory of RHD files",
+            RecordingFormat::RhsSignalTypeDirectory => "RHS \"one file per signal type\" directory",
+            RecordingFormat::RhdSignalTypeDirectory => "RHD \"one file per signal type\" directory",
+            RecordingFormat::RhsChannelDirectory => "RHS \"one file per channel\" directory",
+            RecordingFormat::RhdChannelDirectory => "RHD \"one file per channel\" directory",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// Detects the format of the recording at `path`.
+///
+/// Files are identified by their magic number, regardless of extension.
+/// Directories are identified by an `info.rhs`/`info.rhd` header next to `time.dat`
+/// (with per-channel `amp-*.dat`/`board-*.dat` files for the "one file per channel"
+/// format), or otherwise by the `.rhs`/`.rhd` files they contain.
+///
+/// # Errors
+///
+/// Returns [`IntanError::UnrecognizedFileFormat`] for files that are not Intan
+/// recordings, and [`IntanError::NoFiles`] if a directory holds no recognizable
+/// recording.
+pub fn detect_format<P: AsRef<Path>>(path: P) -> Result<RecordingFormat, IntanError> {
+    let path = path.as_ref();
+
+    if path.is_file() {
+        let magic_number = File::open(path)?
+            .read_u32::<LittleEndian>()
+            .map_err(|_| IntanError::UnrecognizedFileFormat)?;
+        return match magic_number {
+            RHS_MAGIC_NUMBER => Ok(RecordingFormat::RhsFile),
+            RHD_MAGIC_NUMBER => Ok(RecordingFormat::RhdFile),
+            _ => Err(IntanError::UnrecognizedFileFormat),
+        };
+    }
+
+    if !path.is_dir() {
+        return Err(IntanError::Other(format!(
+            "Path '{}' is neither a file nor a directory",
+            path.display()
+        )));
+    }
+
+    if path.join("time.dat").is_file() {
+        let per_channel = has_per_channel_files(path)?;
+        if path.join("info.rhs").is_file() {
+            return Ok(if per_channel {
+                RecordingFormat::RhsChannelDirectory
+            } else {
+                RecordingFormat::RhsSignalTypeDirectory
+            });
+        }
+        if path.join("info.rhd").is_file() {
+            return Ok(if per_channel {
+                RecordingFormat::RhdChannelDirectory
+            } else {
+                RecordingFormat::RhdSignalTypeDirectory
+            });
+        }
+    }
+
+    if contains_extension(path, "rhs")? {
+        Ok(RecordingFormat::RhsFileDirectory)
+    } else if contains_extension(path, "rhd")? {
+        Ok(RecordingFormat::RhdFileDirectory)
+    } else {
+        Err(IntanError::NoFiles {
+            directory: Some(path.to_path_buf()),
+            pattern: None,
+        })
+    }
+}
+
+/// Returns true if the directory holds per-channel `amp-*.dat` or `board-*.dat` files
+fn has_per_channel_files(dir_path: &Path) -> Result<bool, Box<dyn Error>> {
+    for entry in fs::read_dir(dir_path)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if (name.starts_with("amp-") || name.starts_with("board-")) && name.ends_with(".dat") {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Returns true if the directory holds a file with the given extension (case-insensitive)
+fn contains_extension(dir_path: &Path, extension: &str) -> Result<bool, Box<dyn Error>> {
+    for entry in fs::read_dir(dir_path)? {
+        let path = entry?.path();
+        let matches = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.eq_ignore_ascii_case(extension))
+            .unwrap_or(false);
+        if matches && path.is_file() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Error for a detected format that the requested loader cannot read
+pub(crate) fn unsupported(format: RecordingFormat, path: &Path, hint: &str) -> IntanError {
+    IntanError::Other(format!(
+        "'{}' is a {}, which is not supported here; {}",
+        path.display(),
+        format,
+        hint
+    ))
+}
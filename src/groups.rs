@@ -0,0 +1,343 @@
+//! Groups of amplifier channels, by port or from a channel map.
+//!
+//! Many analyses run on sets of electrodes rather than on single channels: the
+//! channels of one headstage, the sites of one shank, or the four wires of a
+//! tetrode. [`RhsFile::groups`] groups the amplifier channels by the port they were
+//! recorded on, and [`RhsFile::groups_from_map`] by a [`ChannelMap`] read from a
+//! file. Each [`ChannelGroup`] gives access to the channels and amplifier data of
+//! its members.
+//!
+//! A channel map file is either JSON, with one array of native channel names per
+//! group:
+//!
+//! ```text
+//! {
+//!     "tetrode 1": ["A-000", "A-001", "A-002", "A-003"],
+//!     "tetrode 2": ["A-004", "A-005", "A-006", "A-007"]
+//! }
+//! ```
+//!
+//! or text with one line per channel holding the native channel name and the
+//! group, separated by commas, tabs, or spaces. Blank lines, lines starting with
+//! `#`, and a header line starting with `channel` are ignored:
+//!
+//! ```text
+//! channel,group
+//! A-000,shank0
+//! A-001,shank0
+//! A-002,shank1
+//! ```
+//!
+//! Files ending in `.json` are read as JSON, and all others as text. Groups keep the
+//! order in which they first appear, and their channels the order in which they
+//! are listed.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::{load, ChannelMap};
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! for group in rhs_file.groups() {
+//!     println!("Port {}: {} channels", group.name, group.num_channels());
+//! }
+//!
+//! let map = ChannelMap::from_file("tetrodes.json").unwrap();
+//! for group in rhs_file.groups_from_map(&map).unwrap() {
+//!     if let Some(data) = group.amplifier_data() {
+//!         println!("{}: {:?}", group.name, data.dim());
+//!     }
+//! }
+//! ```
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use ndarray::{Array2, Axis, CowArray, Ix1, Ix2, Slice};
+
+use crate::json::Json;
+use crate::reader::ChannelRows;
+use crate::types::{ChannelInfo, IntanError, RhsFile};
+
+/// Named groups of amplifier channels, such as the shanks or tetrodes of a probe.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChannelMap {
+    /// One entry per group
+    pub groups: Vec<ChannelMapGroup>,
+}
+
+/// One group of a [`ChannelMap`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelMapGroup {
+    /// Name of the group
+    pub name: String,
+    /// Native names of the group's amplifier channels (e.g. "A-000")
+    pub channels: Vec<String>,
+}
+
+impl ChannelMap {
+    /// Reads a channel map file, as JSON if its name ends in `.json` and as text
+    /// otherwise.
+    ///
+    /// See the [module documentation](crate::groups) for the file formats.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a valid channel map.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ChannelMap, Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let is_json = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        if is_json {
+            ChannelMap::parse_json(&text)
+        } else {
+            ChannelMap::parse_text(&text)
+        }
+    }
+
+    /// Parses a channel map in the JSON format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the text is not a JSON object of arrays of channel
+    /// names.
+    pub fn parse_json(text: &str) -> Result<ChannelMap, Box<dyn Error>> {
+        let invalid = || {
+            IntanError::Other(
+                "A JSON channel map must map group names to arrays of channel names".to_string(),
+            )
+        };
+        let json = Json::parse(text)
+            .map_err(|e| IntanError::Other(format!("Invalid JSON channel map: {}", e)))?;
+        let Json::Object(entries) = json else {
+            return Err(Box::new(invalid()));
+        };
+
+        let mut map = ChannelMap::default();
+        for (name, channels) in entries {
+            let channels = channels
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|channel| channel.as_str().map(str::to_string))
+                .collect::<Option<Vec<String>>>()
+                .ok_or_else(invalid)?;
+            map.groups.push(ChannelMapGroup { name, channels });
+        }
+        Ok(map)
+    }
+
+    /// Parses a channel map in the text format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line does not have a channel name and a group.
+    pub fn parse_text(text: &str) -> Result<ChannelMap, Box<dyn Error>> {
+        let mut map = ChannelMap::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|field| !field.is_empty())
+                .collect();
+            let (channel, group) = match fields.as_slice() {
+                [channel, ..] if channel.eq_ignore_ascii_case("channel") => continue,
+                [channel, group, ..] => (channel.to_string(), group.to_string()),
+                _ => {
+                    return Err(Box::new(IntanError::Other(format!(
+                        "Channel map line {} needs a channel name and a group",
+                        number + 1
+                    ))))
+                }
+            };
+            match map.groups.iter_mut().find(|g| g.name == group) {
+                Some(existing) => existing.channels.push(channel),
+                None => map.groups.push(ChannelMapGroup {
+                    name: group,
+                    channels: vec![channel],
+                }),
+            }
+        }
+        Ok(map)
+    }
+}
+
+/// A group of amplifier channels of a recording.
+///
+/// Created by [`RhsFile::groups`] and [`RhsFile::groups_from_map`].
+#[derive(Clone)]
+pub struct ChannelGroup<'a> {
+    /// Name of the group: the port prefix (e.g. "A") for groups by port, or the
+    /// group name of the channel map
+    pub name: String,
+    /// Rows of the group's channels in the amplifier data, in group order
+    pub rows: Vec<usize>,
+    /// The group's channels, in group order
+    pub channels: Vec<&'a ChannelInfo>,
+    rhs_file: &'a RhsFile,
+}
+
+impl fmt::Debug for ChannelGroup<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChannelGroup")
+            .field("name", &self.name)
+            .field("rows", &self.rows)
+            .field("channels", &self.channels)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> ChannelGroup<'a> {
+    /// Returns the number of channels in the group.
+    pub fn num_channels(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Returns the amplifier data of the group's channels, one row per channel in
+    /// group order, or `None` if the group is empty or no amplifier data is in
+    /// memory.
+    ///
+    /// The data is borrowed when the group's rows are consecutive in the
+    /// amplifier data, as for groups by port, and copied otherwise. Data loaded as
+    /// raw codes or in single precision is converted to `f64` in its unit.
+    pub fn amplifier_data(&self) -> Option<CowArray<'a, f64, Ix2>> {
+        let data = self.amplifier_double_precision()?;
+        let first = *self.rows.first()?;
+        let consecutive = self
+            .rows
+            .iter()
+            .enumerate()
+            .all(|(i, &row)| row == first + i);
+        Some(match data {
+            Cow::Borrowed(data) if consecutive => {
+                let rows = Slice::from(first..first + self.rows.len());
+                CowArray::from(data.slice_axis(Axis(0), rows))
+            }
+            data => CowArray::from(data.select(Axis(0), &self.rows)),
+        })
+    }
+
+    /// Returns the amplifier data of the `index`th channel of the group, or `None`
+    /// if `index` is out of range or no amplifier data is in memory.
+    ///
+    /// Data loaded as raw codes or in single precision is converted as for
+    /// [`amplifier_data`](ChannelGroup::amplifier_data).
+    pub fn channel_data(&self, index: usize) -> Option<CowArray<'a, f64, Ix1>> {
+        let row = *self.rows.get(index)?;
+        Some(match self.amplifier_double_precision()? {
+            Cow::Borrowed(data) => CowArray::from(data.index_axis(Axis(0), row)),
+            Cow::Owned(data) => CowArray::from(data.index_axis_move(Axis(0), row)),
+        })
+    }
+
+    /// The recording's amplifier data in double precision, or `None` if it is not
+    /// in memory
+    fn amplifier_double_precision(&self) -> Option<Cow<'a, Array2<f64>>> {
+        let data = self.rhs_file.data.as_ref()?;
+        data.amplifier_double_precision(data.units.amplifier)
+            .ok()
+            .flatten()
+    }
+
+    /// Copies the recording with only the group's amplifier channels, in group
+    /// order.
+    ///
+    /// The channels of the other signal types are kept, as are the DC amplifier,
+    /// stimulation, and stimulation flag data of the group's channels, in every
+    /// form they were loaded in.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if signals of the recording were spilled to disk.
+    pub fn to_rhs_file(&self) -> Result<RhsFile, Box<dyn Error>> {
+        let header = &self.rhs_file.header;
+        let all = |count: usize| (0..count).collect();
+        self.rhs_file.select_rows(&ChannelRows {
+            amplifier: self.rows.clone(),
+            board_adc: all(header.board_adc_channels.len()),
+            board_dac: all(header.board_dac_channels.len()),
+            board_dig_in: all(header.board_dig_in_channels.len()),
+            board_dig_out: all(header.board_dig_out_channels.len()),
+        })
+    }
+}
+
+impl RhsFile {
+    /// Groups the amplifier channels by port, in the order the ports first appear.
+    ///
+    /// Each group is named after its port prefix (e.g. "A") and holds the port's
+    /// channels in their order in the amplifier data.
+    pub fn groups(&self) -> Vec<ChannelGroup<'_>> {
+        let mut groups: Vec<ChannelGroup<'_>> = Vec::new();
+        for (row, channel) in self.header.amplifier_channels.iter().enumerate() {
+            let index = match groups.iter().position(|g| g.name == channel.port_prefix) {
+                Some(index) => index,
+                None => {
+                    groups.push(ChannelGroup {
+                        name: channel.port_prefix.clone(),
+                        rows: Vec::new(),
+                        channels: Vec::new(),
+                        rhs_file: self,
+                    });
+                    groups.len() - 1
+                }
+            };
+            groups[index].rows.push(row);
+            groups[index].channels.push(channel);
+        }
+        groups
+    }
+
+    /// Groups the amplifier channels as described by a channel map.
+    ///
+    /// Channels of the recording that are not in the map belong to no group.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the map names a channel that is not an amplifier channel
+    /// of the recording, or lists a channel twice within a group.
+    pub fn groups_from_map(
+        &self,
+        map: &ChannelMap,
+    ) -> Result<Vec<ChannelGroup<'_>>, Box<dyn Error>> {
+        let channels = &self.header.amplifier_channels;
+        let mut groups = Vec::with_capacity(map.groups.len());
+        for map_group in &map.groups {
+            let mut group = ChannelGroup {
+                name: map_group.name.clone(),
+                rows: Vec::with_capacity(map_group.channels.len()),
+                channels: Vec::with_capacity(map_group.channels.len()),
+                rhs_file: self,
+            };
+            for name in &map_group.channels {
+                let row = channels
+                    .iter()
+                    .position(|channel| &channel.native_channel_name == name)
+                    .ok_or_else(|| {
+                        IntanError::Other(format!(
+                            "Channel '{}' of group '{}' is not an amplifier channel",
+                            name, map_group.name
+                        ))
+                    })?;
+                if group.rows.contains(&row) {
+                    return Err(Box::new(IntanError::Other(format!(
+                        "Channel '{}' appears more than once in group '{}'",
+                        name, map_group.name
+                    ))));
+                }
+                group.rows.push(row);
+                group.channels.push(&channels[row]);
+            }
+            groups.push(group);
+        }
+        Ok(groups)
+    }
+}
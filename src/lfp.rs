@@ -0,0 +1,426 @@
+//! Dual-rate loading: full-rate amplifier data plus a downsampled LFP copy.
+//!
+//! [`LfpExtractor`] is a [`BlockProcessor`] that low-pass filters every amplifier
+//! channel and decimates it to a target rate while the file is being loaded, so
+//! the local field potential is available without a second pass over the data.
+//!
+//! When only the LFP is needed, [`extract_lfp`] runs the same filter and
+//! decimation in one call while streaming the recording block by block, so the
+//! full-rate data is never held in memory. [`RhsFile::lfp`] extracts the LFP of a
+//! recording already loaded. The resulting [`LfpData`] carries its own sample
+//! rate, timestamps, and amplifier channel list.
+//!
+//! The anti-aliasing filter is a [`ButterworthFilter`] low-pass of
+//! [`LfpOptions::order`], 4th order by default, which starts in the steady state of
+//! each channel's first sample as the other filters of the [`dsp`](crate::dsp)
+//! module do.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::lfp::{extract_lfp, load_with_lfp, LfpOptions};
+//!
+//! let (rhs_file, lfp) = load_with_lfp("recording.rhs", LfpOptions::default()).unwrap();
+//! println!("Full rate: {} Hz", rhs_file.header.sample_rate);
+//! if let Some(lfp) = lfp {
+//!     println!("LFP rate: {} Hz, shape {:?}", lfp.sample_rate, lfp.data.shape());
+//! }
+//!
+//! // Only the LFP, at 500 Hz
+//! let options = LfpOptions {
+//!     target_sample_rate: 500.0,
+//!     cutoff_frequency: 200.0,
+//!     ..LfpOptions::default()
+//! };
+//! let lfp = extract_lfp("recording.rhs", options).unwrap();
+//! println!("{} channels, {:.1} s", lfp.channels.len(), lfp.duration());
+//! ```
+
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2};
+use std::error::Error;
+use std::path::Path;
+
+use crate::dsp::{filter_channel, Biquad, ButterworthFilter};
+use crate::format::{detect_format, RecordingFormat};
+use crate::processor::{BlockProcessor, DataBlockMut};
+use crate::reader::Sample;
+use crate::stream::RhsBlockReader;
+use crate::types::{ChannelInfo, IntanError, RhsFile, RhsHeader};
+
+/// Options controlling LFP extraction.
+#[derive(Debug, Clone)]
+pub struct LfpOptions {
+    /// Desired output sample rate (Hz). The actual rate is the amplifier sample
+    /// rate divided by the nearest integer decimation factor.
+    pub target_sample_rate: f64,
+    /// Low-pass cutoff frequency (Hz). Clamped to 40% of the actual output rate
+    /// to prevent aliasing.
+    pub cutoff_frequency: f64,
+    /// Order of the Butterworth low-pass filter, from 1 to
+    /// [`MAX_ORDER`](crate::dsp::MAX_ORDER)
+    pub order: usize,
+}
+
+impl Default for LfpOptions {
+    fn default() -> Self {
+        LfpOptions {
+            target_sample_rate: 1000.0,
+            cutoff_frequency: 300.0,
+            order: 4,
+        }
+    }
+}
+
+/// Downsampled local field potential data.
+#[derive(Debug, Clone)]
+pub struct LfpData {
+    /// Sample rate of the LFP data (Hz)
+    pub sample_rate: f64,
+    /// Number of full-rate samples per LFP sample
+    pub decimation_factor: usize,
+    /// Cutoff frequency of the anti-aliasing low-pass filter (Hz)
+    pub cutoff_frequency: f64,
+    /// Timestamps of the retained samples (in full-rate sample numbers)
+    pub timestamps: Array1<i64>,
+    /// Low-pass filtered amplifier data (μV)
+    /// - Shape: [num_channels, num_lfp_samples]
+    pub data: Array2<f64>,
+    /// Amplifier channels, one per row of `data`
+    pub channels: Vec<ChannelInfo>,
+}
+
+impl LfpData {
+    /// Returns the number of LFP samples per channel.
+    pub fn num_samples(&self) -> usize {
+        self.data.ncols()
+    }
+
+    /// Returns the duration of the LFP data in seconds.
+    pub fn duration(&self) -> f64 {
+        self.num_samples() as f64 / self.sample_rate
+    }
+}
+
+/// Block processor producing an LFP copy of the amplifier data during loading.
+///
+/// Filter state is carried across blocks and across files, so a directory load
+/// yields one continuous LFP trace matching the combined recording.
+#[derive(Debug, Clone)]
+pub struct LfpExtractor {
+    options: LfpOptions,
+    sample_rate: f64,
+    decimation_factor: usize,
+    cutoff_frequency: f64,
+    sections: Vec<Biquad>,
+    /// State of every section of every channel
+    states: Vec<Vec<[f64; 2]>>,
+    /// Number of full-rate samples filtered so far
+    samples_filtered: usize,
+    /// One channel of the current block, filtered in place
+    scratch: Vec<f64>,
+    channels: Vec<ChannelInfo>,
+    phase: usize,
+    timestamps: Vec<i64>,
+    samples: Vec<Vec<f64>>,
+}
+
+impl LfpExtractor {
+    /// Creates a new extractor with the given options.
+    pub fn new(options: LfpOptions) -> Self {
+        LfpExtractor {
+            options,
+            sample_rate: 0.0,
+            decimation_factor: 1,
+            cutoff_frequency: 0.0,
+            sections: Vec::new(),
+            states: Vec::new(),
+            samples_filtered: 0,
+            scratch: Vec::new(),
+            channels: Vec::new(),
+            phase: 0,
+            timestamps: Vec::new(),
+            samples: Vec::new(),
+        }
+    }
+
+    /// Consumes the extractor and returns the accumulated LFP data.
+    ///
+    /// Returns `None` if no amplifier data was processed.
+    pub fn into_data(self) -> Option<LfpData> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let num_channels = self.samples.len();
+        let num_samples = self.timestamps.len();
+        let flat: Vec<f64> = self.samples.into_iter().flatten().collect();
+        let data = Array2::from_shape_vec((num_channels, num_samples), flat).ok()?;
+
+        Some(LfpData {
+            sample_rate: self.sample_rate / self.decimation_factor as f64,
+            decimation_factor: self.decimation_factor,
+            cutoff_frequency: self.cutoff_frequency,
+            timestamps: Array1::from(self.timestamps),
+            data,
+            channels: self.channels,
+        })
+    }
+
+    /// Filters and decimates the next samples of every amplifier channel
+    fn push<T: Sample>(&mut self, amp: ArrayView2<'_, T>, timestamps: ArrayView1<'_, i64>) {
+        let first = self.samples_filtered == 0;
+        // Index of the first sample of the block that is retained
+        let skip = (self.decimation_factor - self.phase) % self.decimation_factor;
+        for (channel, (states, out)) in amp
+            .rows()
+            .into_iter()
+            .zip(self.states.iter_mut().zip(self.samples.iter_mut()))
+        {
+            self.scratch.clear();
+            self.scratch
+                .extend(channel.iter().map(|value| value.to_f64()));
+            filter_channel(
+                &self.sections,
+                (&mut self.scratch[..]).into(),
+                states,
+                first,
+            );
+            out.extend(
+                self.scratch
+                    .iter()
+                    .skip(skip)
+                    .step_by(self.decimation_factor),
+            );
+        }
+        self.samples_filtered += amp.ncols();
+
+        // Record timestamps of the retained samples
+        for &ts in timestamps {
+            if self.phase == 0 {
+                self.timestamps.push(ts);
+            }
+            self.phase = (self.phase + 1) % self.decimation_factor;
+        }
+    }
+}
+
+impl BlockProcessor for LfpExtractor {
+    fn begin(&mut self, header: &RhsHeader) -> Result<(), Box<dyn Error>> {
+        let sample_rate = header.sample_rate as f64;
+        let num_channels = header.amplifier_channels.len();
+
+        // Subsequent files of a combined recording continue the existing trace
+        if !self.sections.is_empty() {
+            if (sample_rate - self.sample_rate).abs() > 0.01 || num_channels != self.states.len() {
+                return Err(Box::new(IntanError::Other(
+                    "LFP extraction requires identical sample rates and channel counts across files"
+                        .to_string(),
+                )));
+            }
+            return Ok(());
+        }
+
+        if self.options.target_sample_rate <= 0.0 || self.options.cutoff_frequency <= 0.0 {
+            return Err(Box::new(IntanError::Other(
+                "LFP target sample rate and cutoff frequency must be positive".to_string(),
+            )));
+        }
+
+        self.sample_rate = sample_rate;
+        self.decimation_factor =
+            ((sample_rate / self.options.target_sample_rate).round() as usize).max(1);
+        let output_rate = sample_rate / self.decimation_factor as f64;
+        self.cutoff_frequency = self.options.cutoff_frequency.min(0.4 * output_rate);
+
+        self.sections = ButterworthFilter::lowpass(self.options.order, self.cutoff_frequency)
+            .design(sample_rate)?;
+        self.states = vec![vec![[0.0; 2]; self.sections.len()]; num_channels];
+        self.channels = header.amplifier_channels.clone();
+        self.samples = vec![Vec::new(); num_channels];
+
+        Ok(())
+    }
+
+    fn process_block(&mut self, block: &mut DataBlockMut<'_>) -> Result<(), Box<dyn Error>> {
+        if let Some(amp) = block.amplifier_data.as_ref() {
+            self.push(amp.view(), block.timestamps);
+        } else if let Some(amp) = block.amplifier_data_f32.as_ref() {
+            self.push(amp.view(), block.timestamps);
+        }
+        Ok(())
+    }
+}
+
+/// Loads a file or directory and produces its LFP in the same pass.
+///
+/// Returns the full-rate recording together with the LFP data, which is `None`
+/// when the recording contains no amplifier data.
+///
+/// # Errors
+///
+/// Returns the errors of [`load`](crate::load), and an
+/// [`IntanError::Other`] if the options are invalid.
+pub fn load_with_lfp<P: AsRef<Path>>(
+    path: P,
+    options: LfpOptions,
+) -> Result<(RhsFile, Option<LfpData>), IntanError> {
+    let mut extractor = LfpExtractor::new(options);
+    let rhs_file = crate::load_with_processors(path, &mut [&mut extractor])?;
+    Ok((rhs_file, extractor.into_data()))
+}
+
+/// Streams a file or directory and returns only its LFP.
+///
+/// RHS files and directories of RHS files are read one data block at a time, so
+/// memory use is that of the LFP alone. Other recording layouts and remote files
+/// are loaded in full, and the full-rate data is dropped once the LFP is
+/// extracted.
+///
+/// # Errors
+///
+/// Returns an error if the recording cannot be read, the options are invalid,
+/// the files of a directory differ in sample rate or channel count, or the
+/// recording has no amplifier data.
+pub fn extract_lfp<P: AsRef<Path>>(path: P, options: LfpOptions) -> Result<LfpData, IntanError> {
+    let path = path.as_ref();
+    let mut extractor = LfpExtractor::new(options);
+
+    let files = if path.to_str().is_some_and(crate::is_remote_uri) {
+        None
+    } else {
+        match detect_format(path)? {
+            RecordingFormat::RhsFile => Some(vec![path.to_path_buf()]),
+            RecordingFormat::RhsFileDirectory => Some(crate::find_rhs_files(path)?),
+            _ => None,
+        }
+    };
+    match files {
+        Some(files) => {
+            for file in files {
+                let mut reader = RhsBlockReader::open(file)?;
+                extractor.begin(reader.header())?;
+                for block in reader.by_ref() {
+                    let block = block?;
+                    if let Some(amp) = &block.data.amplifier_data {
+                        extractor.push(amp.view(), block.data.timestamps.view());
+                    }
+                }
+            }
+        }
+        None => {
+            crate::load_with_processors(path, &mut [&mut extractor])?;
+        }
+    }
+
+    extractor.into_data().ok_or_else(no_amplifier_data)
+}
+
+impl RhsFile {
+    /// Low-pass filters and decimates the loaded amplifier data, as
+    /// [`load_with_lfp`] would have while loading.
+    ///
+    /// Both `f64` and `f32` data can be used.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the options are invalid, or if there is no amplifier
+    /// data in scaled form: it was loaded as raw codes or spilled to disk, or the
+    /// recording has none.
+    pub fn lfp(&self, options: LfpOptions) -> Result<LfpData, Box<dyn Error>> {
+        let mut extractor = LfpExtractor::new(options);
+        extractor.begin(&self.header)?;
+        let data = self.data.as_ref().ok_or_else(no_amplifier_data)?;
+        if let Some(amp) = &data.amplifier_data {
+            extractor.push(amp.view(), data.timestamps.view());
+        } else if let Some(amp) = &data.amplifier_data_f32 {
+            extractor.push(amp.view(), data.timestamps.view());
+        } else {
+            return Err(no_amplifier_data().into());
+        }
+        Ok(extractor.into_data().ok_or_else(no_amplifier_data)?)
+    }
+}
+
+/// Error for a recording without scaled amplifier data
+fn no_amplifier_data() -> IntanError {
+    IntanError::Other(
+        "The recording has no scaled amplifier data to extract the LFP from".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::RhsFileBuilder;
+    use crate::signal::SignalType;
+    use ndarray::s;
+
+    fn recording() -> RhsFile {
+        RhsFileBuilder::new()
+            .sample_rate(20000.0)
+            .num_samples(128 * 20)
+            .amplifier_channels(2)
+            .first_timestamp(-7)
+            .sine(0, 50.0, 100.0)
+            .sine(0, 3000.0, 100.0)
+            .sine(1, 800.0, 40.0)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn lfp_is_the_butterworth_lowpass_decimated() {
+        for order in [1, 4, 7] {
+            let rhs_file = recording();
+            let options = LfpOptions {
+                target_sample_rate: 1000.0,
+                cutoff_frequency: 300.0,
+                order,
+            };
+            let lfp = rhs_file.lfp(options).unwrap();
+            assert_eq!((lfp.decimation_factor, lfp.sample_rate), (20, 1000.0));
+
+            let mut filtered = rhs_file.clone();
+            let lowpass = ButterworthFilter::lowpass(order, 300.0);
+            filtered
+                .apply_filter(SignalType::Amplifier, &lowpass)
+                .unwrap();
+            let data = filtered.data.unwrap();
+            let expected = data.amplifier_data.unwrap();
+            assert_eq!(lfp.data, expected.slice(s![.., ..;20]));
+            assert_eq!(lfp.timestamps, data.timestamps.slice(s![..;20]));
+        }
+    }
+
+    #[test]
+    fn lfp_is_continued_across_blocks() {
+        let rhs_file = recording();
+        let data = rhs_file.data.as_ref().unwrap();
+        let amplifier = data.amplifier_data.as_ref().unwrap();
+        let whole = rhs_file.lfp(LfpOptions::default()).unwrap();
+
+        let mut extractor = LfpExtractor::new(LfpOptions::default());
+        extractor.begin(&rhs_file.header).unwrap();
+        for start in (0..rhs_file.num_samples()).step_by(37) {
+            let end = (start + 37).min(rhs_file.num_samples());
+            extractor.push(
+                amplifier.slice(s![.., start..end]),
+                data.timestamps.slice(s![start..end]),
+            );
+        }
+        let blocks = extractor.into_data().unwrap();
+        assert_eq!(blocks.data, whole.data);
+        assert_eq!(blocks.timestamps, whole.timestamps);
+    }
+
+    #[test]
+    fn invalid_orders_are_rejected() {
+        for order in [0, crate::dsp::MAX_ORDER + 1] {
+            let options = LfpOptions {
+                order,
+                ..LfpOptions::default()
+            };
+            assert!(recording().lfp(options).is_err());
+        }
+    }
+}
@@ -13,15 +13,31 @@ This library provides tools to read and process these files, making the data acc
 ## Main Features
 
 - **Single File or Directory Loading**: Load individual RHS files or automatically combine multiple files from a directory
+  - Merging, gap padding, and duplicate detection across files
+  - Directory manifests with `scan_directory`
+- **Configurable Loading**: Channels, time windows, and processing options with `Loader`
+- **Streaming Reads**: Block-by-block reading and live follow mode in constant memory
+- **Out-of-Core and Memory-Mapped Access**: Spilling signals to disk, and on-demand reads with the `mmap` feature
+- **Automatic Format Detection**: All three RHS file layouts, and RHD2000 `.rhd` files
 - **Comprehensive Parsing**: Read and parse Intan RHS files with full support for all sections
 - **Strong Typing**: All data structures are strongly typed with appropriate Rust types
+- **Time Handling**: Zero-copy epochs, sample and wall-clock time conversions
+- **Events and Stimulation**: Digital events, stimulation pulses, and charge
+- **Analysis**: Channel groups, noise, clipping, spectra, similarity, and QC reports
 - **Automatic Processing**: 
-  - Signal scaling to meaningful units (μV, V)
+  - Signal scaling to meaningful units (μV, V), or raw 16-bit codes
   - Notch filter application for line noise removal
   - Timestamp alignment and verification
+- **Filtering**: Butterworth filters, re-referencing, and LFP extraction
+- **Writing and Export**: RHS files, Kilosort binary, EDF+, NumPy, Arrow, Parquet, Zarr, HDF5, MATLAB, and NWB
+- **Provenance**: Header caches, integrity checks, file fingerprints, and RHX settings
+- **Integrations**: Logging through `log`, `serde` support, and remote loading from HTTP(S) and S3
+- **Custom Processing Hooks**: `BlockProcessor`s that run on every data block during load
+- **Synthetic Fixtures**: Valid recordings for tests with `RhsFileBuilder`
+- **Visualization Pyramids**: Min/max envelopes at several zoom levels
 - **Efficient Implementation**:
   - Fast binary parsing with minimal allocations
-  - Proper error handling with descriptive messages
+  - Structured `IntanError` errors and typed `LoadWarning`s
   - Memory-efficient data structures
 
 ## Quick Start
@@ -68,19 +84,99 @@ The crate organizes Intan data into a hierarchy of structs:
 
 ## Error Handling
 
-The library provides descriptive errors for various failure scenarios (file format errors,
-I/O failures, etc.) through the `IntanError` type.
+The loading functions return [`IntanError`], whose variants tell failures apart:
+file format errors, I/O failures, invalid options, selected channels and time
+ranges the recording does not have, incompatible headers, gaps and duplicates
+between files, directories without files, and recordings too long for the
+platform to address. Errors that concern one file are wrapped in
+[`IntanError::File`] with its path and, when it could not be parsed, the byte at
+which reading stopped. Errors in the header are wrapped in
+[`IntanError::HeaderField`], such as "Failed reading custom name of channel A-001
+at byte 194", so that corrupted files can be inspected where they went wrong.
 */
 
+pub mod analysis;
+pub mod anonymize;
+pub mod approx;
+pub mod builder;
+pub mod cache;
+pub mod checkpoint;
+pub mod chunk;
+pub mod datetime;
+pub mod deinterleave;
+pub mod diff;
+pub mod dsp;
+pub mod events;
+pub mod export;
+mod file_reader;
+pub mod fingerprint;
+pub mod flags;
+pub mod follow;
+pub mod format;
+pub mod groups;
+mod json;
+pub mod lfp;
+pub mod loader;
+pub mod manifest;
+pub mod memory;
+pub mod merge;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+mod pipeline;
+pub mod processor;
+pub mod pyramid;
+pub mod qc;
+pub mod quality;
+#[cfg(feature = "remote")]
+pub mod remote;
 mod reader;
+pub mod rhd;
+pub mod segment;
+pub mod select;
+pub mod settings;
+pub mod signal;
+pub mod spill;
+pub mod stats;
+pub mod stim;
+pub mod stream;
+pub mod summary;
 pub mod types;
+pub mod units;
+pub mod validate;
+pub mod writer;
+mod xml;
 
-use std::error::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs;
+use log::info;
 
 // Re-export types
+pub use builder::RhsFileBuilder;
+pub use datetime::DateTime;
+pub use diff::HeaderDifference;
+pub use dsp::{ButterworthFilter, NotchFilter};
+pub use events::{decode_digital_codes, extract_digital_events, DigitalEvents, DigitalLineMap};
+pub use fingerprint::{fingerprint_file, FileFingerprint};
+pub use flags::{StimFlags, StimFlagsView};
+pub use format::{detect_format, RecordingFormat};
+pub use groups::{ChannelGroup, ChannelMap};
+pub use loader::{
+    DuplicateHandling, GapHandling, HeaderCompatibility, LoadOptions, Loader, ParseMode,
+};
+pub use manifest::{scan_directory, FileManifest};
+pub use memory::{estimate_memory, MemoryEstimate};
+pub use processor::{BlockProcessor, DataBlockMut};
+pub use rhd::{RhdData, RhdFile, RhdHeader};
+pub use segment::Segment;
+pub use settings::SettingsXml;
+pub use signal::{Signal, SignalData, SignalType};
+pub use stats::{scan, RecordingStats};
+pub use stim::{extract_pulses, PulseOptions, StimFlag, StimPulse};
+pub use stream::load_chunked;
+pub use summary::RecordingSummary;
 pub use types::*;
+pub use units::{ScaleFactor, ScaleFactors, SignalUnits, Unit};
+pub use validate::{validate, ValidationIssue, ValidationReport};
 
 /// Loads RHS data from a file or directory.
 ///
@@ -90,6 +186,26 @@ pub use types::*;
 ///
 /// When loading from a directory, all RHS files are combined chronologically into
 /// a single dataset. Files must have compatible headers (same channels, sample rates, etc.).
+/// They are ordered by the start time in their RHX file names
+/// (`name_YYMMDD_HHMMSS.rhs`), or by their first timestamps if any name lacks one,
+/// and an order that contradicts contiguous timestamps is an error.
+/// A directory saved in RHX's "one file per signal type" format (`info.rhs` with
+/// `time.dat`, `amplifier.dat`, `stim.dat`, ...) or "one file per channel" format
+/// (`info.rhs` with `time.dat`, `amp-A-000.dat`, `board-DIGITAL-IN-01.dat`, ...) is
+/// instead loaded as one recording, producing the same arrays as a traditional file.
+///
+/// The format is detected from the file's magic number or the directory's contents
+/// (see [`detect_format`]), not from the file extension. RHD recordings are
+/// reported with an error naming the detected format; load them with [`load_any`].
+///
+/// If an RHX `settings.xml` file is found next to the recording, it is parsed into
+/// [`RhsFile::settings`].
+///
+/// With the `remote` feature enabled, `path` may also be an `https://`, `http://`,
+/// or `s3://` URI, which is streamed with range requests (see the `remote` module).
+///
+/// To load only some channels, a time window, or the files of a directory matching
+/// a pattern, or to change how the data is processed, use a [`Loader`].
 ///
 /// # Parameters
 ///
@@ -97,7 +213,7 @@ pub use types::*;
 ///
 /// # Returns
 ///
-/// * `Result<RhsFile, Box<dyn Error>>` - Either the loaded file data or an error
+/// * `Result<RhsFile, IntanError>` - Either the loaded file data or an [`IntanError`]
 ///
 /// # Examples
 ///
@@ -133,27 +249,197 @@ pub use types::*;
 ///
 /// When loading multiple files, the entire combined dataset is loaded into memory.
 /// Be aware of memory usage when dealing with lengthy recording sessions.
-pub fn load<P: AsRef<Path>>(path: P) -> Result<RhsFile, Box<dyn Error>> {
+pub fn load<P: AsRef<Path>>(path: P) -> Result<RhsFile, IntanError> {
+    load_with_processors(path, &mut [])
+}
+
+/// Loads an RHS or RHD file, detecting the format from its magic number.
+///
+/// RHS files and directories are loaded as with [`load`]; RHD files are loaded
+/// with [`rhd::load`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use intan_importer::{load_any, IntanFile};
+///
+/// match load_any("recording.rhd").unwrap() {
+///     IntanFile::Rhs(rhs_file) => println!("RHS: {} s", rhs_file.duration()),
+///     IntanFile::Rhd(rhd_file) => println!("RHD: {} s", rhd_file.duration()),
+/// }
+/// ```
+pub fn load_any<P: AsRef<Path>>(path: P) -> Result<IntanFile, IntanError> {
     let path = path.as_ref();
-    
-    if path.is_file() {
-        // Load single file
-        reader::load_file(path)
-    } else if path.is_dir() {
-        // Load and combine all RHS files in directory
-        load_directory(path)
-    } else {
-        Err(Box::new(IntanError::Other(format!(
-            "Path '{}' is neither a file nor a directory",
-            path.display()
-        ))))
+
+    match detect_file_format(path)? {
+        RecordingFormat::RhdFile => Ok(IntanFile::Rhd(rhd::load(path)?)),
+        format if format.is_rhd() => Err(format::unsupported(
+            format,
+            path,
+            "RHD recordings can only be loaded from single .rhd files",
+        )),
+        _ => load(path).map(IntanFile::Rhs),
     }
 }
 
-/// Loads and combines all RHS files from a directory
-fn load_directory<P: AsRef<Path>>(dir_path: P) -> Result<RhsFile, Box<dyn Error>> {
+/// Loads RHS data from a file or directory, running custom block processors during the load.
+///
+/// Behaves like [`load`], but every processor in `processors` is invoked on each
+/// 128-sample data block after scaling and filtering, with mutable access to the
+/// block's data. When loading a directory, processors run on each file before the
+/// files are combined, and their `begin`/`finish` hooks are called once per file.
+///
+/// See the [`processor`] module for details and an example.
+pub fn load_with_processors<P: AsRef<Path>>(
+    path: P,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<RhsFile, IntanError> {
+    load_with_options(path.as_ref(), &LoadOptions::default(), processors)
+}
+
+/// Loads RHS data from a file or directory with the given options
+pub(crate) fn load_with_options(
+    path: &Path,
+    options: &LoadOptions,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<RhsFile, IntanError> {
+    options.check()?;
+
+    if let Some(uri) = path.to_str().filter(|uri| is_remote_uri(uri)) {
+        if options.fingerprint {
+            return Err(IntanError::InvalidOptions(
+                "Fingerprints can only be computed for local files".to_string(),
+            ));
+        }
+        #[cfg(feature = "remote")]
+        return remote::load_remote_with_options(uri, options, processors);
+        #[cfg(not(feature = "remote"))]
+        return Err(IntanError::Other(format!(
+            "Loading '{}' requires the `remote` feature",
+            uri
+        )));
+    }
+
+    let format = detect_file_format(path)?;
+    let mut rhs_file = match format {
+        RecordingFormat::RhsFile => reader::load_file(path, options, processors),
+        RecordingFormat::RhsFileDirectory => load_directory(path, options, processors),
+        RecordingFormat::RhsSignalTypeDirectory => {
+            if options.verbose {
+                info!("Found \"one file per signal type\" recording in {}", path.display());
+            }
+            reader::load_signal_type_directory(path, options, processors)
+        }
+        RecordingFormat::RhsChannelDirectory => {
+            if options.verbose {
+                info!("Found \"one file per channel\" recording in {}", path.display());
+            }
+            reader::load_channel_directory(path, options, processors)
+        }
+        RecordingFormat::RhdFile => Err(format::unsupported(
+            RecordingFormat::RhdFile,
+            path,
+            "use load_any() or rhd::load() to read RHD files",
+        )),
+        format => Err(format::unsupported(
+            format,
+            path,
+            "RHD recordings can only be loaded from single .rhd files",
+        )),
+    }?;
+
+    // The loaders of RHS files hash each file as they read it
+    let dat_directory = matches!(
+        format,
+        RecordingFormat::RhsSignalTypeDirectory | RecordingFormat::RhsChannelDirectory
+    );
+    if options.fingerprint && dat_directory {
+        rhs_file.fingerprints = fingerprint::fingerprint_dat_directory(path)?;
+    }
+    rhs_file.settings = settings::find_sidecar(path, &mut rhs_file.warnings);
+    Ok(rhs_file)
+}
+
+/// Detects the format of the recording at `path`, naming the file in errors
+fn detect_file_format(path: &Path) -> Result<RecordingFormat, IntanError> {
+    detect_format(path).map_err(|e| match e {
+        // The magic number at the start of the file was not recognized
+        IntanError::UnrecognizedFileFormat => e.in_file(path, Some(0)),
+        IntanError::IoError(_) => e.in_file(path, None),
+        e => e,
+    })
+}
+
+/// Loads separately saved amplifier streams from a "one file per signal type" directory.
+///
+/// When RHX saves data in "one file per signal type" mode, the wideband amplifier
+/// signal (`amplifier.dat`) can be accompanied by software-filtered low-pass
+/// (`lowpass.dat`) and high-pass (`highpass.dat`) streams. This function reads the
+/// `info.rhs` header in `dir_path` and loads the requested streams as distinct arrays.
+///
+/// Streams that were not saved are returned as `None`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use intan_importer::{load_amplifier_streams, AmplifierStream};
+///
+/// let streams = load_amplifier_streams(
+///     "session_dir/",
+///     &[AmplifierStream::Lowpass, AmplifierStream::Highpass],
+/// ).unwrap();
+///
+/// if let Some(lowpass) = &streams.lowpass_data {
+///     println!("Low-pass data: {:?} (downsampled {}x)",
+///              lowpass.shape(), streams.lowpass_downsample_factor);
+/// }
+/// ```
+pub fn load_amplifier_streams<P: AsRef<Path>>(
+    dir_path: P,
+    streams: &[AmplifierStream],
+) -> Result<AmplifierStreams, IntanError> {
+    reader::load_amplifier_streams(dir_path.as_ref(), streams)
+}
+
+/// Loads and combines all RHS files in a directory
+fn load_directory<P: AsRef<Path>>(
+    dir_path: P,
+    options: &LoadOptions,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<RhsFile, IntanError> {
     let dir_path = dir_path.as_ref();
     
+    let rhs_files = find_matching_rhs_files(dir_path, options.file_pattern.as_deref())?;
+    
+    if options.verbose {
+        info!("Found {} RHS files to combine:", rhs_files.len());
+        for file in &rhs_files {
+            info!("  - {}", file.display());
+        }
+    }
+    
+    // Load and combine the files
+    reader::load_and_combine_files(&rhs_files, options, processors)
+}
+
+/// Returns true if `uri` starts with a scheme handled by the `remote` feature
+pub(crate) fn is_remote_uri(uri: &str) -> bool {
+    ["s3://", "https://", "http://"]
+        .iter()
+        .any(|scheme| uri.starts_with(scheme))
+}
+
+/// Returns all .rhs files in a directory, in recording order
+pub(crate) fn find_rhs_files(dir_path: &Path) -> Result<Vec<PathBuf>, IntanError> {
+    find_matching_rhs_files(dir_path, None)
+}
+
+/// Returns the .rhs files in a directory whose names match the glob `pattern`, or
+/// all of them without a pattern, in recording order
+pub(crate) fn find_matching_rhs_files(
+    dir_path: &Path,
+    pattern: Option<&str>,
+) -> Result<Vec<PathBuf>, IntanError> {
     // Find all .rhs files in the directory
     let mut rhs_files: Vec<_> = fs::read_dir(dir_path)?
         .filter_map(|entry| entry.ok())
@@ -163,23 +449,54 @@ fn load_directory<P: AsRef<Path>>(dir_path: P) -> Result<RhsFile, Box<dyn Error>
                 .map(|ext| ext.eq_ignore_ascii_case("rhs"))
                 .unwrap_or(false)
         })
+        .filter(|entry| {
+            let name = entry.file_name();
+            pattern.is_none_or(|pattern| glob_matches(pattern, &name.to_string_lossy()))
+        })
         .map(|entry| entry.path())
         .collect();
     
     if rhs_files.is_empty() {
-        return Err(Box::new(IntanError::Other(
-            "No RHS files found in directory".to_string()
-        )));
+        return Err(IntanError::NoFiles {
+            directory: Some(dir_path.to_path_buf()),
+            pattern: pattern.map(str::to_string),
+        });
     }
     
-    // Sort files by name to ensure consistent ordering
-    rhs_files.sort();
+    // Order the files as they were recorded, which renamed files may not be in
+    manifest::sort_by_recording_time(&mut rhs_files)?;
     
-    println!("Found {} RHS files to combine:", rhs_files.len());
-    for file in &rhs_files {
-        println!("  - {}", file.display());
+    Ok(rhs_files)
+}
+
+/// Returns true if `name` matches the glob `pattern`, where `*` matches any run of
+/// characters and `?` any single character
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` and of the name character it was matched up to
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Let the last `*` absorb one more character and retry
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
     }
-    
-    // Load and combine the files
-    reader::load_and_combine_files(&rhs_files)
-}
\ No newline at end of file
+    pattern[p..].iter().all(|&c| c == '*')
+}
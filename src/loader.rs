@@ -0,0 +1,961 @@
+//! Loading with configurable options.
+//!
+//! [`load`](crate::load) always reads every channel of the whole recording, applies
+//! the notch filter when the header asks for it, and logs its progress through the
+//! [`log`] crate.
+//! [`Loader`] builds a load with each of these steps configured:
+//!
+//! - [`Loader::channels`] keeps only the selected channels, in the header as well
+//!   as the data; the samples of other channels are skipped while reading
+//! - [`Loader::time_range`] reads only the samples within a time window
+//! - [`Loader::wallclock_range`] reads only the samples recorded within a window of
+//!   wall-clock time, for example 14:00 to 14:30 of a session saved across many files
+//! - [`Loader::notch`] skips the automatic notch filter, or forces it on for files
+//!   whose data was saved unfiltered
+//! - [`Loader::zero_phase_notch`], [`Loader::notch_bandwidth`], and
+//!   [`Loader::notch_sections`] change the notch filter from the one RHX applies
+//! - [`Loader::raw`] keeps amplifier, ADC, and DAC data as raw 16-bit codes
+//! - [`Loader::data_type`] stores the scaled analog signals as `f32` instead of `f64`
+//! - [`Loader::spill_to`] keeps the large analog signals in temporary files, for
+//!   recordings larger than memory
+//! - [`Loader::buffer_size`], [`Loader::read_ahead`], and [`Loader::unbuffered`]
+//!   tune the reads from disk, for example on network filesystems
+//! - [`Loader::quiet`] (or [`Loader::verbose`]) silences the progress messages
+//! - [`Loader::progress`] reports progress to a callback instead, for example to
+//!   drive a progress bar
+//! - [`Loader::parse_mode`] makes header parsing stricter or more lenient than
+//!   the default, see [`ParseMode`]
+//! - [`Loader::mask_bad_channels`] leaves out amplifier channels that fail the
+//!   checks of the [`quality`] module
+//! - [`Loader::fingerprint`] hashes every file read, to trace derived data back to
+//!   its raw files
+//! - [`Loader::processor`] registers [`BlockProcessor`]s, as with
+//!   [`load_with_processors`](crate::load_with_processors)
+//!
+//! The settings are collected in a [`LoadOptions`], which can also be built
+//! directly and passed to [`Loader::with_options`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::Loader;
+//!
+//! // Two amplifier channels and a digital input, from minute 10 to minute 20
+//! let rhs_file = Loader::new("recording.rhs")
+//!     .channels(["A-000", "A-001", "DIGITAL-IN-01"])
+//!     .time_range(600.0..1200.0)
+//!     .apply_notch(false)
+//!     .verbose(false)
+//!     .load()
+//!     .unwrap();
+//! println!("Loaded {:.1} s of {} amplifier channels",
+//!          rhs_file.duration(), rhs_file.header.amplifier_channels.len());
+//!
+//! // Channels can also be selected by their position within their signal type
+//! use intan_importer::loader::ChannelSelector;
+//!
+//! let rhs_file = Loader::new("recording.rhs")
+//!     .channels((0..8).map(ChannelSelector::Amplifier))
+//!     .load()
+//!     .unwrap();
+//!
+//! // Progress goes to a callback rather than the log
+//! use intan_importer::loader::{LoadPhase, LoadProgress};
+//!
+//! let rhs_file = Loader::new("recording.rhs")
+//!     .progress(|progress: &LoadProgress| {
+//!         if progress.phase == LoadPhase::Data {
+//!             eprint!("\r{}/{} blocks", progress.blocks_parsed, progress.total_blocks);
+//!         }
+//!     })
+//!     .load()
+//!     .unwrap();
+//! ```
+
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::datetime::DateTime;
+use crate::dsp::{check_notch_shape, INTAN_NOTCH_BANDWIDTH};
+use crate::processor::BlockProcessor;
+use crate::quality::{self, QualityOptions};
+use crate::types::{IntanError, RhsFile};
+
+/// Settings for a load.
+///
+/// The default settings load a recording exactly like [`load`](crate::load).
+#[derive(Clone)]
+pub struct LoadOptions {
+    /// Channels to keep, or `None` for every channel.
+    ///
+    /// Channels keep their order in the file. A signal type with none of its
+    /// channels selected is left out of the data. Selecting an amplifier channel
+    /// keeps its DC amplifier, stimulation, and stimulation flag data as well.
+    pub channels: Option<Vec<ChannelSelector>>,
+    /// Time window to keep, in seconds from the first sample of the recording,
+    /// or `None` for the whole recording.
+    ///
+    /// A sample is kept when its time falls within `start..end`. For a directory of
+    /// files, the window applies to the combined recording.
+    ///
+    /// RHS files are not read in full: the reader seeks to the data block holding
+    /// the first sample of the window and stops after the block holding the last,
+    /// and files of a directory that lie outside the window are skipped.
+    pub time_range: Option<Range<f64>>,
+    /// Window of wall-clock time to keep, or `None` for the whole recording.
+    ///
+    /// A sample is kept when the time at which it was recorded falls within
+    /// `start..end`, placing the files of a directory on the wall clock by the
+    /// start time in their names (see [`DateTime::from_rhx_filename`]) and their
+    /// durations, so gaps between files are accounted for. As with
+    /// [`time_range`](LoadOptions::time_range), only the files and data blocks
+    /// within the window are read. Every file must be named by RHX, and the
+    /// window cannot be combined with a time range. The streaming reader takes
+    /// the start time from the name of the file it opens, and other reads with no
+    /// file name return an error.
+    pub wallclock_range: Option<Range<DateTime>>,
+    /// Glob pattern selecting the .rhs files of a directory to combine by file
+    /// name, such as `*_probe1_*.rhs`, or `None` to combine all of them.
+    ///
+    /// `*` matches any run of characters and `?` any single character; matching is
+    /// case-sensitive. Lets directories holding several interleaved sessions be
+    /// loaded one session at a time. In follow mode, only matching files are
+    /// followed. Ignored when loading a single file.
+    pub file_pattern: Option<String>,
+    /// How to treat header values that the RHS format does not define, such as
+    /// unknown channel types or notch filter modes; see [`ParseMode`].
+    pub parse_mode: ParseMode,
+    /// Which differences between the headers of a directory's files stop them
+    /// from being combined, and which are only logged as warnings.
+    ///
+    /// See [`HeaderCompatibility`]. Also applies to the files followed in follow
+    /// mode.
+    pub header_compatibility: HeaderCompatibility,
+    /// What to do where a directory's files were not recorded back to back, so
+    /// the timestamps jump between one file and the next.
+    ///
+    /// See [`GapHandling`]. A [`time_range`](LoadOptions::time_range) selects
+    /// samples of the files before any padding is inserted. Cannot pad spilled
+    /// data, or raw data with NaN.
+    pub gap_handling: GapHandling,
+    /// Whether to leave out the files of a directory that cannot be read, instead
+    /// of failing the whole load.
+    ///
+    /// Before any data is read, the header and size of every file are checked, and
+    /// files with an invalid header or a size that is not a whole number of data
+    /// blocks are skipped with a warning. They are listed with their errors in
+    /// [`RhsFile::skipped_files`]. Loading still fails if every file is skipped, or
+    /// if the remaining files cannot be combined. Ignored when loading a single
+    /// file.
+    pub skip_corrupt_files: bool,
+    /// What to do with files of a directory that repeat the samples of another
+    /// file, such as backup copies; see [`DuplicateHandling`].
+    ///
+    /// Checking reads the header and one data block of the files that could be
+    /// copies. Ignored when loading a single file.
+    pub duplicate_handling: DuplicateHandling,
+    /// Number of files of a directory to read at the same time, 1 by default.
+    ///
+    /// With more than one, worker threads read and parse the next files while the
+    /// files before them are combined, overlapping disk reads with parsing. The
+    /// combined recording is the same as when the files are read one after another,
+    /// and it is assembled as with a [`memory_budget`](LoadOptions::memory_budget),
+    /// with the same restrictions. Ignored when loading a single file.
+    pub parallel_files: usize,
+    /// Upper bound in bytes for the memory used by the data of a directory load, or
+    /// `None` for no bound.
+    ///
+    /// With a budget, or with [`parallel_files`](LoadOptions::parallel_files) above 1,
+    /// the arrays of the combined recording are allocated once at their final size
+    /// and each file's data is copied in as soon as the files before it are, instead
+    /// of concatenating all files at the end, which briefly needs twice the memory.
+    /// A file is only read when the peak memory estimated for it (see
+    /// [`MemoryEstimate`](crate::memory::MemoryEstimate)) fits in what the budget
+    /// leaves beside the combined arrays and the files being read. The load fails
+    /// before reading any data if the combined recording and its largest file do
+    /// not fit. The estimates cover the data arrays and read buffers, not allocator
+    /// overhead. Cannot be combined with [`spill_dir`](LoadOptions::spill_dir),
+    /// gap padding, or block processors. Ignored when loading a single file.
+    pub memory_budget: Option<u64>,
+    /// When to apply the notch filter set in the header to amplifier data.
+    pub notch: NotchMode,
+    /// Whether to apply the notch filter forward and then backward in time, so it
+    /// shifts no phase, instead of once forward as RHX does.
+    ///
+    /// Each file of a directory is filtered on its own. The streaming reader and
+    /// follow mode filter block by block and return an error when the notch filter
+    /// applies with this option set.
+    pub zero_phase_notch: bool,
+    /// Width of the notch filter in Hz, Intan's 10 Hz by default.
+    ///
+    /// See [`NotchFilter`](crate::dsp::NotchFilter); other widths no longer match
+    /// the filter RHX applies.
+    pub notch_bandwidth: f64,
+    /// Number of notch filter sections in cascade, 1 by default as in RHX.
+    pub notch_sections: usize,
+    /// Whether to keep amplifier, DC amplifier, board ADC, and board DAC data as
+    /// raw 16-bit codes instead of scaling them to physical units.
+    ///
+    /// The codes are stored in the `*_raw` fields of [`RhsData`](crate::RhsData),
+    /// and the scaled fields are left `None`. Raw amplifier data is never notch
+    /// filtered. Stimulation and digital data are processed as usual.
+    /// [`RhsData::scale_factors`](crate::RhsData::scale_factors) gives the
+    /// conversion to physical units. Exporters scale the codes themselves, but
+    /// analysis functions work on the scaled fields, so call
+    /// [`RhsData::scale_raw`](crate::RhsData::scale_raw) before using them.
+    pub raw: bool,
+    /// Floating-point type of the scaled amplifier, DC amplifier, board ADC, and
+    /// board DAC data.
+    ///
+    /// With [`DataType::F32`], these signals are stored in the `*_f32` fields of
+    /// [`RhsData`](crate::RhsData) and the `f64` fields are left `None`. Ignored when
+    /// [`raw`](LoadOptions::raw) is set.
+    pub data_type: DataType,
+    /// Directory in which to spill amplifier, DC amplifier, board ADC, and board
+    /// DAC data to temporary files, or `None` to keep all data in memory.
+    ///
+    /// Each loaded file gets its own subdirectory, deleted when the data is
+    /// dropped. The spilled signals are read back by channel through
+    /// [`RhsData::amplifier_channel`](crate::RhsData::amplifier_channel) and its
+    /// siblings, so [`raw`](LoadOptions::raw) and [`data_type`](LoadOptions::data_type)
+    /// do not apply to them, and block processors do not see them. Only
+    /// traditional RHS files are spilled. See the [`spill`](crate::spill) module.
+    pub spill_dir: Option<PathBuf>,
+    /// How files are read from disk.
+    pub io: IoOptions,
+    /// Whether to log progress messages and summaries.
+    ///
+    /// Messages are emitted through the [`log`] crate, mostly at the `info` level,
+    /// and are only shown if the application installs a logger.
+    pub verbose: bool,
+    /// Callback receiving progress reports, replacing the logged percentages.
+    ///
+    /// When loading a directory, progress is reported for each file in turn.
+    pub progress: Option<Arc<dyn ProgressCallback>>,
+    /// Thresholds for leaving likely-bad amplifier channels out of the loaded
+    /// recording, or `None` to keep every channel.
+    ///
+    /// After loading, the amplifier channels flagged by
+    /// [`RhsFile::channel_quality`] are removed as with
+    /// [`RhsFile::mask_bad_channels`], and each one is logged. The checks see only
+    /// the loaded channels and time window. Block processors run before the
+    /// channels are removed and see all of them. Cannot be combined with
+    /// [`spill_dir`](LoadOptions::spill_dir). See the [`quality`]
+    /// module.
+    pub mask_bad_channels: Option<QualityOptions>,
+    /// Whether to hash the contents of every file read, listing the hashes in
+    /// [`RhsFile::fingerprints`].
+    ///
+    /// Each file is read once more in full. Needs the `fingerprint` feature, and
+    /// cannot be used with remote files. See the [`fingerprint`](crate::fingerprint)
+    /// module.
+    pub fingerprint: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions {
+            channels: None,
+            time_range: None,
+            wallclock_range: None,
+            file_pattern: None,
+            parse_mode: ParseMode::Standard,
+            header_compatibility: HeaderCompatibility::Standard,
+            gap_handling: GapHandling::Keep,
+            skip_corrupt_files: false,
+            duplicate_handling: DuplicateHandling::Error,
+            parallel_files: 1,
+            memory_budget: None,
+            notch: NotchMode::Auto,
+            zero_phase_notch: false,
+            notch_bandwidth: INTAN_NOTCH_BANDWIDTH,
+            notch_sections: 1,
+            raw: false,
+            data_type: DataType::F64,
+            spill_dir: None,
+            io: IoOptions::default(),
+            verbose: true,
+            progress: None,
+            mask_bad_channels: None,
+            fingerprint: false,
+        }
+    }
+}
+
+impl fmt::Debug for LoadOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LoadOptions")
+            .field("channels", &self.channels)
+            .field("time_range", &self.time_range)
+            .field("wallclock_range", &self.wallclock_range)
+            .field("file_pattern", &self.file_pattern)
+            .field("parse_mode", &self.parse_mode)
+            .field("header_compatibility", &self.header_compatibility)
+            .field("gap_handling", &self.gap_handling)
+            .field("skip_corrupt_files", &self.skip_corrupt_files)
+            .field("duplicate_handling", &self.duplicate_handling)
+            .field("parallel_files", &self.parallel_files)
+            .field("memory_budget", &self.memory_budget)
+            .field("notch", &self.notch)
+            .field("zero_phase_notch", &self.zero_phase_notch)
+            .field("notch_bandwidth", &self.notch_bandwidth)
+            .field("notch_sections", &self.notch_sections)
+            .field("raw", &self.raw)
+            .field("data_type", &self.data_type)
+            .field("spill_dir", &self.spill_dir)
+            .field("io", &self.io)
+            .field("verbose", &self.verbose)
+            .field(
+                "progress",
+                &self.progress.as_ref().map(|_| "ProgressCallback"),
+            )
+            .field("mask_bad_channels", &self.mask_bad_channels)
+            .field("fingerprint", &self.fingerprint)
+            .finish()
+    }
+}
+
+impl LoadOptions {
+    /// Checks that the options describe a possible load
+    pub(crate) fn check(&self) -> Result<(), IntanError> {
+        if self.io.buffer_size == 0 {
+            return Err(IntanError::InvalidOptions(
+                "The read buffer size must be greater than 0".to_string(),
+            ));
+        }
+        if let Some(range) = &self.time_range {
+            // Also rejects NaN bounds
+            if !(range.start >= 0.0 && range.end > range.start) {
+                return Err(IntanError::InvalidOptions(format!(
+                    "Invalid time range {}..{} s: the start must be at least 0 and before the end",
+                    range.start, range.end
+                )));
+            }
+        }
+        if let Some(range) = &self.wallclock_range {
+            if range.end <= range.start {
+                return Err(IntanError::InvalidOptions(format!(
+                    "Invalid wall-clock range {}..{}: the start must be before the end",
+                    range.start, range.end
+                )));
+            }
+            if self.time_range.is_some() {
+                return Err(IntanError::InvalidOptions(
+                    "A wall-clock range cannot be combined with a time range".to_string(),
+                ));
+            }
+        }
+        if self.gap_handling.pads() && self.spill_dir.is_some() {
+            return Err(IntanError::InvalidOptions(
+                "Gaps between files cannot be padded in spilled data".to_string(),
+            ));
+        }
+        if self.gap_handling == GapHandling::PadNan && self.raw {
+            return Err(IntanError::InvalidOptions(
+                "Gaps cannot be padded with NaN in raw data; pad with zeros instead".to_string(),
+            ));
+        }
+        if self.parallel_files == 0 {
+            return Err(IntanError::InvalidOptions(
+                "At least one file must be read at a time".to_string(),
+            ));
+        }
+        if self.pipelined() && self.spill_dir.is_some() {
+            return Err(IntanError::InvalidOptions(
+                "Spilled files cannot be loaded in parallel or within a memory budget"
+                    .to_string(),
+            ));
+        }
+        if self.pipelined() && self.gap_handling.pads() {
+            return Err(IntanError::InvalidOptions(
+                "Gaps between files cannot be padded when loading in parallel or within a \
+                 memory budget"
+                    .to_string(),
+            ));
+        }
+        check_notch_shape(self.notch_bandwidth, self.notch_sections)
+            .map_err(|e| IntanError::InvalidOptions(e.to_string()))?;
+        if self.mask_bad_channels.is_some() && self.spill_dir.is_some() {
+            return Err(IntanError::InvalidOptions(
+                "Bad channels cannot be masked in spilled data".to_string(),
+            ));
+        }
+        if self.fingerprint && cfg!(not(feature = "fingerprint")) {
+            return Err(IntanError::InvalidOptions(
+                "Fingerprinting files requires the `fingerprint` feature".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the files of a directory are loaded by [`crate::pipeline`]
+    pub(crate) fn pipelined(&self) -> bool {
+        self.parallel_files > 1 || self.memory_budget.is_some()
+    }
+}
+
+/// Tuning of the reads from disk.
+///
+/// The defaults suit local disks. On network filesystems, larger buffers and an
+/// explicit read-ahead usually cut the number of round trips. Read-ahead and
+/// unbuffered reads are hints that take effect on Linux and are ignored elsewhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoOptions {
+    /// Size of the read buffer in bytes (64 KiB by default).
+    pub buffer_size: usize,
+    /// How far the operating system reads ahead of the reader.
+    pub read_ahead: ReadAhead,
+    /// Whether to read with `O_DIRECT`, bypassing the operating system's page cache.
+    ///
+    /// Reads then go straight from the device into a buffer of `buffer_size`
+    /// rounded up to whole 4 KiB pages, and do not evict other files from the
+    /// cache. Filesystems that do not support `O_DIRECT` fall back to buffered
+    /// reads.
+    pub unbuffered: bool,
+}
+
+impl Default for IoOptions {
+    fn default() -> Self {
+        IoOptions {
+            buffer_size: 65536,
+            read_ahead: ReadAhead::Auto,
+            unbuffered: false,
+        }
+    }
+}
+
+/// Read-ahead requested from the operating system, see [`IoOptions::read_ahead`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadAhead {
+    /// Leave read-ahead to the operating system
+    #[default]
+    Auto,
+    /// Declare the file as read sequentially, which enlarges the read-ahead window
+    Sequential,
+    /// Keep asking the operating system to prefetch this many bytes past the reader
+    Bytes(u64),
+    /// Declare the file as read randomly, which turns read-ahead off
+    Off,
+}
+
+/// When the notch filter set in a file's header is applied to amplifier data.
+///
+/// RHX versions before 3.0 save amplifier data unfiltered and record the notch
+/// setting (50 or 60 Hz) in the header, so the reader applies the filter itself.
+/// Version 3.0 and later apply the filter before saving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NotchMode {
+    /// Apply the filter to files saved by RHX versions before 3.0
+    #[default]
+    Auto,
+    /// Never apply the filter, keeping the signal as saved
+    Skip,
+    /// Apply the filter whenever the header sets one, whatever the RHX version.
+    ///
+    /// For 3.0+ files whose data is known to have been saved without the filter.
+    Force,
+}
+
+/// How to treat header values that the RHS format does not define.
+///
+/// Files in the wild are sometimes malformed, and pre-release RHX versions may
+/// write values this library does not know. The checked values are the file
+/// version, the notch filter mode, and the signal type of each channel. The
+/// modes treat them as follows:
+///
+/// - A channel of unknown signal type that is enabled fails the load, except
+///   under [`Lenient`](ParseMode::Lenient), which leaves the channel out. If the
+///   file stores samples of the channel, they are then read as those of other
+///   channels, which usually shows as gaps in the timestamps.
+/// - Every other unexpected value fails the load under
+///   [`Strict`](ParseMode::Strict), and is ignored under
+///   [`Standard`](ParseMode::Standard).
+///
+/// Under [`Lenient`](ParseMode::Lenient), each unexpected value is logged and
+/// listed in [`RhsFile::warnings`] as a
+/// [`LoadWarning::UnexpectedHeaderValue`](crate::LoadWarning::UnexpectedHeaderValue).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Fail on any value the format does not define
+    Strict,
+    /// Fail on enabled channels of unknown type, and ignore other unexpected
+    /// values
+    #[default]
+    Standard,
+    /// Continue past every unexpected value, recording a warning for each
+    Lenient,
+}
+
+/// Which differences between file headers prevent combining the files.
+///
+/// Files are combined under the header of the first one, so whatever differs in
+/// later files is lost. The policies treat differences as follows:
+///
+/// - The sample rate, the number of channels of each signal type, and whether DC
+///   amplifier data was saved must always match.
+/// - Amplifier channel names must match, except under
+///   [`Lenient`](HeaderCompatibility::Lenient), which logs a warning.
+/// - Acquisition settings (bandwidths, DSP, notch filter, settle and charge
+///   recovery, stimulation step size, board mode, and reference) must match under
+///   [`Strict`](HeaderCompatibility::Strict), and are logged as warnings otherwise.
+/// - Impedance measurements, custom channel names, and notes must match under
+///   [`Strict`](HeaderCompatibility::Strict), and are ignored otherwise.
+///
+/// Impedances are commonly re-measured during a session, and notes edited, so
+/// only the strict policy rejects them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeaderCompatibility {
+    /// Every header field other than the file's data must match
+    Strict,
+    /// The channels must match, and changed settings are logged
+    #[default]
+    Standard,
+    /// Only the sample rate and the shape of the data must match, as for files
+    /// whose channels were renamed or remapped during the session
+    Lenient,
+}
+
+/// What to do where the timestamps of consecutive files of a directory are not
+/// contiguous.
+///
+/// A gap is left wherever a file's first timestamp is not one more than the last
+/// timestamp of the file before it, for example when recording was stopped and
+/// restarted, or a file of the session is missing. Gaps within a file, left by
+/// dropped packets, are always kept as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GapHandling {
+    /// Concatenate the samples and keep the timestamps as saved, so they jump at
+    /// each gap.
+    ///
+    /// [`RhsFile::segments`] lists the continuous stretches between the gaps, and
+    /// [`RhsFile::source_files`] the samples of each file.
+    #[default]
+    Keep,
+    /// Return an error at the first gap
+    Error,
+    /// Fill each gap with samples of value zero, so the timestamps increase by one
+    /// throughout and sample indices map linearly to time.
+    ///
+    /// Analog signals are padded with zero volts, which raw data stores as the
+    /// code at the middle of the ADC range, and digital signals and stimulation
+    /// flags with zeros. Files whose timestamps go backwards cannot be padded.
+    PadZero,
+    /// Fill each gap as with [`PadZero`](GapHandling::PadZero), but with NaN in
+    /// the floating-point signals, including the stimulation current, so the
+    /// padding cannot be mistaken for data.
+    PadNan,
+}
+
+impl GapHandling {
+    /// Whether gaps are filled with padding samples
+    pub(crate) fn pads(self) -> bool {
+        matches!(self, GapHandling::PadZero | GapHandling::PadNan)
+    }
+}
+
+/// What to do with files of a directory that repeat the samples of another file.
+///
+/// Backup workflows can leave a copy of a file in the session folder next to the
+/// original, which would otherwise be combined as if it held new samples. A file
+/// is taken to repeat another when its first timestamp falls within the other
+/// file's timestamps and its first data block is byte for byte the block of the
+/// other file with that timestamp. Recordings whose timestamps restart from zero
+/// are not mistaken for copies, since their data differs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateHandling {
+    /// Return an error naming the copy, the file it repeats, and the samples they
+    /// share
+    #[default]
+    Error,
+    /// Leave out every file whose samples all repeat those of another, listing it
+    /// in [`RhsFile::skipped_files`] with a warning.
+    ///
+    /// Of two identical files, the later one in recording order is left out. A
+    /// file that repeats part of another and continues past its end still
+    /// returns an error, as neither file can be left out without losing samples.
+    Deduplicate,
+    /// Combine every file without checking for copies
+    Keep,
+}
+
+/// Floating-point type in which scaled analog signals are stored.
+///
+/// Single precision halves the memory of the scaled signals. Its 24-bit mantissa
+/// still resolves every 16-bit ADC step, so only the rounding of each value changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DataType {
+    /// `Array2<f64>`, in the `amplifier_data`, `board_adc_data`, ... fields
+    #[default]
+    F64,
+    /// `Array2<f32>`, in the `amplifier_data_f32`, `board_adc_data_f32`, ... fields
+    F32,
+}
+
+/// Phase of a load, as reported in [`LoadProgress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadPhase {
+    /// The header has been read; reported once per file
+    Header,
+    /// Data blocks are being read and parsed
+    Data,
+    /// The notch filter is being applied to amplifier channels
+    Filtering,
+}
+
+/// A progress report passed to a [`ProgressCallback`].
+///
+/// Counts that do not apply to the current phase are 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadProgress {
+    /// Current phase of the load
+    pub phase: LoadPhase,
+    /// Bytes of the file read so far, counting data blocks skipped by a time range
+    pub bytes_read: u64,
+    /// Data blocks parsed so far
+    pub blocks_parsed: u64,
+    /// Data blocks to parse in total
+    pub total_blocks: u64,
+    /// Amplifier channels notch filtered so far
+    pub channels_filtered: usize,
+    /// Amplifier channels to notch filter in total, or 0 if no filter is applied
+    pub total_channels: usize,
+}
+
+/// Receiver of progress reports during a load.
+///
+/// Closures taking a `&LoadProgress` implement this trait, so they can be passed
+/// directly to [`Loader::progress`].
+pub trait ProgressCallback: Send + Sync {
+    /// Called each time the load makes progress.
+    fn report(&self, progress: &LoadProgress);
+}
+
+impl<F> ProgressCallback for F
+where
+    F: Fn(&LoadProgress) + Send + Sync,
+{
+    fn report(&self, progress: &LoadProgress) {
+        self(progress)
+    }
+}
+
+/// A channel to load, identified by name or by position.
+///
+/// Strings convert to [`ChannelSelector::Name`], so channel names can be passed
+/// directly to [`Loader::channels`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelSelector {
+    /// Channel with this native or custom name (`A-000`, `ANALOG-IN-1`, ...)
+    Name(String),
+    /// Amplifier channel at this index in `amplifier_channels`
+    Amplifier(usize),
+    /// Board ADC channel at this index in `board_adc_channels`
+    BoardAdc(usize),
+    /// Board DAC channel at this index in `board_dac_channels`
+    BoardDac(usize),
+    /// Digital input channel at this index in `board_dig_in_channels`
+    BoardDigIn(usize),
+    /// Digital output channel at this index in `board_dig_out_channels`
+    BoardDigOut(usize),
+}
+
+impl From<&str> for ChannelSelector {
+    fn from(name: &str) -> Self {
+        ChannelSelector::Name(name.to_string())
+    }
+}
+
+impl From<String> for ChannelSelector {
+    fn from(name: String) -> Self {
+        ChannelSelector::Name(name)
+    }
+}
+
+impl fmt::Display for ChannelSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChannelSelector::Name(name) => write!(f, "channel '{}'", name),
+            ChannelSelector::Amplifier(index) => write!(f, "amplifier channel {}", index),
+            ChannelSelector::BoardAdc(index) => write!(f, "board ADC channel {}", index),
+            ChannelSelector::BoardDac(index) => write!(f, "board DAC channel {}", index),
+            ChannelSelector::BoardDigIn(index) => write!(f, "digital input channel {}", index),
+            ChannelSelector::BoardDigOut(index) => write!(f, "digital output channel {}", index),
+        }
+    }
+}
+
+/// Builder for loading a recording with [`LoadOptions`].
+///
+/// See the [module documentation](crate::loader) for an overview.
+pub struct Loader<'a> {
+    path: PathBuf,
+    options: LoadOptions,
+    processors: Vec<&'a mut dyn BlockProcessor>,
+}
+
+impl<'a> Loader<'a> {
+    /// Starts a load of the RHS file or directory at `path` with the default options.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Loader::with_options(path, LoadOptions::default())
+    }
+
+    /// Starts a load of the RHS file or directory at `path` with the given options.
+    pub fn with_options<P: AsRef<Path>>(path: P, options: LoadOptions) -> Self {
+        Loader {
+            path: path.as_ref().to_path_buf(),
+            options,
+            processors: Vec::new(),
+        }
+    }
+
+    /// Keeps only these channels, given by name or as [`ChannelSelector`]s.
+    ///
+    /// See [`LoadOptions::channels`].
+    pub fn channels<I, S>(mut self, channels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<ChannelSelector>,
+    {
+        self.options.channels = Some(channels.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Keeps only the samples between `range.start` and `range.end` seconds.
+    ///
+    /// See [`LoadOptions::time_range`].
+    pub fn time_range(mut self, range: Range<f64>) -> Self {
+        self.options.time_range = Some(range);
+        self
+    }
+
+    /// Keeps only the samples recorded between the wall-clock times `range.start`
+    /// and `range.end`.
+    ///
+    /// See [`LoadOptions::wallclock_range`].
+    pub fn wallclock_range(mut self, range: Range<DateTime>) -> Self {
+        self.options.wallclock_range = Some(range);
+        self
+    }
+
+    /// Combines only the .rhs files of a directory whose names match the glob
+    /// `pattern`.
+    ///
+    /// See [`LoadOptions::file_pattern`].
+    pub fn file_pattern<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.options.file_pattern = Some(pattern.into());
+        self
+    }
+
+    /// Sets how header values that the RHS format does not define are treated.
+    ///
+    /// See [`ParseMode`].
+    pub fn parse_mode(mut self, mode: ParseMode) -> Self {
+        self.options.parse_mode = mode;
+        self
+    }
+
+    /// Sets which header differences prevent combining the files of a directory.
+    ///
+    /// See [`LoadOptions::header_compatibility`].
+    pub fn header_compatibility(mut self, policy: HeaderCompatibility) -> Self {
+        self.options.header_compatibility = policy;
+        self
+    }
+
+    /// Sets what to do where the files of a directory were not recorded back to
+    /// back.
+    ///
+    /// See [`LoadOptions::gap_handling`].
+    pub fn gap_handling(mut self, gap_handling: GapHandling) -> Self {
+        self.options.gap_handling = gap_handling;
+        self
+    }
+
+    /// Leaves out the files of a directory that cannot be read, listing them in
+    /// [`RhsFile::skipped_files`], instead of failing the load.
+    ///
+    /// See [`LoadOptions::skip_corrupt_files`].
+    pub fn skip_corrupt_files(mut self, skip: bool) -> Self {
+        self.options.skip_corrupt_files = skip;
+        self
+    }
+
+    /// Sets what to do with files of a directory that repeat the samples of
+    /// another file.
+    ///
+    /// See [`LoadOptions::duplicate_handling`].
+    pub fn duplicate_handling(mut self, handling: DuplicateHandling) -> Self {
+        self.options.duplicate_handling = handling;
+        self
+    }
+
+    /// Reads up to `files` files of a directory at the same time.
+    ///
+    /// See [`LoadOptions::parallel_files`].
+    pub fn parallel_files(mut self, files: usize) -> Self {
+        self.options.parallel_files = files;
+        self
+    }
+
+    /// Bounds the memory used by the data of a directory load to `bytes`.
+    ///
+    /// See [`LoadOptions::memory_budget`].
+    pub fn memory_budget(mut self, bytes: u64) -> Self {
+        self.options.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Sets when the header's notch filter is applied to amplifier data.
+    ///
+    /// See [`NotchMode`].
+    pub fn notch(mut self, notch: NotchMode) -> Self {
+        self.options.notch = notch;
+        self
+    }
+
+    /// Turns the automatic notch filter on ([`NotchMode::Auto`]) or off
+    /// ([`NotchMode::Skip`]).
+    pub fn apply_notch(self, apply_notch: bool) -> Self {
+        self.notch(if apply_notch {
+            NotchMode::Auto
+        } else {
+            NotchMode::Skip
+        })
+    }
+
+    /// Applies the notch filter forward and backward in time, so it shifts no phase.
+    ///
+    /// See [`LoadOptions::zero_phase_notch`].
+    pub fn zero_phase_notch(mut self, zero_phase: bool) -> Self {
+        self.options.zero_phase_notch = zero_phase;
+        self
+    }
+
+    /// Sets the width of the notch filter in Hz.
+    ///
+    /// See [`LoadOptions::notch_bandwidth`].
+    pub fn notch_bandwidth(mut self, bandwidth: f64) -> Self {
+        self.options.notch_bandwidth = bandwidth;
+        self
+    }
+
+    /// Sets the number of notch filter sections in cascade.
+    ///
+    /// See [`LoadOptions::notch_sections`].
+    pub fn notch_sections(mut self, sections: usize) -> Self {
+        self.options.notch_sections = sections;
+        self
+    }
+
+    /// Keeps the analog signals as raw ADC codes rather than scaling them.
+    ///
+    /// See [`LoadOptions::raw`].
+    pub fn raw(mut self, raw: bool) -> Self {
+        self.options.raw = raw;
+        self
+    }
+
+    /// Sets the floating-point type of the scaled analog signals.
+    ///
+    /// See [`LoadOptions::data_type`].
+    pub fn data_type(mut self, data_type: DataType) -> Self {
+        self.options.data_type = data_type;
+        self
+    }
+
+    /// Spills the large analog signals to temporary files in `dir` instead of
+    /// keeping them in memory.
+    ///
+    /// See [`LoadOptions::spill_dir`].
+    pub fn spill_to<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.options.spill_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the size of the read buffer in bytes.
+    ///
+    /// See [`IoOptions::buffer_size`].
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.options.io.buffer_size = buffer_size;
+        self
+    }
+
+    /// Sets the read-ahead requested from the operating system.
+    ///
+    /// See [`ReadAhead`].
+    pub fn read_ahead(mut self, read_ahead: ReadAhead) -> Self {
+        self.options.io.read_ahead = read_ahead;
+        self
+    }
+
+    /// Reads with `O_DIRECT`, bypassing the page cache.
+    ///
+    /// See [`IoOptions::unbuffered`].
+    pub fn unbuffered(mut self, unbuffered: bool) -> Self {
+        self.options.io.unbuffered = unbuffered;
+        self
+    }
+
+    /// Sets whether progress messages are logged.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.options.verbose = verbose;
+        self
+    }
+
+    /// Logs no progress messages or summaries; the same as `verbose(false)`.
+    pub fn quiet(self) -> Self {
+        self.verbose(false)
+    }
+
+    /// Reports progress to `callback` instead of printing it.
+    ///
+    /// See [`LoadOptions::progress`].
+    pub fn progress<F: ProgressCallback + 'static>(mut self, callback: F) -> Self {
+        self.options.progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Leaves the amplifier channels that fail the quality checks out of the
+    /// loaded recording.
+    ///
+    /// See [`LoadOptions::mask_bad_channels`].
+    pub fn mask_bad_channels(mut self, options: QualityOptions) -> Self {
+        self.options.mask_bad_channels = Some(options);
+        self
+    }
+
+    /// Hashes the contents of every file read, for provenance.
+    ///
+    /// See [`LoadOptions::fingerprint`].
+    pub fn fingerprint(mut self, fingerprint: bool) -> Self {
+        self.options.fingerprint = fingerprint;
+        self
+    }
+
+    /// Adds a block processor, run after the processors added before it.
+    pub fn processor(mut self, processor: &'a mut dyn BlockProcessor) -> Self {
+        self.processors.push(processor);
+        self
+    }
+
+    /// Returns the options of this load.
+    pub fn options(&self) -> &LoadOptions {
+        &self.options
+    }
+
+    /// Loads the recording.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`load`](crate::load), and an error if a selected
+    /// channel does not exist or the time range is invalid or lies outside the
+    /// recording.
+    pub fn load(mut self) -> Result<RhsFile, IntanError> {
+        let mut rhs_file =
+            crate::load_with_options(&self.path, &self.options, &mut self.processors)?;
+        quality::mask_loaded(&mut rhs_file, &self.options)?;
+        Ok(rhs_file)
+    }
+}
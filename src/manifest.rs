@@ -0,0 +1,341 @@
+//! Manifests of recording directories, built from headers alone.
+//!
+//! [`scan_directory`] lists the RHS files of a directory with what a catalog needs
+//! to know about each one: its duration, channel counts, first timestamp, and
+//! whether it holds any data. Only the header and the first timestamp of each file
+//! are read, and the number of samples follows from the file size, so a session of
+//! many gigabytes is listed in milliseconds.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::manifest::scan_directory;
+//!
+//! for file in scan_directory("session/").unwrap() {
+//!     println!(
+//!         "{}: {:.1} s, {} amplifier channels, first timestamp {:?}",
+//!         file.path.display(),
+//!         file.duration,
+//!         file.num_amplifier_channels,
+//!         file.first_timestamp
+//!     );
+//! }
+//! ```
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use log::warn;
+
+use crate::datetime::DateTime;
+use crate::loader::DuplicateHandling;
+use crate::reader::{self, SAMPLES_PER_DATA_BLOCK};
+use crate::types::{IntanError, RhsHeader, SkippedFile};
+
+/// Summary of one RHS file, read from its header and size.
+#[derive(Debug, Clone)]
+pub struct FileManifest {
+    /// Path of the file
+    pub path: PathBuf,
+    /// Header of the file
+    pub header: RhsHeader,
+    /// Size of the file in bytes
+    pub file_size: u64,
+    /// Number of samples per channel in the file's whole data blocks
+    pub num_samples: usize,
+    /// Duration of the samples (s)
+    pub duration: f32,
+    /// Number of amplifier channels
+    pub num_amplifier_channels: usize,
+    /// Number of board ADC channels
+    pub num_board_adc_channels: usize,
+    /// Number of board DAC channels
+    pub num_board_dac_channels: usize,
+    /// Number of board digital input channels
+    pub num_board_dig_in_channels: usize,
+    /// Number of board digital output channels
+    pub num_board_dig_out_channels: usize,
+    /// Timestamp of the first sample, or `None` if the file holds no data
+    pub first_timestamp: Option<i64>,
+    /// Whether the file holds at least one whole data block
+    pub data_present: bool,
+    /// Bytes after the last whole data block, as left by a recording that was
+    /// interrupted while writing; 0 for a complete file
+    pub trailing_bytes: u64,
+}
+
+impl FileManifest {
+    /// Returns the time of the first sample (s), from its timestamp and the
+    /// sample rate, or `None` if the file holds no data.
+    pub fn start_time(&self) -> Option<f64> {
+        self.first_timestamp
+            .map(|timestamp| timestamp as f64 / self.header.sample_rate as f64)
+    }
+}
+
+/// Lists the RHS files of a directory in recording order, with a summary of each.
+///
+/// Files are ordered as [`load`](crate::load) combines them: by the start time in
+/// their names, or by their first timestamps. Subdirectories and files with other
+/// extensions are ignored.
+///
+/// # Errors
+///
+/// Returns an error if the directory cannot be read or holds no RHS files, and the
+/// errors of [`read_manifest`] for each file.
+pub fn scan_directory<P: AsRef<Path>>(path: P) -> Result<Vec<FileManifest>, IntanError> {
+    crate::find_rhs_files(path.as_ref())?
+        .iter()
+        .map(read_manifest)
+        .collect()
+}
+
+/// Summarizes a single RHS file from its header and size.
+///
+/// # Errors
+///
+/// Returns an [`IntanError::File`] if the file cannot be read or has an invalid
+/// header.
+pub fn read_manifest<P: AsRef<Path>>(path: P) -> Result<FileManifest, IntanError> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| IntanError::from(e).in_file(path, None))?;
+    let mut reader = BufReader::new(file);
+    summarize_file(path, &mut reader).map_err(|e| reader::file_error(path, &mut reader, e))
+}
+
+/// Summarizes the RHS file at `path` read by `reader`
+fn summarize_file(
+    path: &Path,
+    reader: &mut BufReader<File>,
+) -> Result<FileManifest, Box<dyn Error>> {
+    let file_size = reader.get_ref().metadata()?.len();
+    let header = reader::read_header(reader)?;
+
+    let bytes_per_block = reader::get_bytes_per_data_block(&header)? as u64;
+    let data_size = file_size - reader.stream_position()?;
+    let num_blocks = data_size / bytes_per_block;
+    let num_samples = reader::sample_index(num_blocks * SAMPLES_PER_DATA_BLOCK as u64)?;
+
+    // Each data block starts with the timestamps of its samples
+    let first_timestamp = if num_blocks > 0 {
+        Some(reader.read_i32::<LittleEndian>()? as i64)
+    } else {
+        None
+    };
+
+    Ok(FileManifest {
+        path: path.to_path_buf(),
+        file_size,
+        num_samples,
+        duration: num_samples as f32 / header.sample_rate,
+        num_amplifier_channels: header.amplifier_channels.len(),
+        num_board_adc_channels: header.board_adc_channels.len(),
+        num_board_dac_channels: header.board_dac_channels.len(),
+        num_board_dig_in_channels: header.board_dig_in_channels.len(),
+        num_board_dig_out_channels: header.board_dig_out_channels.len(),
+        first_timestamp,
+        data_present: num_blocks > 0,
+        trailing_bytes: data_size % bytes_per_block,
+        header,
+    })
+}
+
+/// Orders the RHS files of a session by when they were recorded.
+///
+/// Renamed files no longer sort by name, so the order is taken from the start
+/// time RHX writes into each file name (`name_YYMMDD_HHMMSS.rhs`) when every file
+/// has one, and otherwise from the first timestamp of each file when every file
+/// holds data. Files with the same start time, or that cannot be ordered either
+/// way, are ordered by name. First timestamps are compared as the 32-bit values
+/// RHX stores, so files recorded after the timestamps wrapped around follow the
+/// files before the wrap.
+///
+/// Files whose timestamps continue exactly where another file's end must follow
+/// it; an order that contradicts such a continuation is an error, since it would
+/// combine the samples out of sequence. Files whose header cannot be read yet,
+/// such as one RHX has just started, are left out of this check.
+pub(crate) fn sort_by_recording_time(files: &mut [PathBuf]) -> Result<(), Box<dyn Error>> {
+    files.sort();
+    let start_times: Option<Vec<DateTime>> =
+        files.iter().map(DateTime::from_rhx_filename).collect();
+    let manifests: Vec<Option<FileManifest>> =
+        files.iter().map(|path| read_manifest(path).ok()).collect();
+
+    // Sorting is stable, so ties keep the order by name
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    if let Some(start_times) = &start_times {
+        order.sort_by_key(|&i| start_times[i]);
+    } else if let Some(first_timestamps) = manifests
+        .iter()
+        .map(|manifest| manifest.as_ref()?.first_timestamp)
+        .collect::<Option<Vec<i64>>>()
+    {
+        order.sort_by_key(|&i| first_timestamps[i]);
+        // The session starts after the largest step between consecutive first
+        // timestamps, counted around the 32-bit range; without a wrap, this is the
+        // step from the last file back to the first
+        let step = |k: usize| {
+            let previous = order[(k + order.len() - 1) % order.len()];
+            (first_timestamps[order[k]] - first_timestamps[previous]).rem_euclid(1 << 32)
+        };
+        if let Some(start) = (0..order.len()).rev().max_by_key(|&k| step(k)) {
+            order.rotate_left(start);
+        }
+    }
+
+    // A file continuing another one's timestamps must come after it
+    for (position, &i) in order.iter().enumerate() {
+        let Some(manifest) = &manifests[i] else {
+            continue;
+        };
+        let Some(first_timestamp) = manifest.first_timestamp else {
+            continue;
+        };
+        // Compared in 32 bits, as a file may continue past a wrap
+        let end = (first_timestamp + manifest.num_samples as i64) as i32;
+        for &j in &order[..position] {
+            let continues = manifests[j].as_ref().is_some_and(|next| {
+                next.first_timestamp.map(|t| t as i32) == Some(end) && next.num_samples > 0
+            });
+            if continues {
+                return Err(Box::new(IntanError::Other(format!(
+                    "{} comes before {} in recording time, but its timestamps continue \
+                     from the end of that file",
+                    files[j].display(),
+                    files[i].display()
+                ))));
+            }
+        }
+    }
+
+    let sorted: Vec<PathBuf> = order.iter().map(|&i| files[i].clone()).collect();
+    files.clone_from_slice(&sorted);
+    Ok(())
+}
+
+/// Finds the files that repeat samples of another file, as backup copies do, and
+/// leaves them out or returns an error as `handling` asks
+///
+/// Returns the remaining files in order, and the files left out with the reason.
+pub(crate) fn remove_duplicate_files(
+    files: &[PathBuf],
+    handling: DuplicateHandling,
+) -> Result<(Vec<PathBuf>, Vec<SkippedFile>), Box<dyn Error>> {
+    if handling == DuplicateHandling::Keep {
+        return Ok((files.to_vec(), Vec::new()));
+    }
+
+    // Unreadable files cannot repeat anything
+    let manifests: Vec<Option<FileManifest>> =
+        files.iter().map(|path| read_manifest(path).ok()).collect();
+    let mut removed = vec![false; files.len()];
+    let mut skipped = Vec::new();
+    for j in 0..files.len() {
+        for i in 0..j {
+            if removed[i] || removed[j] {
+                continue;
+            }
+            let (Some(first), Some(second)) = (&manifests[i], &manifests[j]) else {
+                continue;
+            };
+            // The copy is whichever file starts within the other
+            let (original, copy, copy_index, samples) =
+                if let Some(samples) = repeated_samples(first, second)? {
+                    (first, second, j, samples)
+                } else if let Some(samples) = repeated_samples(second, first)? {
+                    (second, first, i, samples)
+                } else {
+                    continue;
+                };
+            let explanation = format!(
+                "{} repeats samples {}..{} of {}, starting at timestamp {}",
+                copy.path.display(),
+                samples.start,
+                samples.end,
+                original.path.display(),
+                copy.first_timestamp.unwrap_or_default()
+            );
+
+            let extra_samples = copy.num_samples - samples.len();
+            if handling == DuplicateHandling::Error || extra_samples > 0 {
+                let error = IntanError::DuplicateFile {
+                    original: original.path.clone(),
+                    repeated_samples: samples.clone(),
+                    extra_samples,
+                };
+                return Err(Box::new(error.in_file(&copy.path, None)));
+            }
+            warn!("Leaving out {}", explanation);
+            removed[copy_index] = true;
+            skipped.push(SkippedFile {
+                path: copy.path.to_string_lossy().to_string(),
+                error: format!(
+                    "Repeats samples {}..{} of {}",
+                    samples.start,
+                    samples.end,
+                    original.path.display()
+                ),
+            });
+        }
+    }
+
+    let remaining = files
+        .iter()
+        .zip(&removed)
+        .filter(|(_, &removed)| !removed)
+        .map(|(path, _)| path.clone())
+        .collect();
+    Ok((remaining, skipped))
+}
+
+/// Returns the samples of `original` that `copy` repeats, if `copy` starts
+/// within `original` and its first data block is identical to the block of
+/// `original` with the same timestamp
+fn repeated_samples(
+    original: &FileManifest,
+    copy: &FileManifest,
+) -> Result<Option<Range<usize>>, Box<dyn Error>> {
+    let (Some(original_first), Some(copy_first)) = (original.first_timestamp, copy.first_timestamp)
+    else {
+        return Ok(None);
+    };
+    let offset = copy_first - original_first;
+    if offset < 0 || offset >= original.num_samples as i64 {
+        return Ok(None);
+    }
+    let offset = offset as usize;
+    let bytes_per_block = reader::get_bytes_per_data_block(&original.header)? as u64;
+    if !offset.is_multiple_of(SAMPLES_PER_DATA_BLOCK)
+        || reader::get_bytes_per_data_block(&copy.header)? as u64 != bytes_per_block
+    {
+        return Ok(None);
+    }
+
+    let block = (offset / SAMPLES_PER_DATA_BLOCK) as u64;
+    if read_data_block(original, block, bytes_per_block)?
+        != read_data_block(copy, 0, bytes_per_block)?
+    {
+        return Ok(None);
+    }
+    let end = original.num_samples.min(offset + copy.num_samples);
+    Ok(Some(offset..end))
+}
+
+/// Reads the bytes of data block `block` of a file
+fn read_data_block(
+    manifest: &FileManifest,
+    block: u64,
+    bytes_per_block: u64,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let num_blocks = (manifest.num_samples / SAMPLES_PER_DATA_BLOCK) as u64;
+    let data_start = manifest.file_size - manifest.trailing_bytes - num_blocks * bytes_per_block;
+    let mut file = File::open(&manifest.path)?;
+    file.seek(SeekFrom::Start(data_start + block * bytes_per_block))?;
+    let mut bytes = vec![0; bytes_per_block as usize];
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
@@ -0,0 +1,236 @@
+//! Memory requirements of a load, estimated before loading.
+//!
+//! [`estimate_memory`] reads only the header of an RHS file and uses the file size
+//! to work out how many bytes each signal type of the loaded
+//! [`RhsData`](crate::RhsData) will occupy. Batch pipelines can compare the
+//! estimate with the memory available and switch to
+//! [streaming](crate::stream) or [spilling](crate::spill) for recordings that would
+//! not fit, instead of failing part way through a load.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::memory::estimate_memory;
+//! use intan_importer::stream::RhsBlockReader;
+//!
+//! let estimate = estimate_memory("recording.rhs").unwrap();
+//! if estimate.peak > 8 << 30 {
+//!     for block in RhsBlockReader::open("recording.rhs").unwrap() {
+//!         // process block by block
+//!     }
+//! } else {
+//!     let rhs_file = intan_importer::load("recording.rhs").unwrap();
+//! }
+//! ```
+
+use std::fs::File;
+use std::io::BufReader;
+use std::mem::size_of;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::datetime::DateTime;
+use crate::flags::StimFlags;
+use crate::loader::{DataType, LoadOptions};
+use crate::reader::{self, SAMPLES_PER_DATA_BLOCK};
+use crate::types::{IntanError, RhsHeader};
+
+/// Estimated size in bytes of each signal type of the loaded data.
+///
+/// Signal types that will not be loaded take 0 bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    /// Number of samples per channel that will be loaded
+    pub num_samples: usize,
+    /// Bytes of `timestamps`
+    pub timestamps: u64,
+    /// Bytes of the amplifier data, in whichever field the options select
+    pub amplifier: u64,
+    /// Bytes of the DC amplifier data
+    pub dc_amplifier: u64,
+    /// Bytes of `stim_data`
+    pub stim: u64,
+    /// Bytes of the three stimulation flag fields, not counting their runs of set
+    /// samples, which grow with the number of stimulation events
+    pub stim_flags: u64,
+    /// Bytes of the board ADC data
+    pub board_adc: u64,
+    /// Bytes of the board DAC data
+    pub board_dac: u64,
+    /// Bytes of `board_dig_in_data`
+    pub board_dig_in: u64,
+    /// Bytes of `board_dig_out_data`
+    pub board_dig_out: u64,
+    /// Bytes written to temporary files instead of memory when spilling
+    pub spilled: u64,
+    /// Upper bound for the memory in use at any point of the load, including the
+    /// buffers of raw codes read from the file
+    pub peak: u64,
+}
+
+impl MemoryEstimate {
+    /// Estimates the memory needed to load `num_samples` samples of a recording
+    /// described by `header`, with the channels, time range, and data type of
+    /// `options`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a selected channel does not exist, or if the time range
+    /// lies outside the recording.
+    pub fn from_header(
+        header: &RhsHeader,
+        num_samples: usize,
+        options: &LoadOptions,
+    ) -> Result<MemoryEstimate, IntanError> {
+        options.check()?;
+        let options = &*reader::resolve_wallclock_range(options, None)?;
+
+        let mut header = header.clone();
+        reader::select_channels(&mut header, options)?;
+        let samples = reader::selected_samples(options, header.sample_rate, 0, num_samples as u64)?;
+        if num_samples > 0 && samples.is_empty() {
+            return Err(reader::time_range_error(
+                options,
+                num_samples as u64,
+                header.sample_rate,
+            ));
+        }
+
+        Ok(estimate(&header, samples, options))
+    }
+
+    /// Returns the total bytes of the loaded data.
+    pub fn total(&self) -> u64 {
+        self.timestamps
+            + self.amplifier
+            + self.dc_amplifier
+            + self.stim
+            + self.stim_flags
+            + self.board_adc
+            + self.board_dac
+            + self.board_dig_in
+            + self.board_dig_out
+    }
+}
+
+/// Estimates the memory needed to load an RHS file with the default options.
+///
+/// Only the header is read.
+///
+/// # Errors
+///
+/// Returns an error if the path is not an RHS file, if the header is invalid, or
+/// if the file size does not match whole data blocks.
+pub fn estimate_memory<P: AsRef<Path>>(path: P) -> Result<MemoryEstimate, IntanError> {
+    estimate_memory_with_options(path, &LoadOptions::default())
+}
+
+/// Estimates the memory needed to load an RHS file with the channels, time range,
+/// data type, and spill directory of `options`.
+///
+/// # Errors
+///
+/// Returns the errors of [`estimate_memory`] and [`MemoryEstimate::from_header`].
+pub fn estimate_memory_with_options<P: AsRef<Path>>(
+    path: P,
+    options: &LoadOptions,
+) -> Result<MemoryEstimate, IntanError> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        return Err(IntanError::Other(format!(
+            "Memory estimates need a single RHS file, but '{}' is a directory",
+            path.display()
+        )));
+    }
+
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let header = reader::read_header(&mut reader)?;
+    let quiet = LoadOptions {
+        verbose: false,
+        progress: None,
+        ..options.clone()
+    };
+    let (_, _, num_samples) = reader::calculate_data_size(&header, file_size, &quiet, &mut reader)?;
+
+    let options = reader::resolve_wallclock_range(options, DateTime::from_rhx_filename(path))?;
+    MemoryEstimate::from_header(&header, reader::sample_index(num_samples)?, &options)
+}
+
+/// Adds up the arrays a load allocates for the selected channels and samples
+pub(crate) fn estimate(
+    header: &RhsHeader,
+    samples: Range<usize>,
+    options: &LoadOptions,
+) -> MemoryEstimate {
+    let num_samples = samples.len() as u64;
+    // Whole data blocks are read before the samples are trimmed to the time range
+    let blocks = reader::selected_blocks(&samples);
+    let raw_samples = (blocks.end - blocks.start) * SAMPLES_PER_DATA_BLOCK as u64;
+
+    let num_amplifier = header.amplifier_channels.len() as u64;
+    let num_dc_amplifier = if header.dc_amplifier_data_saved {
+        num_amplifier
+    } else {
+        0
+    };
+    let num_adc = header.board_adc_channels.len() as u64;
+    let num_dac = header.board_dac_channels.len() as u64;
+    let num_dig_in = header.board_dig_in_channels.len() as u64;
+    let num_dig_out = header.board_dig_out_channels.len() as u64;
+    let num_analog = num_amplifier + num_dc_amplifier + num_adc + num_dac;
+
+    let analog_size = if options.raw {
+        size_of::<u16>()
+    } else {
+        match options.data_type {
+            DataType::F64 => size_of::<f64>(),
+            DataType::F32 => size_of::<f32>(),
+        }
+    } as u64;
+    let spill = options.spill_dir.is_some();
+    let analog = |num_channels: u64| {
+        if spill {
+            0
+        } else {
+            num_channels * num_samples * analog_size
+        }
+    };
+    let digital = |num_channels: u64| num_channels * num_samples * size_of::<i32>() as u64;
+
+    let mut estimate = MemoryEstimate {
+        num_samples: samples.len(),
+        timestamps: num_samples * size_of::<i64>() as u64,
+        amplifier: analog(num_amplifier),
+        dc_amplifier: analog(num_dc_amplifier),
+        stim: num_amplifier * num_samples * size_of::<f64>() as u64,
+        stim_flags: if num_amplifier > 0 {
+            3 * (size_of::<StimFlags>() as u64
+                + num_amplifier * size_of::<Vec<Range<usize>>>() as u64)
+        } else {
+            0
+        },
+        board_adc: analog(num_adc),
+        board_dac: analog(num_dac),
+        board_dig_in: digital(num_dig_in),
+        board_dig_out: digital(num_dig_out),
+        spilled: if spill {
+            num_analog * num_samples * size_of::<u16>() as u64
+        } else {
+            0
+        },
+        peak: 0,
+    };
+
+    // Raw 16-bit codes of every signal type, with digital channels sharing one word
+    // per sample; spilled signals are read one block at a time
+    let raw_analog = if spill { 0 } else { num_analog };
+    let raw_words =
+        raw_analog + num_amplifier + u64::from(num_dig_in > 0) + u64::from(num_dig_out > 0);
+    let raw_buffers = raw_samples * (raw_words * size_of::<u16>() as u64 + size_of::<i32>() as u64);
+    // Timestamps, and raw codes kept as they are, move into the loaded data
+    let moved = estimate.timestamps + if options.raw { analog(num_analog) } else { 0 };
+    estimate.peak = raw_buffers + estimate.total() - moved;
+    estimate
+}
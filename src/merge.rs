@@ -0,0 +1,197 @@
+//! Concatenation of recordings loaded separately.
+//!
+//! Loading a directory combines its files into one recording. [`RhsFile::merge`]
+//! does the same for recordings that are already in memory, for example files of
+//! one session that were loaded individually, from different directories, or with
+//! a [`Loader`](crate::Loader) each. The headers are checked for compatibility as
+//! when loading a directory, and the samples of the second recording are appended
+//! to those of the first.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let mut recording = load("day1/recording_1.rhs").unwrap();
+//! recording.merge(load("day1_backup/recording_2.rhs").unwrap()).unwrap();
+//! for source in recording.source_files.as_ref().unwrap() {
+//!     println!("{:?} from '{}'", source.samples(), source.path);
+//! }
+//! ```
+
+use std::path::Path;
+
+use crate::loader::HeaderCompatibility;
+use crate::reader;
+use crate::types::{IntanError, RhsData, RhsFile};
+
+impl RhsFile {
+    /// Appends the samples of `other` to this recording.
+    ///
+    /// The headers must be compatible as for loading a directory with the
+    /// [`Standard`](HeaderCompatibility::Standard) policy: the same sample rate,
+    /// the same number of channels of each signal type, and the same amplifier
+    /// channel names, with differing acquisition settings logged as warnings. The
+    /// data of both recordings must also have been
+    /// loaded in the same form, with the same signal types present in the same
+    /// units and spilled to disk in both or neither. The header, settings, and
+    /// notes of this recording are kept.
+    ///
+    /// `other`'s timestamps are continued from the last timestamp of this
+    /// recording as for the files of a directory, so a timestamp counter that
+    /// passed 2^31 between the recordings keeps counting up. The samples of each
+    /// recording are listed in [`source_files`](RhsFile::source_files), with an
+    /// empty path for a recording that was loaded from a single file. The skipped
+    /// files and [`fingerprints`](RhsFile::fingerprints) of both are kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IntanError::IncompatibleHeaders`] if the headers are not
+    /// compatible, and an error if the data was loaded in different forms, in which
+    /// cases the recording is left unchanged.
+    pub fn merge(&mut self, other: RhsFile) -> Result<(), IntanError> {
+        self.merge_with(other, HeaderCompatibility::Standard)
+    }
+
+    /// Appends the samples of `other` to this recording, with the header
+    /// differences allowed by `policy`.
+    ///
+    /// See [`merge`](RhsFile::merge) and [`HeaderCompatibility`].
+    pub fn merge_with(
+        &mut self,
+        mut other: RhsFile,
+        policy: HeaderCompatibility,
+    ) -> Result<(), IntanError> {
+        // Differences are attributed to the first file of the other recording
+        let other_path = other
+            .source_files
+            .as_ref()
+            .and_then(|spans| spans.first())
+            .map_or("", |span| span.path.as_str());
+        let warnings = reader::verify_header_compatibility(
+            &self.header,
+            &other.header,
+            Path::new(other_path),
+            policy,
+        )?;
+        if let (Some(data), Some(other_data)) = (&self.data, &other.data) {
+            verify_same_form(data, other_data)?;
+        }
+        self.warnings.extend(warnings);
+        self.warnings.append(&mut other.warnings);
+
+        let sample_offset = self.num_samples();
+        let unnamed_span =
+            |data: Option<&RhsData>| reader::source_file_span(Path::new(""), data, 0);
+        let mut spans = match self.source_files.take() {
+            Some(spans) => spans,
+            None => vec![unnamed_span(self.data.as_ref())],
+        };
+        let other_spans = match other.source_files.take() {
+            Some(spans) => spans,
+            None => vec![unnamed_span(other.data.as_ref())],
+        };
+
+        let previous_timestamp = self
+            .data
+            .as_ref()
+            .and_then(|d| d.timestamps.last().copied());
+        match (self.data.is_some(), other.data) {
+            (true, Some(mut other_data)) => {
+                reader::unwrap_timestamps(other_data.timestamps.view_mut(), previous_timestamp);
+                reader::combine_data(self, vec![other_data])?;
+            }
+            (false, Some(other_data)) => {
+                self.data = Some(other_data);
+                self.data_present = true;
+            }
+            (_, None) => {}
+        }
+
+        // Offset the other recording's spans, whose timestamps may have been unwrapped
+        let timestamps = self.data.as_ref().map(|data| &data.timestamps);
+        spans.extend(other_spans.into_iter().map(|mut span| {
+            span.sample_offset += sample_offset;
+            if span.num_samples > 0 {
+                span.first_timestamp = timestamps.and_then(|t| t.get(span.sample_offset).copied());
+            }
+            span
+        }));
+        self.source_files = Some(spans);
+        self.skipped_files.append(&mut other.skipped_files);
+        self.fingerprints.append(&mut other.fingerprints);
+        Ok(())
+    }
+}
+
+/// Checks that two recordings' data were loaded with the same signals, units, and
+/// spilling, so that every array has a counterpart to be appended to
+fn verify_same_form(data: &RhsData, other: &RhsData) -> Result<(), IntanError> {
+    if data.units != other.units {
+        return Err(IntanError::Other(
+            "Recordings with data in different units cannot be merged".to_string(),
+        ));
+    }
+    if data.spilled.is_some() != other.spilled.is_some() {
+        return Err(IntanError::Other(
+            "A recording with spilled signals can only be merged with another one".to_string(),
+        ));
+    }
+
+    let (present, other_present) = (loaded(data), loaded(other));
+    for (i, name) in SIGNALS.iter().enumerate() {
+        if present[i] != other_present[i] {
+            return Err(IntanError::Other(format!(
+                "Cannot merge recordings: {} is loaded in only one of them",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Names of the optional signals of [`RhsData`], in the order of [`loaded`]
+const SIGNALS: [&str; 18] = [
+    "amplifier_data",
+    "dc_amplifier_data",
+    "stim_data",
+    "compliance_limit_data",
+    "charge_recovery_data",
+    "amp_settle_data",
+    "board_adc_data",
+    "board_dac_data",
+    "board_dig_in_data",
+    "board_dig_out_data",
+    "amplifier_data_raw",
+    "dc_amplifier_data_raw",
+    "board_adc_data_raw",
+    "board_dac_data_raw",
+    "amplifier_data_f32",
+    "dc_amplifier_data_f32",
+    "board_adc_data_f32",
+    "board_dac_data_f32",
+];
+
+/// Returns whether each signal of [`SIGNALS`] is present in the data
+fn loaded(data: &RhsData) -> [bool; 18] {
+    [
+        data.amplifier_data.is_some(),
+        data.dc_amplifier_data.is_some(),
+        data.stim_data.is_some(),
+        data.compliance_limit_data.is_some(),
+        data.charge_recovery_data.is_some(),
+        data.amp_settle_data.is_some(),
+        data.board_adc_data.is_some(),
+        data.board_dac_data.is_some(),
+        data.board_dig_in_data.is_some(),
+        data.board_dig_out_data.is_some(),
+        data.amplifier_data_raw.is_some(),
+        data.dc_amplifier_data_raw.is_some(),
+        data.board_adc_data_raw.is_some(),
+        data.board_dac_data_raw.is_some(),
+        data.amplifier_data_f32.is_some(),
+        data.dc_amplifier_data_f32.is_some(),
+        data.board_adc_data_f32.is_some(),
+        data.board_dac_data_f32.is_some(),
+    ]
+}
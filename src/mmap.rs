@@ -0,0 +1,221 @@
+//! Memory-mapped access to RHS files.
+//!
+//! Requires the `mmap` feature. [`MappedRhsFile`] maps a file into memory and parses
+//! only its header when opened. Data is deinterleaved on request, for just the
+//! channels and samples asked for, so exploring a long recording touches only the
+//! parts of the file that are actually read. The operating system pages the file
+//! in and out as needed, and nothing is copied until a read is made.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::mmap::MappedRhsFile;
+//!
+//! let file = MappedRhsFile::open("recording.rhs").unwrap();
+//! println!("{} samples of {} amplifier channels",
+//!          file.num_samples(), file.header().amplifier_channels.len());
+//!
+//! // One second of the third amplifier channel, starting one minute in
+//! let fs = file.header().sample_rate as usize;
+//! let trace = file.amplifier_channel(2, 60 * fs..61 * fs).unwrap();
+//! println!("{} samples, first {:.1} μV", trace.len(), trace[0]);
+//! ```
+
+use std::fs::File;
+use std::io::{Cursor, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use memmap2::Mmap;
+use ndarray::{Array1, Axis};
+
+use crate::datetime::DateTime;
+use crate::loader::{ChannelSelector, LoadOptions};
+use crate::reader;
+use crate::types::{IntanError, RhsData, RhsFile, RhsHeader};
+
+/// An RHS file mapped into memory, read lazily by channel and sample range.
+///
+/// See the [module documentation](crate::mmap) for an overview.
+pub struct MappedRhsFile {
+    path: PathBuf,
+    mmap: Mmap,
+    header: RhsHeader,
+    /// Byte offset of the first data block
+    data_offset: u64,
+    num_samples: usize,
+    /// Wall-clock time of the first sample, from the file name
+    start_time: Option<DateTime>,
+}
+
+impl MappedRhsFile {
+    /// Maps an RHS file into memory and reads its header.
+    ///
+    /// The file must not be modified while it is mapped; changes made by other
+    /// processes may show up in, or invalidate, data read afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened or mapped, has an invalid
+    /// header, or its size does not match a whole number of data blocks.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, IntanError> {
+        let path = path.as_ref();
+        let start_time = DateTime::from_rhx_filename(path);
+        let file = File::open(path).map_err(|e| IntanError::from(e).in_file(path, None))?;
+        // Safety: the mapping is only ever read, and the caller is told not to
+        // modify the file while it is mapped.
+        let mmap =
+            unsafe { Mmap::map(&file) }.map_err(|e| IntanError::from(e).in_file(path, None))?;
+
+        let quiet = LoadOptions {
+            verbose: false,
+            ..LoadOptions::default()
+        };
+        let mut cursor = Cursor::new(&mmap[..]);
+        let (header, num_samples) = reader::read_header(&mut cursor)
+            .and_then(|header| {
+                let (_, _, num_samples) =
+                    reader::calculate_data_size(&header, mmap.len() as u64, &quiet, &mut cursor)?;
+                Ok((header, reader::sample_index(num_samples)?))
+            })
+            .map_err(|e| reader::file_error(path, &mut cursor, e))?;
+        let data_offset = cursor.position();
+
+        Ok(MappedRhsFile {
+            path: path.to_path_buf(),
+            mmap,
+            header,
+            data_offset,
+            num_samples,
+            start_time,
+        })
+    }
+
+    /// Returns the header of the file.
+    pub fn header(&self) -> &RhsHeader {
+        &self.header
+    }
+
+    /// Returns the number of samples per channel in the file.
+    pub fn num_samples(&self) -> usize {
+        self.num_samples
+    }
+
+    /// Returns the duration of the recording in seconds.
+    pub fn duration(&self) -> f64 {
+        self.num_samples as f64 / self.header.sample_rate as f64
+    }
+
+    /// Reads the given samples of every channel.
+    ///
+    /// The data is scaled as by [`load`](crate::load). When the header calls for a
+    /// notch filter, it is applied to the requested samples only, so their first
+    /// few samples can differ slightly from a load of the whole file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `samples` is empty or extends past the end of the file,
+    /// or an [`IntanError::File`] if the data cannot be read.
+    pub fn read_samples(&self, samples: Range<usize>) -> Result<RhsData, IntanError> {
+        self.read(None, samples)
+    }
+
+    /// Reads the given samples of the selected channels.
+    ///
+    /// Channels are selected as with [`Loader::channels`](crate::Loader::channels),
+    /// and the rows of the returned data follow the channels' order in the file.
+    /// Samples of other channels are never deinterleaved.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`IntanError::ChannelNotFound`] if a selected channel does not
+    /// exist, and the errors of [`read_samples`](MappedRhsFile::read_samples).
+    pub fn read_channels<I, S>(
+        &self,
+        channels: I,
+        samples: Range<usize>,
+    ) -> Result<RhsData, IntanError>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<ChannelSelector>,
+    {
+        let channels = channels.into_iter().map(Into::into).collect();
+        self.read(Some(channels), samples)
+    }
+
+    /// Reads the given samples of one amplifier channel, in μV.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`IntanError::ChannelNotFound`] if there is no amplifier channel
+    /// at `index`, and the errors of [`read_samples`](MappedRhsFile::read_samples).
+    pub fn amplifier_channel(
+        &self,
+        index: usize,
+        samples: Range<usize>,
+    ) -> Result<Array1<f64>, IntanError> {
+        let data = self.read_channels([ChannelSelector::Amplifier(index)], samples)?;
+        let amplifier_data = data
+            .amplifier_data
+            .ok_or(IntanError::ChannelNotFound(ChannelSelector::Amplifier(index)))?;
+        Ok(amplifier_data.index_axis_move(Axis(0), 0))
+    }
+
+    /// Loads the file from memory with the given options, like
+    /// [`Loader::load`](crate::Loader::load).
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`Loader::load`](crate::Loader::load).
+    pub fn load(&self, options: &LoadOptions) -> Result<RhsFile, IntanError> {
+        options.check()?;
+        let mut cursor = Cursor::new(&self.mmap[..]);
+        let mut rhs_file = reader::read_recording(
+            &mut cursor,
+            self.mmap.len() as u64,
+            options,
+            0,
+            None,
+            self.start_time,
+            &mut [],
+        )
+        .map_err(|e| reader::file_error(&self.path, &mut cursor, e))?;
+        reader::add_file_warnings(&mut rhs_file, &self.path, options.verbose);
+        Ok(rhs_file)
+    }
+
+    fn read(
+        &self,
+        channels: Option<Vec<ChannelSelector>>,
+        samples: Range<usize>,
+    ) -> Result<RhsData, IntanError> {
+        if samples.is_empty() || samples.end > self.num_samples {
+            return Err(IntanError::Other(format!(
+                "Samples {}..{} are outside the recording, which has {} samples",
+                samples.start, samples.end, self.num_samples
+            )));
+        }
+
+        let options = LoadOptions {
+            channels,
+            verbose: false,
+            ..LoadOptions::default()
+        };
+        let mut header = self.header.clone();
+        let rows = reader::select_channels(&mut header, &options)?;
+
+        let mut cursor = Cursor::new(&self.mmap[..]);
+        cursor
+            .seek(SeekFrom::Start(self.data_offset))
+            .map_err(|e| reader::file_error(&self.path, &mut cursor, e))?;
+        reader::read_selected_data(
+            &mut cursor,
+            &self.header,
+            &header,
+            rows.as_ref(),
+            samples,
+            &options,
+        )
+        .map_err(|e| reader::file_error(&self.path, &mut cursor, e))
+    }
+}
@@ -0,0 +1,373 @@
+//! Loading the files of a directory in parallel within a memory budget.
+//!
+//! Worker threads read and parse files while the files before them are copied into
+//! the combined recording, whose arrays are allocated once at their final size. A
+//! worker only starts on a file when the memory estimated for reading it fits in
+//! what the budget leaves beside the combined arrays and the files already in
+//! flight, and files are started in order, so the file the combined recording
+//! waits for is never held back by later ones.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::sync::{mpsc, Condvar, Mutex, PoisonError};
+use std::thread;
+
+use log::info;
+use ndarray::{s, Array2};
+
+use crate::loader::LoadOptions;
+use crate::manifest;
+use crate::memory;
+use crate::processor::BlockProcessor;
+use crate::reader;
+use crate::types::{IntanError, RhsData, RhsFile, SkippedFile};
+
+/// Files started by the workers and the memory left for more
+struct Admission {
+    /// Index of the next file to start
+    next: usize,
+    /// Bytes of the budget not reserved
+    available: u64,
+    /// Whether the workers should stop, once the load is over
+    stopped: bool,
+}
+
+/// Loads `files` (each with the index of its first sample in the combined
+/// recording) with [`LoadOptions::parallel_files`] threads within
+/// [`LoadOptions::memory_budget`], and combines them
+///
+/// `previous_timestamp` is the last timestamp before the first file, and
+/// `skipped_files` the files already left out.
+pub(crate) fn load_files(
+    files: &[(&Path, u64)],
+    previous_timestamp: Option<i64>,
+    skipped_files: Vec<SkippedFile>,
+    options: &LoadOptions,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<RhsFile, IntanError> {
+    if !processors.is_empty() {
+        return Err(IntanError::InvalidOptions(
+            "Block processors cannot run while files are loaded in parallel or within a \
+             memory budget"
+                .to_string(),
+        ));
+    }
+
+    // Estimate each file, and the combined recording, from the headers
+    let mut costs = Vec::with_capacity(files.len());
+    let mut lengths = Vec::with_capacity(files.len());
+    let mut combined_bytes = 0;
+    for &(path, first_sample) in files {
+        let manifest = manifest::read_manifest(path)?;
+        let mut header = manifest.header;
+        reader::select_channels(&mut header, options)?;
+        let samples = reader::selected_samples(
+            options,
+            header.sample_rate,
+            first_sample,
+            manifest.num_samples as u64,
+        )?;
+        let estimate = memory::estimate(&header, samples, options);
+        combined_bytes += estimate.total();
+        costs.push(estimate.peak);
+        lengths.push(estimate.num_samples);
+    }
+    let budget = options.memory_budget.unwrap_or(u64::MAX);
+    let needed = combined_bytes.saturating_add(costs.iter().copied().max().unwrap_or(0));
+    if needed > budget {
+        return Err(IntanError::Other(format!(
+            "Loading {} files needs at least {:.1} MiB, more than the memory budget of {:.1} MiB",
+            files.len(),
+            needed as f64 / (1 << 20) as f64,
+            budget as f64 / (1 << 20) as f64
+        )));
+    }
+
+    let admission = Mutex::new(Admission {
+        next: 0,
+        available: budget - combined_bytes,
+        stopped: false,
+    });
+    let admitted = Condvar::new();
+    let num_threads = options.parallel_files.clamp(1, files.len());
+
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..num_threads {
+            let sender = sender.clone();
+            let (admission, admitted, costs) = (&admission, &admitted, &costs);
+            scope.spawn(move || loop {
+                let index = {
+                    let mut state = admission.lock().unwrap_or_else(PoisonError::into_inner);
+                    while !state.stopped
+                        && state.next < files.len()
+                        && costs[state.next] > state.available
+                    {
+                        state = admitted.wait(state).unwrap_or_else(PoisonError::into_inner);
+                    }
+                    if state.stopped || state.next == files.len() {
+                        return;
+                    }
+                    state.available -= costs[state.next];
+                    state.next += 1;
+                    state.next - 1
+                };
+
+                let (path, first_sample) = files[index];
+                if options.verbose {
+                    info!(
+                        "Loading file {}/{}: {}",
+                        index + 1,
+                        files.len(),
+                        path.display()
+                    );
+                }
+                let previous = if index == 0 { previous_timestamp } else { None };
+                let result = reader::load_file_at(path, options, first_sample, previous, &mut []);
+                if sender.send((index, result)).is_err() {
+                    return;
+                }
+            });
+        }
+        drop(sender);
+
+        // Receives the loaded file at `index`, keeping later files until their turn
+        let mut pending = BTreeMap::new();
+        let mut receive = |index: usize| -> Result<RhsFile, IntanError> {
+            while !pending.contains_key(&index) {
+                let (i, result) = receiver.recv().map_err(|_| {
+                    IntanError::Other("A file loading thread stopped unexpectedly".to_string())
+                })?;
+                pending.insert(i, result);
+            }
+            pending.remove(&index).unwrap_or_else(|| {
+                Err(IntanError::Other(
+                    "A file loading thread stopped unexpectedly".to_string(),
+                ))
+            })
+        };
+        // Frees the reservation of a file once it has been combined
+        let release = |index: usize| {
+            let mut state = admission.lock().unwrap_or_else(PoisonError::into_inner);
+            state.available += costs[index];
+            admitted.notify_all();
+        };
+
+        let result = combine_in_order(
+            files,
+            &lengths,
+            previous_timestamp,
+            skipped_files,
+            options,
+            &mut receive,
+            &release,
+        );
+
+        admission
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .stopped = true;
+        admitted.notify_all();
+        result
+    })
+}
+
+/// Combines the files, received in order from `receive`, into arrays holding the
+/// `lengths` of samples of all files, releasing each file once it is copied
+fn combine_in_order(
+    files: &[(&Path, u64)],
+    lengths: &[usize],
+    previous_timestamp: Option<i64>,
+    skipped_files: Vec<SkippedFile>,
+    options: &LoadOptions,
+    receive: &mut dyn FnMut(usize) -> Result<RhsFile, IntanError>,
+    release: &dyn Fn(usize),
+) -> Result<RhsFile, IntanError> {
+    let mut combined_file = receive(0)?;
+    let total_samples = lengths.iter().try_fold(0, |total, &num_samples| {
+        reader::add_samples(&combined_file.header, total, num_samples)
+    })?;
+    let mut previous_timestamp = reader::last_timestamp(&combined_file).or(previous_timestamp);
+    combined_file.skipped_files = skipped_files;
+    if files.len() == 1 {
+        return Ok(combined_file);
+    }
+
+    let first_path = files[0].0;
+    let first_span = reader::source_file_span(first_path, combined_file.data.as_ref(), 0);
+    let mut sample_offset = first_span.num_samples;
+    if let Some(first) = combined_file.data.take() {
+        let mut data = reader::padding_like(&first, 0, total_samples, false);
+        copy_part(&mut data, first, 0, true)?;
+        combined_file.data = Some(data);
+    }
+    combined_file.source_files = Some(vec![first_span]);
+    release(0);
+
+    for (index, &(file_path, _)) in files.iter().enumerate().skip(1) {
+        let mut next_file = receive(index)?;
+        if let Some(data) = next_file.data.as_mut() {
+            reader::unwrap_timestamps(data.timestamps.view_mut(), previous_timestamp);
+        }
+        let gap = reader::file_gap(previous_timestamp, &next_file);
+        previous_timestamp = reader::last_timestamp(&next_file).or(previous_timestamp);
+
+        let warnings = reader::verify_header_compatibility(
+            &combined_file.header,
+            &next_file.header,
+            file_path,
+            options.header_compatibility,
+        )
+        .map_err(|e| e.in_file(file_path, None))?;
+        combined_file.warnings.extend(warnings);
+
+        let keep_data = combined_file.data_present && next_file.data_present;
+        if let (Some((_, missing)), true) = (gap, keep_data) {
+            // Padding is rejected with a memory budget, so this only checks the gap
+            reader::handle_gap(
+                file_path,
+                missing,
+                next_file.header.sample_rate,
+                options,
+                &mut combined_file.warnings,
+            )?;
+        }
+        let next_data = next_file.data.take().filter(|_| keep_data);
+        let span = reader::source_file_span(file_path, next_data.as_ref(), sample_offset);
+        if let (Some(data), Some(next_data)) = (combined_file.data.as_mut(), next_data) {
+            copy_part(data, next_data, sample_offset, false)?;
+        }
+        sample_offset += span.num_samples;
+        if let Some(sources) = combined_file.source_files.as_mut() {
+            sources.push(span);
+        }
+        combined_file.warnings.append(&mut next_file.warnings);
+        combined_file
+            .fingerprints
+            .append(&mut next_file.fingerprints);
+        release(index);
+    }
+
+    if combined_file.data.is_some() && sample_offset != total_samples {
+        return Err(IntanError::Other(format!(
+            "The files held {} samples instead of the {} their sizes indicate; were they \
+             modified while loading?",
+            sample_offset, total_samples
+        )));
+    }
+    if options.verbose {
+        info!("Successfully combined {} files", files.len());
+        info!("Total duration: {:.2} seconds", combined_file.duration());
+    }
+    Ok(combined_file)
+}
+
+/// Copies the samples of `part` into `combined` from sample `offset` on
+///
+/// Stimulation flags are taken from the `first` part and appended from later ones.
+fn copy_part(
+    combined: &mut RhsData,
+    part: RhsData,
+    offset: usize,
+    first: bool,
+) -> Result<(), Box<dyn Error>> {
+    let end = offset + part.num_samples();
+    if end > combined.num_samples() {
+        return Err(Box::new(IntanError::Other(
+            "The files held more samples than their sizes indicate; were they modified \
+             while loading?"
+                .to_string(),
+        )));
+    }
+
+    fn copy<T: Clone>(combined: &mut Option<Array2<T>>, part: Option<Array2<T>>, offset: usize) {
+        if let (Some(combined), Some(part)) = (combined, part) {
+            combined
+                .slice_mut(s![.., offset..offset + part.ncols()])
+                .assign(&part);
+        }
+    }
+    combined
+        .timestamps
+        .slice_mut(s![offset..end])
+        .assign(&part.timestamps);
+    copy(&mut combined.amplifier_data, part.amplifier_data, offset);
+    copy(
+        &mut combined.dc_amplifier_data,
+        part.dc_amplifier_data,
+        offset,
+    );
+    copy(&mut combined.stim_data, part.stim_data, offset);
+    copy(&mut combined.board_adc_data, part.board_adc_data, offset);
+    copy(&mut combined.board_dac_data, part.board_dac_data, offset);
+    copy(
+        &mut combined.board_dig_in_data,
+        part.board_dig_in_data,
+        offset,
+    );
+    copy(
+        &mut combined.board_dig_out_data,
+        part.board_dig_out_data,
+        offset,
+    );
+    copy(
+        &mut combined.amplifier_data_raw,
+        part.amplifier_data_raw,
+        offset,
+    );
+    copy(
+        &mut combined.dc_amplifier_data_raw,
+        part.dc_amplifier_data_raw,
+        offset,
+    );
+    copy(
+        &mut combined.board_adc_data_raw,
+        part.board_adc_data_raw,
+        offset,
+    );
+    copy(
+        &mut combined.board_dac_data_raw,
+        part.board_dac_data_raw,
+        offset,
+    );
+    copy(
+        &mut combined.amplifier_data_f32,
+        part.amplifier_data_f32,
+        offset,
+    );
+    copy(
+        &mut combined.dc_amplifier_data_f32,
+        part.dc_amplifier_data_f32,
+        offset,
+    );
+    copy(
+        &mut combined.board_adc_data_f32,
+        part.board_adc_data_f32,
+        offset,
+    );
+    copy(
+        &mut combined.board_dac_data_f32,
+        part.board_dac_data_f32,
+        offset,
+    );
+
+    for (combined_flags, part_flags) in [
+        (
+            &mut combined.compliance_limit_data,
+            part.compliance_limit_data,
+        ),
+        (
+            &mut combined.charge_recovery_data,
+            part.charge_recovery_data,
+        ),
+        (&mut combined.amp_settle_data, part.amp_settle_data),
+    ] {
+        if first {
+            *combined_flags = part_flags;
+        } else if let (Some(combined_flags), Some(part_flags)) = (combined_flags, part_flags) {
+            combined_flags.append(part_flags.view())?;
+        }
+    }
+    Ok(())
+}
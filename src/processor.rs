@@ -0,0 +1,275 @@
+//! Plugin hooks for custom per-block processing.
+//!
+//! A [`BlockProcessor`] is invoked once for every 128-sample data block of a file
+//! after the samples have been scaled to physical units (and notch filtered, when
+//! applicable). Processors receive mutable views into the block's signals (the
+//! stimulation flags are read-only), so they can blank artifacts, apply custom
+//! corrections, or accumulate features without forking the reader.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::{load_with_processors, BlockProcessor, DataBlockMut};
+//! use std::error::Error;
+//!
+//! /// Clamps amplifier samples to ±5 mV and counts how many were clamped.
+//! struct ArtifactClamp {
+//!     clamped: usize,
+//! }
+//!
+//! impl BlockProcessor for ArtifactClamp {
+//!     fn process_block(&mut self, block: &mut DataBlockMut<'_>) -> Result<(), Box<dyn Error>> {
+//!         if let Some(amp) = block.amplifier_data.as_mut() {
+//!             for value in amp.iter_mut() {
+//!                 if value.abs() > 5000.0 {
+//!                     *value = value.signum() * 5000.0;
+//!                     self.clamped += 1;
+//!                 }
+//!             }
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut clamp = ArtifactClamp { clamped: 0 };
+//! let rhs_file = load_with_processors("recording.rhs", &mut [&mut clamp]).unwrap();
+//! println!("Clamped {} samples in {:.1} s of data", clamp.clamped, rhs_file.duration());
+//! ```
+
+use ndarray::{s, ArrayView1, ArrayViewMut2};
+use std::error::Error;
+
+use crate::flags::StimFlagsView;
+use crate::types::{RhsData, RhsDataView, RhsHeader};
+use crate::units::SignalUnits;
+
+/// Mutable view of a single data block.
+///
+/// Every signal field mirrors the corresponding field of [`RhsData`], restricted to
+/// the samples of this block. Fields are `None` when the signal is not present in
+/// the recording.
+pub struct DataBlockMut<'a> {
+    /// Index of this block within the file (0-based)
+    pub block_index: usize,
+    /// Index of the first sample of this block within the file
+    pub sample_offset: usize,
+    /// Timestamps for the samples in this block
+    pub timestamps: ArrayView1<'a, i64>,
+    /// Amplifier data (μV)
+    pub amplifier_data: Option<ArrayViewMut2<'a, f64>>,
+    /// DC amplifier data (V)
+    pub dc_amplifier_data: Option<ArrayViewMut2<'a, f64>>,
+    /// Stimulation current data (μA)
+    pub stim_data: Option<ArrayViewMut2<'a, f64>>,
+    /// Compliance limit status (read-only)
+    pub compliance_limit_data: Option<StimFlagsView<'a>>,
+    /// Charge recovery status (read-only)
+    pub charge_recovery_data: Option<StimFlagsView<'a>>,
+    /// Amplifier settle status (read-only)
+    pub amp_settle_data: Option<StimFlagsView<'a>>,
+    /// Board ADC data (V)
+    pub board_adc_data: Option<ArrayViewMut2<'a, f64>>,
+    /// Board DAC data (V)
+    pub board_dac_data: Option<ArrayViewMut2<'a, f64>>,
+    /// Board digital input data (0 or 1)
+    pub board_dig_in_data: Option<ArrayViewMut2<'a, i32>>,
+    /// Board digital output data (0 or 1)
+    pub board_dig_out_data: Option<ArrayViewMut2<'a, i32>>,
+    /// Amplifier data as raw ADC codes
+    pub amplifier_data_raw: Option<ArrayViewMut2<'a, u16>>,
+    /// DC amplifier data as raw ADC codes
+    pub dc_amplifier_data_raw: Option<ArrayViewMut2<'a, u16>>,
+    /// Board ADC data as raw ADC codes
+    pub board_adc_data_raw: Option<ArrayViewMut2<'a, u16>>,
+    /// Board DAC data as raw DAC codes
+    pub board_dac_data_raw: Option<ArrayViewMut2<'a, u16>>,
+    /// Amplifier data in single precision (μV)
+    pub amplifier_data_f32: Option<ArrayViewMut2<'a, f32>>,
+    /// DC amplifier data in single precision (V)
+    pub dc_amplifier_data_f32: Option<ArrayViewMut2<'a, f32>>,
+    /// Board ADC data in single precision (V)
+    pub board_adc_data_f32: Option<ArrayViewMut2<'a, f32>>,
+    /// Board DAC data in single precision (V)
+    pub board_dac_data_f32: Option<ArrayViewMut2<'a, f32>>,
+    /// Physical unit of each analog signal
+    pub units: SignalUnits,
+}
+
+impl DataBlockMut<'_> {
+    /// Returns the number of samples in this block.
+    pub fn num_samples(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    /// Returns a read-only view of this block.
+    pub fn view(&self) -> RhsDataView<'_> {
+        RhsDataView {
+            timestamps: self.timestamps.view(),
+            amplifier_data: self.amplifier_data.as_ref().map(|a| a.view()),
+            dc_amplifier_data: self.dc_amplifier_data.as_ref().map(|a| a.view()),
+            stim_data: self.stim_data.as_ref().map(|a| a.view()),
+            compliance_limit_data: self.compliance_limit_data,
+            charge_recovery_data: self.charge_recovery_data,
+            amp_settle_data: self.amp_settle_data,
+            board_adc_data: self.board_adc_data.as_ref().map(|a| a.view()),
+            board_dac_data: self.board_dac_data.as_ref().map(|a| a.view()),
+            board_dig_in_data: self.board_dig_in_data.as_ref().map(|a| a.view()),
+            board_dig_out_data: self.board_dig_out_data.as_ref().map(|a| a.view()),
+            amplifier_data_raw: self.amplifier_data_raw.as_ref().map(|a| a.view()),
+            dc_amplifier_data_raw: self.dc_amplifier_data_raw.as_ref().map(|a| a.view()),
+            board_adc_data_raw: self.board_adc_data_raw.as_ref().map(|a| a.view()),
+            board_dac_data_raw: self.board_dac_data_raw.as_ref().map(|a| a.view()),
+            amplifier_data_f32: self.amplifier_data_f32.as_ref().map(|a| a.view()),
+            dc_amplifier_data_f32: self.dc_amplifier_data_f32.as_ref().map(|a| a.view()),
+            board_adc_data_f32: self.board_adc_data_f32.as_ref().map(|a| a.view()),
+            board_dac_data_f32: self.board_dac_data_f32.as_ref().map(|a| a.view()),
+            units: self.units,
+        }
+    }
+}
+
+/// A user-defined processing stage that runs on every data block during loading.
+///
+/// Only [`process_block`](BlockProcessor::process_block) is required. The
+/// [`begin`](BlockProcessor::begin) and [`finish`](BlockProcessor::finish) hooks
+/// are called once per file, before the first block and after the last one.
+/// Returning an error from any hook aborts the load.
+pub trait BlockProcessor {
+    /// Called once per file before any block is processed.
+    fn begin(&mut self, _header: &RhsHeader) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Called for every data block, in file order.
+    fn process_block(&mut self, block: &mut DataBlockMut<'_>) -> Result<(), Box<dyn Error>>;
+
+    /// Called once per file after the last block has been processed.
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Closures can be used directly as processors.
+impl<F> BlockProcessor for F
+where
+    F: FnMut(&mut DataBlockMut<'_>) -> Result<(), Box<dyn Error>>,
+{
+    fn process_block(&mut self, block: &mut DataBlockMut<'_>) -> Result<(), Box<dyn Error>> {
+        self(block)
+    }
+}
+
+/// Runs all processors over the data, one block of `samples_per_block` samples at a time.
+pub(crate) fn run_processors(
+    header: &RhsHeader,
+    data: &mut RhsData,
+    samples_per_block: usize,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<(), Box<dyn Error>> {
+    if processors.is_empty() {
+        return Ok(());
+    }
+
+    for processor in processors.iter_mut() {
+        processor.begin(header)?;
+    }
+
+    let num_samples = data.timestamps.len();
+    let mut block_index = 0;
+    let mut start = 0;
+
+    while start < num_samples {
+        let end = (start + samples_per_block).min(num_samples);
+
+        for processor in processors.iter_mut() {
+            let mut block = DataBlockMut {
+                block_index,
+                sample_offset: start,
+                timestamps: data.timestamps.slice(s![start..end]),
+                amplifier_data: data
+                    .amplifier_data
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                dc_amplifier_data: data
+                    .dc_amplifier_data
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                stim_data: data
+                    .stim_data
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                compliance_limit_data: data
+                    .compliance_limit_data
+                    .as_ref()
+                    .map(|f| f.view().slice(start, end)),
+                charge_recovery_data: data
+                    .charge_recovery_data
+                    .as_ref()
+                    .map(|f| f.view().slice(start, end)),
+                amp_settle_data: data
+                    .amp_settle_data
+                    .as_ref()
+                    .map(|f| f.view().slice(start, end)),
+                board_adc_data: data
+                    .board_adc_data
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                board_dac_data: data
+                    .board_dac_data
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                board_dig_in_data: data
+                    .board_dig_in_data
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                board_dig_out_data: data
+                    .board_dig_out_data
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                amplifier_data_raw: data
+                    .amplifier_data_raw
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                dc_amplifier_data_raw: data
+                    .dc_amplifier_data_raw
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                board_adc_data_raw: data
+                    .board_adc_data_raw
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                board_dac_data_raw: data
+                    .board_dac_data_raw
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                amplifier_data_f32: data
+                    .amplifier_data_f32
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                dc_amplifier_data_f32: data
+                    .dc_amplifier_data_f32
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                board_adc_data_f32: data
+                    .board_adc_data_f32
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                board_dac_data_f32: data
+                    .board_dac_data_f32
+                    .as_mut()
+                    .map(|a| a.slice_mut(s![.., start..end])),
+                units: data.units,
+            };
+            processor.process_block(&mut block)?;
+        }
+
+        block_index += 1;
+        start = end;
+    }
+
+    for processor in processors.iter_mut() {
+        processor.finish()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,415 @@
+//! Multi-resolution min/max pyramids for visualization.
+//!
+//! Drawing hours of multi-channel data at a few thousand pixels per screen only
+//! needs the minimum and maximum of each pixel's span. A [`Pyramid`] precomputes
+//! these envelopes at several zoom levels, each `level_factor` times coarser than
+//! the previous one, so a viewer can fetch any time range at any zoom by reading
+//! a bounded number of bins instead of scanning the raw samples.
+//!
+//! Pyramids can be saved next to the recording and reopened later, which avoids
+//! rebuilding them every time a viewer opens the same file. A saved pyramid records
+//! the sample rate and a hash of the data it summarizes, so a cache left over from
+//! another recording of the same shape is rebuilt rather than reused.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//! use intan_importer::pyramid::PyramidOptions;
+//!
+//! let rhs_file = load("long_recording.rhs").unwrap();
+//! let pyramid = rhs_file
+//!     .amplifier_pyramid_cached("long_recording.pyr", &PyramidOptions::default())
+//!     .unwrap()
+//!     .expect("recording has amplifier data");
+//!
+//! // Envelope of channel 0 over the first minute, at most 1920 bins (one per pixel)
+//! let minute = (60.0 * pyramid.sample_rate) as usize;
+//! let envelope = pyramid.query(0, 0, minute, 1920);
+//! println!("{} bins of {} samples each", envelope.min.len(), envelope.bin_size);
+//! ```
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use ndarray::{s, Array2, ArrayView2, Axis};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::types::{IntanError, RhsFile};
+
+/// Magic bytes identifying a saved pyramid file
+const PYRAMID_MAGIC: &[u8; 8] = b"INTANPYR";
+/// Version of the saved pyramid format
+const PYRAMID_FORMAT_VERSION: u32 = 2;
+/// Size of the header of a saved pyramid, from the magic bytes to the source hash
+const PYRAMID_HEADER_SIZE: u64 = 52;
+/// Size of the bin size and bin count that precede each saved level
+const LEVEL_HEADER_SIZE: u64 = 16;
+
+/// Controls the zoom levels of a pyramid.
+#[derive(Debug, Clone)]
+pub struct PyramidOptions {
+    /// Number of samples summarized by each bin of the finest level
+    pub base_bin_size: usize,
+    /// Ratio of bin sizes between consecutive levels (at least 2)
+    pub level_factor: usize,
+}
+
+impl Default for PyramidOptions {
+    fn default() -> Self {
+        PyramidOptions {
+            base_bin_size: 16,
+            level_factor: 4,
+        }
+    }
+}
+
+/// Min/max envelope of every channel at one zoom level.
+#[derive(Debug, Clone)]
+pub struct EnvelopeLevel {
+    /// Number of samples summarized by each bin
+    pub bin_size: usize,
+    /// Minimum of each bin, shape (channels, bins)
+    pub min: Array2<f32>,
+    /// Maximum of each bin, shape (channels, bins)
+    pub max: Array2<f32>,
+}
+
+impl EnvelopeLevel {
+    /// Number of bins per channel at this level.
+    pub fn num_bins(&self) -> usize {
+        self.min.ncols()
+    }
+}
+
+/// Result of a [`Pyramid::query`].
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    /// Number of samples summarized by each bin
+    pub bin_size: usize,
+    /// Sample index at which the first bin starts
+    pub start_sample: usize,
+    /// Minimum of each bin
+    pub min: Vec<f32>,
+    /// Maximum of each bin
+    pub max: Vec<f32>,
+}
+
+/// Precomputed min/max envelopes at several zoom levels.
+#[derive(Debug, Clone)]
+pub struct Pyramid {
+    /// Sample rate of the underlying data in Hz
+    pub sample_rate: f64,
+    /// Number of samples in the underlying data
+    pub num_samples: usize,
+    /// Ratio of bin sizes between consecutive levels
+    pub level_factor: usize,
+    /// Hash of the underlying data, which identifies the recording a saved pyramid
+    /// was built from
+    pub source_hash: u64,
+    /// Levels from finest to coarsest; the coarsest has a single bin
+    pub levels: Vec<EnvelopeLevel>,
+}
+
+impl Pyramid {
+    /// Builds a pyramid from data shaped (channels, samples).
+    pub fn build(data: ArrayView2<f64>, sample_rate: f64, options: &PyramidOptions) -> Pyramid {
+        let base_bin_size = options.base_bin_size.max(1);
+        let level_factor = options.level_factor.max(2);
+        let num_samples = data.ncols();
+
+        let mut levels = vec![base_level(data, base_bin_size)];
+        while levels[levels.len() - 1].num_bins() > 1 {
+            let next = coarsen(&levels[levels.len() - 1], level_factor);
+            levels.push(next);
+        }
+
+        Pyramid {
+            sample_rate,
+            num_samples,
+            level_factor,
+            source_hash: data_hash(data),
+            levels,
+        }
+    }
+
+    /// Number of channels in the pyramid.
+    pub fn num_channels(&self) -> usize {
+        self.levels[0].min.nrows()
+    }
+
+    /// Returns the envelope of `channel` over samples `start..end` using the finest
+    /// level that needs no more than `max_bins` bins to cover the range.
+    ///
+    /// The returned bins are aligned to the level's bin grid, so the first bin may
+    /// start before `start` and the last may extend past `end`. The cost depends only
+    /// on `max_bins` and the number of levels, not on the length of the recording.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of range.
+    pub fn query(&self, channel: usize, start: usize, end: usize, max_bins: usize) -> Envelope {
+        let end = end.min(self.num_samples);
+        let start = start.min(end);
+        let max_bins = max_bins.max(1);
+
+        let level = self
+            .levels
+            .iter()
+            .find(|level| bins_covering(start, end, level.bin_size) <= max_bins)
+            .unwrap_or(&self.levels[self.levels.len() - 1]);
+
+        let first_bin = start / level.bin_size;
+        let last_bin = if end > start {
+            end.div_ceil(level.bin_size).min(level.num_bins())
+        } else {
+            first_bin
+        };
+
+        Envelope {
+            bin_size: level.bin_size,
+            start_sample: first_bin * level.bin_size,
+            min: level.min.row(channel).slice(s![first_bin..last_bin]).to_vec(),
+            max: level.max.row(channel).slice(s![first_bin..last_bin]).to_vec(),
+        }
+    }
+
+    /// Saves the pyramid to a little-endian binary file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), IntanError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(PYRAMID_MAGIC)?;
+        writer.write_u32::<LittleEndian>(PYRAMID_FORMAT_VERSION)?;
+        writer.write_f64::<LittleEndian>(self.sample_rate)?;
+        writer.write_u64::<LittleEndian>(self.num_samples as u64)?;
+        writer.write_u64::<LittleEndian>(self.num_channels() as u64)?;
+        writer.write_u32::<LittleEndian>(self.level_factor as u32)?;
+        writer.write_u32::<LittleEndian>(self.levels.len() as u32)?;
+        writer.write_u64::<LittleEndian>(self.source_hash)?;
+
+        for level in &self.levels {
+            writer.write_u64::<LittleEndian>(level.bin_size as u64)?;
+            writer.write_u64::<LittleEndian>(level.num_bins() as u64)?;
+            for &value in level.min.iter().chain(level.max.iter()) {
+                writer.write_f32::<LittleEndian>(value)?;
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Opens a pyramid previously written with [`Pyramid::save`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is not a pyramid of this
+    /// format version, or is shorter than the levels its header describes.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Pyramid, IntanError> {
+        let file = File::open(path)?;
+        let file_size = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != PYRAMID_MAGIC {
+            return Err(IntanError::UnrecognizedFileFormat);
+        }
+        let version = reader.read_u32::<LittleEndian>()?;
+        if version != PYRAMID_FORMAT_VERSION {
+            return Err(IntanError::Other(format!(
+                "Unsupported pyramid format version {}",
+                version
+            )));
+        }
+
+        let sample_rate = reader.read_f64::<LittleEndian>()?;
+        let num_samples = reader.read_u64::<LittleEndian>()? as usize;
+        let num_channels = reader.read_u64::<LittleEndian>()?;
+        let level_factor = reader.read_u32::<LittleEndian>()? as usize;
+        let num_levels = reader.read_u32::<LittleEndian>()? as u64;
+        let source_hash = reader.read_u64::<LittleEndian>()?;
+
+        // Check every count against the bytes left before allocating for it, so a
+        // corrupt header cannot ask for more memory than the file could fill
+        let mut remaining = file_size.saturating_sub(PYRAMID_HEADER_SIZE);
+        if num_levels == 0 || num_levels * LEVEL_HEADER_SIZE > remaining {
+            return Err(IntanError::FileSizeError);
+        }
+
+        let mut levels = Vec::with_capacity(num_levels as usize);
+        for _ in 0..num_levels {
+            let bin_size = reader.read_u64::<LittleEndian>()? as usize;
+            let num_bins = reader.read_u64::<LittleEndian>()?;
+            remaining -= LEVEL_HEADER_SIZE;
+            // Minimum and maximum of each bin, as f32
+            let level_size = num_channels
+                .checked_mul(num_bins)
+                .and_then(|len| len.checked_mul(8))
+                .filter(|&size| size <= remaining)
+                .ok_or(IntanError::FileSizeError)?;
+            remaining -= level_size;
+
+            let shape = (num_channels as usize, num_bins as usize);
+            let len = shape.0 * shape.1;
+            let mut min = vec![0f32; len];
+            reader.read_f32_into::<LittleEndian>(&mut min)?;
+            let mut max = vec![0f32; len];
+            reader.read_f32_into::<LittleEndian>(&mut max)?;
+
+            levels.push(EnvelopeLevel {
+                bin_size,
+                min: Array2::from_shape_vec(shape, min).map_err(|_| IntanError::FileSizeError)?,
+                max: Array2::from_shape_vec(shape, max).map_err(|_| IntanError::FileSizeError)?,
+            });
+        }
+
+        Ok(Pyramid {
+            sample_rate,
+            num_samples,
+            level_factor,
+            source_hash,
+            levels,
+        })
+    }
+}
+
+impl RhsFile {
+    /// Builds a pyramid of the amplifier data, or `None` if no amplifier data was loaded.
+    ///
+    /// Amplifier data loaded as raw codes or in single precision is converted first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the amplifier data was spilled to disk.
+    pub fn amplifier_pyramid(
+        &self,
+        options: &PyramidOptions,
+    ) -> Result<Option<Pyramid>, IntanError> {
+        let amplifier_data = match self.amplifier_double_precision()? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        Ok(Some(Pyramid::build(
+            amplifier_data.view(),
+            self.header.sample_rate as f64,
+            options,
+        )))
+    }
+
+    /// Like [`RhsFile::amplifier_pyramid`], but reuses the pyramid saved at `cache_path`
+    /// when it matches this recording and options, and saves a newly built one otherwise.
+    ///
+    /// A saved pyramid matches when its sample rate, shape, and levels are the same
+    /// and it was built from the same amplifier data, as its
+    /// [`source_hash`](Pyramid::source_hash) records. Files that cannot be read as a
+    /// pyramid are replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the amplifier data was spilled to disk or the pyramid
+    /// cannot be saved.
+    pub fn amplifier_pyramid_cached<P: AsRef<Path>>(
+        &self,
+        cache_path: P,
+        options: &PyramidOptions,
+    ) -> Result<Option<Pyramid>, IntanError> {
+        let amplifier_data = match self.amplifier_double_precision()? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let cache_path = cache_path.as_ref();
+        let sample_rate = self.header.sample_rate as f64;
+
+        if cache_path.exists() {
+            if let Ok(pyramid) = Pyramid::open(cache_path) {
+                if pyramid.sample_rate == sample_rate
+                    && pyramid.num_samples == amplifier_data.ncols()
+                    && pyramid.num_channels() == amplifier_data.nrows()
+                    && pyramid.level_factor == options.level_factor.max(2)
+                    && pyramid.levels[0].bin_size == options.base_bin_size.max(1)
+                    && pyramid.source_hash == data_hash(amplifier_data.view())
+                {
+                    return Ok(Some(pyramid));
+                }
+            }
+        }
+
+        let pyramid = Pyramid::build(amplifier_data.view(), sample_rate, options);
+        pyramid.save(cache_path)?;
+        Ok(Some(pyramid))
+    }
+
+    /// The amplifier data in double precision, in its unit
+    fn amplifier_double_precision(&self) -> Result<Option<Cow<'_, Array2<f64>>>, IntanError> {
+        match self.data.as_ref() {
+            Some(data) => data.amplifier_double_precision(data.units.amplifier),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Number of bins of `bin_size` needed to cover samples `start..end`.
+fn bins_covering(start: usize, end: usize, bin_size: usize) -> usize {
+    if end <= start {
+        return 0;
+    }
+    end.div_ceil(bin_size) - start / bin_size
+}
+
+/// Hash of the shape and values of `data`, FNV-1a with one 64-bit word per value.
+///
+/// This identifies the recording a saved pyramid summarizes; it is not meant to
+/// resist deliberate collisions.
+fn data_hash(data: ArrayView2<f64>) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let (rows, cols) = data.dim();
+    [rows as u64, cols as u64]
+        .into_iter()
+        .chain(data.iter().map(|value| value.to_bits()))
+        .fold(OFFSET_BASIS, |hash, word| (hash ^ word).wrapping_mul(PRIME))
+}
+
+/// Computes the finest level directly from the samples.
+fn base_level(data: ArrayView2<f64>, bin_size: usize) -> EnvelopeLevel {
+    let num_bins = data.ncols().div_ceil(bin_size).max(1);
+    let mut min = Array2::from_elem((data.nrows(), num_bins), f32::NAN);
+    let mut max = Array2::from_elem((data.nrows(), num_bins), f32::NAN);
+
+    for (ch, row) in data.axis_iter(Axis(0)).enumerate() {
+        for (bin, chunk) in row.axis_chunks_iter(Axis(0), bin_size).enumerate() {
+            let (lo, hi) = chunk.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &x| {
+                (lo.min(x), hi.max(x))
+            });
+            min[[ch, bin]] = lo as f32;
+            max[[ch, bin]] = hi as f32;
+        }
+    }
+
+    EnvelopeLevel { bin_size, min, max }
+}
+
+/// Computes the next coarser level by merging groups of `factor` bins.
+fn coarsen(level: &EnvelopeLevel, factor: usize) -> EnvelopeLevel {
+    let num_bins = level.num_bins().div_ceil(factor);
+    let mut min = Array2::zeros((level.min.nrows(), num_bins));
+    let mut max = Array2::zeros((level.max.nrows(), num_bins));
+
+    for ch in 0..level.min.nrows() {
+        for bin in 0..num_bins {
+            let range = bin * factor..((bin + 1) * factor).min(level.num_bins());
+            let fine_min = level.min.slice(s![ch, range.clone()]);
+            let fine_max = level.max.slice(s![ch, range]);
+            min[[ch, bin]] = fine_min.iter().copied().fold(f32::INFINITY, f32::min);
+            max[[ch, bin]] = fine_max.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        }
+    }
+
+    EnvelopeLevel {
+        bin_size: level.bin_size * factor,
+        min,
+        max,
+    }
+}
@@ -0,0 +1,409 @@
+//! Session quality-control summary.
+//!
+//! [`RhsFile::qc_report`] condenses a recording into a [`QcReport`] covering
+//! electrode impedances, noise levels, likely-bad channels, clipping, timestamp
+//! gaps, and stimulation statistics. The report serializes to JSON with
+//! [`QcReport::to_json`], so it can be written after every session and fed into a
+//! lab-wide QC dashboard.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! let report = rhs_file.qc_report().unwrap();
+//! println!("{} bad channels", report.bad_channels.len());
+//! std::fs::write("recording.qc.json", report.to_json()).unwrap();
+//! ```
+
+use ndarray::{Array2, ArrayView1, Axis};
+use std::borrow::Cow;
+
+use crate::json::Json;
+use crate::reader::{ADC_DAC_OFFSET, AMPLIFIER_SCALE_FACTOR};
+use crate::flags::StimFlags;
+use crate::types::{IntanError, RhsFile};
+use crate::units::Unit;
+
+/// Conversion from median absolute deviation to standard deviation for Gaussian noise
+pub(crate) const MAD_TO_SIGMA: f64 = 1.0 / 0.6745;
+
+/// Thresholds used when building a QC report.
+#[derive(Debug, Clone)]
+pub struct QcOptions {
+    /// Impedance magnitude above which an electrode is flagged (Ω)
+    pub max_impedance_ohms: f32,
+    /// Channels whose RMS exceeds this multiple of the median RMS are flagged as noisy
+    pub noisy_rms_factor: f64,
+    /// Channels whose RMS is below this value are flagged as flat (μV)
+    pub flat_rms_uv: f64,
+    /// Channels with more than this fraction of clipped samples are flagged
+    pub max_clipped_fraction: f64,
+}
+
+impl Default for QcOptions {
+    fn default() -> Self {
+        QcOptions {
+            max_impedance_ohms: 2.0e6,
+            noisy_rms_factor: 3.0,
+            flat_rms_uv: 0.5,
+            max_clipped_fraction: 0.001,
+        }
+    }
+}
+
+/// QC metrics for one amplifier channel.
+#[derive(Debug, Clone)]
+pub struct ChannelQc {
+    /// Custom channel name
+    pub name: String,
+    /// Native channel name
+    pub native_name: String,
+    /// Electrode impedance magnitude (Ω)
+    pub impedance_magnitude: f32,
+    /// Electrode impedance phase (radians)
+    pub impedance_phase: f32,
+    /// Root-mean-square amplitude (μV), `None` if no data is present
+    pub rms: Option<f64>,
+    /// Noise estimate from the median absolute deviation (μV), `None` if no data is present
+    pub noise: Option<f64>,
+    /// Number of samples at the ADC rails
+    pub clipped_samples: usize,
+    /// Fraction of samples at the ADC rails
+    pub clipped_fraction: f64,
+    /// Reasons this channel was flagged as bad (empty if the channel looks good)
+    pub issues: Vec<String>,
+}
+
+/// Summary of discontinuities in the timestamps.
+#[derive(Debug, Clone, Default)]
+pub struct GapSummary {
+    /// Number of places where consecutive timestamps differ by more than one
+    pub num_gaps: usize,
+    /// Total number of samples missing across all gaps
+    pub missing_samples: u64,
+    /// Number of places where timestamps go backwards or repeat
+    pub num_non_monotonic: usize,
+}
+
+/// Stimulation statistics for one amplifier channel.
+#[derive(Debug, Clone)]
+pub struct StimChannelQc {
+    /// Custom channel name
+    pub name: String,
+    /// Number of stimulation onsets (transitions from zero to non-zero current)
+    pub num_pulses: usize,
+    /// Largest absolute stimulation current (μA)
+    pub max_current: f64,
+    /// Number of samples where the compliance limit was reached
+    pub compliance_limit_samples: usize,
+    /// Number of samples with charge recovery active
+    pub charge_recovery_samples: usize,
+    /// Number of samples with amplifier settle active
+    pub amp_settle_samples: usize,
+}
+
+/// Machine-readable quality-control summary of a recording.
+#[derive(Debug, Clone)]
+pub struct QcReport {
+    /// Source file paths, if the recording was combined from several files
+    pub source_files: Option<Vec<String>>,
+    /// Amplifier sample rate (Hz)
+    pub sample_rate: f32,
+    /// Recording duration (s)
+    pub duration: f32,
+    /// Number of samples
+    pub num_samples: usize,
+    /// Per-channel metrics for every amplifier channel
+    pub channels: Vec<ChannelQc>,
+    /// Custom names of channels with at least one issue
+    pub bad_channels: Vec<String>,
+    /// Timestamp gap summary
+    pub gaps: GapSummary,
+    /// Stimulation statistics for channels that delivered stimulation
+    pub stim: Vec<StimChannelQc>,
+}
+
+impl RhsFile {
+    /// Builds a QC report using the default thresholds.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the amplifier data was spilled to disk.
+    pub fn qc_report(&self) -> Result<QcReport, IntanError> {
+        self.qc_report_with(&QcOptions::default())
+    }
+
+    /// Builds a QC report using custom thresholds.
+    ///
+    /// Amplifier data loaded as raw codes or in single precision is converted first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the amplifier data was spilled to disk.
+    pub fn qc_report_with(&self, options: &QcOptions) -> Result<QcReport, IntanError> {
+        let data = self.data.as_ref();
+        // Thresholds are in μV, so convert amplifier data that was rescaled after loading
+        let amp = match data {
+            Some(data) => data.amplifier_double_precision(Unit::Microvolts)?,
+            None => None,
+        };
+
+        // Per-channel signal metrics
+        let mut channels: Vec<ChannelQc> = self
+            .header
+            .amplifier_channels
+            .iter()
+            .enumerate()
+            .map(|(i, channel)| {
+                let signal = amp.as_ref().map(|a| a.index_axis(Axis(0), i));
+                let (clipped_samples, clipped_fraction) =
+                    signal.as_ref().map_or((0, 0.0), count_clipped);
+
+                ChannelQc {
+                    name: channel.custom_channel_name.clone(),
+                    native_name: channel.native_channel_name.clone(),
+                    impedance_magnitude: channel.electrode_impedance_magnitude,
+                    impedance_phase: channel.electrode_impedance_phase,
+                    rms: signal.as_ref().map(rms),
+                    noise: signal.as_ref().map(mad_noise),
+                    clipped_samples,
+                    clipped_fraction,
+                    issues: Vec::new(),
+                }
+            })
+            .collect();
+
+        flag_bad_channels(&mut channels, options);
+
+        let bad_channels = channels
+            .iter()
+            .filter(|c| !c.issues.is_empty())
+            .map(|c| c.name.clone())
+            .collect();
+
+        let gaps = data
+            .map(|d| summarize_gaps(d.timestamps.view()))
+            .unwrap_or_default();
+
+        Ok(QcReport {
+            source_files: self.source_files.as_ref().map(|sources| {
+                sources.iter().map(|source| source.path.clone()).collect()
+            }),
+            sample_rate: self.header.sample_rate,
+            duration: self.duration(),
+            num_samples: self.num_samples(),
+            channels,
+            bad_channels,
+            gaps,
+            stim: self.stim_qc(),
+        })
+    }
+
+    /// Collects stimulation statistics for channels that delivered stimulation.
+    fn stim_qc(&self) -> Vec<StimChannelQc> {
+        let data = match self.data.as_ref() {
+            Some(data) => data,
+            None => return Vec::new(),
+        };
+        // Report currents in μA even if the data was rescaled after loading
+        let stim: Cow<Array2<f64>> = match data.stim_data.as_ref() {
+            Some(stim) if data.units.stim == Unit::Microamps => Cow::Borrowed(stim),
+            Some(_) => match data.stim_in_ua() {
+                Some(stim) => Cow::Owned(stim),
+                None => return Vec::new(),
+            },
+            None => return Vec::new(),
+        };
+
+        let count_true = |flags: Option<&StimFlags>, i: usize| flags.map_or(0, |f| f.count(i));
+
+        self.header
+            .amplifier_channels
+            .iter()
+            .enumerate()
+            .map(|(i, channel)| {
+                let row = stim.row(i);
+                let num_pulses = row
+                    .iter()
+                    .zip(row.iter().skip(1))
+                    .filter(|(&prev, &cur)| prev == 0.0 && cur != 0.0)
+                    .count()
+                    + usize::from(row.first().is_some_and(|&v| v != 0.0));
+
+                StimChannelQc {
+                    name: channel.custom_channel_name.clone(),
+                    num_pulses,
+                    max_current: row.iter().map(|&v| v.abs()).fold(0.0, f64::max),
+                    compliance_limit_samples: count_true(data.compliance_limit_data.as_ref(), i),
+                    charge_recovery_samples: count_true(data.charge_recovery_data.as_ref(), i),
+                    amp_settle_samples: count_true(data.amp_settle_data.as_ref(), i),
+                }
+            })
+            .filter(|s| {
+                s.num_pulses > 0 || s.compliance_limit_samples > 0 || s.amp_settle_samples > 0
+            })
+            .collect()
+    }
+}
+
+impl QcReport {
+    /// Serializes the report as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        let channels = self
+            .channels
+            .iter()
+            .map(|c| {
+                Json::object(vec![
+                    ("name", c.name.as_str().into()),
+                    ("native_name", c.native_name.as_str().into()),
+                    ("impedance_magnitude_ohms", c.impedance_magnitude.into()),
+                    ("impedance_phase_rad", c.impedance_phase.into()),
+                    ("rms_uv", c.rms.into()),
+                    ("noise_uv", c.noise.into()),
+                    ("clipped_samples", c.clipped_samples.into()),
+                    ("clipped_fraction", c.clipped_fraction.into()),
+                    ("issues", c.issues.clone().into()),
+                ])
+            })
+            .collect();
+
+        let stim = self
+            .stim
+            .iter()
+            .map(|s| {
+                Json::object(vec![
+                    ("name", s.name.as_str().into()),
+                    ("num_pulses", s.num_pulses.into()),
+                    ("max_current_ua", s.max_current.into()),
+                    ("compliance_limit_samples", s.compliance_limit_samples.into()),
+                    ("charge_recovery_samples", s.charge_recovery_samples.into()),
+                    ("amp_settle_samples", s.amp_settle_samples.into()),
+                ])
+            })
+            .collect();
+
+        Json::object(vec![
+            ("source_files", self.source_files.clone().into()),
+            ("sample_rate_hz", self.sample_rate.into()),
+            ("duration_s", self.duration.into()),
+            ("num_samples", self.num_samples.into()),
+            ("bad_channels", self.bad_channels.clone().into()),
+            (
+                "gaps",
+                Json::object(vec![
+                    ("num_gaps", self.gaps.num_gaps.into()),
+                    ("missing_samples", self.gaps.missing_samples.into()),
+                    ("num_non_monotonic", self.gaps.num_non_monotonic.into()),
+                ]),
+            ),
+            ("channels", Json::Array(channels)),
+            ("stim", Json::Array(stim)),
+        ])
+        .to_string_pretty()
+    }
+}
+
+/// Flags channels with impedance, noise, flat-line, or clipping problems.
+fn flag_bad_channels(channels: &mut [ChannelQc], options: &QcOptions) {
+    let mut rms_values: Vec<f64> = channels.iter().filter_map(|c| c.rms).collect();
+    let median_rms = median(&mut rms_values);
+
+    for channel in channels.iter_mut() {
+        if channel.impedance_magnitude > options.max_impedance_ohms {
+            channel.issues.push(format!(
+                "impedance {:.0} kΩ exceeds {:.0} kΩ",
+                channel.impedance_magnitude / 1000.0,
+                options.max_impedance_ohms / 1000.0
+            ));
+        }
+
+        if let Some(rms) = channel.rms {
+            if rms < options.flat_rms_uv {
+                channel.issues.push(format!("flat signal (RMS {:.3} μV)", rms));
+            } else if let Some(median_rms) = median_rms {
+                if rms > options.noisy_rms_factor * median_rms {
+                    channel.issues.push(format!(
+                        "noisy (RMS {:.1} μV vs median {:.1} μV)",
+                        rms, median_rms
+                    ));
+                }
+            }
+        }
+
+        if channel.clipped_fraction > options.max_clipped_fraction {
+            channel.issues.push(format!(
+                "clipping in {:.2}% of samples",
+                channel.clipped_fraction * 100.0
+            ));
+        }
+    }
+}
+
+/// Returns whether an amplifier value (μV) is at one of the ADC rails.
+pub(crate) fn is_clipped(value: f64) -> bool {
+    let low_rail = (0.0 - ADC_DAC_OFFSET) * AMPLIFIER_SCALE_FACTOR;
+    let high_rail = (65535.0 - ADC_DAC_OFFSET) * AMPLIFIER_SCALE_FACTOR;
+    let tolerance = AMPLIFIER_SCALE_FACTOR / 2.0;
+    value <= low_rail + tolerance || value >= high_rail - tolerance
+}
+
+/// Returns the number and fraction of samples at the amplifier ADC rails.
+fn count_clipped(signal: &ArrayView1<'_, f64>) -> (usize, f64) {
+    let clipped = signal.iter().filter(|&&v| is_clipped(v)).count();
+
+    let fraction = if signal.is_empty() {
+        0.0
+    } else {
+        clipped as f64 / signal.len() as f64
+    };
+    (clipped, fraction)
+}
+
+fn rms(signal: &ArrayView1<'_, f64>) -> f64 {
+    if signal.is_empty() {
+        return 0.0;
+    }
+    (signal.iter().map(|v| v * v).sum::<f64>() / signal.len() as f64).sqrt()
+}
+
+/// Robust noise estimate: median(|x - median(x)|) / 0.6745
+fn mad_noise(signal: &ArrayView1<'_, f64>) -> f64 {
+    let mut values = signal.to_vec();
+    let center = match median(&mut values) {
+        Some(center) => center,
+        None => return 0.0,
+    };
+    let mut deviations: Vec<f64> = values.iter().map(|v| (v - center).abs()).collect();
+    median(&mut deviations).unwrap_or(0.0) * MAD_TO_SIGMA
+}
+
+/// Median of the values, reordering them in the process.
+pub(crate) fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mid = values.len() / 2;
+    let (_, &mut upper, _) = values.select_nth_unstable_by(mid, |a, b| a.total_cmp(b));
+    if values.len() % 2 == 1 {
+        Some(upper)
+    } else {
+        let lower = values[..mid].iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        Some((lower + upper) / 2.0)
+    }
+}
+
+fn summarize_gaps(timestamps: ArrayView1<'_, i64>) -> GapSummary {
+    let mut summary = GapSummary::default();
+    for pair in timestamps.windows(2) {
+        let step = pair[1] - pair[0];
+        if step > 1 {
+            summary.num_gaps += 1;
+            summary.missing_samples += (step - 1) as u64;
+        } else if step < 1 {
+            summary.num_non_monotonic += 1;
+        }
+    }
+    summary
+}
@@ -0,0 +1,322 @@
+//! Detection of likely-bad amplifier channels.
+//!
+//! [`RhsFile::channel_quality`] rates every amplifier channel with four checks:
+//!
+//! - **Impedance outliers**: electrodes whose impedance magnitude lies far from
+//!   that of the other electrodes, on a logarithmic scale, such as open or shorted
+//!   sites. Channels without an impedance measurement are not checked.
+//! - **Flat lines**: channels whose signal barely varies, such as disconnected
+//!   inputs.
+//! - **RMS outliers**: channels whose RMS lies far above or below that of the other
+//!   channels.
+//! - **Saturation**: channels that hit the limits of the ADC often.
+//!
+//! Outliers are measured in robust standard deviations from the median of all
+//! channels, estimated from the median absolute deviation, so a few bad channels do
+//! not hide each other. [`RhsFile::mask_bad_channels`] removes the flagged channels
+//! from a recording, and [`LoadOptions::mask_bad_channels`] does so while loading.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::quality::QualityOptions;
+//! use intan_importer::{load, Loader};
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! for channel in rhs_file.channel_quality(&QualityOptions::default()) {
+//!     if channel.is_bad() {
+//!         println!("{}: {:?}", channel.native_name, channel.issues);
+//!     }
+//! }
+//!
+//! // Leave the bad channels out of the loaded recording
+//! let clean = Loader::new("recording.rhs")
+//!     .mask_bad_channels(QualityOptions::default())
+//!     .load()
+//!     .unwrap();
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use log::info;
+use ndarray::{Array2, ArrayView1, Axis};
+
+use crate::loader::LoadOptions;
+use crate::qc::{is_clipped, median, MAD_TO_SIGMA};
+use crate::signal::SignalType;
+use crate::types::{IntanError, LoadWarning, RhsFile};
+use crate::units::Unit;
+
+/// Thresholds of the channel quality checks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityOptions {
+    /// Robust standard deviations of log impedance magnitude from the median beyond
+    /// which an electrode is an impedance outlier
+    pub impedance_outlier_threshold: f64,
+    /// RMS below which a channel is a flat line (μV)
+    pub flat_rms_uv: f64,
+    /// Robust standard deviations of RMS from the median beyond which a channel is
+    /// an RMS outlier
+    pub rms_outlier_threshold: f64,
+    /// Number of times per second a channel may reach the ADC limits before it is
+    /// flagged as saturating
+    pub max_saturations_per_second: f64,
+}
+
+impl Default for QualityOptions {
+    fn default() -> Self {
+        QualityOptions {
+            impedance_outlier_threshold: 5.0,
+            flat_rms_uv: 0.5,
+            rms_outlier_threshold: 5.0,
+            max_saturations_per_second: 1.0,
+        }
+    }
+}
+
+/// A reason for flagging a channel as likely bad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QualityIssue {
+    /// Impedance magnitude far from that of the other electrodes
+    ImpedanceOutlier,
+    /// Signal that barely varies
+    FlatLine,
+    /// RMS far from that of the other channels
+    RmsOutlier,
+    /// Frequent saturation of the ADC
+    Saturation,
+}
+
+impl fmt::Display for QualityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            QualityIssue::ImpedanceOutlier => "impedance outlier",
+            QualityIssue::FlatLine => "flat line",
+            QualityIssue::RmsOutlier => "RMS outlier",
+            QualityIssue::Saturation => "saturation",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// Quality measures of one amplifier channel.
+#[derive(Debug, Clone)]
+pub struct ChannelQuality {
+    /// Row of the channel in the amplifier data
+    pub index: usize,
+    /// Native channel name
+    pub native_name: String,
+    /// Custom channel name
+    pub name: String,
+    /// Electrode impedance magnitude (Ω)
+    pub impedance_magnitude: f32,
+    /// Robust standard deviations of the log impedance magnitude from the median,
+    /// or `None` if the impedance was not measured or does not vary
+    pub impedance_deviation: Option<f64>,
+    /// RMS about the channel's mean (μV), or `None` if no amplifier data is loaded
+    pub rms: Option<f64>,
+    /// Robust standard deviations of the RMS from the median, or `None` if no
+    /// amplifier data is loaded or the RMS does not vary
+    pub rms_deviation: Option<f64>,
+    /// Number of times the signal reached the ADC limits
+    pub saturation_events: usize,
+    /// Saturation events per second of data
+    pub saturations_per_second: f64,
+    /// Reasons the channel was flagged, empty for a channel that looks good
+    pub issues: Vec<QualityIssue>,
+}
+
+impl ChannelQuality {
+    /// Returns whether the channel was flagged as likely bad.
+    pub fn is_bad(&self) -> bool {
+        !self.issues.is_empty()
+    }
+}
+
+impl RhsFile {
+    /// Rates every amplifier channel with the quality checks.
+    ///
+    /// Without loaded amplifier data, only the impedances are checked.
+    pub fn channel_quality(&self, options: &QualityOptions) -> Vec<ChannelQuality> {
+        // Thresholds are in μV, so convert raw, f32, and rescaled amplifier data
+        let amplifier: Option<Cow<Array2<f64>>> =
+            self.data.as_ref().and_then(|d| match &d.amplifier_data {
+                Some(data) if d.units.amplifier == Unit::Microvolts => Some(Cow::Borrowed(data)),
+                _ => d.amplifier_in_uv().map(Cow::Owned),
+            });
+        let duration = self.duration() as f64;
+
+        let mut channels: Vec<ChannelQuality> = self
+            .header
+            .amplifier_channels
+            .iter()
+            .enumerate()
+            .map(|(index, channel)| {
+                let signal = amplifier.as_ref().map(|amp| amp.index_axis(Axis(0), index));
+                let saturation_events = signal.as_ref().map_or(0, count_saturations);
+                ChannelQuality {
+                    index,
+                    native_name: channel.native_channel_name.clone(),
+                    name: channel.custom_channel_name.clone(),
+                    impedance_magnitude: channel.electrode_impedance_magnitude,
+                    impedance_deviation: None,
+                    rms: signal.as_ref().map(rms_about_mean),
+                    rms_deviation: None,
+                    saturation_events,
+                    saturations_per_second: if duration > 0.0 {
+                        saturation_events as f64 / duration
+                    } else {
+                        0.0
+                    },
+                    issues: Vec::new(),
+                }
+            })
+            .collect();
+
+        let log_impedances: Vec<Option<f64>> = channels
+            .iter()
+            .map(|c| (c.impedance_magnitude > 0.0).then(|| (c.impedance_magnitude as f64).log10()))
+            .collect();
+        let impedance_deviations = robust_deviations(&log_impedances);
+        let rms_values: Vec<Option<f64>> = channels.iter().map(|c| c.rms).collect();
+        let rms_deviations = robust_deviations(&rms_values);
+
+        for (i, channel) in channels.iter_mut().enumerate() {
+            channel.impedance_deviation = impedance_deviations[i];
+            channel.rms_deviation = rms_deviations[i];
+
+            if impedance_deviations[i]
+                .is_some_and(|deviation| deviation.abs() > options.impedance_outlier_threshold)
+            {
+                channel.issues.push(QualityIssue::ImpedanceOutlier);
+            }
+            if channel.rms.is_some_and(|rms| rms < options.flat_rms_uv) {
+                channel.issues.push(QualityIssue::FlatLine);
+            } else if rms_deviations[i]
+                .is_some_and(|deviation| deviation.abs() > options.rms_outlier_threshold)
+            {
+                channel.issues.push(QualityIssue::RmsOutlier);
+            }
+            if channel.saturations_per_second > options.max_saturations_per_second {
+                channel.issues.push(QualityIssue::Saturation);
+            }
+        }
+        channels
+    }
+
+    /// Removes the amplifier channels flagged by [`channel_quality`] and returns
+    /// their ratings.
+    ///
+    /// The channels are removed as with [`retain_channels`], together with their DC
+    /// amplifier, stimulation, and stimulation flag data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the amplifier data is not loaded in memory, since flat
+    /// lines, RMS outliers, and saturation cannot be detected without it.
+    ///
+    /// [`channel_quality`]: RhsFile::channel_quality
+    /// [`retain_channels`]: RhsFile::retain_channels
+    pub fn mask_bad_channels(
+        &mut self,
+        options: &QualityOptions,
+    ) -> Result<Vec<ChannelQuality>, Box<dyn Error>> {
+        let amplifier_loaded = self.data.as_ref().is_some_and(|data| {
+            data.amplifier_data.is_some()
+                || data.amplifier_data_f32.is_some()
+                || data.amplifier_data_raw.is_some()
+        });
+        if !amplifier_loaded && !self.header.amplifier_channels.is_empty() {
+            return Err(Box::new(IntanError::Other(
+                "Bad channels can only be masked with the amplifier data loaded in memory"
+                    .to_string(),
+            )));
+        }
+
+        let bad: Vec<ChannelQuality> = self
+            .channel_quality(options)
+            .into_iter()
+            .filter(ChannelQuality::is_bad)
+            .collect();
+        if !bad.is_empty() {
+            let bad_rows: HashSet<usize> = bad.iter().map(|c| c.index).collect();
+            let mut row = 0;
+            self.retain_channels(|signal_type, _| {
+                if signal_type != SignalType::Amplifier {
+                    return true;
+                }
+                row += 1;
+                !bad_rows.contains(&(row - 1))
+            })?;
+        }
+        Ok(bad)
+    }
+}
+
+/// Masks the bad channels of a loaded recording as requested by
+/// [`LoadOptions::mask_bad_channels`], logging the channels removed
+pub(crate) fn mask_loaded(
+    rhs_file: &mut RhsFile,
+    options: &LoadOptions,
+) -> Result<(), Box<dyn Error>> {
+    let Some(quality_options) = &options.mask_bad_channels else {
+        return Ok(());
+    };
+    let masked = rhs_file.mask_bad_channels(quality_options)?;
+    for channel in masked {
+        let warning = LoadWarning::ChannelMasked {
+            native_name: channel.native_name,
+            issues: channel.issues,
+        };
+        if options.verbose {
+            info!("{}", warning);
+        }
+        rhs_file.warnings.push(warning);
+    }
+    Ok(())
+}
+
+/// Returns how many robust standard deviations each value lies from the median of
+/// the values, or `None` for missing values and values that do not vary
+fn robust_deviations(values: &[Option<f64>]) -> Vec<Option<f64>> {
+    let mut present: Vec<f64> = values.iter().flatten().copied().collect();
+    let spread = median(&mut present).and_then(|center| {
+        let mut deviations: Vec<f64> = present.iter().map(|v| (v - center).abs()).collect();
+        let sigma = median(&mut deviations)? * MAD_TO_SIGMA;
+        (sigma > 0.0).then_some((center, sigma))
+    });
+
+    values
+        .iter()
+        .map(|value| {
+            let (center, sigma) = spread?;
+            Some((value.as_ref()? - center) / sigma)
+        })
+        .collect()
+}
+
+/// Root mean square of a signal after removing its mean
+fn rms_about_mean(signal: &ArrayView1<'_, f64>) -> f64 {
+    let Some(mean) = signal.mean() else {
+        return 0.0;
+    };
+    (signal.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / signal.len() as f64).sqrt()
+}
+
+/// Counts the times a signal enters the ADC limits
+fn count_saturations(signal: &ArrayView1<'_, f64>) -> usize {
+    let mut count = 0;
+    let mut saturated = false;
+    for &value in signal {
+        let clipped = is_clipped(value);
+        if clipped && !saturated {
+            count += 1;
+        }
+        saturated = clipped;
+    }
+    count
+}
@@ -1,24 +1,42 @@
 use byteorder::{LittleEndian, ReadBytesExt};
-use ndarray::{Array1, Array2, s};
-use std::f64::consts::PI;
+use log::{debug, info, warn};
+use ndarray::parallel::prelude::*;
+use ndarray::{concatenate, s, Array1, Array2, ArrayViewMut1, Axis};
+use std::borrow::Cow;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufReader, Read, Seek, SeekFrom};
-use std::path::Path;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Instant;
 
+use crate::datetime::DateTime;
+use crate::deinterleave::deinterleave;
+use crate::dsp::{NotchDesign, NotchFilter};
+use crate::file_reader;
+use crate::fingerprint::fingerprint_file;
+use crate::flags::{RunBuilder, StimFlags};
+use crate::loader::{
+    ChannelSelector, DataType, GapHandling, HeaderCompatibility, LoadOptions, LoadPhase,
+    LoadProgress, NotchMode, ParseMode,
+};
+use crate::processor::{run_processors, BlockProcessor};
+use crate::spill;
 use crate::types::*;
+use crate::units::SignalUnits;
 
 // Constants used throughout the reader
-const RHS_MAGIC_NUMBER: u32 = 0xd69127ac;
-const SAMPLES_PER_DATA_BLOCK: usize = 128;
+pub(crate) const RHS_MAGIC_NUMBER: u32 = 0xd69127ac;
+pub(crate) const SAMPLES_PER_DATA_BLOCK: usize = 128;
 const PRINT_PROGRESS_STEP: usize = 10;
 
 // Scaling constants (from Intan RHS data format specification)
-const AMPLIFIER_SCALE_FACTOR: f64 = 0.195; // μV per bit
-const DC_AMPLIFIER_SCALE_FACTOR: f64 = 19.23; // mV per bit (note: positive, not negative)
-const ADC_DAC_SCALE_FACTOR: f64 = 0.0003125; // V per bit (312.5 μV = 0.0003125 V)
-const DC_AMPLIFIER_OFFSET: f64 = 512.0;
-const ADC_DAC_OFFSET: f64 = 32768.0;
+pub(crate) const AMPLIFIER_SCALE_FACTOR: f64 = 0.195; // μV per bit
+pub(crate) const DC_AMPLIFIER_SCALE_FACTOR: f64 = 19.23; // mV per bit (note: positive, not negative)
+pub(crate) const ADC_DAC_SCALE_FACTOR: f64 = 0.0003125; // V per bit (312.5 μV = 0.0003125 V)
+pub(crate) const DC_AMPLIFIER_OFFSET: f64 = 512.0;
+pub(crate) const ADC_DAC_OFFSET: f64 = 32768.0;
 
 /// Loads an RHS file and returns a strongly-typed struct representation.
 ///
@@ -29,6 +47,8 @@ const ADC_DAC_OFFSET: f64 = 32768.0;
 /// # Arguments
 ///
 /// * `file_path` - Path to the RHS file to load
+/// * `options` - Channels, time window, and processing to apply
+/// * `processors` - Block processors to run over the scaled data, in order
 ///
 /// # Returns
 ///
@@ -38,51 +58,271 @@ const ADC_DAC_OFFSET: f64 = 32768.0;
 ///
 /// This function uses buffered I/O for improved reading performance. The parsing
 /// process will report progress for large files.
-pub fn load_file<P: AsRef<Path>>(file_path: P) -> Result<RhsFile, Box<dyn std::error::Error>> {
+pub fn load_file<P: AsRef<Path>>(
+    file_path: P,
+    options: &LoadOptions,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<RhsFile, IntanError> {
+    load_file_at(file_path.as_ref(), options, 0, None, processors)
+}
+
+/// Loads an RHS file whose first sample is sample `first_sample` of a combined recording
+pub(crate) fn load_file_at(
+    file_path: &Path,
+    options: &LoadOptions,
+    first_sample: u64,
+    previous_timestamp: Option<i64>,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<RhsFile, IntanError> {
     // Start timing
     let tic = Instant::now();
 
-    // Open file with buffered reader for better I/O performance
-    let file = File::open(file_path.as_ref())?;
-    let file_size = file.metadata()?.len();
-    let mut reader = BufReader::with_capacity(65536, file); // 64KB buffer
+    // Open file with a buffered reader tuned by the I/O options
+    let (mut reader, file_size) = file_reader::open(file_path, &options.io)
+        .map_err(|e| IntanError::from(e).in_file(file_path, None))?;
+
+    let mut rhs_file = read_recording(
+        &mut reader,
+        file_size,
+        options,
+        first_sample,
+        previous_timestamp,
+        DateTime::from_rhx_filename(file_path),
+        processors,
+    )
+    .map_err(|e| file_error(file_path, &mut reader, e))?;
+    add_file_warnings(&mut rhs_file, file_path, options.verbose);
+    if options.fingerprint {
+        rhs_file.fingerprints.push(fingerprint_file(file_path)?);
+    }
+
+    // Report how long read took
+    if options.verbose {
+        info!(
+            "Done! Elapsed time: {:.1} seconds",
+            tic.elapsed().as_secs_f64()
+        );
+    }
+
+    Ok(rhs_file)
+}
+
+/// Wraps an error raised while reading the file at `path` with the file, and with
+/// the position of `reader` if the file could not be read or parsed
+pub(crate) fn file_error<R: Seek>(
+    path: &Path,
+    reader: &mut R,
+    error: impl Into<IntanError>,
+) -> IntanError {
+    let error = error.into();
+    let offset = if error.is_read_error() {
+        reader.stream_position().ok()
+    } else {
+        None
+    };
+    error.in_file(path, offset)
+}
+
+/// Reads a complete RHS file of `file_size` bytes from any seekable source, whose
+/// first sample was recorded at `start_time`
+#[cfg(feature = "remote")]
+pub(crate) fn load_from_reader<R: Read + Seek>(
+    reader: &mut R,
+    file_size: u64,
+    options: &LoadOptions,
+    start_time: Option<DateTime>,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<RhsFile, Box<dyn std::error::Error>> {
+    read_recording(reader, file_size, options, 0, None, start_time, processors)
+}
+
+/// Reads an RHS file whose first sample is sample `first_sample` of a combined recording
+///
+/// The timestamps are unwrapped continuing from `previous_timestamp`, the last
+/// timestamp of the recording before the file, and `start_time` is the wall-clock
+/// time of the file's first sample.
+pub(crate) fn read_recording<R: Read + Seek>(
+    reader: &mut R,
+    file_size: u64,
+    options: &LoadOptions,
+    first_sample: u64,
+    previous_timestamp: Option<i64>,
+    start_time: Option<DateTime>,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<RhsFile, Box<dyn std::error::Error>> {
+    let options = &*resolve_wallclock_range(options, start_time)?;
 
     // Read header
-    let header = read_header(&mut reader)?;
+    let (file_header, header_warnings) = read_header_with_mode(reader, options.parse_mode)?;
+    if options.verbose {
+        log_header_summary(&file_header);
+    }
 
     // Calculate how much data is present
     let (data_present, num_blocks, num_samples) =
-        calculate_data_size(&header, file_size, &mut reader)?;
+        calculate_data_size(&file_header, file_size, options, reader)?;
+
+    // The returned header lists only the selected channels
+    let mut header = file_header.clone();
+    let rows = select_channels(&mut header, options)?;
+    let samples = selected_samples(options, header.sample_rate, first_sample, num_samples)?;
+    let recording_start = start_time_at(start_time, samples.start, header.sample_rate);
+
+    if let Some(callback) = &options.progress {
+        let blocks = selected_blocks(&samples);
+        callback.report(&LoadProgress {
+            phase: LoadPhase::Header,
+            bytes_read: reader.stream_position()?,
+            blocks_parsed: 0,
+            total_blocks: blocks.end - blocks.start,
+            channels_filtered: 0,
+            total_channels: 0,
+        });
+    }
 
     // Read data if present
     let data = if data_present {
-        let data = read_all_data_blocks(&header, num_samples, num_blocks, &mut reader)?;
-        check_end_of_file(file_size, &mut reader)?;
+        if samples.is_empty() {
+            return Err(time_range_error(options, num_samples, header.sample_rate).into());
+        }
+
+        let reads_to_end = selected_blocks(&samples).end == num_blocks;
+        let mut data =
+            read_selected_data(reader, &file_header, &header, rows.as_ref(), samples, options)?;
+        if reads_to_end {
+            check_end_of_file(file_size, reader)?;
+        }
+        if previous_timestamp.is_some() {
+            unwrap_timestamps(data.timestamps.view_mut(), previous_timestamp);
+        }
+
+        // Run user-defined block processors
+        run_processors(&header, &mut data, SAMPLES_PER_DATA_BLOCK, processors)?;
 
-        // Apply processing to the data
-        let data = process_data(&header, data)?;
         Some(data)
     } else {
         None
     };
 
-    // Report how long read took
-    println!(
-        "Done! Elapsed time: {:.1} seconds",
-        tic.elapsed().as_secs_f64()
-    );
-
     // Return the complete RHS file
     Ok(RhsFile {
         header,
         data,
         data_present,
         source_files: None,  // Add this line
+        skipped_files: Vec::new(),
+        fingerprints: Vec::new(),
+        warnings: header_warnings,
+        settings: None,
+        recording_start,
     })
 }
 
 /// Reads the header from an RHS file
-fn read_header<R: Read + Seek>(reader: &mut R) -> Result<RhsHeader, Box<dyn std::error::Error>> {
+pub(crate) fn read_header<R: Read + Seek>(reader: &mut R) -> Result<RhsHeader, Box<dyn std::error::Error>> {
+    Ok(read_header_with_mode(reader, ParseMode::Standard)?.0)
+}
+
+/// Values of a header that the RHS format does not define, and how to treat them
+struct HeaderChecks {
+    mode: ParseMode,
+    warnings: Vec<LoadWarning>,
+}
+
+impl HeaderChecks {
+    /// Fails under strict parsing, and records a warning under lenient parsing
+    fn unexpected(&mut self, field: String, value: impl ToString) -> Result<(), IntanError> {
+        let value = value.to_string();
+        match self.mode {
+            ParseMode::Strict => Err(IntanError::UnexpectedHeaderValue { field, value }),
+            ParseMode::Standard => Ok(()),
+            ParseMode::Lenient => {
+                self.warnings.push(LoadWarning::UnexpectedHeaderValue {
+                    path: String::new(),
+                    field,
+                    value,
+                });
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Reader that tracks its position, so that header errors can name the field they
+/// occurred in and the byte at which it starts
+struct TrackedReader<'a, R> {
+    inner: &'a mut R,
+    position: u64,
+}
+
+impl<'a, R: Read + Seek> TrackedReader<'a, R> {
+    fn new(inner: &'a mut R) -> std::io::Result<Self> {
+        let position = inner.stream_position()?;
+        Ok(TrackedReader { inner, position })
+    }
+
+    /// Reads the field described by `field` with `read`, wrapping any error in an
+    /// [`IntanError::HeaderField`]
+    fn field<T, E: Into<IntanError>>(
+        &mut self,
+        field: impl fmt::Display,
+        read: impl FnOnce(&mut Self) -> Result<T, E>,
+    ) -> Result<T, IntanError> {
+        let offset = self.position;
+        read(self).map_err(|e| IntanError::HeaderField {
+            field: field.to_string(),
+            offset,
+            source: Box::new(e.into()),
+        })
+    }
+
+    fn i16(&mut self, field: impl fmt::Display) -> Result<i16, IntanError> {
+        self.field(field, |r| r.read_i16::<LittleEndian>())
+    }
+
+    fn f32(&mut self, field: impl fmt::Display) -> Result<f32, IntanError> {
+        self.field(field, |r| r.read_f32::<LittleEndian>())
+    }
+
+    fn qstring(&mut self, field: impl fmt::Display) -> Result<String, IntanError> {
+        self.field(field, read_qstring)
+    }
+}
+
+impl<R: Read> Read for TrackedReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: Seek> Seek for TrackedReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        Ok(self.position)
+    }
+}
+
+/// Reads the header from an RHS file, treating values the format does not define
+/// as `mode` says
+///
+/// Returns the warnings of lenient parsing along with the header. Their paths are
+/// empty, to be set with [`add_file_warnings`].
+pub(crate) fn read_header_with_mode<R: Read + Seek>(
+    reader: &mut R,
+    mode: ParseMode,
+) -> Result<(RhsHeader, Vec<LoadWarning>), Box<dyn std::error::Error>> {
+    let reader = &mut TrackedReader::new(reader)?;
+    let mut checks = HeaderChecks {
+        mode,
+        warnings: Vec::new(),
+    };
+
     // Create header with default values for RHS format
     let mut header = RhsHeader {
         version: Version { major: 0, minor: 0 },
@@ -115,6 +355,8 @@ fn read_header<R: Read + Seek>(reader: &mut R) -> Result<RhsHeader, Box<dyn std:
         reference_channel: String::new(),
         amplifier_channels: Vec::new(),
         spike_triggers: Vec::new(),
+        aux_input_channels: Vec::new(),
+        supply_voltage_channels: Vec::new(),
         board_adc_channels: Vec::new(),
         board_dac_channels: Vec::new(),
         board_dig_in_channels: Vec::new(),
@@ -149,10 +391,10 @@ fn read_header<R: Read + Seek>(reader: &mut R) -> Result<RhsHeader, Box<dyn std:
     check_magic_number(reader)?;
 
     // Read version number
-    read_version_number(reader, &mut header)?;
+    read_version_number(reader, &mut header, &mut checks)?;
 
     // Read sample rate
-    header.sample_rate = reader.read_f32::<LittleEndian>()?;
+    header.sample_rate = reader.f32("sample rate")?;
     header.frequency_parameters.amplifier_sample_rate = header.sample_rate;
     header.frequency_parameters.board_adc_sample_rate = header.sample_rate;
     header.frequency_parameters.board_dig_in_sample_rate = header.sample_rate;
@@ -161,55 +403,52 @@ fn read_header<R: Read + Seek>(reader: &mut R) -> Result<RhsHeader, Box<dyn std:
     read_freq_settings(reader, &mut header)?;
 
     // Read notch filter
-    read_notch_filter_frequency(reader, &mut header)?;
+    read_notch_filter_frequency(reader, &mut header, &mut checks)?;
 
     // Read impedance test frequencies
     read_impedance_test_frequencies(reader, &mut header)?;
 
     // Read amp settle mode
-    header.amp_settle_mode = reader.read_i16::<LittleEndian>()? as i32;
+    header.amp_settle_mode = reader.i16("amp settle mode")? as i32;
     header.stim_parameters.amp_settle_mode = header.amp_settle_mode;
 
     // Read charge recovery mode
-    header.charge_recovery_mode = reader.read_i16::<LittleEndian>()? as i32;
+    header.charge_recovery_mode = reader.i16("charge recovery mode")? as i32;
     header.stim_parameters.charge_recovery_mode = header.charge_recovery_mode;
 
     // Read stim step size
-    header.stim_step_size = reader.read_f32::<LittleEndian>()?;
+    header.stim_step_size = reader.f32("stim step size")?;
     header.stim_parameters.stim_step_size = header.stim_step_size;
 
     // Read recovery current limit
-    header.recovery_current_limit = reader.read_f32::<LittleEndian>()?;
+    header.recovery_current_limit = reader.f32("recovery current limit")?;
     header.stim_parameters.charge_recovery_current_limit = header.recovery_current_limit;
 
     // Read recovery target voltage
-    header.recovery_target_voltage = reader.read_f32::<LittleEndian>()?;
+    header.recovery_target_voltage = reader.f32("recovery target voltage")?;
     header.stim_parameters.charge_recovery_target_voltage = header.recovery_target_voltage;
 
     // Read notes
     read_notes(reader, &mut header)?;
 
     // Read DC amp saved flag
-    header.dc_amplifier_data_saved = reader.read_i16::<LittleEndian>()? != 0;
+    header.dc_amplifier_data_saved = reader.i16("DC amplifier data saved")? != 0;
 
     // Read eval board mode
-    header.eval_board_mode = reader.read_i16::<LittleEndian>()? as i32;
+    header.eval_board_mode = reader.i16("eval board mode")? as i32;
 
     // Read reference channel
-    header.reference_channel = read_qstring(reader)?;
+    header.reference_channel = reader.qstring("reference channel")?;
 
     // Read signal summary
-    read_signal_summary(reader, &mut header)?;
+    read_signal_summary(reader, &mut header, &mut checks)?;
 
-    // Print header summary
-    print_header_summary(&header);
-
-    Ok(header)
+    Ok((header, checks.warnings))
 }
 
 /// Helper function to check the magic number that identifies RHS files
-fn check_magic_number<R: Read>(reader: &mut R) -> Result<(), IntanError> {
-    let magic_number = reader.read_u32::<LittleEndian>()?;
+fn check_magic_number<R: Read + Seek>(reader: &mut TrackedReader<R>) -> Result<(), IntanError> {
+    let magic_number = reader.field("magic number", |r| r.read_u32::<LittleEndian>())?;
     if magic_number != RHS_MAGIC_NUMBER {
         return Err(IntanError::UnrecognizedFileFormat);
     }
@@ -217,72 +456,88 @@ fn check_magic_number<R: Read>(reader: &mut R) -> Result<(), IntanError> {
 }
 
 /// Helper function to read the version number
-fn read_version_number<R: Read>(reader: &mut R, header: &mut RhsHeader) -> Result<(), IntanError> {
+fn read_version_number<R: Read + Seek>(
+    reader: &mut TrackedReader<R>,
+    header: &mut RhsHeader,
+    checks: &mut HeaderChecks,
+) -> Result<(), IntanError> {
     let mut version_bytes = [0u8; 4];
-    reader.read_exact(&mut version_bytes)?;
+    reader.field("version number", |r| r.read_exact(&mut version_bytes))?;
 
     header.version.major = i16::from_le_bytes([version_bytes[0], version_bytes[1]]) as i32;
     header.version.minor = i16::from_le_bytes([version_bytes[2], version_bytes[3]]) as i32;
 
-    println!(
-        "\nReading Intan Technologies RHS Data File, Version {}.{}\n",
-        header.version.major, header.version.minor
-    );
+    // RHS files were written by versions 1 to 3 of Intan's software
+    if !(1..=3).contains(&header.version.major) {
+        let version = format!("{}.{}", header.version.major, header.version.minor);
+        checks.unexpected("file version".to_string(), version)?;
+    }
 
     Ok(())
 }
 
 /// Helper function to read frequency settings
-fn read_freq_settings<R: Read>(reader: &mut R, header: &mut RhsHeader) -> Result<(), IntanError> {
+fn read_freq_settings<R: Read + Seek>(
+    reader: &mut TrackedReader<R>,
+    header: &mut RhsHeader,
+) -> Result<(), IntanError> {
     // Read DSP enabled flag
-    header.dsp_enabled = reader.read_i16::<LittleEndian>()? as i32;
+    header.dsp_enabled = reader.i16("DSP enabled")? as i32;
     header.frequency_parameters.dsp_enabled = header.dsp_enabled;
 
     // Read actual DSP cutoff frequency
-    header.actual_dsp_cutoff_frequency = reader.read_f32::<LittleEndian>()?;
+    header.actual_dsp_cutoff_frequency = reader.f32("actual DSP cutoff frequency")?;
     header.frequency_parameters.actual_dsp_cutoff_frequency = header.actual_dsp_cutoff_frequency;
 
     // Read actual lower bandwidth
-    header.actual_lower_bandwidth = reader.read_f32::<LittleEndian>()?;
+    header.actual_lower_bandwidth = reader.f32("actual lower bandwidth")?;
     header.frequency_parameters.actual_lower_bandwidth = header.actual_lower_bandwidth;
 
     // Read actual lower settle bandwidth
-    header.actual_lower_settle_bandwidth = reader.read_f32::<LittleEndian>()?;
+    header.actual_lower_settle_bandwidth = reader.f32("actual lower settle bandwidth")?;
     header.frequency_parameters.actual_lower_settle_bandwidth =
         header.actual_lower_settle_bandwidth;
 
     // Read actual upper bandwidth
-    header.actual_upper_bandwidth = reader.read_f32::<LittleEndian>()?;
+    header.actual_upper_bandwidth = reader.f32("actual upper bandwidth")?;
     header.frequency_parameters.actual_upper_bandwidth = header.actual_upper_bandwidth;
 
     // Read desired DSP cutoff frequency
-    header.desired_dsp_cutoff_frequency = reader.read_f32::<LittleEndian>()?;
+    header.desired_dsp_cutoff_frequency = reader.f32("desired DSP cutoff frequency")?;
     header.frequency_parameters.desired_dsp_cutoff_frequency = header.desired_dsp_cutoff_frequency;
 
     // Read desired lower bandwidth
-    header.desired_lower_bandwidth = reader.read_f32::<LittleEndian>()?;
+    header.desired_lower_bandwidth = reader.f32("desired lower bandwidth")?;
     header.frequency_parameters.desired_lower_bandwidth = header.desired_lower_bandwidth;
 
     // Read desired lower settle bandwidth
-    header.desired_lower_settle_bandwidth = reader.read_f32::<LittleEndian>()?;
+    header.desired_lower_settle_bandwidth = reader.f32("desired lower settle bandwidth")?;
     header.frequency_parameters.desired_lower_settle_bandwidth =
         header.desired_lower_settle_bandwidth;
 
     // Read desired upper bandwidth
-    header.desired_upper_bandwidth = reader.read_f32::<LittleEndian>()?;
+    header.desired_upper_bandwidth = reader.f32("desired upper bandwidth")?;
     header.frequency_parameters.desired_upper_bandwidth = header.desired_upper_bandwidth;
 
     Ok(())
 }
 
 /// Helper function to read notch filter frequency
-fn read_notch_filter_frequency<R: Read>(reader: &mut R, header: &mut RhsHeader) -> Result<(), IntanError> {
-    let notch_filter_mode = reader.read_i16::<LittleEndian>()? as i32;
+fn read_notch_filter_frequency<R: Read + Seek>(
+    reader: &mut TrackedReader<R>,
+    header: &mut RhsHeader,
+    checks: &mut HeaderChecks,
+) -> Result<(), IntanError> {
+    let notch_filter_mode = reader.i16("notch filter mode")? as i32;
 
     header.notch_filter_frequency = match notch_filter_mode {
+        0 => None,
         1 => Some(50),
         2 => Some(60),
-        _ => None,
+        _ => {
+            checks.unexpected("notch filter mode".to_string(), notch_filter_mode)?;
+            None
+        }
     };
 
     header.frequency_parameters.notch_filter_frequency = header.notch_filter_frequency;
@@ -291,12 +546,12 @@ fn read_notch_filter_frequency<R: Read>(reader: &mut R, header: &mut RhsHeader)
 }
 
 /// Helper function to read impedance test frequencies
-fn read_impedance_test_frequencies<R: Read>(
-    reader: &mut R,
+fn read_impedance_test_frequencies<R: Read + Seek>(
+    reader: &mut TrackedReader<R>,
     header: &mut RhsHeader,
 ) -> Result<(), IntanError> {
-    header.desired_impedance_test_frequency = reader.read_f32::<LittleEndian>()?;
-    header.actual_impedance_test_frequency = reader.read_f32::<LittleEndian>()?;
+    header.desired_impedance_test_frequency = reader.f32("desired impedance test frequency")?;
+    header.actual_impedance_test_frequency = reader.f32("actual impedance test frequency")?;
 
     header.frequency_parameters.desired_impedance_test_frequency =
         header.desired_impedance_test_frequency;
@@ -307,37 +562,60 @@ fn read_impedance_test_frequencies<R: Read>(
 }
 
 /// Helper function to read notes
-fn read_notes<R: Read + Seek>(reader: &mut R, header: &mut RhsHeader) -> Result<(), IntanError> {
-    header.notes.note1 = read_qstring(reader)?;
-    header.notes.note2 = read_qstring(reader)?;
-    header.notes.note3 = read_qstring(reader)?;
+fn read_notes<R: Read + Seek>(
+    reader: &mut TrackedReader<R>,
+    header: &mut RhsHeader,
+) -> Result<(), IntanError> {
+    header.notes.note1 = reader.qstring("note 1")?;
+    header.notes.note2 = reader.qstring("note 2")?;
+    header.notes.note3 = reader.qstring("note 3")?;
 
     Ok(())
 }
 
 /// Helper function to read signal summary
-fn read_signal_summary<R: Read + Seek>(reader: &mut R, header: &mut RhsHeader) -> Result<(), IntanError> {
-    let number_of_signal_groups = reader.read_i16::<LittleEndian>()?;
+fn read_signal_summary<R: Read + Seek>(
+    reader: &mut TrackedReader<R>,
+    header: &mut RhsHeader,
+    checks: &mut HeaderChecks,
+) -> Result<(), IntanError> {
+    let number_of_signal_groups = reader.i16("number of signal groups")?;
 
-    for _ in 1..=number_of_signal_groups {
-        add_signal_group_information(header, reader)?;
+    for index in 1..=number_of_signal_groups {
+        add_signal_group_information(header, reader, index, checks)?;
     }
 
     Ok(())
 }
 
 /// Helper function to add signal group information
-fn add_signal_group_information<R: Read + Seek>(header: &mut RhsHeader, reader: &mut R) -> Result<(), IntanError> {
-    let signal_group_name = read_qstring(reader)?;
-    let signal_group_prefix = read_qstring(reader)?;
+fn add_signal_group_information<R: Read + Seek>(
+    header: &mut RhsHeader,
+    reader: &mut TrackedReader<R>,
+    index: i16,
+    checks: &mut HeaderChecks,
+) -> Result<(), IntanError> {
+    let signal_group_name = reader.qstring(format_args!("name of signal group {}", index))?;
+    let group = &signal_group_name;
+    let signal_group_prefix = reader.qstring(format_args!("prefix of signal group {}", group))?;
 
-    let signal_group_enabled = reader.read_i16::<LittleEndian>()?;
-    let signal_group_num_channels = reader.read_i16::<LittleEndian>()?;
-    let _ = reader.read_i16::<LittleEndian>()?; // signal_group_num_channels (unused)
+    let signal_group_enabled =
+        reader.i16(format_args!("enabled flag of signal group {}", group))?;
+    let signal_group_num_channels =
+        reader.i16(format_args!("number of channels of signal group {}", group))?;
+    // Number of amplifier channels (unused)
+    reader.i16(format_args!("number of amplifier channels of signal group {}", group))?;
 
     if signal_group_num_channels > 0 && signal_group_enabled > 0 {
-        for _ in 0..signal_group_num_channels {
-            add_channel_information(header, reader, &signal_group_name, &signal_group_prefix)?;
+        for index in 1..=signal_group_num_channels {
+            add_channel_information(
+                header,
+                reader,
+                index,
+                &signal_group_name,
+                &signal_group_prefix,
+                checks,
+            )?;
         }
     }
 
@@ -347,9 +625,11 @@ fn add_signal_group_information<R: Read + Seek>(header: &mut RhsHeader, reader:
 /// Helper function to add channel information
 fn add_channel_information<R: Read + Seek>(
     header: &mut RhsHeader,
-    reader: &mut R,
+    reader: &mut TrackedReader<R>,
+    index: i16,
     signal_group_name: &str,
     signal_group_prefix: &str,
+    checks: &mut HeaderChecks,
 ) -> Result<(), IntanError> {
     // Create new channel
     let mut new_channel = ChannelInfo {
@@ -361,6 +641,7 @@ fn add_channel_information<R: Read + Seek>(
         native_order: 0,
         custom_order: 0,
         chip_channel: 0,
+        command_stream: 0,
         board_stream: 0,
         electrode_impedance_magnitude: 0.0,
         electrode_impedance_phase: 0.0,
@@ -374,29 +655,51 @@ fn add_channel_information<R: Read + Seek>(
         digital_edge_polarity: 0,
     };
 
-    // Read channel information
-    new_channel.native_channel_name = read_qstring(reader)?;
-    new_channel.custom_channel_name = read_qstring(reader)?;
-
-    new_channel.native_order = reader.read_i16::<LittleEndian>()? as i32;
-    new_channel.custom_order = reader.read_i16::<LittleEndian>()? as i32;
-
-    let signal_type = reader.read_i16::<LittleEndian>()? as i32;
-    let channel_enabled = reader.read_i16::<LittleEndian>()? as i32;
-
-    new_channel.chip_channel = reader.read_i16::<LittleEndian>()? as i32;
-    let _ = reader.read_i16::<LittleEndian>()?; // reserved
-    new_channel.board_stream = reader.read_i16::<LittleEndian>()? as i32;
+    // Read channel information, naming the channel in errors once its name is known
+    new_channel.native_channel_name = reader.qstring(format_args!(
+        "native name of channel {} of signal group {}",
+        index, signal_group_name
+    ))?;
+    let channel = new_channel.native_channel_name.clone();
+    new_channel.custom_channel_name =
+        reader.qstring(format_args!("custom name of channel {}", channel))?;
+
+    new_channel.native_order =
+        reader.i16(format_args!("native order of channel {}", channel))? as i32;
+    new_channel.custom_order =
+        reader.i16(format_args!("custom order of channel {}", channel))? as i32;
+
+    let signal_type_offset = reader.position;
+    let signal_type = reader.i16(format_args!("signal type of channel {}", channel))? as i32;
+    let channel_enabled = reader.i16(format_args!("enabled flag of channel {}", channel))? as i32;
+
+    new_channel.chip_channel =
+        reader.i16(format_args!("chip channel of channel {}", channel))? as i32;
+    new_channel.command_stream =
+        reader.i16(format_args!("command stream of channel {}", channel))? as i32;
+    new_channel.board_stream =
+        reader.i16(format_args!("board stream of channel {}", channel))? as i32;
 
     // Read trigger information
-    new_trigger.voltage_trigger_mode = reader.read_i16::<LittleEndian>()? as i32;
-    new_trigger.voltage_threshold = reader.read_i16::<LittleEndian>()? as i32;
-    new_trigger.digital_trigger_channel = reader.read_i16::<LittleEndian>()? as i32;
-    new_trigger.digital_edge_polarity = reader.read_i16::<LittleEndian>()? as i32;
+    new_trigger.voltage_trigger_mode =
+        reader.i16(format_args!("voltage trigger mode of channel {}", channel))? as i32;
+    new_trigger.voltage_threshold =
+        reader.i16(format_args!("voltage threshold of channel {}", channel))? as i32;
+    new_trigger.digital_trigger_channel =
+        reader.i16(format_args!("digital trigger channel of channel {}", channel))? as i32;
+    new_trigger.digital_edge_polarity =
+        reader.i16(format_args!("digital edge polarity of channel {}", channel))? as i32;
 
     // Read impedance information
-    new_channel.electrode_impedance_magnitude = reader.read_f32::<LittleEndian>()?;
-    new_channel.electrode_impedance_phase = reader.read_f32::<LittleEndian>()?;
+    new_channel.electrode_impedance_magnitude =
+        reader.f32(format_args!("impedance magnitude of channel {}", channel))?;
+    new_channel.electrode_impedance_phase =
+        reader.f32(format_args!("impedance phase of channel {}", channel))?;
+
+    let known_type = (0..=6).contains(&signal_type);
+    if !known_type && (channel_enabled == 0 || checks.mode == ParseMode::Lenient) {
+        checks.unexpected(format!("signal type of channel {}", channel), signal_type)?;
+    }
 
     // If channel is enabled, add it to the appropriate list
     if channel_enabled == 0 {
@@ -408,90 +711,38 @@ fn add_channel_information<R: Read + Seek>(
             header.amplifier_channels.push(new_channel);
             header.spike_triggers.push(new_trigger);
         }
-        1 => return Err(IntanError::InvalidChannelType), // AuxInputSignals
-        2 => return Err(IntanError::InvalidChannelType), // VddSignals
+        1 => header.aux_input_channels.push(new_channel),
+        2 => header.supply_voltage_channels.push(new_channel),
         3 => header.board_adc_channels.push(new_channel),
         4 => header.board_dac_channels.push(new_channel),
         5 => header.board_dig_in_channels.push(new_channel),
         6 => header.board_dig_out_channels.push(new_channel),
-        _ => return Err(IntanError::InvalidChannelType),
+        // Left out under lenient parsing, which recorded the type above
+        _ if checks.mode == ParseMode::Lenient => {}
+        _ => {
+            return Err(IntanError::HeaderField {
+                field: format!("signal type of channel {}", channel),
+                offset: signal_type_offset,
+                source: Box::new(IntanError::InvalidChannelType),
+            })
+        }
     }
 
     Ok(())
 }
 
-// Helper function to print header summary
-fn print_header_summary(header: &RhsHeader) {
-    println!(
-        "Found {} amplifier channel{}.",
-        header.amplifier_channels.len(),
-        if header.amplifier_channels.len() != 1 {
-            "s"
-        } else {
-            ""
-        }
-    );
-
-    if header.dc_amplifier_data_saved {
-        println!(
-            "Found {} DC amplifier channel{}.",
-            header.amplifier_channels.len(),
-            if header.amplifier_channels.len() != 1 {
-                "s"
-            } else {
-                ""
-            }
-        );
+// Helper function to log header summary
+pub(crate) fn log_header_summary(header: &RhsHeader) {
+    for line in header.summary().to_string().lines() {
+        info!("{}", line);
     }
-
-    println!(
-        "Found {} board ADC channel{}.",
-        header.board_adc_channels.len(),
-        if header.board_adc_channels.len() != 1 {
-            "s"
-        } else {
-            ""
-        }
-    );
-
-    println!(
-        "Found {} board DAC channel{}.",
-        header.board_dac_channels.len(),
-        if header.board_dac_channels.len() != 1 {
-            "s"
-        } else {
-            ""
-        }
-    );
-
-    println!(
-        "Found {} board digital input channel{}.",
-        header.board_dig_in_channels.len(),
-        if header.board_dig_in_channels.len() != 1 {
-            "s"
-        } else {
-            ""
-        }
-    );
-
-    println!(
-        "Found {} board digital output channel{}.",
-        header.board_dig_out_channels.len(),
-        if header.board_dig_out_channels.len() != 1 {
-            "s"
-        } else {
-            ""
-        }
-    );
-
-    println!();
 }
 
 /// Helper function to read a QString (UTF-16 encoded string)
 ///
 /// QtStrings in RHS files are stored as UTF-16 with a 4-byte length prefix.
 /// A special value of 0xFFFFFFFF indicates an empty string.
-fn read_qstring<R: Read + Seek>(reader: &mut R) -> Result<String, IntanError> {
+pub(crate) fn read_qstring<R: Read + Seek>(reader: &mut R) -> Result<String, IntanError> {
     let length = reader.read_u32::<LittleEndian>()?;
 
     // If length set to 0xFFFFFFFF, return empty string
@@ -536,6 +787,7 @@ fn read_qstring<R: Read + Seek>(reader: &mut R) -> Result<String, IntanError> {
 ///
 /// * `header` - The parsed header information
 /// * `file_size` - The total size of the file in bytes
+/// * `options` - Load options; the record time is printed if verbose
 /// * `reader` - The file reader, positioned after the header
 ///
 /// # Returns
@@ -544,21 +796,24 @@ fn read_qstring<R: Read + Seek>(reader: &mut R) -> Result<String, IntanError> {
 /// * `data_present` - Boolean indicating if any data blocks are present
 /// * `num_blocks` - Number of data blocks in the file
 /// * `num_samples` - Total number of samples in the file
-fn calculate_data_size<R: Read + Seek>(
+pub(crate) fn calculate_data_size<R: Read + Seek>(
     header: &RhsHeader,
     file_size: u64,
+    options: &LoadOptions,
     reader: &mut R,
 ) -> Result<(bool, u64, u64), Box<dyn std::error::Error>> {
     let bytes_per_block = get_bytes_per_data_block(header)?;
 
     // Calculate how many bytes remain in the file after the header
     let current_position = reader.stream_position()?;
-    let bytes_remaining = file_size - current_position;
+    let bytes_remaining = file_size
+        .checked_sub(current_position)
+        .ok_or(IntanError::FileSizeError)?;
 
     let data_present = bytes_remaining > 0;
 
     // If the file size is somehow different than expected, raise an error
-    if bytes_remaining % bytes_per_block as u64 != 0 {
+    if !bytes_remaining.is_multiple_of(bytes_per_block as u64) {
         return Err(Box::new(IntanError::FileSizeError));
     }
 
@@ -567,23 +822,460 @@ fn calculate_data_size<R: Read + Seek>(
 
     let num_samples = num_blocks * header.num_samples_per_data_block as u64;
 
-    print_record_time_summary(num_samples, header.sample_rate, data_present);
+    if options.verbose {
+        log_record_time_summary(num_samples, header.sample_rate, data_present);
+    }
 
     Ok((data_present, num_blocks, num_samples))
 }
 
-// Helper function to print record time summary
-fn print_record_time_summary(num_amp_samples: u64, sample_rate: f32, data_present: bool) {
+/// Converts a number of samples, or the index of a sample, to `usize`, failing on
+/// platforms whose `usize` cannot hold it
+pub(crate) fn sample_index(num_samples: u64) -> Result<usize, IntanError> {
+    usize::try_from(num_samples)
+        .map_err(|_| IntanError::ExceedsAddressSpace(format!("{} samples", num_samples)))
+}
+
+/// Checks that `num_samples` samples of the channels of `header` can be stored, so
+/// that the sizes and indices of the arrays holding them cannot overflow
+///
+/// Each signal type is stored in one array, which Rust limits to `isize::MAX`
+/// bytes, and no element is wider than 8 bytes.
+pub(crate) fn check_data_size(header: &RhsHeader, num_samples: usize) -> Result<(), IntanError> {
+    let num_rows = [
+        header.amplifier_channels.len(),
+        header.board_adc_channels.len(),
+        header.board_dac_channels.len(),
+        header.board_dig_in_channels.len(),
+        header.board_dig_out_channels.len(),
+        1, // timestamps
+    ]
+    .into_iter()
+    .max()
+    .unwrap_or(1);
+    let fits = num_rows
+        .checked_mul(num_samples)
+        .and_then(|elements| elements.checked_mul(std::mem::size_of::<f64>()))
+        .is_some_and(|bytes| bytes <= isize::MAX as usize);
+    if fits {
+        Ok(())
+    } else {
+        Err(IntanError::ExceedsAddressSpace(format!(
+            "{} samples of {} channels",
+            num_samples, num_rows
+        )))
+    }
+}
+
+/// Returns the number of samples of a recording with the channels of `header` once
+/// `num_samples` are added to its `total`, failing if they could not be stored
+pub(crate) fn add_samples(
+    header: &RhsHeader,
+    total: usize,
+    num_samples: usize,
+) -> Result<usize, IntanError> {
+    let sum = total.checked_add(num_samples).ok_or_else(|| {
+        IntanError::ExceedsAddressSpace(format!("{} samples", total as u64 + num_samples as u64))
+    })?;
+    check_data_size(header, sum)?;
+    Ok(sum)
+}
+
+/// Reads and processes the given samples of the selected channels
+///
+/// `reader` must be positioned at the first data block. `file_header` describes the
+/// layout of the blocks, and `header` lists the channels kept by `rows`. The reader
+/// seeks past the blocks before the samples and stops after the block holding the
+/// last one.
+pub(crate) fn read_selected_data<R: Read + Seek>(
+    reader: &mut R,
+    file_header: &RhsHeader,
+    header: &RhsHeader,
+    rows: Option<&ChannelRows>,
+    samples: Range<usize>,
+    options: &LoadOptions,
+) -> Result<RhsData, Box<dyn std::error::Error>> {
+    if let Some(spill_dir) = &options.spill_dir {
+        return spill::read_spilled_data(
+            reader,
+            file_header,
+            header,
+            rows,
+            samples,
+            options,
+            spill_dir,
+        );
+    }
+
+    let blocks = selected_blocks(&samples);
+    let num_read = sample_index((blocks.end - blocks.start) * SAMPLES_PER_DATA_BLOCK as u64)?;
+    check_data_size(header, num_read)?;
+    let first_timestamp = skip_to_block(reader, file_header, blocks.start)?;
+    let mut data = read_data_blocks(file_header, rows, blocks.end - blocks.start, options, reader)?;
+    unwrap_timestamps(data.timestamps.view_mut(), first_timestamp);
+
+    // Sample indices relative to the first block read
+    let block_start = blocks.start as usize * SAMPLES_PER_DATA_BLOCK;
+    let samples = samples.start - block_start..samples.end - block_start;
+    let data = select_raw_data(data, None, samples);
+
+    // Apply processing to the data
+    process_data(header, data, options)
+}
+
+/// Returns the samples of a file selected by the time range in `options`
+///
+/// `first_sample` is the index of the file's first sample within the combined
+/// recording it belongs to, and `num_samples` is the number of samples in the file.
+pub(crate) fn selected_samples(
+    options: &LoadOptions,
+    sample_rate: f32,
+    first_sample: u64,
+    num_samples: u64,
+) -> Result<Range<usize>, IntanError> {
+    let Some(range) = &options.time_range else {
+        return Ok(0..sample_index(num_samples)?);
+    };
+
+    let to_file_sample = |seconds: f64| {
+        let sample = first_sample_at(seconds, sample_rate);
+        sample_index(sample.saturating_sub(first_sample).min(num_samples))
+    };
+    Ok(to_file_sample(range.start)?..to_file_sample(range.end)?)
+}
+
+/// Returns the index of the first sample at or after `seconds` from the first
+/// sample of a recording
+///
+/// A sample is selected when its time lies in a range, so both ends of a range
+/// round up, except for times that only miss a sample by floating-point error.
+pub(crate) fn first_sample_at(seconds: f64, sample_rate: f32) -> u64 {
+    let position = seconds * sample_rate as f64;
+    let sample = if (position - position.round()).abs() < 1e-6 {
+        position.round()
+    } else {
+        position.ceil()
+    };
+    // Negative times saturate to the first sample
+    sample as u64
+}
+
+/// Returns the wall-clock time of sample `first_sample` of a file whose first
+/// sample was recorded at `start_time`
+pub(crate) fn start_time_at(
+    start_time: Option<DateTime>,
+    first_sample: usize,
+    sample_rate: f32,
+) -> Option<DateTime> {
+    start_time.map(|start| start.add_seconds(first_sample as f64 / sample_rate as f64))
+}
+
+/// Returns `options` with its wall-clock range replaced by the time range it
+/// covers in a recording whose first sample was recorded at `start_time`
+pub(crate) fn resolve_wallclock_range(
+    options: &LoadOptions,
+    start_time: Option<DateTime>,
+) -> Result<Cow<'_, LoadOptions>, IntanError> {
+    let Some(range) = &options.wallclock_range else {
+        return Ok(Cow::Borrowed(options));
+    };
+    let Some(start_time) = start_time else {
+        return Err(IntanError::Other(
+            "A wall-clock range needs the recording's start time, which RHX puts in the \
+             names of the files it saves"
+                .to_string(),
+        ));
+    };
+
+    let end = range.end.seconds_since(&start_time);
+    if end <= 0.0 {
+        return Err(wallclock_range_error(range, start_time));
+    }
+    let start = range.start.seconds_since(&start_time).max(0.0);
+    Ok(Cow::Owned(with_time_range(options, start..end)))
+}
+
+/// Returns `options` with its wall-clock range replaced by the time range it
+/// covers in the recording combined from `file_paths`, placing each file on the
+/// wall clock by the start time in its name and its duration
+fn resolve_files_wallclock_range<'o>(
+    file_paths: &[PathBuf],
+    options: &'o LoadOptions,
+) -> Result<Cow<'o, LoadOptions>, IntanError> {
+    let Some(range) = &options.wallclock_range else {
+        return Ok(Cow::Borrowed(options));
+    };
+
+    // Seconds of the combined recording at which the window starts and ends
+    let mut start = None;
+    let mut end = None;
+    let mut first_second = 0.0;
+    let mut recording_start = None;
+    for path in file_paths {
+        let file_start = DateTime::from_rhx_filename(path).ok_or_else(|| {
+            IntanError::Other(format!(
+                "Cannot place {} on the wall clock: its name has no RHX start time",
+                path.display()
+            ))
+        })?;
+        recording_start.get_or_insert(file_start);
+        let (header, num_samples) = read_header_and_length(path)?;
+        let duration = num_samples as f64 / header.sample_rate as f64;
+
+        let offset_start = range.start.seconds_since(&file_start);
+        let offset_end = range.end.seconds_since(&file_start);
+        if start.is_none() && offset_start < duration {
+            // A window starting between two files starts with the second
+            start = Some(first_second + offset_start.max(0.0));
+        }
+        if offset_end > 0.0 {
+            end = Some(first_second + offset_end.min(duration));
+        }
+        first_second += duration;
+    }
+
+    match (start, end, recording_start) {
+        (Some(start), Some(end), _) if start < end => {
+            Ok(Cow::Owned(with_time_range(options, start..end)))
+        }
+        (_, _, Some(recording_start)) => Err(wallclock_range_error(range, recording_start)),
+        _ => Err(IntanError::NoFiles {
+            directory: None,
+            pattern: None,
+        }),
+    }
+}
+
+/// Returns a copy of `options` keeping the samples within `range` seconds instead
+/// of a wall-clock range
+fn with_time_range(options: &LoadOptions, range: Range<f64>) -> LoadOptions {
+    LoadOptions {
+        time_range: Some(range),
+        wallclock_range: None,
+        ..options.clone()
+    }
+}
+
+/// Error for a wall-clock range that selects no samples of a recording
+fn wallclock_range_error(range: &Range<DateTime>, start_time: DateTime) -> IntanError {
+    IntanError::WallclockRangeOutsideRecording {
+        range: range.clone(),
+        recording_start: start_time,
+    }
+}
+
+/// Returns the data blocks containing the given samples
+pub(crate) fn selected_blocks(samples: &Range<usize>) -> Range<u64> {
+    let start = samples.start / SAMPLES_PER_DATA_BLOCK;
+    let end = samples.end.div_ceil(SAMPLES_PER_DATA_BLOCK);
+    start as u64..end as u64
+}
+
+/// Seeks past the first `block` data blocks, starting at the first one
+///
+/// Returns the file's first timestamp if any blocks were skipped, so the timestamps
+/// read after them can be unwrapped as they would be in a read of the whole file.
+pub(crate) fn skip_to_block<R: Read + Seek>(
+    reader: &mut R,
+    header: &RhsHeader,
+    block: u64,
+) -> Result<Option<i64>, Box<dyn std::error::Error>> {
+    if block == 0 {
+        return Ok(None);
+    }
+
+    // Each data block starts with the timestamps of its samples
+    let first_timestamp = reader.read_i32::<LittleEndian>()? as i64;
+    let bytes_per_block = get_bytes_per_data_block(header)? as u64;
+    reader.seek(SeekFrom::Current((block * bytes_per_block) as i64 - 4))?;
+    Ok(Some(first_timestamp))
+}
+
+/// Error for a time range that selects no samples of a recording
+pub(crate) fn time_range_error(
+    options: &LoadOptions,
+    num_samples: u64,
+    sample_rate: f32,
+) -> IntanError {
+    IntanError::TimeRangeOutsideRecording {
+        range: options.time_range.clone().unwrap_or(0.0..0.0),
+        duration: num_samples as f64 / sample_rate as f64,
+    }
+}
+
+/// Rows of the raw data arrays kept by a channel selection, per signal type
+pub(crate) struct ChannelRows {
+    pub(crate) amplifier: Vec<usize>,
+    pub(crate) board_adc: Vec<usize>,
+    pub(crate) board_dac: Vec<usize>,
+    pub(crate) board_dig_in: Vec<usize>,
+    pub(crate) board_dig_out: Vec<usize>,
+}
+
+/// Restricts the header's channel lists to the channels selected in `options`
+///
+/// Returns the kept rows of each signal type, or `None` if every channel is kept.
+pub(crate) fn select_channels(
+    header: &mut RhsHeader,
+    options: &LoadOptions,
+) -> Result<Option<ChannelRows>, IntanError> {
+    let Some(selectors) = &options.channels else {
+        return Ok(None);
+    };
+
+    let mut selection = resolve_channel_selectors(header, selectors)?;
+
+    // Channels keep their order in the file, and are kept once however often selected
+    for rows in [
+        &mut selection.amplifier,
+        &mut selection.board_adc,
+        &mut selection.board_dac,
+        &mut selection.board_dig_in,
+        &mut selection.board_dig_out,
+    ] {
+        rows.sort_unstable();
+        rows.dedup();
+    }
+
+    select_header_channels(header, &selection);
+    Ok(Some(selection))
+}
+
+/// Finds the rows of the channels picked by `selectors`, in the order selected
+///
+/// A name selects every channel that has it. Rows are listed as often as they are
+/// selected.
+pub(crate) fn resolve_channel_selectors(
+    header: &RhsHeader,
+    selectors: &[ChannelSelector],
+) -> Result<ChannelRows, IntanError> {
+    let mut selection = ChannelRows {
+        amplifier: Vec::new(),
+        board_adc: Vec::new(),
+        board_dac: Vec::new(),
+        board_dig_in: Vec::new(),
+        board_dig_out: Vec::new(),
+    };
+    for selector in selectors {
+        let (rows, channels, index) = match selector {
+            ChannelSelector::Name(name) => {
+                let has_name = |channel: &ChannelInfo| {
+                    *name == channel.native_channel_name || *name == channel.custom_channel_name
+                };
+                let mut found = false;
+                for (rows, channels) in [
+                    (&mut selection.amplifier, &header.amplifier_channels),
+                    (&mut selection.board_adc, &header.board_adc_channels),
+                    (&mut selection.board_dac, &header.board_dac_channels),
+                    (&mut selection.board_dig_in, &header.board_dig_in_channels),
+                    (&mut selection.board_dig_out, &header.board_dig_out_channels),
+                ] {
+                    for (i, channel) in channels.iter().enumerate() {
+                        if has_name(channel) {
+                            rows.push(i);
+                            found = true;
+                        }
+                    }
+                }
+                if !found {
+                    return Err(IntanError::ChannelNotFound(selector.clone()));
+                }
+                continue;
+            }
+            ChannelSelector::Amplifier(index) => {
+                (&mut selection.amplifier, &header.amplifier_channels, *index)
+            }
+            ChannelSelector::BoardAdc(index) => {
+                (&mut selection.board_adc, &header.board_adc_channels, *index)
+            }
+            ChannelSelector::BoardDac(index) => {
+                (&mut selection.board_dac, &header.board_dac_channels, *index)
+            }
+            ChannelSelector::BoardDigIn(index) => {
+                (&mut selection.board_dig_in, &header.board_dig_in_channels, *index)
+            }
+            ChannelSelector::BoardDigOut(index) => {
+                (&mut selection.board_dig_out, &header.board_dig_out_channels, *index)
+            }
+        };
+        if index >= channels.len() {
+            return Err(IntanError::ChannelNotFound(selector.clone()));
+        }
+        rows.push(index);
+    }
+
+    Ok(selection)
+}
+
+/// Restricts the header's channel lists, and the spike triggers of the amplifier
+/// channels, to the given rows, in the given order
+pub(crate) fn select_header_channels(header: &mut RhsHeader, selection: &ChannelRows) {
+    // Spike triggers are listed once per amplifier channel
+    if header.spike_triggers.len() == header.amplifier_channels.len() {
+        keep_rows(&mut header.spike_triggers, &selection.amplifier);
+    }
+    keep_rows(&mut header.amplifier_channels, &selection.amplifier);
+    keep_rows(&mut header.board_adc_channels, &selection.board_adc);
+    keep_rows(&mut header.board_dac_channels, &selection.board_dac);
+    keep_rows(&mut header.board_dig_in_channels, &selection.board_dig_in);
+    keep_rows(&mut header.board_dig_out_channels, &selection.board_dig_out);
+}
+
+/// Keeps only the items at the given indices, in order
+fn keep_rows<T: Clone>(items: &mut Vec<T>, rows: &[usize]) {
+    *items = rows.iter().map(|&i| items[i].clone()).collect();
+}
+
+/// Restricts raw data to the selected channel rows and samples
+pub(crate) fn select_raw_data(raw_data: RawData, rows: Option<&ChannelRows>, samples: Range<usize>) -> RawData {
+    let all_samples = samples.start == 0 && samples.end == raw_data.timestamps.len();
+
+    // Samples are trimmed in place, without copying the kept samples
+    let select = |array: Option<Array2<u16>>, rows: Option<&Vec<usize>>| {
+        let mut array = array?;
+        if !all_samples {
+            array.slice_collapse(s![.., samples.clone()]);
+        }
+        match rows {
+            None => Some(array),
+            Some(rows) if rows.is_empty() => None,
+            Some(rows) => Some(array.select(Axis(0), rows)),
+        }
+    };
+    // Digital channels share a single word per sample, which is kept whole
+    let select_digital = |array: Option<Array2<u16>>, rows: Option<&Vec<usize>>| {
+        select(array, None).filter(|_| rows.is_none_or(|rows| !rows.is_empty()))
+    };
+
+    let mut timestamps = raw_data.timestamps;
+    if !all_samples {
+        timestamps.slice_collapse(s![samples.clone()]);
+    }
+
+    RawData {
+        timestamps,
+        amplifier_data_raw: select(raw_data.amplifier_data_raw, rows.map(|r| &r.amplifier)),
+        dc_amplifier_data_raw: select(raw_data.dc_amplifier_data_raw, rows.map(|r| &r.amplifier)),
+        stim_data_raw: select(raw_data.stim_data_raw, rows.map(|r| &r.amplifier)),
+        board_adc_data_raw: select(raw_data.board_adc_data_raw, rows.map(|r| &r.board_adc)),
+        board_dac_data_raw: select(raw_data.board_dac_data_raw, rows.map(|r| &r.board_dac)),
+        board_dig_in_raw: select_digital(raw_data.board_dig_in_raw, rows.map(|r| &r.board_dig_in)),
+        board_dig_out_raw: select_digital(raw_data.board_dig_out_raw, rows.map(|r| &r.board_dig_out)),
+    }
+}
+
+// Helper function to log record time summary
+fn log_record_time_summary(num_amp_samples: u64, sample_rate: f32, data_present: bool) {
     let record_time = num_amp_samples as f32 / sample_rate;
 
     if data_present {
-        println!(
+        info!(
             "File contains {:.3} seconds of data. Amplifiers were sampled at {:.2} kS/s.",
             record_time,
             sample_rate / 1000.0
         );
     } else {
-        println!(
+        info!(
             "Header file contains no data. Amplifiers were sampled at {:.2} kS/s.",
             sample_rate / 1000.0
         );
@@ -591,7 +1283,9 @@ fn print_record_time_summary(num_amp_samples: u64, sample_rate: f32, data_presen
 }
 
 // Helper function to get bytes per data block
-fn get_bytes_per_data_block(header: &RhsHeader) -> Result<usize, Box<dyn std::error::Error>> {
+pub(crate) fn get_bytes_per_data_block(header: &RhsHeader) -> Result<usize, Box<dyn std::error::Error>> {
+    // Sizes are added up in 64 bits, so that a header listing more channels than a
+    // block of this platform can hold gives an error rather than a wrapped size
     // RHS files always have 128 samples per data block
     let num_samples_per_data_block = 128;
 
@@ -645,7 +1339,9 @@ fn get_bytes_per_data_block(header: &RhsHeader) -> Result<usize, Box<dyn std::er
         bytes_per_block += bytes_per_signal_type(num_samples_per_data_block, 1, 2);
     }
 
-    Ok(bytes_per_block)
+    usize::try_from(bytes_per_block).map_err(|_| {
+        IntanError::ExceedsAddressSpace(format!("Data blocks of {} bytes", bytes_per_block)).into()
+    })
 }
 
 // Helper function to calculate bytes per signal type
@@ -653,109 +1349,95 @@ fn bytes_per_signal_type(
     num_samples: usize,
     num_channels: usize,
     bytes_per_sample: usize,
-) -> usize {
-    num_samples * num_channels * bytes_per_sample
+) -> u64 {
+    num_samples as u64 * num_channels as u64 * bytes_per_sample as u64
 }
 
-// Helper struct to store raw data during reading
-struct RawData {
-    timestamps: Array1<i32>,
-    amplifier_data_raw: Option<Array2<i32>>,
-    dc_amplifier_data_raw: Option<Array2<i32>>,
-    stim_data_raw: Option<Array2<i32>>,
-    board_adc_data_raw: Option<Array2<i32>>,
-    board_dac_data_raw: Option<Array2<i32>>,
-    board_dig_in_raw: Option<Array2<i32>>,
-    board_dig_out_raw: Option<Array2<i32>>,
+// Helper struct to store raw data during reading, as the 16-bit words stored in
+// the file
+pub(crate) struct RawData {
+    pub(crate) timestamps: Array1<i64>,
+    pub(crate) amplifier_data_raw: Option<Array2<u16>>,
+    pub(crate) dc_amplifier_data_raw: Option<Array2<u16>>,
+    pub(crate) stim_data_raw: Option<Array2<u16>>,
+    pub(crate) board_adc_data_raw: Option<Array2<u16>>,
+    pub(crate) board_dac_data_raw: Option<Array2<u16>>,
+    pub(crate) board_dig_in_raw: Option<Array2<u16>>,
+    pub(crate) board_dig_out_raw: Option<Array2<u16>>,
 }
 
-/// Helper function to read all data blocks
+/// Helper function to read data blocks
 ///
-/// This function reads all data blocks from the file into memory, organized by channel type.
-fn read_all_data_blocks<R: Read + Seek>(
+/// This function reads `num_blocks` data blocks, starting at the current position
+/// of `reader`, into memory, organized by channel type. With a channel selection,
+/// only the selected rows are stored and the samples of other channels are skipped.
+pub(crate) fn read_data_blocks<R: Read + Seek>(
     header: &RhsHeader,
-    num_samples: u64,
+    rows: Option<&ChannelRows>,
     num_blocks: u64,
+    options: &LoadOptions,
     reader: &mut R,
 ) -> Result<RawData, Box<dyn std::error::Error>> {
-    println!("Reading data from file...");
+    let num_samples = sample_index(num_blocks * SAMPLES_PER_DATA_BLOCK as u64)?;
+    if options.verbose {
+        info!("Reading data from file...");
+    }
 
-    // Initialize memory for raw data
-    let mut raw_data = RawData {
-        timestamps: Array1::zeros(num_samples as usize),
-        amplifier_data_raw: if !header.amplifier_channels.is_empty() {
-            Some(Array2::zeros((
-                header.amplifier_channels.len(),
-                num_samples as usize,
-            )))
+    // Initialize memory for the selected channels of each signal type
+    let allocate = |selected: Option<&Vec<usize>>, channels: &[ChannelInfo]| {
+        let num_channels = selected.map_or(channels.len(), Vec::len);
+        if num_channels > 0 {
+            Some(Array2::zeros((num_channels, num_samples)))
         } else {
             None
-        },
-        dc_amplifier_data_raw: if !header.amplifier_channels.is_empty()
-            && header.dc_amplifier_data_saved
-        {
-            Some(Array2::zeros((
-                header.amplifier_channels.len(),
-                num_samples as usize,
-            )))
-        } else {
-            None
-        },
-        stim_data_raw: if !header.amplifier_channels.is_empty() {
-            Some(Array2::zeros((
-                header.amplifier_channels.len(),
-                num_samples as usize,
-            )))
-        } else {
-            None
-        },
-        board_adc_data_raw: if !header.board_adc_channels.is_empty() {
-            Some(Array2::zeros((
-                header.board_adc_channels.len(),
-                num_samples as usize,
-            )))
-        } else {
-            None
-        },
-        board_dac_data_raw: if !header.board_dac_channels.is_empty() {
-            Some(Array2::zeros((
-                header.board_dac_channels.len(),
-                num_samples as usize,
-            )))
-        } else {
-            None
-        },
-        board_dig_in_raw: if !header.board_dig_in_channels.is_empty() {
-            Some(Array2::zeros((
-                header.board_dig_in_channels.len(),
-                num_samples as usize,
-            )))
-        } else {
-            None
-        },
-        board_dig_out_raw: if !header.board_dig_out_channels.is_empty() {
-            Some(Array2::zeros((
-                header.board_dig_out_channels.len(),
-                num_samples as usize,
-            )))
+        }
+    };
+    // Digital channels share one word per sample, stored as a single row
+    let allocate_word = |selected: Option<&Vec<usize>>, channels: &[ChannelInfo]| {
+        allocate(selected, channels).map(|_: Array2<u16>| Array2::zeros((1, num_samples)))
+    };
+    let mut raw_data = RawData {
+        timestamps: Array1::zeros(num_samples),
+        amplifier_data_raw: allocate(rows.map(|r| &r.amplifier), &header.amplifier_channels),
+        dc_amplifier_data_raw: if header.dc_amplifier_data_saved {
+            allocate(rows.map(|r| &r.amplifier), &header.amplifier_channels)
         } else {
             None
         },
+        stim_data_raw: allocate(rows.map(|r| &r.amplifier), &header.amplifier_channels),
+        board_adc_data_raw: allocate(rows.map(|r| &r.board_adc), &header.board_adc_channels),
+        board_dac_data_raw: allocate(rows.map(|r| &r.board_dac), &header.board_dac_channels),
+        board_dig_in_raw: allocate_word(rows.map(|r| &r.board_dig_in), &header.board_dig_in_channels),
+        board_dig_out_raw: allocate_word(rows.map(|r| &r.board_dig_out), &header.board_dig_out_channels),
     };
 
     // Read each data block
     let print_step = PRINT_PROGRESS_STEP;
     let mut percent_done = print_step;
-    let num_blocks = num_blocks as usize;
+    let start_position = match options.progress {
+        Some(_) => reader.stream_position()?,
+        None => 0,
+    };
+    let bytes_per_block = get_bytes_per_data_block(header)? as u64;
 
-    for i in 0..num_blocks {
+    for i in 0..num_blocks as usize {
         let index = i * SAMPLES_PER_DATA_BLOCK;
-        read_one_data_block(&mut raw_data, header, index, reader)?;
+        read_one_data_block(&mut raw_data, header, rows, index, reader)?;
 
-        // Print progress
+        // Report progress to the callback, or print it
         let progress = (i as f64 / num_blocks as f64) * 100.0;
-        if progress >= percent_done as f64 {
-            println!("{}% done...", percent_done);
+        if let Some(callback) = &options.progress {
+            let blocks_parsed = i as u64 + 1;
+            callback.report(&LoadProgress {
+                phase: LoadPhase::Data,
+                bytes_read: start_position + blocks_parsed * bytes_per_block,
+                blocks_parsed,
+                total_blocks: num_blocks,
+                channels_filtered: 0,
+                total_channels: 0,
+            });
+        } else if options.verbose && progress >= percent_done as f64 {
+            debug!("{}% done...", percent_done);
             percent_done += print_step;
         }
     }
@@ -770,6 +1452,7 @@ fn read_all_data_blocks<R: Read + Seek>(
 fn read_one_data_block<R: Read + Seek>(
     data: &mut RawData,
     header: &RhsHeader,
+    rows: Option<&ChannelRows>,
     index: usize,
     reader: &mut R,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -779,7 +1462,7 @@ fn read_one_data_block<R: Read + Seek>(
     read_timestamps(reader, &mut data.timestamps, index, samples_per_block)?;
 
     // Read analog signals
-    read_analog_signals(reader, data, header, index, samples_per_block)?;
+    read_analog_signals(reader, data, header, rows, index, samples_per_block)?;
 
     // Read digital signals
     read_digital_signals(reader, data, header, index, samples_per_block)?;
@@ -792,7 +1475,7 @@ fn read_one_data_block<R: Read + Seek>(
 /// Reads a block of timestamp values from the file into the timestamps array.
 fn read_timestamps<R: Read>(
     reader: &mut R,
-    timestamps: &mut Array1<i32>,
+    timestamps: &mut Array1<i64>,
     index: usize,
     num_samples: usize,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -805,7 +1488,7 @@ fn read_timestamps<R: Read>(
 
     let mut timestamps_slice = timestamps.slice_mut(s![start..end]);
 
-    // Parse bytes into i32 values
+    // Parse bytes into i32 values, widened to i64
     for i in 0..num_samples {
         let ts = i32::from_le_bytes([
             buffer[i * 4],
@@ -813,7 +1496,7 @@ fn read_timestamps<R: Read>(
             buffer[i * 4 + 2],
             buffer[i * 4 + 3],
         ]);
-        timestamps_slice[i] = ts;
+        timestamps_slice[i] = ts as i64;
     }
 
     Ok(())
@@ -826,87 +1509,77 @@ fn read_analog_signals<R: Read>(
     reader: &mut R,
     data: &mut RawData,
     header: &RhsHeader,
+    rows: Option<&ChannelRows>,
     index: usize,
     samples_per_block: usize,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let num_amplifier_channels = header.amplifier_channels.len();
+    let amplifier_rows = rows.map(|r| r.amplifier.as_slice());
 
     // Read amplifier data
-    if num_amplifier_channels > 0 {
-        if let Some(ref mut amp_data) = data.amplifier_data_raw {
-            read_analog_signal_type(
-                reader,
-                amp_data,
-                index,
-                samples_per_block,
-                num_amplifier_channels,
-            )?;
-        }
-    }
+    read_analog_signal_type(
+        reader,
+        data.amplifier_data_raw.as_mut(),
+        amplifier_rows,
+        index,
+        samples_per_block,
+        num_amplifier_channels,
+    )?;
 
     // Read DC amplifier data
-    if num_amplifier_channels > 0 && header.dc_amplifier_data_saved {
-        if let Some(ref mut dc_amp_data) = data.dc_amplifier_data_raw {
-            read_analog_signal_type(
-                reader,
-                dc_amp_data,
-                index,
-                samples_per_block,
-                num_amplifier_channels,
-            )?;
-        }
+    if header.dc_amplifier_data_saved {
+        read_analog_signal_type(
+            reader,
+            data.dc_amplifier_data_raw.as_mut(),
+            amplifier_rows,
+            index,
+            samples_per_block,
+            num_amplifier_channels,
+        )?;
     }
 
     // Read stim data
-    if num_amplifier_channels > 0 {
-        if let Some(ref mut stim_data) = data.stim_data_raw {
-            read_analog_signal_type(
-                reader,
-                stim_data,
-                index,
-                samples_per_block,
-                num_amplifier_channels,
-            )?;
-        }
-    }
+    read_analog_signal_type(
+        reader,
+        data.stim_data_raw.as_mut(),
+        amplifier_rows,
+        index,
+        samples_per_block,
+        num_amplifier_channels,
+    )?;
 
     // Read board ADC data
-    let num_board_adc_channels = header.board_adc_channels.len();
-    if num_board_adc_channels > 0 {
-        if let Some(ref mut adc_data) = data.board_adc_data_raw {
-            read_analog_signal_type(
-                reader,
-                adc_data,
-                index,
-                samples_per_block,
-                num_board_adc_channels,
-            )?;
-        }
-    }
+    read_analog_signal_type(
+        reader,
+        data.board_adc_data_raw.as_mut(),
+        rows.map(|r| r.board_adc.as_slice()),
+        index,
+        samples_per_block,
+        header.board_adc_channels.len(),
+    )?;
 
     // Read board DAC data
-    let num_board_dac_channels = header.board_dac_channels.len();
-    if num_board_dac_channels > 0 {
-        if let Some(ref mut dac_data) = data.board_dac_data_raw {
-            read_analog_signal_type(
-                reader,
-                dac_data,
-                index,
-                samples_per_block,
-                num_board_dac_channels,
-            )?;
-        }
-    }
+    read_analog_signal_type(
+        reader,
+        data.board_dac_data_raw.as_mut(),
+        rows.map(|r| r.board_dac.as_slice()),
+        index,
+        samples_per_block,
+        header.board_dac_channels.len(),
+    )?;
 
     Ok(())
 }
 
 /// Helper function to read an analog signal type
 ///
-/// Reads a block of analog samples for multiple channels and stores them in the destination array.
+/// Reads a block of analog samples for multiple channels and stores the channels in
+/// `rows` (all channels if `None`) in the destination array. Without a destination,
+/// the block is skipped.
 fn read_analog_signal_type<R: Read>(
     reader: &mut R,
-    dest: &mut Array2<i32>,
+    dest: Option<&mut Array2<u16>>,
+    rows: Option<&[usize]>,
     start: usize,
     num_samples: usize,
     num_channels: usize,
@@ -915,22 +1588,18 @@ fn read_analog_signal_type<R: Read>(
         return Ok(());
     }
 
-    let end = start + num_samples;
+    let Some(dest) = dest else {
+        return skip_bytes(reader, num_samples * num_channels * 2);
+    };
 
-    // Read all channel data in one operation
-    let mut buffer = vec![0u8; num_samples * num_channels * 2];
-    reader.read_exact(&mut buffer)?;
+    let end = start + num_samples;
 
-    let mut t_slice = dest.slice_mut(s![.., start..end]);
+    // Read all channel data in one operation as unsigned 16-bit codes
+    let mut buffer = vec![0u16; num_samples * num_channels];
+    reader.read_u16_into::<LittleEndian>(&mut buffer)?;
 
-    // Parse bytes into i16 values and store in the appropriate channel/sample position
-    for ch in 0..num_channels {
-        for s in 0..num_samples {
-            let idx = 2 * (s * num_channels + ch);
-            let sample = i16::from_le_bytes([buffer[idx], buffer[idx + 1]]) as i32;
-            t_slice[[ch, s]] = sample;
-        }
-    }
+    // Move each code to its channel/sample position
+    deinterleave(&buffer, num_channels, rows, dest.slice_mut(s![.., start..end]));
 
     Ok(())
 }
@@ -962,46 +1631,60 @@ fn read_digital_signals<R: Read>(
 
 /// Helper function to read a digital signal type
 ///
-/// Reads a block of digital samples for multiple channels and stores them in the destination array.
-/// For digital signals, the same value is copied to all channels since they share the same data word.
+/// Reads a block of digital samples and stores them in the destination array.
+/// For digital signals, the same value is copied to all rows since the channels
+/// share the same data word. Without a destination, the block is skipped.
 fn read_digital_signal_type<R: Read>(
     reader: &mut R,
-    dest: &mut Option<Array2<i32>>,
+    dest: &mut Option<Array2<u16>>,
     start: usize,
     num_samples: usize,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(dest_array) = dest.as_mut() {
-        let num_channels = dest_array.shape()[0];
-        if num_channels < 1 {
-            return Ok(());
-        }
+    let Some(dest_array) = dest.as_mut() else {
+        return skip_bytes(reader, num_samples * 2);
+    };
+
+    let num_channels = dest_array.shape()[0];
+    if num_channels < 1 {
+        return Ok(());
+    }
 
-        let end = start + num_samples;
+    let end = start + num_samples;
 
-        // Read all digital data in one operation
-        let mut buffer = vec![0u8; num_samples * 2];
-        reader.read_exact(&mut buffer)?;
+    // Read all digital data in one operation
+    let mut buffer = vec![0u8; num_samples * 2];
+    reader.read_exact(&mut buffer)?;
 
-        let mut t_slice = dest_array.slice_mut(s![.., start..end]);
+    let mut t_slice = dest_array.slice_mut(s![.., start..end]);
 
-        // For each sample, duplicate the value across all channels
-        for s in 0..num_samples {
-            let value = u16::from_le_bytes([buffer[s * 2], buffer[s * 2 + 1]]) as i32;
+    // For each sample, duplicate the value across all channels
+    for s in 0..num_samples {
+        let value = u16::from_le_bytes([buffer[s * 2], buffer[s * 2 + 1]]);
 
-            for ch in 0..num_channels {
-                t_slice[[ch, s]] = value;
-            }
+        for ch in 0..num_channels {
+            t_slice[[ch, s]] = value;
         }
     }
 
     Ok(())
 }
 
+/// Helper function to skip bytes of unselected channels
+///
+/// Reads past the bytes rather than seeking, which would discard the read buffer.
+fn skip_bytes<R: Read>(reader: &mut R, num_bytes: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let skipped = std::io::copy(&mut reader.take(num_bytes as u64), &mut std::io::sink())?;
+    if skipped < num_bytes as u64 {
+        return Err(Box::new(IntanError::IoError(std::io::ErrorKind::UnexpectedEof.into())));
+    }
+    Ok(())
+}
+
 /// Helper function to check end of file
 ///
 /// Verifies that we've reached the end of the file after reading all data.
 /// If there are bytes remaining, there's a problem with our understanding of the file format.
-fn check_end_of_file<R: Read + Seek>(filesize: u64, reader: &mut R) -> Result<(), Box<dyn std::error::Error>> {
+pub(crate) fn check_end_of_file<R: Read + Seek>(filesize: u64, reader: &mut R) -> Result<(), Box<dyn std::error::Error>> {
     let current_position = reader.stream_position()?;
     let bytes_remaining = filesize - current_position;
 
@@ -1013,15 +1696,30 @@ fn check_end_of_file<R: Read + Seek>(filesize: u64, reader: &mut R) -> Result<()
 }
 
 // Helper function to process raw data into final form
-fn process_data(
+pub(crate) fn process_data(
     header: &RhsHeader,
     raw_data: RawData,
+    options: &LoadOptions,
 ) -> Result<RhsData, Box<dyn std::error::Error>> {
-    println!("Processing data...");
+    if options.verbose {
+        info!("Processing data...");
+    }
+
+    // Every raw buffer is moved out and consumed, so nothing is copied needlessly
+    let RawData {
+        timestamps,
+        mut amplifier_data_raw,
+        mut dc_amplifier_data_raw,
+        stim_data_raw,
+        mut board_adc_data_raw,
+        mut board_dac_data_raw,
+        board_dig_in_raw,
+        board_dig_out_raw,
+    } = raw_data;
 
     // Create RhsData struct to hold processed data
     let mut data = RhsData {
-        timestamps: raw_data.timestamps.clone(),
+        timestamps,
         amplifier_data: None,
         dc_amplifier_data: None,
         stim_data: None,
@@ -1032,31 +1730,59 @@ fn process_data(
         board_dac_data: None,
         board_dig_in_data: None,
         board_dig_out_data: None,
+        amplifier_data_raw: None,
+        dc_amplifier_data_raw: None,
+        board_adc_data_raw: None,
+        board_dac_data_raw: None,
+        amplifier_data_f32: None,
+        dc_amplifier_data_f32: None,
+        board_adc_data_f32: None,
+        board_dac_data_f32: None,
+        spilled: None,
+        units: SignalUnits::default(),
     };
 
-    // Scale timestamps
-    check_timestamps(&data.timestamps);
-
-    // Process amplifier data
-    if let Some(amp_data_raw) = raw_data.amplifier_data_raw {
-        let mut amp_data = scale_amplifier_data(&amp_data_raw);
+    // Keep the analog signals as raw codes if requested
+    if options.raw {
+        data.amplifier_data_raw = amplifier_data_raw.take().map(standard_layout);
+        data.dc_amplifier_data_raw = dc_amplifier_data_raw.take().map(standard_layout);
+        data.board_adc_data_raw = board_adc_data_raw.take().map(standard_layout);
+        data.board_dac_data_raw = board_dac_data_raw.take().map(standard_layout);
+    }
 
-        // Apply notch filter if necessary
-        apply_notch_filter(header, &mut amp_data);
+    // Each signal is scaled one channel at a time, freeing the raw values of a
+    // channel as soon as they are converted, so peak memory stays close to the
+    // size of the processed data
 
-        data.amplifier_data = Some(amp_data);
+    // Process amplifier data
+    if let Some(amp_data_raw) = amplifier_data_raw {
+        match options.data_type {
+            DataType::F64 => {
+                data.amplifier_data = Some(process_amplifier_data(header, amp_data_raw, options)?);
+            }
+            DataType::F32 => {
+                data.amplifier_data_f32 =
+                    Some(process_amplifier_data(header, amp_data_raw, options)?);
+            }
+        }
     }
 
     // Process DC amplifier data
-    if let Some(dc_amp_data_raw) = raw_data.dc_amplifier_data_raw {
-        let dc_amp_data = scale_dc_amplifier_data(&dc_amp_data_raw);
-        data.dc_amplifier_data = Some(dc_amp_data);
+    if let Some(dc_amp_data_raw) = dc_amplifier_data_raw {
+        match options.data_type {
+            DataType::F64 => {
+                data.dc_amplifier_data = Some(scale_dc_amplifier_data(dc_amp_data_raw));
+            }
+            DataType::F32 => {
+                data.dc_amplifier_data_f32 = Some(scale_dc_amplifier_data(dc_amp_data_raw));
+            }
+        }
     }
 
     // Process stim data
-    if let Some(stim_data_raw) = raw_data.stim_data_raw {
+    if let Some(stim_data_raw) = stim_data_raw {
         let (stim_data, compliance_limit_data, charge_recovery_data, amp_settle_data) =
-            extract_stim_data(&stim_data_raw, header.stim_step_size);
+            extract_stim_data(stim_data_raw, header.stim_step_size);
 
         data.stim_data = Some(stim_data);
         data.compliance_limit_data = Some(compliance_limit_data);
@@ -1065,19 +1791,23 @@ fn process_data(
     }
 
     // Process board ADC data
-    if let Some(adc_data_raw) = raw_data.board_adc_data_raw {
-        let adc_data = scale_adc_data(&adc_data_raw);
-        data.board_adc_data = Some(adc_data);
+    if let Some(adc_data_raw) = board_adc_data_raw {
+        match options.data_type {
+            DataType::F64 => data.board_adc_data = Some(scale_adc_data(adc_data_raw)),
+            DataType::F32 => data.board_adc_data_f32 = Some(scale_adc_data(adc_data_raw)),
+        }
     }
 
     // Process board DAC data
-    if let Some(dac_data_raw) = raw_data.board_dac_data_raw {
-        let dac_data = scale_dac_data(&dac_data_raw);
-        data.board_dac_data = Some(dac_data);
+    if let Some(dac_data_raw) = board_dac_data_raw {
+        match options.data_type {
+            DataType::F64 => data.board_dac_data = Some(scale_dac_data(dac_data_raw)),
+            DataType::F32 => data.board_dac_data_f32 = Some(scale_dac_data(dac_data_raw)),
+        }
     }
 
     // Process digital input data
-    if let Some(dig_in_raw) = raw_data.board_dig_in_raw {
+    if let Some(dig_in_raw) = board_dig_in_raw {
         data.board_dig_in_data = Some(extract_digital_data(
             &dig_in_raw,
             &header.board_dig_in_channels,
@@ -1085,7 +1815,7 @@ fn process_data(
     }
 
     // Process digital output data
-    if let Some(dig_out_raw) = raw_data.board_dig_out_raw {
+    if let Some(dig_out_raw) = board_dig_out_raw {
         data.board_dig_out_data = Some(extract_digital_data(
             &dig_out_raw,
             &header.board_dig_out_channels,
@@ -1095,117 +1825,238 @@ fn process_data(
     Ok(data)
 }
 
-// Helper function to scale timestamps
-fn check_timestamps(timestamps: &Array1<i32>) {
-    // Check for gaps in timestamps
-    let num_gaps = timestamps
+/// Sets the path of the `warnings` that have none to `path`, logging them
+pub(crate) fn attribute_warnings(warnings: &mut [LoadWarning], path: &Path) {
+    for warning in warnings {
+        if let Some(warning_path) = warning.path_mut().filter(|p| p.is_empty()) {
+            *warning_path = path.to_string_lossy().to_string();
+            warn!("{}", warning);
+        }
+    }
+}
+
+/// Attributes the header warnings of the recording read from `path` to the file,
+/// logging them, and adds a warning if its timestamps have gaps
+pub(crate) fn add_file_warnings(rhs_file: &mut RhsFile, path: &Path, verbose: bool) {
+    attribute_warnings(&mut rhs_file.warnings, path);
+
+    let Some(data) = &rhs_file.data else {
+        return;
+    };
+    let num_gaps = data
+        .timestamps
         .windows(2)
         .into_iter()
         .filter(|window| window[1] - window[0] != 1)
         .count();
 
     if num_gaps == 0 {
-        println!("No missing timestamps in data.");
+        if verbose {
+            info!("No missing timestamps in data.");
+        }
+        return;
+    }
+    let warning = LoadWarning::TimestampGaps {
+        path: path.to_string_lossy().to_string(),
+        num_gaps,
+    };
+    if verbose {
+        warn!("{}", warning);
+    }
+    rhs_file.warnings.push(warning);
+}
+
+/// Unwraps timestamps that overflowed the 32-bit values RHX stores
+///
+/// RHX counts samples in a signed 32-bit integer, which wraps from its largest to
+/// its smallest value after about 20 hours at 30 kS/s. Wherever a timestamp drops
+/// by more than 2^31, 2^32 is added to it and to every later timestamp, so time
+/// keeps increasing. Unwrapping continues from `previous`, the unwrapped timestamp
+/// before the first one, or from the first timestamp if `previous` is `None`.
+/// Only the low 32 bits of each timestamp are used, so timestamps that were already
+/// unwrapped are unwrapped again the same way.
+pub(crate) fn unwrap_timestamps(mut timestamps: ArrayViewMut1<'_, i64>, previous: Option<i64>) {
+    let Some(mut previous) = previous.or_else(|| timestamps.first().copied()) else {
+        return;
+    };
+    for timestamp in timestamps.iter_mut() {
+        let stored = *timestamp as i32 as i64;
+        let previous_stored = previous as i32 as i64;
+        let mut offset = previous - previous_stored;
+        if stored - previous_stored < -(1 << 31) {
+            offset += 1 << 32;
+        }
+        *timestamp = stored + offset;
+        previous = *timestamp;
+    }
+}
+
+/// Floating-point type that scaled signals are stored as
+///
+/// Scaling and filtering are computed in double precision, and each result is
+/// rounded once when stored. The notch filter reads the stored samples, so `f32`
+/// amplifier data can differ from rounded `f64` data in the last bit.
+pub(crate) trait Sample: Copy + Default + Send + Sync {
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+}
+
+impl Sample for f64 {
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl Sample for f32 {
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+/// Scales amplifier data and applies the notch filter if necessary
+fn process_amplifier_data<T: Sample>(
+    header: &RhsHeader,
+    data_raw: Array2<u16>,
+    options: &LoadOptions,
+) -> Result<Array2<T>, Box<dyn std::error::Error>> {
+    let mut amp_data = scale_amplifier_data(data_raw);
+
+    // Apply notch filter if necessary
+    apply_notch_filter(header, &mut amp_data, options)?;
+
+    Ok(amp_data)
+}
+
+/// Returns the codes in standard layout, copying them only if their samples were
+/// trimmed in place
+fn standard_layout(data_raw: Array2<u16>) -> Array2<u16> {
+    if data_raw.is_standard_layout() {
+        data_raw
     } else {
-        println!(
-            "Warning: {} gaps in timestamp data found. Time scale will not be uniform!",
-            num_gaps
-        );
+        data_raw.as_standard_layout().into_owned()
+    }
+}
+
+/// Converts raw codes with `f`, consuming `data_raw` one channel at a time
+///
+/// The output is allocated zeroed, so its pages are only committed as channels
+/// are written, while the raw codes of each channel are freed once converted.
+fn map_channels<T: Clone + Default>(data_raw: Array2<u16>, f: impl Fn(u16) -> T) -> Array2<T> {
+    let mut data = Array2::from_elem(data_raw.dim(), T::default());
+    consume_channels(data_raw, |channel, values| {
+        for (out, &x) in data.row_mut(channel).iter_mut().zip(values) {
+            *out = f(x);
+        }
+    });
+    data
+}
+
+/// Passes each channel of `data_raw` to `process_channel`, last channel first,
+/// shrinking the raw buffer after every channel so its memory is released as the
+/// processed data grows
+///
+/// Channels may be spaced further apart than their length, as they are after
+/// [`select_raw_data`] trims the samples in place.
+fn consume_channels(data_raw: Array2<u16>, mut process_channel: impl FnMut(usize, &[u16])) {
+    let (num_channels, num_samples) = data_raw.dim();
+    if num_samples == 0 {
+        (0..num_channels).rev().for_each(|channel| process_channel(channel, &[]));
+        return;
+    }
+
+    let strides = data_raw.strides();
+    let (data_raw, row_stride) = if strides[1] == 1 && strides[0] >= num_samples as isize {
+        let row_stride = strides[0] as usize;
+        (data_raw, row_stride)
+    } else {
+        (data_raw.as_standard_layout().into_owned(), num_samples)
+    };
+    let (mut values, offset) = data_raw.into_raw_vec_and_offset();
+    let offset = offset.unwrap_or(0);
+
+    for channel in (0..num_channels).rev() {
+        let start = offset + channel * row_stride;
+        process_channel(channel, &values[start..start + num_samples]);
+        values.truncate(start);
+        values.shrink_to_fit();
     }
 }
 
 /// Scales amplifier data from raw ADC values to microvolts
 ///
 /// Uses the scaling factor of 0.195 μV/bit with an offset of 32768
-/// Raw values are treated as unsigned 16-bit integers
-fn scale_amplifier_data(data_raw: &Array2<i32>) -> Array2<f64> {
-    // Convert from signed to unsigned representation, then scale to microvolts
-    data_raw.mapv(|x| {
-        // Data was read as signed int16 but represents unsigned uint16 values
-        let unsigned_val = if x < 0 { 
-            (x + 65536) as f64 
-        } else { 
-            x as f64 
-        };
-        (unsigned_val - ADC_DAC_OFFSET) * AMPLIFIER_SCALE_FACTOR
-    })
+/// Raw values are unsigned 16-bit codes
+fn scale_amplifier_data<T: Sample>(data_raw: Array2<u16>) -> Array2<T> {
+    // Remove the offset, then scale to microvolts
+    map_channels(data_raw, |x| T::from_f64((x as f64 - ADC_DAC_OFFSET) * AMPLIFIER_SCALE_FACTOR))
 }
 
 /// Scales DC amplifier data from raw ADC values to volts
 ///
 /// Uses the scaling factor of 19.23 mV/bit with an offset of 512
 /// Returns values in volts (not millivolts) for consistency
-fn scale_dc_amplifier_data(data_raw: &Array2<i32>) -> Array2<f64> {
-    // Convert from signed to unsigned, then scale to millivolts and convert to volts
-    data_raw.mapv(|x| {
-        let unsigned_val = if x < 0 { 
-            (x + 65536) as f64 
-        } else { 
-            x as f64 
-        };
-        // Scale to millivolts then convert to volts
-        ((unsigned_val - DC_AMPLIFIER_OFFSET) * DC_AMPLIFIER_SCALE_FACTOR) / 1000.0
+fn scale_dc_amplifier_data<T: Sample>(data_raw: Array2<u16>) -> Array2<T> {
+    // Remove the offset, scale to millivolts, then convert to volts
+    map_channels(data_raw, |x| {
+        T::from_f64(((x as f64 - DC_AMPLIFIER_OFFSET) * DC_AMPLIFIER_SCALE_FACTOR) / 1000.0)
     })
 }
 
 /// Scales ADC data from raw ADC values to volts
 ///
 /// Uses the scaling factor of 0.0003125 V/bit with an offset of 32768
-/// Raw values are treated as unsigned 16-bit integers
-fn scale_adc_data(data_raw: &Array2<i32>) -> Array2<f64> {
-    // Convert from signed to unsigned representation, then scale to volts
-    data_raw.mapv(|x| {
-        let unsigned_val = if x < 0 { 
-            (x + 65536) as f64 
-        } else { 
-            x as f64 
-        };
-        (unsigned_val - ADC_DAC_OFFSET) * ADC_DAC_SCALE_FACTOR
-    })
+/// Raw values are unsigned 16-bit codes
+fn scale_adc_data<T: Sample>(data_raw: Array2<u16>) -> Array2<T> {
+    // Remove the offset, then scale to volts
+    map_channels(data_raw, |x| T::from_f64((x as f64 - ADC_DAC_OFFSET) * ADC_DAC_SCALE_FACTOR))
 }
 
 /// Scales DAC data from raw DAC values to volts
 ///
 /// Uses the scaling factor of 0.0003125 V/bit with an offset of 32768
-/// Raw values are treated as unsigned 16-bit integers
-fn scale_dac_data(data_raw: &Array2<i32>) -> Array2<f64> {
-    // Convert from signed to unsigned representation, then scale to volts
-    data_raw.mapv(|x| {
-        let unsigned_val = if x < 0 { 
-            (x + 65536) as f64 
-        } else { 
-            x as f64 
-        };
-        (unsigned_val - ADC_DAC_OFFSET) * ADC_DAC_SCALE_FACTOR
-    })
+/// Raw values are unsigned 16-bit codes
+fn scale_dac_data<T: Sample>(data_raw: Array2<u16>) -> Array2<T> {
+    // Remove the offset, then scale to volts
+    map_channels(data_raw, |x| T::from_f64((x as f64 - ADC_DAC_OFFSET) * ADC_DAC_SCALE_FACTOR))
 }
 
 // Helper function to extract stim data
 fn extract_stim_data(
-    stim_data_raw: &Array2<i32>,
+    stim_data_raw: Array2<u16>,
     stim_step_size: f32,
-) -> (Array2<i32>, Array2<bool>, Array2<bool>, Array2<bool>) {
-    let shape = stim_data_raw.shape();
-    let num_channels = shape[0];
-    let num_samples = shape[1];
+) -> (Array2<f64>, StimFlags, StimFlags, StimFlags) {
+    let (num_channels, num_samples) = stim_data_raw.dim();
 
-    let mut stim_data = Array2::<i32>::zeros((num_channels, num_samples));
-    let mut compliance_limit_data = Array2::<bool>::from_elem((num_channels, num_samples), false);
-    let mut charge_recovery_data = Array2::<bool>::from_elem((num_channels, num_samples), false);
-    let mut amp_settle_data = Array2::<bool>::from_elem((num_channels, num_samples), false);
+    let mut stim_data = Array2::<f64>::zeros((num_channels, num_samples));
+    // Flags are collected as runs of set samples, one list per channel
+    let mut compliance_limit_runs = vec![Vec::new(); num_channels];
+    let mut charge_recovery_runs = vec![Vec::new(); num_channels];
+    let mut amp_settle_runs = vec![Vec::new(); num_channels];
 
-    for i in 0..num_channels {
-        for j in 0..num_samples {
-            let value = stim_data_raw[[i, j]];
+    consume_channels(stim_data_raw, |i, values| {
+        let mut compliance_limit = RunBuilder::default();
+        let mut charge_recovery = RunBuilder::default();
+        let mut amp_settle = RunBuilder::default();
+
+        for (j, &code) in values.iter().enumerate() {
+            let value = code as i32;
 
             // Interpret 2^15 bit (compliance limit) as true or false
-            compliance_limit_data[[i, j]] = (value & 32768) != 0;
+            compliance_limit.push(j, (value & 32768) != 0);
 
             // Interpret 2^14 bit (charge recovery) as true or false
-            charge_recovery_data[[i, j]] = (value & 16384) != 0;
+            charge_recovery.push(j, (value & 16384) != 0);
 
             // Interpret 2^13 bit (amp settle) as true or false
-            amp_settle_data[[i, j]] = (value & 8192) != 0;
+            amp_settle.push(j, (value & 8192) != 0);
 
             // Interpret 2^8 bit (stim polarity) as +1 for 0_bit or -1 for 1_bit
             let stim_polarity = 1 - 2 * ((value & 256) >> 8);
@@ -1214,21 +2065,25 @@ fn extract_stim_data(
             let curr_amp = value & 255;
 
             // Multiply current amplitude by the correct sign and scaling factor
-            stim_data[[i, j]] = ((curr_amp * stim_polarity) as f32 * stim_step_size) as i32;
+            stim_data[[i, j]] = (curr_amp * stim_polarity) as f64 * stim_step_size as f64;
         }
-    }
+
+        compliance_limit_runs[i] = compliance_limit.finish(num_samples);
+        charge_recovery_runs[i] = charge_recovery.finish(num_samples);
+        amp_settle_runs[i] = amp_settle.finish(num_samples);
+    });
 
     (
         stim_data,
-        compliance_limit_data,
-        charge_recovery_data,
-        amp_settle_data,
+        StimFlags::from_runs(num_samples, compliance_limit_runs),
+        StimFlags::from_runs(num_samples, charge_recovery_runs),
+        StimFlags::from_runs(num_samples, amp_settle_runs),
     )
 }
 
 // Helper function to extract digital data
 fn extract_digital_data(
-    digital_data_raw: &Array2<i32>,
+    digital_data_raw: &Array2<u16>,
     channels: &[ChannelInfo],
 ) -> Result<Array2<i32>, Box<dyn std::error::Error>> {
     let shape = digital_data_raw.shape();
@@ -1238,7 +2093,7 @@ fn extract_digital_data(
     let mut digital_data = Array2::<i32>::zeros((num_channels, num_samples));
 
     for (i, channel) in channels.iter().enumerate() {
-        let mask = 1 << channel.native_order;
+        let mask = 1u16 << channel.native_order;
 
         for j in 0..num_samples {
             digital_data[[i, j]] = if (digital_data_raw[[0, j]] & mask) != 0 {
@@ -1253,243 +2108,1267 @@ fn extract_digital_data(
 }
 
 // Helper function to apply notch filter
-fn apply_notch_filter(header: &RhsHeader, data: &mut Array2<f64>) {
-    // If data was not recorded with notch filter turned on, return without applying notch filter
-    if header.notch_filter_frequency.is_none() {
-        return;
-    }
+fn apply_notch_filter<T: Sample>(
+    header: &RhsHeader,
+    data: &mut Array2<T>,
+    options: &LoadOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(filter) = notch_filter(header, options) else {
+        return Ok(());
+    };
 
-    // Similarly, if data was recorded from Intan RHX software version 3.0 or later,
-    // any active notch filter was already applied to the saved data, so it should not be re-applied
-    if header.version.major >= 3 {
-        return;
+    // Apply notch filter individually to each channel, in parallel
+    if options.verbose && options.progress.is_none() {
+        info!("Applying notch filter...");
     }
-
-    let notch_freq = header.notch_filter_frequency.unwrap() as f32;
-
-    // Apply notch filter individually to each channel
-    println!("Applying notch filter...");
     let print_step = 10;
-    let mut percent_done = print_step;
     let num_channels = data.shape()[0];
 
-    for i in 0..num_channels {
-        // Get channel data
-        let channel_data: Vec<f64> = data.slice(s![i, ..]).to_vec();
-
-        // Apply notch filter
-        let filtered_data = notch_filter(&channel_data, header.sample_rate, notch_freq, 10);
-
-        // Update the array
-        let mut slice = data.slice_mut(s![i, ..]);
-        for (j, &value) in filtered_data.iter().enumerate() {
-            slice[j] = value;
+    notch_filter_rows(data, &filter, header.sample_rate as f64, |channels_filtered| {
+        // Report progress to the callback, or print it
+        if let Some(callback) = &options.progress {
+            callback.report(&LoadProgress {
+                phase: LoadPhase::Filtering,
+                bytes_read: 0,
+                blocks_parsed: 0,
+                total_blocks: 0,
+                channels_filtered,
+                total_channels: num_channels,
+            });
+        } else if options.verbose {
+            let percent_done =
+                |channels: usize| channels * 100 / num_channels / print_step * print_step;
+            if percent_done(channels_filtered) > percent_done(channels_filtered - 1) {
+                debug!("{}% done...", percent_done(channels_filtered));
+            }
         }
+    })
+}
 
-        // Print progress
-        let progress = (i as f64 / num_channels as f64) * 100.0;
-        if progress >= percent_done as f64 {
-            println!("{}% done...", percent_done);
-            percent_done += print_step;
-        }
+// Helper function to get the notch filter to apply, if any
+pub(crate) fn notch_filter(header: &RhsHeader, options: &LoadOptions) -> Option<NotchFilter> {
+    // If data was not recorded with notch filter turned on, no notch filter is applied
+    let notch_freq = header.notch_filter_frequency?;
+
+    // Similarly, if data was recorded from Intan RHX software version 3.0 or later,
+    // any active notch filter was already applied to the saved data, so it should not be re-applied
+    // unless the caller forces it
+    match options.notch {
+        NotchMode::Skip => return None,
+        NotchMode::Auto if header.version.major >= 3 => return None,
+        NotchMode::Auto | NotchMode::Force => {}
     }
-}
 
-// Helper function to apply notch filter to a single channel
-fn notch_filter(signal_in: &[f64], f_sample: f32, f_notch: f32, bandwidth: i32) -> Vec<f64> {
-    let t_step = 1.0 / f_sample as f64;
-    let f_c = f_notch as f64 * t_step;
-    let signal_length = signal_in.len();
+    Some(NotchFilter {
+        frequency: notch_freq as f64,
+        bandwidth: options.notch_bandwidth,
+        sections: options.notch_sections,
+        zero_phase: options.zero_phase_notch,
+    })
+}
 
-    // Calculate filter parameters
-    let d = (-2.0 * PI * (bandwidth as f64 / 2.0) * t_step).exp();
-    let b = (1.0 + d * d) * (2.0 * PI * f_c).cos();
-    let a0 = 1.0;
-    let a1 = -b;
-    let a2 = d * d;
-    let a = (1.0 + d * d) / 2.0;
-    let b0 = 1.0;
-    let b1 = -2.0 * (2.0 * PI * f_c).cos();
-    let b2 = 1.0;
+/// Applies the notch filter to every row of `data` in place, in parallel across channels
+///
+/// `on_filtered` is called from the worker threads after each channel with the
+/// number of channels filtered so far.
+pub(crate) fn notch_filter_rows<T: Sample>(
+    data: &mut Array2<T>,
+    filter: &NotchFilter,
+    sample_rate: f64,
+    on_filtered: impl Fn(usize) + Sync,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let design = filter.design(sample_rate)?;
+    let channels_filtered = AtomicUsize::new(0);
 
-    let mut signal_out = vec![0.0; signal_length];
+    data.axis_iter_mut(Axis(0)).into_par_iter().for_each(|channel| {
+        if filter.zero_phase {
+            design.filter_zero_phase(channel);
+        } else {
+            design.filter(channel, &mut design.history(), 0);
+        }
+        on_filtered(channels_filtered.fetch_add(1, Ordering::Relaxed) + 1);
+    });
+    Ok(())
+}
 
-    // Initialize first two samples
-    signal_out[0] = signal_in[0];
-    signal_out[1] = signal_in[1];
+/// Notch filter applied to amplifier data one block at a time
+///
+/// Filtering consecutive blocks gives the same result as [`notch_filter_rows`] over
+/// the whole signal, because the last two input and output samples of every section
+/// of every channel carry over to the next block.
+pub(crate) struct BlockNotchFilter {
+    design: NotchDesign,
+    /// Previous `[input, input before, output, output before]` of each section of
+    /// each channel
+    history: Vec<Vec<[f64; 4]>>,
+    samples_filtered: usize,
+}
 
-    // Apply filter to the rest of the samples
-    for i in 2..signal_length {
-        signal_out[i] =
-            (a * b0 * signal_in[i] + a * b1 * signal_in[i - 1] + a * b2 * signal_in[i - 2]
-                - a2 * signal_out[i - 2]
-                - a1 * signal_out[i - 1])
-                / a0;
+impl BlockNotchFilter {
+    /// Returns the filter the header calls for, or `None` if no notch filter applies
+    ///
+    /// Fails if `options` ask for a zero-phase notch filter, which needs the whole
+    /// signal at once.
+    pub(crate) fn new(
+        header: &RhsHeader,
+        options: &LoadOptions,
+    ) -> Result<Option<BlockNotchFilter>, Box<dyn std::error::Error>> {
+        let Some(filter) = notch_filter(header, options) else {
+            return Ok(None);
+        };
+        if filter.zero_phase {
+            return Err(Box::new(IntanError::Other(
+                "A zero-phase notch filter needs the whole signal and cannot be applied \
+                 block by block"
+                    .to_string(),
+            )));
+        }
+        let design = filter.design(header.sample_rate as f64)?;
+        Ok(Some(BlockNotchFilter {
+            design,
+            history: vec![design.history(); header.amplifier_channels.len()],
+            samples_filtered: 0,
+        }))
     }
 
-    signal_out
+    /// Filters the next block of amplifier data in place
+    pub(crate) fn filter<T: Sample>(&mut self, data: &mut Array2<T>) {
+        for (channel, history) in data.rows_mut().into_iter().zip(&mut self.history) {
+            self.design.filter(channel, history, self.samples_filtered);
+        }
+
+        self.samples_filtered += data.ncols();
+    }
 }
 
 
 // Add these functions to the end of reader.rs
 
 /// Loads and combines multiple RHS files into a single dataset
-pub fn load_and_combine_files(file_paths: &[std::path::PathBuf]) -> Result<RhsFile, Box<dyn std::error::Error>> {
+///
+/// With a time range in `options`, files entirely outside the range are skipped
+/// without reading their data.
+pub fn load_and_combine_files(
+    file_paths: &[PathBuf],
+    options: &LoadOptions,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<RhsFile, IntanError> {
     
     if file_paths.is_empty() {
-        return Err(Box::new(IntanError::Other("No files to load".to_string())));
+        return Err(IntanError::NoFiles {
+            directory: None,
+            pattern: None,
+        });
     }
+
+    let (readable, mut skipped_files) = if options.skip_corrupt_files {
+        readable_files(file_paths)?
+    } else {
+        (file_paths.to_vec(), Vec::new())
+    };
+    let (readable, duplicates) =
+        crate::manifest::remove_duplicate_files(&readable, options.duplicate_handling)?;
+    skipped_files.extend(duplicates);
+    let file_paths = readable.as_slice();
+    let options = &*resolve_files_wallclock_range(file_paths, options)?;
+    let files = files_in_time_range(file_paths, options)?;
     
+    // Files before the time range are skipped, but their timestamps may have wrapped
+    let (first_path, first_sample) = files[0];
+    let previous_timestamp = if first_path != file_paths[0] {
+        crate::manifest::read_manifest(&file_paths[0])?.first_timestamp
+    } else {
+        None
+    };
+    if options.pipelined() {
+        return crate::pipeline::load_files(
+            &files,
+            previous_timestamp,
+            skipped_files,
+            options,
+            processors,
+        );
+    }
+
     // Load the first file
-    println!("\nLoading file 1/{}: {}", file_paths.len(), file_paths[0].display());
-    let mut combined_file = load_file(&file_paths[0])?;
+    if options.verbose {
+        info!("Loading file 1/{}: {}", files.len(), first_path.display());
+    }
+    let mut combined_file =
+        load_file_at(first_path, options, first_sample, previous_timestamp, processors)?;
+    let mut previous_timestamp = last_timestamp(&combined_file).or(previous_timestamp);
+    combined_file.skipped_files = skipped_files;
     
-    if file_paths.len() == 1 {
+    if files.len() == 1 {
         return Ok(combined_file);
     }
     
-    // Track source files
-    combined_file.source_files = Some(vec![file_paths[0].to_string_lossy().to_string()]);
+    // Track source files and the samples each contributes
+    let first_span = source_file_span(first_path, combined_file.data.as_ref(), 0);
+    let mut sample_offset = first_span.num_samples;
+    combined_file.source_files = Some(vec![first_span]);
     
-    // Load and combine remaining files
-    for (i, file_path) in file_paths[1..].iter().enumerate() {
-        println!("\nLoading file {}/{}: {}", i + 2, file_paths.len(), file_path.display());
-        let next_file = load_file(file_path)?;
+    // Load the remaining files, then combine them all at once
+    let mut parts = Vec::new();
+    for (i, &(file_path, first_sample)) in files[1..].iter().enumerate() {
+        if options.verbose {
+            info!("Loading file {}/{}: {}", i + 2, files.len(), file_path.display());
+        }
+        let next_file =
+            load_file_at(file_path, options, first_sample, previous_timestamp, processors)?;
+        let gap = file_gap(previous_timestamp, &next_file);
+        previous_timestamp = last_timestamp(&next_file).or(previous_timestamp);
 
         
         // Verify headers are compatible
-        verify_header_compatibility(&combined_file.header, &next_file.header)?;
+        let warnings = verify_header_compatibility(
+            &combined_file.header,
+            &next_file.header,
+            file_path,
+            options.header_compatibility,
+        )
+        .map_err(|e| e.in_file(file_path, None))?;
+        combined_file.warnings.extend(warnings);
         
-        // Combine the data
-        if combined_file.data_present && next_file.data_present {
-            combine_data(&mut combined_file, next_file)?;
+        // Keep the data for combining
+        let keep_data = combined_file.data_present && next_file.data_present;
+        let next_data = next_file.data.as_ref().filter(|_| keep_data);
+        if let (Some((last, missing)), Some(data)) = (gap, next_data) {
+            let padding = handle_gap(
+                file_path,
+                missing,
+                next_file.header.sample_rate,
+                options,
+                &mut combined_file.warnings,
+            )?;
+            if padding > 0 {
+                // Checked before the padding is allocated
+                sample_offset = add_samples(&combined_file.header, sample_offset, padding)
+                    .map_err(|e| e.in_file(file_path, None))?;
+                let nan = options.gap_handling == GapHandling::PadNan;
+                parts.push(padding_like(data, last + 1, padding, nan));
+            }
         }
+        let span = source_file_span(
+            file_path,
+            next_file.data.as_ref().filter(|_| keep_data),
+            sample_offset,
+        );
+        sample_offset = add_samples(&combined_file.header, sample_offset, span.num_samples)
+            .map_err(|e| e.in_file(file_path, None))?;
+        if keep_data {
+            parts.extend(next_file.data);
+        }
+        combined_file.warnings.extend(next_file.warnings);
+        combined_file.fingerprints.extend(next_file.fingerprints);
         
         // Add to source files list
         if let Some(ref mut sources) = combined_file.source_files {
-            sources.push(file_path.to_string_lossy().to_string());
+            sources.push(span);
         }
     }
+    combine_data(&mut combined_file, parts)?;
     
-    println!("\nSuccessfully combined {} files", file_paths.len());
-    println!("Total duration: {:.2} seconds", combined_file.duration());
+    if options.verbose {
+        info!("Successfully combined {} files", files.len());
+        info!("Total duration: {:.2} seconds", combined_file.duration());
+    }
     
     Ok(combined_file)
 }
-/// Verifies that two headers are compatible for combining data
-fn verify_header_compatibility(header1: &RhsHeader, header2: &RhsHeader) -> Result<(), Box<dyn std::error::Error>> {
+
+/// Describes the samples of `data`, read from the file at `path`, starting at
+/// sample `sample_offset` of the combined data
+pub(crate) fn source_file_span(path: &Path, data: Option<&RhsData>, sample_offset: usize) -> SourceFileSpan {
+    SourceFileSpan {
+        path: path.to_string_lossy().to_string(),
+        sample_offset,
+        num_samples: data.map_or(0, RhsData::num_samples),
+        first_timestamp: data.and_then(|data| data.timestamps.first().copied()),
+    }
+}
+
+/// Returns the last timestamp of a file's data, if it has any
+pub(crate) fn last_timestamp(rhs_file: &RhsFile) -> Option<i64> {
+    rhs_file.data.as_ref()?.timestamps.last().copied()
+}
+
+/// Splits `file_paths` into the files whose header and size are valid and those
+/// that are skipped, with their errors
+///
+/// Returns an error if no file is valid.
+pub(crate) fn readable_files(
+    file_paths: &[PathBuf],
+) -> Result<(Vec<PathBuf>, Vec<SkippedFile>), Box<dyn std::error::Error>> {
+    let mut readable = Vec::new();
+    let mut skipped = Vec::new();
+    for path in file_paths {
+        let error = match crate::manifest::read_manifest(path) {
+            Ok(manifest) if manifest.trailing_bytes == 0 => {
+                readable.push(path.clone());
+                continue;
+            }
+            Ok(_) => IntanError::FileSizeError.to_string(),
+            // The path is recorded separately, but the header field is kept
+            Err(IntanError::File { source, .. }) => source.to_string(),
+            Err(e) => e.to_string(),
+        };
+        warn!("Skipping {}, which cannot be read: {}", path.display(), error);
+        skipped.push(SkippedFile {
+            path: path.to_string_lossy().to_string(),
+            error,
+        });
+    }
+
+    if readable.is_empty() {
+        return Err(Box::new(IntanError::Other(format!(
+            "None of the {} files can be read; the first fails with: {}",
+            skipped.len(),
+            skipped[0].error
+        ))));
+    }
+    Ok((readable, skipped))
+}
+
+/// Returns the last timestamp before `next_file` and the number of samples missing
+/// between it and the file's first timestamp, if they are not contiguous
+pub(crate) fn file_gap(previous_timestamp: Option<i64>, next_file: &RhsFile) -> Option<(i64, i64)> {
+    let last = previous_timestamp?;
+    let first = *next_file.data.as_ref()?.timestamps.first()?;
+    (first != last + 1).then_some((last, first - last - 1))
+}
+
+/// Applies the gap handling of `options` to a gap of `missing` samples before the
+/// file at `path`, returning the number of samples of padding to insert
+pub(crate) fn handle_gap(
+    path: &Path,
+    missing: i64,
+    sample_rate: f32,
+    options: &LoadOptions,
+    warnings: &mut Vec<LoadWarning>,
+) -> Result<usize, IntanError> {
+    let gap = IntanError::Gap {
+        missing_samples: missing,
+        sample_rate,
+    };
+    if !options.gap_handling.pads() {
+        if options.gap_handling == GapHandling::Error {
+            return Err(gap.in_file(path, None));
+        }
+        let warning = LoadWarning::FileGap {
+            path: path.to_string_lossy().to_string(),
+            missing_samples: missing,
+            padded_samples: 0,
+        };
+        if options.verbose {
+            warn!("{}", warning);
+        }
+        warnings.push(warning);
+        return Ok(0);
+    }
+    // Gaps that go back cannot be padded
+    if missing < 0 {
+        return Err(gap.in_file(path, None));
+    }
+    let padding = sample_index(missing as u64)?;
+
+    if options.verbose {
+        info!(
+            "Padding {} missing samples ({:.3} s) before {}",
+            missing,
+            missing as f64 / sample_rate as f64,
+            path.display()
+        );
+    }
+    warnings.push(LoadWarning::FileGap {
+        path: path.to_string_lossy().to_string(),
+        missing_samples: missing,
+        padded_samples: padding,
+    });
+    Ok(padding)
+}
+
+/// Returns `num_samples` samples of padding in the same form as `data`, with
+/// timestamps counting up from `first_timestamp`
+///
+/// Floating-point signals are NaN when `nan` is set and zero otherwise; raw codes
+/// are those of zero volts.
+pub(crate) fn padding_like(
+    data: &RhsData,
+    first_timestamp: i64,
+    num_samples: usize,
+    nan: bool,
+) -> RhsData {
+    fn fill<T: Clone>(
+        array: &Option<Array2<T>>,
+        num_samples: usize,
+        value: T,
+    ) -> Option<Array2<T>> {
+        array
+            .as_ref()
+            .map(|array| Array2::from_elem((array.nrows(), num_samples), value))
+    }
+    let float = if nan { f64::NAN } else { 0.0 };
+    let flags = |flags: &Option<StimFlags>| {
+        flags
+            .as_ref()
+            .map(|flags| StimFlags::new(flags.num_channels(), num_samples))
+    };
+
+    RhsData {
+        timestamps: Array1::from_iter(first_timestamp..first_timestamp + num_samples as i64),
+        amplifier_data: fill(&data.amplifier_data, num_samples, float),
+        dc_amplifier_data: fill(&data.dc_amplifier_data, num_samples, float),
+        stim_data: fill(&data.stim_data, num_samples, float),
+        compliance_limit_data: flags(&data.compliance_limit_data),
+        charge_recovery_data: flags(&data.charge_recovery_data),
+        amp_settle_data: flags(&data.amp_settle_data),
+        board_adc_data: fill(&data.board_adc_data, num_samples, float),
+        board_dac_data: fill(&data.board_dac_data, num_samples, float),
+        board_dig_in_data: fill(&data.board_dig_in_data, num_samples, 0),
+        board_dig_out_data: fill(&data.board_dig_out_data, num_samples, 0),
+        amplifier_data_raw: fill(&data.amplifier_data_raw, num_samples, ADC_DAC_OFFSET as u16),
+        dc_amplifier_data_raw: fill(
+            &data.dc_amplifier_data_raw,
+            num_samples,
+            DC_AMPLIFIER_OFFSET as u16,
+        ),
+        board_adc_data_raw: fill(&data.board_adc_data_raw, num_samples, ADC_DAC_OFFSET as u16),
+        board_dac_data_raw: fill(&data.board_dac_data_raw, num_samples, ADC_DAC_OFFSET as u16),
+        amplifier_data_f32: fill(&data.amplifier_data_f32, num_samples, float as f32),
+        dc_amplifier_data_f32: fill(&data.dc_amplifier_data_f32, num_samples, float as f32),
+        board_adc_data_f32: fill(&data.board_adc_data_f32, num_samples, float as f32),
+        board_dac_data_f32: fill(&data.board_dac_data_f32, num_samples, float as f32),
+        spilled: None,
+        units: data.units,
+    }
+}
+
+/// Returns the files with samples in the time range of `options`, each with the
+/// index of its first sample in the combined recording
+fn files_in_time_range<'p>(
+    file_paths: &'p [PathBuf],
+    options: &LoadOptions,
+) -> Result<Vec<(&'p Path, u64)>, Box<dyn std::error::Error>> {
+    if options.time_range.is_none() {
+        return Ok(file_paths.iter().map(|path| (path.as_path(), 0)).collect());
+    }
+
+    let mut files = Vec::new();
+    let mut first_sample = 0;
+    let mut sample_rate = 0.0;
+    for path in file_paths {
+        let (header, num_samples) = read_header_and_length(path)?;
+        sample_rate = header.sample_rate;
+        if !selected_samples(options, sample_rate, first_sample, num_samples)?.is_empty() {
+            files.push((path.as_path(), first_sample));
+        }
+        first_sample += num_samples;
+    }
+
+    if files.is_empty() {
+        return Err(time_range_error(options, first_sample, sample_rate).into());
+    }
+    Ok(files)
+}
+
+/// Reads the header of an RHS file and returns it with the number of samples in the file
+fn read_header_and_length(file_path: &Path) -> Result<(RhsHeader, u64), Box<dyn std::error::Error>> {
+    let file = File::open(file_path)?;
+    let file_size = file.metadata()?.len();
+    let mut reader = BufReader::with_capacity(65536, file);
+    let header = read_header(&mut reader)?;
+
+    let bytes_per_block = get_bytes_per_data_block(&header)? as u64;
+    let num_blocks = (file_size - reader.stream_position()?) / bytes_per_block;
+    Ok((header, num_blocks * SAMPLES_PER_DATA_BLOCK as u64))
+}
+
+/// Verifies that two headers are compatible for combining data under `policy`,
+/// returning and logging the differences it allows in the file at `path`, whose
+/// header is `header2`
+pub(crate) fn verify_header_compatibility(
+    header1: &RhsHeader,
+    header2: &RhsHeader,
+    path: &Path,
+    policy: HeaderCompatibility,
+) -> Result<Vec<LoadWarning>, IntanError> {
     // Check sample rate
     if (header1.sample_rate - header2.sample_rate).abs() > 0.01 {
-        return Err(Box::new(IntanError::Other(format!(
+        return Err(IntanError::IncompatibleHeaders(format!(
             "Sample rates don't match: {} Hz vs {} Hz",
             header1.sample_rate, header2.sample_rate
-        ))));
+        )));
     }
     
     // Check number of channels
-    if header1.amplifier_channels.len() != header2.amplifier_channels.len() {
-        return Err(Box::new(IntanError::Other(format!(
-            "Number of amplifier channels don't match: {} vs {}",
-            header1.amplifier_channels.len(), header2.amplifier_channels.len()
-        ))));
+    let counts = [
+        ("amplifier", &header1.amplifier_channels, &header2.amplifier_channels),
+        ("board ADC", &header1.board_adc_channels, &header2.board_adc_channels),
+        ("board DAC", &header1.board_dac_channels, &header2.board_dac_channels),
+        ("digital input", &header1.board_dig_in_channels, &header2.board_dig_in_channels),
+        ("digital output", &header1.board_dig_out_channels, &header2.board_dig_out_channels),
+    ];
+    for (kind, channels1, channels2) in counts {
+        if channels1.len() != channels2.len() {
+            return Err(IntanError::IncompatibleHeaders(format!(
+                "Number of {} channels don't match: {} vs {}",
+                kind, channels1.len(), channels2.len()
+            )));
+        }
     }
-    
-    if header1.board_adc_channels.len() != header2.board_adc_channels.len() {
-        return Err(Box::new(IntanError::Other(format!(
-            "Number of board ADC channels don't match: {} vs {}",
-            header1.board_adc_channels.len(), header2.board_adc_channels.len()
-        ))));
-    }
-    
-    if header1.board_dig_in_channels.len() != header2.board_dig_in_channels.len() {
-        return Err(Box::new(IntanError::Other(format!(
-            "Number of digital input channels don't match: {} vs {}",
-            header1.board_dig_in_channels.len(), header2.board_dig_in_channels.len()
-        ))));
+    if header1.dc_amplifier_data_saved != header2.dc_amplifier_data_saved {
+        return Err(IntanError::IncompatibleHeaders(
+            "DC amplifier data is saved in only one of the files".to_string(),
+        ));
     }
     
     // Verify channel names match
+    let path = path.to_string_lossy();
+    let mut warnings = Vec::new();
     for (i, (ch1, ch2)) in header1.amplifier_channels.iter().zip(&header2.amplifier_channels).enumerate() {
         if ch1.native_channel_name != ch2.native_channel_name {
-            return Err(Box::new(IntanError::Other(format!(
-                "Amplifier channel {} names don't match: '{}' vs '{}'",
-                i, ch1.native_channel_name, ch2.native_channel_name
-            ))));
+            if policy != HeaderCompatibility::Lenient {
+                return Err(IntanError::IncompatibleHeaders(format!(
+                    "Amplifier channel {} names don't match: '{}' vs '{}'",
+                    i, ch1.native_channel_name, ch2.native_channel_name
+                )));
+            }
+            warnings.push(LoadWarning::ChannelNameMismatch {
+                path: path.to_string(),
+                channel: i,
+                kept_name: ch1.native_channel_name.clone(),
+                name: ch2.native_channel_name.clone(),
+            });
         }
     }
-    
-    Ok(())
+
+    for (setting, value1, value2) in setting_differences(header1, header2) {
+        if policy == HeaderCompatibility::Strict {
+            return Err(IntanError::IncompatibleHeaders(format!(
+                "Headers differ in their {} ({} vs {})",
+                setting, value1, value2
+            )));
+        }
+        warnings.push(LoadWarning::SettingMismatch {
+            path: path.to_string(),
+            setting: setting.to_string(),
+            kept_value: value1,
+            value: value2,
+        });
+    }
+
+    if policy == HeaderCompatibility::Strict {
+        if let Some(difference) = metadata_differences(header1, header2).first() {
+            return Err(IntanError::IncompatibleHeaders(format!(
+                "Headers differ in their {}",
+                difference
+            )));
+        }
+    }
+
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+    Ok(warnings)
 }
 
-/// Combines data from two RHS files
-fn combine_data(combined: &mut RhsFile, next: RhsFile) -> Result<(), Box<dyn std::error::Error>> {
-    use ndarray::{Axis, concatenate};
-    
-    if let (Some(combined_data), Some(next_data)) = (combined.data.as_mut(), next.data) {
- 
-        // Concatenate timestamps without adjustment, already saved with correct number between files
-        combined_data.timestamps = concatenate![Axis(0), combined_data.timestamps.view(), next_data.timestamps.view()];
-        
-        // Concatenate amplifier data
-        if let (Some(combined_amp), Some(next_amp)) = 
-            (&mut combined_data.amplifier_data, next_data.amplifier_data) {
-            *combined_amp = concatenate![Axis(1), combined_amp.view(), next_amp.view()];
+/// Lists the name and both values of the acquisition settings that differ
+/// between two headers
+fn setting_differences(
+    header1: &RhsHeader,
+    header2: &RhsHeader,
+) -> Vec<(&'static str, String, String)> {
+    let mut differences = Vec::new();
+    let mut compare = |name: &'static str, value1: &dyn fmt::Debug, value2: &dyn fmt::Debug| {
+        let (value1, value2) = (format!("{:?}", value1), format!("{:?}", value2));
+        if value1 != value2 {
+            differences.push((name, value1, value2));
         }
-        
-        // Concatenate DC amplifier data
-        if let (Some(combined_dc), Some(next_dc)) = 
-            (&mut combined_data.dc_amplifier_data, next_data.dc_amplifier_data) {
-            *combined_dc = concatenate![Axis(1), combined_dc.view(), next_dc.view()];
+    };
+    compare("DSP enable", &header1.dsp_enabled, &header2.dsp_enabled);
+    compare(
+        "DSP cutoff frequency",
+        &header1.actual_dsp_cutoff_frequency,
+        &header2.actual_dsp_cutoff_frequency,
+    );
+    compare("lower bandwidth", &header1.actual_lower_bandwidth, &header2.actual_lower_bandwidth);
+    compare(
+        "lower settle bandwidth",
+        &header1.actual_lower_settle_bandwidth,
+        &header2.actual_lower_settle_bandwidth,
+    );
+    compare("upper bandwidth", &header1.actual_upper_bandwidth, &header2.actual_upper_bandwidth);
+    compare("notch filter", &header1.notch_filter_frequency, &header2.notch_filter_frequency);
+    compare("amplifier settle mode", &header1.amp_settle_mode, &header2.amp_settle_mode);
+    compare("charge recovery mode", &header1.charge_recovery_mode, &header2.charge_recovery_mode);
+    compare("stimulation step size", &header1.stim_step_size, &header2.stim_step_size);
+    compare(
+        "charge recovery current limit",
+        &header1.recovery_current_limit,
+        &header2.recovery_current_limit,
+    );
+    compare(
+        "charge recovery target voltage",
+        &header1.recovery_target_voltage,
+        &header2.recovery_target_voltage,
+    );
+    compare("board mode", &header1.eval_board_mode, &header2.eval_board_mode);
+    compare("reference channel", &header1.reference_channel, &header2.reference_channel);
+    differences
+}
+
+/// Describes the impedance measurements, custom channel names, and notes that
+/// differ between two headers
+fn metadata_differences(header1: &RhsHeader, header2: &RhsHeader) -> Vec<String> {
+    let mut differences = Vec::new();
+    if header1.actual_impedance_test_frequency != header2.actual_impedance_test_frequency {
+        differences.push(format!(
+            "impedance test frequency ({} Hz vs {} Hz)",
+            header1.actual_impedance_test_frequency, header2.actual_impedance_test_frequency
+        ));
+    }
+    let channel_pairs = || {
+        header1
+            .amplifier_channels
+            .iter()
+            .zip(&header2.amplifier_channels)
+            .chain(header1.board_adc_channels.iter().zip(&header2.board_adc_channels))
+            .chain(header1.board_dac_channels.iter().zip(&header2.board_dac_channels))
+            .chain(header1.board_dig_in_channels.iter().zip(&header2.board_dig_in_channels))
+            .chain(header1.board_dig_out_channels.iter().zip(&header2.board_dig_out_channels))
+    };
+    if let Some((ch1, _)) = header1
+        .amplifier_channels
+        .iter()
+        .zip(&header2.amplifier_channels)
+        .find(|(ch1, ch2)| {
+            ch1.electrode_impedance_magnitude != ch2.electrode_impedance_magnitude
+                || ch1.electrode_impedance_phase != ch2.electrode_impedance_phase
+        })
+    {
+        differences.push(format!("impedance of channel {}", ch1.native_channel_name));
+    }
+    if let Some((ch1, ch2)) =
+        channel_pairs().find(|(ch1, ch2)| ch1.custom_channel_name != ch2.custom_channel_name)
+    {
+        differences.push(format!(
+            "custom name of channel {} ('{}' vs '{}')",
+            ch1.native_channel_name, ch1.custom_channel_name, ch2.custom_channel_name
+        ));
+    }
+    let notes = |header: &RhsHeader| {
+        let notes = &header.notes;
+        [notes.note1.clone(), notes.note2.clone(), notes.note3.clone()]
+    };
+    if notes(header1) != notes(header2) {
+        differences.push("notes".to_string());
+    }
+    differences
+}
+/// Appends the data of the following files of a recording to the first file
+/// Combines data from two RHS files
+pub(crate) fn combine_data(
+    combined: &mut RhsFile,
+    mut parts: Vec<RhsData>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(combined_data) = combined.data.as_mut() else {
+        return Ok(());
+    };
+    parts.iter().try_fold(combined_data.timestamps.len(), |total, part| {
+        add_samples(&combined.header, total, part.timestamps.len())
+    })?;
+
+    // Concatenate timestamps without adjustment, already saved with correct number between files
+    let mut timestamps = vec![combined_data.timestamps.view()];
+    timestamps.extend(parts.iter().map(|part| part.timestamps.view()));
+    let timestamps = concatenate(Axis(0), &timestamps)?;
+    combined_data.timestamps = timestamps;
+
+    // Concatenate each signal in one pass, so every sample is copied once
+    concat_signal(combined_data, &mut parts, |d| &mut d.amplifier_data)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.dc_amplifier_data)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.stim_data)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.board_adc_data)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.board_dac_data)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.board_dig_in_data)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.board_dig_out_data)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.amplifier_data_raw)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.dc_amplifier_data_raw)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.board_adc_data_raw)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.board_dac_data_raw)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.amplifier_data_f32)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.dc_amplifier_data_f32)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.board_adc_data_f32)?;
+    concat_signal(combined_data, &mut parts, |d| &mut d.board_dac_data_f32)?;
+
+    for part in parts {
+        // Append stimulation flags
+        for (combined_flags, next_flags) in [
+            (&mut combined_data.compliance_limit_data, part.compliance_limit_data),
+            (&mut combined_data.charge_recovery_data, part.charge_recovery_data),
+            (&mut combined_data.amp_settle_data, part.amp_settle_data),
+        ] {
+            if let (Some(combined_flags), Some(next_flags)) = (combined_flags, next_flags) {
+                combined_flags.append(next_flags.view())?;
+            }
         }
-        
-        // Concatenate stim data
-        if let (Some(combined_stim), Some(next_stim)) = 
-            (&mut combined_data.stim_data, next_data.stim_data) {
-            *combined_stim = concatenate![Axis(1), combined_stim.view(), next_stim.view()];
+
+        // Append spilled signals
+        if let (Some(combined_spilled), Some(next_spilled)) =
+            (&mut combined_data.spilled, part.spilled)
+        {
+            combined_spilled.append(next_spilled);
         }
-        
-        // Concatenate compliance limit data
-        if let (Some(combined_comp), Some(next_comp)) = 
-            (&mut combined_data.compliance_limit_data, next_data.compliance_limit_data) {
-            *combined_comp = concatenate![Axis(1), combined_comp.view(), next_comp.view()];
+    }
+
+    Ok(())
+}
+
+/// Appends one signal of every part to the combined data, freeing the parts' arrays
+///
+/// Parts without the signal are skipped, and nothing is appended if the combined
+/// data lacks it.
+fn concat_signal<T: Clone>(
+    combined: &mut RhsData,
+    parts: &mut [RhsData],
+    signal: fn(&mut RhsData) -> &mut Option<Array2<T>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let parts: Vec<Array2<T>> = parts.iter_mut().filter_map(|part| signal(part).take()).collect();
+    if let Some(combined) = signal(combined) {
+        let mut views = vec![combined.view()];
+        views.extend(parts.iter().map(|part| part.view()));
+        *combined = concatenate(Axis(1), &views)?;
+    }
+    Ok(())
+}
+/// Reads only the header of an RHS file, without loading any data
+pub fn read_header_file<P: AsRef<Path>>(file_path: P) -> Result<RhsHeader, IntanError> {
+    Ok(read_header_file_with_mode(file_path.as_ref(), ParseMode::Standard)?.0)
+}
+
+/// Reads only the header of an RHS file, treating values the format does not
+/// define as `mode` says, as [`read_header_with_mode`] does
+pub(crate) fn read_header_file_with_mode(
+    file_path: &Path,
+    mode: ParseMode,
+) -> Result<(RhsHeader, Vec<LoadWarning>), IntanError> {
+    let file = File::open(file_path).map_err(|e| IntanError::from(e).in_file(file_path, None))?;
+    let mut reader = BufReader::with_capacity(65536, file);
+    read_header_with_mode(&mut reader, mode).map_err(|e| file_error(file_path, &mut reader, e))
+}
+
+/// Loads the requested amplifier streams from a "one file per signal type" directory
+///
+/// The directory must contain the `info.rhs` header file and `time.dat`. Streams
+/// whose `.dat` file is missing are left as `None`.
+pub fn load_amplifier_streams(
+    dir_path: &Path,
+    streams: &[AmplifierStream],
+) -> Result<AmplifierStreams, IntanError> {
+    let header = read_header_file(dir_path.join("info.rhs"))?;
+    let num_channels = header.amplifier_channels.len();
+    let num_samples = dat_num_samples(dir_path)?;
+
+    let mut result = AmplifierStreams {
+        wideband_data: None,
+        lowpass_data: None,
+        lowpass_downsample_factor: 1,
+        highpass_data: None,
+    };
+
+    for &stream in streams {
+        let path = dir_path.join(stream.file_name());
+        if !path.is_file() {
+            continue;
         }
-        
-        // Concatenate charge recovery data
-        if let (Some(combined_charge), Some(next_charge)) = 
-            (&mut combined_data.charge_recovery_data, next_data.charge_recovery_data) {
-            *combined_charge = concatenate![Axis(1), combined_charge.view(), next_charge.view()];
+
+        let (data, downsample_factor) = read_amplifier_dat(&path, num_channels, num_samples)?;
+        match stream {
+            AmplifierStream::Wideband => result.wideband_data = Some(data),
+            AmplifierStream::Lowpass => {
+                result.lowpass_data = Some(data);
+                result.lowpass_downsample_factor = downsample_factor;
+            }
+            AmplifierStream::Highpass => result.highpass_data = Some(data),
         }
-        
-        // Concatenate amp settle data
-        if let (Some(combined_settle), Some(next_settle)) = 
-            (&mut combined_data.amp_settle_data, next_data.amp_settle_data) {
-            *combined_settle = concatenate![Axis(1), combined_settle.view(), next_settle.view()];
+    }
+
+    Ok(result)
+}
+
+/// Returns the number of samples in a "one file per signal type" recording, based on `time.dat`
+fn dat_num_samples(dir_path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    let time_path = dir_path.join("time.dat");
+    let file_size = std::fs::metadata(&time_path)
+        .map_err(|e| {
+            IntanError::Other(format!("Cannot read '{}': {}", time_path.display(), e))
+        })?
+        .len();
+
+    // Timestamps are stored as 4-byte integers
+    if !file_size.is_multiple_of(4) {
+        return Err(Box::new(IntanError::FileSizeError));
+    }
+
+    Ok((file_size / 4) as usize)
+}
+
+/// Reads the `num_samples` timestamps of `time.dat` in a directory recording
+fn read_time_dat(
+    dir_path: &Path,
+    num_samples: usize,
+    options: &LoadOptions,
+) -> Result<Array1<i64>, Box<dyn std::error::Error>> {
+    let (mut reader, _) = file_reader::open(&dir_path.join("time.dat"), &options.io)?;
+    let mut timestamps = vec![0i32; num_samples];
+    reader.read_i32_into::<LittleEndian>(&mut timestamps)?;
+    let mut timestamps = Array1::from_iter(timestamps.into_iter().map(i64::from));
+    unwrap_timestamps(timestamps.view_mut(), None);
+    Ok(timestamps)
+}
+
+/// Reads an amplifier stream `.dat` file (interleaved int16, 0.195 μV per bit)
+///
+/// Returns the scaled data and the downsampling factor relative to `num_samples`.
+fn read_amplifier_dat(
+    path: &Path,
+    num_channels: usize,
+    num_samples: usize,
+) -> Result<(Array2<f64>, usize), Box<dyn std::error::Error>> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len() as usize;
+    let mut reader = BufReader::with_capacity(65536, file);
+
+    let frame_size = num_channels * 2;
+    if frame_size == 0 || !file_size.is_multiple_of(frame_size) {
+        return Err(Box::new(IntanError::FileSizeError));
+    }
+
+    // Low-pass streams are saved at a reduced rate, which must divide the full sample count
+    let stream_samples = file_size / frame_size;
+    let downsample_factor = if stream_samples == 0 || stream_samples == num_samples {
+        1
+    } else if num_samples.is_multiple_of(stream_samples) {
+        num_samples / stream_samples
+    } else {
+        return Err(Box::new(IntanError::Other(format!(
+            "'{}' contains {} samples, which is not a whole fraction of {} samples",
+            path.display(),
+            stream_samples,
+            num_samples
+        ))));
+    };
+
+    let mut data = Array2::<f64>::zeros((num_channels, stream_samples));
+    let mut buffer = vec![0u8; frame_size * SAMPLES_PER_DATA_BLOCK];
+    let mut sample = 0;
+
+    while sample < stream_samples {
+        let frames = SAMPLES_PER_DATA_BLOCK.min(stream_samples - sample);
+        let bytes = &mut buffer[..frames * frame_size];
+        reader.read_exact(bytes)?;
+
+        for s in 0..frames {
+            for ch in 0..num_channels {
+                let idx = 2 * (s * num_channels + ch);
+                let value = i16::from_le_bytes([bytes[idx], bytes[idx + 1]]);
+                data[[ch, sample + s]] = value as f64 * AMPLIFIER_SCALE_FACTOR;
+            }
         }
-        
-        // Concatenate board ADC data
-        if let (Some(combined_adc), Some(next_adc)) = 
-            (&mut combined_data.board_adc_data, next_data.board_adc_data) {
-            *combined_adc = concatenate![Axis(1), combined_adc.view(), next_adc.view()];
+
+        sample += frames;
+    }
+
+    Ok((data, downsample_factor))
+}
+
+/// Loads a "one file per signal type" recording into a single `RhsFile`
+///
+/// The header comes from `info.rhs` and the sample count from `time.dat`. Each
+/// signal type is read from its own `.dat` file (`amplifier.dat`, `dcamplifier.dat`,
+/// `stim.dat`, `analogin.dat`, `analogout.dat`, `digitalin.dat`, `digitalout.dat`)
+/// and then scaled and filtered exactly like data from a traditional RHS file.
+/// Signal types whose file is missing are left as `None`.
+pub fn load_signal_type_directory(
+    dir_path: &Path,
+    options: &LoadOptions,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<RhsFile, IntanError> {
+    let tic = Instant::now();
+    let start_time = DateTime::from_rhx_filename(dir_path);
+    let options = &*resolve_wallclock_range(options, start_time)?;
+
+    let (mut header, header_warnings) =
+        read_header_file_with_mode(&dir_path.join("info.rhs"), options.parse_mode)?;
+    let num_samples = dat_num_samples(dir_path)?;
+    if options.verbose {
+        log_header_summary(&header);
+        log_record_time_summary(num_samples as u64, header.sample_rate, num_samples > 0);
+        info!("Reading data from .dat files...");
+    }
+
+    let timestamps = read_time_dat(dir_path, num_samples, options)?;
+
+    let num_amplifier_channels = header.amplifier_channels.len();
+    let dig_in_words = if header.board_dig_in_channels.is_empty() { 0 } else { 1 };
+    let dig_out_words = if header.board_dig_out_channels.is_empty() { 0 } else { 1 };
+    let read_dat = |name: &str, num_channels: usize| {
+        read_signal_dat(&dir_path.join(name), num_channels, num_samples, options)
+    };
+
+    // amplifier.dat holds signed, offset-corrected values; shift them back to raw
+    // unsigned codes so they are scaled the same way as RHS data
+    let amplifier_data_raw = read_dat("amplifier.dat", num_amplifier_channels)?
+        .map(|data| data.mapv(|x| x.wrapping_add(ADC_DAC_OFFSET as u16)));
+
+    let raw_data = RawData {
+        timestamps,
+        amplifier_data_raw,
+        dc_amplifier_data_raw: if header.dc_amplifier_data_saved {
+            read_dat("dcamplifier.dat", num_amplifier_channels)?
+        } else {
+            None
+        },
+        stim_data_raw: read_dat("stim.dat", num_amplifier_channels)?,
+        board_adc_data_raw: read_dat("analogin.dat", header.board_adc_channels.len())?,
+        board_dac_data_raw: read_dat("analogout.dat", header.board_dac_channels.len())?,
+        board_dig_in_raw: read_dat("digitalin.dat", dig_in_words)?,
+        board_dig_out_raw: read_dat("digitalout.dat", dig_out_words)?,
+    };
+
+    let first_sample = selected_samples(options, header.sample_rate, 0, num_samples as u64)?.start;
+    let recording_start = start_time_at(start_time, first_sample, header.sample_rate);
+    let data = select_dat_data(&mut header, raw_data, num_samples, options)?;
+    let data = match data {
+        Some(raw_data) => {
+            let mut data = process_data(&header, raw_data, options)?;
+            run_processors(&header, &mut data, SAMPLES_PER_DATA_BLOCK, processors)?;
+            Some(data)
         }
-        
-        // Concatenate board DAC data
-        if let (Some(combined_dac), Some(next_dac)) = 
-            (&mut combined_data.board_dac_data, next_data.board_dac_data) {
-            *combined_dac = concatenate![Axis(1), combined_dac.view(), next_dac.view()];
+        None => None,
+    };
+
+    if options.verbose {
+        info!(
+            "Done! Elapsed time: {:.1} seconds",
+            tic.elapsed().as_secs_f64()
+        );
+    }
+
+    let mut rhs_file = RhsFile {
+        header,
+        data_present: data.is_some(),
+        data,
+        source_files: None,
+        skipped_files: Vec::new(),
+        fingerprints: Vec::new(),
+        warnings: header_warnings,
+        settings: None,
+        recording_start,
+    };
+    add_file_warnings(&mut rhs_file, dir_path, options.verbose);
+    Ok(rhs_file)
+}
+
+/// Applies the channel and time selection of `options` to data read from `.dat` files
+///
+/// Returns `None` if the recording has no samples.
+fn select_dat_data(
+    header: &mut RhsHeader,
+    raw_data: RawData,
+    num_samples: usize,
+    options: &LoadOptions,
+) -> Result<Option<RawData>, Box<dyn std::error::Error>> {
+    let rows = select_channels(header, options)?;
+    if num_samples == 0 {
+        return Ok(None);
+    }
+
+    let samples = selected_samples(options, header.sample_rate, 0, num_samples as u64)?;
+    if samples.is_empty() {
+        return Err(time_range_error(options, num_samples as u64, header.sample_rate).into());
+    }
+    Ok(Some(select_raw_data(raw_data, rows.as_ref(), samples)))
+}
+
+/// Reads a `.dat` file of interleaved uint16 samples into a [channels, samples] array
+///
+/// Returns `None` if there are no channels of this type or the file does not exist.
+fn read_signal_dat(
+    path: &Path,
+    num_channels: usize,
+    num_samples: usize,
+    options: &LoadOptions,
+) -> Result<Option<Array2<u16>>, Box<dyn std::error::Error>> {
+    if num_channels == 0 || !path.is_file() {
+        return Ok(None);
+    }
+
+    let (mut reader, file_size) = file_reader::open(path, &options.io)?;
+    let expected_size = (num_channels * num_samples * 2) as u64;
+    if file_size != expected_size {
+        return Err(Box::new(IntanError::Other(format!(
+            "'{}' should contain {} bytes for {} channels and {} samples",
+            path.display(),
+            expected_size,
+            num_channels,
+            num_samples
+        ))));
+    }
+
+    let mut data = Array2::<u16>::zeros((num_channels, num_samples));
+    let mut buffer = vec![0u16; num_channels * SAMPLES_PER_DATA_BLOCK];
+    let mut sample = 0;
+
+    while sample < num_samples {
+        let frames = SAMPLES_PER_DATA_BLOCK.min(num_samples - sample);
+        let values = &mut buffer[..frames * num_channels];
+        reader.read_u16_into::<LittleEndian>(values)?;
+        deinterleave(values, num_channels, None, data.slice_mut(s![.., sample..sample + frames]));
+
+        sample += frames;
+    }
+
+    Ok(Some(data))
+}
+
+/// Loads a "one file per channel" recording into a single `RhsFile`
+///
+/// Each channel listed in `info.rhs` is read from its own file, named after the
+/// channel's native name: `amp-A-000.dat`, `dc-A-000.dat`, and `stim-A-000.dat` for
+/// amplifier channels and `board-ANALOG-IN-1.dat`, `board-DIGITAL-IN-01.dat`, ... for
+/// board channels. The assembled data is scaled and filtered exactly like data from
+/// a traditional RHS file. Signal types whose files are all missing are left as `None`.
+pub fn load_channel_directory(
+    dir_path: &Path,
+    options: &LoadOptions,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<RhsFile, IntanError> {
+    let tic = Instant::now();
+    let start_time = DateTime::from_rhx_filename(dir_path);
+    let options = &*resolve_wallclock_range(options, start_time)?;
+
+    let (mut header, header_warnings) =
+        read_header_file_with_mode(&dir_path.join("info.rhs"), options.parse_mode)?;
+    let num_samples = dat_num_samples(dir_path)?;
+    if options.verbose {
+        log_header_summary(&header);
+        log_record_time_summary(num_samples as u64, header.sample_rate, num_samples > 0);
+        info!("Reading data from per-channel .dat files...");
+    }
+
+    let timestamps = read_time_dat(dir_path, num_samples, options)?;
+
+    let amplifier = &header.amplifier_channels;
+    let read_dats = |prefix: &str, channels: &[ChannelInfo]| {
+        read_channel_dats(dir_path, prefix, channels, num_samples, options)
+    };
+
+    // amp-*.dat files hold signed, offset-corrected values; shift them back to raw
+    // unsigned codes so they are scaled the same way as RHS data
+    let amplifier_data_raw = read_dats("amp", amplifier)?
+        .map(|data| data.mapv(|x| x.wrapping_add(ADC_DAC_OFFSET as u16)));
+
+    let raw_data = RawData {
+        timestamps,
+        amplifier_data_raw,
+        dc_amplifier_data_raw: if header.dc_amplifier_data_saved {
+            read_dats("dc", amplifier)?
+        } else {
+            None
+        },
+        stim_data_raw: read_dats("stim", amplifier)?,
+        board_adc_data_raw: read_dats("board", &header.board_adc_channels)?,
+        board_dac_data_raw: read_dats("board", &header.board_dac_channels)?,
+        board_dig_in_raw: read_dats("board", &header.board_dig_in_channels)?
+            .map(|bits| pack_digital_words(&bits, &header.board_dig_in_channels)),
+        board_dig_out_raw: read_dats("board", &header.board_dig_out_channels)?
+            .map(|bits| pack_digital_words(&bits, &header.board_dig_out_channels)),
+    };
+
+    let first_sample = selected_samples(options, header.sample_rate, 0, num_samples as u64)?.start;
+    let recording_start = start_time_at(start_time, first_sample, header.sample_rate);
+    let data = select_dat_data(&mut header, raw_data, num_samples, options)?;
+    let data = match data {
+        Some(raw_data) => {
+            let mut data = process_data(&header, raw_data, options)?;
+            run_processors(&header, &mut data, SAMPLES_PER_DATA_BLOCK, processors)?;
+            Some(data)
         }
-        
-        // Concatenate digital input data
-        if let (Some(combined_din), Some(next_din)) = 
-            (&mut combined_data.board_dig_in_data, next_data.board_dig_in_data) {
-            *combined_din = concatenate![Axis(1), combined_din.view(), next_din.view()];
+        None => None,
+    };
+
+    if options.verbose {
+        info!(
+            "Done! Elapsed time: {:.1} seconds",
+            tic.elapsed().as_secs_f64()
+        );
+    }
+
+    let mut rhs_file = RhsFile {
+        header,
+        data_present: data.is_some(),
+        data,
+        source_files: None,
+        skipped_files: Vec::new(),
+        fingerprints: Vec::new(),
+        warnings: header_warnings,
+        settings: None,
+        recording_start,
+    };
+    add_file_warnings(&mut rhs_file, dir_path, options.verbose);
+    Ok(rhs_file)
+}
+
+/// Reads one `{prefix}-{native name}.dat` file per channel into a [channels, samples] array
+///
+/// Returns `None` if there are no channels or none of their files exist, and an
+/// error if only some of them exist.
+fn read_channel_dats(
+    dir_path: &Path,
+    prefix: &str,
+    channels: &[ChannelInfo],
+    num_samples: usize,
+    options: &LoadOptions,
+) -> Result<Option<Array2<u16>>, Box<dyn std::error::Error>> {
+    let paths: Vec<_> = channels
+        .iter()
+        .map(|channel| channel_dat_path(dir_path, prefix, &channel.native_channel_name))
+        .collect();
+
+    let num_found = paths.iter().filter(|path| path.is_some()).count();
+    if num_found == 0 {
+        return Ok(None);
+    }
+    if num_found < paths.len() {
+        let missing = channels
+            .iter()
+            .zip(&paths)
+            .find(|(_, path)| path.is_none())
+            .map(|(channel, _)| channel.native_channel_name.as_str())
+            .unwrap_or_default();
+        return Err(Box::new(IntanError::Other(format!(
+            "Missing '{}-{}.dat' in '{}'",
+            prefix,
+            missing,
+            dir_path.display()
+        ))));
+    }
+
+    let mut data = Array2::<u16>::zeros((channels.len(), num_samples));
+    for (i, path) in paths.iter().flatten().enumerate() {
+        if let Some(channel_data) = read_signal_dat(path, 1, num_samples, options)? {
+            data.row_mut(i).assign(&channel_data.row(0));
         }
-        
-        // Concatenate digital output data
-        if let (Some(combined_dout), Some(next_dout)) = 
-            (&mut combined_data.board_dig_out_data, next_data.board_dig_out_data) {
-            *combined_dout = concatenate![Axis(1), combined_dout.view(), next_dout.view()];
+    }
+
+    Ok(Some(data))
+}
+
+/// Finds the per-channel file for a channel, accepting the short `DIN`/`DOUT` names
+/// some RHX versions use for digital channels
+fn channel_dat_path(dir_path: &Path, prefix: &str, native_name: &str) -> Option<std::path::PathBuf> {
+    let short_name = native_name
+        .replace("DIGITAL-IN", "DIN")
+        .replace("DIGITAL-OUT", "DOUT");
+
+    [native_name, short_name.as_str()]
+        .iter()
+        .map(|name| dir_path.join(format!("{}-{}.dat", prefix, name)))
+        .find(|path| path.is_file())
+}
+
+/// Packs per-channel 0/1 digital samples into the 16-bit words used by RHS data blocks
+fn pack_digital_words(bits: &Array2<u16>, channels: &[ChannelInfo]) -> Array2<u16> {
+    let mut words = Array2::<u16>::zeros((1, bits.ncols()));
+    for (row, channel) in bits.rows().into_iter().zip(channels) {
+        for (word, &bit) in words.row_mut(0).iter_mut().zip(row.iter()) {
+            if bit != 0 {
+                *word |= 1 << channel.native_order;
+            }
         }
     }
-    
-    Ok(())
-}
\ No newline at end of file
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: i64 = i32::MAX as i64;
+    const MIN: i64 = i32::MIN as i64;
+    const WRAP: i64 = 1 << 32;
+
+    /// Unwraps stored timestamps, continuing from `previous`
+    fn unwrapped(stored: &[i64], previous: Option<i64>) -> Vec<i64> {
+        let mut timestamps = Array1::from(stored.to_vec());
+        unwrap_timestamps(timestamps.view_mut(), previous);
+        timestamps.to_vec()
+    }
+
+    #[test]
+    fn unwraps_a_wrap_within_a_block() {
+        let stored = [MAX - 2, MAX - 1, MAX, MIN, MIN + 1];
+        let expected: Vec<i64> = (MAX - 2..=MAX + 2).collect();
+        assert_eq!(unwrapped(&stored, None), expected);
+    }
+
+    #[test]
+    fn unwraps_a_wrap_across_a_file_boundary() {
+        // The file before ended on the largest stored timestamp
+        assert_eq!(unwrapped(&[MIN, MIN + 1], Some(MAX)), [MAX + 1, MAX + 2]);
+        // A file following one that already wrapped keeps its offset, and adds
+        // another when it wraps again
+        assert_eq!(
+            unwrapped(&[MIN + 5, MIN + 6], Some(MIN + 4 + WRAP)),
+            [MIN + 5 + WRAP, MIN + 6 + WRAP]
+        );
+        assert_eq!(
+            unwrapped(&[MIN, MIN + 1], Some(MAX + WRAP)),
+            [MAX + 1 + WRAP, MAX + 2 + WRAP]
+        );
+    }
+
+    #[test]
+    fn continues_without_a_wrap_across_a_file_boundary() {
+        assert_eq!(unwrapped(&[100, 101], Some(99)), [100, 101]);
+        assert_eq!(unwrapped(&[5, 6], Some(4 + WRAP)), [5 + WRAP, 6 + WRAP]);
+    }
+
+    #[test]
+    fn keeps_backward_steps_that_are_not_wraps() {
+        assert_eq!(unwrapped(&[100, 101, 50, 51], None), [100, 101, 50, 51]);
+        assert_eq!(unwrapped(&[5, -5, -4], None), [5, -5, -4]);
+        // A drop of exactly 2^31 is not more than 2^31
+        assert_eq!(unwrapped(&[MAX, -1], None), [MAX, -1]);
+        // A file whose timestamps go back from the end of the file before
+        assert_eq!(unwrapped(&[10, 11], Some(1000)), [10, 11]);
+    }
+
+    #[test]
+    fn keeps_negative_starts() {
+        // RHX starts counting below zero when triggered recordings keep pre-trigger samples
+        assert_eq!(unwrapped(&[-3, -2, -1, 0, 1], None), [-3, -2, -1, 0, 1]);
+        assert_eq!(unwrapped(&[MIN, MIN + 1], None), [MIN, MIN + 1]);
+        assert_eq!(unwrapped(&[-2, -1], Some(-3)), [-2, -1]);
+    }
+
+    #[test]
+    fn unwraps_each_wrap_once() {
+        let stored = [MAX, MIN, 0, MAX, MIN];
+        assert_eq!(
+            unwrapped(&stored, None),
+            [MAX, MAX + 1, WRAP, MAX + WRAP, MAX + 1 + WRAP]
+        );
+        // Unwrapping unwrapped timestamps again leaves them unchanged
+        let once = unwrapped(&stored, None);
+        assert_eq!(unwrapped(&once, None), once);
+    }
+
+    #[test]
+    fn leaves_empty_timestamps_alone() {
+        assert!(unwrapped(&[], None).is_empty());
+        assert!(unwrapped(&[], Some(MAX)).is_empty());
+    }
+}
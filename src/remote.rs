@@ -0,0 +1,402 @@
+//! Loading recordings directly from HTTP(S) servers and S3.
+//!
+//! Requires the `remote` feature. With it enabled, [`load`](crate::load) accepts
+//! `https://`, `http://`, and `s3://` URIs in place of local paths. The file is
+//! read with HTTP range requests: the header is fetched with a small initial
+//! request, and data blocks are then streamed in progressively larger chunks, so
+//! multi-gigabyte recordings never have to be downloaded to disk first.
+//!
+//! `s3://bucket/key` URIs are resolved to the bucket's virtual-hosted endpoint in
+//! the region given by `AWS_REGION` (or `AWS_DEFAULT_REGION`, defaulting to
+//! `us-east-1`). If `AWS_ENDPOINT_URL` is set, path-style requests are sent to that
+//! endpoint instead, which supports S3-compatible stores such as MinIO. Requests
+//! are signed with AWS Signature Version 4 when `AWS_ACCESS_KEY_ID` and
+//! `AWS_SECRET_ACCESS_KEY` (and optionally `AWS_SESSION_TOKEN`) are set, and sent
+//! anonymously otherwise. Presigned S3 URLs can be passed as ordinary `https://` URIs.
+//!
+//! Only traditional single RHS files can be loaded remotely.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("s3://lab-recordings/rat07/session_240315.rhs").unwrap();
+//! println!("Streamed {} seconds of data", rhs_file.duration());
+//! ```
+
+use std::error::Error;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Instant;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use log::info;
+
+use crate::datetime::{DateTime, UtcDateTime};
+use crate::format::{self, RecordingFormat};
+use crate::loader::LoadOptions;
+use crate::processor::BlockProcessor;
+use crate::reader::{self, RHS_MAGIC_NUMBER};
+use crate::rhd::RHD_MAGIC_NUMBER;
+use crate::types::{IntanError, RhsFile};
+
+/// Size of the first request, which normally covers the whole header
+const INITIAL_CHUNK_SIZE: u64 = 64 * 1024;
+/// Upper limit for chunks fetched while streaming data blocks
+const MAX_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+/// SHA-256 of an empty payload, sent with every (bodyless) GET request
+const EMPTY_PAYLOAD_SHA256: &str =
+    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Loads an RHS file from an `https://`, `http://`, or `s3://` URI.
+///
+/// See the [module documentation](self) for how URIs are resolved and authenticated.
+pub fn load_remote(
+    uri: &str,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<RhsFile, IntanError> {
+    load_remote_with_options(uri, &LoadOptions::default(), processors)
+}
+
+/// Loads an RHS file from an `https://`, `http://`, or `s3://` URI with the given options.
+pub(crate) fn load_remote_with_options(
+    uri: &str,
+    options: &LoadOptions,
+    processors: &mut [&mut dyn BlockProcessor],
+) -> Result<RhsFile, IntanError> {
+    let tic = Instant::now();
+
+    let mut reader = RemoteReader::open(uri)?;
+    let file_size = reader.size;
+
+    let magic_number = reader
+        .read_u32::<LittleEndian>()
+        .map_err(|_| IntanError::UnrecognizedFileFormat)?;
+    match magic_number {
+        RHS_MAGIC_NUMBER => {}
+        RHD_MAGIC_NUMBER => {
+            return Err(format::unsupported(
+                RecordingFormat::RhdFile,
+                std::path::Path::new(uri),
+                "RHD files can only be loaded from local paths",
+            ))
+        }
+        _ => return Err(IntanError::UnrecognizedFileFormat),
+    }
+    reader.seek(SeekFrom::Start(0))?;
+
+    // The start time is in the file name, before any query string
+    let name = uri.split(['?', '#']).next().unwrap_or(uri);
+    let start_time = DateTime::from_rhx_filename(std::path::Path::new(name));
+    let mut rhs_file =
+        reader::load_from_reader(&mut reader, file_size, options, start_time, processors)
+            .map_err(|e| reader::file_error(std::path::Path::new(uri), &mut reader, e))?;
+    reader::add_file_warnings(&mut rhs_file, std::path::Path::new(uri), options.verbose);
+
+    if options.verbose {
+        info!(
+            "Done! Elapsed time: {:.1} seconds ({} requests)",
+            tic.elapsed().as_secs_f64(),
+            reader.num_requests
+        );
+    }
+
+    Ok(rhs_file)
+}
+
+/// A seekable reader over a remote file, backed by HTTP range requests
+struct RemoteReader {
+    request: RemoteRequest,
+    size: u64,
+    pos: u64,
+    buffer: Vec<u8>,
+    buffer_start: u64,
+    chunk_size: u64,
+    num_requests: usize,
+}
+
+impl RemoteReader {
+    /// Fetches the first chunk, which also reveals the file size
+    fn open(uri: &str) -> Result<RemoteReader, Box<dyn Error>> {
+        let mut reader = RemoteReader {
+            request: RemoteRequest::from_uri(uri)?,
+            size: 0,
+            pos: 0,
+            buffer: Vec::new(),
+            buffer_start: 0,
+            chunk_size: INITIAL_CHUNK_SIZE,
+            num_requests: 0,
+        };
+        reader.size = reader.fetch(0)?;
+        Ok(reader)
+    }
+
+    /// Replaces the buffer with the chunk starting at `start` and returns the file size
+    fn fetch(&mut self, start: u64) -> Result<u64, Box<dyn Error>> {
+        let end = start + self.chunk_size - 1;
+        let response = self.request.get(&format!("bytes={}-{}", start, end))?;
+        self.num_requests += 1;
+
+        let size = match response.status() {
+            206 => response
+                .header("Content-Range")
+                .and_then(|range| range.rsplit('/').next())
+                .and_then(|total| total.parse().ok())
+                .ok_or_else(|| {
+                    IntanError::Other("Missing total size in Content-Range header".to_string())
+                })?,
+            // The server ignored the range and sent the whole file
+            200 => {
+                let mut buffer = Vec::new();
+                response.into_reader().read_to_end(&mut buffer)?;
+                self.buffer = buffer;
+                self.buffer_start = 0;
+                self.chunk_size = u64::MAX;
+                return Ok(self.buffer.len() as u64);
+            }
+            status => {
+                return Err(Box::new(IntanError::Other(format!(
+                    "Unexpected HTTP status {} for {}",
+                    status, self.request.url
+                ))))
+            }
+        };
+
+        let mut buffer = Vec::with_capacity(self.chunk_size.min(size) as usize);
+        response
+            .into_reader()
+            .take(self.chunk_size)
+            .read_to_end(&mut buffer)?;
+        self.buffer = buffer;
+        self.buffer_start = start;
+        Ok(size)
+    }
+
+    fn buffer_end(&self) -> u64 {
+        self.buffer_start + self.buffer.len() as u64
+    }
+}
+
+impl Read for RemoteReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size || out.is_empty() {
+            return Ok(0);
+        }
+
+        if self.pos < self.buffer_start || self.pos >= self.buffer_end() {
+            // Sequential reads are data blocks being streamed, so fetch more at once
+            if self.pos == self.buffer_end() {
+                self.chunk_size = self.chunk_size.saturating_mul(2).min(MAX_CHUNK_SIZE);
+            }
+            self.fetch(self.pos)
+                .map_err(|e| io::Error::other(e.to_string()))?;
+        }
+
+        let offset = (self.pos - self.buffer_start) as usize;
+        let n = out.len().min(self.buffer.len() - offset);
+        out[..n].copy_from_slice(&self.buffer[offset..offset + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for RemoteReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = new_pos.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// A GET request for a remote file, with optional AWS credentials for signing
+struct RemoteRequest {
+    url: String,
+    s3: Option<S3Target>,
+}
+
+/// Where and how to send S3 requests
+struct S3Target {
+    host: String,
+    canonical_uri: String,
+    region: String,
+    credentials: Option<AwsCredentials>,
+}
+
+struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl RemoteRequest {
+    fn from_uri(uri: &str) -> Result<RemoteRequest, Box<dyn Error>> {
+        let Some(location) = uri.strip_prefix("s3://") else {
+            return Ok(RemoteRequest {
+                url: uri.to_string(),
+                s3: None,
+            });
+        };
+
+        let (bucket, key) = location
+            .split_once('/')
+            .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+            .ok_or_else(|| {
+                IntanError::Other(format!("Expected s3://bucket/key, got '{}'", uri))
+            })?;
+        let key = uri_encode_path(key);
+
+        let region = std::env::var("AWS_REGION")
+            .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| "us-east-1".to_string());
+
+        let (url, host, canonical_uri) = match std::env::var("AWS_ENDPOINT_URL") {
+            Ok(endpoint) => {
+                let endpoint = endpoint.trim_end_matches('/');
+                let host = endpoint
+                    .split_once("://")
+                    .map_or(endpoint, |(_, rest)| rest)
+                    .split('/')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                let canonical_uri = format!("/{}/{}", bucket, key);
+                (format!("{}{}", endpoint, canonical_uri), host, canonical_uri)
+            }
+            Err(_) => {
+                let host = format!("{}.s3.{}.amazonaws.com", bucket, region);
+                let canonical_uri = format!("/{}", key);
+                (format!("https://{}{}", host, canonical_uri), host, canonical_uri)
+            }
+        };
+
+        let credentials = match (
+            std::env::var("AWS_ACCESS_KEY_ID"),
+            std::env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            (Ok(access_key_id), Ok(secret_access_key)) => Some(AwsCredentials {
+                access_key_id,
+                secret_access_key,
+                session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+            }),
+            _ => None,
+        };
+
+        Ok(RemoteRequest {
+            url,
+            s3: Some(S3Target {
+                host,
+                canonical_uri,
+                region,
+                credentials,
+            }),
+        })
+    }
+
+    /// Sends a GET request for a byte range
+    fn get(&self, range: &str) -> Result<ureq::Response, Box<dyn Error>> {
+        let mut request = ureq::get(&self.url).set("Range", range);
+
+        if let Some(S3Target {
+            host,
+            canonical_uri,
+            region,
+            credentials: Some(credentials),
+        }) = &self.s3
+        {
+            let amz_date = UtcDateTime::now().to_iso8601_basic();
+            let mut headers = vec![
+                ("host", host.clone()),
+                ("range", range.to_string()),
+                ("x-amz-content-sha256", EMPTY_PAYLOAD_SHA256.to_string()),
+                ("x-amz-date", amz_date.clone()),
+            ];
+            if let Some(token) = &credentials.session_token {
+                headers.push(("x-amz-security-token", token.clone()));
+            }
+
+            let authorization = sign_v4(credentials, region, canonical_uri, &headers, &amz_date);
+            for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+                request = request.set(name, value);
+            }
+            request = request.set("Authorization", &authorization);
+        }
+
+        request.call().map_err(|e| -> Box<dyn Error> {
+            Box::new(IntanError::Other(format!(
+                "Request for {} failed: {}",
+                self.url, e
+            )))
+        })
+    }
+}
+
+/// Computes the `Authorization` header for an AWS Signature Version 4 GET request
+///
+/// `headers` must be sorted by lowercase name and include every header to sign.
+fn sign_v4(
+    credentials: &AwsCredentials,
+    region: &str,
+    canonical_uri: &str,
+    headers: &[(&str, String)],
+    amz_date: &str,
+) -> String {
+    use hmac_sha256::{Hash, HMAC};
+
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "GET\n{}\n\n{}\n{}\n{}",
+        canonical_uri, canonical_headers, signed_headers, EMPTY_PAYLOAD_SHA256
+    );
+
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(&Hash::hash(canonical_request.as_bytes()))
+    );
+
+    let mut key = HMAC::mac(date, format!("AWS4{}", credentials.secret_access_key));
+    for part in [region, "s3", "aws4_request"] {
+        key = HMAC::mac(part, key);
+    }
+    let signature = hex(&HMAC::mac(string_to_sign, key));
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id, scope, signed_headers, signature
+    )
+}
+
+/// Percent-encodes an object key, keeping `/` separators
+fn uri_encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~' | b'/') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,581 @@
+//! Reader for RHD2000 (.rhd) files.
+//!
+//! RHD files are written by Intan's RHD2000-series recording systems, which
+//! record from amplifier channels without stimulation. The layout resembles RHS
+//! files, but data blocks hold 60 samples (128 from format version 2.0 on), and
+//! the headstage additionally samples auxiliary inputs at a quarter of the
+//! amplifier rate, supply voltages once per block, and optional temperature
+//! sensors.
+//!
+//! Use [`load_any`](crate::load_any) to open RHS and RHD files through one entry
+//! point, or [`rhd::load`](load) when the file is known to be an RHD file.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::rhd;
+//!
+//! let rhd_file = rhd::load("recording.rhd").unwrap();
+//! println!("{} amplifier channels", rhd_file.header.amplifier_channels.len());
+//!
+//! if let Some(aux) = rhd_file.data.as_ref().and_then(|d| d.aux_input_data.as_ref()) {
+//!     println!("Aux inputs: {:?} samples (quarter rate)", aux.shape());
+//! }
+//! ```
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use log::info;
+use ndarray::{s, Array1, Array2};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::dsp::NotchFilter;
+use crate::reader::{
+    file_error, notch_filter_rows, read_qstring, sample_index, unwrap_timestamps,
+    ADC_DAC_OFFSET, AMPLIFIER_SCALE_FACTOR,
+};
+use crate::types::{ChannelInfo, IntanError, Notes, SpikeTrigger, Version};
+
+/// Magic number identifying RHD files
+pub(crate) const RHD_MAGIC_NUMBER: u32 = 0xc6912702;
+
+// Scaling constants (from Intan RHD data format specification)
+const AUX_INPUT_SCALE_FACTOR: f64 = 37.4e-6; // V per bit
+const SUPPLY_VOLTAGE_SCALE_FACTOR: f64 = 74.8e-6; // V per bit
+const TEMP_SENSOR_SCALE_FACTOR: f64 = 0.01; // °C per bit
+const BOARD_ADC_SCALE_FACTOR: f64 = 50.354e-6; // V per bit (USB interface board)
+const BOARD_ADC_SCALE_FACTOR_MODE_1: f64 = 152.59e-6; // V per bit (±5 V, offset)
+const BOARD_ADC_SCALE_FACTOR_MODE_13: f64 = 312.5e-6; // V per bit (±10 V, offset)
+
+/// Header information from an RHD file.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RhdHeader {
+    /// File format version
+    pub version: Version,
+    /// Amplifier sample rate (Hz)
+    pub sample_rate: f32,
+    /// Number of samples per data block (60, or 128 from version 2.0 on)
+    pub num_samples_per_data_block: i32,
+
+    /// Whether DSP was enabled (1) or disabled (0)
+    pub dsp_enabled: i32,
+    /// Actual DSP cutoff frequency achieved (Hz)
+    pub actual_dsp_cutoff_frequency: f32,
+    /// Actual lower bandwidth achieved (Hz)
+    pub actual_lower_bandwidth: f32,
+    /// Actual upper bandwidth achieved (Hz)
+    pub actual_upper_bandwidth: f32,
+    /// User-requested DSP cutoff frequency (Hz)
+    pub desired_dsp_cutoff_frequency: f32,
+    /// User-requested lower bandwidth (Hz)
+    pub desired_lower_bandwidth: f32,
+    /// User-requested upper bandwidth (Hz)
+    pub desired_upper_bandwidth: f32,
+
+    /// Notch filter frequency (50Hz, 60Hz, or None)
+    pub notch_filter_frequency: Option<i32>,
+    /// User-requested impedance test frequency (Hz)
+    pub desired_impedance_test_frequency: f32,
+    /// Actual impedance test frequency achieved (Hz)
+    pub actual_impedance_test_frequency: f32,
+
+    /// User notes saved with the recording
+    pub notes: Notes,
+    /// Number of temperature sensor channels
+    pub num_temp_sensor_channels: i32,
+    /// Evaluation board mode, which determines the board ADC scaling
+    /// - 0: USB interface board (0-3.3 V)
+    /// - 1: ±5 V range
+    /// - 13: ±10 V range (Recording Controller)
+    pub eval_board_mode: i32,
+    /// Name of the reference channel used
+    pub reference_channel: String,
+
+    /// List of amplifier channels in the recording
+    pub amplifier_channels: Vec<ChannelInfo>,
+    /// List of spike trigger configurations (one per amplifier channel)
+    pub spike_triggers: Vec<SpikeTrigger>,
+    /// List of auxiliary input channels (sampled at 1/4 of the amplifier rate)
+    pub aux_input_channels: Vec<ChannelInfo>,
+    /// List of supply voltage channels (sampled once per data block)
+    pub supply_voltage_channels: Vec<ChannelInfo>,
+    /// List of board ADC channels
+    pub board_adc_channels: Vec<ChannelInfo>,
+    /// List of board digital input channels
+    pub board_dig_in_channels: Vec<ChannelInfo>,
+    /// List of board digital output channels
+    pub board_dig_out_channels: Vec<ChannelInfo>,
+}
+
+impl RhdHeader {
+    /// Sample rate of the auxiliary inputs (Hz)
+    pub fn aux_input_sample_rate(&self) -> f32 {
+        self.sample_rate / 4.0
+    }
+
+    /// Sample rate of the supply voltage and temperature channels (Hz)
+    pub fn supply_voltage_sample_rate(&self) -> f32 {
+        self.sample_rate / self.num_samples_per_data_block as f32
+    }
+}
+
+/// Data contained in an RHD file.
+#[derive(Debug, Clone)]
+pub struct RhdData {
+    /// Timestamps for each amplifier sample (in sample numbers)
+    pub timestamps: Array1<i64>,
+    /// Neural data from amplifier channels (μV)
+    /// - Shape: [num_channels, num_samples]
+    pub amplifier_data: Option<Array2<f64>>,
+    /// Auxiliary input data (V)
+    /// - Shape: [num_channels, num_samples / 4]
+    pub aux_input_data: Option<Array2<f64>>,
+    /// Supply voltage data (V)
+    /// - Shape: [num_channels, num_data_blocks]
+    pub supply_voltage_data: Option<Array2<f64>>,
+    /// Temperature sensor data (°C)
+    /// - Shape: [num_channels, num_data_blocks]
+    pub temp_sensor_data: Option<Array2<f64>>,
+    /// Board ADC data (V)
+    /// - Shape: [num_channels, num_samples]
+    pub board_adc_data: Option<Array2<f64>>,
+    /// Board digital input data (0 or 1)
+    /// - Shape: [num_channels, num_samples]
+    pub board_dig_in_data: Option<Array2<i32>>,
+    /// Board digital output data (0 or 1)
+    /// - Shape: [num_channels, num_samples]
+    pub board_dig_out_data: Option<Array2<i32>>,
+}
+
+/// Complete representation of an RHD file, including header and data.
+#[derive(Debug, Clone)]
+pub struct RhdFile {
+    /// Header information containing metadata and configuration
+    pub header: RhdHeader,
+    /// Recorded data (if present in the file)
+    pub data: Option<RhdData>,
+    /// Flag indicating whether data is present in the file
+    pub data_present: bool,
+}
+
+impl RhdFile {
+    /// Returns the duration of the recording in seconds.
+    ///
+    /// If no data is present, returns 0.0.
+    pub fn duration(&self) -> f32 {
+        self.num_samples() as f32 / self.header.sample_rate
+    }
+
+    /// Returns the number of amplifier samples in the recording.
+    ///
+    /// If no data is present, returns 0.
+    pub fn num_samples(&self) -> usize {
+        self.data.as_ref().map_or(0, |data| data.timestamps.len())
+    }
+}
+
+/// Loads an RHD file.
+///
+/// Amplifier data is scaled to μV and all other analog signals to V (temperature
+/// to °C). As with RHS files, a notch filter enabled during recording is applied
+/// to the amplifier data for files saved by software older than version 3.0.
+///
+/// # Errors
+///
+/// Returns an [`IntanError::File`] naming the file if it cannot be read or parsed.
+pub fn load<P: AsRef<Path>>(file_path: P) -> Result<RhdFile, IntanError> {
+    let file_path = file_path.as_ref();
+    let file = File::open(file_path).map_err(|e| IntanError::from(e).in_file(file_path, None))?;
+    let mut reader = BufReader::with_capacity(65536, file);
+    read_file(&mut reader).map_err(|e| file_error(file_path, &mut reader, e))
+}
+
+/// Reads a complete RHD file
+fn read_file(reader: &mut BufReader<File>) -> Result<RhdFile, Box<dyn Error>> {
+    let tic = Instant::now();
+
+    let file_size = reader.get_ref().metadata()?.len();
+    let header = read_header(reader)?;
+
+    // Calculate how much data is present
+    let bytes_per_block = bytes_per_data_block(&header);
+    let bytes_remaining = file_size - reader.stream_position()?;
+    if !bytes_remaining.is_multiple_of(bytes_per_block as u64) {
+        return Err(Box::new(IntanError::FileSizeError));
+    }
+    let num_blocks = bytes_remaining / bytes_per_block as u64;
+    // The sample count is checked, so the block count, which is smaller, fits too
+    let num_samples = sample_index(num_blocks * header.num_samples_per_data_block as u64)?;
+    let num_blocks = num_blocks as usize;
+    let data_present = num_blocks > 0;
+
+    info!(
+        "File contains {:.3} seconds of data. Amplifiers were sampled at {:.2} kS/s.",
+        num_samples as f32 / header.sample_rate,
+        header.sample_rate / 1000.0
+    );
+
+    let data = if data_present {
+        Some(read_data(&header, num_blocks, bytes_per_block, reader)?)
+    } else {
+        None
+    };
+
+    info!(
+        "Done! Elapsed time: {:.1} seconds",
+        tic.elapsed().as_secs_f64()
+    );
+
+    Ok(RhdFile {
+        header,
+        data,
+        data_present,
+    })
+}
+
+/// Reads the header from an RHD file
+fn read_header<R: Read + Seek>(reader: &mut R) -> Result<RhdHeader, Box<dyn Error>> {
+    if reader.read_u32::<LittleEndian>()? != RHD_MAGIC_NUMBER {
+        return Err(Box::new(IntanError::UnrecognizedFileFormat));
+    }
+
+    let version = Version {
+        major: reader.read_i16::<LittleEndian>()? as i32,
+        minor: reader.read_i16::<LittleEndian>()? as i32,
+    };
+    info!(
+        "Reading Intan Technologies RHD Data File, Version {}.{}",
+        version.major, version.minor
+    );
+    let (major, minor) = (version.major, version.minor);
+    let at_least = |min_major: i32, min_minor: i32| (major, minor) >= (min_major, min_minor);
+
+    let sample_rate = reader.read_f32::<LittleEndian>()?;
+    let dsp_enabled = reader.read_i16::<LittleEndian>()? as i32;
+    let actual_dsp_cutoff_frequency = reader.read_f32::<LittleEndian>()?;
+    let actual_lower_bandwidth = reader.read_f32::<LittleEndian>()?;
+    let actual_upper_bandwidth = reader.read_f32::<LittleEndian>()?;
+    let desired_dsp_cutoff_frequency = reader.read_f32::<LittleEndian>()?;
+    let desired_lower_bandwidth = reader.read_f32::<LittleEndian>()?;
+    let desired_upper_bandwidth = reader.read_f32::<LittleEndian>()?;
+
+    let notch_filter_frequency = match reader.read_i16::<LittleEndian>()? {
+        1 => Some(50),
+        2 => Some(60),
+        _ => None,
+    };
+
+    let desired_impedance_test_frequency = reader.read_f32::<LittleEndian>()?;
+    let actual_impedance_test_frequency = reader.read_f32::<LittleEndian>()?;
+
+    let notes = Notes {
+        note1: read_qstring(reader)?,
+        note2: read_qstring(reader)?,
+        note3: read_qstring(reader)?,
+    };
+
+    // Fields added in later format versions
+    let num_temp_sensor_channels = if at_least(1, 1) {
+        reader.read_i16::<LittleEndian>()? as i32
+    } else {
+        0
+    };
+    let eval_board_mode = if at_least(1, 3) {
+        reader.read_i16::<LittleEndian>()? as i32
+    } else {
+        0
+    };
+    let reference_channel = if at_least(2, 0) {
+        read_qstring(reader)?
+    } else {
+        String::new()
+    };
+
+    let mut header = RhdHeader {
+        version,
+        sample_rate,
+        num_samples_per_data_block: if at_least(2, 0) { 128 } else { 60 },
+        dsp_enabled,
+        actual_dsp_cutoff_frequency,
+        actual_lower_bandwidth,
+        actual_upper_bandwidth,
+        desired_dsp_cutoff_frequency,
+        desired_lower_bandwidth,
+        desired_upper_bandwidth,
+        notch_filter_frequency,
+        desired_impedance_test_frequency,
+        actual_impedance_test_frequency,
+        notes,
+        num_temp_sensor_channels,
+        eval_board_mode,
+        reference_channel,
+        amplifier_channels: Vec::new(),
+        spike_triggers: Vec::new(),
+        aux_input_channels: Vec::new(),
+        supply_voltage_channels: Vec::new(),
+        board_adc_channels: Vec::new(),
+        board_dig_in_channels: Vec::new(),
+        board_dig_out_channels: Vec::new(),
+    };
+
+    // Read signal summary
+    let number_of_signal_groups = reader.read_i16::<LittleEndian>()?;
+    for signal_group in 1..=number_of_signal_groups {
+        read_signal_group(&mut header, reader, signal_group as i32)?;
+    }
+
+    info!(
+        "Found {} amplifier channels, {} auxiliary input channels, {} supply voltage channels, \
+         {} board ADC channels, {} board digital input channels, {} board digital output channels.",
+        header.amplifier_channels.len(),
+        header.aux_input_channels.len(),
+        header.supply_voltage_channels.len(),
+        header.board_adc_channels.len(),
+        header.board_dig_in_channels.len(),
+        header.board_dig_out_channels.len()
+    );
+
+    Ok(header)
+}
+
+/// Reads one signal group and its enabled channels
+fn read_signal_group<R: Read + Seek>(
+    header: &mut RhdHeader,
+    reader: &mut R,
+    signal_group: i32,
+) -> Result<(), IntanError> {
+    let port_name = read_qstring(reader)?;
+    let port_prefix = read_qstring(reader)?;
+    let enabled = reader.read_i16::<LittleEndian>()?;
+    let num_channels = reader.read_i16::<LittleEndian>()?;
+    let _ = reader.read_i16::<LittleEndian>()?; // number of amplifier channels (unused)
+
+    if num_channels <= 0 || enabled <= 0 {
+        return Ok(());
+    }
+
+    for _ in 0..num_channels {
+        let native_channel_name = read_qstring(reader)?;
+        let custom_channel_name = read_qstring(reader)?;
+        let native_order = reader.read_i16::<LittleEndian>()? as i32;
+        let custom_order = reader.read_i16::<LittleEndian>()? as i32;
+        let signal_type = reader.read_i16::<LittleEndian>()?;
+        let channel_enabled = reader.read_i16::<LittleEndian>()?;
+        let chip_channel = reader.read_i16::<LittleEndian>()? as i32;
+        let board_stream = reader.read_i16::<LittleEndian>()? as i32;
+
+        let trigger = SpikeTrigger {
+            voltage_trigger_mode: reader.read_i16::<LittleEndian>()? as i32,
+            voltage_threshold: reader.read_i16::<LittleEndian>()? as i32,
+            digital_trigger_channel: reader.read_i16::<LittleEndian>()? as i32,
+            digital_edge_polarity: reader.read_i16::<LittleEndian>()? as i32,
+        };
+
+        let channel = ChannelInfo {
+            port_name: port_name.clone(),
+            port_prefix: port_prefix.clone(),
+            port_number: signal_group,
+            native_channel_name,
+            custom_channel_name,
+            native_order,
+            custom_order,
+            chip_channel,
+            command_stream: 0,
+            board_stream,
+            electrode_impedance_magnitude: reader.read_f32::<LittleEndian>()?,
+            electrode_impedance_phase: reader.read_f32::<LittleEndian>()?,
+        };
+
+        if channel_enabled == 0 {
+            continue;
+        }
+
+        match signal_type {
+            0 => {
+                header.amplifier_channels.push(channel);
+                header.spike_triggers.push(trigger);
+            }
+            1 => header.aux_input_channels.push(channel),
+            2 => header.supply_voltage_channels.push(channel),
+            3 => header.board_adc_channels.push(channel),
+            4 => header.board_dig_in_channels.push(channel),
+            5 => header.board_dig_out_channels.push(channel),
+            _ => return Err(IntanError::InvalidChannelType),
+        }
+    }
+
+    Ok(())
+}
+
+/// Number of bytes in one data block
+fn bytes_per_data_block(header: &RhdHeader) -> usize {
+    let n = header.num_samples_per_data_block as usize;
+
+    let mut bytes = n * 4; // timestamps
+    bytes += n * 2 * header.amplifier_channels.len();
+    bytes += (n / 4) * 2 * header.aux_input_channels.len();
+    bytes += 2 * header.supply_voltage_channels.len();
+    bytes += 2 * header.num_temp_sensor_channels.max(0) as usize;
+    bytes += n * 2 * header.board_adc_channels.len();
+    if !header.board_dig_in_channels.is_empty() {
+        bytes += n * 2;
+    }
+    if !header.board_dig_out_channels.is_empty() {
+        bytes += n * 2;
+    }
+    bytes
+}
+
+/// Reads and scales all data blocks
+fn read_data<R: Read>(
+    header: &RhdHeader,
+    num_blocks: usize,
+    bytes_per_block: usize,
+    reader: &mut R,
+) -> Result<RhdData, Box<dyn Error>> {
+    info!("Reading data from file...");
+
+    let n = header.num_samples_per_data_block as usize;
+    let num_samples = num_blocks * n;
+    let num_temp = header.num_temp_sensor_channels.max(0) as usize;
+    let unsigned_timestamps = (header.version.major, header.version.minor) < (1, 2);
+
+    let alloc = |channels: usize, samples: usize| {
+        (channels > 0).then(|| Array2::<f64>::zeros((channels, samples)))
+    };
+
+    let mut timestamps = Array1::<i64>::zeros(num_samples);
+    let mut amplifier_data = alloc(header.amplifier_channels.len(), num_samples);
+    let mut aux_input_data = alloc(header.aux_input_channels.len(), num_blocks * (n / 4));
+    let mut supply_voltage_data = alloc(header.supply_voltage_channels.len(), num_blocks);
+    let mut temp_sensor_data = alloc(num_temp, num_blocks);
+    let mut board_adc_data = alloc(header.board_adc_channels.len(), num_samples);
+    let mut dig_in_words = Array1::<i32>::zeros(num_samples);
+    let mut dig_out_words = Array1::<i32>::zeros(num_samples);
+
+    let (adc_offset, adc_scale) = match header.eval_board_mode {
+        1 => (ADC_DAC_OFFSET, BOARD_ADC_SCALE_FACTOR_MODE_1),
+        13 => (ADC_DAC_OFFSET, BOARD_ADC_SCALE_FACTOR_MODE_13),
+        _ => (0.0, BOARD_ADC_SCALE_FACTOR),
+    };
+
+    let mut buffer = vec![0u8; bytes_per_block];
+    for block in 0..num_blocks {
+        reader.read_exact(&mut buffer)?;
+        let mut pos = 0;
+        let mut next_u16 = || {
+            let value = u16::from_le_bytes([buffer[pos], buffer[pos + 1]]);
+            pos += 2;
+            value
+        };
+
+        let start = block * n;
+
+        // Timestamps are stored as two 16-bit words each
+        for t in 0..n {
+            let low = next_u16() as u32;
+            let high = next_u16() as u32;
+            let value = (high << 16) | low;
+            timestamps[start + t] = if unsigned_timestamps {
+                value as i64
+            } else {
+                value as i32 as i64
+            };
+        }
+
+        read_signal_block(&mut next_u16, &mut amplifier_data, start, n, |x| {
+            (x as f64 - ADC_DAC_OFFSET) * AMPLIFIER_SCALE_FACTOR
+        });
+        read_signal_block(&mut next_u16, &mut aux_input_data, block * (n / 4), n / 4, |x| {
+            x as f64 * AUX_INPUT_SCALE_FACTOR
+        });
+        read_signal_block(&mut next_u16, &mut supply_voltage_data, block, 1, |x| {
+            x as f64 * SUPPLY_VOLTAGE_SCALE_FACTOR
+        });
+        read_signal_block(&mut next_u16, &mut temp_sensor_data, block, 1, |x| {
+            x as i16 as f64 * TEMP_SENSOR_SCALE_FACTOR
+        });
+        read_signal_block(&mut next_u16, &mut board_adc_data, start, n, |x| {
+            (x as f64 - adc_offset) * adc_scale
+        });
+
+        if !header.board_dig_in_channels.is_empty() {
+            for t in 0..n {
+                dig_in_words[start + t] = next_u16() as i32;
+            }
+        }
+        if !header.board_dig_out_channels.is_empty() {
+            for t in 0..n {
+                dig_out_words[start + t] = next_u16() as i32;
+            }
+        }
+    }
+
+    if !unsigned_timestamps {
+        unwrap_timestamps(timestamps.view_mut(), None);
+    }
+
+    // Apply the notch filter for files saved before RHX 3.0, as for RHS files
+    if let (Some(notch), Some(amp)) = (header.notch_filter_frequency, amplifier_data.as_mut()) {
+        if header.version.major < 3 {
+            info!("Applying notch filter...");
+            let filter = NotchFilter::new(notch as f64);
+            notch_filter_rows(amp, &filter, header.sample_rate as f64, |_| {})?;
+        }
+    }
+
+    Ok(RhdData {
+        timestamps,
+        amplifier_data,
+        aux_input_data,
+        supply_voltage_data,
+        temp_sensor_data,
+        board_adc_data,
+        board_dig_in_data: extract_bits(&dig_in_words, &header.board_dig_in_channels),
+        board_dig_out_data: extract_bits(&dig_out_words, &header.board_dig_out_channels),
+    })
+}
+
+/// Reads `num_samples` values for every channel of `dest`.
+///
+/// Within a data block, each channel's samples are stored contiguously, one
+/// channel after another.
+fn read_signal_block<N, F>(
+    next_u16: &mut N,
+    dest: &mut Option<Array2<f64>>,
+    start: usize,
+    num_samples: usize,
+    scale: F,
+) where
+    N: FnMut() -> u16,
+    F: Fn(u16) -> f64,
+{
+    if let Some(dest) = dest.as_mut() {
+        let num_channels = dest.nrows();
+        let mut block = dest.slice_mut(s![.., start..start + num_samples]);
+        for ch in 0..num_channels {
+            for t in 0..num_samples {
+                block[[ch, t]] = scale(next_u16());
+            }
+        }
+    }
+}
+
+/// Splits 16-bit digital words into one 0/1 row per channel, using each channel's native order as bit index
+fn extract_bits(words: &Array1<i32>, channels: &[ChannelInfo]) -> Option<Array2<i32>> {
+    if channels.is_empty() {
+        return None;
+    }
+    let mut bits = Array2::<i32>::zeros((channels.len(), words.len()));
+    for (i, channel) in channels.iter().enumerate() {
+        let mask = 1 << channel.native_order;
+        for (j, &word) in words.iter().enumerate() {
+            bits[[i, j]] = i32::from(word & mask != 0);
+        }
+    }
+    Some(bits)
+}
@@ -0,0 +1,137 @@
+//! Continuous segments of recordings with timestamp gaps.
+//!
+//! Timestamps count up by one per sample while RHX records without interruption.
+//! Dropped USB packets, paused recordings, and combined files that were not
+//! recorded back to back leave gaps, across which the sample index no longer maps
+//! to time linearly. [`RhsFile::segments`] lists the continuous stretches of
+//! samples between gaps with their start and end times, and
+//! [`RhsFile::split_segments`] copies each stretch into a recording of its own, so
+//! analyses that assume a uniform time axis can run on each part separately.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! for segment in rhs_file.segments() {
+//!     println!(
+//!         "samples {:?}: {:.3} s to {:.3} s",
+//!         segment.samples, segment.start_time, segment.end_time
+//!     );
+//! }
+//!
+//! // One recording per continuous segment
+//! for part in rhs_file.split_segments().unwrap() {
+//!     println!("{:.3} s of continuous data", part.duration());
+//! }
+//! ```
+
+use std::error::Error;
+use std::ops::Range;
+
+use ndarray::ArrayView1;
+
+use crate::types::{IntanError, RhsFile};
+
+/// A stretch of samples whose timestamps increase by one from sample to sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    /// Indices of the segment's samples within the recording
+    pub samples: Range<usize>,
+    /// Time of the first sample (s), from its timestamp
+    pub start_time: f64,
+    /// Time just after the last sample (s), from its timestamp, so that
+    /// `end_time - start_time` is the segment's duration
+    pub end_time: f64,
+}
+
+impl Segment {
+    /// Returns the number of samples in the segment.
+    pub fn num_samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns the duration of the segment in seconds.
+    pub fn duration(&self) -> f64 {
+        self.end_time - self.start_time
+    }
+}
+
+impl RhsFile {
+    /// Returns the continuous segments of the recording, in order.
+    ///
+    /// A new segment starts wherever a timestamp is not one more than the previous
+    /// one, whether samples are missing or the timestamps go backwards. A recording
+    /// without gaps has a single segment, and one without data has none.
+    pub fn segments(&self) -> Vec<Segment> {
+        match &self.data {
+            Some(data) => find_segments(data.timestamps.view(), self.header.sample_rate),
+            None => Vec::new(),
+        }
+    }
+
+    /// Splits the recording at its timestamp gaps into one recording per
+    /// continuous segment.
+    ///
+    /// Each part is a copy with the full header and the samples of one of the
+    /// [`segments`](RhsFile::segments). A recording without data is returned as a
+    /// single part.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if signals of the recording were spilled to disk.
+    pub fn split_segments(&self) -> Result<Vec<RhsFile>, Box<dyn Error>> {
+        let Some(data) = &self.data else {
+            return Ok(vec![self.clone()]);
+        };
+        if data.spilled.is_some() {
+            return Err(Box::new(IntanError::Other(
+                "Recordings with spilled signals cannot be split into segments".to_string(),
+            )));
+        }
+
+        let view = data.view();
+        Ok(self
+            .segments()
+            .into_iter()
+            .map(|segment| RhsFile {
+                header: self.header.clone(),
+                data: Some(
+                    view.slice_samples(segment.samples.start, segment.samples.end)
+                        .to_owned(),
+                ),
+                data_present: true,
+                source_files: self.source_files_in(segment.samples.clone()),
+                skipped_files: self.skipped_files.clone(),
+                fingerprints: self.fingerprints.clone(),
+                warnings: self.warnings.clone(),
+                settings: self.settings.clone(),
+                recording_start: self.sample_to_wallclock(segment.samples.start),
+            })
+            .collect())
+    }
+}
+
+/// Splits the samples where consecutive timestamps do not differ by one
+fn find_segments(timestamps: ArrayView1<'_, i64>, sample_rate: f32) -> Vec<Segment> {
+    let sample_rate = sample_rate as f64;
+    let segment = |samples: Range<usize>| Segment {
+        start_time: timestamps[samples.start] as f64 / sample_rate,
+        end_time: (timestamps[samples.end - 1] as f64 + 1.0) / sample_rate,
+        samples,
+    };
+
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for i in 1..timestamps.len() {
+        if timestamps[i] - timestamps[i - 1] != 1 {
+            segments.push(segment(start..i));
+            start = i;
+        }
+    }
+    if !timestamps.is_empty() {
+        segments.push(segment(start..timestamps.len()));
+    }
+    segments
+}
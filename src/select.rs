@@ -0,0 +1,182 @@
+//! Channel selection and reordering of loaded recordings.
+//!
+//! [`LoadOptions::channels`](crate::LoadOptions::channels) picks channels while a
+//! file is read. Once a recording is in memory, [`RhsFile::select_channels`] and
+//! [`RhsFile::retain_channels`] do the same: the header's channel lists, the spike
+//! triggers, and the rows of every signal, including the DC amplifier, stimulation,
+//! and stimulation flag data of the amplifier channels, are filtered together, so
+//! the result stays consistent. `select_channels` also puts the channels in the
+//! order they are listed, for example to match the geometry of a probe.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::{load, SignalType};
+//!
+//! let mut rhs_file = load("recording.rhs").unwrap();
+//!
+//! // Four amplifier channels in probe order
+//! let shank = rhs_file.select_channels(["A-003", "A-000", "A-002", "A-001"]).unwrap();
+//! assert_eq!(shank.header.amplifier_channels[0].native_channel_name, "A-003");
+//!
+//! // Drop the amplifier channels with high impedance, keeping the other signals
+//! rhs_file
+//!     .retain_channels(|signal_type, channel| {
+//!         signal_type != SignalType::Amplifier || channel.electrode_impedance_magnitude < 1e6
+//!     })
+//!     .unwrap();
+//! ```
+
+use std::collections::HashSet;
+use std::error::Error;
+
+use ndarray::{Array2, Axis};
+
+use crate::flags::StimFlags;
+use crate::loader::ChannelSelector;
+use crate::reader::{self, ChannelRows};
+use crate::signal::SignalType;
+use crate::types::{ChannelInfo, IntanError, RhsData, RhsFile};
+
+impl RhsFile {
+    /// Returns a copy of the recording holding only the given channels, in the
+    /// given order.
+    ///
+    /// Channels are selected by name or index as with
+    /// [`LoadOptions::channels`](crate::LoadOptions::channels). Each signal type
+    /// keeps its channels in the order they are first listed, and a signal type
+    /// with none of its channels listed is left out of the copy.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a selected channel does not exist, or if signals of the
+    /// recording were spilled to disk.
+    pub fn select_channels<I, S>(&self, channels: I) -> Result<RhsFile, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<ChannelSelector>,
+    {
+        let selectors: Vec<ChannelSelector> = channels.into_iter().map(Into::into).collect();
+        let mut rows = reader::resolve_channel_selectors(&self.header, &selectors)?;
+        for rows in [
+            &mut rows.amplifier,
+            &mut rows.board_adc,
+            &mut rows.board_dac,
+            &mut rows.board_dig_in,
+            &mut rows.board_dig_out,
+        ] {
+            let mut seen = HashSet::new();
+            rows.retain(|&row| seen.insert(row));
+        }
+
+        self.select_rows(&rows)
+    }
+
+    /// Keeps only the channels for which `keep` returns true, in their current
+    /// order.
+    ///
+    /// `keep` is called once for each channel of the header's amplifier, board
+    /// ADC, board DAC, digital input, and digital output channel lists, with the
+    /// matching [`SignalType`]. Removing an amplifier channel also removes its DC
+    /// amplifier, stimulation, and stimulation flag data, and a signal type left
+    /// without channels is removed from the data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if signals of the recording were spilled to disk, in which
+    /// case the recording is left unchanged.
+    pub fn retain_channels<F>(&mut self, mut keep: F) -> Result<(), Box<dyn Error>>
+    where
+        F: FnMut(SignalType, &ChannelInfo) -> bool,
+    {
+        let mut kept_rows = |signal_type: SignalType| -> Vec<usize> {
+            signal_type
+                .channels(&self.header)
+                .iter()
+                .enumerate()
+                .filter(|(_, channel)| keep(signal_type, channel))
+                .map(|(row, _)| row)
+                .collect()
+        };
+        let rows = ChannelRows {
+            amplifier: kept_rows(SignalType::Amplifier),
+            board_adc: kept_rows(SignalType::BoardAdc),
+            board_dac: kept_rows(SignalType::BoardDac),
+            board_dig_in: kept_rows(SignalType::DigIn),
+            board_dig_out: kept_rows(SignalType::DigOut),
+        };
+
+        *self = self.select_rows(&rows)?;
+        Ok(())
+    }
+
+    /// Copies the recording with the given rows of each signal type
+    pub(crate) fn select_rows(&self, rows: &ChannelRows) -> Result<RhsFile, Box<dyn Error>> {
+        let data = match &self.data {
+            Some(data) if data.spilled.is_some() => {
+                return Err(Box::new(IntanError::Other(
+                    "Channels of spilled signals cannot be selected after loading; \
+                     select them with LoadOptions::channels instead"
+                        .to_string(),
+                )));
+            }
+            Some(data) => Some(select_data(data, rows)),
+            None => None,
+        };
+
+        let mut header = self.header.clone();
+        reader::select_header_channels(&mut header, rows);
+        Ok(RhsFile {
+            header,
+            data,
+            data_present: self.data_present,
+            source_files: self.source_files.clone(),
+            skipped_files: self.skipped_files.clone(),
+            fingerprints: self.fingerprints.clone(),
+            warnings: self.warnings.clone(),
+            settings: self.settings.clone(),
+            recording_start: self.recording_start,
+        })
+    }
+}
+
+/// Copies the given rows of every signal, leaving out signals without rows
+fn select_data(data: &RhsData, rows: &ChannelRows) -> RhsData {
+    fn select<T: Clone>(array: &Option<Array2<T>>, rows: &[usize]) -> Option<Array2<T>> {
+        array
+            .as_ref()
+            .filter(|_| !rows.is_empty())
+            .map(|array| array.select(Axis(0), rows))
+    }
+    let amplifier = &rows.amplifier;
+    let select_flags = |flags: &Option<StimFlags>| {
+        flags
+            .as_ref()
+            .filter(|_| !amplifier.is_empty())
+            .map(|flags| flags.select(amplifier))
+    };
+
+    RhsData {
+        timestamps: data.timestamps.clone(),
+        amplifier_data: select(&data.amplifier_data, amplifier),
+        dc_amplifier_data: select(&data.dc_amplifier_data, amplifier),
+        stim_data: select(&data.stim_data, amplifier),
+        compliance_limit_data: select_flags(&data.compliance_limit_data),
+        charge_recovery_data: select_flags(&data.charge_recovery_data),
+        amp_settle_data: select_flags(&data.amp_settle_data),
+        board_adc_data: select(&data.board_adc_data, &rows.board_adc),
+        board_dac_data: select(&data.board_dac_data, &rows.board_dac),
+        board_dig_in_data: select(&data.board_dig_in_data, &rows.board_dig_in),
+        board_dig_out_data: select(&data.board_dig_out_data, &rows.board_dig_out),
+        amplifier_data_raw: select(&data.amplifier_data_raw, amplifier),
+        dc_amplifier_data_raw: select(&data.dc_amplifier_data_raw, amplifier),
+        board_adc_data_raw: select(&data.board_adc_data_raw, &rows.board_adc),
+        board_dac_data_raw: select(&data.board_dac_data_raw, &rows.board_dac),
+        amplifier_data_f32: select(&data.amplifier_data_f32, amplifier),
+        dc_amplifier_data_f32: select(&data.dc_amplifier_data_f32, amplifier),
+        board_adc_data_f32: select(&data.board_adc_data_f32, &rows.board_adc),
+        board_dac_data_f32: select(&data.board_dac_data_f32, &rows.board_dac),
+        spilled: None,
+        units: data.units,
+    }
+}
@@ -0,0 +1,211 @@
+//! The RHX `settings.xml` sidecar.
+//!
+//! Intan RHX saves a `settings.xml` file next to each recording. It holds
+//! information that is not part of the binary header, such as the display color
+//! and enabled state of every channel and the software filter settings. When
+//! [`load`](crate::load) finds this file in the same directory as the recording,
+//! it is parsed into [`RhsFile::settings`](crate::RhsFile::settings).
+//!
+//! The settings file is optional: if it is missing or cannot be parsed, the
+//! recording is still loaded and `settings` is `None`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("session/recording.rhs").unwrap();
+//! if let Some(settings) = &rhs_file.settings {
+//!     if let Some(channel) = settings.channel("A-000") {
+//!         println!("A-000 is drawn in {:?}", channel.color);
+//!     }
+//!     println!("Notch filter: {:?}", settings.setting("NotchFilterFreq"));
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use log::warn;
+
+use crate::types::{IntanError, LoadWarning};
+use crate::xml::XmlElement;
+
+/// File name of the sidecar written by RHX
+pub const SETTINGS_FILE_NAME: &str = "settings.xml";
+
+/// Parsed contents of an RHX `settings.xml` file.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SettingsXml {
+    /// Version of the RHX software that wrote the file
+    pub version: Option<String>,
+    /// Controller type, e.g. `ControllerStimRecord`
+    pub controller_type: Option<String>,
+    /// Settings grouped by the XML element they appear on (e.g. `GeneralConfig`)
+    ///
+    /// Attributes and text-only child elements are both included, keyed by name.
+    /// Attributes of the root element are stored under the root element's name.
+    pub sections: BTreeMap<String, BTreeMap<String, String>>,
+    /// Signal groups (ports) and their channels
+    pub signal_groups: Vec<SettingsSignalGroup>,
+}
+
+/// A signal group (port) in the settings file.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SettingsSignalGroup {
+    /// Name of the port (e.g. "Port A")
+    pub name: String,
+    /// Prefix for the port (e.g. "A")
+    pub prefix: String,
+    /// Whether the port was enabled
+    pub enabled: bool,
+    /// Channels in the port
+    pub channels: Vec<SettingsChannel>,
+}
+
+/// A channel in the settings file.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SettingsChannel {
+    /// Default channel name assigned by the system
+    pub native_channel_name: String,
+    /// User-defined custom name for the channel
+    pub custom_channel_name: String,
+    /// Whether the channel was enabled
+    pub enabled: bool,
+    /// Display color, as written by RHX (e.g. `#ff8000`)
+    pub color: Option<String>,
+    /// All attributes of the channel, including those above
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl SettingsXml {
+    /// Reads and parses a settings file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<SettingsXml, Box<dyn Error>> {
+        SettingsXml::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses the contents of a settings file.
+    pub fn parse(xml: &str) -> Result<SettingsXml, Box<dyn Error>> {
+        let root = XmlElement::parse(xml).map_err(|message| {
+            IntanError::Other(format!("Invalid settings XML: {}", message))
+        })?;
+
+        let mut settings = SettingsXml {
+            version: root.attribute("version").map(str::to_string),
+            controller_type: root.attribute("type").map(str::to_string),
+            sections: BTreeMap::new(),
+            signal_groups: Vec::new(),
+        };
+        settings.add_section(&root);
+        settings.collect(&root);
+
+        Ok(settings)
+    }
+
+    /// Looks up a channel by its native name (e.g. "A-000").
+    pub fn channel(&self, native_channel_name: &str) -> Option<&SettingsChannel> {
+        self.signal_groups
+            .iter()
+            .flat_map(|group| &group.channels)
+            .find(|channel| channel.native_channel_name == native_channel_name)
+    }
+
+    /// Looks up a setting by name in any section, returning the first match.
+    pub fn setting(&self, name: &str) -> Option<&str> {
+        self.sections
+            .values()
+            .find_map(|section| section.get(name))
+            .map(String::as_str)
+    }
+
+    /// Walks the element tree, recording signal groups and settings sections
+    fn collect(&mut self, element: &XmlElement) {
+        for child in &element.children {
+            if child.name == "SignalGroup" {
+                self.signal_groups.push(signal_group(child));
+            } else if child.children.is_empty() && child.attributes.is_empty() {
+                self.sections
+                    .entry(element.name.clone())
+                    .or_default()
+                    .insert(child.name.clone(), child.text.clone());
+            } else {
+                self.add_section(child);
+                self.collect(child);
+            }
+        }
+    }
+
+    fn add_section(&mut self, element: &XmlElement) {
+        let section = self.sections.entry(element.name.clone()).or_default();
+        for (key, value) in &element.attributes {
+            section.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+/// Loads the settings sidecar for a recording, if there is one.
+///
+/// `path` is the recording file or directory. A sidecar that cannot be parsed is
+/// added to `warnings` and otherwise ignored, since it is not needed to read the
+/// data.
+pub(crate) fn find_sidecar(path: &Path, warnings: &mut Vec<LoadWarning>) -> Option<SettingsXml> {
+    let dir_path = if path.is_dir() { path } else { path.parent()? };
+    let settings_path = dir_path.join(SETTINGS_FILE_NAME);
+    if !settings_path.is_file() {
+        return None;
+    }
+
+    match SettingsXml::from_file(&settings_path) {
+        Ok(settings) => Some(settings),
+        Err(e) => {
+            let warning = LoadWarning::UnreadableSettings {
+                path: settings_path.to_string_lossy().to_string(),
+                error: e.to_string(),
+            };
+            warn!("{}", warning);
+            warnings.push(warning);
+            None
+        }
+    }
+}
+
+fn signal_group(element: &XmlElement) -> SettingsSignalGroup {
+    SettingsSignalGroup {
+        name: element.attribute("name").unwrap_or_default().to_string(),
+        prefix: element.attribute("prefix").unwrap_or_default().to_string(),
+        enabled: element.attribute("enabled").is_none_or(parse_bool),
+        channels: element
+            .children
+            .iter()
+            .filter(|child| child.name == "Channel")
+            .map(channel)
+            .collect(),
+    }
+}
+
+fn channel(element: &XmlElement) -> SettingsChannel {
+    let native_channel_name = element
+        .attribute("nativeChannelName")
+        .unwrap_or_default()
+        .to_string();
+    SettingsChannel {
+        custom_channel_name: element
+            .attribute("customChannelName")
+            .map(str::to_string)
+            .unwrap_or_else(|| native_channel_name.clone()),
+        native_channel_name,
+        enabled: element.attribute("enabled").is_none_or(parse_bool),
+        color: element.attribute("color").map(str::to_string),
+        attributes: element.attributes.iter().cloned().collect(),
+    }
+}
+
+/// RHX writes booleans as `True`/`False`
+fn parse_bool(value: &str) -> bool {
+    value.eq_ignore_ascii_case("true") || value == "1"
+}
@@ -0,0 +1,310 @@
+//! A uniform interface to every signal type of a recording.
+//!
+//! [`RhsData`](crate::RhsData) keeps each signal type in its own fields, in
+//! whichever of `f64`, `f32`, or raw codes it was loaded as. [`RhsFile::signals`]
+//! wraps each present signal type in a [`Signal`] that pairs the data with its
+//! channel list, unit, and sample rate, so analysis code can treat amplifier, ADC,
+//! digital, and every other signal type alike.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! for signal in rhs_file.signals() {
+//!     let unit = signal.units().map_or("", |unit| unit.symbol());
+//!     for (channel, values) in signal.channels().iter().zip(signal.to_f64().rows()) {
+//!         let mean = values.mean().unwrap_or(0.0);
+//!         let name = &channel.native_channel_name;
+//!         println!("{} {}: mean {:.3} {}", signal.signal_type(), name, mean, unit);
+//!     }
+//! }
+//! ```
+
+use std::fmt;
+
+use ndarray::{Array2, ArrayView2};
+
+use crate::types::{ChannelInfo, RhsDataView, RhsFile, RhsFileView, RhsHeader};
+use crate::units::{ScaleFactor, ScaleFactors, Unit};
+
+/// A signal type of an RHS recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignalType {
+    /// Amplifier data
+    Amplifier,
+    /// DC amplifier data
+    DcAmplifier,
+    /// Stimulation current
+    Stim,
+    /// Board ADC data
+    BoardAdc,
+    /// Board DAC data
+    BoardDac,
+    /// Board digital inputs
+    DigIn,
+    /// Board digital outputs
+    DigOut,
+}
+
+impl SignalType {
+    /// All signal types, in the order of the data fields.
+    pub const ALL: [SignalType; 7] = [
+        SignalType::Amplifier,
+        SignalType::DcAmplifier,
+        SignalType::Stim,
+        SignalType::BoardAdc,
+        SignalType::BoardDac,
+        SignalType::DigIn,
+        SignalType::DigOut,
+    ];
+
+    /// Returns the signal type's name, e.g. `"board_adc"`, as used in the names of
+    /// the data fields.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SignalType::Amplifier => "amplifier",
+            SignalType::DcAmplifier => "dc_amplifier",
+            SignalType::Stim => "stim",
+            SignalType::BoardAdc => "board_adc",
+            SignalType::BoardDac => "board_dac",
+            SignalType::DigIn => "board_dig_in",
+            SignalType::DigOut => "board_dig_out",
+        }
+    }
+
+    /// Returns the header's channels of this signal type.
+    ///
+    /// DC amplifier and stimulation data are recorded on the amplifier channels.
+    pub fn channels<'h>(&self, header: &'h RhsHeader) -> &'h [ChannelInfo] {
+        match self {
+            SignalType::Amplifier | SignalType::DcAmplifier | SignalType::Stim => {
+                &header.amplifier_channels
+            }
+            SignalType::BoardAdc => &header.board_adc_channels,
+            SignalType::BoardDac => &header.board_dac_channels,
+            SignalType::DigIn => &header.board_dig_in_channels,
+            SignalType::DigOut => &header.board_dig_out_channels,
+        }
+    }
+}
+
+impl fmt::Display for SignalType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The data of a signal, in the form it was loaded in.
+///
+/// Each array has shape `[num_channels, num_samples]`.
+#[derive(Debug, Clone)]
+pub enum SignalData<'a> {
+    /// Scaled data in double precision
+    F64(ArrayView2<'a, f64>),
+    /// Scaled data in single precision
+    F32(ArrayView2<'a, f32>),
+    /// Raw 16-bit codes
+    Raw(ArrayView2<'a, u16>),
+    /// Digital states (0 or 1)
+    Digital(ArrayView2<'a, i32>),
+}
+
+impl SignalData<'_> {
+    /// Returns the shape of the data, `[num_channels, num_samples]`.
+    pub fn dim(&self) -> (usize, usize) {
+        match self {
+            SignalData::F64(data) => data.dim(),
+            SignalData::F32(data) => data.dim(),
+            SignalData::Raw(data) => data.dim(),
+            SignalData::Digital(data) => data.dim(),
+        }
+    }
+}
+
+/// One signal type of a recording, with its channels, unit, and sample rate.
+///
+/// Created by [`RhsFile::signal`] and [`RhsFile::signals`], or by the same methods
+/// of an [`RhsFileView`].
+#[derive(Debug, Clone)]
+pub struct Signal<'a> {
+    signal_type: SignalType,
+    channels: &'a [ChannelInfo],
+    data: SignalData<'a>,
+    unit: Option<Unit>,
+    scale_factor: Option<ScaleFactor>,
+    sample_rate: f32,
+}
+
+impl<'a> Signal<'a> {
+    /// Returns the signal type.
+    pub fn signal_type(&self) -> SignalType {
+        self.signal_type
+    }
+
+    /// Returns the channels, one per row of the data.
+    pub fn channels(&self) -> &'a [ChannelInfo] {
+        self.channels
+    }
+
+    /// Returns the data, in the form it was loaded in.
+    pub fn data(&self) -> &SignalData<'a> {
+        &self.data
+    }
+
+    /// Returns the physical unit of the signal, or `None` for digital states.
+    ///
+    /// Scaled data and [`to_f64`](Signal::to_f64) are in this unit.
+    pub fn units(&self) -> Option<Unit> {
+        self.unit
+    }
+
+    /// Returns the conversion of raw codes to physical units, or `None` if the data
+    /// is not raw.
+    pub fn scale_factor(&self) -> Option<ScaleFactor> {
+        self.scale_factor
+    }
+
+    /// Returns the sample rate (Hz).
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Returns the number of channels.
+    pub fn num_channels(&self) -> usize {
+        self.data.dim().0
+    }
+
+    /// Returns the number of samples per channel.
+    pub fn num_samples(&self) -> usize {
+        self.data.dim().1
+    }
+
+    /// Copies the data into an `f64` array.
+    ///
+    /// Analog values are in [`units`](Signal::units), with raw codes scaled as
+    /// [`RhsData::scale_raw`](crate::RhsData::scale_raw) would scale them. Digital
+    /// states become 0.0 or 1.0.
+    pub fn to_f64(&self) -> Array2<f64> {
+        match &self.data {
+            SignalData::F64(data) => data.to_owned(),
+            SignalData::F32(data) => data.mapv(f64::from),
+            SignalData::Raw(data) => match (self.scale_factor, self.unit) {
+                (Some(scale_factor), Some(unit)) => scale_factor
+                    .apply_array(&data.to_owned(), unit)
+                    .unwrap_or_else(|| data.mapv(|code| scale_factor.apply(code))),
+                _ => data.mapv(f64::from),
+            },
+            SignalData::Digital(data) => data.mapv(f64::from),
+        }
+    }
+}
+
+impl RhsFile {
+    /// Returns one signal type of the loaded data, or `None` if it was not loaded.
+    ///
+    /// Signals spilled to disk with
+    /// [`LoadOptions::spill_dir`](crate::loader::LoadOptions::spill_dir) are not in
+    /// memory and are not returned.
+    pub fn signal(&self, signal_type: SignalType) -> Option<Signal<'_>> {
+        signal(&self.header, self.data.as_ref()?.view(), signal_type)
+    }
+
+    /// Returns every signal type present in the loaded data, in the order of
+    /// [`SignalType::ALL`].
+    pub fn signals(&self) -> Vec<Signal<'_>> {
+        SignalType::ALL
+            .iter()
+            .filter_map(|&signal_type| self.signal(signal_type))
+            .collect()
+    }
+}
+
+impl<'a> RhsFileView<'a> {
+    /// Returns one signal type of the view, or `None` if it was not loaded.
+    pub fn signal(&self, signal_type: SignalType) -> Option<Signal<'a>> {
+        signal(self.header, self.data.clone(), signal_type)
+    }
+
+    /// Returns every signal type present in the view, in the order of
+    /// [`SignalType::ALL`].
+    pub fn signals(&self) -> Vec<Signal<'a>> {
+        SignalType::ALL
+            .iter()
+            .filter_map(|&signal_type| self.signal(signal_type))
+            .collect()
+    }
+}
+
+/// Wraps one signal type of a view of the data described by `header`
+pub(crate) fn signal<'a>(
+    header: &'a RhsHeader,
+    view: RhsDataView<'a>,
+    signal_type: SignalType,
+) -> Option<Signal<'a>> {
+    let units = view.units;
+    let scale_factors = ScaleFactors::RHS;
+    let analog = |f64_data: Option<ArrayView2<'a, f64>>,
+                  f32_data: Option<ArrayView2<'a, f32>>,
+                  raw_data: Option<ArrayView2<'a, u16>>,
+                  unit: Unit,
+                  scale_factor: ScaleFactor| {
+        if let Some(data) = f64_data {
+            Some((SignalData::F64(data), Some(unit), None))
+        } else if let Some(data) = f32_data {
+            Some((SignalData::F32(data), Some(unit), None))
+        } else {
+            raw_data.map(|data| (SignalData::Raw(data), Some(unit), Some(scale_factor)))
+        }
+    };
+
+    let (data, unit, scale_factor) = match signal_type {
+        SignalType::Amplifier => analog(
+            view.amplifier_data,
+            view.amplifier_data_f32,
+            view.amplifier_data_raw,
+            units.amplifier,
+            scale_factors.amplifier,
+        ),
+        SignalType::DcAmplifier => analog(
+            view.dc_amplifier_data,
+            view.dc_amplifier_data_f32,
+            view.dc_amplifier_data_raw,
+            units.dc_amplifier,
+            scale_factors.dc_amplifier,
+        ),
+        SignalType::Stim => view
+            .stim_data
+            .map(|data| (SignalData::F64(data), Some(units.stim), None)),
+        SignalType::BoardAdc => analog(
+            view.board_adc_data,
+            view.board_adc_data_f32,
+            view.board_adc_data_raw,
+            units.board_adc,
+            scale_factors.board_adc,
+        ),
+        SignalType::BoardDac => analog(
+            view.board_dac_data,
+            view.board_dac_data_f32,
+            view.board_dac_data_raw,
+            units.board_dac,
+            scale_factors.board_dac,
+        ),
+        SignalType::DigIn => view
+            .board_dig_in_data
+            .map(|data| (SignalData::Digital(data), None, None)),
+        SignalType::DigOut => view
+            .board_dig_out_data
+            .map(|data| (SignalData::Digital(data), None, None)),
+    }?;
+
+    Some(Signal {
+        signal_type,
+        channels: signal_type.channels(header),
+        data,
+        unit,
+        scale_factor,
+        sample_rate: header.sample_rate,
+    })
+}
@@ -0,0 +1,566 @@
+//! Out-of-core loading, with the large analog signals spilled to temporary files.
+//!
+//! Amplifier, DC amplifier, board ADC, and board DAC data make up nearly all of a
+//! recording. When [`LoadOptions::spill_dir`] is set, these signals are written to
+//! temporary files while the file is read, one file per channel, instead of being
+//! kept in memory. Timestamps, stimulation, and digital data stay resident as usual.
+//!
+//! The loaded [`RhsData`] then has `None` in the spilled signals' fields and holds
+//! a [`SpilledSignals`] handle in [`RhsData::spilled`]. Channels are read back one
+//! at a time with [`RhsData::amplifier_channel`] and its siblings, which return the
+//! same values a regular load would hold in the corresponding array row, notch
+//! filter included. The temporary files are deleted when the last clone of the
+//! data referring to them is dropped.
+//!
+//! Spilling applies to traditional RHS files and directories of them. To use the
+//! original file as the backing store instead, see `MappedRhsFile` (with the `mmap`
+//! feature).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::Loader;
+//!
+//! let rhs_file = Loader::new("long_recording.rhs")
+//!     .spill_to(std::env::temp_dir())
+//!     .load()
+//!     .unwrap();
+//! let data = rhs_file.data.as_ref().unwrap();
+//!
+//! for index in 0..rhs_file.header.amplifier_channels.len() {
+//!     let channel = data.amplifier_channel(index).unwrap();
+//!     let rms = (channel.iter().map(|x| x * x).sum::<f64>() / channel.len() as f64).sqrt();
+//!     println!("channel {}: {:.1} μV RMS", index, rms);
+//! }
+//! ```
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use ndarray::{Array1, Array2, ArrayView2, Axis};
+
+use crate::chunk::concat_views;
+use crate::dsp::NotchDesign;
+use crate::loader::{ChannelSelector, DataType, LoadOptions, LoadPhase, LoadProgress, NotchMode};
+use crate::reader::{self, ChannelRows, SAMPLES_PER_DATA_BLOCK};
+use crate::types::{IntanError, RhsData, RhsHeader};
+use crate::units::{ScaleFactor, ScaleFactors, Unit};
+
+/// Number of spill directories created by this process, to keep their names unique
+static SPILL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A signal type that can be spilled to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpilledSignal {
+    /// Amplifier data
+    Amplifier,
+    /// DC amplifier data
+    DcAmplifier,
+    /// Board ADC data
+    BoardAdc,
+    /// Board DAC data
+    BoardDac,
+}
+
+impl SpilledSignal {
+    /// All spilled signal types.
+    pub const ALL: [SpilledSignal; 4] = [
+        SpilledSignal::Amplifier,
+        SpilledSignal::DcAmplifier,
+        SpilledSignal::BoardAdc,
+        SpilledSignal::BoardDac,
+    ];
+
+    /// Prefix of the signal's temporary files
+    fn file_prefix(&self) -> &'static str {
+        match self {
+            SpilledSignal::Amplifier => "amplifier",
+            SpilledSignal::DcAmplifier => "dc-amplifier",
+            SpilledSignal::BoardAdc => "board-adc",
+            SpilledSignal::BoardDac => "board-dac",
+        }
+    }
+
+    /// Index of the signal in per-signal arrays
+    fn index(&self) -> usize {
+        *self as usize
+    }
+
+    /// Error for a channel of the signal that does not exist; DC amplifier data is
+    /// recorded on the amplifier channels
+    fn channel_not_found(&self, index: usize) -> IntanError {
+        IntanError::ChannelNotFound(match self {
+            SpilledSignal::Amplifier | SpilledSignal::DcAmplifier => {
+                ChannelSelector::Amplifier(index)
+            }
+            SpilledSignal::BoardAdc => ChannelSelector::BoardAdc(index),
+            SpilledSignal::BoardDac => ChannelSelector::BoardDac(index),
+        })
+    }
+}
+
+/// Handle to the signals of a recording that were spilled to temporary files.
+///
+/// Clones share the files, which are deleted once every clone has been dropped.
+/// See the [module documentation](crate::spill) for an overview.
+#[derive(Debug, Clone)]
+pub struct SpilledSignals {
+    /// Spilled data of each loaded file, in recording order
+    parts: Vec<Arc<SpillPart>>,
+}
+
+/// The spilled signals of one RHS file
+#[derive(Debug)]
+struct SpillPart {
+    /// Temporary directory holding one file of raw codes per channel
+    dir: PathBuf,
+    num_samples: usize,
+    /// Number of spilled channels of each signal type
+    num_channels: [usize; 4],
+    /// Notch filter of the amplifier data, if it must be notch filtered
+    notch: Option<(NotchDesign, bool)>,
+}
+
+impl Drop for SpillPart {
+    fn drop(&mut self) {
+        // Leftover files are harmless, so a failed cleanup is ignored
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+impl SpillPart {
+    fn channel_path(&self, signal: SpilledSignal, index: usize) -> PathBuf {
+        channel_path(&self.dir, signal, index)
+    }
+
+    /// Reads the raw codes of one channel
+    fn read_codes(&self, signal: SpilledSignal, index: usize) -> Result<Vec<u16>, IntanError> {
+        let file = File::open(self.channel_path(signal, index))?;
+        let mut reader = BufReader::with_capacity(65536, file);
+        let mut codes = vec![0u16; self.num_samples];
+        reader.read_u16_into::<LittleEndian>(&mut codes)?;
+        Ok(codes)
+    }
+
+    /// Reads one channel scaled to `unit`, notch filtering amplifier data if necessary
+    fn read_scaled(
+        &self,
+        signal: SpilledSignal,
+        index: usize,
+        factor: ScaleFactor,
+        unit: Unit,
+    ) -> Result<Vec<f64>, IntanError> {
+        let codes = Array1::from(self.read_codes(signal, index)?).insert_axis(Axis(0));
+        let mut values = factor
+            .apply_array(&codes, unit)
+            .ok_or_else(|| IntanError::Other(format!("Cannot scale to {}", unit)))?
+            .into_raw_vec_and_offset()
+            .0;
+
+        match self.notch {
+            Some((design, zero_phase)) if signal == SpilledSignal::Amplifier => {
+                let channel = (&mut values[..]).into();
+                if zero_phase {
+                    design.filter_zero_phase(channel);
+                } else {
+                    design.filter(channel, &mut design.history(), 0);
+                }
+                Ok(values)
+            }
+            _ => Ok(values),
+        }
+    }
+}
+
+impl SpilledSignals {
+    /// Returns the number of samples per channel.
+    pub fn num_samples(&self) -> usize {
+        self.parts.iter().map(|part| part.num_samples).sum()
+    }
+
+    /// Returns the number of spilled channels of `signal`.
+    pub fn num_channels(&self, signal: SpilledSignal) -> usize {
+        self.parts
+            .first()
+            .map_or(0, |part| part.num_channels[signal.index()])
+    }
+
+    /// Reads the raw codes of one channel.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the channel was not spilled or a temporary file cannot
+    /// be read.
+    pub fn read_codes(
+        &self,
+        signal: SpilledSignal,
+        index: usize,
+    ) -> Result<Array1<u16>, IntanError> {
+        self.check_channel(signal, index)?;
+        let mut codes = Vec::with_capacity(self.num_samples());
+        for part in &self.parts {
+            codes.extend(part.read_codes(signal, index)?);
+        }
+        Ok(Array1::from(codes))
+    }
+
+    /// Reads one channel scaled to `unit`, as a regular load would produce it
+    fn read_scaled(
+        &self,
+        signal: SpilledSignal,
+        index: usize,
+        unit: Unit,
+    ) -> Result<Array1<f64>, IntanError> {
+        self.check_channel(signal, index)?;
+        let factor = scale_factor(signal);
+        let mut values = Vec::with_capacity(self.num_samples());
+        for part in &self.parts {
+            values.extend(part.read_scaled(signal, index, factor, unit)?);
+        }
+        Ok(Array1::from(values))
+    }
+
+    fn check_channel(&self, signal: SpilledSignal, index: usize) -> Result<(), IntanError> {
+        if index >= self.num_channels(signal) {
+            return Err(signal.channel_not_found(index));
+        }
+        Ok(())
+    }
+
+    /// Appends the spilled signals of the next file of a recording
+    pub(crate) fn append(&mut self, next: SpilledSignals) {
+        self.parts.extend(next.parts);
+    }
+}
+
+impl RhsData {
+    /// Returns one amplifier channel in the unit of [`units`](RhsData::units).
+    ///
+    /// The channel is taken from whichever form the data was loaded in:
+    /// `amplifier_data`, `amplifier_data_f32`, the raw codes, or the spilled files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no amplifier channel at `index`, or if a
+    /// spilled channel cannot be read.
+    pub fn amplifier_channel(&self, index: usize) -> Result<Array1<f64>, IntanError> {
+        self.channel(
+            SpilledSignal::Amplifier,
+            index,
+            self.amplifier_data.as_ref(),
+            self.amplifier_data_f32.as_ref(),
+            self.amplifier_data_raw.as_ref(),
+        )
+    }
+
+    /// Returns one DC amplifier channel in the unit of [`units`](RhsData::units).
+    ///
+    /// # Errors
+    ///
+    /// See [`amplifier_channel`](RhsData::amplifier_channel).
+    pub fn dc_amplifier_channel(&self, index: usize) -> Result<Array1<f64>, IntanError> {
+        self.channel(
+            SpilledSignal::DcAmplifier,
+            index,
+            self.dc_amplifier_data.as_ref(),
+            self.dc_amplifier_data_f32.as_ref(),
+            self.dc_amplifier_data_raw.as_ref(),
+        )
+    }
+
+    /// Returns one board ADC channel in the unit of [`units`](RhsData::units).
+    ///
+    /// # Errors
+    ///
+    /// See [`amplifier_channel`](RhsData::amplifier_channel).
+    pub fn board_adc_channel(&self, index: usize) -> Result<Array1<f64>, IntanError> {
+        self.channel(
+            SpilledSignal::BoardAdc,
+            index,
+            self.board_adc_data.as_ref(),
+            self.board_adc_data_f32.as_ref(),
+            self.board_adc_data_raw.as_ref(),
+        )
+    }
+
+    /// Returns one board DAC channel in the unit of [`units`](RhsData::units).
+    ///
+    /// # Errors
+    ///
+    /// See [`amplifier_channel`](RhsData::amplifier_channel).
+    pub fn board_dac_channel(&self, index: usize) -> Result<Array1<f64>, IntanError> {
+        self.channel(
+            SpilledSignal::BoardDac,
+            index,
+            self.board_dac_data.as_ref(),
+            self.board_dac_data_f32.as_ref(),
+            self.board_dac_data_raw.as_ref(),
+        )
+    }
+
+    /// Reads every spilled channel back into memory, filling the `f64` fields of
+    /// the spilled signals, and drops the handle to the temporary files.
+    ///
+    /// Does nothing if no signals were spilled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a spilled channel cannot be read.
+    pub fn load_spilled(&mut self) -> Result<(), IntanError> {
+        let Some(spilled) = self.spilled.take() else {
+            return Ok(());
+        };
+
+        for signal in SpilledSignal::ALL {
+            let num_channels = spilled.num_channels(signal);
+            if num_channels == 0 {
+                continue;
+            }
+            let unit = self.unit_of(signal);
+            let mut data = Array2::zeros((num_channels, spilled.num_samples()));
+            for (index, mut row) in data.axis_iter_mut(Axis(0)).enumerate() {
+                row.assign(&spilled.read_scaled(signal, index, unit)?);
+            }
+            *self.field_of(signal) = Some(data);
+        }
+        Ok(())
+    }
+
+    fn channel(
+        &self,
+        signal: SpilledSignal,
+        index: usize,
+        data: Option<&Array2<f64>>,
+        data_f32: Option<&Array2<f32>>,
+        codes: Option<&Array2<u16>>,
+    ) -> Result<Array1<f64>, IntanError> {
+        let unit = self.unit_of(signal);
+        let row = |rows: usize| {
+            (index < rows)
+                .then_some(index)
+                .ok_or_else(|| signal.channel_not_found(index))
+        };
+
+        if let Some(data) = data {
+            return Ok(data.row(row(data.nrows())?).to_owned());
+        }
+        if let Some(data) = data_f32 {
+            return Ok(data.row(row(data.nrows())?).mapv(f64::from));
+        }
+        if let Some(codes) = codes {
+            let codes = codes.select(Axis(0), &[row(codes.nrows())?]);
+            let scaled = scale_factor(signal)
+                .apply_array(&codes, unit)
+                .ok_or_else(|| IntanError::Other(format!("Cannot scale to {}", unit)))?;
+            return Ok(scaled.index_axis_move(Axis(0), 0));
+        }
+        match &self.spilled {
+            Some(spilled) => spilled.read_scaled(signal, index, unit),
+            None => Err(signal.channel_not_found(index)),
+        }
+    }
+
+    fn unit_of(&self, signal: SpilledSignal) -> Unit {
+        match signal {
+            SpilledSignal::Amplifier => self.units.amplifier,
+            SpilledSignal::DcAmplifier => self.units.dc_amplifier,
+            SpilledSignal::BoardAdc => self.units.board_adc,
+            SpilledSignal::BoardDac => self.units.board_dac,
+        }
+    }
+
+    fn field_of(&mut self, signal: SpilledSignal) -> &mut Option<Array2<f64>> {
+        match signal {
+            SpilledSignal::Amplifier => &mut self.amplifier_data,
+            SpilledSignal::DcAmplifier => &mut self.dc_amplifier_data,
+            SpilledSignal::BoardAdc => &mut self.board_adc_data,
+            SpilledSignal::BoardDac => &mut self.board_dac_data,
+        }
+    }
+}
+
+/// Reads the selected samples like `reader::read_selected_data`, one data block at
+/// a time, spilling the analog signals to a new directory inside `spill_dir`
+pub(crate) fn read_spilled_data<R: Read + Seek>(
+    reader: &mut R,
+    file_header: &RhsHeader,
+    header: &RhsHeader,
+    rows: Option<&ChannelRows>,
+    samples: Range<usize>,
+    options: &LoadOptions,
+    spill_dir: &Path,
+) -> Result<RhsData, Box<dyn Error>> {
+    let blocks = reader::selected_blocks(&samples);
+    let first_timestamp = reader::skip_to_block(reader, file_header, blocks.start)?;
+
+    let mut writer = SpillWriter::create(spill_dir)?;
+    let block_options = LoadOptions {
+        raw: true,
+        data_type: DataType::F64,
+        notch: NotchMode::Skip,
+        verbose: false,
+        progress: None,
+        spill_dir: None,
+        ..options.clone()
+    };
+
+    let mut resident = Vec::new();
+    for block in blocks.clone() {
+        let raw_data = reader::read_data_blocks(file_header, rows, 1, &block_options, reader)?;
+
+        // Trim the first and last blocks to the selected samples
+        let block_start = block as usize * SAMPLES_PER_DATA_BLOCK;
+        let start = samples.start.max(block_start) - block_start;
+        let end = samples.end.min(block_start + SAMPLES_PER_DATA_BLOCK) - block_start;
+        let raw_data = reader::select_raw_data(raw_data, None, start..end);
+
+        let mut data = reader::process_data(header, raw_data, &block_options)?;
+        writer.write(SpilledSignal::Amplifier, data.amplifier_data_raw.take())?;
+        writer.write(
+            SpilledSignal::DcAmplifier,
+            data.dc_amplifier_data_raw.take(),
+        )?;
+        writer.write(SpilledSignal::BoardAdc, data.board_adc_data_raw.take())?;
+        writer.write(SpilledSignal::BoardDac, data.board_dac_data_raw.take())?;
+        resident.push(data);
+
+        if let Some(callback) = &options.progress {
+            callback.report(&LoadProgress {
+                phase: LoadPhase::Data,
+                bytes_read: reader.stream_position()?,
+                blocks_parsed: block - blocks.start + 1,
+                total_blocks: blocks.end - blocks.start,
+                channels_filtered: 0,
+                total_channels: 0,
+            });
+        }
+    }
+
+    let views: Vec<_> = resident.iter().map(|data| data.view()).collect();
+    let mut data = concat_views(&views)?;
+    reader::unwrap_timestamps(data.timestamps.view_mut(), first_timestamp);
+    data.spilled = Some(SpilledSignals {
+        parts: vec![Arc::new(writer.finish(
+            samples.len(),
+            header,
+            options,
+        )?)],
+    });
+    Ok(data)
+}
+
+/// Writes the spilled channels of one file, one buffered file per channel
+struct SpillWriter {
+    dir: PathBuf,
+    writers: [Vec<BufWriter<File>>; 4],
+}
+
+impl SpillWriter {
+    fn create(spill_dir: &Path) -> Result<SpillWriter, Box<dyn Error>> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.subsec_nanos());
+        let dir = spill_dir.join(format!(
+            "intan-spill-{}-{}-{}",
+            std::process::id(),
+            SPILL_COUNT.fetch_add(1, Ordering::Relaxed),
+            nanos
+        ));
+        fs::create_dir_all(spill_dir)?;
+        fs::create_dir(&dir)?;
+        Ok(SpillWriter {
+            dir,
+            writers: Default::default(),
+        })
+    }
+
+    /// Appends a block of codes to the channels' files, creating them on first use
+    fn write(
+        &mut self,
+        signal: SpilledSignal,
+        codes: Option<Array2<u16>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(codes) = codes else {
+            return Ok(());
+        };
+
+        let writers = &mut self.writers[signal.index()];
+        if writers.is_empty() {
+            for index in 0..codes.nrows() {
+                let file = File::create(channel_path(&self.dir, signal, index))?;
+                writers.push(BufWriter::with_capacity(16384, file));
+            }
+        }
+        write_rows(writers, codes.view())
+    }
+
+    /// Flushes every file and returns the finished part
+    fn finish(
+        mut self,
+        num_samples: usize,
+        header: &RhsHeader,
+        options: &LoadOptions,
+    ) -> Result<SpillPart, Box<dyn Error>> {
+        let mut num_channels = [0; 4];
+        for (count, writers) in num_channels.iter_mut().zip(&mut self.writers) {
+            for writer in writers.iter_mut() {
+                writer.flush()?;
+            }
+            *count = writers.len();
+        }
+
+        let notch = match reader::notch_filter(header, options) {
+            Some(filter) => Some((filter.design(header.sample_rate as f64)?, filter.zero_phase)),
+            None => None,
+        };
+        Ok(SpillPart {
+            dir: std::mem::take(&mut self.dir),
+            num_samples,
+            num_channels,
+            notch,
+        })
+    }
+}
+
+impl Drop for SpillWriter {
+    fn drop(&mut self) {
+        // Only reached with a directory left when the load failed
+        if !self.dir.as_os_str().is_empty() {
+            self.writers = Default::default();
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+fn write_rows(
+    writers: &mut [BufWriter<File>],
+    codes: ArrayView2<'_, u16>,
+) -> Result<(), Box<dyn Error>> {
+    for (writer, row) in writers.iter_mut().zip(codes.rows()) {
+        for &code in row {
+            writer.write_all(&code.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn channel_path(dir: &Path, signal: SpilledSignal, index: usize) -> PathBuf {
+    dir.join(format!("{}-{:03}.u16", signal.file_prefix(), index))
+}
+
+fn scale_factor(signal: SpilledSignal) -> ScaleFactor {
+    let factors = ScaleFactors::RHS;
+    match signal {
+        SpilledSignal::Amplifier => factors.amplifier,
+        SpilledSignal::DcAmplifier => factors.dc_amplifier,
+        SpilledSignal::BoardAdc => factors.board_adc,
+        SpilledSignal::BoardDac => factors.board_dac,
+    }
+}
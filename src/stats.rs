@@ -0,0 +1,416 @@
+//! Whole-recording statistics computed in a single streaming pass.
+//!
+//! [`scan`] reads a recording block by block with an
+//! [`RhsBlockReader`] and keeps only running totals,
+//! so it needs the same small amount of memory for a one-minute test and a
+//! day-long session. The resulting [`RecordingStats`] holds the minimum, maximum,
+//! and RMS of every analog channel, the edge counts of every digital channel, the
+//! stimulation pulse counts of every amplifier channel, and a summary of timestamp
+//! gaps. It serializes to JSON with [`RecordingStats::to_json`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::stats::scan;
+//!
+//! let stats = scan("recording.rhs").unwrap();
+//! for channel in &stats.amplifier {
+//!     println!("{}: RMS {:.1} μV", channel.name, channel.rms);
+//! }
+//! println!("{} timestamp gaps", stats.gaps.num_gaps);
+//! ```
+
+use std::path::Path;
+
+use ndarray::Array2;
+
+use crate::flags::StimFlags;
+use crate::json::Json;
+use crate::loader::{DataType, LoadOptions};
+use crate::qc::GapSummary;
+use crate::stream::RhsBlockReader;
+use crate::types::{ChannelInfo, IntanError, RhsData, RhsHeader};
+
+/// Statistics of one analog channel.
+#[derive(Debug, Clone)]
+pub struct SignalStats {
+    /// Custom channel name
+    pub name: String,
+    /// Native channel name
+    pub native_name: String,
+    /// Smallest sample, in the unit of the signal
+    pub min: f64,
+    /// Largest sample, in the unit of the signal
+    pub max: f64,
+    /// Root-mean-square amplitude, in the unit of the signal
+    pub rms: f64,
+}
+
+/// Edge counts of one digital channel.
+#[derive(Debug, Clone)]
+pub struct DigitalStats {
+    /// Custom channel name
+    pub name: String,
+    /// Native channel name
+    pub native_name: String,
+    /// Number of transitions from 0 to 1
+    pub rising_edges: usize,
+    /// Number of transitions from 1 to 0
+    pub falling_edges: usize,
+}
+
+/// Stimulation statistics of one amplifier channel.
+#[derive(Debug, Clone)]
+pub struct StimStats {
+    /// Custom channel name
+    pub name: String,
+    /// Native channel name
+    pub native_name: String,
+    /// Number of stimulation onsets (transitions from zero to non-zero current)
+    pub num_pulses: usize,
+    /// Largest absolute stimulation current (μA)
+    pub max_current: f64,
+    /// Number of samples where the compliance limit was reached
+    pub compliance_limit_samples: usize,
+    /// Number of samples with charge recovery active
+    pub charge_recovery_samples: usize,
+    /// Number of samples with amplifier settle active
+    pub amp_settle_samples: usize,
+}
+
+/// Statistics of a whole recording, as computed by [`scan`].
+///
+/// Each list holds one entry per channel of the signal type, in header order, and
+/// is empty if the recording holds no data of that type.
+#[derive(Debug, Clone)]
+pub struct RecordingStats {
+    /// Header of the scanned file, listing only the selected channels
+    pub header: RhsHeader,
+    /// Number of samples scanned
+    pub num_samples: usize,
+    /// Duration of the scanned samples (s)
+    pub duration: f32,
+    /// Amplifier channels (μV)
+    pub amplifier: Vec<SignalStats>,
+    /// DC amplifier channels (V)
+    pub dc_amplifier: Vec<SignalStats>,
+    /// Board ADC channels (V)
+    pub board_adc: Vec<SignalStats>,
+    /// Board DAC channels (V)
+    pub board_dac: Vec<SignalStats>,
+    /// Board digital input channels
+    pub board_dig_in: Vec<DigitalStats>,
+    /// Board digital output channels
+    pub board_dig_out: Vec<DigitalStats>,
+    /// Stimulation on amplifier channels
+    pub stim: Vec<StimStats>,
+    /// Timestamp gap summary
+    pub gaps: GapSummary,
+}
+
+/// Computes the statistics of an RHS file in a single streaming pass.
+///
+/// Amplifier data is notch filtered as it would be when loading the file.
+///
+/// # Errors
+///
+/// Returns the errors of [`RhsBlockReader::open`] and any error reading the data
+/// blocks.
+pub fn scan<P: AsRef<Path>>(path: P) -> Result<RecordingStats, IntanError> {
+    scan_with_options(path, &LoadOptions::default())
+}
+
+/// Computes the statistics of an RHS file like [`scan`], for the channels, time
+/// range, and notch filter setting of `options`.
+///
+/// Signals are always scaled to `f64`, so the [`raw`](LoadOptions::raw),
+/// [`data_type`](LoadOptions::data_type), and [`spill_dir`](LoadOptions::spill_dir)
+/// settings are ignored.
+///
+/// # Errors
+///
+/// Returns the errors of [`RhsBlockReader::with_options`] and any error reading the
+/// data blocks.
+pub fn scan_with_options<P: AsRef<Path>>(
+    path: P,
+    options: &LoadOptions,
+) -> Result<RecordingStats, IntanError> {
+    let options = LoadOptions {
+        raw: false,
+        data_type: DataType::F64,
+        spill_dir: None,
+        ..options.clone()
+    };
+    let blocks = RhsBlockReader::with_options(path, &options)?;
+    let header = blocks.header().clone();
+
+    let mut totals = RunningTotals::default();
+    for block in blocks {
+        totals.add(&block?.data);
+    }
+    Ok(totals.finish(header))
+}
+
+impl RecordingStats {
+    /// Serializes the statistics as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        let signals = |stats: &[SignalStats]| {
+            let stats = stats
+                .iter()
+                .map(|s| {
+                    Json::object(vec![
+                        ("name", s.name.as_str().into()),
+                        ("native_name", s.native_name.as_str().into()),
+                        ("min", s.min.into()),
+                        ("max", s.max.into()),
+                        ("rms", s.rms.into()),
+                    ])
+                })
+                .collect();
+            Json::Array(stats)
+        };
+        let digital = |stats: &[DigitalStats]| {
+            let stats = stats
+                .iter()
+                .map(|s| {
+                    Json::object(vec![
+                        ("name", s.name.as_str().into()),
+                        ("native_name", s.native_name.as_str().into()),
+                        ("rising_edges", s.rising_edges.into()),
+                        ("falling_edges", s.falling_edges.into()),
+                    ])
+                })
+                .collect();
+            Json::Array(stats)
+        };
+        let stim = self
+            .stim
+            .iter()
+            .map(|s| {
+                Json::object(vec![
+                    ("name", s.name.as_str().into()),
+                    ("native_name", s.native_name.as_str().into()),
+                    ("num_pulses", s.num_pulses.into()),
+                    ("max_current_ua", s.max_current.into()),
+                    (
+                        "compliance_limit_samples",
+                        s.compliance_limit_samples.into(),
+                    ),
+                    ("charge_recovery_samples", s.charge_recovery_samples.into()),
+                    ("amp_settle_samples", s.amp_settle_samples.into()),
+                ])
+            })
+            .collect();
+
+        Json::object(vec![
+            ("sample_rate_hz", self.header.sample_rate.into()),
+            ("duration_s", self.duration.into()),
+            ("num_samples", self.num_samples.into()),
+            (
+                "gaps",
+                Json::object(vec![
+                    ("num_gaps", self.gaps.num_gaps.into()),
+                    ("missing_samples", self.gaps.missing_samples.into()),
+                    ("num_non_monotonic", self.gaps.num_non_monotonic.into()),
+                ]),
+            ),
+            ("amplifier_uv", signals(&self.amplifier)),
+            ("dc_amplifier_v", signals(&self.dc_amplifier)),
+            ("board_adc_v", signals(&self.board_adc)),
+            ("board_dac_v", signals(&self.board_dac)),
+            ("board_dig_in", digital(&self.board_dig_in)),
+            ("board_dig_out", digital(&self.board_dig_out)),
+            ("stim", Json::Array(stim)),
+        ])
+        .to_string_pretty()
+    }
+}
+
+/// Totals carried from one block to the next
+///
+/// The per-channel lists stay `None` until a block holds data of their type.
+#[derive(Default)]
+struct RunningTotals {
+    num_samples: usize,
+    amplifier: Option<Vec<SignalTotals>>,
+    dc_amplifier: Option<Vec<SignalTotals>>,
+    board_adc: Option<Vec<SignalTotals>>,
+    board_dac: Option<Vec<SignalTotals>>,
+    board_dig_in: Option<Vec<DigitalTotals>>,
+    board_dig_out: Option<Vec<DigitalTotals>>,
+    stim: Option<Vec<StimTotals>>,
+    last_timestamp: Option<i64>,
+    gaps: GapSummary,
+}
+
+#[derive(Clone, Copy)]
+struct SignalTotals {
+    min: f64,
+    max: f64,
+    sum_squares: f64,
+}
+
+impl Default for SignalTotals {
+    fn default() -> Self {
+        SignalTotals {
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum_squares: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct DigitalTotals {
+    last: Option<i32>,
+    rising_edges: usize,
+    falling_edges: usize,
+}
+
+#[derive(Clone, Copy, Default)]
+struct StimTotals {
+    /// Whether the last sample carried current
+    active: bool,
+    num_pulses: usize,
+    max_current: f64,
+    compliance_limit_samples: usize,
+    charge_recovery_samples: usize,
+    amp_settle_samples: usize,
+}
+
+impl RunningTotals {
+    fn add(&mut self, data: &RhsData) {
+        self.num_samples += data.timestamps.len();
+
+        for &timestamp in &data.timestamps {
+            if let Some(last) = self.last_timestamp {
+                let step = timestamp - last;
+                if step > 1 {
+                    self.gaps.num_gaps += 1;
+                    self.gaps.missing_samples += (step - 1) as u64;
+                } else if step < 1 {
+                    self.gaps.num_non_monotonic += 1;
+                }
+            }
+            self.last_timestamp = Some(timestamp);
+        }
+
+        add_signal(&mut self.amplifier, data.amplifier_data.as_ref());
+        add_signal(&mut self.dc_amplifier, data.dc_amplifier_data.as_ref());
+        add_signal(&mut self.board_adc, data.board_adc_data.as_ref());
+        add_signal(&mut self.board_dac, data.board_dac_data.as_ref());
+        add_digital(&mut self.board_dig_in, data.board_dig_in_data.as_ref());
+        add_digital(&mut self.board_dig_out, data.board_dig_out_data.as_ref());
+
+        if let Some(stim_data) = &data.stim_data {
+            let totals = self
+                .stim
+                .get_or_insert_with(|| vec![StimTotals::default(); stim_data.nrows()]);
+            let count = |flags: Option<&StimFlags>, i: usize| flags.map_or(0, |f| f.count(i));
+
+            for (i, (totals, row)) in totals.iter_mut().zip(stim_data.rows()).enumerate() {
+                for &current in row {
+                    let active = current != 0.0;
+                    if active && !totals.active {
+                        totals.num_pulses += 1;
+                    }
+                    totals.active = active;
+                    totals.max_current = totals.max_current.max(current.abs());
+                }
+                totals.compliance_limit_samples += count(data.compliance_limit_data.as_ref(), i);
+                totals.charge_recovery_samples += count(data.charge_recovery_data.as_ref(), i);
+                totals.amp_settle_samples += count(data.amp_settle_data.as_ref(), i);
+            }
+        }
+    }
+
+    fn finish(self, header: RhsHeader) -> RecordingStats {
+        let num_samples = self.num_samples;
+        let signals = |channels: &[ChannelInfo], totals: Option<Vec<SignalTotals>>| {
+            let totals = totals.unwrap_or_default();
+            channels
+                .iter()
+                .zip(totals)
+                .map(|(channel, totals)| SignalStats {
+                    name: channel.custom_channel_name.clone(),
+                    native_name: channel.native_channel_name.clone(),
+                    min: totals.min,
+                    max: totals.max,
+                    rms: (totals.sum_squares / num_samples as f64).sqrt(),
+                })
+                .collect()
+        };
+        let digital = |channels: &[ChannelInfo], totals: Option<Vec<DigitalTotals>>| {
+            let totals = totals.unwrap_or_default();
+            channels
+                .iter()
+                .zip(totals)
+                .map(|(channel, totals)| DigitalStats {
+                    name: channel.custom_channel_name.clone(),
+                    native_name: channel.native_channel_name.clone(),
+                    rising_edges: totals.rising_edges,
+                    falling_edges: totals.falling_edges,
+                })
+                .collect()
+        };
+        let stim = header
+            .amplifier_channels
+            .iter()
+            .zip(self.stim.unwrap_or_default())
+            .map(|(channel, totals)| StimStats {
+                name: channel.custom_channel_name.clone(),
+                native_name: channel.native_channel_name.clone(),
+                num_pulses: totals.num_pulses,
+                max_current: totals.max_current,
+                compliance_limit_samples: totals.compliance_limit_samples,
+                charge_recovery_samples: totals.charge_recovery_samples,
+                amp_settle_samples: totals.amp_settle_samples,
+            })
+            .collect();
+
+        RecordingStats {
+            num_samples,
+            duration: num_samples as f32 / header.sample_rate,
+            amplifier: signals(&header.amplifier_channels, self.amplifier),
+            dc_amplifier: signals(&header.amplifier_channels, self.dc_amplifier),
+            board_adc: signals(&header.board_adc_channels, self.board_adc),
+            board_dac: signals(&header.board_dac_channels, self.board_dac),
+            board_dig_in: digital(&header.board_dig_in_channels, self.board_dig_in),
+            board_dig_out: digital(&header.board_dig_out_channels, self.board_dig_out),
+            stim,
+            gaps: self.gaps,
+            header,
+        }
+    }
+}
+
+fn add_signal(totals: &mut Option<Vec<SignalTotals>>, data: Option<&Array2<f64>>) {
+    let Some(data) = data else {
+        return;
+    };
+    let totals = totals.get_or_insert_with(|| vec![SignalTotals::default(); data.nrows()]);
+    for (totals, row) in totals.iter_mut().zip(data.rows()) {
+        for &value in row {
+            totals.min = totals.min.min(value);
+            totals.max = totals.max.max(value);
+            totals.sum_squares += value * value;
+        }
+    }
+}
+
+fn add_digital(totals: &mut Option<Vec<DigitalTotals>>, data: Option<&Array2<i32>>) {
+    let Some(data) = data else {
+        return;
+    };
+    let totals = totals.get_or_insert_with(|| vec![DigitalTotals::default(); data.nrows()]);
+    for (totals, row) in totals.iter_mut().zip(data.rows()) {
+        for &value in row {
+            match (totals.last, value) {
+                (Some(0), 1) => totals.rising_edges += 1,
+                (Some(1), 0) => totals.falling_edges += 1,
+                _ => {}
+            }
+            totals.last = Some(value);
+        }
+    }
+}
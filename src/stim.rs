@@ -0,0 +1,552 @@
+//! Stimulation pulses as discrete events.
+//!
+//! The stimulation current of each channel is stored sample by sample, while
+//! analyses work with pulses: when each one started and ended, how strong it was,
+//! and the shape of its phases. [`extract_pulses`] turns the current into
+//! [`StimPulse`]s, each made of one or more [`StimPhase`]s of constant sign.
+//! Phases of opposite sign separated by no more than
+//! [`PulseOptions::max_interphase_gap`] of zero current belong to the same pulse,
+//! so a biphasic pulse with an interphase delay is one pulse of two phases.
+//!
+//! Each phase carries the charge it delivered, the integral of its current, and
+//! each pulse its net charge. Pulses whose net charge exceeds
+//! [`PulseOptions::max_charge_imbalance`] of their larger phase charge are flagged
+//! as unbalanced, and [`RhsFile::stim_charge`] totals the charge of every channel
+//! over the recording, as reported for the safety of chronic stimulation.
+//!
+//! The compliance limit, charge recovery, and amplifier settle flags are reported
+//! as [`FlagInterval`]s of consecutive flagged samples by
+//! [`RhsFile::flag_intervals`], and summarized per channel and for the whole
+//! session by [`RhsFile::flag_report`].
+//!
+//! Times are computed from the timestamps, as by
+//! [`RhsFile::time_seconds`], so they stay correct across timestamp gaps and
+//! start at the first timestamp of the recording.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//! use intan_importer::stim::{PulseOptions, PulseShape, StimFlag};
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! for pulse in rhs_file.stim_pulses(&PulseOptions::default()).unwrap() {
+//!     if pulse.shape == PulseShape::Biphasic {
+//!         println!(
+//!             "channel {}: {:.1} μA at {:.4} s, phases of {:?} s",
+//!             pulse.channel,
+//!             pulse.amplitude,
+//!             pulse.onset_time,
+//!             pulse.phase_durations()
+//!         );
+//!     }
+//! }
+//!
+//! for channel in rhs_file.stim_charge(&PulseOptions::default()).unwrap() {
+//!     println!(
+//!         "{}: {:.1} nC delivered, {:.3} nC net, {} unbalanced pulses",
+//!         channel.native_name,
+//!         channel.cathodic_charge + channel.anodic_charge,
+//!         channel.net_charge,
+//!         channel.unbalanced_pulses
+//!     );
+//! }
+//!
+//! let report = rhs_file.flag_report(StimFlag::ComplianceLimit).unwrap();
+//! println!(
+//!     "compliance limit hit {} times on {} channels, for {:.3} s in total",
+//!     report.num_intervals, report.num_channels_affected, report.duration
+//! );
+//! for interval in rhs_file.flag_intervals(StimFlag::AmpSettle).unwrap() {
+//!     println!("channel {}: settle over samples {:?}", interval.channel, interval.samples);
+//! }
+//! ```
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use ndarray::{Array1, Array2};
+
+use crate::flags::StimFlags;
+use crate::types::{ChannelInfo, IntanError, RhsData, RhsFile};
+use crate::units::Unit;
+
+/// Settings of pulse extraction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PulseOptions {
+    /// Longest stretch of zero current between two phases of one pulse (s)
+    pub max_interphase_gap: f64,
+    /// Largest net charge of a balanced pulse, as a fraction of the charge of its
+    /// larger polarity
+    pub max_charge_imbalance: f64,
+}
+
+impl Default for PulseOptions {
+    fn default() -> Self {
+        PulseOptions {
+            max_interphase_gap: 0.001,
+            max_charge_imbalance: 0.05,
+        }
+    }
+}
+
+/// Classification of a pulse by its phases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PulseShape {
+    /// A single phase
+    Monophasic,
+    /// Two phases of opposite sign
+    Biphasic,
+    /// Three or more phases
+    Multiphasic,
+}
+
+impl fmt::Display for PulseShape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            PulseShape::Monophasic => "monophasic",
+            PulseShape::Biphasic => "biphasic",
+            PulseShape::Multiphasic => "multiphasic",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// A stretch of stimulation current of constant sign.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StimPhase {
+    /// Indices of the phase's samples
+    pub samples: Range<usize>,
+    /// Current of largest magnitude within the phase, with its sign (μA)
+    pub amplitude: f64,
+    /// Duration of the phase (s), from the timestamps of its first sample and of
+    /// the sample after its last
+    pub duration: f64,
+    /// Charge delivered during the phase, with the sign of its current (nC)
+    pub charge: f64,
+}
+
+/// One stimulation pulse of a channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StimPulse {
+    /// Row of the channel in the stimulation data
+    pub channel: usize,
+    /// Indices of the samples from the onset of the first phase up to the end of
+    /// the last
+    pub samples: Range<usize>,
+    /// Time of the first sample of the pulse (s), from its timestamp
+    pub onset_time: f64,
+    /// Time of the sample after the pulse (s), from its timestamp
+    pub offset_time: f64,
+    /// Largest current magnitude of the pulse (μA)
+    pub amplitude: f64,
+    /// Phases of the pulse, in order
+    pub phases: Vec<StimPhase>,
+    /// Classification of the pulse by its phases
+    pub shape: PulseShape,
+    /// Sum of the charges of the phases (nC), zero for a balanced pulse
+    pub net_charge: f64,
+    /// Whether the net charge is within [`PulseOptions::max_charge_imbalance`]
+    pub charge_balanced: bool,
+}
+
+/// Stimulation charge of one channel over a recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelCharge {
+    /// Row of the channel in the stimulation data
+    pub channel: usize,
+    /// Native channel name
+    pub native_name: String,
+    /// Number of pulses
+    pub num_pulses: usize,
+    /// Total magnitude of the charge of the negative phases (nC)
+    pub cathodic_charge: f64,
+    /// Total charge of the positive phases (nC)
+    pub anodic_charge: f64,
+    /// Cumulative net charge of all pulses (nC)
+    pub net_charge: f64,
+    /// Number of pulses flagged as not charge balanced
+    pub unbalanced_pulses: usize,
+}
+
+impl StimPulse {
+    /// Returns the duration of each phase (s).
+    pub fn phase_durations(&self) -> Vec<f64> {
+        self.phases.iter().map(|phase| phase.duration).collect()
+    }
+
+    /// Returns the duration of the pulse (s).
+    pub fn duration(&self) -> f64 {
+        self.offset_time - self.onset_time
+    }
+
+    /// Returns the net charge as a fraction of the charge of the pulse's larger
+    /// polarity: 0 for a balanced pulse and 1 for a monophasic one.
+    pub fn charge_imbalance(&self) -> f64 {
+        let polarity = |negative: bool| -> f64 {
+            self.phases
+                .iter()
+                .filter(|phase| (phase.charge < 0.0) == negative)
+                .map(|phase| phase.charge.abs())
+                .sum()
+        };
+        let larger = polarity(true).max(polarity(false));
+        if larger > 0.0 {
+            self.net_charge.abs() / larger
+        } else {
+            0.0
+        }
+    }
+}
+
+/// One of the status flags recorded with every stimulation sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StimFlag {
+    /// The stimulator reached its compliance limit
+    ComplianceLimit,
+    /// Charge recovery was active
+    ChargeRecovery,
+    /// Amplifier settle was active
+    AmpSettle,
+}
+
+impl StimFlag {
+    /// Returns the flag's data, if loaded.
+    pub fn data<'a>(&self, data: &'a RhsData) -> Option<&'a StimFlags> {
+        match self {
+            StimFlag::ComplianceLimit => data.compliance_limit_data.as_ref(),
+            StimFlag::ChargeRecovery => data.charge_recovery_data.as_ref(),
+            StimFlag::AmpSettle => data.amp_settle_data.as_ref(),
+        }
+    }
+}
+
+impl fmt::Display for StimFlag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            StimFlag::ComplianceLimit => "compliance limit",
+            StimFlag::ChargeRecovery => "charge recovery",
+            StimFlag::AmpSettle => "amplifier settle",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// Consecutive samples of a channel during which a stimulation flag was set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlagInterval {
+    /// Row of the channel in the flag data
+    pub channel: usize,
+    /// Indices of the flagged samples
+    pub samples: Range<usize>,
+    /// Time of the first flagged sample (s), from its timestamp
+    pub start_time: f64,
+    /// Time of the sample after the interval (s), from its timestamp
+    pub end_time: f64,
+}
+
+/// Statistics of a stimulation flag on one channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelFlagStats {
+    /// Row of the channel in the flag data
+    pub channel: usize,
+    /// Native channel name
+    pub native_name: String,
+    /// Number of intervals during which the flag was set
+    pub num_intervals: usize,
+    /// Number of flagged samples
+    pub num_samples: usize,
+    /// Total duration of the intervals (s)
+    pub duration: f64,
+    /// Duration of the longest interval (s)
+    pub longest: f64,
+    /// Fraction of the recording's samples that were flagged
+    pub fraction: f64,
+}
+
+/// Statistics of a stimulation flag over a recording, for session QC.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlagReport {
+    /// The flag reported on
+    pub flag: StimFlag,
+    /// Statistics of every amplifier channel
+    pub channels: Vec<ChannelFlagStats>,
+    /// Number of intervals over all channels
+    pub num_intervals: usize,
+    /// Number of channels on which the flag was set at least once
+    pub num_channels_affected: usize,
+    /// Total duration of the intervals over all channels (s)
+    pub duration: f64,
+}
+
+/// Finds the stimulation pulses of every channel, ordered by channel and then by
+/// onset.
+///
+/// Currents are reported in μA and charges in nC whatever the unit the data was
+/// loaded in. A phase's charge is the sum of its currents times the sample period.
+/// `sample_rate` converts timestamps to seconds, normally the
+/// [`sample_rate`](crate::RhsHeader::sample_rate) of the header. The time after
+/// the last sample of the recording is extrapolated from the sample rate.
+///
+/// # Errors
+///
+/// Returns an error if no stimulation data is loaded.
+pub fn extract_pulses(
+    data: &RhsData,
+    sample_rate: f32,
+    options: &PulseOptions,
+) -> Result<Vec<StimPulse>, Box<dyn Error>> {
+    // Amplitudes are in μA, so convert stimulation data that was rescaled
+    let stim: Cow<Array2<f64>> = match &data.stim_data {
+        Some(stim) if data.units.stim == Unit::Microamps => Cow::Borrowed(stim),
+        Some(_) => Cow::Owned(data.stim_in_ua().ok_or_else(|| {
+            IntanError::Other("Stimulation data could not be converted to μA".to_string())
+        })?),
+        None => {
+            return Err(Box::new(IntanError::Other(
+                "No stimulation data is loaded".to_string(),
+            )))
+        }
+    };
+
+    let sample_rate = sample_rate as f64;
+    let timestamps = &data.timestamps;
+    let time = |sample: usize| time_at(timestamps, sample, sample_rate);
+    let max_gap = (options.max_interphase_gap * sample_rate).round().max(0.0) as usize;
+
+    let mut pulses = Vec::new();
+    for (channel, row) in stim.rows().into_iter().enumerate() {
+        let mut phases: Vec<StimPhase> = Vec::new();
+        let mut finish = |phases: &mut Vec<StimPhase>| {
+            if phases.is_empty() {
+                return;
+            }
+            let phases = std::mem::take(phases);
+            let samples = phases[0].samples.start..phases[phases.len() - 1].samples.end;
+            let mut pulse = StimPulse {
+                channel,
+                onset_time: time(samples.start),
+                offset_time: time(samples.end),
+                samples,
+                amplitude: phases.iter().map(|p| p.amplitude.abs()).fold(0.0, f64::max),
+                shape: match phases.len() {
+                    1 => PulseShape::Monophasic,
+                    2 => PulseShape::Biphasic,
+                    _ => PulseShape::Multiphasic,
+                },
+                net_charge: phases.iter().map(|p| p.charge).sum(),
+                phases,
+                charge_balanced: true,
+            };
+            pulse.charge_balanced = pulse.charge_imbalance() <= options.max_charge_imbalance;
+            pulses.push(pulse);
+        };
+
+        let mut sample = 0;
+        while sample < row.len() {
+            if row[sample] == 0.0 {
+                sample += 1;
+                continue;
+            }
+            let (start, sign) = (sample, row[sample].signum());
+            let (mut amplitude, mut sum) = (0.0_f64, 0.0);
+            while sample < row.len() && row[sample] != 0.0 && row[sample].signum() == sign {
+                if row[sample].abs() > amplitude.abs() {
+                    amplitude = row[sample];
+                }
+                sum += row[sample];
+                sample += 1;
+            }
+
+            // Phases of the same sign are separate pulses even when close together
+            let continues = phases.last().is_some_and(|last| {
+                last.amplitude.signum() != sign && start - last.samples.end <= max_gap
+            });
+            if !continues {
+                finish(&mut phases);
+            }
+            phases.push(StimPhase {
+                samples: start..sample,
+                amplitude,
+                duration: time(sample) - time(start),
+                // μA times s is μC
+                charge: sum / sample_rate * 1e3,
+            });
+        }
+        finish(&mut phases);
+    }
+    Ok(pulses)
+}
+
+/// Returns the intervals during which `flag` was set, ordered by channel and then
+/// by start.
+///
+/// `sample_rate` converts timestamps to seconds, as for [`extract_pulses`].
+///
+/// # Errors
+///
+/// Returns an error if the flag's data is not loaded.
+pub fn flag_intervals(
+    data: &RhsData,
+    flag: StimFlag,
+    sample_rate: f32,
+) -> Result<Vec<FlagInterval>, Box<dyn Error>> {
+    let flags = flag
+        .data(data)
+        .ok_or_else(|| IntanError::Other(format!("No {} data is loaded", flag)))?;
+    let sample_rate = sample_rate as f64;
+    let mut intervals = Vec::new();
+    for channel in 0..flags.num_channels() {
+        intervals.extend(flags.runs(channel).iter().map(|run| FlagInterval {
+            channel,
+            samples: run.clone(),
+            start_time: time_at(&data.timestamps, run.start, sample_rate),
+            end_time: time_at(&data.timestamps, run.end, sample_rate),
+        }));
+    }
+    Ok(intervals)
+}
+
+/// Totals the charge of `pulses` for each of `channels`, the amplifier channels
+/// whose rows the pulses' channel indices refer to.
+pub fn charge_by_channel(pulses: &[StimPulse], channels: &[ChannelInfo]) -> Vec<ChannelCharge> {
+    let mut totals: Vec<ChannelCharge> = channels
+        .iter()
+        .enumerate()
+        .map(|(channel, info)| ChannelCharge {
+            channel,
+            native_name: info.native_channel_name.clone(),
+            num_pulses: 0,
+            cathodic_charge: 0.0,
+            anodic_charge: 0.0,
+            net_charge: 0.0,
+            unbalanced_pulses: 0,
+        })
+        .collect();
+    for pulse in pulses {
+        let Some(total) = totals.get_mut(pulse.channel) else {
+            continue;
+        };
+        total.num_pulses += 1;
+        for phase in &pulse.phases {
+            if phase.charge < 0.0 {
+                total.cathodic_charge -= phase.charge;
+            } else {
+                total.anodic_charge += phase.charge;
+            }
+        }
+        total.net_charge += pulse.net_charge;
+        total.unbalanced_pulses += usize::from(!pulse.charge_balanced);
+    }
+    totals
+}
+
+impl RhsFile {
+    /// Finds the stimulation pulses of every channel, with times from the header's
+    /// sample rate.
+    ///
+    /// See [`extract_pulses`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no stimulation data is loaded.
+    pub fn stim_pulses(&self, options: &PulseOptions) -> Result<Vec<StimPulse>, Box<dyn Error>> {
+        let data = self
+            .data
+            .as_ref()
+            .ok_or_else(|| IntanError::Other("The recording has no data".to_string()))?;
+        extract_pulses(data, self.header.sample_rate, options)
+    }
+
+    /// Totals the stimulation charge of every amplifier channel over the
+    /// recording.
+    ///
+    /// See [`extract_pulses`] and [`charge_by_channel`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no stimulation data is loaded.
+    pub fn stim_charge(
+        &self,
+        options: &PulseOptions,
+    ) -> Result<Vec<ChannelCharge>, Box<dyn Error>> {
+        let pulses = self.stim_pulses(options)?;
+        Ok(charge_by_channel(&pulses, &self.header.amplifier_channels))
+    }
+
+    /// Returns the intervals during which `flag` was set, with times from the
+    /// header's sample rate.
+    ///
+    /// See [`flag_intervals`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the flag's data is not loaded.
+    pub fn flag_intervals(&self, flag: StimFlag) -> Result<Vec<FlagInterval>, Box<dyn Error>> {
+        let data = self
+            .data
+            .as_ref()
+            .ok_or_else(|| IntanError::Other("The recording has no data".to_string()))?;
+        flag_intervals(data, flag, self.header.sample_rate)
+    }
+
+    /// Summarizes the intervals during which `flag` was set, per amplifier channel
+    /// and over all channels.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the flag's data is not loaded.
+    pub fn flag_report(&self, flag: StimFlag) -> Result<FlagReport, Box<dyn Error>> {
+        let intervals = self.flag_intervals(flag)?;
+        let num_samples = self.num_samples();
+        let mut channels: Vec<ChannelFlagStats> = self
+            .header
+            .amplifier_channels
+            .iter()
+            .enumerate()
+            .map(|(channel, info)| ChannelFlagStats {
+                channel,
+                native_name: info.native_channel_name.clone(),
+                num_intervals: 0,
+                num_samples: 0,
+                duration: 0.0,
+                longest: 0.0,
+                fraction: 0.0,
+            })
+            .collect();
+        for interval in &intervals {
+            let Some(stats) = channels.get_mut(interval.channel) else {
+                continue;
+            };
+            let duration = interval.end_time - interval.start_time;
+            stats.num_intervals += 1;
+            stats.num_samples += interval.samples.len();
+            stats.duration += duration;
+            stats.longest = stats.longest.max(duration);
+        }
+        for stats in &mut channels {
+            if num_samples > 0 {
+                stats.fraction = stats.num_samples as f64 / num_samples as f64;
+            }
+        }
+
+        Ok(FlagReport {
+            flag,
+            num_intervals: intervals.len(),
+            num_channels_affected: channels.iter().filter(|c| c.num_intervals > 0).count(),
+            duration: channels.iter().map(|c| c.duration).sum(),
+            channels,
+        })
+    }
+}
+
+/// Time in seconds of `sample` from its timestamp, or for the sample after the
+/// last, one sample period after the last timestamp
+fn time_at(timestamps: &Array1<i64>, sample: usize, sample_rate: f64) -> f64 {
+    match timestamps.get(sample) {
+        Some(&timestamp) => timestamp as f64 / sample_rate,
+        None => (timestamps[sample - 1] + 1) as f64 / sample_rate,
+    }
+}
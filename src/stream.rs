@@ -0,0 +1,405 @@
+//! Streaming reads of RHS files, one data block at a time.
+//!
+//! [`load`](crate::load) keeps a whole recording in memory. [`RhsBlockReader`]
+//! instead reads a file one 128-sample data block at a time, so pipelines can
+//! process recordings of any length with constant memory. Each [`DataBlock`] holds
+//! the timestamps and scaled signals of its samples, exactly as they would appear
+//! in the loaded data.
+//!
+//! The reader accepts the same [`LoadOptions`] as [`Loader`](crate::Loader):
+//! a channel selection skips the samples of other channels, a time range seeks
+//! to its first block, the notch filter is applied across block boundaries, and
+//! progress is reported after every block. Butterworth filters added with
+//! [`RhsBlockReader::with_filter`] run on every block after the notch filter.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::stream::RhsBlockReader;
+//!
+//! let mut max_abs = 0.0f64;
+//! for block in RhsBlockReader::open("recording.rhs").unwrap() {
+//!     let block = block.unwrap();
+//!     if let Some(amp) = &block.data.amplifier_data {
+//!         max_abs = amp.iter().fold(max_abs, |m, &v| m.max(v.abs()));
+//!     }
+//! }
+//! println!("Largest amplifier sample: {:.1} μV", max_abs);
+//! ```
+//!
+//! [`load_chunked`] regroups the blocks into chunks of any size and passes each
+//! one to a callback, for converters that write data out in their own block size:
+//!
+//! ```no_run
+//! use intan_importer::stream::{load_chunked, RhsDataChunk};
+//!
+//! // One second of data at a time
+//! load_chunked("recording.rhs", 30_000, |chunk: RhsDataChunk| {
+//!     println!("chunk {}: {} samples", chunk.index, chunk.data.num_samples());
+//!     Ok(())
+//! })
+//! .unwrap();
+//! ```
+
+use std::error::Error;
+use std::io::{Read, Seek};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::chunk::{ChunkAlignment, OwnedChunk, Rechunker};
+use crate::datetime::DateTime;
+use crate::dsp::{ButterworthFilter, SignalFilter};
+use crate::file_reader;
+pub use crate::file_reader::FileReader;
+use crate::loader::{LoadOptions, LoadPhase, LoadProgress, NotchMode, ProgressCallback};
+use crate::reader::{self, BlockNotchFilter, ChannelRows, SAMPLES_PER_DATA_BLOCK};
+use crate::signal::SignalType;
+use crate::types::{IntanError, LoadWarning, RhsData, RhsHeader};
+
+/// A data block read by [`RhsBlockReader`].
+#[derive(Debug, Clone)]
+pub struct DataBlock {
+    /// Index of this block among the blocks read (0-based)
+    pub index: usize,
+    /// Index of the first sample of this block among the samples read
+    pub sample_offset: usize,
+    /// The block's data.
+    ///
+    /// Blocks hold 128 samples, except the first and last blocks of a time range,
+    /// which are trimmed to the range.
+    pub data: RhsData,
+}
+
+/// Iterator over the data blocks of an RHS file.
+///
+/// See the [module documentation](crate::stream) for an overview.
+pub struct RhsBlockReader<R = FileReader> {
+    reader: R,
+    /// Path of the file, named in the errors of the blocks
+    path: Option<PathBuf>,
+    file_size: u64,
+    /// Header as stored in the file, describing the layout of the data blocks
+    file_header: RhsHeader,
+    /// Header listing only the selected channels
+    header: RhsHeader,
+    rows: Option<ChannelRows>,
+    /// Options used to process each block, with printing, progress reports, and
+    /// the whole-signal notch filter turned off
+    block_options: LoadOptions,
+    progress: Option<Arc<dyn ProgressCallback>>,
+    notch: Option<BlockNotchFilter>,
+    filters: Vec<SignalFilter>,
+    samples: Range<usize>,
+    /// Data blocks of the file still to be read
+    blocks: Range<u64>,
+    num_file_blocks: u64,
+    index: usize,
+    sample_offset: usize,
+    /// Last timestamp yielded, from which the next block's timestamps are unwrapped
+    last_timestamp: Option<i64>,
+    failed: bool,
+    /// Warnings of lenient header parsing
+    warnings: Vec<LoadWarning>,
+}
+
+impl RhsBlockReader {
+    /// Opens an RHS file for reading with the default options.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened or has an invalid header.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, IntanError> {
+        RhsBlockReader::with_options(path, &LoadOptions::default())
+    }
+
+    /// Opens an RHS file for reading with the given options.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened or has an invalid header, if
+    /// a selected channel does not exist, or if the time range is invalid or lies
+    /// outside the recording. Errors that concern the file, including those of the
+    /// blocks, are [`IntanError::File`] errors naming it.
+    pub fn with_options<P: AsRef<Path>>(
+        path: P,
+        options: &LoadOptions,
+    ) -> Result<Self, IntanError> {
+        let path = path.as_ref();
+        options.check()?;
+        let options = reader::resolve_wallclock_range(options, DateTime::from_rhx_filename(path))
+            .map_err(|e| e.in_file(path, None))?;
+        let (reader, file_size) = file_reader::open(path, &options.io)
+            .map_err(|e| IntanError::from(e).in_file(path, None))?;
+        let mut block_reader = RhsBlockReader::from_reader(reader, file_size, &options)
+            .map_err(|e| e.in_file(path, None))?;
+        reader::attribute_warnings(&mut block_reader.warnings, path);
+        block_reader.path = Some(path.to_path_buf());
+        Ok(block_reader)
+    }
+}
+
+impl<R: Read + Seek> RhsBlockReader<R> {
+    /// Starts reading an RHS file of `file_size` bytes from any seekable source,
+    /// positioned at the start of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`RhsBlockReader::with_options`].
+    pub fn from_reader(
+        mut reader: R,
+        file_size: u64,
+        options: &LoadOptions,
+    ) -> Result<Self, IntanError> {
+        options.check()?;
+        let options = &*reader::resolve_wallclock_range(options, None)?;
+
+        let (file_header, warnings) =
+            reader::read_header_with_mode(&mut reader, options.parse_mode)?;
+        if options.verbose {
+            reader::log_header_summary(&file_header);
+        }
+        let (data_present, num_file_blocks, num_samples) =
+            reader::calculate_data_size(&file_header, file_size, options, &mut reader)?;
+
+        let mut header = file_header.clone();
+        let rows = reader::select_channels(&mut header, options)?;
+        let samples = reader::selected_samples(options, header.sample_rate, 0, num_samples)?;
+        if data_present && samples.is_empty() {
+            return Err(reader::time_range_error(options, num_samples, header.sample_rate));
+        }
+
+        // Seek to the block holding the first selected sample
+        let blocks = reader::selected_blocks(&samples);
+        let last_timestamp = reader::skip_to_block(&mut reader, &file_header, blocks.start)?;
+
+        if let Some(callback) = &options.progress {
+            callback.report(&LoadProgress {
+                phase: LoadPhase::Header,
+                bytes_read: reader.stream_position()?,
+                blocks_parsed: 0,
+                total_blocks: blocks.end - blocks.start,
+                channels_filtered: 0,
+                total_channels: 0,
+            });
+        }
+
+        let notch = BlockNotchFilter::new(&header, options)?;
+
+        Ok(RhsBlockReader {
+            reader,
+            path: None,
+            file_size,
+            file_header,
+            header,
+            rows,
+            block_options: LoadOptions {
+                verbose: false,
+                notch: NotchMode::Skip,
+                progress: None,
+                ..options.clone()
+            },
+            progress: options.progress.clone(),
+            notch,
+            filters: Vec::new(),
+            samples,
+            blocks,
+            num_file_blocks,
+            index: 0,
+            sample_offset: 0,
+            last_timestamp,
+            failed: false,
+            warnings,
+        })
+    }
+
+    /// Adds a Butterworth filter of the amplifier or board ADC data, applied to
+    /// every block after the notch filter.
+    ///
+    /// The filter state carries over from block to block, so the blocks hold the
+    /// same values as filtering the whole signal at once. Filters added for the
+    /// same signal are applied in the order they were added.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `signal` cannot be filtered or the filter is not valid
+    /// at the recording's sample rate.
+    pub fn with_filter(
+        mut self,
+        signal: SignalType,
+        filter: ButterworthFilter,
+    ) -> Result<Self, IntanError> {
+        let mut signal_filter = SignalFilter::new(signal, filter);
+        signal_filter.prepare(&self.header)?;
+        self.filters.push(signal_filter);
+        Ok(self)
+    }
+
+    /// Returns the header, listing only the selected channels.
+    pub fn header(&self) -> &RhsHeader {
+        &self.header
+    }
+
+    /// Returns the warnings of lenient header parsing, as listed in
+    /// [`RhsFile::warnings`](crate::RhsFile::warnings).
+    ///
+    /// Their paths are empty for readers started with
+    /// [`from_reader`](RhsBlockReader::from_reader).
+    pub fn warnings(&self) -> &[LoadWarning] {
+        &self.warnings
+    }
+
+    /// Returns the number of samples the reader yields in total.
+    pub fn num_samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns the number of blocks not yet read.
+    pub fn remaining_blocks(&self) -> usize {
+        if self.failed {
+            0
+        } else {
+            (self.blocks.end - self.blocks.start) as usize
+        }
+    }
+
+    fn read_next_block(&mut self, block: u64) -> Result<DataBlock, Box<dyn Error>> {
+        let raw_data = reader::read_data_blocks(
+            &self.file_header,
+            self.rows.as_ref(),
+            1,
+            &self.block_options,
+            &mut self.reader,
+        )?;
+        if block + 1 == self.num_file_blocks {
+            reader::check_end_of_file(self.file_size, &mut self.reader)?;
+        }
+
+        // Trim the first and last blocks to the selected samples
+        let block_start = block as usize * SAMPLES_PER_DATA_BLOCK;
+        let start = self.samples.start.max(block_start) - block_start;
+        let end = self.samples.end.min(block_start + SAMPLES_PER_DATA_BLOCK) - block_start;
+        let raw_data = reader::select_raw_data(raw_data, None, start..end);
+
+        let mut data = reader::process_data(&self.header, raw_data, &self.block_options)?;
+        reader::unwrap_timestamps(data.timestamps.view_mut(), self.last_timestamp);
+        self.last_timestamp = data.timestamps.last().copied();
+        if let Some(notch) = &mut self.notch {
+            if let Some(amp_data) = &mut data.amplifier_data {
+                notch.filter(amp_data);
+            }
+            if let Some(amp_data) = &mut data.amplifier_data_f32 {
+                notch.filter(amp_data);
+            }
+        }
+        for filter in &mut self.filters {
+            filter.process_data(&mut data)?;
+        }
+
+        let data_block = DataBlock {
+            index: self.index,
+            sample_offset: self.sample_offset,
+            data,
+        };
+        self.index += 1;
+        self.sample_offset += end - start;
+
+        if let Some(callback) = &self.progress {
+            callback.report(&LoadProgress {
+                phase: LoadPhase::Data,
+                bytes_read: self.reader.stream_position()?,
+                blocks_parsed: self.index as u64,
+                total_blocks: self.index as u64 + self.remaining_blocks() as u64,
+                channels_filtered: 0,
+                total_channels: 0,
+            });
+        }
+        Ok(data_block)
+    }
+}
+
+impl<R: Read + Seek> Iterator for RhsBlockReader<R> {
+    type Item = Result<DataBlock, IntanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let block = self.blocks.next()?;
+
+        let result = self.read_next_block(block);
+        // The position within the file is unknown after an error
+        self.failed = result.is_err();
+        Some(result.map_err(|e| match &self.path {
+            Some(path) => reader::file_error(path, &mut self.reader, e),
+            None => e.into(),
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.remaining_blocks();
+        (remaining, Some(remaining))
+    }
+}
+
+/// A chunk of scaled data passed to the [`load_chunked`] callback.
+pub type RhsDataChunk = OwnedChunk;
+
+/// Reads an RHS file in chunks of `chunk_samples` samples, passing each chunk to
+/// `callback` as soon as it is complete.
+///
+/// Only the data of the current chunk is kept in memory. Chunks start at the first
+/// sample and hold `chunk_samples` samples each, except the last, which holds the
+/// remainder. Returns the file's header once every chunk has been processed.
+///
+/// # Errors
+///
+/// Returns the errors of [`RhsBlockReader::open`], any error reading the data
+/// blocks, and the first error returned by `callback`, which stops the read.
+///
+/// # Panics
+///
+/// Panics if `chunk_samples` is zero.
+pub fn load_chunked<P, F>(
+    path: P,
+    chunk_samples: usize,
+    callback: F,
+) -> Result<RhsHeader, IntanError>
+where
+    P: AsRef<Path>,
+    F: FnMut(RhsDataChunk) -> Result<(), Box<dyn Error>>,
+{
+    load_chunked_with_options(path, chunk_samples, &LoadOptions::default(), callback)
+}
+
+/// Reads an RHS file in chunks like [`load_chunked`], with the channels, time
+/// range, and other settings of `options`.
+///
+/// The returned header lists only the selected channels.
+///
+/// # Errors
+///
+/// Returns the errors of [`RhsBlockReader::with_options`], any error reading the
+/// data blocks, and the first error returned by `callback`.
+///
+/// # Panics
+///
+/// Panics if `chunk_samples` is zero.
+pub fn load_chunked_with_options<P, F>(
+    path: P,
+    chunk_samples: usize,
+    options: &LoadOptions,
+    callback: F,
+) -> Result<RhsHeader, IntanError>
+where
+    P: AsRef<Path>,
+    F: FnMut(RhsDataChunk) -> Result<(), Box<dyn Error>>,
+{
+    let mut rechunker = Rechunker::new(chunk_samples, ChunkAlignment::Start, callback);
+    let mut blocks = RhsBlockReader::with_options(path, options)?;
+    for block in blocks.by_ref() {
+        rechunker.push(block?.data.view())?;
+    }
+    rechunker.flush()?;
+    Ok(blocks.header)
+}
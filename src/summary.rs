@@ -0,0 +1,201 @@
+//! Human-readable summaries of recordings.
+//!
+//! [`RhsHeader::summary`] and [`RhsFile::summary`] collect what is usually checked
+//! first about a recording, namely the file version, duration, channel counts,
+//! filter settings, and notes, into a [`RecordingSummary`]. Its fields can be
+//! inspected directly, and its `Display` implementation prints one line per item,
+//! as logged when a file is loaded. `RhsHeader` and `RhsFile` display as their
+//! summaries.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! println!("{}", rhs_file);
+//!
+//! let summary = rhs_file.summary();
+//! if summary.notch_filter_frequency.is_none() {
+//!     println!("Recorded without a notch filter");
+//! }
+//! ```
+
+use std::fmt;
+
+use crate::types::{Notes, RhsFile, RhsHeader, Version};
+
+/// Summary of a recording's header and, for loaded files, its duration.
+#[derive(Debug, Clone)]
+pub struct RecordingSummary {
+    /// File format version
+    pub version: Version,
+    /// Amplifier sample rate (Hz)
+    pub sample_rate: f32,
+    /// Number of samples per channel, or `None` for a summary of a header alone
+    pub num_samples: Option<usize>,
+    /// Duration of the samples (s), or `None` for a summary of a header alone
+    pub duration: Option<f32>,
+    /// Number of amplifier channels
+    pub num_amplifier_channels: usize,
+    /// Whether DC amplifier data was saved
+    pub dc_amplifier_data_saved: bool,
+    /// Number of board ADC channels
+    pub num_board_adc_channels: usize,
+    /// Number of board DAC channels
+    pub num_board_dac_channels: usize,
+    /// Number of board digital input channels
+    pub num_board_dig_in_channels: usize,
+    /// Number of board digital output channels
+    pub num_board_dig_out_channels: usize,
+    /// Actual lower bandwidth of the amplifiers (Hz)
+    pub lower_bandwidth: f32,
+    /// Actual lower bandwidth of the amplifiers while they settle (Hz)
+    pub lower_settle_bandwidth: f32,
+    /// Actual upper bandwidth of the amplifiers (Hz)
+    pub upper_bandwidth: f32,
+    /// Actual cutoff frequency of the DSP offset removal filter (Hz), or `None` if
+    /// it was disabled
+    pub dsp_cutoff_frequency: Option<f32>,
+    /// Notch filter frequency (Hz), or `None` if the notch filter was disabled
+    pub notch_filter_frequency: Option<i32>,
+    /// Notes saved with the recording
+    pub notes: Notes,
+}
+
+impl fmt::Display for RecordingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Intan Technologies RHS Data File, Version {}.{}",
+            self.version.major, self.version.minor
+        )?;
+        match (self.num_samples, self.duration) {
+            (Some(0), _) => writeln!(
+                f,
+                "No data. Amplifiers were sampled at {:.2} kS/s.",
+                self.sample_rate / 1000.0
+            )?,
+            (Some(num_samples), Some(duration)) => writeln!(
+                f,
+                "{:.3} seconds of data ({} samples). Amplifiers were sampled at {:.2} kS/s.",
+                duration,
+                num_samples,
+                self.sample_rate / 1000.0
+            )?,
+            _ => {}
+        }
+
+        let mut count = |n: usize, what: &str| {
+            writeln!(
+                f,
+                "Found {} {} channel{}.",
+                n,
+                what,
+                if n != 1 { "s" } else { "" }
+            )
+        };
+        count(self.num_amplifier_channels, "amplifier")?;
+        if self.dc_amplifier_data_saved {
+            count(self.num_amplifier_channels, "DC amplifier")?;
+        }
+        count(self.num_board_adc_channels, "board ADC")?;
+        count(self.num_board_dac_channels, "board DAC")?;
+        count(self.num_board_dig_in_channels, "board digital input")?;
+        count(self.num_board_dig_out_channels, "board digital output")?;
+
+        write!(
+            f,
+            "Amplifier bandwidth: {:.2} Hz to {:.0} Hz ({:.0} Hz while settling).",
+            self.lower_bandwidth, self.upper_bandwidth, self.lower_settle_bandwidth
+        )?;
+        match self.dsp_cutoff_frequency {
+            Some(cutoff) => write!(f, "\nDSP offset removal: {:.2} Hz.", cutoff)?,
+            None => write!(f, "\nDSP offset removal: disabled.")?,
+        }
+        match self.notch_filter_frequency {
+            Some(frequency) => write!(f, "\nNotch filter: {} Hz.", frequency)?,
+            None => write!(f, "\nNotch filter: disabled.")?,
+        }
+
+        let notes = [&self.notes.note1, &self.notes.note2, &self.notes.note3];
+        for (i, note) in notes.into_iter().enumerate() {
+            if !note.is_empty() {
+                write!(f, "\nNote {}: {}", i + 1, note)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RhsHeader {
+    /// Returns a summary of the file version, channel counts, filter settings, and
+    /// notes.
+    ///
+    /// Its `Display` output, one line per item, is the summary logged when a file
+    /// is loaded.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use intan_importer::load;
+    ///
+    /// let rhs_file = load("path/to/your/file.rhs").unwrap();
+    /// println!("{}", rhs_file.header.summary());
+    /// ```
+    pub fn summary(&self) -> RecordingSummary {
+        let frequency_parameters = &self.frequency_parameters;
+        RecordingSummary {
+            version: self.version.clone(),
+            sample_rate: self.sample_rate,
+            num_samples: None,
+            duration: None,
+            num_amplifier_channels: self.amplifier_channels.len(),
+            dc_amplifier_data_saved: self.dc_amplifier_data_saved,
+            num_board_adc_channels: self.board_adc_channels.len(),
+            num_board_dac_channels: self.board_dac_channels.len(),
+            num_board_dig_in_channels: self.board_dig_in_channels.len(),
+            num_board_dig_out_channels: self.board_dig_out_channels.len(),
+            lower_bandwidth: frequency_parameters.actual_lower_bandwidth,
+            lower_settle_bandwidth: frequency_parameters.actual_lower_settle_bandwidth,
+            upper_bandwidth: frequency_parameters.actual_upper_bandwidth,
+            dsp_cutoff_frequency: (frequency_parameters.dsp_enabled != 0)
+                .then_some(frequency_parameters.actual_dsp_cutoff_frequency),
+            notch_filter_frequency: frequency_parameters.notch_filter_frequency,
+            notes: self.notes.clone(),
+        }
+    }
+}
+
+impl fmt::Display for RhsHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.summary().fmt(f)
+    }
+}
+
+impl RhsFile {
+    /// Returns a summary of the header and the duration of the loaded data.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use intan_importer::load;
+    ///
+    /// let rhs_file = load("path/to/your/file.rhs").unwrap();
+    /// let summary = rhs_file.summary();
+    /// println!("{:.1} s of data", summary.duration.unwrap());
+    /// ```
+    pub fn summary(&self) -> RecordingSummary {
+        RecordingSummary {
+            num_samples: Some(self.num_samples()),
+            duration: Some(self.duration()),
+            ..self.header.summary()
+        }
+    }
+}
+
+impl fmt::Display for RhsFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.summary().fmt(f)
+    }
+}
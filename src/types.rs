@@ -1,12 +1,26 @@
-use ndarray::{Array1, Array2};
+use ndarray::{s, Array1, Array2, ArrayView1, ArrayView2};
 use std::error::Error;
 use std::fmt;
 use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::datetime::DateTime;
+use crate::fingerprint::FileFingerprint;
+use crate::flags::{StimFlags, StimFlagsView};
+use crate::loader::ChannelSelector;
+use crate::quality::QualityIssue;
+use crate::reader;
+use crate::rhd::RhdFile;
+use crate::settings::SettingsXml;
+use crate::spill::SpilledSignals;
+use crate::units::SignalUnits;
 
 /// Version information for the RHS file.
 ///
 /// Contains major and minor version numbers for the file format.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Version {
     /// Major version number
     pub major: i32,
@@ -19,6 +33,7 @@ pub struct Version {
 /// Intan recording software allows up to three notes to be stored with each recording.
 /// These are typically used to document experimental conditions or other metadata.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Notes {
     /// First note text
     pub note1: String,
@@ -34,6 +49,7 @@ pub struct Notes {
 /// Includes both the originally requested values ("desired_*") and the actual
 /// values that were achieved by the hardware ("actual_*").
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FrequencyParameters {
     /// Sample rate for amplifier channels (Hz)
     pub amplifier_sample_rate: f32,
@@ -72,6 +88,7 @@ pub struct FrequencyParameters {
 /// Contains settings related to electrical stimulation, which is a feature
 /// of some Intan recording systems.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StimParameters {
     /// Stimulation current step size (μA)
     pub stim_step_size: f32,
@@ -94,6 +111,7 @@ pub struct StimParameters {
 /// Contains naming, ordering, and hardware configuration for a single recording channel.
 /// This includes amplifier channels, ADC channels, digital inputs, etc.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ChannelInfo {
     /// Name of the port (e.g., "Port A")
     pub port_name: String,
@@ -111,6 +129,9 @@ pub struct ChannelInfo {
     pub custom_order: i32,
     /// Channel on the chip
     pub chip_channel: i32,
+    /// Command stream of the channel, written by RHX and ignored by the official
+    /// readers; 0 for RHD files, which have no such field
+    pub command_stream: i32,
     /// Hardware stream on the board
     pub board_stream: i32,
     /// Measured electrode impedance magnitude (Ω)
@@ -123,6 +144,7 @@ pub struct ChannelInfo {
 ///
 /// Contains settings for spike detection triggers.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SpikeTrigger {
     /// Voltage trigger mode
     /// - 0: Trigger on digital input
@@ -144,6 +166,7 @@ pub struct SpikeTrigger {
 /// This includes version information, sampling rates, filter settings, channel
 /// configurations, and more.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RhsHeader {
     /// File format version
     pub version: Version,
@@ -218,6 +241,15 @@ pub struct RhsHeader {
     pub amplifier_channels: Vec<ChannelInfo>,
     /// List of spike trigger configurations (one per amplifier channel)
     pub spike_triggers: Vec<SpikeTrigger>,
+    /// List of auxiliary input channels
+    ///
+    /// RHS data blocks carry no samples for these channels; they are listed so
+    /// files that enable them can be loaded and re-saved.
+    pub aux_input_channels: Vec<ChannelInfo>,
+    /// List of supply voltage (Vdd) channels
+    ///
+    /// As with auxiliary inputs, RHS data blocks carry no samples for these channels.
+    pub supply_voltage_channels: Vec<ChannelInfo>,
     /// List of board ADC (analog-to-digital converter) channels
     pub board_adc_channels: Vec<ChannelInfo>,
     /// List of board DAC (digital-to-analog converter) channels
@@ -241,8 +273,9 @@ pub struct RhsHeader {
 /// and the second dimension is the time sample.
 #[derive(Debug, Clone)]
 pub struct RhsData {
-    /// Timestamps for each sample (in sample numbers - divide by sample_rate to get seconds)
-    pub timestamps: Array1<i32>,
+    /// Timestamps for each sample (in sample numbers - divide by sample_rate to get seconds),
+    /// unwrapped where the 32-bit values stored by RHX wrap around
+    pub timestamps: Array1<i64>,
     /// Neural data from amplifier channels (μV)
     /// - Shape: [num_channels, num_samples]
     pub amplifier_data: Option<Array2<f64>>,
@@ -251,22 +284,22 @@ pub struct RhsData {
     pub dc_amplifier_data: Option<Array2<f64>>,
     /// Stimulation current data (μA)
     /// - Shape: [num_channels, num_samples]
-    pub stim_data: Option<Array2<i32>>,
+    pub stim_data: Option<Array2<f64>>,
     /// Compliance limit status for each channel and sample
-    /// - true: compliance limit was reached
-    /// - false: compliance limit was not reached
-    /// - Shape: [num_channels, num_samples]
-    pub compliance_limit_data: Option<Array2<bool>>,
+    /// - set: compliance limit was reached
+    /// - clear: compliance limit was not reached
+    /// - Shape: [num_channels, num_samples], stored as runs of set samples
+    pub compliance_limit_data: Option<StimFlags>,
     /// Charge recovery status for each channel and sample
-    /// - true: charge recovery was active
-    /// - false: charge recovery was inactive
-    /// - Shape: [num_channels, num_samples]
-    pub charge_recovery_data: Option<Array2<bool>>,
+    /// - set: charge recovery was active
+    /// - clear: charge recovery was inactive
+    /// - Shape: [num_channels, num_samples], stored as runs of set samples
+    pub charge_recovery_data: Option<StimFlags>,
     /// Amplifier settle status for each channel and sample
-    /// - true: amplifier settle was active
-    /// - false: amplifier settle was inactive
-    /// - Shape: [num_channels, num_samples]
-    pub amp_settle_data: Option<Array2<bool>>,
+    /// - set: amplifier settle was active
+    /// - clear: amplifier settle was inactive
+    /// - Shape: [num_channels, num_samples], stored as runs of set samples
+    pub amp_settle_data: Option<StimFlags>,
     /// Board ADC data (V)
     /// - Shape: [num_channels, num_samples]
     pub board_adc_data: Option<Array2<f64>>,
@@ -279,6 +312,471 @@ pub struct RhsData {
     /// Board digital output data (0 or 1)
     /// - Shape: [num_channels, num_samples]
     pub board_dig_out_data: Option<Array2<i32>>,
+    /// Amplifier data as raw ADC codes, kept instead of `amplifier_data` when
+    /// loading with [`LoadOptions::raw`](crate::loader::LoadOptions::raw)
+    /// - Shape: [num_channels, num_samples]
+    pub amplifier_data_raw: Option<Array2<u16>>,
+    /// DC amplifier data as raw ADC codes, kept instead of `dc_amplifier_data`
+    /// - Shape: [num_channels, num_samples]
+    pub dc_amplifier_data_raw: Option<Array2<u16>>,
+    /// Board ADC data as raw ADC codes, kept instead of `board_adc_data`
+    /// - Shape: [num_channels, num_samples]
+    pub board_adc_data_raw: Option<Array2<u16>>,
+    /// Board DAC data as raw DAC codes, kept instead of `board_dac_data`
+    /// - Shape: [num_channels, num_samples]
+    pub board_dac_data_raw: Option<Array2<u16>>,
+    /// Amplifier data in single precision, kept instead of `amplifier_data` when
+    /// loading with [`DataType::F32`](crate::loader::DataType::F32)
+    /// - Shape: [num_channels, num_samples]
+    pub amplifier_data_f32: Option<Array2<f32>>,
+    /// DC amplifier data in single precision, kept instead of `dc_amplifier_data`
+    /// - Shape: [num_channels, num_samples]
+    pub dc_amplifier_data_f32: Option<Array2<f32>>,
+    /// Board ADC data in single precision, kept instead of `board_adc_data`
+    /// - Shape: [num_channels, num_samples]
+    pub board_adc_data_f32: Option<Array2<f32>>,
+    /// Board DAC data in single precision, kept instead of `board_dac_data`
+    /// - Shape: [num_channels, num_samples]
+    pub board_dac_data_f32: Option<Array2<f32>>,
+    /// Signals spilled to temporary files when loading with
+    /// [`LoadOptions::spill_dir`](crate::loader::LoadOptions::spill_dir)
+    ///
+    /// Views and copies made from views do not include spilled signals.
+    pub spilled: Option<SpilledSignals>,
+    /// Physical unit of each analog signal
+    pub units: SignalUnits,
+}
+
+/// Borrowed view of the data contained in an RHS file.
+///
+/// Mirrors [`RhsData`] field by field, but every array is an `ArrayView` into
+/// existing data, so views over large recordings can be created without copying.
+/// Use [`RhsDataView::to_owned`] to materialize a view into an independent `RhsData`.
+#[derive(Debug, Clone)]
+pub struct RhsDataView<'a> {
+    /// Timestamps for each sample
+    pub timestamps: ArrayView1<'a, i64>,
+    /// Neural data from amplifier channels (μV)
+    pub amplifier_data: Option<ArrayView2<'a, f64>>,
+    /// DC amplifier data (V)
+    pub dc_amplifier_data: Option<ArrayView2<'a, f64>>,
+    /// Stimulation current data (μA)
+    pub stim_data: Option<ArrayView2<'a, f64>>,
+    /// Compliance limit status for each channel and sample
+    pub compliance_limit_data: Option<StimFlagsView<'a>>,
+    /// Charge recovery status for each channel and sample
+    pub charge_recovery_data: Option<StimFlagsView<'a>>,
+    /// Amplifier settle status for each channel and sample
+    pub amp_settle_data: Option<StimFlagsView<'a>>,
+    /// Board ADC data (V)
+    pub board_adc_data: Option<ArrayView2<'a, f64>>,
+    /// Board DAC data (V)
+    pub board_dac_data: Option<ArrayView2<'a, f64>>,
+    /// Board digital input data (0 or 1)
+    pub board_dig_in_data: Option<ArrayView2<'a, i32>>,
+    /// Board digital output data (0 or 1)
+    pub board_dig_out_data: Option<ArrayView2<'a, i32>>,
+    /// Amplifier data as raw ADC codes
+    pub amplifier_data_raw: Option<ArrayView2<'a, u16>>,
+    /// DC amplifier data as raw ADC codes
+    pub dc_amplifier_data_raw: Option<ArrayView2<'a, u16>>,
+    /// Board ADC data as raw ADC codes
+    pub board_adc_data_raw: Option<ArrayView2<'a, u16>>,
+    /// Board DAC data as raw DAC codes
+    pub board_dac_data_raw: Option<ArrayView2<'a, u16>>,
+    /// Amplifier data in single precision
+    pub amplifier_data_f32: Option<ArrayView2<'a, f32>>,
+    /// DC amplifier data in single precision
+    pub dc_amplifier_data_f32: Option<ArrayView2<'a, f32>>,
+    /// Board ADC data in single precision
+    pub board_adc_data_f32: Option<ArrayView2<'a, f32>>,
+    /// Board DAC data in single precision
+    pub board_dac_data_f32: Option<ArrayView2<'a, f32>>,
+    /// Physical unit of each analog signal
+    pub units: SignalUnits,
+}
+
+impl RhsData {
+    /// Returns the number of samples in the data.
+    pub fn num_samples(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    /// Returns a borrowed view of all signals.
+    pub fn view(&self) -> RhsDataView<'_> {
+        RhsDataView {
+            timestamps: self.timestamps.view(),
+            amplifier_data: self.amplifier_data.as_ref().map(|a| a.view()),
+            dc_amplifier_data: self.dc_amplifier_data.as_ref().map(|a| a.view()),
+            stim_data: self.stim_data.as_ref().map(|a| a.view()),
+            compliance_limit_data: self.compliance_limit_data.as_ref().map(StimFlags::view),
+            charge_recovery_data: self.charge_recovery_data.as_ref().map(StimFlags::view),
+            amp_settle_data: self.amp_settle_data.as_ref().map(StimFlags::view),
+            board_adc_data: self.board_adc_data.as_ref().map(|a| a.view()),
+            board_dac_data: self.board_dac_data.as_ref().map(|a| a.view()),
+            board_dig_in_data: self.board_dig_in_data.as_ref().map(|a| a.view()),
+            board_dig_out_data: self.board_dig_out_data.as_ref().map(|a| a.view()),
+            amplifier_data_raw: self.amplifier_data_raw.as_ref().map(|a| a.view()),
+            dc_amplifier_data_raw: self.dc_amplifier_data_raw.as_ref().map(|a| a.view()),
+            board_adc_data_raw: self.board_adc_data_raw.as_ref().map(|a| a.view()),
+            board_dac_data_raw: self.board_dac_data_raw.as_ref().map(|a| a.view()),
+            amplifier_data_f32: self.amplifier_data_f32.as_ref().map(|a| a.view()),
+            dc_amplifier_data_f32: self.dc_amplifier_data_f32.as_ref().map(|a| a.view()),
+            board_adc_data_f32: self.board_adc_data_f32.as_ref().map(|a| a.view()),
+            board_dac_data_f32: self.board_dac_data_f32.as_ref().map(|a| a.view()),
+            units: self.units,
+        }
+    }
+}
+
+impl<'a> RhsDataView<'a> {
+    /// Returns the number of samples in the view.
+    pub fn num_samples(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    /// Restricts the view to the sample range `start..end` (relative to this view).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn slice_samples(&self, start: usize, end: usize) -> RhsDataView<'a> {
+        RhsDataView {
+            timestamps: self.timestamps.slice_move(s![start..end]),
+            amplifier_data: self.amplifier_data.map(|a| a.slice_move(s![.., start..end])),
+            dc_amplifier_data: self.dc_amplifier_data.map(|a| a.slice_move(s![.., start..end])),
+            stim_data: self.stim_data.map(|a| a.slice_move(s![.., start..end])),
+            compliance_limit_data: self.compliance_limit_data.map(|f| f.slice(start, end)),
+            charge_recovery_data: self.charge_recovery_data.map(|f| f.slice(start, end)),
+            amp_settle_data: self.amp_settle_data.map(|f| f.slice(start, end)),
+            board_adc_data: self.board_adc_data.map(|a| a.slice_move(s![.., start..end])),
+            board_dac_data: self.board_dac_data.map(|a| a.slice_move(s![.., start..end])),
+            board_dig_in_data: self.board_dig_in_data.map(|a| a.slice_move(s![.., start..end])),
+            board_dig_out_data: self.board_dig_out_data.map(|a| a.slice_move(s![.., start..end])),
+            amplifier_data_raw: self.amplifier_data_raw.map(|a| a.slice_move(s![.., start..end])),
+            dc_amplifier_data_raw: self
+                .dc_amplifier_data_raw
+                .map(|a| a.slice_move(s![.., start..end])),
+            board_adc_data_raw: self.board_adc_data_raw.map(|a| a.slice_move(s![.., start..end])),
+            board_dac_data_raw: self.board_dac_data_raw.map(|a| a.slice_move(s![.., start..end])),
+            amplifier_data_f32: self.amplifier_data_f32.map(|a| a.slice_move(s![.., start..end])),
+            dc_amplifier_data_f32: self
+                .dc_amplifier_data_f32
+                .map(|a| a.slice_move(s![.., start..end])),
+            board_adc_data_f32: self.board_adc_data_f32.map(|a| a.slice_move(s![.., start..end])),
+            board_dac_data_f32: self.board_dac_data_f32.map(|a| a.slice_move(s![.., start..end])),
+            units: self.units,
+        }
+    }
+
+    /// Copies the viewed data into a new, owned `RhsData`.
+    pub fn to_owned(&self) -> RhsData {
+        RhsData {
+            timestamps: self.timestamps.to_owned(),
+            amplifier_data: self.amplifier_data.map(|a| a.to_owned()),
+            dc_amplifier_data: self.dc_amplifier_data.map(|a| a.to_owned()),
+            stim_data: self.stim_data.map(|a| a.to_owned()),
+            compliance_limit_data: self.compliance_limit_data.map(|f| f.to_owned()),
+            charge_recovery_data: self.charge_recovery_data.map(|f| f.to_owned()),
+            amp_settle_data: self.amp_settle_data.map(|f| f.to_owned()),
+            board_adc_data: self.board_adc_data.map(|a| a.to_owned()),
+            board_dac_data: self.board_dac_data.map(|a| a.to_owned()),
+            board_dig_in_data: self.board_dig_in_data.map(|a| a.to_owned()),
+            board_dig_out_data: self.board_dig_out_data.map(|a| a.to_owned()),
+            amplifier_data_raw: self.amplifier_data_raw.map(|a| a.to_owned()),
+            dc_amplifier_data_raw: self.dc_amplifier_data_raw.map(|a| a.to_owned()),
+            board_adc_data_raw: self.board_adc_data_raw.map(|a| a.to_owned()),
+            board_dac_data_raw: self.board_dac_data_raw.map(|a| a.to_owned()),
+            amplifier_data_f32: self.amplifier_data_f32.map(|a| a.to_owned()),
+            dc_amplifier_data_f32: self.dc_amplifier_data_f32.map(|a| a.to_owned()),
+            board_adc_data_f32: self.board_adc_data_f32.map(|a| a.to_owned()),
+            board_dac_data_f32: self.board_dac_data_f32.map(|a| a.to_owned()),
+            spilled: None,
+            units: self.units,
+        }
+    }
+}
+
+/// Amplifier signal streams that the RHX software can save as separate `.dat` files.
+///
+/// In "one file per signal type" mode, RHX can save the wideband amplifier signal
+/// together with software-filtered low-pass and high-pass copies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AmplifierStream {
+    /// Wideband amplifier signal (`amplifier.dat`)
+    Wideband,
+    /// Low-pass filtered signal, usually downsampled (`lowpass.dat`)
+    Lowpass,
+    /// High-pass filtered signal (`highpass.dat`)
+    Highpass,
+}
+
+impl AmplifierStream {
+    /// All amplifier streams, in the order RHX lists them.
+    pub const ALL: [AmplifierStream; 3] = [
+        AmplifierStream::Wideband,
+        AmplifierStream::Lowpass,
+        AmplifierStream::Highpass,
+    ];
+
+    /// Returns the file name used for this stream in "one file per signal type" mode.
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            AmplifierStream::Wideband => "amplifier.dat",
+            AmplifierStream::Lowpass => "lowpass.dat",
+            AmplifierStream::Highpass => "highpass.dat",
+        }
+    }
+}
+
+/// Amplifier data for each separately saved amplifier stream.
+///
+/// Each field is `None` when the corresponding stream was not saved or not requested.
+#[derive(Debug, Clone)]
+pub struct AmplifierStreams {
+    /// Wideband amplifier data (μV)
+    /// - Shape: [num_channels, num_samples]
+    pub wideband_data: Option<Array2<f64>>,
+    /// Low-pass filtered amplifier data (μV)
+    /// - Shape: [num_channels, num_samples / lowpass_downsample_factor]
+    pub lowpass_data: Option<Array2<f64>>,
+    /// Downsampling factor of the low-pass stream relative to the amplifier sample rate
+    pub lowpass_downsample_factor: usize,
+    /// High-pass filtered amplifier data (μV)
+    /// - Shape: [num_channels, num_samples]
+    pub highpass_data: Option<Array2<f64>>,
+}
+
+impl AmplifierStreams {
+    /// Returns the data for the given stream, if it was loaded.
+    pub fn get(&self, stream: AmplifierStream) -> Option<&Array2<f64>> {
+        match stream {
+            AmplifierStream::Wideband => self.wideband_data.as_ref(),
+            AmplifierStream::Lowpass => self.lowpass_data.as_ref(),
+            AmplifierStream::Highpass => self.highpass_data.as_ref(),
+        }
+    }
+}
+
+/// The samples one file contributed to a recording combined from several files.
+///
+/// Listed in [`RhsFile::source_files`], so that any sample index of the combined
+/// arrays can be traced back to the file it was read from.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceFileSpan {
+    /// Path of the file
+    pub path: String,
+    /// Index of the file's first sample in the combined data
+    pub sample_offset: usize,
+    /// Number of samples the file contributed
+    pub num_samples: usize,
+    /// Timestamp of the file's first sample in the combined data, or `None` if it
+    /// contributed no samples
+    pub first_timestamp: Option<i64>,
+}
+
+impl SourceFileSpan {
+    /// Returns the indices of the file's samples in the combined data.
+    pub fn samples(&self) -> Range<usize> {
+        self.sample_offset..self.sample_offset + self.num_samples
+    }
+}
+
+/// A file of a directory left out of a combined recording because it could not
+/// be read or repeats the samples of another file.
+///
+/// Listed in [`RhsFile::skipped_files`] when loading with
+/// [`LoadOptions::skip_corrupt_files`](crate::LoadOptions::skip_corrupt_files) or
+/// [`DuplicateHandling::Deduplicate`](crate::DuplicateHandling::Deduplicate).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SkippedFile {
+    /// Path of the file
+    pub path: String,
+    /// Why the file was left out
+    pub error: String,
+}
+
+/// A condition found while loading that did not stop the load.
+///
+/// Listed in [`RhsFile::warnings`] in the order they were found, so pipelines can
+/// report or act on them, and logged as well. The paths are those of the files
+/// concerned, and are empty for a recording [merged](RhsFile::merge) from one
+/// loaded without a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LoadWarning {
+    /// The timestamps within a file are not consecutive, so time is not uniform;
+    /// [`RhsFile::segments`] lists the continuous parts
+    TimestampGaps {
+        /// Path of the file
+        path: String,
+        /// Number of places where a timestamp does not follow the one before it
+        num_gaps: usize,
+    },
+    /// A file of a directory does not continue the timestamps of the file before
+    /// it, and the gap handling kept or padded the gap
+    FileGap {
+        /// Path of the file
+        path: String,
+        /// Number of samples missing before the file, negative if its timestamps
+        /// go back
+        missing_samples: i64,
+        /// Number of samples of padding inserted before the file
+        padded_samples: usize,
+    },
+    /// An amplifier channel of a file has a different native name than in the
+    /// first file, whose name was kept, as
+    /// [`HeaderCompatibility::Lenient`](crate::HeaderCompatibility::Lenient) allows
+    ChannelNameMismatch {
+        /// Path of the file
+        path: String,
+        /// Index of the amplifier channel
+        channel: usize,
+        /// Name in the first file, which was kept
+        kept_name: String,
+        /// Name in this file
+        name: String,
+    },
+    /// An acquisition setting of a file differs from the first file's, which was
+    /// kept
+    SettingMismatch {
+        /// Path of the file
+        path: String,
+        /// Name of the setting
+        setting: String,
+        /// Value in the first file, which was kept
+        kept_value: String,
+        /// Value in this file
+        value: String,
+    },
+    /// An amplifier channel was left out by
+    /// [bad channel masking](crate::LoadOptions::mask_bad_channels)
+    ChannelMasked {
+        /// Native name of the channel
+        native_name: String,
+        /// Why the channel was flagged
+        issues: Vec<QualityIssue>,
+    },
+    /// A file's header holds a value the RHS format does not define, which
+    /// [`ParseMode::Lenient`](crate::ParseMode::Lenient) continues past
+    UnexpectedHeaderValue {
+        /// Path of the file
+        path: String,
+        /// Description of the field, such as `signal type of channel A-000`
+        field: String,
+        /// The value found
+        value: String,
+    },
+    /// The RHX settings sidecar could not be parsed and was ignored
+    UnreadableSettings {
+        /// Path of the sidecar
+        path: String,
+        /// Why it could not be parsed
+        error: String,
+    },
+}
+
+impl LoadWarning {
+    /// Returns the path of the file the warning concerns, if it concerns one.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            LoadWarning::TimestampGaps { path, .. }
+            | LoadWarning::FileGap { path, .. }
+            | LoadWarning::ChannelNameMismatch { path, .. }
+            | LoadWarning::SettingMismatch { path, .. }
+            | LoadWarning::UnexpectedHeaderValue { path, .. }
+            | LoadWarning::UnreadableSettings { path, .. } => Some(path),
+            LoadWarning::ChannelMasked { .. } => None,
+        }
+    }
+
+    /// Returns the path of the file the warning concerns for rewriting, if it
+    /// concerns one
+    pub(crate) fn path_mut(&mut self) -> Option<&mut String> {
+        match self {
+            LoadWarning::TimestampGaps { path, .. }
+            | LoadWarning::FileGap { path, .. }
+            | LoadWarning::ChannelNameMismatch { path, .. }
+            | LoadWarning::SettingMismatch { path, .. }
+            | LoadWarning::UnexpectedHeaderValue { path, .. }
+            | LoadWarning::UnreadableSettings { path, .. } => Some(path),
+            LoadWarning::ChannelMasked { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for LoadWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadWarning::TimestampGaps { path, num_gaps } => write!(
+                f,
+                "{} gaps in the timestamps of {}; time is not uniform, and \
+                 RhsFile::segments lists the continuous parts",
+                num_gaps, path
+            ),
+            LoadWarning::FileGap {
+                path,
+                missing_samples,
+                padded_samples,
+            } => {
+                if *missing_samples < 0 {
+                    write!(
+                        f,
+                        "The timestamps of {} go back {} samples from the file before it",
+                        path, -missing_samples
+                    )?;
+                } else {
+                    write!(
+                        f,
+                        "{} does not continue the file before it: {} samples are missing",
+                        path, missing_samples
+                    )?;
+                }
+                if *padded_samples > 0 {
+                    write!(f, "; padded with {} samples", padded_samples)?;
+                }
+                Ok(())
+            }
+            LoadWarning::ChannelNameMismatch {
+                path,
+                channel,
+                kept_name,
+                name,
+            } => write!(
+                f,
+                "Amplifier channel {} names don't match: '{}' vs '{}' in {}; keeping the \
+                 first name",
+                channel, kept_name, name, path
+            ),
+            LoadWarning::SettingMismatch {
+                path,
+                setting,
+                kept_value,
+                value,
+            } => write!(
+                f,
+                "{} differs in its {} ({} vs {}); keeping the first file's",
+                path, setting, kept_value, value
+            ),
+            LoadWarning::ChannelMasked {
+                native_name,
+                issues,
+            } => {
+                let issues: Vec<String> = issues.iter().map(ToString::to_string).collect();
+                write!(f, "Masked channel {}: {}", native_name, issues.join(", "))
+            }
+            LoadWarning::UnexpectedHeaderValue { path, field, value } => write!(
+                f,
+                "Unexpected {} in the header of {} ({}); continuing",
+                field, path, value
+            ),
+            LoadWarning::UnreadableSettings { path, error } => {
+                write!(f, "Ignoring unreadable {}: {}", path, error)
+            }
+        }
+    }
 }
 
 // Add this field to the RhsFile struct in types.rs (around line 279)
@@ -319,8 +817,24 @@ pub struct RhsFile {
     pub data: Option<RhsData>,
     /// Flag indicating whether data is present in the file
     pub data_present: bool,
-    /// List of source files if this was created by combining multiple files
-    pub source_files: Option<Vec<String>>,
+    /// Source files and the samples each contributed, if this was created by
+    /// combining multiple files
+    pub source_files: Option<Vec<SourceFileSpan>>,
+    /// Files of a directory that were left out because they could not be read or
+    /// repeat another file, as [`SkippedFile`] describes
+    pub skipped_files: Vec<SkippedFile>,
+    /// Content hashes of the files read, when loading with
+    /// [`Loader::fingerprint`](crate::Loader::fingerprint); see the
+    /// [`fingerprint`](crate::fingerprint) module
+    pub fingerprints: Vec<FileFingerprint>,
+    /// Conditions found while loading that did not stop the load, such as gaps
+    /// in the timestamps, as [`LoadWarning`] describes
+    pub warnings: Vec<LoadWarning>,
+    /// Contents of the RHX `settings.xml` sidecar, if one was found next to the recording
+    pub settings: Option<SettingsXml>,
+    /// Wall-clock time of the first sample, from the start time in the RHX file or
+    /// directory name, or `None` if the name has none (see [`datetime`](crate::datetime))
+    pub recording_start: Option<DateTime>,
 }
 
 impl RhsFile {
@@ -364,12 +878,324 @@ impl RhsFile {
             0
         }
     }
+
+    /// Returns the source file the sample at `index` was read from, or `None` if
+    /// the recording was not combined from several files or has no such sample.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use intan_importer::load;
+    ///
+    /// let rhs_file = load("recording_session/").unwrap();
+    /// if let Some(source) = rhs_file.source_file_at(1_000_000) {
+    ///     let index_in_file = 1_000_000 - source.sample_offset;
+    ///     println!("Sample {} of {}", index_in_file, source.path);
+    /// }
+    /// ```
+    pub fn source_file_at(&self, index: usize) -> Option<&SourceFileSpan> {
+        self.source_files
+            .as_ref()?
+            .iter()
+            .find(|source| source.samples().contains(&index))
+    }
+
+    /// Returns the source files restricted to the given samples, with sample
+    /// indices relative to the first of them
+    pub(crate) fn source_files_in(&self, samples: Range<usize>) -> Option<Vec<SourceFileSpan>> {
+        let timestamps = &self.data.as_ref()?.timestamps;
+        let sources = self.source_files.as_ref()?;
+        Some(
+            sources
+                .iter()
+                .filter_map(|source| {
+                    let start = source.sample_offset.max(samples.start);
+                    let end = source.samples().end.min(samples.end);
+                    (start < end).then(|| SourceFileSpan {
+                        path: source.path.clone(),
+                        sample_offset: start - samples.start,
+                        num_samples: end - start,
+                        first_timestamp: Some(timestamps[start]),
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the time of every sample in seconds, computed from its timestamp.
+    ///
+    /// Times start at the first timestamp divided by the sample rate, which is not
+    /// zero for recordings that were started by a trigger or split into several
+    /// files, and skip over gaps in the timestamps. If no data is present, returns
+    /// an empty array.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use intan_importer::load;
+    ///
+    /// let rhs_file = load("path/to/your/file.rhs").unwrap();
+    /// let t = rhs_file.time_seconds();
+    /// println!("Recording runs from {:.3} s to {:.3} s", t[0], t[t.len() - 1]);
+    /// ```
+    pub fn time_seconds(&self) -> Array1<f64> {
+        let sample_rate = self.header.sample_rate as f64;
+        match &self.data {
+            Some(data) => data
+                .timestamps
+                .mapv(|timestamp| timestamp as f64 / sample_rate),
+            None => Array1::zeros(0),
+        }
+    }
+
+    /// Returns the time in seconds of the sample at `index`, computed from its
+    /// timestamp, or `None` if there is no such sample.
+    pub fn time_at_sample(&self, index: usize) -> Option<f64> {
+        let timestamp = *self.data.as_ref()?.timestamps.get(index)?;
+        Some(timestamp as f64 / self.header.sample_rate as f64)
+    }
+
+    /// Returns the index of the sample closest to `time` in seconds, on the scale
+    /// of [`time_seconds`](RhsFile::time_seconds).
+    ///
+    /// The timestamps are expected to increase, as RHX writes them. A time within
+    /// a gap in the timestamps maps to the closer sample on either side. Returns
+    /// `None` if `time` lies more than half a sample before the first
+    /// sample or after the last, or if no data is present.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use intan_importer::load;
+    ///
+    /// let rhs_file = load("path/to/your/file.rhs").unwrap();
+    /// // Index of the sample recorded 12.5 s after the timestamp counter started
+    /// if let Some(index) = rhs_file.sample_at_time(12.5) {
+    ///     assert!((rhs_file.time_at_sample(index).unwrap() - 12.5).abs() < 1e-3);
+    /// }
+    /// ```
+    pub fn sample_at_time(&self, time: f64) -> Option<usize> {
+        let timestamps = &self.data.as_ref()?.timestamps;
+        let target = time * self.header.sample_rate as f64;
+        let first = *timestamps.first()? as f64;
+        let last = *timestamps.last()? as f64;
+        if target.is_nan() || target < first - 0.5 || target > last + 0.5 {
+            return None;
+        }
+
+        let after = first_at_or_after(timestamps, target);
+        if after == 0 {
+            return Some(0);
+        }
+        if after == timestamps.len() {
+            return Some(after - 1);
+        }
+        let before_distance = target - timestamps[after - 1] as f64;
+        let after_distance = timestamps[after] as f64 - target;
+        Some(if after_distance < before_distance {
+            after
+        } else {
+            after - 1
+        })
+    }
+
+    /// Returns a view of the samples whose time falls within `range`, in seconds on
+    /// the scale of [`time_seconds`](RhsFile::time_seconds), or `None` if no data
+    /// is present.
+    ///
+    /// No data is copied, so epochs of any length can be analyzed cheaply; use
+    /// [`RhsDataView::to_owned`] on the view's data to keep a copy. A range outside
+    /// the recording gives a view without samples. Signals spilled to disk are not
+    /// part of the view.
+    ///
+    /// The times follow the timestamps, so the first sample of a triggered or split
+    /// recording is not at 0 s but at `time_seconds()[0]`. [`slice_from_start`]
+    /// takes seconds from the first sample instead, like
+    /// [`Loader::time_range`](crate::Loader::time_range).
+    ///
+    /// [`slice_from_start`]: RhsFile::slice_from_start
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use intan_importer::load;
+    ///
+    /// let rhs_file = load("path/to/your/file.rhs").unwrap();
+    /// // From 10 s to 12.5 s after the first sample, whatever its timestamp
+    /// let start = rhs_file.time_seconds()[0];
+    /// let epoch = rhs_file.slice(start + 10.0..start + 12.5).unwrap();
+    /// if let Some(amp_data) = epoch.data.amplifier_data {
+    ///     // ArrayView2 of shape [num_channels, samples between 10 s and 12.5 s]
+    ///     println!("Epoch shape: {:?}", amp_data.shape());
+    /// }
+    /// ```
+    pub fn slice(&self, range: Range<f64>) -> Option<RhsFileView<'_>> {
+        let data = self.data.as_ref()?;
+        let sample_rate = self.header.sample_rate as f64;
+        let start = first_at_or_after(&data.timestamps, range.start * sample_rate);
+        let end = first_at_or_after(&data.timestamps, range.end * sample_rate).max(start);
+
+        Some(RhsFileView {
+            header: &self.header,
+            sample_offset: start,
+            data: data.view().slice_samples(start, end),
+        })
+    }
+
+    /// Returns a view of the samples within `range`, in seconds from the first
+    /// sample of the recording, or `None` if no data is present.
+    ///
+    /// Selects the same samples as loading with
+    /// [`Loader::time_range`](crate::Loader::time_range), counting samples rather
+    /// than timestamps, so gaps in the timestamps are not skipped over. The view is
+    /// otherwise like that of [`slice`](RhsFile::slice).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use intan_importer::load;
+    ///
+    /// let rhs_file = load("path/to/your/file.rhs").unwrap();
+    /// let epoch = rhs_file.slice_from_start(10.0..12.5).unwrap();
+    /// println!("{} samples from sample {}", epoch.num_samples(), epoch.sample_offset);
+    /// ```
+    pub fn slice_from_start(&self, range: Range<f64>) -> Option<RhsFileView<'_>> {
+        let data = self.data.as_ref()?;
+        let sample_rate = self.header.sample_rate;
+        let num_samples = data.timestamps.len();
+        let to_sample = |seconds: f64| {
+            reader::first_sample_at(seconds, sample_rate).min(num_samples as u64) as usize
+        };
+        let start = to_sample(range.start);
+        let end = to_sample(range.end).max(start);
+
+        Some(RhsFileView {
+            header: &self.header,
+            sample_offset: start,
+            data: data.view().slice_samples(start, end),
+        })
+    }
+}
+
+/// Returns the index of the first timestamp that is not below `target`, found by
+/// bisection since timestamps increase
+fn first_at_or_after(timestamps: &Array1<i64>, target: f64) -> usize {
+    let (mut low, mut high) = (0, timestamps.len());
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if (timestamps[middle] as f64) < target {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+/// A view of a time window of an [`RhsFile`], created by [`RhsFile::slice`].
+#[derive(Debug, Clone)]
+pub struct RhsFileView<'a> {
+    /// Header of the recording
+    pub header: &'a RhsHeader,
+    /// Index of the first sample of the view within the recording
+    pub sample_offset: usize,
+    /// View of the data within the window
+    pub data: RhsDataView<'a>,
+}
+
+impl RhsFileView<'_> {
+    /// Returns the number of samples in the view.
+    pub fn num_samples(&self) -> usize {
+        self.data.num_samples()
+    }
+
+    /// Returns the duration of the view in seconds.
+    pub fn duration(&self) -> f32 {
+        self.num_samples() as f32 / self.header.sample_rate
+    }
+}
+
+/// A recording in any of the supported Intan file formats.
+///
+/// Returned by [`load_any`](crate::load_any), which detects the format from the
+/// file's magic number.
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)] // Created once per load; boxing would only add indirection
+pub enum IntanFile {
+    /// A recording from an RHS2000 stimulation/recording system
+    Rhs(RhsFile),
+    /// A recording from an RHD2000 recording system
+    Rhd(RhdFile),
+}
+
+impl IntanFile {
+    /// Returns the amplifier sample rate in Hz.
+    pub fn sample_rate(&self) -> f32 {
+        match self {
+            IntanFile::Rhs(file) => file.header.sample_rate,
+            IntanFile::Rhd(file) => file.header.sample_rate,
+        }
+    }
+
+    /// Returns the duration of the recording in seconds.
+    pub fn duration(&self) -> f32 {
+        match self {
+            IntanFile::Rhs(file) => file.duration(),
+            IntanFile::Rhd(file) => file.duration(),
+        }
+    }
+
+    /// Returns the number of amplifier samples in the recording.
+    pub fn num_samples(&self) -> usize {
+        match self {
+            IntanFile::Rhs(file) => file.num_samples(),
+            IntanFile::Rhd(file) => file.num_samples(),
+        }
+    }
+
+    /// Returns the RHS recording, if this is one.
+    pub fn as_rhs(&self) -> Option<&RhsFile> {
+        match self {
+            IntanFile::Rhs(file) => Some(file),
+            IntanFile::Rhd(_) => None,
+        }
+    }
+
+    /// Returns the RHD recording, if this is one.
+    pub fn as_rhd(&self) -> Option<&RhdFile> {
+        match self {
+            IntanFile::Rhd(file) => Some(file),
+            IntanFile::Rhs(_) => None,
+        }
+    }
 }
 
 /// Custom error types for the Intan importer.
 ///
 /// Represents various error conditions that may occur during file reading
-/// and processing.
+/// and processing. Loading functions return this type, so callers can match on
+/// the kind of failure; errors that concern one file of a directory or combined
+/// recording are wrapped in [`IntanError::File`], which names the file and, for
+/// files that could not be parsed, the byte at which reading stopped. Errors in
+/// the header are wrapped in [`IntanError::HeaderField`], which names the field
+/// and the channel it belongs to, and gives the byte at which the field starts.
+/// [`root_cause`](IntanError::root_cause) returns the error inside.
+///
+/// # Examples
+///
+/// ```no_run
+/// use intan_importer::IntanError;
+///
+/// match intan_importer::load("session_dir/") {
+///     Ok(rhs_file) => println!("Loaded {:.1} s", rhs_file.duration()),
+///     Err(IntanError::File { path, source, .. })
+///         if matches!(*source, IntanError::Gap { .. }) =>
+///     {
+///         println!("Recording is interrupted before {}", path.display());
+///     }
+///     Err(e) => println!("Cannot load the session: {}", e),
+/// }
+/// ```
 #[derive(Debug)]
 pub enum IntanError {
     /// The file format was not recognized as an Intan RHS file
@@ -380,14 +1206,127 @@ pub enum IntanError {
     FileSizeError,
     /// Error reading a string from the file
     StringReadError,
-    /// A requested channel was not found
-    ChannelNotFound,
+    /// A selected channel is not in the recording
+    ChannelNotFound(ChannelSelector),
+    /// The header holds a value the RHS format does not define, which
+    /// [`ParseMode::Strict`](crate::ParseMode::Strict) rejects
+    UnexpectedHeaderValue {
+        /// Description of the field, such as `signal type of channel A-000`
+        field: String,
+        /// The value found
+        value: String,
+    },
+    /// A field of the header could not be read or parsed
+    HeaderField {
+        /// Description of the field, such as `custom name of channel A-000`
+        field: String,
+        /// Byte of the file at which the field starts
+        offset: u64,
+        /// The error
+        source: Box<IntanError>,
+    },
     /// An I/O error occurred during file reading
     IoError(io::Error),
+    /// The load options are invalid or contradict each other
+    InvalidOptions(String),
+    /// No files were found to load
+    NoFiles {
+        /// Directory searched, if the files were to be found in one
+        directory: Option<PathBuf>,
+        /// File name pattern the files had to match
+        pattern: Option<String>,
+    },
+    /// The headers of files to be combined are incompatible
+    IncompatibleHeaders(String),
+    /// A file does not continue the timestamps of the file before it, and the gap
+    /// handling rejects the gap
+    Gap {
+        /// Number of samples missing between the files, negative if the file's
+        /// timestamps go back
+        missing_samples: i64,
+        /// Sample rate in Hz
+        sample_rate: f32,
+    },
+    /// A file repeats samples of another file, as backup copies do
+    DuplicateFile {
+        /// The file whose samples are repeated
+        original: PathBuf,
+        /// Samples of the original file that are repeated
+        repeated_samples: Range<usize>,
+        /// Samples the file holds after the end of the original, which prevent
+        /// leaving it out
+        extra_samples: usize,
+    },
+    /// A time range selects no samples of the recording. A wall-clock range that
+    /// starts after the end of the recording reports this too, with the time range
+    /// it was converted to.
+    TimeRangeOutsideRecording {
+        /// The selected range, in seconds from the start of the recording
+        range: Range<f64>,
+        /// Duration of the recording in seconds
+        duration: f64,
+    },
+    /// A wall-clock range ends before the recording starts
+    WallclockRangeOutsideRecording {
+        /// The selected range
+        range: Range<DateTime>,
+        /// Wall-clock time of the recording's first sample
+        recording_start: DateTime,
+    },
+    /// A recording holds more samples than this platform can address, as a
+    /// multi-day recording can on a 32-bit target
+    ExceedsAddressSpace(String),
+    /// An error concerning one file
+    File {
+        /// Path of the file
+        path: PathBuf,
+        /// Byte of the file at which reading stopped, for files that could not be
+        /// read or parsed. Errors in the header carry the offset of the field in
+        /// [`IntanError::HeaderField`] instead.
+        offset: Option<u64>,
+        /// The error
+        source: Box<IntanError>,
+    },
     /// A general error with a custom message
     Other(String),
 }
 
+impl IntanError {
+    /// Wraps this error with the file it concerns
+    pub(crate) fn in_file(self, path: &Path, offset: Option<u64>) -> IntanError {
+        IntanError::File {
+            path: path.to_path_buf(),
+            offset,
+            source: Box::new(self),
+        }
+    }
+
+    /// Returns the error without the file and field context of [`IntanError::File`]
+    /// and [`IntanError::HeaderField`].
+    pub fn root_cause(&self) -> &IntanError {
+        match self {
+            IntanError::File { source, .. } | IntanError::HeaderField { source, .. } => {
+                source.root_cause()
+            }
+            error => error,
+        }
+    }
+
+    /// Whether the error was raised while reading or parsing a file's bytes, so
+    /// that the read position locates it
+    pub(crate) fn is_read_error(&self) -> bool {
+        matches!(
+            self,
+            IntanError::UnrecognizedFileFormat
+                | IntanError::InvalidChannelType
+                | IntanError::FileSizeError
+                | IntanError::StringReadError
+                | IntanError::UnexpectedHeaderValue { .. }
+                | IntanError::IoError(_)
+        )
+    }
+}
+
 impl fmt::Display for IntanError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -395,17 +1334,137 @@ impl fmt::Display for IntanError {
             IntanError::InvalidChannelType => write!(f, "Invalid channel type"),
             IntanError::FileSizeError => write!(f, "File size error"),
             IntanError::StringReadError => write!(f, "Error reading string from file"),
-            IntanError::ChannelNotFound => write!(f, "Channel not found"),
+            IntanError::ChannelNotFound(selector) => {
+                write!(f, "No {} in the recording", selector)
+            }
+            IntanError::UnexpectedHeaderValue { field, value } => {
+                write!(f, "Unexpected {} in header: {}", field, value)
+            }
+            IntanError::HeaderField {
+                field,
+                offset,
+                source,
+            } => write!(f, "Failed reading {} at byte {}: {}", field, offset, source),
             IntanError::IoError(e) => write!(f, "IO error: {}", e),
+            IntanError::InvalidOptions(msg) => write!(f, "{}", msg),
+            IntanError::NoFiles { directory, pattern } => match (directory, pattern) {
+                (Some(directory), Some(pattern)) => write!(
+                    f,
+                    "No RHS files matching '{}' found in directory {}",
+                    pattern,
+                    directory.display()
+                ),
+                (Some(directory), None) => {
+                    write!(f, "No RHS files found in directory {}", directory.display())
+                }
+                (None, _) => write!(f, "No files to load"),
+            },
+            IntanError::IncompatibleHeaders(msg) => write!(f, "{}", msg),
+            IntanError::Gap {
+                missing_samples, ..
+            } if *missing_samples < 0 => write!(
+                f,
+                "Timestamps go back {} samples from the end of the file before",
+                -missing_samples
+            ),
+            IntanError::Gap {
+                missing_samples,
+                sample_rate,
+            } => write!(
+                f,
+                "Does not continue the file before it: {} samples ({:.3} s) are missing",
+                missing_samples,
+                *missing_samples as f64 / *sample_rate as f64
+            ),
+            IntanError::DuplicateFile {
+                original,
+                repeated_samples,
+                extra_samples,
+            } => {
+                write!(
+                    f,
+                    "Repeats samples {}..{} of {}",
+                    repeated_samples.start,
+                    repeated_samples.end,
+                    original.display()
+                )?;
+                if *extra_samples > 0 {
+                    write!(
+                        f,
+                        ", but continues past its end with {} more samples, so neither file \
+                         can be left out",
+                        extra_samples
+                    )
+                } else {
+                    write!(
+                        f,
+                        "; remove the copy, or load with DuplicateHandling::Deduplicate"
+                    )
+                }
+            }
+            IntanError::TimeRangeOutsideRecording { range, duration } => write!(
+                f,
+                "Time range {}..{} s is outside the recording, which is {:.3} s long",
+                range.start, range.end, duration
+            ),
+            IntanError::WallclockRangeOutsideRecording {
+                range,
+                recording_start,
+            } => write!(
+                f,
+                "Wall-clock range {}..{} is outside the recording, which starts at {}",
+                range.start, range.end, recording_start
+            ),
+            IntanError::ExceedsAddressSpace(what) => write!(
+                f,
+                "{} exceed the memory this platform can address; select fewer channels or \
+                 a shorter time range, or stream the recording",
+                what
+            ),
+            IntanError::File {
+                path,
+                offset,
+                source,
+            } => {
+                write!(f, "{}", path.display())?;
+                if let Some(offset) = offset {
+                    write!(f, " (at byte {})", offset)?;
+                }
+                write!(f, ": {}", source)
+            }
             IntanError::Other(msg) => write!(f, "{}", msg),
         }
     }
 }
 
-impl Error for IntanError {}
+impl Error for IntanError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IntanError::IoError(e) => Some(e),
+            IntanError::File { source, .. } | IntanError::HeaderField { source, .. } => {
+                Some(source.as_ref())
+            }
+            _ => None,
+        }
+    }
+}
 
 impl From<io::Error> for IntanError {
     fn from(error: io::Error) -> Self {
         IntanError::IoError(error)
     }
 }
+
+impl From<Box<dyn Error>> for IntanError {
+    /// Recovers the `IntanError` or I/O error inside, and keeps the message of any
+    /// other error
+    fn from(error: Box<dyn Error>) -> Self {
+        match error.downcast::<IntanError>() {
+            Ok(error) => *error,
+            Err(error) => match error.downcast::<io::Error>() {
+                Ok(error) => IntanError::IoError(*error),
+                Err(error) => IntanError::Other(error.to_string()),
+            },
+        }
+    }
+}
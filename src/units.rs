@@ -0,0 +1,496 @@
+//! Physical units of the analog signals.
+//!
+//! Intan's conventions mix units across signal types: amplifier data is in μV,
+//! DC amplifier, ADC, and DAC data are in V, and stimulation current is in μA.
+//! Every [`RhsData`] records the unit of each signal in its [`SignalUnits`], and
+//! the conversion helpers below always start from the recorded unit, so data that
+//! was already converted is never scaled twice.
+//!
+//! Data loaded with [`LoadOptions::raw`](crate::loader::LoadOptions::raw) keeps
+//! the amplifier, DC amplifier, ADC, and DAC signals as raw 16-bit codes.
+//! [`RhsData::scale_factors`] describes how the codes map to physical units, the
+//! conversion helpers scale the codes when the scaled signal is absent, and
+//! [`RhsData::scale_raw`] replaces the codes with scaled data in place.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::{load, Unit};
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! let data = rhs_file.data.as_ref().unwrap();
+//!
+//! // Amplifier and ADC data on a common millivolt scale
+//! let amplifier_mv = data.amplifier_in_mv().unwrap();
+//! let adc_mv = data.adc_in_mv();
+//! assert_eq!(data.units.amplifier, Unit::Microvolts);
+//! # let _ = (amplifier_mv, adc_mv);
+//!
+//! // Raw codes, scaled one channel at a time
+//! use intan_importer::Loader;
+//!
+//! let rhs_file = Loader::new("recording.rhs").raw(true).load().unwrap();
+//! let data = rhs_file.data.as_ref().unwrap();
+//! let codes = data.amplifier_data_raw.as_ref().unwrap();
+//! let scale = data.scale_factors().amplifier;
+//! let first_channel_uv: Vec<f64> = codes.row(0).iter().map(|&c| scale.apply(c)).collect();
+//! # let _ = first_channel_uv;
+//! ```
+
+use ndarray::Array2;
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::reader::{
+    Sample, ADC_DAC_OFFSET, ADC_DAC_SCALE_FACTOR, AMPLIFIER_SCALE_FACTOR, DC_AMPLIFIER_OFFSET,
+    DC_AMPLIFIER_SCALE_FACTOR,
+};
+use crate::spill::SpilledSignal;
+use crate::types::{IntanError, RhsData};
+
+/// A physical unit of an analog signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Unit {
+    /// Microvolts (μV)
+    Microvolts,
+    /// Millivolts (mV)
+    Millivolts,
+    /// Volts (V)
+    Volts,
+    /// Nanoamperes (nA)
+    Nanoamps,
+    /// Microamperes (μA)
+    Microamps,
+    /// Milliamperes (mA)
+    Milliamps,
+}
+
+impl Unit {
+    /// Returns the unit's symbol, e.g. `"μV"`.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Unit::Microvolts => "μV",
+            Unit::Millivolts => "mV",
+            Unit::Volts => "V",
+            Unit::Nanoamps => "nA",
+            Unit::Microamps => "μA",
+            Unit::Milliamps => "mA",
+        }
+    }
+
+    /// Returns the factor that converts a value in this unit into `target`, or
+    /// `None` if the units measure different quantities (voltage vs. current).
+    pub fn factor_to(&self, target: Unit) -> Option<f64> {
+        let (multiplier, divisor) = self.ratio_to(target)?;
+        Some(multiplier / divisor)
+    }
+
+    /// Conversion factor as an exact power-of-ten multiplier and divisor.
+    ///
+    /// Dividing by 1000 rather than multiplying by 0.001 keeps converted values
+    /// correctly rounded, so μV values survive a round trip through mV unchanged.
+    fn ratio_to(&self, target: Unit) -> Option<(f64, f64)> {
+        let (from_quantity, from_exponent) = self.quantity_and_exponent();
+        let (to_quantity, to_exponent) = target.quantity_and_exponent();
+        if from_quantity != to_quantity {
+            return None;
+        }
+        let exponent = from_exponent - to_exponent;
+        Some(if exponent >= 0 {
+            (10f64.powi(exponent), 1.0)
+        } else {
+            (1.0, 10f64.powi(-exponent))
+        })
+    }
+
+    /// Quantity kind (0 = voltage, 1 = current) and power of ten relative to the base unit
+    fn quantity_and_exponent(&self) -> (u8, i32) {
+        match self {
+            Unit::Microvolts => (0, -6),
+            Unit::Millivolts => (0, -3),
+            Unit::Volts => (0, 0),
+            Unit::Nanoamps => (1, -9),
+            Unit::Microamps => (1, -6),
+            Unit::Milliamps => (1, -3),
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+/// The unit of each analog signal type in an [`RhsData`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SignalUnits {
+    /// Unit of `amplifier_data` (μV when loaded)
+    pub amplifier: Unit,
+    /// Unit of `dc_amplifier_data` (V when loaded)
+    pub dc_amplifier: Unit,
+    /// Unit of `stim_data` (μA when loaded)
+    pub stim: Unit,
+    /// Unit of `board_adc_data` (V when loaded)
+    pub board_adc: Unit,
+    /// Unit of `board_dac_data` (V when loaded)
+    pub board_dac: Unit,
+}
+
+impl Default for SignalUnits {
+    /// The units produced by the loader.
+    fn default() -> Self {
+        SignalUnits {
+            amplifier: Unit::Microvolts,
+            dc_amplifier: Unit::Volts,
+            stim: Unit::Microamps,
+            board_adc: Unit::Volts,
+            board_dac: Unit::Volts,
+        }
+    }
+}
+
+/// Conversion of raw 16-bit codes to a physical unit: `(code - offset) * scale`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScaleFactor {
+    /// Code corresponding to zero
+    pub offset: f64,
+    /// Size of one code step, in `unit`
+    pub scale: f64,
+    /// Unit of the scaled values
+    pub unit: Unit,
+}
+
+impl ScaleFactor {
+    /// Converts a code to a value in [`unit`](ScaleFactor::unit).
+    pub fn apply(&self, code: u16) -> f64 {
+        (code as f64 - self.offset) * self.scale
+    }
+
+    /// Converts an array of codes to values in `target`, or returns `None` if
+    /// `target` measures a different quantity than [`unit`](ScaleFactor::unit).
+    pub fn apply_array(&self, codes: &Array2<u16>, target: Unit) -> Option<Array2<f64>> {
+        let (multiplier, divisor) = self.unit.ratio_to(target)?;
+        Some(codes.mapv(|code| self.apply(code) * multiplier / divisor))
+    }
+}
+
+/// The scale factor of each signal type that can be kept as raw codes.
+///
+/// These are fixed by the RHS format, so every [`RhsData`] returns the same values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScaleFactors {
+    /// Amplifier codes: 0.195 μV per step, offset 32768
+    pub amplifier: ScaleFactor,
+    /// DC amplifier codes: 19.23 mV per step, offset 512
+    pub dc_amplifier: ScaleFactor,
+    /// Board ADC codes: 0.0003125 V per step, offset 32768
+    pub board_adc: ScaleFactor,
+    /// Board DAC codes: 0.0003125 V per step, offset 32768
+    pub board_dac: ScaleFactor,
+}
+
+impl ScaleFactors {
+    /// Scale factors of RHS files.
+    pub const RHS: ScaleFactors = ScaleFactors {
+        amplifier: ScaleFactor {
+            offset: ADC_DAC_OFFSET,
+            scale: AMPLIFIER_SCALE_FACTOR,
+            unit: Unit::Microvolts,
+        },
+        dc_amplifier: ScaleFactor {
+            offset: DC_AMPLIFIER_OFFSET,
+            scale: DC_AMPLIFIER_SCALE_FACTOR,
+            unit: Unit::Millivolts,
+        },
+        board_adc: ScaleFactor {
+            offset: ADC_DAC_OFFSET,
+            scale: ADC_DAC_SCALE_FACTOR,
+            unit: Unit::Volts,
+        },
+        board_dac: ScaleFactor {
+            offset: ADC_DAC_OFFSET,
+            scale: ADC_DAC_SCALE_FACTOR,
+            unit: Unit::Volts,
+        },
+    };
+}
+
+impl RhsData {
+    /// Returns the factors converting the raw codes of each signal to physical units.
+    pub fn scale_factors(&self) -> ScaleFactors {
+        ScaleFactors::RHS
+    }
+
+    /// Replaces the raw codes of each signal with data scaled to the unit recorded
+    /// in [`units`](RhsData::units), exactly as a load without
+    /// [`LoadOptions::raw`](crate::loader::LoadOptions::raw) would produce it
+    /// (apart from the notch filter, which is not applied).
+    ///
+    /// Signals without raw codes are left unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a recorded unit measures a different quantity than the signal.
+    pub fn scale_raw(&mut self) {
+        let factors = self.scale_factors();
+        let units = self.units;
+        let signals = [
+            (
+                &mut self.amplifier_data_raw,
+                &mut self.amplifier_data,
+                factors.amplifier,
+                units.amplifier,
+            ),
+            (
+                &mut self.dc_amplifier_data_raw,
+                &mut self.dc_amplifier_data,
+                factors.dc_amplifier,
+                units.dc_amplifier,
+            ),
+            (
+                &mut self.board_adc_data_raw,
+                &mut self.board_adc_data,
+                factors.board_adc,
+                units.board_adc,
+            ),
+            (
+                &mut self.board_dac_data_raw,
+                &mut self.board_dac_data,
+                factors.board_dac,
+                units.board_dac,
+            ),
+        ];
+        for (codes, scaled, factor, unit) in signals {
+            if let Some(codes) = codes.take() {
+                *scaled = Some(factor.apply_array(&codes, unit).unwrap_or_else(|| {
+                    panic!("cannot convert {} to {}", factor.unit, unit)
+                }));
+            }
+        }
+    }
+
+    /// Returns the data with every analog signal in double precision, borrowed if
+    /// it already is
+    ///
+    /// The exporters write the double-precision fields, so they convert data loaded
+    /// as raw codes or in single precision with this rather than leave it out.
+    /// Spilled signals are not in memory, and return an error.
+    pub(crate) fn to_double_precision(&self) -> Result<Cow<'_, RhsData>, IntanError> {
+        let converted = self.spilled.is_some()
+            || [
+                &self.amplifier_data_raw,
+                &self.dc_amplifier_data_raw,
+                &self.board_adc_data_raw,
+                &self.board_dac_data_raw,
+            ]
+            .iter()
+            .any(|codes| codes.is_some())
+            || [
+                &self.amplifier_data_f32,
+                &self.dc_amplifier_data_f32,
+                &self.board_adc_data_f32,
+                &self.board_dac_data_f32,
+            ]
+            .iter()
+            .any(|data| data.is_some());
+        if !converted {
+            return Ok(Cow::Borrowed(self));
+        }
+        check_in_memory(self)?;
+        self.clone().into_double_precision().map(Cow::Owned)
+    }
+
+    /// Converts every analog signal to double precision in place, as
+    /// [`to_double_precision`](RhsData::to_double_precision) does
+    pub(crate) fn into_double_precision(mut self) -> Result<RhsData, IntanError> {
+        check_in_memory(&self)?;
+        self.scale_raw();
+        for (single, double) in [
+            (&mut self.amplifier_data_f32, &mut self.amplifier_data),
+            (&mut self.dc_amplifier_data_f32, &mut self.dc_amplifier_data),
+            (&mut self.board_adc_data_f32, &mut self.board_adc_data),
+            (&mut self.board_dac_data_f32, &mut self.board_dac_data),
+        ] {
+            if let Some(single) = single.take() {
+                *double = Some(single.mapv(f64::from));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Returns the amplifier data in double precision and `unit`, borrowed if it
+    /// already is, or `None` if no amplifier data was loaded
+    ///
+    /// Like [`to_double_precision`](RhsData::to_double_precision), but converts only
+    /// the amplifier signal, for the analyses that read nothing else. Spilled
+    /// amplifier data is not in memory, and returns an error.
+    pub(crate) fn amplifier_double_precision(
+        &self,
+        unit: Unit,
+    ) -> Result<Option<Cow<'_, Array2<f64>>>, IntanError> {
+        let current = self.units.amplifier;
+        if let Some(data) = self.amplifier_data.as_ref().filter(|_| current == unit) {
+            return Ok(Some(Cow::Borrowed(data)));
+        }
+        let factor = ScaleFactors::RHS.amplifier;
+        let converted = convert(&self.amplifier_data, current, unit)
+            .or_else(|| convert(&self.amplifier_data_f32, current, unit))
+            .or_else(|| factor.apply_array(self.amplifier_data_raw.as_ref()?, unit));
+        let spilled = self
+            .spilled
+            .as_ref()
+            .is_some_and(|spilled| spilled.num_channels(SpilledSignal::Amplifier) > 0);
+        if converted.is_none() && spilled {
+            return Err(IntanError::Other(
+                "Amplifier data spilled to disk is not in memory; read it back with \
+                 RhsData::load_spilled first"
+                    .to_string(),
+            ));
+        }
+        Ok(converted.map(Cow::Owned))
+    }
+
+    /// Amplifier data converted to μV.
+    pub fn amplifier_in_uv(&self) -> Option<Array2<f64>> {
+        let (unit, factor) = (self.units.amplifier, ScaleFactors::RHS.amplifier);
+        convert(&self.amplifier_data, unit, Unit::Microvolts)
+            .or_else(|| convert(&self.amplifier_data_f32, unit, Unit::Microvolts))
+            .or_else(|| factor.apply_array(self.amplifier_data_raw.as_ref()?, Unit::Microvolts))
+    }
+
+    /// Amplifier data converted to mV.
+    pub fn amplifier_in_mv(&self) -> Option<Array2<f64>> {
+        let (unit, factor) = (self.units.amplifier, ScaleFactors::RHS.amplifier);
+        convert(&self.amplifier_data, unit, Unit::Millivolts)
+            .or_else(|| convert(&self.amplifier_data_f32, unit, Unit::Millivolts))
+            .or_else(|| factor.apply_array(self.amplifier_data_raw.as_ref()?, Unit::Millivolts))
+    }
+
+    /// DC amplifier data converted to mV.
+    pub fn dc_amplifier_in_mv(&self) -> Option<Array2<f64>> {
+        let (unit, factor) = (self.units.dc_amplifier, ScaleFactors::RHS.dc_amplifier);
+        convert(&self.dc_amplifier_data, unit, Unit::Millivolts)
+            .or_else(|| convert(&self.dc_amplifier_data_f32, unit, Unit::Millivolts))
+            .or_else(|| factor.apply_array(self.dc_amplifier_data_raw.as_ref()?, Unit::Millivolts))
+    }
+
+    /// DC amplifier data converted to V.
+    pub fn dc_amplifier_in_v(&self) -> Option<Array2<f64>> {
+        let (unit, factor) = (self.units.dc_amplifier, ScaleFactors::RHS.dc_amplifier);
+        convert(&self.dc_amplifier_data, unit, Unit::Volts)
+            .or_else(|| convert(&self.dc_amplifier_data_f32, unit, Unit::Volts))
+            .or_else(|| factor.apply_array(self.dc_amplifier_data_raw.as_ref()?, Unit::Volts))
+    }
+
+    /// Board ADC data converted to mV.
+    pub fn adc_in_mv(&self) -> Option<Array2<f64>> {
+        let (unit, factor) = (self.units.board_adc, ScaleFactors::RHS.board_adc);
+        convert(&self.board_adc_data, unit, Unit::Millivolts)
+            .or_else(|| convert(&self.board_adc_data_f32, unit, Unit::Millivolts))
+            .or_else(|| factor.apply_array(self.board_adc_data_raw.as_ref()?, Unit::Millivolts))
+    }
+
+    /// Board ADC data converted to V.
+    pub fn adc_in_v(&self) -> Option<Array2<f64>> {
+        let (unit, factor) = (self.units.board_adc, ScaleFactors::RHS.board_adc);
+        convert(&self.board_adc_data, unit, Unit::Volts)
+            .or_else(|| convert(&self.board_adc_data_f32, unit, Unit::Volts))
+            .or_else(|| factor.apply_array(self.board_adc_data_raw.as_ref()?, Unit::Volts))
+    }
+
+    /// Board DAC data converted to mV.
+    pub fn dac_in_mv(&self) -> Option<Array2<f64>> {
+        let (unit, factor) = (self.units.board_dac, ScaleFactors::RHS.board_dac);
+        convert(&self.board_dac_data, unit, Unit::Millivolts)
+            .or_else(|| convert(&self.board_dac_data_f32, unit, Unit::Millivolts))
+            .or_else(|| factor.apply_array(self.board_dac_data_raw.as_ref()?, Unit::Millivolts))
+    }
+
+    /// Board DAC data converted to V.
+    pub fn dac_in_v(&self) -> Option<Array2<f64>> {
+        let (unit, factor) = (self.units.board_dac, ScaleFactors::RHS.board_dac);
+        convert(&self.board_dac_data, unit, Unit::Volts)
+            .or_else(|| convert(&self.board_dac_data_f32, unit, Unit::Volts))
+            .or_else(|| factor.apply_array(self.board_dac_data_raw.as_ref()?, Unit::Volts))
+    }
+
+    /// Stimulation current converted to μA.
+    pub fn stim_in_ua(&self) -> Option<Array2<f64>> {
+        convert(&self.stim_data, self.units.stim, Unit::Microamps)
+    }
+
+    /// Stimulation current converted to nA.
+    pub fn stim_in_na(&self) -> Option<Array2<f64>> {
+        convert(&self.stim_data, self.units.stim, Unit::Nanoamps)
+    }
+
+    /// Rescales the analog signals in place to the units in `units` and records them.
+    ///
+    /// Single-precision signals are rescaled in double precision and rounded once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a target unit measures a different quantity than the signal
+    /// (e.g. amplifier data in μA).
+    pub fn convert_units(&mut self, units: SignalUnits) {
+        let current = self.units;
+        let single_signals = [
+            (&mut self.amplifier_data_f32, current.amplifier, units.amplifier),
+            (&mut self.dc_amplifier_data_f32, current.dc_amplifier, units.dc_amplifier),
+            (&mut self.board_adc_data_f32, current.board_adc, units.board_adc),
+            (&mut self.board_dac_data_f32, current.board_dac, units.board_dac),
+        ];
+        for (data, current, target) in single_signals {
+            // Incompatible units panic below, with the double-precision signals
+            let ratio = current.ratio_to(target);
+            if let (Some(data), Some((multiplier, divisor))) = (data.as_mut(), ratio) {
+                if current != target {
+                    data.mapv_inplace(|x| (x as f64 * multiplier / divisor) as f32);
+                }
+            }
+        }
+
+        let signals = [
+            (&mut self.amplifier_data, &mut self.units.amplifier, units.amplifier),
+            (&mut self.dc_amplifier_data, &mut self.units.dc_amplifier, units.dc_amplifier),
+            (&mut self.stim_data, &mut self.units.stim, units.stim),
+            (&mut self.board_adc_data, &mut self.units.board_adc, units.board_adc),
+            (&mut self.board_dac_data, &mut self.units.board_dac, units.board_dac),
+        ];
+        for (data, current, target) in signals {
+            let (multiplier, divisor) = current.ratio_to(target).unwrap_or_else(|| {
+                panic!("cannot convert {} to {}", current, target)
+            });
+            if let Some(data) = data.as_mut() {
+                if current != &target {
+                    data.mapv_inplace(|x| x * multiplier / divisor);
+                }
+            }
+            *current = target;
+        }
+    }
+}
+
+/// Converts an optional signal between units, returning `None` if it is absent or
+/// the units are incompatible.
+fn convert<T: Sample>(data: &Option<Array2<T>>, from: Unit, to: Unit) -> Option<Array2<f64>> {
+    let (multiplier, divisor) = from.ratio_to(to)?;
+    data.as_ref().map(|d| d.mapv(|x| x.to_f64() * multiplier / divisor))
+}
+
+/// Returns an error if signals of `data` are spilled to disk
+fn check_in_memory(data: &RhsData) -> Result<(), IntanError> {
+    if data.spilled.is_some() {
+        return Err(IntanError::Other(
+            "Signals spilled to disk cannot be exported; read them back with \
+             RhsData::load_spilled first"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
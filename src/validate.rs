@@ -0,0 +1,364 @@
+//! Integrity checks of RHS files without loading their data.
+//!
+//! [`validate`] checks that a file is a well-formed RHS file: its magic number,
+//! the consistency of its header, its size against the size of its data blocks,
+//! the continuity of its timestamps, and the plausibility of its channel counts.
+//! Only the header and the timestamps of each data block are read, so archived
+//! sessions can be checked far faster than they load. Every problem found is
+//! listed in the [`ValidationReport`] rather than returned as an error.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::validate;
+//!
+//! let report = validate("archive/recording.rhs");
+//! if !report.is_valid() {
+//!     for issue in &report.issues {
+//!         println!("{}: {}", report.path.display(), issue);
+//!     }
+//! }
+//! ```
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use ndarray::Array1;
+
+use crate::loader::ParseMode;
+use crate::qc::GapSummary;
+use crate::reader::{self, RHS_MAGIC_NUMBER, SAMPLES_PER_DATA_BLOCK};
+use crate::signal::SignalType;
+use crate::types::{IntanError, LoadWarning, RhsHeader};
+
+/// Most channels of each signal type an RHS system records: four headstage ports
+/// of 32 stimulation channels, and the I/O of the controller
+const MAX_CHANNELS: [(SignalType, usize); 5] = [
+    (SignalType::Amplifier, 128),
+    (SignalType::BoardAdc, 8),
+    (SignalType::BoardDac, 8),
+    (SignalType::DigIn, 16),
+    (SignalType::DigOut, 16),
+];
+
+/// A problem found by [`validate`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationIssue {
+    /// The file could not be opened or read
+    Unreadable(String),
+    /// The file does not start with the RHS magic number
+    BadMagicNumber(u32),
+    /// The header could not be parsed
+    InvalidHeader {
+        /// Byte at which the field that could not be read starts
+        offset: u64,
+        /// Why parsing stopped
+        error: String,
+    },
+    /// The header holds a value the RHS format does not define
+    UnexpectedHeaderValue {
+        /// Description of the field, such as `signal type of channel A-000`
+        field: String,
+        /// The value found
+        value: String,
+    },
+    /// The sample rate is not a positive number
+    InvalidSampleRate(f32),
+    /// The lower bandwidth of the amplifiers is not below the upper bandwidth
+    InvalidBandwidth {
+        /// Actual lower bandwidth (Hz)
+        lower: f32,
+        /// Actual upper bandwidth (Hz)
+        upper: f32,
+    },
+    /// The file lists no enabled channels
+    NoChannels,
+    /// The file lists more channels of a signal type than an RHS system records
+    TooManyChannels {
+        /// Signal type of the channels
+        signal_type: SignalType,
+        /// Number of channels listed
+        count: usize,
+        /// Most channels an RHS system records
+        max: usize,
+    },
+    /// Two channels have the same native name
+    DuplicateChannelName(String),
+    /// The file does not end after a whole number of data blocks, as when a
+    /// recording was interrupted while writing
+    SizeMismatch {
+        /// Size of the header and the whole data blocks (bytes)
+        expected: u64,
+        /// Size of the file (bytes)
+        actual: u64,
+    },
+    /// The timestamps skip samples
+    TimestampGaps {
+        /// Number of places where consecutive timestamps differ by more than one
+        num_gaps: usize,
+        /// Total number of samples missing across all gaps
+        missing_samples: u64,
+    },
+    /// The timestamps go back or repeat, as when data blocks were written out of
+    /// order or the header does not describe the data
+    NonMonotonicTimestamps(usize),
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationIssue::Unreadable(error) => write!(f, "Cannot be read: {}", error),
+            ValidationIssue::BadMagicNumber(magic) => {
+                write!(f, "Not an RHS file (magic number {:#010x})", magic)
+            }
+            ValidationIssue::InvalidHeader { offset, error } => {
+                write!(f, "Invalid header at byte {}: {}", offset, error)
+            }
+            ValidationIssue::UnexpectedHeaderValue { field, value } => {
+                write!(f, "Unexpected {} in header: {}", field, value)
+            }
+            ValidationIssue::InvalidSampleRate(rate) => {
+                write!(f, "Invalid sample rate {} Hz", rate)
+            }
+            ValidationIssue::InvalidBandwidth { lower, upper } => write!(
+                f,
+                "Lower bandwidth {} Hz is not below upper bandwidth {} Hz",
+                lower, upper
+            ),
+            ValidationIssue::NoChannels => write!(f, "No enabled channels"),
+            ValidationIssue::TooManyChannels {
+                signal_type,
+                count,
+                max,
+            } => write!(
+                f,
+                "{} {} channels, more than the {} an RHS system records",
+                count, signal_type, max
+            ),
+            ValidationIssue::DuplicateChannelName(name) => {
+                write!(f, "More than one channel is named {}", name)
+            }
+            ValidationIssue::SizeMismatch { expected, actual } => write!(
+                f,
+                "File is {} bytes, {} bytes more than its whole data blocks",
+                actual,
+                actual - expected
+            ),
+            ValidationIssue::TimestampGaps {
+                num_gaps,
+                missing_samples,
+            } => write!(
+                f,
+                "{} gaps in the timestamps, missing {} samples",
+                num_gaps, missing_samples
+            ),
+            ValidationIssue::NonMonotonicTimestamps(count) => {
+                write!(f, "Timestamps go back or repeat {} times", count)
+            }
+        }
+    }
+}
+
+/// Result of [`validate`].
+#[derive(Debug, Clone)]
+pub struct ValidationReport {
+    /// Path of the file
+    pub path: PathBuf,
+    /// Size of the file in bytes, 0 if it could not be read
+    pub file_size: u64,
+    /// Header of the file, or `None` if it could not be parsed
+    pub header: Option<RhsHeader>,
+    /// Number of samples per channel in the file's whole data blocks
+    pub num_samples: usize,
+    /// Discontinuities in the timestamps
+    pub gaps: GapSummary,
+    /// Problems found, empty for a well-formed file
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Returns whether no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Checks the integrity of an RHS file without reading its signals.
+///
+/// The header is parsed as with [`ParseMode::Lenient`], so that every unexpected
+/// value is listed rather than only the first. A file whose magic number or header
+/// is invalid is not checked further. See the [module documentation](self).
+pub fn validate<P: AsRef<Path>>(path: P) -> ValidationReport {
+    let mut report = ValidationReport {
+        path: path.as_ref().to_path_buf(),
+        file_size: 0,
+        header: None,
+        num_samples: 0,
+        gaps: GapSummary::default(),
+        issues: Vec::new(),
+    };
+    if let Err(e) = check_file(&mut report) {
+        report
+            .issues
+            .push(ValidationIssue::Unreadable(e.to_string()));
+    }
+    report
+}
+
+/// Runs the checks of [`validate`] on the file of `report`, returning an error if
+/// the file cannot be read
+fn check_file(report: &mut ValidationReport) -> Result<(), Box<dyn Error>> {
+    let file = File::open(&report.path)?;
+    report.file_size = file.metadata()?.len();
+    // Only the timestamps at the start of each data block are read
+    let mut reader = BufReader::with_capacity(SAMPLES_PER_DATA_BLOCK * 4, file);
+
+    let magic_number = reader.read_u32::<LittleEndian>()?;
+    if magic_number != RHS_MAGIC_NUMBER {
+        report
+            .issues
+            .push(ValidationIssue::BadMagicNumber(magic_number));
+        return Ok(());
+    }
+    reader.seek(SeekFrom::Start(0))?;
+    let (header, warnings) = match reader::read_header_with_mode(&mut reader, ParseMode::Lenient) {
+        Ok(header) => header,
+        Err(e) => {
+            let issue = match IntanError::from(e) {
+                IntanError::HeaderField {
+                    field,
+                    offset,
+                    source,
+                } => ValidationIssue::InvalidHeader {
+                    offset,
+                    error: format!("failed reading {}: {}", field, source),
+                },
+                e => ValidationIssue::InvalidHeader {
+                    offset: reader.stream_position()?,
+                    error: e.to_string(),
+                },
+            };
+            report.issues.push(issue);
+            return Ok(());
+        }
+    };
+    for warning in warnings {
+        if let LoadWarning::UnexpectedHeaderValue { field, value, .. } = warning {
+            report
+                .issues
+                .push(ValidationIssue::UnexpectedHeaderValue { field, value });
+        }
+    }
+    check_header(&header, &mut report.issues);
+
+    let data_start = reader.stream_position()?;
+    let bytes_per_block = reader::get_bytes_per_data_block(&header)? as u64;
+    let num_blocks = (report.file_size - data_start) / bytes_per_block;
+    report.num_samples = reader::sample_index(num_blocks * SAMPLES_PER_DATA_BLOCK as u64)?;
+    let expected = data_start + num_blocks * bytes_per_block;
+    if expected != report.file_size {
+        report.issues.push(ValidationIssue::SizeMismatch {
+            expected,
+            actual: report.file_size,
+        });
+    }
+
+    report.gaps = read_gaps(&mut reader, num_blocks, bytes_per_block)?;
+    if report.gaps.num_gaps > 0 {
+        report.issues.push(ValidationIssue::TimestampGaps {
+            num_gaps: report.gaps.num_gaps,
+            missing_samples: report.gaps.missing_samples,
+        });
+    }
+    if report.gaps.num_non_monotonic > 0 {
+        report.issues.push(ValidationIssue::NonMonotonicTimestamps(
+            report.gaps.num_non_monotonic,
+        ));
+    }
+    report.header = Some(header);
+    Ok(())
+}
+
+/// Adds the inconsistencies of `header` to `issues`
+fn check_header(header: &RhsHeader, issues: &mut Vec<ValidationIssue>) {
+    if !(header.sample_rate.is_finite() && header.sample_rate > 0.0) {
+        issues.push(ValidationIssue::InvalidSampleRate(header.sample_rate));
+    }
+    if header.actual_lower_bandwidth >= header.actual_upper_bandwidth {
+        issues.push(ValidationIssue::InvalidBandwidth {
+            lower: header.actual_lower_bandwidth,
+            upper: header.actual_upper_bandwidth,
+        });
+    }
+
+    let channel_lists = [
+        &header.amplifier_channels,
+        &header.board_adc_channels,
+        &header.board_dac_channels,
+        &header.board_dig_in_channels,
+        &header.board_dig_out_channels,
+    ];
+    if channel_lists.iter().all(|channels| channels.is_empty()) {
+        issues.push(ValidationIssue::NoChannels);
+    }
+    for ((signal_type, max), channels) in MAX_CHANNELS.into_iter().zip(channel_lists) {
+        if channels.len() > max {
+            issues.push(ValidationIssue::TooManyChannels {
+                signal_type,
+                count: channels.len(),
+                max,
+            });
+        }
+    }
+
+    let mut names = HashSet::new();
+    for channel in channel_lists.into_iter().flatten() {
+        if !names.insert(&channel.native_channel_name) {
+            issues.push(ValidationIssue::DuplicateChannelName(
+                channel.native_channel_name.clone(),
+            ));
+        }
+    }
+}
+
+/// Reads the timestamps of `num_blocks` data blocks from the start of the first
+/// one, skipping their signals, and summarizes their discontinuities
+fn read_gaps(
+    reader: &mut BufReader<File>,
+    num_blocks: u64,
+    bytes_per_block: u64,
+) -> Result<GapSummary, Box<dyn Error>> {
+    let signal_bytes = (bytes_per_block - SAMPLES_PER_DATA_BLOCK as u64 * 4) as i64;
+    let mut stored = [0i32; SAMPLES_PER_DATA_BLOCK];
+    let mut timestamps = Array1::<i64>::zeros(SAMPLES_PER_DATA_BLOCK);
+    let mut previous = None;
+    let mut gaps = GapSummary::default();
+
+    for _ in 0..num_blocks {
+        reader.read_i32_into::<LittleEndian>(&mut stored)?;
+        reader.seek_relative(signal_bytes)?;
+        for (timestamp, &value) in timestamps.iter_mut().zip(&stored) {
+            *timestamp = value as i64;
+        }
+        reader::unwrap_timestamps(timestamps.view_mut(), previous);
+
+        for &timestamp in &timestamps {
+            if let Some(previous) = previous {
+                let step = timestamp - previous;
+                if step > 1 {
+                    gaps.num_gaps += 1;
+                    gaps.missing_samples += (step - 1) as u64;
+                } else if step < 1 {
+                    gaps.num_non_monotonic += 1;
+                }
+            }
+            previous = Some(timestamp);
+        }
+    }
+    Ok(gaps)
+}
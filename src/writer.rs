@@ -0,0 +1,553 @@
+//! Saving recordings in the native RHS binary format.
+//!
+//! [`RhsFile::save`] serializes a header and its data back into an `.rhs` file that
+//! the official Intan tools (and [`load`](crate::load)) can read. This makes it
+//! possible to load a recording, modify it (e.g. drop bad channels or anonymize
+//! it), and hand a valid file to colleagues.
+//!
+//! The header and data must stay consistent: every channel listed in the header
+//! needs a matching row in the corresponding data array. Analog values are
+//! converted back to 16-bit ADC codes, so values that are not whole ADC steps
+//! (e.g. after filtering) are rounded to the nearest step. Signals loaded as raw
+//! codes with [`LoadOptions::raw`](crate::loader::LoadOptions::raw) are written
+//! unchanged, and single-precision signals are converted like double-precision ones.
+//! Timestamps are stored in 32 bits as RHX stores them, so timestamps past the
+//! 32-bit range wrap around and are unwrapped again when the file is loaded.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use intan_importer::load;
+//!
+//! let rhs_file = load("recording.rhs").unwrap();
+//! rhs_file.save("recording_copy.rhs").unwrap();
+//! ```
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use ndarray::Array2;
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::flags::StimFlags;
+use crate::reader::{
+    ADC_DAC_OFFSET, ADC_DAC_SCALE_FACTOR, AMPLIFIER_SCALE_FACTOR, DC_AMPLIFIER_OFFSET,
+    DC_AMPLIFIER_SCALE_FACTOR, RHS_MAGIC_NUMBER, SAMPLES_PER_DATA_BLOCK,
+};
+use crate::types::{ChannelInfo, IntanError, RhsData, RhsFile, RhsHeader, SpikeTrigger};
+use crate::units::Unit;
+
+impl RhsFile {
+    /// Saves the recording as an RHS file.
+    ///
+    /// The number of samples must be a multiple of 128, since RHS files consist of
+    /// whole data blocks. Data the loader notch filtered (files older than version
+    /// 3.0 with a notch filter set) is saved with version 3.0, which marks the
+    /// filter as already applied so it is not applied a second time when reloaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data does not match the channels in the header, the
+    /// number of samples is not a multiple of 128, or the file cannot be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Serializes the recording in the RHS format to any writer.
+    ///
+    /// See [`RhsFile::save`] for details.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        let data = self.data.as_ref().filter(|_| self.data_present);
+        let blocks = match data {
+            Some(data) => Some(DataBlocks::new(&self.header, data)?),
+            None => None,
+        };
+
+        let dc_amplifier_data_saved = match &blocks {
+            Some(blocks) => blocks.dc_amplifier.is_some(),
+            None => self.header.dc_amplifier_data_saved,
+        };
+        write_header(writer, &self.header, dc_amplifier_data_saved)?;
+
+        if let Some(blocks) = blocks {
+            blocks.write(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the header in the same field order the reader expects.
+fn write_header<W: Write>(
+    writer: &mut W,
+    header: &RhsHeader,
+    dc_amplifier_data_saved: bool,
+) -> Result<(), Box<dyn Error>> {
+    writer.write_u32::<LittleEndian>(RHS_MAGIC_NUMBER)?;
+
+    // Before version 3.0 the notch filter was applied by the loader, so the saved
+    // (already filtered) data is labeled as version 3.0
+    let (major, minor) = if header.version.major < 3 && header.notch_filter_frequency.is_some() {
+        (3, 0)
+    } else {
+        (header.version.major, header.version.minor)
+    };
+    writer.write_i16::<LittleEndian>(major as i16)?;
+    writer.write_i16::<LittleEndian>(minor as i16)?;
+
+    writer.write_f32::<LittleEndian>(header.sample_rate)?;
+
+    writer.write_i16::<LittleEndian>(header.dsp_enabled as i16)?;
+    for value in [
+        header.actual_dsp_cutoff_frequency,
+        header.actual_lower_bandwidth,
+        header.actual_lower_settle_bandwidth,
+        header.actual_upper_bandwidth,
+        header.desired_dsp_cutoff_frequency,
+        header.desired_lower_bandwidth,
+        header.desired_lower_settle_bandwidth,
+        header.desired_upper_bandwidth,
+    ] {
+        writer.write_f32::<LittleEndian>(value)?;
+    }
+
+    let notch_filter_mode = match header.notch_filter_frequency {
+        Some(50) => 1,
+        Some(60) => 2,
+        _ => 0,
+    };
+    writer.write_i16::<LittleEndian>(notch_filter_mode)?;
+
+    writer.write_f32::<LittleEndian>(header.desired_impedance_test_frequency)?;
+    writer.write_f32::<LittleEndian>(header.actual_impedance_test_frequency)?;
+
+    writer.write_i16::<LittleEndian>(header.amp_settle_mode as i16)?;
+    writer.write_i16::<LittleEndian>(header.charge_recovery_mode as i16)?;
+
+    writer.write_f32::<LittleEndian>(header.stim_step_size)?;
+    writer.write_f32::<LittleEndian>(header.recovery_current_limit)?;
+    writer.write_f32::<LittleEndian>(header.recovery_target_voltage)?;
+
+    write_qstring(writer, &header.notes.note1)?;
+    write_qstring(writer, &header.notes.note2)?;
+    write_qstring(writer, &header.notes.note3)?;
+
+    writer.write_i16::<LittleEndian>(dc_amplifier_data_saved as i16)?;
+    writer.write_i16::<LittleEndian>(header.eval_board_mode as i16)?;
+    write_qstring(writer, &header.reference_channel)?;
+
+    write_signal_groups(writer, header)?;
+
+    Ok(())
+}
+
+/// A channel paired with its signal type code and spike trigger settings
+struct ChannelRecord<'a> {
+    channel: &'a ChannelInfo,
+    signal_type: i16,
+    trigger: Option<&'a SpikeTrigger>,
+}
+
+/// Writes the channels grouped into signal groups by port
+///
+/// Only enabled channels are kept in the header, so every written channel is
+/// marked as enabled. Channels sharing a port name and prefix are written to the
+/// same group, in the order they appear.
+fn write_signal_groups<W: Write>(writer: &mut W, header: &RhsHeader) -> Result<(), Box<dyn Error>> {
+    let amplifier_records = header
+        .amplifier_channels
+        .iter()
+        .enumerate()
+        .map(|(i, channel)| ChannelRecord {
+            channel,
+            signal_type: 0,
+            trigger: header.spike_triggers.get(i),
+        });
+    let other_records = [
+        (&header.aux_input_channels, 1),
+        (&header.supply_voltage_channels, 2),
+        (&header.board_adc_channels, 3),
+        (&header.board_dac_channels, 4),
+        (&header.board_dig_in_channels, 5),
+        (&header.board_dig_out_channels, 6),
+    ]
+    .into_iter()
+    .flat_map(|(channels, signal_type)| {
+        channels.iter().map(move |channel| ChannelRecord {
+            channel,
+            signal_type,
+            trigger: None,
+        })
+    });
+
+    let mut groups: Vec<(&str, &str, Vec<ChannelRecord>)> = Vec::new();
+    for record in amplifier_records.chain(other_records) {
+        let name = record.channel.port_name.as_str();
+        let prefix = record.channel.port_prefix.as_str();
+        match groups.iter_mut().find(|(n, p, _)| *n == name && *p == prefix) {
+            Some((_, _, records)) => records.push(record),
+            None => groups.push((name, prefix, vec![record])),
+        }
+    }
+
+    writer.write_i16::<LittleEndian>(groups.len() as i16)?;
+    for (name, prefix, records) in &groups {
+        write_qstring(writer, name)?;
+        write_qstring(writer, prefix)?;
+        writer.write_i16::<LittleEndian>(1)?; // enabled
+        writer.write_i16::<LittleEndian>(records.len() as i16)?;
+        let num_amplifier_channels = records.iter().filter(|r| r.signal_type == 0).count();
+        writer.write_i16::<LittleEndian>(num_amplifier_channels as i16)?;
+
+        for record in records {
+            write_channel(writer, record)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes one channel record
+fn write_channel<W: Write>(writer: &mut W, record: &ChannelRecord) -> Result<(), Box<dyn Error>> {
+    let channel = record.channel;
+
+    write_qstring(writer, &channel.native_channel_name)?;
+    write_qstring(writer, &channel.custom_channel_name)?;
+    writer.write_i16::<LittleEndian>(channel.native_order as i16)?;
+    writer.write_i16::<LittleEndian>(channel.custom_order as i16)?;
+    writer.write_i16::<LittleEndian>(record.signal_type)?;
+    writer.write_i16::<LittleEndian>(1)?; // enabled
+    writer.write_i16::<LittleEndian>(channel.chip_channel as i16)?;
+    writer.write_i16::<LittleEndian>(channel.command_stream as i16)?;
+    writer.write_i16::<LittleEndian>(channel.board_stream as i16)?;
+
+    let (mode, threshold, digital_channel, polarity) = record
+        .trigger
+        .map(|t| {
+            (
+                t.voltage_trigger_mode,
+                t.voltage_threshold,
+                t.digital_trigger_channel,
+                t.digital_edge_polarity,
+            )
+        })
+        .unwrap_or_default();
+    for value in [mode, threshold, digital_channel, polarity] {
+        writer.write_i16::<LittleEndian>(value as i16)?;
+    }
+
+    writer.write_f32::<LittleEndian>(channel.electrode_impedance_magnitude)?;
+    writer.write_f32::<LittleEndian>(channel.electrode_impedance_phase)?;
+
+    Ok(())
+}
+
+/// Writes a QString (UTF-16 with a 4-byte byte-length prefix)
+fn write_qstring<W: Write>(writer: &mut W, value: &str) -> Result<(), Box<dyn Error>> {
+    let units: Vec<u16> = value.encode_utf16().collect();
+    writer.write_u32::<LittleEndian>((units.len() * 2) as u32)?;
+    for unit in units {
+        writer.write_u16::<LittleEndian>(unit)?;
+    }
+    Ok(())
+}
+
+/// Data converted back to the raw 16-bit words stored in data blocks
+struct DataBlocks<'a> {
+    data: &'a RhsData,
+    num_samples: usize,
+    amplifier: Option<Array2<u16>>,
+    dc_amplifier: Option<Array2<u16>>,
+    stim: Option<Array2<u16>>,
+    board_adc: Option<Array2<u16>>,
+    board_dac: Option<Array2<u16>>,
+    board_dig_in: Option<Vec<u16>>,
+    board_dig_out: Option<Vec<u16>>,
+}
+
+impl<'a> DataBlocks<'a> {
+    /// Checks the data against the header and converts it to raw words
+    fn new(header: &RhsHeader, data: &'a RhsData) -> Result<DataBlocks<'a>, Box<dyn Error>> {
+        let num_samples = data.timestamps.len();
+        if !num_samples.is_multiple_of(SAMPLES_PER_DATA_BLOCK) {
+            return Err(Box::new(IntanError::Other(format!(
+                "Cannot save {} samples: RHS files hold whole data blocks of {} samples",
+                num_samples, SAMPLES_PER_DATA_BLOCK
+            ))));
+        }
+
+        let num_amplifier_channels = header.amplifier_channels.len();
+        let amplifier = check_codes(
+            "amplifier",
+            data.amplifier_data_raw.as_ref(),
+            data.amplifier_data.is_none() && data.amplifier_data_f32.is_none(),
+            num_amplifier_channels,
+            num_samples,
+        )?;
+        let dc_amplifier = check_codes(
+            "DC amplifier",
+            data.dc_amplifier_data_raw.as_ref(),
+            data.dc_amplifier_data.is_none() && data.dc_amplifier_data_f32.is_none(),
+            num_amplifier_channels,
+            num_samples,
+        )?;
+        let board_adc = check_codes(
+            "board ADC",
+            data.board_adc_data_raw.as_ref(),
+            data.board_adc_data.is_none() && data.board_adc_data_f32.is_none(),
+            header.board_adc_channels.len(),
+            num_samples,
+        )?;
+        let board_dac = check_codes(
+            "board DAC",
+            data.board_dac_data_raw.as_ref(),
+            data.board_dac_data.is_none() && data.board_dac_data_f32.is_none(),
+            header.board_dac_channels.len(),
+            num_samples,
+        )?;
+
+        let amplifier_data = double_precision(&data.amplifier_data, &data.amplifier_data_f32);
+        let dc_amplifier_data =
+            double_precision(&data.dc_amplifier_data, &data.dc_amplifier_data_f32);
+        let board_adc_data = double_precision(&data.board_adc_data, &data.board_adc_data_f32);
+        let board_dac_data = double_precision(&data.board_dac_data, &data.board_dac_data_f32);
+
+        let amplifier_data = check_signal(
+            "amplifier",
+            amplifier_data.as_deref(),
+            num_amplifier_channels,
+            num_samples,
+            amplifier.is_none(),
+        )?;
+        let dc_amplifier_data = check_signal(
+            "DC amplifier",
+            dc_amplifier_data.as_deref(),
+            num_amplifier_channels,
+            num_samples,
+            false,
+        )?;
+        let stim = check_signal(
+            "stimulation",
+            data.stim_data.as_ref(),
+            num_amplifier_channels,
+            num_samples,
+            true,
+        )?;
+        let board_adc_data = check_signal(
+            "board ADC",
+            board_adc_data.as_deref(),
+            header.board_adc_channels.len(),
+            num_samples,
+            board_adc.is_none(),
+        )?;
+        let board_dac_data = check_signal(
+            "board DAC",
+            board_dac_data.as_deref(),
+            header.board_dac_channels.len(),
+            num_samples,
+            board_dac.is_none(),
+        )?;
+        let board_dig_in = check_signal(
+            "digital input",
+            data.board_dig_in_data.as_ref(),
+            header.board_dig_in_channels.len(),
+            num_samples,
+            true,
+        )?;
+        let board_dig_out = check_signal(
+            "digital output",
+            data.board_dig_out_data.as_ref(),
+            header.board_dig_out_channels.len(),
+            num_samples,
+            true,
+        )?;
+
+        let units = &data.units;
+        Ok(DataBlocks {
+            data,
+            num_samples,
+            amplifier: amplifier.or_else(|| {
+                let factor = to_unit(units.amplifier, Unit::Microvolts) / AMPLIFIER_SCALE_FACTOR;
+                amplifier_data.map(|d| d.mapv(|x| to_code(x * factor + ADC_DAC_OFFSET)))
+            }),
+            dc_amplifier: dc_amplifier.or_else(|| {
+                let factor = to_unit(units.dc_amplifier, Unit::Millivolts) / DC_AMPLIFIER_SCALE_FACTOR;
+                dc_amplifier_data.map(|d| d.mapv(|x| to_code(x * factor + DC_AMPLIFIER_OFFSET)))
+            }),
+            stim: stim.map(|d| {
+                let step_size = header.stim_step_size as f64 / to_unit(units.stim, Unit::Microamps);
+                stim_words(data, d, step_size)
+            }),
+            board_adc: board_adc.or_else(|| {
+                let factor = to_unit(units.board_adc, Unit::Volts) / ADC_DAC_SCALE_FACTOR;
+                board_adc_data.map(|d| d.mapv(|x| to_code(x * factor + ADC_DAC_OFFSET)))
+            }),
+            board_dac: board_dac.or_else(|| {
+                let factor = to_unit(units.board_dac, Unit::Volts) / ADC_DAC_SCALE_FACTOR;
+                board_dac_data.map(|d| d.mapv(|x| to_code(x * factor + ADC_DAC_OFFSET)))
+            }),
+            board_dig_in: board_dig_in.map(|d| digital_words(d, &header.board_dig_in_channels)),
+            board_dig_out: board_dig_out.map(|d| digital_words(d, &header.board_dig_out_channels)),
+        })
+    }
+
+    /// Writes all data blocks
+    ///
+    /// Samples are interleaved within each signal type the same way the reader
+    /// de-interleaves them, so saved files load back unchanged.
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        let mut buffer = Vec::new();
+
+        for start in (0..self.num_samples).step_by(SAMPLES_PER_DATA_BLOCK) {
+            let end = start + SAMPLES_PER_DATA_BLOCK;
+            buffer.clear();
+
+            // Timestamps are stored in 32 bits, wrapping around as RHX's counter does
+            for &timestamp in self.data.timestamps.slice(ndarray::s![start..end]) {
+                buffer.extend_from_slice(&(timestamp as i32).to_le_bytes());
+            }
+
+            for signal in [
+                &self.amplifier,
+                &self.dc_amplifier,
+                &self.stim,
+                &self.board_adc,
+                &self.board_dac,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                for s in start..end {
+                    for &word in signal.column(s) {
+                        buffer.extend_from_slice(&word.to_le_bytes());
+                    }
+                }
+            }
+
+            for words in [&self.board_dig_in, &self.board_dig_out].into_iter().flatten() {
+                for &word in &words[start..end] {
+                    buffer.extend_from_slice(&word.to_le_bytes());
+                }
+            }
+
+            writer.write_all(&buffer)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that a signal has one row per header channel and one column per sample
+///
+/// Returns `None` for signals without channels. A missing signal is an error if
+/// the header lists channels for it and it is `required`.
+fn check_signal<'a, T>(
+    name: &str,
+    signal: Option<&'a Array2<T>>,
+    num_channels: usize,
+    num_samples: usize,
+    required: bool,
+) -> Result<Option<&'a Array2<T>>, Box<dyn Error>> {
+    if num_channels == 0 {
+        return Ok(None);
+    }
+
+    match signal {
+        Some(signal) if signal.dim() == (num_channels, num_samples) => Ok(Some(signal)),
+        Some(signal) => Err(Box::new(IntanError::Other(format!(
+            "Cannot save {} data of shape {:?}: header lists {} channels and there are {} samples",
+            name,
+            signal.shape(),
+            num_channels,
+            num_samples
+        )))),
+        None if required => Err(Box::new(IntanError::Other(format!(
+            "Cannot save: header lists {} {} channels but the data has none",
+            num_channels, name
+        )))),
+        None => Ok(None),
+    }
+}
+
+/// Returns the double-precision data of a signal, widening its single-precision
+/// data if it has no double-precision data
+fn double_precision<'a>(
+    data: &'a Option<Array2<f64>>,
+    data_f32: &Option<Array2<f32>>,
+) -> Option<Cow<'a, Array2<f64>>> {
+    match (data, data_f32) {
+        (Some(data), _) => Some(Cow::Borrowed(data)),
+        (None, Some(data_f32)) => Some(Cow::Owned(data_f32.mapv(f64::from))),
+        (None, None) => None,
+    }
+}
+
+/// Checks the raw codes of a signal like [`check_signal`] and copies them
+///
+/// Codes are only used when the signal has no scaled data (`unscaled`); scaled
+/// data takes precedence, since it may have been modified after loading.
+fn check_codes(
+    name: &str,
+    codes: Option<&Array2<u16>>,
+    unscaled: bool,
+    num_channels: usize,
+    num_samples: usize,
+) -> Result<Option<Array2<u16>>, Box<dyn Error>> {
+    if !unscaled {
+        return Ok(None);
+    }
+    Ok(check_signal(name, codes, num_channels, num_samples, false)?.cloned())
+}
+
+/// Factor converting values recorded in `from` to the unit the RHS scale factors use
+fn to_unit(from: Unit, to: Unit) -> f64 {
+    // Units are only ever converted within the same quantity, so this cannot fail
+    from.factor_to(to).unwrap_or(1.0)
+}
+
+/// Rounds a value to the nearest unsigned 16-bit ADC code
+fn to_code(value: f64) -> u16 {
+    value.round().clamp(0.0, u16::MAX as f64) as u16
+}
+
+/// Packs stimulation current and status flags back into stim words
+fn stim_words(data: &RhsData, stim: &Array2<f64>, step_size: f64) -> Array2<u16> {
+    let flag = |flags: &Option<StimFlags>, i: usize, j: usize, bit: u16| match flags {
+        Some(flags) if flags.get(i, j) == Some(true) => bit,
+        _ => 0,
+    };
+
+    Array2::from_shape_fn(stim.dim(), |(i, j)| {
+        let current = stim[[i, j]];
+        let amplitude = if step_size != 0.0 {
+            (current.abs() / step_size).round().min(255.0) as u16
+        } else {
+            0
+        };
+        let polarity = if current < 0.0 { 256 } else { 0 };
+
+        amplitude
+            | polarity
+            | flag(&data.compliance_limit_data, i, j, 32768)
+            | flag(&data.charge_recovery_data, i, j, 16384)
+            | flag(&data.amp_settle_data, i, j, 8192)
+    })
+}
+
+/// Packs per-channel digital states into one 16-bit word per sample
+fn digital_words(digital: &Array2<i32>, channels: &[ChannelInfo]) -> Vec<u16> {
+    let mut words = vec![0u16; digital.ncols()];
+    for (row, channel) in digital.rows().into_iter().zip(channels) {
+        for (word, &state) in words.iter_mut().zip(row.iter()) {
+            if state != 0 {
+                *word |= 1 << channel.native_order;
+            }
+        }
+    }
+    words
+}
@@ -0,0 +1,100 @@
+//! Analyses of recordings loaded as raw codes or in single precision.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use common::TempDir;
+use intan_importer::loader::DataType;
+use intan_importer::pyramid::PyramidOptions;
+use intan_importer::{Loader, RhsFile, RhsFileBuilder};
+
+/// Saves a recording with three quiet, one flat and one noisy amplifier channel,
+/// and returns its path
+fn save_recording(dir: &TempDir) -> PathBuf {
+    let path = dir.join("recording.rhs");
+    RhsFileBuilder::new()
+        .sample_rate(20000.0)
+        .num_samples(128 * 40)
+        .amplifier_channels(5)
+        .sine(0, 100.0, 20.0)
+        .sine(1, 150.0, 20.0)
+        .sine(2, 250.0, 20.0)
+        .sine(4, 1000.0, 400.0)
+        .save(&path)
+        .unwrap();
+    path
+}
+
+/// The recording at `path` loaded as `f64`, as raw codes, and as `f32`
+fn loads(path: &PathBuf) -> [(&'static str, RhsFile); 3] {
+    let load = |loader: Loader| loader.quiet().load().unwrap();
+    [
+        ("f64", load(Loader::new(path))),
+        ("raw", load(Loader::new(path).raw(true))),
+        ("f32", load(Loader::new(path).data_type(DataType::F32))),
+    ]
+}
+
+#[test]
+fn qc_reads_raw_and_single_precision_data() {
+    let dir = TempDir::new("analysis_qc");
+    let [(_, scaled), rest @ ..] = loads(&save_recording(&dir));
+    let expected = scaled.qc_report().unwrap();
+    assert_eq!(expected.bad_channels, ["A-003", "A-004"]);
+
+    for (name, rhs_file) in rest {
+        let report = rhs_file.qc_report().unwrap();
+        assert_eq!(report.bad_channels, expected.bad_channels, "{} load", name);
+        for (channel, expected) in report.channels.iter().zip(&expected.channels) {
+            let (rms, expected_rms) = (channel.rms.unwrap(), expected.rms.unwrap());
+            assert!((rms - expected_rms).abs() < 1e-3, "{} load", name);
+            assert_eq!(channel.issues, expected.issues, "{} load", name);
+        }
+    }
+}
+
+#[test]
+fn pyramids_and_groups_read_raw_and_single_precision_data() {
+    let dir = TempDir::new("analysis_pyramid");
+    let [(_, scaled), rest @ ..] = loads(&save_recording(&dir));
+    let options = PyramidOptions::default();
+    let expected = scaled.amplifier_pyramid(&options).unwrap().unwrap();
+    let expected_group = scaled.groups()[0].amplifier_data().unwrap().to_owned();
+
+    for (name, rhs_file) in rest {
+        let pyramid = rhs_file.amplifier_pyramid(&options).unwrap().unwrap();
+        assert_eq!(pyramid.levels.len(), expected.levels.len(), "{} load", name);
+        for (level, expected) in pyramid.levels.iter().zip(&expected.levels) {
+            assert_eq!(level.max, expected.max, "{} load", name);
+            assert_eq!(level.min, expected.min, "{} load", name);
+        }
+
+        let group = &rhs_file.groups()[0];
+        let data = group.amplifier_data().unwrap();
+        common::assert_close(name, &data.to_owned(), &expected_group, 1e-3);
+        let channel = group.channel_data(2).unwrap();
+        assert_eq!(channel.to_vec(), data.row(2).to_vec(), "{} load", name);
+    }
+}
+
+#[test]
+fn spilled_data_is_not_analyzed() {
+    let dir = TempDir::new("analysis_spilled");
+    let path = save_recording(&dir);
+    let spill_dir = dir.join("spill");
+    fs::create_dir(&spill_dir).unwrap();
+    let rhs_file = Loader::new(&path)
+        .spill_to(&spill_dir)
+        .quiet()
+        .load()
+        .unwrap();
+
+    let error = rhs_file.qc_report().unwrap_err();
+    assert!(error.to_string().contains("spilled"), "{}", error);
+    assert!(rhs_file
+        .amplifier_pyramid(&PyramidOptions::default())
+        .is_err());
+    assert!(rhs_file.groups()[0].amplifier_data().is_none());
+}
@@ -0,0 +1,145 @@
+//! The recordings `RhsFileBuilder` generates, on which the other tests rely.
+
+mod common;
+
+use common::TempDir;
+use intan_importer::{IntanError, Loader, RhsFileBuilder};
+
+/// Half an amplifier ADC step, in μV
+const AMPLIFIER_TOLERANCE: f64 = 0.195 / 2.0;
+
+#[test]
+fn signals_are_generated_as_described() {
+    let rhs_file = RhsFileBuilder::new()
+        .sample_rate(20000.0)
+        .num_samples(128 * 4)
+        .amplifier_channels(3)
+        .digital_inputs(2)
+        .first_timestamp(-100)
+        .stim_step_size(0.5)
+        .sine(0, 250.0, 100.0)
+        .sine(0, 1000.0, 20.0)
+        .stim_pulse(2, 50, 5, 3.0)
+        .stim_pulse(2, 500, 10, -4.0)
+        .digital_pulse(1, 10..20)
+        .build()
+        .unwrap();
+    assert_eq!(rhs_file.header.sample_rate, 20000.0);
+    let data = rhs_file.data.as_ref().unwrap();
+    let expected: Vec<i64> = (-100..412).collect();
+    assert_eq!(data.timestamps.to_vec(), expected);
+
+    let amplifier = data.amplifier_data.as_ref().unwrap();
+    for (j, &value) in amplifier.row(0).iter().enumerate() {
+        let t = j as f64 / 20000.0;
+        let sines = 100.0 * (2.0 * std::f64::consts::PI * 250.0 * t).sin()
+            + 20.0 * (2.0 * std::f64::consts::PI * 1000.0 * t).sin();
+        assert!((value - sines).abs() <= AMPLIFIER_TOLERANCE, "sample {}", j);
+    }
+    assert!(amplifier.row(1).iter().all(|&value| value == 0.0));
+
+    // Cathodic first, then anodic, cut off at the end of the recording
+    let stim = data.stim_data.as_ref().unwrap();
+    let pulse = |j: usize| match j {
+        50..=54 => -3.0,
+        55..=59 => 3.0,
+        500..=509 => 4.0,
+        510..=511 => -4.0,
+        _ => 0.0,
+    };
+    for (j, &value) in stim.row(2).iter().enumerate() {
+        assert_eq!(value, pulse(j), "sample {}", j);
+    }
+    assert!(stim.row(0).iter().all(|&value| value == 0.0));
+
+    let digital = data.board_dig_in_data.as_ref().unwrap();
+    for (j, &value) in digital.row(1).iter().enumerate() {
+        assert_eq!(value, (10..20).contains(&j) as i32, "sample {}", j);
+    }
+    assert!(digital.row(0).iter().all(|&value| value == 0));
+}
+
+#[test]
+fn channels_are_named_and_grouped_by_port() {
+    let header = RhsFileBuilder::new()
+        .amplifier_channels(40)
+        .board_adc_channels(2)
+        .digital_inputs(3)
+        .build()
+        .unwrap()
+        .header;
+
+    let names: Vec<&str> = header
+        .amplifier_channels
+        .iter()
+        .map(|channel| channel.native_channel_name.as_str())
+        .collect();
+    assert_eq!(names[..2], ["A-000", "A-001"]);
+    assert_eq!(names[31..34], ["A-031", "B-000", "B-001"]);
+    assert_eq!(header.amplifier_channels[33].port_prefix, "B");
+    assert_eq!(header.amplifier_channels[33].native_order, 1);
+    assert_eq!(
+        header.board_adc_channels[1].native_channel_name,
+        "ANALOG-IN-2"
+    );
+    assert_eq!(
+        header.board_dig_in_channels[2].native_channel_name,
+        "DIGITAL-IN-03"
+    );
+}
+
+#[test]
+fn saved_recordings_load_as_built() {
+    let dir = TempDir::new("builder_saved");
+    let path = dir.join("recording.rhs");
+    let builder = RhsFileBuilder::new()
+        .amplifier_channels(2)
+        .dc_amplifier(true)
+        .board_adc_channels(1)
+        .digital_inputs(1)
+        .sine(1, 300.0, 50.0)
+        .stim_pulse(0, 1000, 3, 2.0)
+        .digital_pulse(0, 0..128);
+    builder.save(&path).unwrap();
+    let built = builder.build().unwrap();
+    let loaded = Loader::new(&path).quiet().load().unwrap();
+
+    assert!(built.header.diff(&loaded.header).is_empty());
+    let (built, loaded) = (built.data.unwrap(), loaded.data.unwrap());
+    assert_eq!(loaded.timestamps, built.timestamps);
+    assert_eq!(loaded.amplifier_data, built.amplifier_data);
+    assert_eq!(loaded.dc_amplifier_data, built.dc_amplifier_data);
+    assert_eq!(loaded.stim_data, built.stim_data);
+    assert_eq!(loaded.board_adc_data, built.board_adc_data);
+    assert_eq!(loaded.board_dig_in_data, built.board_dig_in_data);
+    assert_eq!(std::fs::read(&path).unwrap(), builder.to_bytes().unwrap());
+}
+
+#[test]
+fn settings_beyond_the_format_are_rejected() {
+    let rejected = [
+        RhsFileBuilder::new().sample_rate(0.0),
+        RhsFileBuilder::new().board_adc_channels(9),
+        RhsFileBuilder::new().amplifier_channels(129),
+        RhsFileBuilder::new()
+            .amplifier_channels(2)
+            .sine(2, 10.0, 1.0),
+        RhsFileBuilder::new().stim_pulse(4, 0, 1, 1.0),
+        RhsFileBuilder::new().digital_pulse(0, 0..1),
+        RhsFileBuilder::new().stim_pulse(0, 0, 1, 256.0),
+    ];
+    for builder in &rejected {
+        let error = builder.build().unwrap_err();
+        assert!(
+            matches!(error.downcast_ref(), Some(IntanError::Other(_))),
+            "{:?}: {}",
+            builder,
+            error
+        );
+    }
+
+    // Within the limits, but not a whole number of data blocks for a file
+    let builder = RhsFileBuilder::new().num_samples(200);
+    assert_eq!(builder.build().unwrap().num_samples(), 200);
+    assert!(builder.to_bytes().is_err());
+}
@@ -0,0 +1,446 @@
+//! Combining the files of a directory built with `RhsFileBuilder`.
+
+mod common;
+
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use common::TempDir;
+use intan_importer::loader::DataType;
+use intan_importer::{
+    DuplicateHandling, GapHandling, IntanError, LoadWarning, Loader, RhsFileBuilder,
+};
+use ndarray::s;
+
+/// Samples per file in these tests: 10 data blocks
+const FILE_SAMPLES: usize = 1280;
+
+/// A one-file recording with a sine wave, starting at `first_timestamp`
+fn file(first_timestamp: i32) -> RhsFileBuilder {
+    RhsFileBuilder::new()
+        .num_samples(FILE_SAMPLES)
+        .amplifier_channels(2)
+        .board_adc_channels(1)
+        .digital_inputs(1)
+        .first_timestamp(first_timestamp)
+        .sine(0, 250.0, 100.0)
+        .digital_pulse(0, 100..300)
+}
+
+#[test]
+fn timestamps_wrapping_between_files_are_unwrapped() {
+    let dir = TempDir::new("combine_wrap");
+    // The first file ends on the largest 32-bit timestamp and the next one wraps
+    let first = i32::MAX - FILE_SAMPLES as i32 + 1;
+    file(first)
+        .save(dir.join("session_230105_101500.rhs"))
+        .unwrap();
+    file(i32::MIN)
+        .save(dir.join("session_230105_101501.rhs"))
+        .unwrap();
+
+    let recording = Loader::new(dir.path()).quiet().load().unwrap();
+    let timestamps = &recording.data.as_ref().unwrap().timestamps;
+    let expected: Vec<i64> = (first as i64..).take(2 * FILE_SAMPLES).collect();
+    assert_eq!(timestamps.to_vec(), expected);
+    assert!(recording.warnings.is_empty(), "{:?}", recording.warnings);
+}
+
+#[test]
+fn files_without_start_times_are_ordered_across_a_wrap() {
+    let dir = TempDir::new("combine_wrap_order");
+    // Named against recording order, so only the first timestamps can order them
+    let first = i32::MAX - FILE_SAMPLES as i32 + 1;
+    file(i32::MIN + FILE_SAMPLES as i32)
+        .save(dir.join("a.rhs"))
+        .unwrap();
+    file(i32::MIN).save(dir.join("b.rhs")).unwrap();
+    file(first).save(dir.join("c.rhs")).unwrap();
+
+    let recording = Loader::new(dir.path()).quiet().load().unwrap();
+    let names: Vec<String> = recording
+        .source_files
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|span| span.path.rsplit('/').next().unwrap().to_string())
+        .collect();
+    assert_eq!(names, ["c.rhs", "b.rhs", "a.rhs"]);
+    let timestamps = &recording.data.as_ref().unwrap().timestamps;
+    let expected: Vec<i64> = (first as i64..).take(3 * FILE_SAMPLES).collect();
+    assert_eq!(timestamps.to_vec(), expected);
+}
+
+/// Saves two files of `file()` to `dir`, the second starting `missing` samples
+/// after the end of the first
+fn save_with_gap(dir: &TempDir, missing: usize) {
+    file(0)
+        .dc_amplifier(true)
+        .stim_pulse(1, 0, 5, 3.0)
+        .save(dir.join("a.rhs"))
+        .unwrap();
+    file((FILE_SAMPLES + missing) as i32)
+        .dc_amplifier(true)
+        .stim_pulse(1, 0, 5, 3.0)
+        .save(dir.join("b.rhs"))
+        .unwrap();
+}
+
+#[test]
+fn gaps_are_padded_with_zeros() {
+    let dir = TempDir::new("combine_pad_zero");
+    save_with_gap(&dir, 500);
+    let padding = FILE_SAMPLES..FILE_SAMPLES + 500;
+
+    let recording = Loader::new(dir.path())
+        .gap_handling(GapHandling::PadZero)
+        .quiet()
+        .load()
+        .unwrap();
+    let data = recording.data.as_ref().unwrap();
+    let expected: Vec<i64> = (0..2 * FILE_SAMPLES as i64 + 500).collect();
+    assert_eq!(data.timestamps.to_vec(), expected);
+    assert_eq!(recording.segments().len(), 1);
+    assert_eq!(
+        recording.source_files.as_ref().unwrap()[1].samples().start,
+        padding.end
+    );
+    assert_eq!(
+        recording.warnings,
+        [LoadWarning::FileGap {
+            path: dir.join("b.rhs").to_string_lossy().to_string(),
+            missing_samples: 500,
+            padded_samples: 500,
+        }]
+    );
+
+    let amplifier = data.amplifier_data.as_ref().unwrap();
+    assert!(amplifier
+        .slice(s![.., padding.clone()])
+        .iter()
+        .all(|&v| v == 0.0));
+    // The second file follows the padding unchanged
+    assert_eq!(
+        amplifier.slice(s![.., padding.end..]),
+        amplifier.slice(s![.., ..FILE_SAMPLES])
+    );
+    assert!(amplifier[[0, padding.end + 10]] != 0.0);
+    for array in [
+        &data.dc_amplifier_data,
+        &data.stim_data,
+        &data.board_adc_data,
+    ] {
+        let array = array.as_ref().unwrap();
+        assert!(array
+            .slice(s![.., padding.clone()])
+            .iter()
+            .all(|&v| v == 0.0));
+    }
+    assert_eq!(data.stim_data.as_ref().unwrap()[[1, padding.end]], -3.0);
+    let digital = data.board_dig_in_data.as_ref().unwrap();
+    assert!(digital
+        .slice(s![.., padding.clone()])
+        .iter()
+        .all(|&v| v == 0));
+    assert_eq!(digital[[0, padding.end + 100]], 1);
+    let compliance = data.compliance_limit_data.as_ref().unwrap();
+    assert_eq!(compliance.dim(), (2, expected.len()));
+    assert!(!compliance.any());
+}
+
+#[test]
+fn raw_gaps_are_padded_with_the_code_for_zero() {
+    let dir = TempDir::new("combine_pad_zero_raw");
+    save_with_gap(&dir, 300);
+    let padding = FILE_SAMPLES..FILE_SAMPLES + 300;
+
+    let recording = Loader::new(dir.path())
+        .gap_handling(GapHandling::PadZero)
+        .raw(true)
+        .quiet()
+        .load()
+        .unwrap();
+    let data = recording.data.as_ref().unwrap();
+    assert_eq!(data.num_samples(), 2 * FILE_SAMPLES + 300);
+    // Zero volts is the middle of the ADC range, and of the 10-bit DC amplifier range
+    for (array, code) in [
+        (&data.amplifier_data_raw, 32768),
+        (&data.board_adc_data_raw, 32768),
+        (&data.dc_amplifier_data_raw, 512),
+    ] {
+        let array = array.as_ref().unwrap();
+        assert!(array
+            .slice(s![.., padding.clone()])
+            .iter()
+            .all(|&v| v == code));
+    }
+    let digital = data.board_dig_in_data.as_ref().unwrap();
+    assert!(digital
+        .slice(s![.., padding.clone()])
+        .iter()
+        .all(|&v| v == 0));
+}
+
+#[test]
+fn gaps_are_padded_with_nan() {
+    let dir = TempDir::new("combine_pad_nan");
+    save_with_gap(&dir, 200);
+    let padding = FILE_SAMPLES..FILE_SAMPLES + 200;
+
+    let recording = Loader::new(dir.path())
+        .gap_handling(GapHandling::PadNan)
+        .quiet()
+        .load()
+        .unwrap();
+    let data = recording.data.as_ref().unwrap();
+    for array in [
+        &data.amplifier_data,
+        &data.dc_amplifier_data,
+        &data.stim_data,
+        &data.board_adc_data,
+    ] {
+        let array = array.as_ref().unwrap();
+        assert!(array
+            .slice(s![.., padding.clone()])
+            .iter()
+            .all(|v| v.is_nan()));
+        assert!(array
+            .slice(s![.., padding.end..])
+            .iter()
+            .all(|v| !v.is_nan()));
+    }
+    let digital = data.board_dig_in_data.as_ref().unwrap();
+    assert!(digital
+        .slice(s![.., padding.clone()])
+        .iter()
+        .all(|&v| v == 0));
+
+    let recording = Loader::new(dir.path())
+        .gap_handling(GapHandling::PadNan)
+        .data_type(DataType::F32)
+        .quiet()
+        .load()
+        .unwrap();
+    let amplifier = recording.data.unwrap().amplifier_data_f32.unwrap();
+    assert!(amplifier.slice(s![.., padding]).iter().all(|v| v.is_nan()));
+
+    // Raw codes have no NaN
+    let error = Loader::new(dir.path())
+        .gap_handling(GapHandling::PadNan)
+        .raw(true)
+        .load()
+        .unwrap_err();
+    assert!(matches!(error, IntanError::InvalidOptions(_)), "{}", error);
+}
+
+#[test]
+fn gaps_are_rejected_or_kept() {
+    let dir = TempDir::new("combine_gap_error");
+    save_with_gap(&dir, 128);
+
+    let error = Loader::new(dir.path())
+        .gap_handling(GapHandling::Error)
+        .quiet()
+        .load()
+        .unwrap_err();
+    assert!(
+        matches!(
+            error.root_cause(),
+            IntanError::Gap {
+                missing_samples: 128,
+                ..
+            }
+        ),
+        "{}",
+        error
+    );
+
+    let recording = Loader::new(dir.path()).quiet().load().unwrap();
+    assert_eq!(
+        recording.data.as_ref().unwrap().num_samples(),
+        2 * FILE_SAMPLES
+    );
+    assert_eq!(recording.segments().len(), 2);
+}
+
+#[test]
+fn timestamps_going_backwards_cannot_be_padded() {
+    let dir = TempDir::new("combine_pad_backwards");
+    // Recording order from the names, though the timestamps restart
+    file(5000)
+        .save(dir.join("session_230105_101500.rhs"))
+        .unwrap();
+    file(0)
+        .sine(1, 30.0, 40.0)
+        .save(dir.join("session_230105_101501.rhs"))
+        .unwrap();
+    let missing = -(5000 + FILE_SAMPLES as i64);
+
+    for handling in [GapHandling::PadZero, GapHandling::PadNan] {
+        let error = Loader::new(dir.path())
+            .gap_handling(handling)
+            .quiet()
+            .load()
+            .unwrap_err();
+        match error.root_cause() {
+            IntanError::Gap {
+                missing_samples, ..
+            } => assert_eq!(*missing_samples, missing),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    // Kept as they are, the files are combined with a warning
+    let recording = Loader::new(dir.path()).quiet().load().unwrap();
+    assert!(matches!(
+        recording.warnings[..],
+        [LoadWarning::FileGap {
+            missing_samples,
+            padded_samples: 0,
+            ..
+        }] if missing_samples == missing
+    ));
+}
+
+/// Writes the data blocks `blocks` of a recording from `file(0)`, as a file of
+/// the recording cut at other places would hold them
+fn save_blocks(path: &Path, blocks: Range<usize>) {
+    let block_bytes = |num_blocks: usize| {
+        file(0)
+            .num_samples(num_blocks * 128)
+            .to_bytes()
+            .unwrap()
+            .len()
+    };
+    let block_size = block_bytes(2) - block_bytes(1);
+    let header_size = block_bytes(1) - block_size;
+
+    let bytes = file(0).num_samples(blocks.end * 128).to_bytes().unwrap();
+    let mut contents = bytes[..header_size].to_vec();
+    contents.extend_from_slice(&bytes[header_size + blocks.start * block_size..]);
+    fs::write(path, contents).unwrap();
+}
+
+#[test]
+fn identical_copies_are_rejected_or_left_out() {
+    let dir = TempDir::new("combine_identical_copy");
+    file(0).save(dir.join("a.rhs")).unwrap();
+    file(FILE_SAMPLES as i32).save(dir.join("b.rhs")).unwrap();
+    fs::copy(dir.join("a.rhs"), dir.join("a_copy.rhs")).unwrap();
+
+    let error = Loader::new(dir.path()).quiet().load().unwrap_err();
+    match &error {
+        IntanError::File { path, source, .. } => {
+            assert_eq!(*path, dir.join("a_copy.rhs"));
+            match &**source {
+                IntanError::DuplicateFile {
+                    original,
+                    repeated_samples,
+                    extra_samples,
+                } => {
+                    assert_eq!(*original, dir.join("a.rhs"));
+                    assert_eq!(*repeated_samples, 0..FILE_SAMPLES);
+                    assert_eq!(*extra_samples, 0);
+                }
+                other => panic!("unexpected error: {:?}", other),
+            }
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let recording = Loader::new(dir.path())
+        .duplicate_handling(DuplicateHandling::Deduplicate)
+        .quiet()
+        .load()
+        .unwrap();
+    let skipped: Vec<&str> = recording
+        .skipped_files
+        .iter()
+        .map(|skipped| skipped.path.as_str())
+        .collect();
+    assert_eq!(skipped, [dir.join("a_copy.rhs").to_string_lossy()]);
+    let expected: Vec<i64> = (0..2 * FILE_SAMPLES as i64).collect();
+    assert_eq!(recording.data.unwrap().timestamps.to_vec(), expected);
+}
+
+#[test]
+fn copies_of_part_of_a_file_are_left_out() {
+    let dir = TempDir::new("combine_partial_copy");
+    save_blocks(&dir.join("a.rhs"), 0..10);
+    save_blocks(&dir.join("b.rhs"), 10..20);
+    save_blocks(&dir.join("part.rhs"), 12..15);
+
+    let error = Loader::new(dir.path()).quiet().load().unwrap_err();
+    assert!(
+        matches!(
+            error.root_cause(),
+            IntanError::DuplicateFile {
+                repeated_samples,
+                extra_samples: 0,
+                ..
+            } if *repeated_samples == (256..640)
+        ),
+        "{}",
+        error
+    );
+
+    let recording = Loader::new(dir.path())
+        .duplicate_handling(DuplicateHandling::Deduplicate)
+        .quiet()
+        .load()
+        .unwrap();
+    assert_eq!(recording.skipped_files.len(), 1);
+    assert_eq!(
+        recording.skipped_files[0].path,
+        dir.join("part.rhs").to_string_lossy()
+    );
+    assert_eq!(recording.data.unwrap().num_samples(), 2 * FILE_SAMPLES);
+}
+
+#[test]
+fn overlapping_files_cannot_be_left_out() {
+    let dir = TempDir::new("combine_overlap");
+    save_blocks(&dir.join("a.rhs"), 0..10);
+    save_blocks(&dir.join("b.rhs"), 10..20);
+    // Repeats the second half of the first file, then continues past its end
+    save_blocks(&dir.join("overlap.rhs"), 5..15);
+
+    for handling in [DuplicateHandling::Error, DuplicateHandling::Deduplicate] {
+        let error = Loader::new(dir.path())
+            .duplicate_handling(handling)
+            .quiet()
+            .load()
+            .unwrap_err();
+        match error.root_cause() {
+            IntanError::DuplicateFile {
+                original,
+                repeated_samples,
+                extra_samples,
+            } => {
+                assert_eq!(*original, dir.join("a.rhs"));
+                assert_eq!(*repeated_samples, 640..FILE_SAMPLES);
+                assert_eq!(*extra_samples, 640);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn timestamps_restarting_from_zero_are_not_copies() {
+    let dir = TempDir::new("combine_restart");
+    // Two recordings of a session, each starting from timestamp zero
+    file(0).save(dir.join("session_230105_101500.rhs")).unwrap();
+    file(0)
+        .sine(1, 30.0, 40.0)
+        .save(dir.join("session_230105_103000.rhs"))
+        .unwrap();
+
+    let recording = Loader::new(dir.path()).quiet().load().unwrap();
+    assert!(recording.skipped_files.is_empty());
+    let data = recording.data.as_ref().unwrap();
+    assert_eq!(data.num_samples(), 2 * FILE_SAMPLES);
+    assert_eq!(data.timestamps[FILE_SAMPLES], 0);
+    assert_eq!(recording.segments().len(), 2);
+}
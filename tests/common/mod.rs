@@ -0,0 +1,52 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ndarray::Array2;
+
+/// A directory under the system temporary directory, removed when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates an empty directory named after the test
+    pub fn new(name: &str) -> TempDir {
+        let path =
+            std::env::temp_dir().join(format!("intan_importer_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Asserts that two arrays have the same shape and differ by at most `tolerance`
+pub fn assert_close(name: &str, actual: &Array2<f64>, expected: &Array2<f64>, tolerance: f64) {
+    assert_eq!(actual.dim(), expected.dim(), "shape of {}", name);
+    for ((index, a), e) in actual.indexed_iter().zip(expected.iter()) {
+        assert!(
+            (a - e).abs() <= tolerance,
+            "{}{:?}: {} differs from {} by more than {}",
+            name,
+            index,
+            a,
+            e,
+            tolerance
+        );
+    }
+}
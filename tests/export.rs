@@ -0,0 +1,132 @@
+//! Exports of recordings loaded as raw codes or in single precision.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use common::TempDir;
+use intan_importer::export::edf::EdfOptions;
+use intan_importer::loader::DataType;
+use intan_importer::{Loader, RhsFile, RhsFileBuilder};
+
+/// Saves a recording with amplifier, ADC and digital channels, and returns its path
+fn save_recording(dir: &TempDir) -> PathBuf {
+    let path = dir.join("recording.rhs");
+    RhsFileBuilder::new()
+        .sample_rate(20000.0)
+        .num_samples(128 * 160)
+        .amplifier_channels(4)
+        .board_adc_channels(2)
+        .digital_inputs(1)
+        .sine(0, 100.0, 250.0)
+        .sine(3, 1000.0, 40.0)
+        .digital_pulse(0, 1000..3000)
+        .save(&path)
+        .unwrap();
+    path
+}
+
+/// The recording at `path` loaded as `f64`, as raw codes, and as `f32`
+fn loads(path: &PathBuf) -> [(&'static str, RhsFile); 3] {
+    let load = |loader: Loader| loader.quiet().load().unwrap();
+    [
+        ("f64", load(Loader::new(path))),
+        ("raw", load(Loader::new(path).raw(true))),
+        ("f32", load(Loader::new(path).data_type(DataType::F32))),
+    ]
+}
+
+#[test]
+fn edf_export_includes_raw_and_single_precision_data() {
+    let dir = TempDir::new("export_edf");
+    let path = save_recording(&dir);
+    let options = EdfOptions {
+        start_time: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+        ..EdfOptions::default()
+    };
+
+    let mut exports = Vec::new();
+    for (name, rhs_file) in loads(&path) {
+        let edf = dir.join(&format!("{}.edf", name));
+        rhs_file.to_edf(&edf, &options).unwrap();
+        exports.push((name, fs::read(&edf).unwrap()));
+    }
+    // 4 amplifier and 2 ADC signals, and the annotations
+    let num_signals = String::from_utf8_lossy(&exports[0].1[252..256]).to_string();
+    assert_eq!(num_signals.trim(), "7");
+    for (name, bytes) in &exports[1..] {
+        assert!(*bytes == exports[0].1, "EDF of the {} load differs", name);
+    }
+}
+
+#[test]
+fn binary_and_npz_exports_include_raw_and_single_precision_data() {
+    let dir = TempDir::new("export_binary");
+    let path = save_recording(&dir);
+
+    let mut binaries = Vec::new();
+    let mut archives = Vec::new();
+    for (name, rhs_file) in loads(&path) {
+        let binary = dir.join(&format!("{}.bin", name));
+        rhs_file.to_int16_binary(&binary).unwrap();
+        binaries.push((name, fs::read(&binary).unwrap()));
+        let archive = dir.join(&format!("{}.npz", name));
+        rhs_file.to_npz(&archive).unwrap();
+        archives.push((name, fs::read(&archive).unwrap()));
+    }
+    assert_eq!(binaries[0].1.len(), 4 * 128 * 160 * 2);
+    for (name, bytes) in &binaries[1..] {
+        assert!(
+            *bytes == binaries[0].1,
+            "binary of the {} load differs",
+            name
+        );
+    }
+    // Raw codes are scaled exactly as the loader scales them
+    assert!(
+        archives[1].1 == archives[0].1,
+        "NPZ of the raw load differs"
+    );
+    // Single precision is widened, so only the size is the same
+    assert_eq!(archives[2].1.len(), archives[0].1.len());
+}
+
+#[test]
+fn spilled_data_is_not_exported() {
+    let dir = TempDir::new("export_spilled");
+    let path = save_recording(&dir);
+    let spill_dir = dir.join("spill");
+    fs::create_dir(&spill_dir).unwrap();
+    let rhs_file = Loader::new(&path)
+        .spill_to(&spill_dir)
+        .quiet()
+        .load()
+        .unwrap();
+    assert!(rhs_file.data.as_ref().unwrap().spilled.is_some());
+
+    let error = rhs_file
+        .to_edf(dir.join("spilled.edf"), &EdfOptions::default())
+        .unwrap_err();
+    assert!(error.to_string().contains("spilled"), "{}", error);
+    assert!(rhs_file.to_npz(dir.join("spilled.npz")).is_err());
+    assert!(rhs_file.to_int16_binary(dir.join("spilled.bin")).is_err());
+}
+
+#[cfg(feature = "arrow")]
+#[test]
+fn arrow_export_includes_raw_data() {
+    use intan_importer::export::arrow::ArrowOptions;
+
+    let dir = TempDir::new("export_arrow");
+    let path = save_recording(&dir);
+    let [(_, scaled), (_, raw), (_, single)] = loads(&path);
+    let options = ArrowOptions::default();
+
+    let expected = scaled.to_arrow(&options).unwrap();
+    assert_eq!(raw.to_arrow(&options).unwrap(), expected);
+    assert_eq!(raw.into_arrow(&options).unwrap(), expected);
+    let single = single.to_arrow(&options).unwrap();
+    assert_eq!(single[0].schema(), expected[0].schema());
+}
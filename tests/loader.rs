@@ -0,0 +1,155 @@
+//! Loading recordings built with `RhsFileBuilder`: selections and their errors.
+
+mod common;
+
+use common::TempDir;
+use intan_importer::loader::ChannelSelector;
+use intan_importer::{DateTime, IntanError, Loader, RhsFileBuilder};
+
+#[test]
+fn missing_channels_are_reported_with_their_selector() {
+    let dir = TempDir::new("loader_missing_channel");
+    let path = dir.join("recording.rhs");
+    RhsFileBuilder::new()
+        .amplifier_channels(4)
+        .board_adc_channels(1)
+        .save(&path)
+        .unwrap();
+
+    let error = Loader::new(&path)
+        .channels(["A-001", "A-009"])
+        .load()
+        .unwrap_err();
+    match error.root_cause() {
+        IntanError::ChannelNotFound(ChannelSelector::Name(name)) => assert_eq!(name, "A-009"),
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let error = Loader::new(&path)
+        .channels([ChannelSelector::BoardAdc(1)])
+        .load()
+        .unwrap_err();
+    assert!(matches!(
+        error.root_cause(),
+        IntanError::ChannelNotFound(ChannelSelector::BoardAdc(1))
+    ));
+    assert_eq!(
+        error.root_cause().to_string(),
+        "No board ADC channel 1 in the recording"
+    );
+}
+
+#[test]
+fn time_ranges_outside_the_recording_are_reported() {
+    let dir = TempDir::new("loader_time_range");
+    let path = dir.join("recording.rhs");
+    // 12,800 samples at 30 kHz
+    RhsFileBuilder::new().save(&path).unwrap();
+
+    let error = Loader::new(&path).time_range(1.0..2.0).load().unwrap_err();
+    match error.root_cause() {
+        IntanError::TimeRangeOutsideRecording { range, duration } => {
+            assert_eq!(*range, 1.0..2.0);
+            assert!((duration - 12800.0 / 30000.0).abs() < 1e-9);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let recording = Loader::new(&path).time_range(0.1..0.2).load().unwrap();
+    assert_eq!(recording.data.unwrap().timestamps.len(), 3000);
+}
+
+#[test]
+fn wallclock_ranges_outside_the_recording_are_reported() {
+    let dir = TempDir::new("loader_wallclock_range");
+    let path = dir.join("mouse_230105_101500.rhs");
+    RhsFileBuilder::new().save(&path).unwrap();
+    let start = DateTime::new(2023, 1, 5, 10, 15, 0).unwrap();
+
+    let before = DateTime::new(2023, 1, 5, 9, 0, 0).unwrap()..start;
+    let error = Loader::new(&path)
+        .wallclock_range(before.clone())
+        .load()
+        .unwrap_err();
+    match error.root_cause() {
+        IntanError::WallclockRangeOutsideRecording {
+            range,
+            recording_start,
+        } => {
+            assert_eq!(*range, before);
+            assert_eq!(*recording_start, start);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    // A window starting after the end of the recording selects no samples either
+    let after = start.add_seconds(1.0)..start.add_seconds(2.0);
+    let error = Loader::new(&path)
+        .wallclock_range(after)
+        .load()
+        .unwrap_err();
+    assert!(matches!(
+        error.root_cause(),
+        IntanError::TimeRangeOutsideRecording { .. }
+    ));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mapped_reads_report_missing_channels_and_samples() {
+    use intan_importer::mmap::MappedRhsFile;
+
+    let dir = TempDir::new("loader_mapped");
+    let path = dir.join("recording.rhs");
+    RhsFileBuilder::new()
+        .amplifier_channels(4)
+        .save(&path)
+        .unwrap();
+    let file = MappedRhsFile::open(&path).unwrap();
+
+    let error = file.read_channels(["A-009"], 0..100).unwrap_err();
+    match error {
+        IntanError::ChannelNotFound(ChannelSelector::Name(name)) => assert_eq!(name, "A-009"),
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(matches!(
+        file.amplifier_channel(4, 0..100),
+        Err(IntanError::ChannelNotFound(ChannelSelector::Amplifier(4)))
+    ));
+    assert!(file.read_samples(0..file.num_samples() + 1).is_err());
+    assert_eq!(file.amplifier_channel(3, 100..200).unwrap().len(), 100);
+}
+
+#[test]
+fn slices_from_the_start_match_time_ranges() {
+    let dir = TempDir::new("loader_slice");
+    let path = dir.join("recording.rhs");
+    // 12,800 samples at 30 kHz, from timestamp 1000
+    RhsFileBuilder::new()
+        .first_timestamp(1000)
+        .save(&path)
+        .unwrap();
+    let recording = Loader::new(&path).load().unwrap();
+
+    for range in [0.0..0.01, 0.1..0.2, 0.0105..0.4, 0.4..1.0] {
+        let loaded = Loader::new(&path).time_range(range.clone()).load();
+        let view = recording.slice_from_start(range.clone()).unwrap();
+        match loaded {
+            Ok(loaded) => {
+                let timestamps = loaded.data.unwrap().timestamps;
+                assert_eq!(view.data.timestamps, timestamps.view(), "{:?}", range);
+            }
+            Err(_) => assert_eq!(view.num_samples(), 0, "{:?}", range),
+        }
+    }
+    assert_eq!(
+        recording.slice_from_start(0.0..0.01).unwrap().num_samples(),
+        300
+    );
+
+    // On the timestamp scale, the recording starts 1000 samples in
+    assert_eq!(recording.slice(0.0..0.01).unwrap().num_samples(), 0);
+    let start = recording.time_seconds()[0];
+    let view = recording.slice(start..start + 0.01).unwrap();
+    assert_eq!((view.sample_offset, view.num_samples()), (0, 300));
+}
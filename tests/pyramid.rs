@@ -0,0 +1,161 @@
+//! Saving, reopening, and caching min/max pyramids.
+
+mod common;
+
+use std::fs;
+
+use common::TempDir;
+use intan_importer::pyramid::{Pyramid, PyramidOptions};
+use intan_importer::{IntanError, RhsFile, RhsFileBuilder};
+
+/// A recording of two amplifier channels with a sine of `amplitude` μV on the first
+fn recording(sample_rate: f32, amplitude: f64) -> RhsFile {
+    RhsFileBuilder::new()
+        .sample_rate(sample_rate)
+        .num_samples(128 * 20)
+        .amplifier_channels(2)
+        .sine(0, 50.0, amplitude)
+        .build()
+        .unwrap()
+}
+
+/// Largest value of channel 0, from the coarsest level
+fn peak(pyramid: &Pyramid) -> f32 {
+    pyramid.levels[pyramid.levels.len() - 1].max[[0, 0]]
+}
+
+fn assert_same(actual: &Pyramid, expected: &Pyramid) {
+    assert_eq!(actual.sample_rate, expected.sample_rate);
+    assert_eq!(actual.num_samples, expected.num_samples);
+    assert_eq!(actual.level_factor, expected.level_factor);
+    assert_eq!(actual.source_hash, expected.source_hash);
+    assert_eq!(actual.levels.len(), expected.levels.len());
+    for (level, expected) in actual.levels.iter().zip(&expected.levels) {
+        assert_eq!(level.bin_size, expected.bin_size);
+        assert_eq!(level.min, expected.min);
+        assert_eq!(level.max, expected.max);
+    }
+}
+
+#[test]
+fn saved_pyramids_open_as_built() {
+    let dir = TempDir::new("pyramid_saved");
+    let path = dir.join("recording.pyr");
+    let options = PyramidOptions::default();
+    let pyramid = recording(20000.0, 100.0)
+        .amplifier_pyramid(&options)
+        .unwrap()
+        .unwrap();
+    assert_eq!(pyramid.levels[0].bin_size, 16);
+    assert_eq!(pyramid.levels[pyramid.levels.len() - 1].num_bins(), 1);
+    assert!((peak(&pyramid) - 100.0).abs() < 0.2);
+
+    pyramid.save(&path).unwrap();
+    assert_same(&Pyramid::open(&path).unwrap(), &pyramid);
+}
+
+#[test]
+fn cached_pyramids_are_reused_for_the_same_recording() {
+    let dir = TempDir::new("pyramid_reused");
+    let path = dir.join("recording.pyr");
+    let options = PyramidOptions::default();
+    let rhs_file = recording(20000.0, 100.0);
+    let built = rhs_file
+        .amplifier_pyramid_cached(&path, &options)
+        .unwrap()
+        .unwrap();
+    assert!(path.exists());
+
+    // Mark the saved peak, which only a pyramid read from the cache returns
+    let mut bytes = fs::read(&path).unwrap();
+    let end = bytes.len();
+    let marked = 12345f32.to_le_bytes();
+    bytes[end - 8..end - 4].copy_from_slice(&marked);
+    fs::write(&path, &bytes).unwrap();
+
+    let cached = rhs_file
+        .amplifier_pyramid_cached(&path, &options)
+        .unwrap()
+        .unwrap();
+    assert_eq!(peak(&cached), 12345.0);
+    assert_eq!(cached.source_hash, built.source_hash);
+}
+
+#[test]
+fn cached_pyramids_of_other_recordings_are_rebuilt() {
+    let dir = TempDir::new("pyramid_rebuilt");
+    let path = dir.join("recording.pyr");
+    let options = PyramidOptions::default();
+    let first = recording(20000.0, 100.0);
+    first.amplifier_pyramid_cached(&path, &options).unwrap();
+
+    // Same shape, different data
+    let louder = recording(20000.0, 4000.0);
+    let pyramid = louder
+        .amplifier_pyramid_cached(&path, &options)
+        .unwrap()
+        .unwrap();
+    assert!((peak(&pyramid) - 4000.0).abs() < 0.2);
+    assert_same(&Pyramid::open(&path).unwrap(), &pyramid);
+
+    // Same data, different sample rate
+    let slower = recording(10000.0, 4000.0);
+    let pyramid = slower
+        .amplifier_pyramid_cached(&path, &options)
+        .unwrap()
+        .unwrap();
+    assert_eq!(pyramid.sample_rate, 10000.0);
+
+    // Different levels
+    let options = PyramidOptions {
+        base_bin_size: 8,
+        level_factor: 2,
+    };
+    let pyramid = slower
+        .amplifier_pyramid_cached(&path, &options)
+        .unwrap()
+        .unwrap();
+    assert_eq!(pyramid.levels[0].bin_size, 8);
+    assert_eq!(Pyramid::open(&path).unwrap().level_factor, 2);
+}
+
+#[test]
+fn corrupt_pyramids_are_rejected_and_replaced() {
+    let dir = TempDir::new("pyramid_corrupt");
+    let path = dir.join("recording.pyr");
+    let options = PyramidOptions::default();
+    let rhs_file = recording(20000.0, 100.0);
+    let pyramid = rhs_file.amplifier_pyramid(&options).unwrap().unwrap();
+    pyramid.save(&path).unwrap();
+    let saved = fs::read(&path).unwrap();
+
+    // A channel count that would need far more memory than the file holds
+    let mut bytes = saved.clone();
+    bytes[28..36].copy_from_slice(&(u64::MAX / 4).to_le_bytes());
+    fs::write(&path, &bytes).unwrap();
+    assert!(matches!(
+        Pyramid::open(&path),
+        Err(IntanError::FileSizeError)
+    ));
+
+    // A level count the file is too short to hold
+    let mut bytes = saved.clone();
+    bytes[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+    fs::write(&path, &bytes).unwrap();
+    assert!(matches!(
+        Pyramid::open(&path),
+        Err(IntanError::FileSizeError)
+    ));
+
+    fs::write(&path, &saved[..saved.len() - 4]).unwrap();
+    assert!(Pyramid::open(&path).is_err());
+    fs::write(&path, b"not a pyramid").unwrap();
+    assert!(Pyramid::open(&path).is_err());
+
+    let rebuilt = rhs_file
+        .amplifier_pyramid_cached(&path, &options)
+        .unwrap()
+        .unwrap();
+    assert_same(&rebuilt, &pyramid);
+    assert_eq!(fs::read(&path).unwrap(), saved);
+}
@@ -0,0 +1,331 @@
+//! Round trips through `RhsFile::save`: a saved recording loads back unchanged.
+
+mod common;
+
+use common::{assert_close, TempDir};
+use intan_importer::loader::DataType;
+use intan_importer::{load, Loader, RhsFile, RhsFileBuilder, StimFlags};
+use ndarray::Array2;
+
+/// Half an ADC step of each analog signal, in the units they are loaded in
+const AMPLIFIER_TOLERANCE: f64 = 0.195 / 2.0;
+const DC_AMPLIFIER_TOLERANCE: f64 = 0.01923 / 2.0;
+const ADC_DAC_TOLERANCE: f64 = 0.0003125 / 2.0;
+
+/// A recording with every signal type, spread over two ports
+fn fixture() -> RhsFileBuilder {
+    RhsFileBuilder::new()
+        .sample_rate(20000.0)
+        .num_samples(128 * 8)
+        .amplifier_channels(40)
+        .dc_amplifier(true)
+        .board_adc_channels(2)
+        .board_dac_channels(2)
+        .digital_inputs(3)
+        .digital_outputs(2)
+        .stim_step_size(2.5)
+        .notes("first", "second", "third")
+        .sine(0, 100.0, 250.0)
+        .sine(35, 40.0, 80.0)
+        .stim_pulse(1, 100, 10, 20.0)
+        .stim_pulse(34, 500, 4, -7.5)
+        .digital_pulse(0, 10..200)
+        .digital_pulse(2, 150..700)
+}
+
+/// Saves a recording and loads it back
+fn round_trip(rhs_file: &RhsFile, dir: &TempDir, name: &str) -> RhsFile {
+    let path = dir.join(name);
+    rhs_file.save(&path).unwrap();
+    Loader::new(&path).quiet().load().unwrap()
+}
+
+fn assert_same_header(saved: &RhsFile, loaded: &RhsFile) {
+    let differences = saved.header.diff(&loaded.header);
+    assert!(differences.is_empty(), "header changed: {:?}", differences);
+}
+
+/// Asserts that the scaled data of two recordings agrees to within half an ADC step
+fn assert_same_data(expected: &RhsFile, actual: &RhsFile) {
+    let expected = expected.data.as_ref().unwrap();
+    let actual = actual.data.as_ref().unwrap();
+    assert_eq!(actual.timestamps, expected.timestamps);
+
+    let analog = [
+        (
+            "amplifier",
+            &actual.amplifier_data,
+            &expected.amplifier_data,
+            AMPLIFIER_TOLERANCE,
+        ),
+        (
+            "DC amplifier",
+            &actual.dc_amplifier_data,
+            &expected.dc_amplifier_data,
+            DC_AMPLIFIER_TOLERANCE,
+        ),
+        (
+            "board ADC",
+            &actual.board_adc_data,
+            &expected.board_adc_data,
+            ADC_DAC_TOLERANCE,
+        ),
+        (
+            "board DAC",
+            &actual.board_dac_data,
+            &expected.board_dac_data,
+            ADC_DAC_TOLERANCE,
+        ),
+    ];
+    for (name, actual, expected, tolerance) in analog {
+        assert_eq!(actual.is_some(), expected.is_some(), "presence of {}", name);
+        if let (Some(actual), Some(expected)) = (actual, expected) {
+            assert_close(name, actual, expected, tolerance);
+        }
+    }
+
+    assert_eq!(actual.stim_data, expected.stim_data);
+    for (name, actual, expected) in [
+        (
+            "compliance limit",
+            &actual.compliance_limit_data,
+            &expected.compliance_limit_data,
+        ),
+        (
+            "charge recovery",
+            &actual.charge_recovery_data,
+            &expected.charge_recovery_data,
+        ),
+        (
+            "amplifier settle",
+            &actual.amp_settle_data,
+            &expected.amp_settle_data,
+        ),
+    ] {
+        let to_array = |flags: &Option<StimFlags>| flags.as_ref().map(StimFlags::to_array);
+        assert_eq!(to_array(actual), to_array(expected), "{} flags", name);
+    }
+    assert_eq!(actual.board_dig_in_data, expected.board_dig_in_data);
+    assert_eq!(actual.board_dig_out_data, expected.board_dig_out_data);
+}
+
+#[test]
+fn saved_file_loads_back_unchanged() {
+    let dir = TempDir::new("writer_unchanged");
+    let built = fixture().build().unwrap();
+    let loaded = round_trip(&built, &dir, "built.rhs");
+    assert_same_header(&built, &loaded);
+    assert_same_data(&built, &loaded);
+
+    // Saving the loaded recording again reproduces the same bytes
+    let path = dir.join("built.rhs");
+    let copy = dir.join("copy.rhs");
+    loaded.save(&copy).unwrap();
+    assert_eq!(std::fs::read(copy).unwrap(), std::fs::read(path).unwrap());
+}
+
+#[test]
+fn raw_codes_round_trip_exactly() {
+    let dir = TempDir::new("writer_raw");
+    let original = dir.join("original.rhs");
+    fixture().save(&original).unwrap();
+
+    let raw = Loader::new(&original).raw(true).quiet().load().unwrap();
+    let copy = round_trip(&raw, &dir, "copy.rhs");
+    let copy_raw = Loader::new(dir.join("copy.rhs"))
+        .raw(true)
+        .quiet()
+        .load()
+        .unwrap();
+    assert_same_header(&raw, &copy);
+
+    let (expected, actual) = (raw.data.unwrap(), copy_raw.data.unwrap());
+    assert!(expected.amplifier_data_raw.is_some());
+    assert_eq!(actual.amplifier_data_raw, expected.amplifier_data_raw);
+    assert_eq!(actual.dc_amplifier_data_raw, expected.dc_amplifier_data_raw);
+    assert_eq!(actual.board_adc_data_raw, expected.board_adc_data_raw);
+    assert_eq!(actual.board_dac_data_raw, expected.board_dac_data_raw);
+
+    // The raw load saved as scaled data matches the scaled load of the original
+    assert_same_data(&load(&original).unwrap(), &copy);
+}
+
+#[test]
+fn single_precision_data_round_trips() {
+    let dir = TempDir::new("writer_f32");
+    let original = dir.join("original.rhs");
+    fixture().save(&original).unwrap();
+
+    let f32_load = Loader::new(&original)
+        .data_type(DataType::F32)
+        .quiet()
+        .load()
+        .unwrap();
+    let copy = round_trip(&f32_load, &dir, "copy.rhs");
+    assert_same_header(&f32_load, &copy);
+    assert_same_data(&load(&original).unwrap(), &copy);
+}
+
+#[test]
+fn values_between_steps_are_rounded_to_the_nearest_step() {
+    let dir = TempDir::new("writer_rounding");
+    let mut recording = fixture().build().unwrap();
+    let expected = recording.clone();
+
+    // Offsets below half a step round back to the original codes
+    let data = recording.data.as_mut().unwrap();
+    let amplifier = data.amplifier_data.as_mut().unwrap();
+    amplifier.mapv_inplace(|uv| uv + 0.09);
+    let adc = data.board_adc_data.as_mut().unwrap();
+    adc.mapv_inplace(|v| v - 0.00015);
+
+    let loaded = round_trip(&recording, &dir, "rounded.rhs");
+    assert_same_data(&expected, &loaded);
+    assert_close(
+        "amplifier",
+        loaded
+            .data
+            .as_ref()
+            .unwrap()
+            .amplifier_data
+            .as_ref()
+            .unwrap(),
+        recording
+            .data
+            .as_ref()
+            .unwrap()
+            .amplifier_data
+            .as_ref()
+            .unwrap(),
+        AMPLIFIER_TOLERANCE,
+    );
+}
+
+#[test]
+fn timestamps_past_the_32_bit_range_wrap_and_unwrap() {
+    let dir = TempDir::new("writer_wrap");
+    // The recording crosses i32::MAX in its second data block
+    let built = RhsFileBuilder::new()
+        .num_samples(128 * 4)
+        .first_timestamp(i32::MAX - 200)
+        .build()
+        .unwrap();
+    let timestamps = &built.data.as_ref().unwrap().timestamps;
+    assert!(timestamps[511] > i32::MAX as i64);
+
+    let loaded = round_trip(&built, &dir, "wrap.rhs");
+    assert_eq!(loaded.data.as_ref().unwrap().timestamps, *timestamps);
+}
+
+#[test]
+fn stim_words_keep_current_polarity_and_flags() {
+    let dir = TempDir::new("writer_stim");
+    let mut recording = fixture().build().unwrap();
+    let data = recording.data.as_mut().unwrap();
+    let (channels, samples) = data.stim_data.as_ref().unwrap().dim();
+
+    // Each status flag set on different samples, overlapping on sample 105
+    let flags = |pattern: fn(usize, usize) -> bool| {
+        let values = Array2::from_shape_fn((channels, samples), |(i, j)| pattern(i, j));
+        Some(StimFlags::from_array(values.view()))
+    };
+    data.compliance_limit_data = flags(|i, j| i == 1 && (105..108).contains(&j));
+    data.charge_recovery_data = flags(|i, j| i == 1 && (100..120).contains(&j));
+    data.amp_settle_data = flags(|i, j| (i == 1 || i == 34) && (90..130).contains(&j));
+
+    let loaded = round_trip(&recording, &dir, "stim.rhs");
+    assert_same_data(&recording, &loaded);
+
+    let stim = loaded.data.as_ref().unwrap().stim_data.clone().unwrap();
+    // Cathodic-first pulse of 20 μA, then the anodic-first pulse of -7.5 μA
+    assert_eq!(stim[[1, 105]], -20.0);
+    assert_eq!(stim[[1, 115]], 20.0);
+    assert_eq!(stim[[34, 500]], 7.5);
+    assert_eq!(stim[[34, 504]], -7.5);
+    let flags = loaded.data.as_ref().unwrap();
+    assert_eq!(
+        flags.compliance_limit_data.as_ref().unwrap().get(1, 105),
+        Some(true)
+    );
+    assert_eq!(
+        flags.charge_recovery_data.as_ref().unwrap().get(1, 99),
+        Some(false)
+    );
+    assert_eq!(
+        flags.amp_settle_data.as_ref().unwrap().get(34, 129),
+        Some(true)
+    );
+}
+
+#[test]
+fn digital_channels_are_packed_by_native_order() {
+    let dir = TempDir::new("writer_digital");
+    let mut recording = fixture().build().unwrap();
+
+    // Inputs on bits 2, 5, and 15, and outputs on bits 0 and 9
+    for (channel, bit) in recording
+        .header
+        .board_dig_in_channels
+        .iter_mut()
+        .zip([2, 5, 15])
+    {
+        channel.native_order = bit;
+    }
+    for (channel, bit) in recording
+        .header
+        .board_dig_out_channels
+        .iter_mut()
+        .zip([0, 9])
+    {
+        channel.native_order = bit;
+    }
+    let data = recording.data.as_mut().unwrap();
+    let samples = data.timestamps.len();
+    data.board_dig_in_data = Some(Array2::from_shape_fn((3, samples), |(i, j)| {
+        ((j >> i) & 1) as i32
+    }));
+    data.board_dig_out_data = Some(Array2::from_shape_fn((2, samples), |(i, j)| {
+        ((j / 7 + i) % 2) as i32
+    }));
+
+    let loaded = round_trip(&recording, &dir, "digital.rhs");
+    assert_same_header(&recording, &loaded);
+    assert_same_data(&recording, &loaded);
+}
+
+#[test]
+fn channels_are_regrouped_by_port() {
+    let dir = TempDir::new("writer_groups");
+    let mut recording = fixture().build().unwrap();
+
+    // Drop channels so that each port keeps a subset, and renumber what is left
+    recording
+        .retain_channels(|_, channel| !channel.native_channel_name.ends_with('7'))
+        .unwrap();
+    for (i, channel) in recording.header.amplifier_channels.iter_mut().enumerate() {
+        channel.custom_channel_name = format!("electrode {}", i);
+        channel.command_stream = (i % 4) as i32;
+    }
+    let ports: Vec<&str> = recording
+        .header
+        .amplifier_channels
+        .iter()
+        .map(|channel| channel.port_prefix.as_str())
+        .collect();
+    assert!(ports.contains(&"A") && ports.contains(&"B"));
+
+    let loaded = round_trip(&recording, &dir, "groups.rhs");
+    assert_same_header(&recording, &loaded);
+    assert_same_data(&recording, &loaded);
+}
+
+#[test]
+fn data_that_does_not_match_the_header_is_rejected() {
+    let dir = TempDir::new("writer_mismatch");
+    let mut recording = fixture().build().unwrap();
+    recording.header.amplifier_channels.pop();
+    assert!(recording.save(dir.join("mismatch.rhs")).is_err());
+
+    let partial = RhsFileBuilder::new().num_samples(200).build().unwrap();
+    assert!(partial.save(dir.join("partial.rhs")).is_err());
+}